@@ -1,21 +1,12 @@
 //!include "utils.wgsl"
+//!include "camera.wgsl"
+//!include "packing.wgsl"
 
 struct Ray {
     origin: vec3<f32>,
     direction: vec3<f32>,
 };
 
-struct Camera {
-    origin: vec3<f32>,
-    focalLength: f32,
-    forward: vec3<f32>,
-    vfov: f32,
-    right: vec3<f32>,
-    _padding2: f32,
-    up: vec3<f32>,
-    _padding3: f32,
-}
-
 struct HitRecord {
     hit: bool,
     t: f32,
@@ -24,25 +15,75 @@ struct HitRecord {
     frontFace: bool,
     attenuation: vec3<f32>,
     material: f32,
+    // How far metals scatter reflections from the mirror direction
+    roughness: f32,
+    // The index of refraction of dielectrics
+    ior: f32,
+    // Camera rays pass through emissive surfaces hidden from them
+    hiddenFromCamera: bool,
+    // What was hit, as written to objectIds
+    object: u32,
+    // The texture coordinates of triangles, which their instance's canvas is painted over
+    uv: vec2<f32>,
 }
 
+// A sphere's index, or an instance's index in the scene with OBJECT_INSTANCE set. Match the
+// renderer's.
+const NO_OBJECT: u32 = 0xffffffffu;
+const OBJECT_INSTANCE: u32 = 0x80000000u;
+
+const REFERENCE_DEPTH: u32 = 256u;
+
 struct Settings {
   samplesPerPixel: u32,
   depth: u32,
   tMin: f32,
   tMax: f32,
+  // Non-zero for reference mode, which is compiled in as the REFERENCE variant
+  reference: u32,
+  // Only used when resolving
+  toneMapping: u32,
+  exposure: f32,
+  // The brightest a sample can be, zero to leave samples as they are
+  maxRadiance: f32,
+  // Non-zero to gather caustics from photons, which is compiled in as the CAUSTICS variant
+  causticPreview: u32,
+  // The size of the cells photons are gathered over
+  causticRadius: f32,
+  // How many photons emitPhotons is dispatched for
+  photons: u32,
+}
+
+struct Environment {
+  yaw: f32,
+  intensity: f32,
+  showBackground: u32,
+  // Scales the angular size of the sun, keeping its power
+  sunSize: f32,
+  backgroundColor: vec3<f32>,
+  sunIntensity: f32,
+  sunDirection: vec3<f32>,
+  // Zero when the sky has no sun
+  sunRadius: f32,
+}
+
+struct ShadowCatcher {
+  hit: bool,
+  background: vec3<f32>,
+  lighting: vec3<f32>,
 }
 
 struct Sphere {
   center: vec3<f32>,
   radius: f32,
-  albedo: vec3<f32>,
-  material: f32,
+  // Indexes materials
+  material: u32,
 }
 
 struct Triangle {
   a: vec3<f32>,
-  _pad0: f32,
+  // Indexes materials
+  material: u32,
   b: vec3<f32>,
   _pad1: f32,
   c: vec3<f32>,
@@ -53,8 +94,26 @@ struct Triangle {
   _pad4: f32,
   cn: vec3<f32>,
   _pad5: f32,
+  // Texture coordinates at a, b and c
+  ta: vec2<f32>,
+  tb: vec2<f32>,
+  tc: vec2<f32>,
+  _pad6: vec2<f32>,
+}
+
+// Shared by the spheres and triangles made of it. The albedo of emissive ones is their radiance
+struct Material {
   albedo: vec3<f32>,
-  material: f32,
+  kind: u32,
+  // Only used by metals
+  roughness: f32,
+  // Only used by dielectrics
+  ior: f32,
+}
+
+// Limited to MAX_NUMBER_OF_MATERIALS, for the whole of it to fit in a uniform buffer
+struct Materials {
+  materials: array<Material, 256>,
 }
 
 struct Node {
@@ -64,102 +123,406 @@ struct Node {
   triangleCount: u32,
 }
 
+// A placement of a mesh, whose BVH starts at `root` in bvhNodes
+struct Instance {
+  worldToObject: mat4x4<f32>,
+  objectToWorld: mat4x4<f32>,
+  root: u32,
+  // Non-zero if camera rays pass through the mesh's emissive triangles
+  hiddenFromCamera: u32,
+  // The instance's index in the scene, which `instances` are reordered from
+  index: u32,
+  // The layer of `canvases` painted over the mesh's albedo, or NO_CANVAS
+  canvas: u32,
+}
+
+const NO_CANVAS: u32 = 0xffffffffu;
+
+// Limited to MAX_NUMBER_OF_INSTANCES, for the whole of it to fit in a uniform buffer
+struct Instances {
+  count: u32,
+  // The top-level BVH over the instances' bounds in world space, whose leaves index `instances`
+  tlas: array<Node, 512>,
+  instances: array<Instance, 256>,
+}
+
+// One step of a material expression
+struct Instruction {
+  op: u32,
+  value: f32,
+}
+
 struct SphereData {
   sphereCount: u32,
-  spheres: array<Sphere>,
+  // The animation time in seconds, for the albedo expressions
+  time: f32,
+  spheres: array<Sphere, 256>,
+  // Where each sphere's albedo expression starts in expressionCode, or NO_EXPRESSION
+  albedoExpressions: array<u32, 256>,
+  // Non-zero for spheres camera rays pass through if they're emissive
+  hiddenFromCamera: array<u32, 256>,
+  expressionCode: array<Instruction, EXPRESSION_CODE_SIZE>,
+}
+
+const NO_EXPRESSION: u32 = 0xffffffffu;
+// Matches MAX_EXPRESSION_CODE in the renderer
+const EXPRESSION_CODE_SIZE: u32 = 1024u;
+// Matches MAX_STACK_DEPTH in the renderer
+const EXPRESSION_STACK_SIZE: u32 = 8u;
+// Matches MAX_LOCALS in the renderer
+const EXPRESSION_LOCALS: u32 = 8u;
+// The most instructions an expression runs, including the one ending it
+const EXPRESSION_LENGTH: u32 = 65u;
+
+// Matches Op in the renderer, grouped by how many values each takes off the stack but for
+// OP_STORE, which pushes nothing
+const OP_END: u32 = 0u;
+const OP_CONSTANT: u32 = 1u;
+const OP_X: u32 = 2u;
+const OP_Y: u32 = 3u;
+const OP_Z: u32 = 4u;
+const OP_NORMAL_X: u32 = 5u;
+const OP_NORMAL_Y: u32 = 6u;
+const OP_NORMAL_Z: u32 = 7u;
+const OP_TIME: u32 = 8u;
+const OP_U: u32 = 9u;
+const OP_V: u32 = 10u;
+const OP_LOAD: u32 = 11u;
+const OP_NEGATE: u32 = 12u;
+const OP_SIN: u32 = 13u;
+const OP_COS: u32 = 14u;
+const OP_ABS: u32 = 15u;
+const OP_FLOOR: u32 = 16u;
+const OP_FRACT: u32 = 17u;
+const OP_SQRT: u32 = 18u;
+const OP_ADD: u32 = 19u;
+const OP_SUBTRACT: u32 = 20u;
+const OP_MULTIPLY: u32 = 21u;
+const OP_DIVIDE: u32 = 22u;
+const OP_MIN: u32 = 23u;
+const OP_MAX: u32 = 24u;
+const OP_POW: u32 = 25u;
+const OP_STEP: u32 = 26u;
+const OP_MIX: u32 = 27u;
+const OP_CLAMP: u32 = 28u;
+const OP_SMOOTHSTEP: u32 = 29u;
+const OP_NOISE: u32 = 30u;
+const OP_CANVAS_RED: u32 = 31u;
+const OP_CANVAS_GREEN: u32 = 32u;
+const OP_CANVAS_BLUE: u32 = 33u;
+const OP_STORE: u32 = 34u;
+
+const LIGHT_SPHERE: u32 = 0u;
+const LIGHT_TRIANGLE: u32 = 1u;
+
+// An emissive sphere or triangle
+struct Light {
+  // LIGHT_SPHERE or LIGHT_TRIANGLE
+  kind: u32,
+  // Into sphereData.spheres or triangles
+  index: u32,
+  // Into instances.instances, for triangles
+  instance: u32,
+}
+
+struct Lights {
+  count: u32,
+  lights: array<Light>,
+}
+
+const ANALYTIC_LIGHT_POINT: u32 = 0u;
+const ANALYTIC_LIGHT_DIRECTIONAL: u32 = 1u;
+const ANALYTIC_LIGHT_QUAD: u32 = 2u;
+
+// A light only reached with shadow rays
+struct AnalyticLight {
+  // The position of a point light, the direction a directional light travels in, or a corner of
+  // a quad light
+  vector: vec3<f32>,
+  // ANALYTIC_LIGHT_POINT, ANALYTIC_LIGHT_DIRECTIONAL or ANALYTIC_LIGHT_QUAD
+  kind: u32,
+  // The intensity of a point light, the irradiance of a directional light or the radiance of a
+  // quad light
+  radiance: vec3<f32>,
+  // The edges of a quad light from `vector`, which emits towards u × v
+  u: vec3<f32>,
+  v: vec3<f32>,
+}
+
+// Matches MAX_NUMBER_OF_SPHERES in the renderer
+const LINKED_SPHERES: u32 = 256u;
+// The link slot of a sphere or instance whose emission isn't linked
+const NO_LINK_SLOT: u32 = 0xffffffffu;
+
+struct AnalyticLights {
+  count: u32,
+  // A bit for each light that doesn't illuminate each sphere, then each instance
+  unlit: array<vec2<u32>, 512>,
+  // The slot of each sphere, then each instance, whose emission is linked, or NO_LINK_SLOT
+  emitterSlots: array<u32, 512>,
+  // A bit for each linked emitter's slot that doesn't illuminate each sphere, then each instance
+  unlitByEmitters: array<u32, 512>,
+  lights: array<AnalyticLight>,
+}
+
+// Matches CAUSTIC_GRID_CELLS in the renderer
+const CAUSTIC_GRID_CELLS: u32 = 65536u;
+// How many cells after the one a position hashes to are tried when it's taken by another
+const CAUSTIC_GRID_PROBES: u32 = 8u;
+const NO_CAUSTIC_CELL: u32 = 0xffffffffu;
+
+struct CausticCell {
+  // Which cell of the scene this is, zero while unused
+  key: atomic<u32>,
+  // The bits of each channel's irradiance, added to with compare-exchange loops
+  irradiance: array<atomic<u32>, 3>,
+}
+
+// The light reaching a point from the analytic lights, with and without their shadows, as a
+// white Lambertian surface there reflects it
+struct AnalyticLighting {
+  shadowed: vec3<f32>,
+  unshadowed: vec3<f32>,
 }
 
-@group(0) @binding(0) var outputTex: texture_storage_2d<rgba8unorm, write>;
+// A point sampled uniformly on the surface of a light
+struct LightSample {
+  point: vec3<f32>,
+  // Facing the point the light is sampled from, for lights that emit from both sides
+  normal: vec3<f32>,
+  radiance: vec3<f32>,
+  area: f32,
+}
+
+// The sum of the samples traced since the last reset, one per pixel in rows of renderSize.x
+@group(0) @binding(0) var<storage, read_write> accumulation: array<vec4<f32>>;
 @group(0) @binding(1) var<uniform> camera: Camera;
 @group(0) @binding(2) var<storage, read> sphereData: SphereData;
 @group(0) @binding(3) var<storage, read> triangles: array<Triangle>;
 @group(0) @binding(4) var<storage, read> triangleIndices: array<u32>;
 @group(0) @binding(5) var<storage, read> bvhNodes: array<Node>;
-@group(0) @binding(6) var<uniform> time: u32;
+// The animation frame, the index of the sample since accumulation started over, and the first
+// row of the band of the image being traced
+@group(0) @binding(6) var<uniform> seed: vec4<u32>;
 @group(0) @binding(7) var skyTexture: texture_cube<f32>;
 @group(0) @binding(8) var skyTextureSampler: sampler;
 @group(0) @binding(9) var<uniform> settings: Settings;
-
+@group(0) @binding(10) var<uniform> renderSize: vec2<u32>;
+@group(0) @binding(11) var<uniform> environment: Environment;
+// How much of the accumulated sum to keep, zero to start over
+@group(0) @binding(12) var<uniform> history: f32;
+// Every emissive surface, sampled directly at diffuse bounces
+@group(0) @binding(14) var<storage, read> lights: Lights;
+@group(0) @binding(15) var<storage, read> analyticLights: AnalyticLights;
+@group(0) @binding(16) var<uniform> instances: Instances;
+// The object the camera ray of each pixel's latest sample hit first, read back for picking
+@group(0) @binding(17) var<storage, read_write> objectIds: array<u32>;
+@group(0) @binding(18) var<uniform> materials: Materials;
+// What's been painted over the albedo of meshes, a layer for each mesh with a canvas
+@group(0) @binding(20) var canvases: texture_2d_array<f32>;
+//!ifdef CAUSTICS
+// The power of the photons that reached each cell of a hashed grid over the scene through
+// specular bounces, divided by the area of the cell, refilled for every sample
+@group(0) @binding(19) var<storage, read_write> causticGrid: array<CausticCell>;
+//!endif
+//!ifdef DENOISE
+// The normal and distance of the surface seen through the center of each pixel, zero where
+// nothing is hit, which guides the denoiser
+//!ifdef PACKED_PAYLOAD
+// Packed into the normal's octahedral encoding and the bits of the distance
+@group(0) @binding(13) var<storage, read_write> gBuffer: array<vec2<u32>>;
+//!else
+@group(0) @binding(13) var<storage, read_write> gBuffer: array<vec4<f32>>;
+//!endif
+//!endif
+
+//!ifdef WORKGROUP_8X8
+@compute @workgroup_size(8, 8)
+//!else
+//!ifdef WORKGROUP_32X8
+@compute @workgroup_size(32, 8)
+//!else
 @compute @workgroup_size(16, 16)
-fn main(@builtin(global_invocation_id) threadId: vec3<u32>) {
-    var randomState: vec4<u32> = vec4<u32>(threadId.xy, threadId.xy + vec2<u32>(1u, 1u) * time);
+//!endif
+//!endif
+fn main(@builtin(global_invocation_id) bandThreadId: vec3<u32>) {
+    let threadId = vec3<u32>(bandThreadId.x, bandThreadId.y + seed.z, bandThreadId.z);
+    var randomState: vec4<u32> = initialRandomState(threadId.xy, seed.xy);
 
-    let screen_size: vec2<u32> = vec2<u32>(textureDimensions(outputTex));
+    let screen_size: vec2<u32> = renderSize;
 
     if threadId.x >= screen_size.x || threadId.y >= screen_size.y {
         return;
     }
 
-    let aspectRatio: f32 = f32(screen_size.x) / f32(screen_size.y);
-
-    let theta = radians(camera.vfov);
-    let h = tan(theta / 2.0);
-    let viewPortHeight: f32 = 2.0 * h * camera.focalLength;
-    let viewPortWidth: f32 = aspectRatio * viewPortHeight;
-
-    let viewPortU: vec3<f32> = viewPortWidth * camera.right;
-    let viewPortV: vec3<f32> = -viewPortHeight * camera.up;
-
-    let pixelDeltaU = viewPortU / f32(screen_size.x);
-    let pixelDeltaV = viewPortV / f32(screen_size.y);
-
-    let upper_left: vec3<f32> = camera.origin + camera.focalLength * camera.forward - 0.5 * (viewPortU + viewPortV);
-    let pixel00Location: vec3<f32> = upper_left + 0.5 * (pixelDeltaU + pixelDeltaV);
-
-    let pixelLocation: vec3<f32> = pixel00Location + f32(threadId.x) * pixelDeltaU + f32(threadId.y) * pixelDeltaV;
+    //!ifdef DENOISE
+    let center = vec2<f32>(threadId.xy) + 0.5;
+    let surface = hitScene(Ray(
+        cameraRayOrigin(camera, center, screen_size),
+        cameraRayDirection(camera, center, screen_size)
+    ));
+    //!ifdef PACKED_PAYLOAD
+    gBuffer[threadId.y * screen_size.x + threadId.x] = select(
+        vec2<u32>(0u),
+        vec2<u32>(packNormal(surface.normal), bitcast<u32>(surface.t)),
+        surface.hit
+    );
+    //!else
+    gBuffer[threadId.y * screen_size.x + threadId.x] = select(
+        vec4<f32>(0.0),
+        vec4<f32>(surface.normal, surface.t),
+        surface.hit
+    );
+    //!endif
+    //!endif
 
     var color: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+    var objectId = NO_OBJECT;
     for (var i = 0u; i < settings.samplesPerPixel; i = i + 1u) {
         let px: f32 = -0.5 + hybridTaus(&randomState).value;
         let py: f32 = -0.5 + hybridTaus(&randomState).value;
 
-        let sample: vec3<f32> = pixelDeltaU * px + pixelDeltaV * py;
-        let sampleLocation: vec3<f32> = pixelLocation + sample;
-
-        let ray: Ray = Ray(camera.origin, sampleLocation - camera.origin);
+        let pixel = vec2<f32>(threadId.xy) + 0.5 + vec2<f32>(px, py);
+        let ray = Ray(
+            cameraRayOrigin(camera, pixel, screen_size),
+            cameraRayDirection(camera, pixel, screen_size)
+        );
 
-        color = color + rayColor(ray, &randomState);
+        color = color + clampRadiance(rayColor(ray, &randomState, &objectId));
     }
 
     color = color / f32(settings.samplesPerPixel);
 
-    let fragColor: vec4<f32> = vec4<f32>(color, 1.0);
-    textureStore(outputTex, vec2<i32>(threadId.xy), fragColor);
+    let index = threadId.y * screen_size.x + threadId.x;
+    objectIds[index] = objectId;
+    var previous = vec4<f32>(0.0);
+    if history > 0.0 {
+        previous = accumulation[index] * history;
+    }
+    accumulation[index] = previous + vec4<f32>(color, 1.0);
 }
 
-fn rayColor(initialRay: Ray, randomState: ptr<function, vec4<u32>>) -> vec3<f32> {
+// Scales down samples brighter than the limit, which trades a little energy for far fewer
+// fireflies. Reference renders are left unbiased.
+fn clampRadiance(radiance: vec3<f32>) -> vec3<f32> {
+    //!ifdef REFERENCE
+    return radiance;
+    //!else
+    let brightest = max(radiance.r, max(radiance.g, radiance.b));
+    if settings.maxRadiance <= 0.0 || brightest <= settings.maxRadiance {
+        return radiance;
+    }
+    return radiance * (settings.maxRadiance / brightest);
+    //!endif
+}
+
+fn rayColor(
+    ray: Ray,
+    randomState: ptr<function, vec4<u32>>,
+    objectId: ptr<function, u32>
+) -> vec3<f32> {
+    //!ifdef DEBUG_NORMALS
+    let hitRecord = hitScene(ray);
+    *objectId = hitRecord.object;
+    if !hitRecord.hit {
+        return vec3<f32>(0.0);
+    }
+    return hitRecord.normal * 0.5 + 0.5;
+    //!else
+    var shadowCatcher = ShadowCatcher(false, vec3<f32>(0.0), vec3<f32>(1.0));
+    let color = tracePath(ray, randomState, &shadowCatcher, objectId);
+    if !shadowCatcher.hit {
+        return color;
+    }
+
+    // The background behind the catcher, darkened by however much of the environment's light the
+    // scene blocks (or brightened by what it reflects) compared to an empty scene
+    return shadowCatcher.background * color / shadowCatcher.lighting;
+    //!endif
+}
+
+fn tracePath(
+    initialRay: Ray,
+    randomState: ptr<function, vec4<u32>>,
+    shadowCatcher: ptr<function, ShadowCatcher>,
+    objectId: ptr<function, u32>
+) -> vec3<f32> {
+    // The throughput of the path so far, and the light sampled directly along it
     var color = vec3<f32>(1.0, 1.0, 1.0);
-    let randomSeed = hybridTaus(randomState).value;
+    var radiance = vec3<f32>(0.0);
+    // Whether the last bounce sampled the lights directly, so hitting one now would count it twice
+    var lightsSampled = false;
+    //!ifdef CAUSTICS
+    // Whether the path has bounced off a diffuse surface, after which the photons stand in for
+    // the light reaching it through specular bounces
+    var diffuseBounced = false;
+    //!endif
+    var randomSeed = hybridTaus(randomState).value;
 
     var correction: u32 = 0u;
 
     var currentRay: Ray = initialRay;
-    for (var i = 0u; i < settings.depth + correction; i = i + 1u) {
+    //!ifdef REFERENCE
+    let depth = REFERENCE_DEPTH;
+    //!else
+    let depth = settings.depth;
+    //!endif
+    for (var i = 0u; i < depth + correction; i = i + 1u) {
+        //!ifdef REFERENCE
+        // Reusing one random number for the whole path correlates the bounces
+        randomSeed = hybridTaus(randomState).value;
+        //!endif
+
         let hitRecord: HitRecord = hitScene(currentRay);
+        // What the camera sees, unless it's a hidden emitter and passed through below
+        if i == correction {
+            *objectId = hitRecord.object;
+        }
 
         if !hitRecord.hit {
-            color = color * getBackgroundColor(currentRay);
-            break;
+            // Only hidden emitters have been passed through, so this is a camera ray
+            if i == correction {
+                return radiance + color * getCameraBackgroundColor(currentRay);
+            }
+            return radiance + color * getBackgroundColor(currentRay);
         }
 
         var bounceDir: vec3<f32>;
         let dir = normalize(currentRay.direction);
-        switch (u32(hitRecord.material)) {
+        var material = u32(hitRecord.material);
+        var attenuation = hitRecord.attenuation;
+        //!ifdef ILLUMINANCE
+        // What the camera sees is measured as if it were white and diffuse, reflecting 1 / π of
+        // the illuminance reaching it. Emitters are left as they are.
+        if i == correction && material != 5u {
+            material = 0u;
+            attenuation = vec3<f32>(1.0);
+        }
+        //!endif
+        switch (material) {
             // Lambertian
             case 0u: {
+                color = color * attenuation;
+                radiance += color * directLight(hitRecord.p, hitRecord.normal, hitRecord.object, randomState);
+                lightsSampled = true;
+                //!ifdef CAUSTICS
+                radiance += color * causticIrradiance(hitRecord.p, randomState) / PI;
+                diffuseBounced = true;
+                //!endif
+
                 bounceDir = scatter(dir, hitRecord.normal, randomSeed);
                 if dot(bounceDir, hitRecord.normal) <= 0.0 {
-                    return color * hitRecord.attenuation * getBackgroundColor(currentRay);
+                    return radiance + color * getBackgroundColor(currentRay);
                 }
-
-                color = color * hitRecord.attenuation;
                 break;
             }
             // Metal
             case 1u: {
-                bounceDir = reflect(dir, hitRecord.normal);
+                lightsSampled = false;
+                bounceDir = fuzzyReflect(dir, hitRecord.normal, hitRecord.roughness, randomUnit(dir.xy * randomSeed));
+                // Rough metals scatter some reflections into themselves
                 if dot(bounceDir, hitRecord.normal) <= 0.0 {
-                    return color * hitRecord.attenuation * getBackgroundColor(currentRay);
+                    return radiance;
                 }
 
                 color = color * hitRecord.attenuation;
@@ -167,14 +530,21 @@ fn rayColor(initialRay: Ray, randomState: ptr<function, vec4<u32>>) -> vec3<f32>
             }
             // Dielectric
             case 2u: {
-                let refractionIndex: f32 = select(1.5, 1.0 / 1.5, hitRecord.frontFace);
+                let refractionIndex: f32 = select(hitRecord.ior, 1.0 / hitRecord.ior, hitRecord.frontFace);
 
                 let cosTheta: f32 = min(dot(-dir, hitRecord.normal), 1.0);
                 let sinTheta: f32 = sqrt(1.0 - cosTheta * cosTheta);
 
                 let cannotRefract: bool = refractionIndex * sinTheta > 1.0;
 
-                if cannotRefract || reflectance(cosTheta, refractionIndex) > rand(hitRecord.p.xy) {
+                //!ifdef REFERENCE
+                let threshold = hybridTaus(randomState).value;
+                //!else
+                let threshold = rand(hitRecord.p.xy);
+                //!endif
+
+                lightsSampled = false;
+                if cannotRefract || reflectance(cosTheta, refractionIndex) > threshold {
                     bounceDir = reflect(dir, hitRecord.normal);
                 } else {
                     bounceDir = refract(dir, hitRecord.normal, refractionIndex);
@@ -183,17 +553,57 @@ fn rayColor(initialRay: Ray, randomState: ptr<function, vec4<u32>>) -> vec3<f32>
                 color = color * hitRecord.attenuation;
                 break;
             }
-            // Gizmo
-            case 3u: {
-                let dot = dot(initialRay.direction, hitRecord.normal);
-                if i == 0u && dot <= 0.2 && dot >= -0.2 {
-                    return hitRecord.attenuation;
+            //!ifdef SHADOW_CATCHER
+            // Shadow catcher
+            case 4u: {
+                if i == correction {
+                    bounceDir = scatter(dir, hitRecord.normal, randomSeed);
+                    // The analytic lights are never hit by the path, so their shadows are
+                    // compared directly
+                    let analytic = analyticLight(hitRecord.p, hitRecord.normal, hitRecord.object, randomState);
+                    radiance += color * analytic.shadowed;
+                    (*shadowCatcher).hit = true;
+                    (*shadowCatcher).background = getCameraBackgroundColor(currentRay);
+                    (*shadowCatcher).lighting = max(
+                        getBackgroundColor(Ray(hitRecord.p, bounceDir)) + analytic.unshadowed,
+                        vec3<f32>(0.0001)
+                    );
+                    break;
+                }
+
+                // Other surfaces are lit by it like a diffuse surface
+                color = color * hitRecord.attenuation;
+                radiance += color * directLight(hitRecord.p, hitRecord.normal, hitRecord.object, randomState);
+                lightsSampled = true;
+
+                bounceDir = scatter(dir, hitRecord.normal, randomSeed);
+                if dot(bounceDir, hitRecord.normal) <= 0.0 {
+                    return radiance + color * getBackgroundColor(currentRay);
                 }
-                bounceDir = dir;
-                correction = correction + 1u;
                 break;
             }
+            //!endif
+            // Emissive, where the attenuation is the emitted radiance
+            case 5u: {
+                // Passed through by camera rays, though it still lights the scene. Whether the
+                // lights were sampled carries over.
+                if hitRecord.hiddenFromCamera && i == correction {
+                    bounceDir = dir;
+                    correction = correction + 1u;
+                    break;
+                }
+                if lightsSampled {
+                    return radiance;
+                }
+                //!ifdef CAUSTICS
+                if diffuseBounced {
+                    return radiance;
+                }
+                //!endif
+                return radiance + color * hitRecord.attenuation;
+            }
             default: {
+                lightsSampled = false;
                 bounceDir = scatter(dir, hitRecord.normal, randomSeed);
                 color = color * hitRecord.attenuation;
                 break;
@@ -203,12 +613,363 @@ fn rayColor(initialRay: Ray, randomState: ptr<function, vec4<u32>>) -> vec3<f32>
         currentRay = Ray(hitRecord.p, bounceDir);
     }
 
-    return color;
+    return radiance + color;
+}
+
+//!ifdef CAUSTICS
+// Traces a photon from a random point on a random emissive surface for each of settings.photons,
+// through metals and glass, into the caustic grid cell of the first diffuse surface it reaches.
+// Photons reaching one without a specular bounce are dropped, the path tracer samples that light.
+@compute @workgroup_size(64)
+fn emitPhotons(@builtin(global_invocation_id) threadId: vec3<u32>) {
+    if threadId.x >= settings.photons || lights.count == 0u {
+        return;
+    }
+    var randomState = initialRandomState(vec2<u32>(threadId.x, 0xffffffffu), seed.xy);
+
+    let pick = min(u32(hybridTaus(&randomState).value * f32(lights.count)), lights.count - 1u);
+    let light = lights.lights[pick];
+    let u = vec2<f32>(hybridTaus(&randomState).value, hybridTaus(&randomState).value);
+    let sample = sampleLight(light, vec3<f32>(0.0), u);
+    // Triangles emit from both sides, so a photon leaves from a random one
+    var normal = sample.normal;
+    var sides = 1.0;
+    if light.kind == LIGHT_TRIANGLE {
+        sides = 2.0;
+        if hybridTaus(&randomState).value < 0.5 {
+            normal = -normal;
+        }
+    }
+
+    // Cosine-weighted, so every photon carries the same share of the light's power
+    var direction = normalize(normal + randomUnitVector(&randomState));
+    var power = sample.radiance * sample.area * PI * sides * f32(lights.count) / f32(settings.photons);
+    var ray = Ray(sample.point, direction);
+    var specular = false;
+    for (var i = 0u; i < settings.depth; i++) {
+        let hitRecord = hitScene(ray);
+        if !hitRecord.hit {
+            return;
+        }
+
+        let dir = normalize(ray.direction);
+        switch (u32(hitRecord.material)) {
+            case 0u: {
+                if specular {
+                    depositPhoton(hitRecord.p, power);
+                }
+                return;
+            }
+            case 1u: {
+                direction = fuzzyReflect(dir, hitRecord.normal, hitRecord.roughness, randomUnitVector(&randomState));
+                if dot(direction, hitRecord.normal) <= 0.0 {
+                    return;
+                }
+            }
+            case 2u: {
+                let refractionIndex: f32 = select(hitRecord.ior, 1.0 / hitRecord.ior, hitRecord.frontFace);
+                let cosTheta: f32 = min(dot(-dir, hitRecord.normal), 1.0);
+                let sinTheta: f32 = sqrt(1.0 - cosTheta * cosTheta);
+                let cannotRefract = refractionIndex * sinTheta > 1.0;
+                let threshold = hybridTaus(&randomState).value;
+                if cannotRefract || reflectance(cosTheta, refractionIndex) > threshold {
+                    direction = reflect(dir, hitRecord.normal);
+                } else {
+                    direction = refract(dir, hitRecord.normal, refractionIndex);
+                }
+            }
+            default: {
+                return;
+            }
+        }
+        power *= hitRecord.attenuation;
+        specular = true;
+        ray = Ray(hitRecord.p, direction);
+    }
+}
+
+// A direction picked uniformly over the sphere
+fn randomUnitVector(randomState: ptr<function, vec4<u32>>) -> vec3<f32> {
+    let z = 1.0 - 2.0 * hybridTaus(randomState).value;
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    let phi = 2.0 * PI * hybridTaus(randomState).value;
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn depositPhoton(point: vec3<f32>, power: vec3<f32>) {
+    let index = findCausticCell(point, true);
+    if index == NO_CAUSTIC_CELL {
+        return;
+    }
+    // A surface crossing a cell covers about the area of one of its faces
+    let irradiance = power / (settings.causticRadius * settings.causticRadius);
+    for (var channel = 0u; channel < 3u; channel++) {
+        var old = atomicLoad(&causticGrid[index].irradiance[channel]);
+        loop {
+            let added = bitcast<u32>(bitcast<f32>(old) + irradiance[channel]);
+            let result = atomicCompareExchangeWeak(&causticGrid[index].irradiance[channel], old, added);
+            if result.exchanged {
+                break;
+            }
+            old = result.old_value;
+        }
+    }
+}
+
+// The irradiance photons brought to the cell around `point`, which is jittered by up to half a
+// cell so the cells' edges blur away as samples accumulate
+fn causticIrradiance(point: vec3<f32>, randomState: ptr<function, vec4<u32>>) -> vec3<f32> {
+    let jitter = vec3<f32>(
+        hybridTaus(randomState).value,
+        hybridTaus(randomState).value,
+        hybridTaus(randomState).value
+    ) - 0.5;
+    let index = findCausticCell(point + jitter * settings.causticRadius, false);
+    if index == NO_CAUSTIC_CELL {
+        return vec3<f32>(0.0);
+    }
+    return vec3<f32>(
+        bitcast<f32>(atomicLoad(&causticGrid[index].irradiance[0])),
+        bitcast<f32>(atomicLoad(&causticGrid[index].irradiance[1])),
+        bitcast<f32>(atomicLoad(&causticGrid[index].irradiance[2]))
+    );
+}
+
+// The grid cell `point` falls in, claiming an unused one for it when `claim` is set.
+// NO_CAUSTIC_CELL if it has none, or every cell it could have is taken by others.
+fn findCausticCell(point: vec3<f32>, claim: bool) -> u32 {
+    let cell = vec3<i32>(floor(point / settings.causticRadius));
+    let key = pcgHash(bitcast<u32>(cell.x) ^ pcgHash(bitcast<u32>(cell.y) ^ pcgHash(bitcast<u32>(cell.z)))) | 1u;
+    var index = pcgHash(key) % CAUSTIC_GRID_CELLS;
+    for (var probe = 0u; probe < CAUSTIC_GRID_PROBES; probe++) {
+        if claim {
+            loop {
+                let result = atomicCompareExchangeWeak(&causticGrid[index].key, 0u, key);
+                if result.exchanged || result.old_value == key {
+                    return index;
+                }
+                // Weak exchanges can fail spuriously while the cell is still unused
+                if result.old_value != 0u {
+                    break;
+                }
+            }
+        } else {
+            let found = atomicLoad(&causticGrid[index].key);
+            if found == key {
+                return index;
+            }
+            if found == 0u {
+                return NO_CAUSTIC_CELL;
+            }
+        }
+        index = (index + 1u) % CAUSTIC_GRID_CELLS;
+    }
+    return NO_CAUSTIC_CELL;
+}
+//!endif
+
+// The light reaching `point` on a diffuse surface of `object` facing `normal` straight from a
+// random emissive surface and every analytic light linked to it, without the albedo
+fn directLight(
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    object: u32,
+    randomState: ptr<function, vec4<u32>>
+) -> vec3<f32> {
+    let analytic = analyticLight(point, normal, object, randomState).shadowed;
+    if lights.count == 0u {
+        return analytic;
+    }
+
+    let pick = min(u32(hybridTaus(randomState).value * f32(lights.count)), lights.count - 1u);
+    let light = lights.lights[pick];
+    if !emitterIlluminates(light, object) {
+        return analytic;
+    }
+    let u = vec2<f32>(hybridTaus(randomState).value, hybridTaus(randomState).value);
+    let sample = sampleLight(light, point, u);
+
+    let toLight = sample.point - point;
+    let distanceSquared = dot(toLight, toLight);
+    let direction = toLight * inverseSqrt(distanceSquared);
+    let cosSurface = dot(normal, direction);
+    let cosLight = -dot(sample.normal, direction);
+    if cosSurface <= 0.0 || cosLight <= 0.0 {
+        return analytic;
+    }
+
+    // The light is at t = 1 along the unnormalized direction
+    let occluder = hitScene(Ray(point, toLight));
+    if occluder.hit && occluder.t < 0.999 {
+        return analytic;
+    }
+
+    // The Lambertian BRDF is 1 / PI, and the sample's probability density by area is
+    // 1 / (count * area)
+    let geometry = cosSurface * cosLight / distanceSquared;
+    return analytic + sample.radiance * geometry * sample.area * f32(lights.count) / PI;
+}
+
+// Where the links of `object`, which isn't NO_OBJECT, are in the arrays of analyticLights
+fn linkedIndex(object: u32) -> u32 {
+    if (object & OBJECT_INSTANCE) != 0u {
+        return LINKED_SPHERES + (object & ~OBJECT_INSTANCE);
+    }
+    return object;
+}
+
+// The lights that don't illuminate `object`, a bit for each
+fn unlitMask(object: u32) -> vec2<u32> {
+    if object == NO_OBJECT {
+        return vec2<u32>(0u);
+    }
+    return analyticLights.unlit[linkedIndex(object)];
+}
+
+// Whether the sphere or instance the emissive surface `light` is part of illuminates `object`
+fn emitterIlluminates(light: Light, object: u32) -> bool {
+    if object == NO_OBJECT {
+        return true;
+    }
+    let emitter = select(light.index, LINKED_SPHERES + light.instance, light.kind == LIGHT_TRIANGLE);
+    let slot = analyticLights.emitterSlots[emitter];
+    if slot == NO_LINK_SLOT {
+        return true;
+    }
+    return (analyticLights.unlitByEmitters[linkedIndex(object)] & (1u << slot)) == 0u;
+}
+
+fn analyticLight(
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    object: u32,
+    randomState: ptr<function, vec4<u32>>
+) -> AnalyticLighting {
+    var lighting = AnalyticLighting(vec3<f32>(0.0), vec3<f32>(0.0));
+    let unlit = unlitMask(object);
+    for (var i = 0u; i < analyticLights.count; i++) {
+        if (unlit[i / 32u] & (1u << (i % 32u))) != 0u {
+            continue;
+        }
+        let light = analyticLights.lights[i];
+
+        // Shadow rays end at the light, at t = 1 for point and quad lights
+        var toLight: vec3<f32>;
+        var falloff: f32;
+        var tEnd: f32;
+        if light.kind == ANALYTIC_LIGHT_DIRECTIONAL {
+            toLight = -light.vector;
+            falloff = 1.0;
+            tEnd = settings.tMax;
+        } else if light.kind == ANALYTIC_LIGHT_QUAD {
+            // One uniform point on the rectangle per light, whose density by area is 1 / area
+            let s = hybridTaus(randomState).value;
+            let t = hybridTaus(randomState).value;
+            toLight = light.vector + light.u * s + light.v * t - point;
+            let distanceSquared = max(dot(toLight, toLight), 0.0001);
+            // The normal scaled by the area, and the quad only emits from its front
+            let areaNormal = cross(light.u, light.v);
+            let cosLightArea = -dot(areaNormal, toLight) * inverseSqrt(distanceSquared);
+            falloff = max(cosLightArea, 0.0) / distanceSquared;
+            tEnd = 0.999;
+        } else {
+            toLight = light.vector - point;
+            falloff = 1.0 / max(dot(toLight, toLight), 0.0001);
+            tEnd = 0.999;
+        }
+
+        let cosSurface = dot(normal, normalize(toLight));
+        if cosSurface <= 0.0 || falloff <= 0.0 {
+            continue;
+        }
+        let radiance = light.radiance * cosSurface * falloff / PI;
+        lighting.unshadowed += radiance;
+        let occluder = hitScene(Ray(point, toLight));
+        if occluder.hit && occluder.t < tEnd {
+            continue;
+        }
+
+        lighting.shadowed += radiance;
+    }
+    return lighting;
+}
+
+fn sampleLight(light: Light, origin: vec3<f32>, u: vec2<f32>) -> LightSample {
+    if light.kind == LIGHT_SPHERE {
+        let sphere = sphereData.spheres[light.index];
+        let z = 1.0 - 2.0 * u.x;
+        let r = sqrt(max(1.0 - z * z, 0.0));
+        let phi = 2.0 * PI * u.y;
+        let normal = vec3<f32>(r * cos(phi), r * sin(phi), z);
+        let point = sphere.center + normal * sphere.radius;
+        return LightSample(
+            point,
+            normal,
+            materials.materials[sphere.material].albedo * sphereAlbedoScale(light.index, point),
+            4.0 * PI * sphere.radius * sphere.radius
+        );
+    }
+
+    let triangle = triangles[light.index];
+    let objectToWorld = instances.instances[light.instance].objectToWorld;
+    let a = (objectToWorld * vec4<f32>(triangle.a, 1.0)).xyz;
+    let b = (objectToWorld * vec4<f32>(triangle.b, 1.0)).xyz;
+    let c = (objectToWorld * vec4<f32>(triangle.c, 1.0)).xyz;
+    let root = sqrt(u.x);
+    let point = a * (1.0 - root) + b * (root * (1.0 - u.y)) + c * (root * u.y);
+    let perpendicular = cross(b - a, c - a);
+    let normal = normalize(perpendicular);
+    return LightSample(
+        point,
+        select(-normal, normal, dot(origin - point, normal) > 0.0),
+        materials.materials[triangle.material].albedo,
+        0.5 * length(perpendicular)
+    );
 }
 
 fn getBackgroundColor(ray: Ray) -> vec3<f32> {
-    let bgColor: vec4<f32> = textureSampleLevel(skyTexture, skyTextureSampler, ray.direction, 0.0);
-    return bgColor.rgb;
+    let cosYaw = cos(environment.yaw);
+    let sinYaw = sin(environment.yaw);
+    var direction = vec3<f32>(
+        cosYaw * ray.direction.x + sinYaw * ray.direction.z,
+        ray.direction.y,
+        -sinYaw * ray.direction.x + cosYaw * ray.direction.z
+    );
+
+    var scale = environment.intensity;
+    if environment.sunRadius > 0.0 {
+        let sunAngle = acos(clamp(dot(direction, environment.sunDirection), -1.0, 1.0));
+        let resizedRadius = environment.sunRadius * environment.sunSize;
+        if sunAngle < resizedRadius {
+            // Stretches or squeezes the sun's disk to the new size, dimming it by as much as it
+            // grows so the power it lights the scene with stays the same
+            direction = rotateFrom(environment.sunDirection, direction, sunAngle / environment.sunSize);
+            scale *= environment.sunIntensity / (environment.sunSize * environment.sunSize);
+        } else if sunAngle < environment.sunRadius {
+            // Where a shrunk sun used to be, shows the sky just outside it instead
+            direction = rotateFrom(environment.sunDirection, direction, environment.sunRadius * 1.5);
+        }
+    }
+
+    let bgColor: vec4<f32> = textureSampleLevel(skyTexture, skyTextureSampler, direction, 0.0);
+    return bgColor.rgb * scale;
+}
+
+// The direction `angle` radians away from `center`, towards `direction`
+fn rotateFrom(center: vec3<f32>, direction: vec3<f32>, angle: f32) -> vec3<f32> {
+    let away = direction - center * dot(direction, center);
+    if dot(away, away) < 1e-12 {
+        return center;
+    }
+    return center * cos(angle) + normalize(away) * sin(angle);
+}
+
+// What camera rays see when they miss the scene
+fn getCameraBackgroundColor(ray: Ray) -> vec3<f32> {
+    if environment.showBackground == 0u {
+        return environment.backgroundColor;
+    }
+    return getBackgroundColor(ray);
 }
 
 fn hitScene(ray: Ray) -> HitRecord {
@@ -219,9 +980,15 @@ fn hitScene(ray: Ray) -> HitRecord {
         vec3<f32>(0.0, 0.0, 0.0),
         false,
         vec3<f32>(0.0, 0.0, 0.0),
-        0.0
+        0.0,
+        0.0,
+        0.0,
+        false,
+        NO_OBJECT,
+        vec2<f32>(0.0, 0.0)
     );
 
+    var closestSphere = 0u;
     for (var i = 0u; i < sphereData.sphereCount; i = i + 1u) {
         let sphere = sphereData.spheres[i];
         let objectHitRecord = hitSphere(ray, sphere);
@@ -232,21 +999,32 @@ fn hitScene(ray: Ray) -> HitRecord {
 
         if !hitRecord.hit || objectHitRecord.t < hitRecord.t {
             hitRecord = objectHitRecord;
+            closestSphere = i;
         }
     }
+    // Only evaluated for the closest sphere, even if a triangle turns out to be closer still
+    if hitRecord.hit {
+        hitRecord.attenuation *= sphereAlbedoScale(closestSphere, hitRecord.p);
+        hitRecord.hiddenFromCamera = sphereData.hiddenFromCamera[closestSphere] != 0u;
+        hitRecord.object = closestSphere;
+    }
 
 
-    var node: Node = bvhNodes[0u];
+    if instances.count == 0u {
+        return hitRecord;
+    }
+
+    var node: Node = instances.tlas[0u];
     var stack: array<Node, 15>;
     var stackLocation: u32 = 0u;
-    var nearestHit: f32 = 9999.0;
+    var nearestHit: f32 = select(9999.0, hitRecord.t, hitRecord.hit);
 
     while true {
         var contents: u32 = u32(node.leftChildIndex);
 
         if node.triangleCount == 0u {
-            var child1: Node = bvhNodes[contents];
-            var child2: Node = bvhNodes[contents + 1u];
+            var child1: Node = instances.tlas[contents];
+            var child2: Node = instances.tlas[contents + 1u];
 
             var distance1: f32 = hitAabb(ray, child1);
             var distance2: f32 = hitAabb(ray, child2);
@@ -276,8 +1054,7 @@ fn hitScene(ray: Ray) -> HitRecord {
             }
         } else {
             for (var i = 0u; i < node.triangleCount; i++) {
-                let triangle = triangles[(triangleIndices[i + contents])];
-                let objectHitRecord = hitTriangle(ray, triangle);
+                let objectHitRecord = hitInstance(ray, instances.instances[i + contents], nearestHit);
 
                 if !objectHitRecord.hit {
                      continue;
@@ -301,12 +1078,274 @@ fn hitScene(ray: Ray) -> HitRecord {
     return hitRecord;
 }
 
+// Traces the ray through the instance's mesh in object space, where distances along it stay
+// the same since its direction isn't renormalized
+fn hitInstance(ray: Ray, instance: Instance, nearestHit: f32) -> HitRecord {
+    let localRay = Ray(
+        (instance.worldToObject * vec4<f32>(ray.origin, 1.0)).xyz,
+        (instance.worldToObject * vec4<f32>(ray.direction, 0.0)).xyz
+    );
+    var hitRecord = hitBlas(localRay, instance.root, nearestHit);
+    if hitRecord.hit {
+        hitRecord.p = ray.origin + hitRecord.t * ray.direction;
+        // Normals transform by the inverse transpose, which keeps them on the ray's side
+        let normal = transpose(instance.worldToObject) * vec4<f32>(hitRecord.normal, 0.0);
+        hitRecord.normal = normalize(normal.xyz);
+        hitRecord.hiddenFromCamera = instance.hiddenFromCamera != 0u;
+        hitRecord.object = OBJECT_INSTANCE | instance.index;
+        // Lights are sampled by the albedo of their material alone
+        if instance.canvas != NO_CANVAS && u32(hitRecord.material) != 5u {
+            hitRecord.attenuation *= canvasAlbedo(instance.canvas, hitRecord.uv);
+        }
+    }
+    return hitRecord;
+}
+
+// The color painted on `layer` of the canvases at `uv`, which wraps around like the canvas does
+// when painted on
+fn canvasAlbedo(layer: u32, uv: vec2<f32>) -> vec3<f32> {
+    let size = textureDimensions(canvases);
+    let wrapped = fract(vec2<f32>(uv.x, 1.0 - uv.y));
+    let texel = min(vec2<u32>(wrapped * vec2<f32>(size)), size - 1u);
+    return textureLoad(canvases, texel, layer, 0).rgb;
+}
+
+// The closest hit nearer than `nearestHit` on the triangles of the BVH starting at `root`
+fn hitBlas(ray: Ray, root: u32, nearestHit: f32) -> HitRecord {
+    var hitRecord: HitRecord = HitRecord(
+        false,
+        0.0,
+        vec3<f32>(0.0, 0.0, 0.0),
+        vec3<f32>(0.0, 0.0, 0.0),
+        false,
+        vec3<f32>(0.0, 0.0, 0.0),
+        0.0,
+        0.0,
+        0.0,
+        false,
+        NO_OBJECT,
+        vec2<f32>(0.0, 0.0)
+    );
+
+    var node: Node = bvhNodes[root];
+    var stack: array<Node, 15>;
+    var stackLocation: u32 = 0u;
+    var nearest: f32 = nearestHit;
+
+    while true {
+        var contents: u32 = u32(node.leftChildIndex);
+
+        if node.triangleCount == 0u {
+            var child1: Node = bvhNodes[contents];
+            var child2: Node = bvhNodes[contents + 1u];
+
+            var distance1: f32 = hitAabb(ray, child1);
+            var distance2: f32 = hitAabb(ray, child2);
+            if distance1 > distance2 {
+                var tempDist: f32 = distance1;
+                distance1 = distance2;
+                distance2 = tempDist;
+
+                var tempChild: Node = child1;
+                child1 = child2;
+                child2 = tempChild;
+            }
+
+            if distance1 > nearest {
+                if stackLocation == 0u {
+                     break;
+                } else {
+                    stackLocation -= 1u;
+                    node = stack[stackLocation];
+                }
+            } else {
+                node = child1;
+                if distance2 < nearest {
+                    stack[stackLocation] = child2;
+                    stackLocation += 1u;
+                }
+            }
+        } else {
+            for (var i = 0u; i < node.triangleCount; i++) {
+                let triangle = triangles[(triangleIndices[i + contents])];
+                let objectHitRecord = hitTriangle(ray, triangle);
+
+                if !objectHitRecord.hit || objectHitRecord.t >= nearest {
+                     continue;
+                }
+
+                hitRecord = objectHitRecord;
+                nearest = hitRecord.t;
+            }
+
+            if stackLocation == 0u {
+                 break;
+            } else {
+                stackLocation -= 1u;
+                node = stack[stackLocation];
+            }
+        }
+    }
+
+    return hitRecord;
+}
+
+// What a sphere's albedo expression multiplies its albedo by at `point` on its surface, or one
+// if it has none
+fn sphereAlbedoScale(index: u32, point: vec3<f32>) -> vec3<f32> {
+    let start = sphereData.albedoExpressions[index];
+    if start == NO_EXPRESSION {
+        return vec3<f32>(1.0);
+    }
+    let position = point - sphereData.spheres[index].center;
+    return evaluateExpression(start, position, normalize(position));
+}
+
+// Runs the expression starting at `start` in expressionCode, which leaves one value for a gray
+// or three for a color on the stack
+fn evaluateExpression(start: u32, position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var stack: array<f32, EXPRESSION_STACK_SIZE>;
+    var locals: array<f32, EXPRESSION_LOCALS>;
+    var depth = 0u;
+    let uv = sphereTextureCoordinates(normal);
+    let end = min(start + EXPRESSION_LENGTH, EXPRESSION_CODE_SIZE);
+    for (var i = start; i < end; i++) {
+        let instruction = sphereData.expressionCode[i];
+        let op = instruction.op;
+        if op == OP_END {
+            break;
+        }
+
+        if op == OP_STORE {
+            depth--;
+            locals[min(u32(instruction.value), EXPRESSION_LOCALS - 1u)] = stack[depth];
+        } else if op <= OP_LOAD {
+            var value = instruction.value;
+            switch op {
+                case OP_X { value = position.x; }
+                case OP_Y { value = position.y; }
+                case OP_Z { value = position.z; }
+                case OP_NORMAL_X { value = normal.x; }
+                case OP_NORMAL_Y { value = normal.y; }
+                case OP_NORMAL_Z { value = normal.z; }
+                case OP_TIME { value = sphereData.time; }
+                case OP_U { value = uv.x; }
+                case OP_V { value = uv.y; }
+                case OP_LOAD { value = locals[min(u32(instruction.value), EXPRESSION_LOCALS - 1u)]; }
+                default {}
+            }
+            stack[depth] = value;
+            depth++;
+        } else if op <= OP_SQRT {
+            let a = stack[depth - 1u];
+            var value = -a;
+            switch op {
+                case OP_SIN { value = sin(a); }
+                case OP_COS { value = cos(a); }
+                case OP_ABS { value = abs(a); }
+                case OP_FLOOR { value = floor(a); }
+                case OP_FRACT { value = fract(a); }
+                case OP_SQRT { value = sqrt(a); }
+                default {}
+            }
+            stack[depth - 1u] = value;
+        } else if op <= OP_STEP {
+            let a = stack[depth - 2u];
+            let b = stack[depth - 1u];
+            var value = a + b;
+            switch op {
+                case OP_SUBTRACT { value = a - b; }
+                case OP_MULTIPLY { value = a * b; }
+                case OP_DIVIDE { value = a / b; }
+                case OP_MIN { value = min(a, b); }
+                case OP_MAX { value = max(a, b); }
+                case OP_POW { value = pow(a, b); }
+                case OP_STEP { value = step(a, b); }
+                default {}
+            }
+            stack[depth - 2u] = value;
+            depth--;
+        } else {
+            let a = stack[depth - 3u];
+            let b = stack[depth - 2u];
+            let c = stack[depth - 1u];
+            var value = mix(a, b, c);
+            switch op {
+                case OP_CLAMP { value = clamp(a, b, c); }
+                case OP_SMOOTHSTEP { value = smoothstep(a, b, c); }
+                case OP_NOISE { value = valueNoise(vec3<f32>(a, b, c)); }
+                case OP_CANVAS_RED { value = expressionCanvas(a, b, c).r; }
+                case OP_CANVAS_GREEN { value = expressionCanvas(a, b, c).g; }
+                case OP_CANVAS_BLUE { value = expressionCanvas(a, b, c).b; }
+                default {}
+            }
+            stack[depth - 3u] = value;
+            depth -= 2u;
+        }
+    }
+
+    let color = select(vec3<f32>(stack[0]), vec3<f32>(stack[0], stack[1], stack[2]), depth == 3u);
+    return max(color, vec3<f32>(0.0));
+}
+
+// The U around the sphere with `normal` from the negative X axis, and the V up it from the
+// bottom. Matches texture_coordinates in the renderer
+fn sphereTextureCoordinates(normal: vec3<f32>) -> vec2<f32> {
+    return vec2<f32>(
+        0.5 + atan2(normal.z, normal.x) / (2.0 * PI),
+        0.5 + asin(clamp(normal.y, -1.0, 1.0)) / PI
+    );
+}
+
+// What an expression reads from the canvas numbered `layer` at `u` and `v`, white past the last.
+// The texture is a single texel when there are no canvases
+fn expressionCanvas(layer: f32, u: f32, v: f32) -> vec3<f32> {
+    let missing = layer < 0.0 || u32(layer) >= u32(textureNumLayers(canvases));
+    if missing || textureDimensions(canvases).x == 1u {
+        return vec3<f32>(1.0);
+    }
+    return canvasAlbedo(u32(layer), vec2<f32>(u, v));
+}
+
+// Trilinearly interpolates a random value between 0 and 1 at each corner of the unit cube
+// around `point`, eased so its slope is continuous. Matches value_noise in the renderer.
+fn valueNoise(point: vec3<f32>) -> f32 {
+    let cell = floor(point);
+    let f = point - cell;
+    let eased = f * f * (3.0 - 2.0 * f);
+    let c = bitcast<vec3<u32>>(vec3<i32>(cell));
+    let face0 = mix(
+        mix(latticeHash(c), latticeHash(c + vec3<u32>(1u, 0u, 0u)), eased.x),
+        mix(latticeHash(c + vec3<u32>(0u, 1u, 0u)), latticeHash(c + vec3<u32>(1u, 1u, 0u)), eased.x),
+        eased.y
+    );
+    let face1 = mix(
+        mix(latticeHash(c + vec3<u32>(0u, 0u, 1u)), latticeHash(c + vec3<u32>(1u, 0u, 1u)), eased.x),
+        mix(latticeHash(c + vec3<u32>(0u, 1u, 1u)), latticeHash(c + vec3<u32>(1u, 1u, 1u)), eased.x),
+        eased.y
+    );
+    return mix(face0, face1, eased.z);
+}
+
+// A random value between 0 and 1 for a corner of the noise lattice. Matches lattice_hash in the
+// renderer.
+fn latticeHash(corner: vec3<u32>) -> f32 {
+    var hash = (corner.x * 0x8da6b343u) ^ (corner.y * 0xd8163841u) ^ (corner.z * 0xcb1ab31fu);
+    hash ^= hash >> 16u;
+    hash *= 0x7feb352du;
+    hash ^= hash >> 15u;
+    hash *= 0x846ca68bu;
+    hash ^= hash >> 16u;
+    return f32(hash >> 8u) / 16777216.0;
+}
+
 fn hitSphere(ray: Ray, sphere: Sphere) -> HitRecord {
     let centerToRayOrigin: vec3<f32> = ray.origin - sphere.center;
     let a: f32 = dot(ray.direction, ray.direction);
     let b: f32 = 2.0 * dot(ray.direction, centerToRayOrigin);
     let c: f32 = dot(centerToRayOrigin, centerToRayOrigin) - sphere.radius * sphere.radius;
     let discriminant: f32 = b * b - 4.0 * a * c;
+    let material = materials.materials[sphere.material];
 
     var hitRecord: HitRecord = HitRecord(
         false,
@@ -314,8 +1353,13 @@ fn hitSphere(ray: Ray, sphere: Sphere) -> HitRecord {
         vec3<f32>(0.0, 0.0, 0.0),
         vec3<f32>(0.0, 0.0, 0.0),
         false,
-        sphere.albedo,
-        sphere.material
+        material.albedo,
+        f32(material.kind),
+        material.roughness,
+        material.ior,
+        false,
+        NO_OBJECT,
+        vec2<f32>(0.0, 0.0)
     );
 
     if discriminant < 0.0 {
@@ -339,13 +1383,6 @@ fn hitSphere(ray: Ray, sphere: Sphere) -> HitRecord {
     hitRecord.frontFace = dot(ray.direction, outwardNormal) < 0.0;
     hitRecord.normal = select(-outwardNormal, outwardNormal, hitRecord.frontFace);
 
-    if u32(sphere.material) == 3u {
-        let dot = dot(ray.direction, hitRecord.normal);
-        if !(dot <= 0.2 && dot >= -0.2) {
-            hitRecord.hit = false;
-        }
-    }
-
     return hitRecord;
 }
 
@@ -355,6 +1392,7 @@ fn hitTriangle(ray: Ray, triangle: Triangle) -> HitRecord {
 
     let h: vec3<f32> = cross(ray.direction, edge2);
     let a: f32 = dot(edge1, h);
+    let material = materials.materials[triangle.material];
 
     var hitRecord: HitRecord = HitRecord(
         false,
@@ -362,8 +1400,13 @@ fn hitTriangle(ray: Ray, triangle: Triangle) -> HitRecord {
         vec3<f32>(0.0, 0.0, 0.0),
         vec3<f32>(0.0, 0.0, 0.0),
         false,
-        vec3<f32>(1.0, 1.0, 1.0),
-        0.0,
+        material.albedo,
+        f32(material.kind),
+        material.roughness,
+        material.ior,
+        false,
+        NO_OBJECT,
+        vec2<f32>(0.0, 0.0)
     );
 
     if a > -0.00001 && a < 0.00001 {
@@ -401,6 +1444,7 @@ fn hitTriangle(ray: Ray, triangle: Triangle) -> HitRecord {
     let outwardNormal: vec3<f32> = normalize(triangle.an * barycentric.x + triangle.bn * barycentric.y + triangle.cn * barycentric.z);
     hitRecord.frontFace = dot(ray.direction, outwardNormal) < 0.0;
     hitRecord.normal = select(-outwardNormal, outwardNormal, hitRecord.frontFace);
+    hitRecord.uv = triangle.ta * barycentric.x + triangle.tb * barycentric.y + triangle.tc * barycentric.z;
 
     return hitRecord;
 }
@@ -416,10 +1460,13 @@ fn scatter(dir: vec3<f32 >, normal: vec3<f32>, seed: f32) -> vec3<f32> {
 }
 
 fn reflect(dir: vec3<f32 >, normal: vec3<f32>) -> vec3<f32> {
-    let reflected: vec3<f32> = normalize(dir - 2.0 * dot(dir, normal) * normal);
-    let fuzz: f32 = 0.0;
+    return normalize(dir - 2.0 * dot(dir, normal) * normal);
+}
 
-    return reflected + fuzz * randomUnit(dir.xy) * dot(reflected, normal);
+// The mirror direction pushed towards `randomDirection`, a random unit vector, by `fuzz`. Goes
+// below the surface now and then for rough metals, which the callers treat as absorbed.
+fn fuzzyReflect(dir: vec3<f32>, normal: vec3<f32>, fuzz: f32, randomDirection: vec3<f32>) -> vec3<f32> {
+    return normalize(reflect(dir, normal) + fuzz * randomDirection);
 }
 
 fn reflectance(cosine: f32, refIdx: f32) -> f32 {
@@ -1,37 +1,162 @@
-struct VertexOutput {
-    @location(0) tex_coord: vec2<f32>,
-    @builtin(position) position: vec4<f32>,
-};
+//!include "fullscreen.wgsl"
 
-@vertex
-fn vs_main(
-    @builtin(vertex_index) in_vertex_index: u32,
-    @builtin(instance_index) in_instance_index: u32
-) -> VertexOutput {
-    var out: VertexOutput;
-    let x = f32((in_vertex_index & 1u) ^ in_instance_index);
-    let y = f32((in_vertex_index >> 1u) ^ in_instance_index);
-    out.position = vec4<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0, 1.0);
-    out.tex_coord = vec2<f32>(x, y);
-    return out;
+struct Resolve {
+    samples: u32,
+    scale: f32,
+    offset: vec2<u32>,
+    // What the luminance is multiplied by for the false color bands, zero when it's off
+    falseColorNits: f32,
+    width: u32,
+    // One of the TONE_MAPPING_ constants
+    toneMapping: u32,
+    // The factor the radiance is multiplied by before tone mapping
+    exposure: f32,
 }
 
+const TONE_MAPPING_LINEAR: u32 = 0u;
+const TONE_MAPPING_REINHARD: u32 = 1u;
+const TONE_MAPPING_ACES: u32 = 2u;
+const TONE_MAPPING_UNCHARTED2: u32 = 3u;
+const TONE_MAPPING_DISPLAY_LUT: u32 = 4u;
+
+// Matches LutDomains in the renderer
+struct LutDomains {
+    shaperMin: vec4<f32>,
+    shaperMax: vec4<f32>,
+    tableMin: vec4<f32>,
+    tableMax: vec4<f32>,
+    hasShaper: u32,
+    // Zero when no LUT is loaded, leaving the radiance as the linear operator does
+    loaded: u32,
+}
 
 @group(0) @binding(0)
-var textures: binding_array<texture_2d<f32>>;
+var<storage, read> accumulation: array<vec4<f32>>;
 @group(0) @binding(1)
-var texture_sampler: sampler;
-@group(0) @binding(2)
-var<uniform> progressive_rendering_samples: u32;
+var<uniform> resolve: Resolve;
+
+// A display transform baked to a .cube file, red changing fastest through the table
+@group(1) @binding(0)
+var lutTable: texture_3d<f32>;
+@group(1) @binding(1)
+var lutShaper: texture_1d<f32>;
+@group(1) @binding(2)
+var<uniform> lutDomains: LutDomains;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var color = vec4<f32>(0.0);
+    let pixel = vec2<u32>(in.position.xy) - resolve.offset;
+    let color = accumulation[pixel.y * resolve.width + pixel.x] / f32(max(resolve.samples, 1u));
+
+    if resolve.falseColorNits > 0.0 {
+        return vec4<f32>(falseColor(color.rgb) * resolve.scale, color.a);
+    }
+
+    return vec4<f32>(toneMap(color.rgb * resolve.exposure) * resolve.scale, color.a);
+}
+
+// Compresses radiance into [0, 1] rather than clipping it, except for the linear operator
+fn toneMap(color: vec3<f32>) -> vec3<f32> {
+    switch resolve.toneMapping {
+        case TONE_MAPPING_REINHARD: {
+            return color / (1.0 + color);
+        }
+        case TONE_MAPPING_ACES: {
+            return aces(color);
+        }
+        case TONE_MAPPING_UNCHARTED2: {
+            // Hable's filmic curve, with white at 11.2
+            let exposureBias = 2.0;
+            return uncharted2(color * exposureBias) / uncharted2(vec3<f32>(11.2));
+        }
+        case TONE_MAPPING_DISPLAY_LUT: {
+            if lutDomains.loaded == 0u {
+                return color;
+            }
+            // The LUT gives sRGB encoded values, decoded for the output to encode again
+            return srgbToLinear(displayLut(color));
+        }
+        default: {
+            return color;
+        }
+    }
+}
+
+// Matches DisplayLut::apply in the renderer, interpolating by hand as the textures are 32-bit
+// floats, which can't be filtered everywhere
+fn displayLut(color: vec3<f32>) -> vec3<f32> {
+    var shaped = color;
+    if lutDomains.hasShaper != 0u {
+        let size = textureDimensions(lutShaper);
+        let position = lutPosition(color, lutDomains.shaperMin.xyz, lutDomains.shaperMax.xyz, size);
+        let index = min(vec3<u32>(position), vec3<u32>(size - 2u));
+        let fraction = position - vec3<f32>(index);
+        for (var channel = 0; channel < 3; channel++) {
+            let a = textureLoad(lutShaper, index[channel], 0)[channel];
+            let b = textureLoad(lutShaper, index[channel] + 1u, 0)[channel];
+            shaped[channel] = mix(a, b, fraction[channel]);
+        }
+    }
 
-    // TODO: maybe do averaging in compute shader?
-    for (var i = 0u; i < progressive_rendering_samples; i = i + 1u) {
-        color = color + textureSample(textures[i], texture_sampler, in.tex_coord);
+    let size = textureDimensions(lutTable).x;
+    let position = lutPosition(shaped, lutDomains.tableMin.xyz, lutDomains.tableMax.xyz, size);
+    let index = min(vec3<u32>(position), vec3<u32>(size - 2u));
+    let fraction = position - vec3<f32>(index);
+    var corners: array<vec3<f32>, 8>;
+    for (var corner = 0u; corner < 8u; corner++) {
+        let offset = vec3<u32>(corner & 1u, (corner >> 1u) & 1u, corner >> 2u);
+        corners[corner] = textureLoad(lutTable, index + offset, 0).rgb;
     }
+    let low = mix(mix(corners[0], corners[1], fraction.r), mix(corners[2], corners[3], fraction.r), fraction.g);
+    let high = mix(mix(corners[4], corners[5], fraction.r), mix(corners[6], corners[7], fraction.r), fraction.g);
+    return mix(low, high, fraction.b);
+}
+
+// Where `color` falls among the entries of a LUT with `size` of them along each axis over the
+// domain, clamped to it
+fn lutPosition(color: vec3<f32>, domainMin: vec3<f32>, domainMax: vec3<f32>, size: u32) -> vec3<f32> {
+    let t = clamp((color - domainMin) / (domainMax - domainMin), vec3<f32>(0.0), vec3<f32>(1.0));
+    return t * f32(size - 1u);
+}
+
+fn srgbToLinear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let x = color * 0.6;
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn uncharted2(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+// Matches the bands in the renderer's false color legend, one per decade of nits or lux
+fn falseColor(color: vec3<f32>) -> vec3<f32> {
+    var bands = array<vec3<f32>, 7>(
+        vec3<f32>(20.0, 0.0, 60.0),
+        vec3<f32>(0.0, 60.0, 255.0),
+        vec3<f32>(0.0, 200.0, 200.0),
+        vec3<f32>(0.0, 200.0, 0.0),
+        vec3<f32>(255.0, 230.0, 0.0),
+        vec3<f32>(255.0, 120.0, 0.0),
+        vec3<f32>(255.0, 0.0, 0.0),
+    );
+
+    let nits = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722)) * resolve.falseColorNits;
+    let band = u32(clamp(floor(log2(max(nits, 0.0001)) / log2(10.0)) + 1.0, 0.0, 6.0));
 
-    return color / f32(progressive_rendering_samples);
+    // The legend colors are sRGB encoded, while the output is linear
+    return pow(bands[band] / 255.0, vec3<f32>(2.2));
 }
@@ -0,0 +1,295 @@
+//!include "camera.wgsl"
+//!include "packing.wgsl"
+
+// A spatiotemporal variance-guided filter (SVGF): the noisy image is blended with the previous
+// frame's where the same surface was visible, then blurred with an edge-aware à-trous wavelet
+// filter whose strength follows the estimated variance of each pixel.
+
+struct Params {
+    camera: Camera,
+    previousCamera: Camera,
+    size: vec2<u32>,
+    // Zero when there's no previous frame to reproject, e.g. after a resize
+    historyValid: u32,
+    // The number of à-trous passes, the last of which is shown
+    iterations: u32,
+}
+
+struct FilterPass {
+    // The distance in pixels between the taps of the à-trous filter
+    stepWidth: u32,
+    iteration: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+// The sum of the samples, with their number in alpha
+@group(0) @binding(1) var<storage, read> accumulation: array<vec4<f32>>;
+//!ifdef PACKED_PAYLOAD
+// The packed normal and the bits of the distance of the surface seen through each pixel, zero
+// where nothing is hit
+@group(0) @binding(2) var<storage, read> gBuffer: array<vec2<u32>>;
+@group(0) @binding(3) var<storage, read> previousGBuffer: array<vec2<u32>>;
+// The previous frame after one pass of the filter, in RGBM
+@group(0) @binding(4) var<storage, read_write> historyColor: array<u32>;
+//!else
+// The normal and distance of the surface seen through each pixel, zero where nothing is hit
+@group(0) @binding(2) var<storage, read> gBuffer: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read> previousGBuffer: array<vec4<f32>>;
+// The previous frame after one pass of the filter
+@group(0) @binding(4) var<storage, read_write> historyColor: array<vec4<f32>>;
+//!endif
+@group(0) @binding(5) var<storage, read> historyMoments: array<vec4<f32>>;
+// The first and second moments of the luminance, and the number of frames they're worth
+@group(0) @binding(6) var<storage, read_write> moments: array<vec4<f32>>;
+
+@group(1) @binding(0) var<uniform> filterPass: FilterPass;
+// The color with the variance in alpha, except for the temporal pass
+//!ifdef PACKED_PAYLOAD
+// RGBM with the bits of the variance
+@group(1) @binding(1) var<storage, read> source: array<vec2<u32>>;
+// Words rather than vectors, since the last pass writes full precision colors to be shown
+@group(1) @binding(2) var<storage, read_write> destination: array<u32>;
+//!else
+@group(1) @binding(1) var<storage, read> source: array<vec4<f32>>;
+@group(1) @binding(2) var<storage, read_write> destination: array<vec4<f32>>;
+//!endif
+
+const MAX_HISTORY: f32 = 32.0;
+// The least the current frame contributes, which bounds how long stale history lingers
+const MIN_ALPHA: f32 = 0.2;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn pixelIndex(pixel: vec2<u32>) -> u32 {
+    return pixel.y * params.size.x + pixel.x;
+}
+
+fn isInside(pixel: vec2<i32>) -> bool {
+    return all(pixel >= vec2<i32>(0)) && all(pixel < vec2<i32>(params.size));
+}
+
+// The normal and distance of the surface seen through the pixel at `index`
+fn loadSurface(index: u32) -> vec4<f32> {
+    //!ifdef PACKED_PAYLOAD
+    let packed = gBuffer[index];
+    return vec4<f32>(unpackNormal(packed.x), bitcast<f32>(packed.y));
+    //!else
+    return gBuffer[index];
+    //!endif
+}
+
+fn loadPreviousSurface(index: u32) -> vec4<f32> {
+    //!ifdef PACKED_PAYLOAD
+    let packed = previousGBuffer[index];
+    return vec4<f32>(unpackNormal(packed.x), bitcast<f32>(packed.y));
+    //!else
+    return previousGBuffer[index];
+    //!endif
+}
+
+fn loadHistory(index: u32) -> vec3<f32> {
+    //!ifdef PACKED_PAYLOAD
+    return unpackRgbm(historyColor[index]);
+    //!else
+    return historyColor[index].rgb;
+    //!endif
+}
+
+fn storeHistory(index: u32, color: vec3<f32>) {
+    //!ifdef PACKED_PAYLOAD
+    historyColor[index] = packRgbm(color);
+    //!else
+    historyColor[index] = vec4<f32>(color, 0.0);
+    //!endif
+}
+
+fn loadColor(index: u32) -> vec4<f32> {
+    //!ifdef PACKED_PAYLOAD
+    let packed = source[index];
+    return vec4<f32>(unpackRgbm(packed.x), bitcast<f32>(packed.y));
+    //!else
+    return source[index];
+    //!endif
+}
+
+// Writes full precision for the last pass, whose output is shown
+fn storeColor(index: u32, color: vec4<f32>, last: bool) {
+    //!ifdef PACKED_PAYLOAD
+    if last {
+        for (var i = 0u; i < 4u; i++) {
+            destination[index * 4u + i] = bitcast<u32>(color[i]);
+        }
+    } else {
+        destination[index * 2u] = packRgbm(color.rgb);
+        destination[index * 2u + 1u] = bitcast<u32>(color.a);
+    }
+    //!else
+    destination[index] = color;
+    //!endif
+}
+
+fn surfacePosition(pixel: vec2<u32>, distance: f32) -> vec3<f32> {
+    let center = vec2<f32>(pixel) + 0.5;
+    let direction = cameraRayDirection(params.camera, center, params.size);
+    return cameraRayOrigin(params.camera, center, params.size) + direction * distance;
+}
+
+fn normalWeight(normal: vec3<f32>, other: vec3<f32>) -> f32 {
+    return pow(max(dot(normal, other), 0.0), 128.0);
+}
+
+// Falls off with the distance of `other` from the plane of the center surface, relative to how
+// far away it is
+fn planeWeight(position: vec3<f32>, normal: vec3<f32>, distance: f32, other: vec3<f32>) -> f32 {
+    return exp(-abs(dot(other - position, normal)) / (0.02 * distance));
+}
+
+@compute @workgroup_size(16, 16)
+fn temporal(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.size.x || id.y >= params.size.y {
+        return;
+    }
+
+    let index = pixelIndex(id.xy);
+    let sum = accumulation[index];
+    let color = sum.rgb / max(sum.a, 0.0001);
+    let brightness = luminance(color);
+
+    var integratedColor = color;
+    var integratedMoments = vec3<f32>(brightness, brightness * brightness, 1.0);
+
+    let surface = loadSurface(index);
+    if params.historyValid != 0u && surface.w > 0.0 {
+        let position = surfacePosition(id.xy, surface.w);
+        let previousPixel = projectToPixel(params.previousCamera, position, params.size);
+        if isInside(vec2<i32>(floor(previousPixel))) && all(previousPixel >= vec2<f32>(0.0)) {
+            let previousIndex = pixelIndex(vec2<u32>(previousPixel));
+            let previousSurface = loadPreviousSurface(previousIndex);
+            let expectedDistance = distance(position, params.previousCamera.origin);
+
+            // Only reuse history of the same surface, not whatever was in front of it before
+            let sameSurface = previousSurface.w > 0.0
+                && dot(previousSurface.xyz, surface.xyz) > 0.9
+                && abs(previousSurface.w - expectedDistance) < 0.05 * expectedDistance;
+            if sameSurface {
+                let previousMoments = historyMoments[previousIndex];
+                let frames = min(previousMoments.z + 1.0, MAX_HISTORY);
+                let alpha = max(1.0 / frames, MIN_ALPHA);
+                integratedColor = mix(loadHistory(previousIndex), color, alpha);
+                integratedMoments = vec3<f32>(
+                    mix(previousMoments.xy, integratedMoments.xy, alpha),
+                    frames
+                );
+            }
+        }
+    }
+
+    storeColor(index, vec4<f32>(integratedColor, 0.0), false);
+    moments[index] = vec4<f32>(integratedMoments, 0.0);
+}
+
+@compute @workgroup_size(16, 16)
+fn variance(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.size.x || id.y >= params.size.y {
+        return;
+    }
+
+    let index = pixelIndex(id.xy);
+    let color = loadColor(index);
+    let surface = loadSurface(index);
+    var pixelMoments = moments[index];
+
+    // A few frames aren't enough to tell noise from detail, so borrow the moments of nearby
+    // pixels on the same surface
+    if pixelMoments.z < 4.0 && surface.w > 0.0 {
+        let position = surfacePosition(id.xy, surface.w);
+        var sum = vec2<f32>(0.0);
+        var weights = 0.0;
+        for (var y = -3; y <= 3; y++) {
+            for (var x = -3; x <= 3; x++) {
+                let pixel = vec2<i32>(id.xy) + vec2<i32>(x, y);
+                if !isInside(pixel) {
+                    continue;
+                }
+                let other = loadSurface(pixelIndex(vec2<u32>(pixel)));
+                if other.w <= 0.0 {
+                    continue;
+                }
+
+                let otherPosition = surfacePosition(vec2<u32>(pixel), other.w);
+                let weight = normalWeight(surface.xyz, other.xyz)
+                    * planeWeight(position, surface.xyz, surface.w, otherPosition);
+                sum += moments[pixelIndex(vec2<u32>(pixel))].xy * weight;
+                weights += weight;
+            }
+        }
+        pixelMoments = vec4<f32>(sum / max(weights, 0.0001), pixelMoments.zw);
+    }
+
+    let variance = max(pixelMoments.y - pixelMoments.x * pixelMoments.x, 0.0);
+    storeColor(index, vec4<f32>(color.rgb, variance), false);
+}
+
+@compute @workgroup_size(16, 16)
+fn atrous(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.size.x || id.y >= params.size.y {
+        return;
+    }
+
+    let index = pixelIndex(id.xy);
+    let center = loadColor(index);
+    let surface = loadSurface(index);
+    // The shown image is opaque, rather than carrying the variance in alpha
+    let last = filterPass.iteration + 1u == params.iterations;
+    if surface.w <= 0.0 {
+        if filterPass.iteration == 0u {
+            storeHistory(index, center.rgb);
+        }
+        storeColor(index, select(center, vec4<f32>(center.rgb, 1.0), last), last);
+        return;
+    }
+
+    let position = surfacePosition(id.xy, surface.w);
+    let centerLuminance = luminance(center.rgb);
+    let luminanceScale = 4.0 * sqrt(center.a) + 0.0001;
+    // The B3 spline, from the center outwards
+    var kernel = array<f32, 3>(0.375, 0.25, 0.0625);
+
+    var colorSum = vec3<f32>(0.0);
+    var varianceSum = 0.0;
+    var weights = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let pixel = vec2<i32>(id.xy) + vec2<i32>(x, y) * i32(filterPass.stepWidth);
+            if !isInside(pixel) {
+                continue;
+            }
+            let otherIndex = pixelIndex(vec2<u32>(pixel));
+            let other = loadSurface(otherIndex);
+            if other.w <= 0.0 {
+                continue;
+            }
+
+            let sample = loadColor(otherIndex);
+            let otherPosition = surfacePosition(vec2<u32>(pixel), other.w);
+            let weight = kernel[abs(x)] * kernel[abs(y)]
+                * normalWeight(surface.xyz, other.xyz)
+                * planeWeight(position, surface.xyz, surface.w, otherPosition)
+                * exp(-abs(centerLuminance - luminance(sample.rgb)) / luminanceScale);
+
+            colorSum += sample.rgb * weight;
+            varianceSum += sample.a * weight * weight;
+            weights += weight;
+        }
+    }
+
+    // The center always contributes, so the weights can't be zero
+    let filtered = vec4<f32>(colorSum / weights, varianceSum / (weights * weights));
+    // The first blur becomes the history
+    if filterPass.iteration == 0u {
+        storeHistory(index, filtered.rgb);
+    }
+    storeColor(index, select(filtered, vec4<f32>(filtered.rgb, 1.0), last), last);
+}
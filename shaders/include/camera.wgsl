@@ -0,0 +1,89 @@
+//!include "utils.wgsl"
+
+struct Camera {
+    origin: vec3<f32>,
+    focalLength: f32,
+    forward: vec3<f32>,
+    vfov: f32,
+    right: vec3<f32>,
+    // One of the PROJECTION_ constants
+    projection: u32,
+    up: vec3<f32>,
+    // The distance between the eyes of the stereo projections
+    ipd: f32,
+}
+
+// Matches Projection in the renderer
+const PROJECTION_PERSPECTIVE: u32 = 0u;
+const PROJECTION_STEREO_360: u32 = 1u;
+const PROJECTION_STEREO_180: u32 = 2u;
+
+// Where `pixel` falls on a stereo image: the longitude and latitude of its direction, then -1
+// for the left eye or 1 for the right. The 360° projection stacks the left eye's panorama above
+// the right's, the 180° one puts them side by side
+fn stereoPixel(camera: Camera, pixel: vec2<f32>, size: vec2<u32>) -> vec3<f32> {
+    let uv = pixel / vec2<f32>(size);
+    if camera.projection == PROJECTION_STEREO_360 {
+        let eye = select(-1.0, 1.0, uv.y >= 0.5);
+        return vec3<f32>((uv.x - 0.5) * 2.0 * PI, (0.5 - fract(uv.y * 2.0)) * PI, eye);
+    }
+    let eye = select(-1.0, 1.0, uv.x >= 0.5);
+    return vec3<f32>((fract(uv.x * 2.0) - 0.5) * PI, (0.5 - uv.y) * PI, eye);
+}
+
+// The width and height of the image plane at the focal length
+fn viewportExtent(camera: Camera, size: vec2<u32>) -> vec2<f32> {
+    let height = 2.0 * tan(radians(camera.vfov) / 2.0) * camera.focalLength;
+    return vec2<f32>(height * f32(size.x) / f32(size.y), height);
+}
+
+// The normalized direction from the camera through `pixel`, in pixels from the top left corner
+// of an image of `size`
+fn cameraRayDirection(camera: Camera, pixel: vec2<f32>, size: vec2<u32>) -> vec3<f32> {
+    if camera.projection != PROJECTION_PERSPECTIVE {
+        let stereo = stereoPixel(camera, pixel, size);
+        let level = sin(stereo.x) * camera.right + cos(stereo.x) * camera.forward;
+        return normalize(cos(stereo.y) * level + sin(stereo.y) * camera.up);
+    }
+
+    let extent = viewportExtent(camera, size);
+    let uv = pixel / vec2<f32>(size) - 0.5;
+    return normalize(
+        camera.focalLength * camera.forward
+        + uv.x * extent.x * camera.right
+        - uv.y * extent.y * camera.up
+    );
+}
+
+// Where the ray through `pixel` starts. For the stereo projections that's an eye on a circle the
+// IPD across around the camera, facing the pixel's direction, so every column of the panorama
+// has the parallax of looking that way (omni-directional stereo)
+fn cameraRayOrigin(camera: Camera, pixel: vec2<f32>, size: vec2<u32>) -> vec3<f32> {
+    if camera.projection == PROJECTION_PERSPECTIVE {
+        return camera.origin;
+    }
+
+    let stereo = stereoPixel(camera, pixel, size);
+    let eyeRight = cos(stereo.x) * camera.right - sin(stereo.x) * camera.forward;
+    // Narrowed towards the poles, where the eyes would otherwise swap places within a few pixels
+    return camera.origin + eyeRight * (stereo.z * 0.5 * camera.ipd * cos(stereo.y));
+}
+
+// The pixel `point` is seen through, which is negative for points behind the camera or on a
+// stereo image, which isn't reprojected
+fn projectToPixel(camera: Camera, point: vec3<f32>, size: vec2<u32>) -> vec2<f32> {
+    if camera.projection != PROJECTION_PERSPECTIVE {
+        return vec2<f32>(-1.0);
+    }
+
+    let toPoint = point - camera.origin;
+    let depth = dot(toPoint, camera.forward);
+    if depth <= 0.0 {
+        return vec2<f32>(-1.0);
+    }
+
+    let extent = viewportExtent(camera, size);
+    let x = dot(toPoint, camera.right) * camera.focalLength / depth;
+    let y = dot(toPoint, camera.up) * camera.focalLength / depth;
+    return (vec2<f32>(x / extent.x, -y / extent.y) + 0.5) * vec2<f32>(size);
+}
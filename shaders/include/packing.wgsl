@@ -0,0 +1,34 @@
+// Compact encodings of the data passes hand each other per pixel, which roughly halve the memory
+// traffic of the denoiser at some cost in precision
+
+// The brightest radiance RGBM holds, above which it's clipped
+const RGBM_RANGE: f32 = 16.0;
+
+// Folds the unit `normal` onto an octahedron and that onto a square, at 16 bits per axis
+fn packNormal(normal: vec3<f32>) -> u32 {
+    let n = normal / max(abs(normal.x) + abs(normal.y) + abs(normal.z), 0.0001);
+    let signs = select(vec2<f32>(-1.0), vec2<f32>(1.0), n.xy >= vec2<f32>(0.0));
+    let folded = select(n.xy, (1.0 - abs(n.yx)) * signs, n.z < 0.0);
+    return pack2x16snorm(folded);
+}
+
+fn unpackNormal(packed: u32) -> vec3<f32> {
+    let folded = unpack2x16snorm(packed);
+    let n = vec3<f32>(folded, 1.0 - abs(folded.x) - abs(folded.y));
+    let unfold = max(-n.z, 0.0);
+    let signs = select(vec2<f32>(-1.0), vec2<f32>(1.0), n.xy >= vec2<f32>(0.0));
+    return normalize(vec3<f32>(n.xy - unfold * signs, n.z));
+}
+
+// Radiance as 8-bit colors sharing an 8-bit multiplier
+fn packRgbm(color: vec3<f32>) -> u32 {
+    let scaled = clamp(color / RGBM_RANGE, vec3<f32>(0.0), vec3<f32>(1.0));
+    // Rounded up so the colors divided by it stay within one
+    let multiplier = ceil(max(max(scaled.r, scaled.g), max(scaled.b, 0.000001)) * 255.0) / 255.0;
+    return pack4x8unorm(vec4<f32>(scaled / multiplier, multiplier));
+}
+
+fn unpackRgbm(packed: u32) -> vec3<f32> {
+    let rgbm = unpack4x8unorm(packed);
+    return rgbm.rgb * rgbm.a * RGBM_RANGE;
+}
@@ -30,6 +30,20 @@ fn randomUnitInHemisphere(seed: vec2<f32>, normal: vec3<f32>) -> vec3<f32> {
     }
 }
 
+// PCG hash, for turning sequential seeds into uncorrelated ones
+fn pcgHash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// A state for hybridTaus that only depends on the pixel and the seed. The Tausworthe steps need
+// components above 128 to produce good numbers.
+fn initialRandomState(pixel: vec2<u32>, seed: vec2<u32>) -> vec4<u32> {
+    let hash = pcgHash(pixel.x ^ pcgHash(pixel.y ^ pcgHash(seed.x ^ pcgHash(seed.y))));
+    return vec4<u32>(hash | 128u, pcgHash(hash) | 128u, pcgHash(hash + 1u) | 128u, pcgHash(hash + 2u));
+}
+
 fn tauStep(z: u32, s1: i32, s2: i32, s3: i32, m: u32) -> u32 {
     let b = ((z << u32(s1)) ^ z) >> u32(s2);
     return ((z & m) << u32(s3)) ^ b;
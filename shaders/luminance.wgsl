@@ -0,0 +1,48 @@
+// Sums the log luminance of the accumulated image for auto-exposure. Each workgroup strides
+// over the pixels and writes one partial sum, which the renderer reads back and adds up.
+
+struct Params {
+    // The number of samples the accumulated sums are worth
+    samples: u32,
+    pixels: u32,
+}
+
+const WORKGROUP_SIZE: u32 = 256u;
+// Matches the renderer's EXPOSURE_WORKGROUPS
+const WORKGROUPS: u32 = 64u;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> accumulation: array<vec4<f32>>;
+// The sum of the log2 luminance and the number of pixels counted, per workgroup
+@group(0) @binding(2) var<storage, read_write> partialSums: array<vec2<f32>>;
+
+var<workgroup> sums: array<vec2<f32>, WORKGROUP_SIZE>;
+
+@compute @workgroup_size(256)
+fn main(
+    @builtin(global_invocation_id) threadId: vec3<u32>,
+    @builtin(local_invocation_index) localIndex: u32,
+    @builtin(workgroup_id) workgroupId: vec3<u32>,
+) {
+    let samples = f32(max(params.samples, 1u));
+    var sum = vec2<f32>(0.0);
+    for (var pixel = threadId.x; pixel < params.pixels; pixel += WORKGROUP_SIZE * WORKGROUPS) {
+        let color = accumulation[pixel].rgb / samples;
+        let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+        // Floored so black pixels don't drag the average down without limit
+        sum += vec2<f32>(log2(max(luminance, 0.0001)), 1.0);
+    }
+    sums[localIndex] = sum;
+    workgroupBarrier();
+
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+        if localIndex < stride {
+            sums[localIndex] += sums[localIndex + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if localIndex == 0u {
+        partialSums[workgroupId.x] = sums[0];
+    }
+}
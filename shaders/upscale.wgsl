@@ -0,0 +1,153 @@
+//!include "fullscreen.wgsl"
+
+// Edge adaptive spatial upsampling, following the EASU pass of AMD FidelityFX
+// Super Resolution 1.0.
+
+struct Upscaling {
+    inputSize: vec2<f32>,
+    outputSize: vec2<f32>,
+    enabled: u32,
+}
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> upscaling: Upscaling;
+
+fn load(pixel: vec2<f32>) -> vec3<f32> {
+    let clamped = clamp(vec2<i32>(pixel), vec2<i32>(0), vec2<i32>(upscaling.inputSize) - 1);
+    return textureLoad(input, clamped, 0).rgb;
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return color.b * 0.5 + (color.r * 0.5 + color.g);
+}
+
+// Accumulates the direction and length of the edge around one of the four
+// bilinear taps in the center of the kernel:
+//    a
+//  b c d
+//    e
+fn edge(w: f32, lA: f32, lB: f32, lC: f32, lD: f32, lE: f32, dir: ptr<function, vec2<f32>>, len: ptr<function, f32>) {
+    let dc = lD - lC;
+    let cb = lC - lB;
+    let dirX = lD - lB;
+    var lenX = clamp(abs(dirX) / max(max(abs(dc), abs(cb)), 1e-5), 0.0, 1.0);
+    lenX = lenX * lenX;
+
+    let ec = lE - lC;
+    let ca = lC - lA;
+    let dirY = lE - lA;
+    var lenY = clamp(abs(dirY) / max(max(abs(ec), abs(ca)), 1e-5), 0.0, 1.0);
+    lenY = lenY * lenY;
+
+    *dir = *dir + vec2<f32>(dirX, dirY) * w;
+    *len = *len + (lenX + lenY) * w;
+}
+
+// Approximation of lanczos2 without sin(), rcp() or sqrt(), rotated and
+// stretched along the edge direction.
+fn tap(offset: vec2<f32>, dir: vec2<f32>, len: vec2<f32>, lob: f32, clp: f32, color: vec3<f32>, accumulatedColor: ptr<function, vec3<f32>>, accumulatedWeight: ptr<function, f32>) {
+    var v = vec2<f32>(offset.x * dir.x + offset.y * dir.y, offset.x * -dir.y + offset.y * dir.x);
+    v = v * len;
+    let d2 = min(dot(v, v), clp);
+
+    var wB = 2.0 / 5.0 * d2 - 1.0;
+    var wA = lob * d2 - 1.0;
+    wB = wB * wB;
+    wA = wA * wA;
+    wB = 25.0 / 16.0 * wB - (25.0 / 16.0 - 1.0);
+    let w = wB * wA;
+
+    *accumulatedColor = *accumulatedColor + color * w;
+    *accumulatedWeight = *accumulatedWeight + w;
+}
+
+fn easu(position: vec2<f32>) -> vec3<f32> {
+    let scale = upscaling.inputSize / upscaling.outputSize;
+    var pp = position * scale - 0.5;
+    let fp = floor(pp);
+    pp = pp - fp;
+
+    // 12-tap kernel:
+    //    b c
+    //  e f g h
+    //  i j k l
+    //    n o
+    let b = load(fp + vec2<f32>(0.0, -1.0) + 0.5);
+    let c = load(fp + vec2<f32>(1.0, -1.0) + 0.5);
+    let e = load(fp + vec2<f32>(-1.0, 0.0) + 0.5);
+    let f = load(fp + vec2<f32>(0.0, 0.0) + 0.5);
+    let g = load(fp + vec2<f32>(1.0, 0.0) + 0.5);
+    let h = load(fp + vec2<f32>(2.0, 0.0) + 0.5);
+    let i = load(fp + vec2<f32>(-1.0, 1.0) + 0.5);
+    let j = load(fp + vec2<f32>(0.0, 1.0) + 0.5);
+    let k = load(fp + vec2<f32>(1.0, 1.0) + 0.5);
+    let l = load(fp + vec2<f32>(2.0, 1.0) + 0.5);
+    let n = load(fp + vec2<f32>(0.0, 2.0) + 0.5);
+    let o = load(fp + vec2<f32>(1.0, 2.0) + 0.5);
+
+    let bL = luma(b);
+    let cL = luma(c);
+    let eL = luma(e);
+    let fL = luma(f);
+    let gL = luma(g);
+    let hL = luma(h);
+    let iL = luma(i);
+    let jL = luma(j);
+    let kL = luma(k);
+    let lL = luma(l);
+    let nL = luma(n);
+    let oL = luma(o);
+
+    var dir = vec2<f32>(0.0);
+    var len = 0.0;
+    edge((1.0 - pp.x) * (1.0 - pp.y), bL, eL, fL, gL, jL, &dir, &len);
+    edge(pp.x * (1.0 - pp.y), cL, fL, gL, hL, kL, &dir, &len);
+    edge((1.0 - pp.x) * pp.y, fL, iL, jL, kL, nL, &dir, &len);
+    edge(pp.x * pp.y, gL, jL, kL, lL, oL, &dir, &len);
+
+    let dirR = dot(dir, dir);
+    if dirR < 1.0 / 32768.0 {
+        dir = vec2<f32>(1.0, 0.0);
+    } else {
+        dir = dir * inverseSqrt(dirR);
+    }
+
+    len = len * 0.5;
+    len = len * len;
+
+    let stretch = dot(dir, dir) / max(abs(dir.x), abs(dir.y));
+    let len2 = vec2<f32>(1.0 + (stretch - 1.0) * len, 1.0 - 0.5 * len);
+    let lob = 0.5 + ((1.0 / 4.0 - 0.04) - 0.5) * len;
+    let clp = 1.0 / lob;
+
+    var accumulatedColor = vec3<f32>(0.0);
+    var accumulatedWeight = 0.0;
+    tap(vec2<f32>(0.0, -1.0) - pp, dir, len2, lob, clp, b, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(1.0, -1.0) - pp, dir, len2, lob, clp, c, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(-1.0, 1.0) - pp, dir, len2, lob, clp, i, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(0.0, 1.0) - pp, dir, len2, lob, clp, j, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(0.0, 0.0) - pp, dir, len2, lob, clp, f, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(-1.0, 0.0) - pp, dir, len2, lob, clp, e, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(1.0, 1.0) - pp, dir, len2, lob, clp, k, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(2.0, 1.0) - pp, dir, len2, lob, clp, l, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(2.0, 0.0) - pp, dir, len2, lob, clp, h, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(1.0, 0.0) - pp, dir, len2, lob, clp, g, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(1.0, 2.0) - pp, dir, len2, lob, clp, o, &accumulatedColor, &accumulatedWeight);
+    tap(vec2<f32>(0.0, 2.0) - pp, dir, len2, lob, clp, n, &accumulatedColor, &accumulatedWeight);
+
+    // Deringing: clamp to the range of the four nearest input pixels.
+    let minColor = min(min(f, g), min(j, k));
+    let maxColor = max(max(f, g), max(j, k));
+
+    return min(maxColor, max(minColor, accumulatedColor / accumulatedWeight));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if upscaling.enabled == 0u {
+        let pixel = in.position.xy * upscaling.inputSize / upscaling.outputSize;
+        return vec4<f32>(load(pixel), 1.0);
+    }
+
+    return vec4<f32>(easu(in.position.xy), 1.0);
+}
@@ -1,39 +1,326 @@
-use std::time::Instant;
+use std::{
+    io,
+    path::PathBuf,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use cgmath::Vector3;
+use cgmath::{InnerSpace, Vector3};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use winit::{
     dpi::PhysicalPosition,
-    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::Window,
+    event::{
+        ElementState, Event, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode,
+        WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    monitor::VideoMode,
+    window::{Fullscreen, Window},
 };
 
 use crate::{
+    assets,
+    command_palette::CommandPalette,
+    diagnostics::Diagnostics,
+    display_lut::DisplayLut,
+    environment_library::EnvironmentLibrary,
+    frame_capture::FrameCapture,
+    gizmo::{Gizmo, GizmoMode},
+    hotkeys::{Hotkeys, KeyChord},
+    hud::Hud,
+    jobs::{JobHandle, Jobs},
     model::{self, Model},
-    renderer::Renderer,
-    scene::{Camera, CameraController, Ray},
-    scene::{HitRecord, Material, Scene, Sphere, SphereDescriptor},
+    outlines::Outlines,
+    output_window::OutputWindow,
+    overlays::{color32, Overlays},
+    poly_haven::PolyHaven,
+    renderer::{
+        Calibration, DebugView, PickedObject, RenderSettings, Renderer, HDR_OUTPUT_FORMAT,
+        MATERIAL_PREVIEW_SIZE,
+    },
+    scene::{Camera, CameraController, Projection, Ray, ScrollZoom},
+    scene::{
+        Material, MaterialLibrary, PointCachePlayer, Repair, SavedSnapshot, ScatterBrush, Scene,
+        SceneEvent, Sphere, SphereDescriptor, SpherePacking, TexturePainter, FURNACE_RADIANCE,
+    },
+    scene_library::SceneLibrary,
+    texture::{self, TextureBudget},
+    thumbnails,
     ui::Ui,
+    WINDOW_TITLE,
 };
 
+/// A Radiance HDR image decoded by [`texture::read_hdr_pixels`], possibly downscaled, along
+/// with its original width and height.
+type DecodedHdri = ((u32, u32), (u32, u32, Vec<[f32; 4]>));
+
+/// What the texture budget calls the environment, which replaces the previous one.
+const ENVIRONMENT_BUDGET_NAME: &str = "environment";
+/// How far the cursor has to move while held to draw a selection box rather than click, in
+/// physical pixels.
+const BOX_SELECT_DISTANCE: f64 = 4.0;
+/// How many camera views can be saved to recall later.
+const CAMERA_BOOKMARKS: usize = 3;
+
+/// A scene file read in the background, along with what was repaired in it. Imported scenes
+/// have no render settings, so they keep the current ones.
+type LoadedScene = (
+    PathBuf,
+    io::Result<(Scene, Option<RenderSettings>, Vec<Repair>)>,
+);
+
+/// Something that loses the scene's unsaved changes, waiting for them to be confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Discarding {
+    Quit,
+    LoadScene,
+    /// Replacing the scene with the diagnostic scene one of the diagnostic actions loads.
+    LoadDiagnosticScene(Action),
+}
+
+impl Discarding {
+    /// The question asked before doing it, and the button confirming it.
+    fn prompt(self) -> (&'static str, &'static str) {
+        match self {
+            Discarding::Quit => ("Quit anyway?", "Quit without saving"),
+            Discarding::LoadScene | Discarding::LoadDiagnosticScene(_) => {
+                ("Load another scene anyway?", "Load without saving")
+            }
+        }
+    }
+}
+
+/// Everything the editor can do from the command palette or a shortcut, independent of how it is
+/// triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    OpenCommandPalette,
+    AddSphere,
+    RemoveSphere,
+    SelectNextSphere,
+    SelectPreviousSphere,
+    ClearSelection,
+    RemoveSelection,
+    DuplicateSelection,
+    CopySelection,
+    Paste,
+    DropSelectionToGround,
+    FrameSelection,
+    TranslateMode,
+    RotateMode,
+    ScaleMode,
+    TogglePanels,
+    TogglePresentationMode,
+    ToggleFullscreen,
+    ToggleDetachOutput,
+    TogglePictureInPicture,
+    ToggleHdrOutput,
+    ToggleFalseColor,
+    ToggleReferenceMode,
+    ToggleNormalsView,
+    /// Saves the free camera's view to the bookmark at the index.
+    SaveCameraBookmark(u8),
+    /// Moves the free camera to the view saved to the bookmark at the index.
+    RecallCameraBookmark(u8),
+    SaveImage,
+    SaveExr,
+    /// Saves the scene to the scene path.
+    SaveScene,
+    LoadScene,
+    ImportScene,
+    LoadEnvironment,
+    CaptureFrame,
+    /// Applies the quality preset at the index, so the first few can have shortcuts.
+    ApplyQualityPreset(u8),
+    Calibrate,
+    LoadVeachMisScene,
+    LoadWhiteFurnaceScene,
+    RunFurnaceCheck,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 46] = [
+        Action::OpenCommandPalette,
+        Action::AddSphere,
+        Action::RemoveSphere,
+        Action::SelectNextSphere,
+        Action::SelectPreviousSphere,
+        Action::ClearSelection,
+        Action::RemoveSelection,
+        Action::DuplicateSelection,
+        Action::CopySelection,
+        Action::Paste,
+        Action::DropSelectionToGround,
+        Action::FrameSelection,
+        Action::TranslateMode,
+        Action::RotateMode,
+        Action::ScaleMode,
+        Action::TogglePanels,
+        Action::TogglePresentationMode,
+        Action::ToggleFullscreen,
+        Action::ToggleDetachOutput,
+        Action::TogglePictureInPicture,
+        Action::ToggleHdrOutput,
+        Action::ToggleFalseColor,
+        Action::ToggleReferenceMode,
+        Action::ToggleNormalsView,
+        Action::SaveCameraBookmark(0),
+        Action::SaveCameraBookmark(1),
+        Action::SaveCameraBookmark(2),
+        Action::RecallCameraBookmark(0),
+        Action::RecallCameraBookmark(1),
+        Action::RecallCameraBookmark(2),
+        Action::SaveImage,
+        Action::SaveExr,
+        Action::SaveScene,
+        Action::LoadScene,
+        Action::ImportScene,
+        Action::LoadEnvironment,
+        Action::CaptureFrame,
+        Action::ApplyQualityPreset(0),
+        Action::ApplyQualityPreset(1),
+        Action::ApplyQualityPreset(2),
+        Action::ApplyQualityPreset(3),
+        Action::Calibrate,
+        Action::LoadVeachMisScene,
+        Action::LoadWhiteFurnaceScene,
+        Action::RunFurnaceCheck,
+        Action::Quit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::OpenCommandPalette => "Open command palette",
+            Action::AddSphere => "Add sphere",
+            Action::RemoveSphere => "Remove last sphere",
+            Action::SelectNextSphere => "Select next sphere",
+            Action::SelectPreviousSphere => "Select previous sphere",
+            Action::ClearSelection => "Clear selection",
+            Action::RemoveSelection => "Remove selected objects",
+            Action::DuplicateSelection => "Duplicate selected objects",
+            Action::CopySelection => "Copy selected objects",
+            Action::Paste => "Paste objects",
+            Action::DropSelectionToGround => "Drop selected sphere to ground",
+            Action::FrameSelection => "Frame selected objects",
+            Action::TranslateMode => "Move with the gizmo",
+            Action::RotateMode => "Rotate with the gizmo",
+            Action::ScaleMode => "Scale with the gizmo",
+            Action::TogglePanels => "Toggle panels",
+            Action::TogglePresentationMode => "Toggle presentation mode",
+            Action::ToggleFullscreen => "Toggle fullscreen",
+            Action::ToggleDetachOutput => "Toggle detached render output",
+            Action::TogglePictureInPicture => "Toggle picture-in-picture",
+            Action::ToggleHdrOutput => "Toggle HDR output",
+            Action::ToggleFalseColor => "Toggle false color view",
+            Action::ToggleReferenceMode => "Toggle reference mode",
+            Action::ToggleNormalsView => "Toggle normals debug view",
+            Action::SaveCameraBookmark(0) => "Save camera bookmark 1",
+            Action::SaveCameraBookmark(1) => "Save camera bookmark 2",
+            Action::SaveCameraBookmark(_) => "Save camera bookmark 3",
+            Action::RecallCameraBookmark(0) => "Go to camera bookmark 1",
+            Action::RecallCameraBookmark(1) => "Go to camera bookmark 2",
+            Action::RecallCameraBookmark(_) => "Go to camera bookmark 3",
+            Action::SaveImage => "Save image",
+            Action::SaveExr => "Save image as EXR",
+            Action::SaveScene => "Save scene",
+            Action::LoadScene => "Load scene from file",
+            Action::ImportScene => "Import PBRT or Mitsuba scene",
+            Action::LoadEnvironment => "Load environment from file",
+            Action::CaptureFrame => "Capture frame with RenderDoc",
+            Action::ApplyQualityPreset(0) => "Apply quality preset 1",
+            Action::ApplyQualityPreset(1) => "Apply quality preset 2",
+            Action::ApplyQualityPreset(2) => "Apply quality preset 3",
+            Action::ApplyQualityPreset(_) => "Apply quality preset 4",
+            Action::Calibrate => "Calibrate performance for this GPU",
+            Action::LoadVeachMisScene => "Load the Veach MIS test scene",
+            Action::LoadWhiteFurnaceScene => "Load the white furnace test scene",
+            Action::RunFurnaceCheck => "Run the white furnace check",
+            Action::Quit => "Quit",
+        }
+    }
+}
+
 pub struct App {
     pub renderer: Renderer,
     ui: Ui,
+    material_preview: egui::TextureId,
+    environments: EnvironmentLibrary,
+    /// The scene files in the working directory.
+    scenes: SceneLibrary,
+    poly_haven: PolyHaven,
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
     surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
+    sdr_format: wgpu::TextureFormat,
+    hdr_supported: bool,
     window_size: winit::dpi::PhysicalSize<u32>,
+    cursor_position: PhysicalPosition<f64>,
     cursor_ray: Ray,
+    /// Where the cursor was pressed to click or drag out a selection box.
+    box_select_start: Option<PhysicalPosition<f64>>,
+    /// The object under the cursor, outlined more faintly than the selection.
+    hovered: Option<Uuid>,
 
     scene: Scene,
     camera_controller: CameraController,
+    scatter_brush: ScatterBrush,
+    texture_painter: TexturePainter,
+    sphere_packing: SpherePacking,
+    point_cache: PointCachePlayer,
+    diagnostics: Diagnostics,
+    overlays: Overlays,
+    outlines: Outlines,
+    hud: Hud,
+    gizmo: Gizmo,
+    jobs: Jobs,
+    /// Decoding a newly selected HDRI, along with its path.
+    environment_job: Option<JobHandle<(PathBuf, image::ImageResult<DecodedHdri>)>>,
+    texture_budget: TextureBudget,
+    /// Where the scene is saved to and loaded from.
+    scene_path: String,
+    /// Reading a scene file in the background.
+    scene_job: Option<JobHandle<LoadedScene>>,
+    /// Writing the scene file in the background, along with its path.
+    save_job: Option<JobHandle<(PathBuf, io::Result<SavedSnapshot>)>>,
+    /// Writing saved images, along with their paths.
+    image_jobs: Vec<JobHandle<(PathBuf, image::ImageResult<()>)>>,
 
     start_time: Instant,
     last_frame_time: std::time::Instant,
     frame_times: Vec<u128>,
 
     window: Window,
+    output_window: Option<OutputWindow>,
+    detach_output: bool,
+    /// The primaries renders are saved to EXRs in.
+    exr_color_space: texture::ExrColorSpace,
+    /// The video mode used for exclusive fullscreen, borderless fullscreen is used if `None`.
+    fullscreen_mode: Option<VideoMode>,
+    title: String,
+    /// Whether the side panel and the windows opened from it are shown, the HUD always is.
+    show_panels: bool,
+    /// Whether only the render is shown, without any panels, overlays, gizmos or the HUD.
+    presentation_mode: bool,
+    /// Whether the mouse and hotkeys only move the camera in presentation mode, so a stray
+    /// click can't select or move anything that isn't drawn.
+    lock_presentation_input: bool,
+    command_palette: CommandPalette,
+    /// Views of the free camera saved to recall later.
+    camera_bookmarks: [Option<Camera>; CAMERA_BOOKMARKS],
+    /// `None` if the system's clipboard couldn't be opened.
+    clipboard: Option<arboard::Clipboard>,
+    frame_capture: FrameCapture,
+    hotkeys: Hotkeys,
+    modifiers: ModifiersState,
+    /// What's waiting for the scene's unsaved changes to be confirmed lost.
+    discarding: Option<Discarding>,
+    /// What was repaired in the scene last loaded, shown until dismissed.
+    repairs: Vec<Repair>,
+    should_quit: bool,
 }
 
 impl App {
@@ -45,7 +332,8 @@ impl App {
         self.window_size
     }
 
-    pub async fn new(window: Window) -> Self {
+    /// Opens the editor on the window, loading `scene_path` in place of the demo scene if given.
+    pub async fn new(window: Window, scene_path: Option<PathBuf>) -> Self {
         let window_size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
@@ -67,7 +355,10 @@ impl App {
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::TEXTURE_BINDING_ARRAY | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+                    // Optional, for the renderer's profiler and sample batching
+                    features: adapter.features()
+                        & (wgpu::Features::PIPELINE_STATISTICS_QUERY
+                            | wgpu::Features::TIMESTAMP_QUERY),
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web, we'll have to disable some.
                     limits: if cfg!(target_arch = "wasm32") {
@@ -75,7 +366,15 @@ impl App {
                     } else {
                         wgpu::Limits {
                             max_texture_dimension_2d: 16384,
-                            max_sampled_textures_per_shader_stage: 256,
+                            // The accumulation buffers of large windows don't fit in the default
+                            max_storage_buffer_binding_size: adapter
+                                .limits()
+                                .max_storage_buffer_binding_size,
+                            max_buffer_size: adapter.limits().max_buffer_size,
+                            // The compute shader binds one more than the default, for picking
+                            max_storage_buffers_per_shader_stage: adapter
+                                .limits()
+                                .max_storage_buffers_per_shader_stage,
                             ..Default::default()
                         }
                     },
@@ -104,73 +403,152 @@ impl App {
         };
         surface.configure(&device, &config);
 
+        let hdr_supported = surface_caps.formats.contains(&HDR_OUTPUT_FORMAT);
+
         let camera = Camera::new();
 
+        let mut materials = MaterialLibrary::new();
+        let red = materials.find_or_add(Vector3::new(0.8, 0.3, 0.3), Material::Diffuse);
+        let glass = materials.find_or_add(Vector3::new(1.0, 1.0, 1.0), Material::Dielectric);
+        let red_metal = materials.find_or_add(Vector3::new(0.8, 0.3, 0.3), Material::Metal);
+        let ground = materials.find_or_add(Vector3::new(0.8, 0.8, 0.0), Material::Diffuse);
+        let white = materials.find_or_add(Vector3::new(1.0, 1.0, 1.0), Material::Diffuse);
+
         let spheres = vec![
             Sphere::new(SphereDescriptor {
                 center: Vector3::new(0.0, 0.0, -1.0),
                 radius: 0.5,
-                albedo: Vector3::new(0.8, 0.3, 0.3),
-                material: Material::Diffuse,
+                material: red,
             }),
             Sphere::new(SphereDescriptor {
                 center: Vector3::new(1.0, 0.0, -1.0),
                 radius: 0.5,
-                albedo: Vector3::new(1.0, 1.0, 1.0),
-                material: Material::Dielectric,
+                material: glass,
             }),
             Sphere::new(SphereDescriptor {
                 center: Vector3::new(0.0, 1.0, -1.0),
                 radius: 0.5,
-                albedo: Vector3::new(0.8, 0.3, 0.3),
-                material: Material::Diffuse,
+                material: red,
             }),
             Sphere::new(SphereDescriptor {
                 center: Vector3::new(0.0, 2.0, -1.0),
                 radius: 0.5,
-                albedo: Vector3::new(0.8, 0.3, 0.3),
-                material: Material::Metal,
+                material: red_metal,
             }),
             Sphere::new(SphereDescriptor {
                 center: Vector3::new(0.0, -100.5, -1.0),
                 radius: 100.0,
-                albedo: Vector3::new(0.8, 0.8, 0.0),
-                material: Material::Diffuse,
+                material: ground,
             }),
         ];
 
-        let ui = Ui::new(&window, &device, surface_format);
+        let ui_formats = if hdr_supported {
+            vec![surface_format, HDR_OUTPUT_FORMAT]
+        } else {
+            vec![surface_format]
+        };
+        let mut ui = Ui::new(&window, &device, &ui_formats);
 
-        let model = Model::from_obj("assets/models/bunny.obj", &device, &queue).unwrap();
+        let texture_budget = TextureBudget::new(texture::DEFAULT_TEXTURE_BUDGET);
+        let model = Model::from_obj(
+            &assets::resolve("assets/models/bunny.obj").to_string_lossy(),
+            white,
+        )
+        .unwrap();
         let triangles: Vec<model::Triangle> = model
             .meshes
             .into_iter()
             .flat_map(|m| m.triangles)
             .collect::<Vec<_>>();
 
-        let scene = Scene::new(spheres, triangles, camera);
+        let mut scene = Scene::new(spheres, triangles, camera);
+        scene.materials = materials;
+        let repairs = scene.validate();
+        log_repairs(&scene.name, &repairs);
 
-        let renderer = Renderer::new(&device, &queue, &config, &scene);
+        let mut renderer = Renderer::new(&device, &queue, &config, &scene);
+        let adapter_name = adapter.get_info().name;
+        match Calibration::load(&adapter_name) {
+            Some(calibration) => renderer.apply_calibration(calibration),
+            // Picks interactive defaults the first time the GPU is used
+            None => renderer.calibrate(&device, &queue, &scene, &adapter_name),
+        }
+        let material_preview =
+            ui.register_native_texture(&device, renderer.material_preview_view());
 
-        Self {
+        let mut app = Self {
+            instance,
+            adapter,
             surface,
             device,
             queue,
             config,
+            sdr_format: surface_format,
+            hdr_supported,
             window_size,
             ui,
+            material_preview,
+            environments: EnvironmentLibrary::new(
+                &assets::resolve("assets/hdri"),
+                &assets::resolve("assets/hdri/partly_cloudy_sky.hdr"),
+            ),
+            scenes: SceneLibrary::new(&std::env::current_dir().unwrap_or_default()),
+            poly_haven: PolyHaven::new(),
             scene,
             camera_controller: CameraController::new(),
+            scatter_brush: ScatterBrush::new(),
+            texture_painter: TexturePainter::new(),
+            sphere_packing: SpherePacking::new(),
+            point_cache: PointCachePlayer::new(),
+            diagnostics: Diagnostics::new(),
+            overlays: Overlays::new(),
+            outlines: Outlines::new(),
+            hud: Hud::new(),
+            gizmo: Gizmo::new(),
+            jobs: Jobs::new(),
+            environment_job: None,
+            texture_budget,
+            scene_path: "scene.json".to_string(),
+            scene_job: None,
+            save_job: None,
+            image_jobs: Vec::new(),
             start_time: Instant::now(),
             last_frame_time: Instant::now(),
             frame_times: Vec::new(),
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            box_select_start: None,
+            hovered: None,
             cursor_ray: Ray {
                 origin: Vector3::new(0.0, 0.0, 0.0),
                 direction: Vector3::new(0.0, 0.0, -1.0),
             },
             renderer,
             window,
+            output_window: None,
+            detach_output: false,
+            exr_color_space: texture::ExrColorSpace::default(),
+            fullscreen_mode: None,
+            title: String::new(),
+            show_panels: true,
+            presentation_mode: false,
+            lock_presentation_input: true,
+            command_palette: CommandPalette::default(),
+            camera_bookmarks: Default::default(),
+            clipboard: arboard::Clipboard::new()
+                .map_err(|e| log::info!("The clipboard isn't available: {}", e))
+                .ok(),
+            frame_capture: FrameCapture::new(),
+            hotkeys: Hotkeys::load(),
+            modifiers: ModifiersState::empty(),
+            discarding: None,
+            repairs,
+            should_quit: false,
+        };
+        if let Some(scene_path) = scene_path {
+            app.scene_path = scene_path.to_string_lossy().into_owned();
+            app.load_scene_file();
         }
+        app
     }
 
     fn render_ui(&mut self) {
@@ -179,11 +557,12 @@ impl App {
         let avg_frame_time =
             self.frame_times.iter().sum::<u128>() as f64 / self.frame_times.len() as f64;
         let context = self.ui.platform.borrow().context();
+        let show_panels = self.show_panels && !self.presentation_mode;
 
         egui::panel::SidePanel::left("top_panel")
             .min_width(200.0)
             .resizable(true)
-            .show(&context, |ui| {
+            .show_animated(&context, show_panels, |ui| {
                 ui.heading("Pathtracer");
                 ui.separator();
 
@@ -192,14 +571,835 @@ impl App {
                     avg_frame_time,
                     1000.0 / avg_frame_time
                 )));
+                self.render_status_ui(ui);
+
+                ui.checkbox(&mut self.detach_output, "Detach render output")
+                    .on_hover_text(
+                        "Show the render in a separate borderless window, \
+                        on another monitor if there is one",
+                    );
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Save image")
+                        .on_hover_text("Save the render as a PNG in the working directory")
+                        .clicked()
+                    {
+                        self.perform(Action::SaveImage);
+                    }
+                    if ui
+                        .button("Save EXR")
+                        .on_hover_text(
+                            "Save the linear render as a 32-bit OpenEXR in the working \
+                            directory, for tonemapping elsewhere",
+                        )
+                        .clicked()
+                    {
+                        self.perform(Action::SaveExr);
+                    }
+                    egui::ComboBox::from_id_source("exr color space")
+                        .selected_text(self.exr_color_space.name())
+                        .show_ui(ui, |ui| {
+                            for color_space in texture::ExrColorSpace::ALL {
+                                ui.selectable_value(
+                                    &mut self.exr_color_space,
+                                    color_space,
+                                    color_space.name(),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("The color space EXRs are saved in");
+                });
+                self.frame_capture.render_ui(ui);
+                self.jobs.render_ui(ui);
+                self.render_scene_file_ui(ui);
 
                 ui.separator();
 
+                self.render_display_ui(ui);
+                self.overlays.render_ui(ui);
+                self.gizmo.render_ui(ui);
+                self.texture_budget.render_ui(ui);
+                self.ui.render_accessibility_ui(ui);
+                self.hotkeys.render_ui(ui);
                 self.renderer
                     .render_ui(ui, self.scene.camera.moved_recently());
+                if self.renderer.take_calibration_request() {
+                    self.perform(Action::Calibrate);
+                }
+                if self.renderer.take_display_lut_request() {
+                    self.pick_display_lut();
+                }
+                self.render_environment_ui(ui);
                 self.render_camera_ui(ui);
-                self.scene.render_ui(ui, &context, &mut self.renderer);
+                self.scatter_brush.render_ui(ui, &self.scene);
+                self.texture_painter.render_ui(ui, &mut self.scene);
+                self.sphere_packing.render_ui(ui, &mut self.scene);
+                self.point_cache
+                    .render_ui(ui, &mut self.scene, &mut self.jobs);
+                if let Some(action) = self.diagnostics.render_ui(ui) {
+                    self.perform(action);
+                }
+                self.scene.render_ui(
+                    ui,
+                    &context,
+                    egui::Image::new(egui::load::SizedTexture::new(
+                        self.material_preview,
+                        egui::Vec2::splat(MATERIAL_PREVIEW_SIZE as f32),
+                    )),
+                );
             });
+
+        // The render is in the other window when detached
+        if self.output_window.is_none() && !self.presentation_mode {
+            self.outlines.draw(
+                &context,
+                &self.scene,
+                &self.scene.camera,
+                self.window_size,
+                self.hovered,
+                self.ui.palette.highlight(),
+            );
+            self.overlays.draw(
+                &context,
+                &self.scene,
+                &self.scene.camera,
+                self.window_size,
+                self.ui.palette.highlight(),
+            );
+            self.gizmo.draw(
+                &context,
+                &self.scene,
+                &self.scene.camera,
+                self.window_size,
+                self.ui.palette,
+            );
+            self.draw_selection_box(&context);
+        }
+        if !self.presentation_mode {
+            self.hud.draw(&context);
+        }
+
+        if let Some(discarding) = self.discarding {
+            let (question, confirmation) = discarding.prompt();
+            egui::Window::new("Unsaved changes")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(&context, |ui| {
+                    ui.label(format!(
+                        "\"{}\" has unsaved changes. {}",
+                        self.scene.name, question
+                    ));
+                    ui.horizontal(|ui| {
+                        if ui.button(confirmation).clicked() {
+                            self.discarding = None;
+                            self.discard(discarding);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.discarding = None;
+                        }
+                    });
+                });
+        }
+
+        if !self.repairs.is_empty() {
+            egui::Window::new("Scene repaired")
+                .collapsible(false)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .show(&context, |ui| {
+                    ui.label(format!(
+                        "\"{}\" had data the renderer can't handle, which was repaired:",
+                        self.scene.name
+                    ));
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for repair in &self.repairs {
+                                ui.label(repair.to_string());
+                            }
+                        });
+                    if ui.button("OK").clicked() {
+                        self.repairs.clear();
+                    }
+                });
+        }
+
+        self.renderer.render_shader_error_ui(&context);
+
+        if let Some(action) =
+            self.command_palette
+                .render_ui(&context, &self.available_actions(), &self.hotkeys)
+        {
+            self.perform(action);
+        }
+    }
+
+    /// Lets everything that depends on the scene react to what changed since the last frame.
+    fn handle_scene_events(&mut self) {
+        for event in self.scene.take_events() {
+            self.renderer
+                .handle_event(&self.device, &self.scene, &event);
+            self.point_cache.handle_event(&event);
+            if let SceneEvent::ObjectRemoved(uuid) = event {
+                self.scene.deselect(uuid);
+            }
+        }
+    }
+
+    /// The actions that can currently be performed.
+    fn available_actions(&self) -> Vec<Action> {
+        Action::ALL
+            .into_iter()
+            .filter(|&action| match action {
+                Action::OpenCommandPalette => false,
+                Action::ToggleHdrOutput => self.hdr_supported,
+                Action::CaptureFrame => self.frame_capture.is_available(),
+                Action::ApplyQualityPreset(index) => {
+                    (index as usize) < self.renderer.quality_preset_count()
+                }
+                Action::ClearSelection
+                | Action::RemoveSelection
+                | Action::DuplicateSelection
+                | Action::FrameSelection => !self.scene.selection.is_empty(),
+                Action::CopySelection => {
+                    self.clipboard.is_some() && !self.scene.selection.is_empty()
+                }
+                Action::Paste => self.clipboard.is_some(),
+                Action::RecallCameraBookmark(index) => {
+                    self.camera_bookmarks[index as usize].is_some()
+                }
+                Action::SaveScene => self.save_job.is_none(),
+                Action::LoadScene | Action::ImportScene => self.scene_job.is_none(),
+                Action::DropSelectionToGround => self.scene.has_selected_spheres(),
+                _ => true,
+            })
+            .collect()
+    }
+
+    pub fn perform(&mut self, action: Action) {
+        match action {
+            Action::OpenCommandPalette => self.command_palette.open(),
+            Action::AddSphere => self.scene.add_sphere(),
+            Action::RemoveSphere => self.scene.remove_last_sphere(),
+            Action::SelectNextSphere => self.cycle_selection(1),
+            Action::SelectPreviousSphere => self.cycle_selection(-1),
+            Action::ClearSelection => self.scene.clear_selection(),
+            Action::RemoveSelection => self.scene.remove_selected(),
+            Action::DuplicateSelection => self.scene.duplicate_selected(),
+            Action::CopySelection => self.copy_selection(),
+            Action::Paste => self.paste(),
+            Action::DropSelectionToGround => self.scene.drop_selected_to_ground(),
+            Action::FrameSelection => self.scene.frame_selection(),
+            Action::TranslateMode => self.gizmo.set_mode(GizmoMode::Translate),
+            Action::RotateMode => self.gizmo.set_mode(GizmoMode::Rotate),
+            Action::ScaleMode => self.gizmo.set_mode(GizmoMode::Scale),
+            Action::TogglePanels => self.show_panels = !self.show_panels,
+            Action::TogglePresentationMode => self.toggle_presentation_mode(),
+            Action::ToggleFullscreen => self.toggle_fullscreen(),
+            Action::ToggleDetachOutput => self.detach_output = !self.detach_output,
+            Action::TogglePictureInPicture => {
+                self.renderer.picture_in_picture = !self.renderer.picture_in_picture
+            }
+            Action::ToggleHdrOutput => {
+                if self.hdr_supported {
+                    self.set_hdr_output(self.config.format != HDR_OUTPUT_FORMAT);
+                }
+            }
+            Action::ToggleFalseColor => self.renderer.toggle_false_color(),
+            Action::ToggleReferenceMode => self.renderer.toggle_reference_mode(),
+            Action::ToggleNormalsView => self.renderer.toggle_debug_view(DebugView::Normals),
+            Action::SaveCameraBookmark(index) => {
+                self.camera_bookmarks[index as usize] = Some(self.scene.camera.clone());
+            }
+            Action::RecallCameraBookmark(index) => {
+                if let Some(camera) = &self.camera_bookmarks[index as usize] {
+                    self.scene.camera = camera.clone();
+                    self.scene.camera.mark_moved();
+                    self.camera_controller.look_in(camera.forward);
+                }
+            }
+            Action::SaveImage => self.save_image(image::ImageFormat::Png),
+            Action::SaveExr => self.save_image(image::ImageFormat::OpenExr),
+            Action::SaveScene => {
+                if self.save_job.is_none() {
+                    self.save_scene_file();
+                }
+            }
+            Action::LoadScene => self.pick_scene_file("Load scene", "Scene", &["json"]),
+            Action::ImportScene => {
+                self.pick_scene_file("Import scene", "PBRT or Mitsuba scene", &["pbrt", "xml"]);
+            }
+            Action::LoadEnvironment => self.pick_environment(),
+            Action::CaptureFrame => self.frame_capture.request(),
+            Action::ApplyQualityPreset(index) => {
+                self.renderer.apply_quality_preset(index as usize);
+            }
+            Action::Calibrate => {
+                let adapter = self.adapter.get_info().name;
+                self.renderer
+                    .calibrate(&self.device, &self.queue, &self.scene, &adapter);
+            }
+            Action::LoadVeachMisScene | Action::LoadWhiteFurnaceScene | Action::RunFurnaceCheck => {
+                self.confirm_discarding(Discarding::LoadDiagnosticScene(action));
+            }
+            Action::Quit => self.confirm_discarding(Discarding::Quit),
+        }
+    }
+
+    /// Replaces the scene with one of the diagnostic scenes, lit by an environment of the same
+    /// `radiance` everywhere.
+    fn load_diagnostic_scene(&mut self, scene: Scene, radiance: f32) {
+        self.renderer
+            .set_uniform_environment(&self.device, &self.queue, radiance);
+        self.environments.clear_selection();
+        self.scene = scene;
+        self.scene.publish(SceneEvent::SceneReplaced);
+        self.scene.publish(SceneEvent::EnvironmentChanged);
+    }
+
+    /// Checks the white furnace once it has converged, if a check is waiting for it.
+    fn update_furnace_check(&mut self) {
+        if !self.diagnostics.is_checking() || self.scene.camera.moved_recently() {
+            return;
+        }
+        let (samples, target_samples) = self.renderer.sample_progress(false);
+        if samples < target_samples {
+            return;
+        }
+        match self.renderer.read_image(&self.device, &self.queue) {
+            Some(Ok(image)) => self.diagnostics.finish_check(&image, samples),
+            Some(Err(e)) => eprintln!("Failed to read back the render: {}", e),
+            // Not displayed yet, so wait for it
+            None => {}
+        }
+    }
+
+    /// Summarizes the render state in a label that screen readers announce when it changes.
+    fn render_status_ui(&self, ui: &mut egui::Ui) {
+        let (samples, target_samples) = self
+            .renderer
+            .sample_progress(self.scene.camera.moved_recently());
+        ui.label(format!("Samples: {}/{}", samples, target_samples));
+
+        let selected = match self.scene.selection.as_slice() {
+            [] => "nothing".to_string(),
+            [uuid] => self.scene.name_of(*uuid).unwrap_or("nothing").to_string(),
+            selection => format!("{} objects", selection.len()),
+        };
+        let status = format!(
+            "{} ({}), selected: {}",
+            if samples < target_samples {
+                "Rendering"
+            } else {
+                "Converged"
+            },
+            self.renderer.render_mode(),
+            selected
+        );
+
+        let response = ui.label(status);
+        ui.ctx().accesskit_node_builder(response.id, |builder| {
+            builder.set_live(egui::accesskit::Live::Polite);
+        });
+    }
+
+    fn window_title(&self) -> String {
+        format!(
+            "{} - {}{}",
+            WINDOW_TITLE,
+            self.scene.name,
+            if self.scene.is_dirty() { "*" } else { "" }
+        )
+    }
+
+    /// Does what `discarding` does right away, or asks for confirmation first if the scene has
+    /// unsaved changes.
+    fn confirm_discarding(&mut self, discarding: Discarding) {
+        if self.scene.is_dirty() {
+            self.discarding = Some(discarding);
+        } else {
+            self.discard(discarding);
+        }
+    }
+
+    fn discard(&mut self, discarding: Discarding) {
+        match discarding {
+            Discarding::Quit => self.should_quit = true,
+            Discarding::LoadScene => self.load_scene_file(),
+            Discarding::LoadDiagnosticScene(Action::LoadVeachMisScene) => {
+                self.load_diagnostic_scene(Scene::veach_mis(), 0.0);
+            }
+            Discarding::LoadDiagnosticScene(Action::RunFurnaceCheck) => {
+                self.load_diagnostic_scene(Scene::white_furnace(), FURNACE_RADIANCE);
+                self.diagnostics.start_check();
+            }
+            Discarding::LoadDiagnosticScene(_) => {
+                self.load_diagnostic_scene(Scene::white_furnace(), FURNACE_RADIANCE);
+            }
+        }
+    }
+
+    fn render_display_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Display", |ui| {
+            let mut fullscreen = self.window.fullscreen().is_some();
+            if ui
+                .checkbox(&mut fullscreen, "fullscreen")
+                .on_hover_text("F11")
+                .changed()
+            {
+                self.toggle_fullscreen();
+            }
+
+            let video_modes = self
+                .window
+                .current_monitor()
+                .map(|monitor| monitor.video_modes().collect::<Vec<_>>())
+                .unwrap_or_default();
+
+            let selected_text = self
+                .fullscreen_mode
+                .as_ref()
+                .map_or("Borderless".to_string(), display_mode_label);
+
+            let mut changed = false;
+            egui::ComboBox::from_label("display mode")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    changed |= ui
+                        .selectable_value(&mut self.fullscreen_mode, None, "Borderless")
+                        .changed();
+                    for video_mode in video_modes {
+                        let label = display_mode_label(&video_mode);
+                        changed |= ui
+                            .selectable_value(&mut self.fullscreen_mode, Some(video_mode), label)
+                            .changed();
+                    }
+                });
+
+            if changed && fullscreen {
+                self.window.set_fullscreen(Some(self.fullscreen()));
+            }
+
+            let mut presentation_mode = self.presentation_mode;
+            if ui
+                .checkbox(&mut presentation_mode, "presentation mode")
+                .on_hover_text("Hide everything but the render, until toggled again (F10)")
+                .changed()
+            {
+                self.toggle_presentation_mode();
+            }
+            ui.checkbox(
+                &mut self.lock_presentation_input,
+                "only move the camera when presenting",
+            )
+            .on_hover_text(
+                "Ignore clicks and every hotkey but the ones for presentation mode, \
+                fullscreen, saving images and quitting while presenting",
+            );
+
+            let mut hdr = self.config.format == HDR_OUTPUT_FORMAT;
+            let hdr_checkbox = ui
+                .add_enabled(
+                    self.hdr_supported,
+                    egui::Checkbox::new(&mut hdr, "HDR output"),
+                )
+                .on_hover_text("Output extended range linear colors for HDR displays")
+                .on_disabled_hover_text("The display doesn't support HDR output");
+            if hdr_checkbox.changed() {
+                self.set_hdr_output(hdr);
+            }
+
+            ui.add_enabled(
+                hdr,
+                egui::Slider::new(&mut self.renderer.paper_white, 80.0..=500.0)
+                    .text("paper white (nits)"),
+            )
+            .on_hover_text(
+                "The brightness of diffuse white. Highlights are clipped at this level \
+                until samples are accumulated in floating point.",
+            );
+        });
+    }
+
+    /// Puts the selected objects on the clipboard as text, to be pasted into another scene.
+    fn copy_selection(&mut self) {
+        let (Some(clipboard), Some(text)) = (&mut self.clipboard, self.scene.copy_selected())
+        else {
+            return;
+        };
+        if let Err(e) = clipboard.set_text(text) {
+            eprintln!("Failed to copy the selection: {}", e);
+        }
+    }
+
+    /// Adds the objects on the clipboard to the scene, logging whatever had to be repaired.
+    fn paste(&mut self) {
+        let Some(clipboard) = &mut self.clipboard else {
+            return;
+        };
+        let text = match clipboard.get_text() {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to read the clipboard: {}", e);
+                return;
+            }
+        };
+        match self.scene.paste(&text) {
+            Ok(repairs) => log_repairs(&self.scene.name, &repairs),
+            Err(e) => eprintln!("Failed to paste: {}", e),
+        }
+    }
+
+    /// Shows or hides everything but the render, dropping any drag in progress so it
+    /// doesn't carry on unseen.
+    fn toggle_presentation_mode(&mut self) {
+        self.presentation_mode = !self.presentation_mode;
+        self.gizmo.end_drag();
+        self.scatter_brush.end_stroke();
+        self.texture_painter.end_stroke();
+        self.box_select_start = None;
+    }
+
+    /// Whether input other than the camera's is ignored.
+    fn input_locked(&self) -> bool {
+        self.presentation_mode && self.lock_presentation_input
+    }
+
+    fn set_hdr_output(&mut self, enabled: bool) {
+        let format = if enabled {
+            HDR_OUTPUT_FORMAT
+        } else {
+            self.sdr_format
+        };
+
+        self.config.format = format;
+        self.surface.configure(&self.device, &self.config);
+        self.renderer.set_output_format(&self.device, format);
+        // Reopened with the new format by `sync_output_window`
+        self.output_window = None;
+    }
+
+    fn fullscreen(&self) -> Fullscreen {
+        match &self.fullscreen_mode {
+            Some(video_mode) => Fullscreen::Exclusive(video_mode.clone()),
+            None => Fullscreen::Borderless(self.window.current_monitor()),
+        }
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        if self.window.fullscreen().is_some() {
+            self.window.set_fullscreen(None);
+        } else {
+            self.window.set_fullscreen(Some(self.fullscreen()));
+        }
+    }
+
+    fn render_environment_ui(&mut self, ui: &mut egui::Ui) {
+        let mut open_dialog = false;
+        let selected = ui
+            .collapsing("Environment", |ui| {
+                self.renderer.render_environment_ui(ui, &self.scene.camera);
+                ui.separator();
+                open_dialog = ui
+                    .button("Load environment...")
+                    .on_hover_text("Light the scene with a Radiance HDR image from anywhere")
+                    .clicked();
+                let selected = self.environments.render_ui(ui);
+                let downloaded = ui
+                    .collapsing("Poly Haven", |ui| {
+                        self.poly_haven.render_ui(ui, &mut self.jobs)
+                    })
+                    .body_returned
+                    .flatten();
+                selected.or(downloaded)
+            })
+            .body_returned
+            .flatten();
+        if open_dialog {
+            self.perform(Action::LoadEnvironment);
+        }
+        if let Some(path) = selected {
+            self.load_environment(path);
+        }
+    }
+
+    /// Asks for an HDRI to load with a file dialog.
+    fn pick_environment(&mut self) {
+        let path = rfd::FileDialog::new()
+            .set_title("Load environment")
+            .add_filter("Radiance HDR", &["hdr"])
+            .pick_file();
+        if let Some(path) = path {
+            self.load_environment(path);
+        }
+    }
+
+    /// Asks for a `.cube` display LUT to tone map with a file dialog.
+    fn pick_display_lut(&mut self) {
+        let path = rfd::FileDialog::new()
+            .set_title("Load display LUT")
+            .add_filter("Cube LUT", &["cube"])
+            .pick_file();
+        if let Some(path) = path {
+            match DisplayLut::load(&path) {
+                Ok(lut) => self.renderer.set_display_lut(path, lut),
+                Err(e) => eprintln!("Failed to load {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Decodes the HDRI at `path` in the background, switching to it once it's ready.
+    fn load_environment(&mut self, path: PathBuf) {
+        let name = format!("Loading {}", path.display());
+        let available = self.texture_budget.available(ENVIRONMENT_BUDGET_NAME);
+        // Replacing the handle cancels the previous selection's job
+        self.environment_job = Some(self.jobs.spawn(name, move |job| {
+            let data = std::fs::read(&path);
+            job.set_progress(0.5);
+            if job.is_cancelled() {
+                return None;
+            }
+            let pixels = data
+                .map_err(image::ImageError::from)
+                .and_then(|data| texture::read_hdr_pixels(&data))
+                .map(|(width, height, pixels)| {
+                    // Uploaded as 32-bit floats before being converted to the cube map
+                    let halvings = texture::halvings_to_fit(width, height, 16, available);
+                    let downscaled = texture::downscale_pixels(width, height, pixels, halvings);
+                    ((width, height), downscaled)
+                });
+            Some((path, pixels))
+        }));
+    }
+
+    /// Switches to the environment being loaded in the background once it's decoded.
+    fn update_environment(&mut self) {
+        let Some(job) = &self.environment_job else {
+            return;
+        };
+        let Some((path, result)) = job.try_take() else {
+            if job.is_finished() {
+                self.environment_job = None;
+            }
+            return;
+        };
+        self.environment_job = None;
+
+        match result {
+            Ok((original, (width, height, pixels))) => {
+                self.texture_budget
+                    .record(ENVIRONMENT_BUDGET_NAME, original, (width, height), 16);
+                self.renderer
+                    .set_environment(&self.device, &self.queue, width, height, &pixels);
+                self.scene.publish(SceneEvent::EnvironmentChanged);
+            }
+            Err(e) => eprintln!("Failed to load {}: {}", path.display(), e),
+        }
+    }
+
+    /// Reads back the render and writes it to a PNG or EXR named after the scene in the
+    /// background. The render is read from its viewport rather than the window, so the UI
+    /// drawn over it is never captured.
+    fn save_image(&mut self, format: image::ImageFormat) {
+        let image = match self.renderer.read_image(&self.device, &self.queue) {
+            Some(Ok(image)) => image,
+            Some(Err(e)) => {
+                eprintln!("Failed to read back the render: {}", e);
+                return;
+            }
+            None => {
+                eprintln!("Nothing has been rendered yet");
+                return;
+            }
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let extension = format.extensions_str()[0];
+        let path = PathBuf::from(format!("{}-{}.{}", self.scene.name, timestamp, extension));
+        let metadata = self.renderer.image_metadata(&self.scene);
+        let tone_mapping = self.renderer.tone_mapping();
+        let color_space = self.exr_color_space;
+        let name = format!("Saving {}", path.display());
+        self.image_jobs.push(self.jobs.spawn(name, move |_| {
+            let (width, height) = (image.width, image.height);
+            let result = if format == image::ImageFormat::OpenExr {
+                let pixels = &image.pixels;
+                texture::write_exr(&path, width, height, pixels, color_space, &metadata)
+            } else {
+                // PNGs can't hold radiance, so they look like the view does
+                let pixels = image.pixels.into_iter().map(|p| tone_mapping.apply(p));
+                let pixels = pixels.collect::<Vec<_>>();
+                texture::write_png(&path, width, height, &pixels, &metadata)
+            };
+            Some((path, result))
+        }));
+    }
+
+    fn update_image_jobs(&mut self) {
+        self.image_jobs.retain(|job| {
+            if let Some((path, result)) = job.try_take() {
+                match result {
+                    Ok(()) => log::info!("Saved {}", path.display()),
+                    Err(e) => eprintln!("Failed to save {}: {}", path.display(), e),
+                }
+                return false;
+            }
+            !job.is_finished()
+        });
+    }
+
+    fn render_scene_file_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.scene_path)
+                    .hint_text("scene.json")
+                    .desired_width(120.0),
+            );
+
+            if ui
+                .add_enabled(self.save_job.is_none(), egui::Button::new("Save scene"))
+                .clicked()
+            {
+                self.save_scene_file();
+            }
+
+            if ui
+                .add_enabled(self.scene_job.is_none(), egui::Button::new("Load scene"))
+                .clicked()
+            {
+                self.confirm_discarding(Discarding::LoadScene);
+            }
+        });
+
+        let clicked = ui
+            .collapsing("Scene files", |ui| self.scenes.render_ui(ui))
+            .body_returned
+            .flatten();
+        if let Some(path) = clicked {
+            if self.scene_job.is_none() {
+                self.scene_path = path.to_string_lossy().into_owned();
+                self.confirm_discarding(Discarding::LoadScene);
+            }
+        }
+    }
+
+    /// Writes the scene and the renderer's settings to the scene path in the background, from a
+    /// snapshot so the scene can be edited meanwhile, along with a thumbnail of the render for
+    /// the scene files' gallery.
+    fn save_scene_file(&mut self) {
+        let path = PathBuf::from(&self.scene_path);
+        let snapshot = self.scene.snapshot(&self.renderer.render_settings());
+        let image = match self.renderer.read_image(&self.device, &self.queue) {
+            Some(Ok(image)) => Some(image),
+            Some(Err(e)) => {
+                log::warn!("Failed to read back the render for a thumbnail: {}", e);
+                None
+            }
+            None => None,
+        };
+        let tone_mapping = self.renderer.tone_mapping();
+        let name = format!("Saving {}", path.display());
+        self.save_job = Some(self.jobs.spawn(name, move |_| {
+            let result = snapshot.write(&path);
+            if let (Ok(_), Some(image)) = (&result, image) {
+                let pixels = image.pixels.into_iter().map(|p| tone_mapping.apply(p));
+                let pixels = pixels.collect::<Vec<_>>();
+                let thumbnail = thumbnails::render(image.width, image.height, &pixels);
+                if let Err(e) = thumbnails::store(&path, &thumbnail) {
+                    log::warn!("Failed to cache thumbnail for {}: {}", path.display(), e);
+                }
+            }
+            Some((path, result))
+        }));
+    }
+
+    /// Names the scene after the file it was saved to once it's written.
+    fn update_save_job(&mut self) {
+        let Some(job) = &self.save_job else {
+            return;
+        };
+        let Some((path, result)) = job.try_take() else {
+            if job.is_finished() {
+                self.save_job = None;
+            }
+            return;
+        };
+        self.save_job = None;
+
+        match result {
+            Ok(saved) => {
+                self.scene.mark_saved(&path, saved);
+                self.scenes.refresh();
+                log::info!("Saved {}", path.display());
+            }
+            Err(e) => eprintln!("Failed to save {}: {}", path.display(), e),
+        }
+    }
+
+    /// Asks for a scene file with a file dialog and loads it once any unsaved changes are
+    /// confirmed lost.
+    fn pick_scene_file(&mut self, title: &str, filter: &str, extensions: &[&str]) {
+        if self.scene_job.is_some() {
+            return;
+        }
+        let path = rfd::FileDialog::new()
+            .set_title(title)
+            .add_filter(filter, extensions)
+            .pick_file();
+        if let Some(path) = path {
+            self.scene_path = path.to_string_lossy().into_owned();
+            self.confirm_discarding(Discarding::LoadScene);
+        }
+    }
+
+    /// Reads the scene file at the scene path in the background, importing it if it's in
+    /// another renderer's format.
+    fn load_scene_file(&mut self) {
+        let path = PathBuf::from(&self.scene_path);
+        let name = format!("Loading {}", path.display());
+        self.scene_job = Some(self.jobs.spawn(name, move |_| {
+            let loaded = if Scene::can_import(&path) {
+                Scene::import(&path).map(|scene| (scene, None))
+            } else {
+                Scene::load(&path).map(|(scene, settings)| (scene, Some(settings)))
+            };
+            let scene = loaded.map(|(mut scene, render_settings)| {
+                let repairs = scene.validate();
+                (scene, render_settings, repairs)
+            });
+            Some((path, scene))
+        }));
+    }
+
+    /// Replaces the scene and the renderer's settings with a scene file once it's read, keeping
+    /// the settings for an imported scene.
+    fn update_scene_file(&mut self) {
+        let Some(job) = &self.scene_job else {
+            return;
+        };
+        let Some((path, result)) = job.try_take() else {
+            if job.is_finished() {
+                self.scene_job = None;
+            }
+            return;
+        };
+        self.scene_job = None;
+
+        match result {
+            Ok((scene, render_settings, repairs)) => {
+                log_repairs(&scene.name, &repairs);
+                self.repairs = repairs;
+                if let Some(render_settings) = render_settings {
+                    self.renderer.set_render_settings(render_settings);
+                }
+                self.scene = scene;
+                self.scene.publish(SceneEvent::SceneReplaced);
+            }
+            Err(e) => eprintln!("Failed to load {}: {}", path.display(), e),
+        }
     }
 
     fn render_camera_ui(&mut self, ui: &mut egui::Ui) {
@@ -218,11 +1418,104 @@ impl App {
             });
             ui.label("Vertical FOV");
             ui.add(egui::Slider::new(&mut self.scene.camera.vfov, 0.0..=180.0));
+            ui.label("Projection");
+            let camera = &mut self.scene.camera;
+            ui.horizontal(|ui| {
+                ui.radio_value(
+                    &mut camera.projection,
+                    Projection::Perspective,
+                    "Perspective",
+                );
+                ui.radio_value(&mut camera.projection, Projection::Stereo360, "360° stereo")
+                    .on_hover_text(
+                        "Render both eyes' equirectangular panoramas, the left above the right, \
+                        for viewing saved images in a VR headset",
+                    );
+                ui.radio_value(&mut camera.projection, Projection::Stereo180, "180° stereo")
+                    .on_hover_text(
+                        "Render the half sphere in front for each eye, the left beside the \
+                        right (VR180)",
+                    );
+            });
+            if camera.projection != Projection::Perspective {
+                ui.add(egui::Slider::new(&mut camera.ipd, 0.0..=0.2).text("IPD"))
+                    .on_hover_text("The distance between the eyes, in scene units");
+            }
             ui.label("Speed");
             ui.add(egui::Slider::new(
                 &mut self.camera_controller.speed,
                 0.0..=10.0,
             ));
+            ui.horizontal(|ui| {
+                let scroll_zoom = &mut self.camera_controller.scroll_zoom;
+                ui.label("Scrolling");
+                ui.radio_value(scroll_zoom, ScrollZoom::Fov, "zooms")
+                    .on_hover_text("Narrow or widen the field of view");
+                ui.radio_value(scroll_zoom, ScrollZoom::Dolly, "dollies")
+                    .on_hover_text("Move the camera forwards or backwards");
+            });
+
+            ui.collapsing("Shake", |ui| {
+                let shake = &mut self.camera_controller.shake;
+                ui.checkbox(&mut shake.enabled, "enabled").on_hover_text(
+                    "Sway the camera like it's hand-held, following the animation's time \
+                    so exported frames are shaken the same way each time",
+                );
+                ui.add_enabled(
+                    shake.enabled,
+                    egui::Slider::new(&mut shake.amplitude, 0.0..=5.0).text("amplitude (°)"),
+                );
+                ui.add_enabled(
+                    shake.enabled,
+                    egui::Slider::new(&mut shake.frequency, 0.1..=10.0).text("frequency (Hz)"),
+                );
+            });
+
+            ui.collapsing("Final camera", |ui| {
+                ui.checkbox(&mut self.renderer.picture_in_picture, "picture-in-picture")
+                    .on_hover_text("Show the final camera's view in the corner of the viewport");
+
+                if ui
+                    .button("Set to current view")
+                    .on_hover_text("Move the final camera to where the free camera is")
+                    .clicked()
+                {
+                    self.scene.final_camera = self.scene.camera.clone();
+                    self.scene.final_camera.mark_moved();
+                    self.scene.publish(SceneEvent::CameraMoved);
+                }
+
+                let final_camera = &mut self.scene.final_camera;
+                let mut responses = Vec::new();
+                ui.label("Origin");
+                ui.horizontal(|ui| {
+                    responses.extend([
+                        ui.add(egui::DragValue::new(&mut final_camera.origin.x).speed(0.1)),
+                        ui.add(egui::DragValue::new(&mut final_camera.origin.y).speed(0.1)),
+                        ui.add(egui::DragValue::new(&mut final_camera.origin.z).speed(0.1)),
+                    ]);
+                });
+                ui.label("Forward");
+                let mut forward = final_camera.forward;
+                ui.horizontal(|ui| {
+                    responses.extend([
+                        ui.add(egui::DragValue::new(&mut forward.x).speed(0.1)),
+                        ui.add(egui::DragValue::new(&mut forward.y).speed(0.1)),
+                        ui.add(egui::DragValue::new(&mut forward.z).speed(0.1)),
+                    ]);
+                });
+                ui.label("Vertical FOV");
+                responses.push(ui.add(egui::Slider::new(&mut final_camera.vfov, 0.0..=180.0)));
+
+                if responses.iter().any(|r| r.changed()) {
+                    // A zero direction has no way to look, so the camera keeps the last one
+                    if forward.magnitude2() > 0.0 {
+                        final_camera.look_in(forward);
+                    }
+                    final_camera.mark_moved();
+                    self.scene.publish(SceneEvent::CameraMoved);
+                }
+            });
         });
     }
 
@@ -236,17 +1529,39 @@ impl App {
             self.frame_times.remove(0);
         }
 
+        let time = self.scene.time();
         self.camera_controller
-            .update_camera(&mut self.scene.camera, delta.as_secs_f32());
-        self.scene.update();
+            .update_camera(&mut self.scene.camera, delta.as_secs_f32(), time);
+        self.point_cache
+            .update(&mut self.scene, delta.as_secs_f32());
+        if let Some(path) = self.poly_haven.update() {
+            self.load_environment(path);
+        }
+        self.update_environment();
+        self.update_scene_file();
+        self.update_save_job();
+        self.update_image_jobs();
+        self.update_furnace_check();
+        let (samples, target) = self
+            .renderer
+            .sample_progress(self.scene.camera.moved_recently());
+        self.hud.update(samples, target);
+
+        let title = self.window_title();
+        if title != self.title {
+            self.window.set_title(&title);
+            self.title = title;
+        }
     }
 
     pub fn ui_input(&mut self, event: &Event<()>) {
-        self.ui.handle_event(event);
+        self.ui.handle_event(event, &self.window);
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         self.render_ui();
+        self.handle_scene_events();
+        self.frame_capture.begin_frame();
 
         let mut output = self.surface.get_current_texture()?;
 
@@ -256,8 +1571,38 @@ impl App {
                 label: Some("Render Encoder"),
             });
 
-        self.renderer
-            .render(&mut output, &mut encoder, &self.scene, &self.queue)?;
+        let mut detached_output = None;
+        if let Some(output_window) = &mut self.output_window {
+            let mut detached = output_window.get_current_texture(&self.device)?;
+            self.renderer.resize(
+                &self.device,
+                detached.texture.width(),
+                detached.texture.height(),
+            );
+            self.renderer.render(
+                &mut detached,
+                &mut encoder,
+                &self.scene,
+                &self.device,
+                &self.queue,
+            )?;
+            detached_output = Some(detached);
+
+            clear(&mut encoder, &output);
+        } else {
+            self.renderer.resize(
+                &self.device,
+                output.texture.width(),
+                output.texture.height(),
+            );
+            self.renderer.render(
+                &mut output,
+                &mut encoder,
+                &self.scene,
+                &self.device,
+                &self.queue,
+            )?;
+        }
 
         self.ui.render(
             &mut encoder,
@@ -268,7 +1613,12 @@ impl App {
         );
 
         self.queue.submit(Some(encoder.finish()));
+        self.renderer.after_submit();
         output.present();
+        if let Some(detached) = detached_output {
+            detached.present();
+        }
+        self.frame_capture.end_frame();
 
         Ok(())
     }
@@ -282,50 +1632,235 @@ impl App {
         }
     }
 
+    /// Opens or closes the output window to match the "Detach render output" checkbox.
+    fn sync_output_window(&mut self, target: &EventLoopWindowTarget<()>) {
+        match (self.detach_output, &self.output_window) {
+            (true, None) => {
+                self.output_window = OutputWindow::new(
+                    target,
+                    &self.window,
+                    &self.instance,
+                    &self.adapter,
+                    &self.device,
+                    self.config.format,
+                );
+                if self.output_window.is_none() {
+                    eprintln!("Failed to open the output window");
+                    self.detach_output = false;
+                }
+            }
+            (false, Some(_)) => self.output_window = None,
+            _ => {}
+        }
+    }
+
+    fn handle_output_window_event(&mut self, event: &WindowEvent) {
+        let Some(output_window) = &mut self.output_window else {
+            return;
+        };
+
+        match event {
+            WindowEvent::Resized(physical_size) => {
+                output_window.resize(&self.device, *physical_size);
+            }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                output_window.resize(&self.device, **new_inner_size);
+            }
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    },
+                ..
+            } => self.detach_output = false,
+            _ => {}
+        }
+    }
+
     fn handle_pointer_move(&mut self, position: PhysicalPosition<f64>) {
         let ray = self
             .scene
             .camera
             .screen_pos_to_ray(position, self.window_size);
+        self.cursor_position = position;
         self.cursor_ray = ray;
+        if self.gizmo.is_dragging() {
+            self.gizmo
+                .drag(&mut self.scene, &self.cursor_ray, self.modifiers);
+        } else {
+            self.gizmo
+                .hover(&self.scene, &self.scene.camera, position, self.window_size);
+        }
+        self.hovered = self.scene.raycast(&ray).map(|hit| hit.object);
+        self.scatter_brush.paint(&mut self.scene, &self.cursor_ray);
+        self.texture_painter
+            .paint(&mut self.scene, &self.cursor_ray);
     }
 
     fn handle_pointer_input(&mut self, button: MouseButton, state: ElementState) {
-        if button == MouseButton::Left && state == ElementState::Pressed {
-            let closest_hit = self
-                .scene
-                .hit_closest_sphere(&self.cursor_ray, 0.001, 1000.0);
-
-            if let Some(HitRecord { sphere, .. }) = closest_hit {
-                if sphere.material == Material::Gizmo {
-                    return;
-                }
+        if button != MouseButton::Left {
+            return;
+        }
 
-                let mut gizmo = Sphere::new(SphereDescriptor {
-                    center: sphere.center,
-                    radius: sphere.radius + 0.01,
-                    albedo: Vector3::new(1.0, 0.6, 0.0),
-                    material: Material::Gizmo,
-                });
-                gizmo.label = Some("selected_sphere_gizmo".to_string());
-
-                self.scene.selected_sphere = Some(sphere.uuid);
-                self.scene
-                    .spheres
-                    .retain(|s| s.label != Some("selected_sphere_gizmo".to_string()));
-                self.scene.spheres.push(gizmo);
-                self.renderer.progressive_rendering.reset_ready_samples();
-            } else {
-                self.scene.selected_sphere = None;
-                self.scene
-                    .spheres
-                    .retain(|s| s.label != Some("selected_sphere_gizmo".to_string()));
-                self.renderer.progressive_rendering.reset_ready_samples();
+        if state == ElementState::Released {
+            self.gizmo.end_drag();
+        }
+
+        if self.scatter_brush.enabled {
+            match state {
+                ElementState::Pressed => self
+                    .scatter_brush
+                    .begin_stroke(&mut self.scene, &self.cursor_ray),
+                ElementState::Released => self.scatter_brush.end_stroke(),
+            }
+        } else if self.texture_painter.enabled {
+            match state {
+                ElementState::Pressed => self
+                    .texture_painter
+                    .begin_stroke(&mut self.scene, &self.cursor_ray),
+                ElementState::Released => self.texture_painter.end_stroke(),
+            }
+        } else if state == ElementState::Pressed {
+            // The camera may have moved since the cursor did
+            self.handle_pointer_move(self.cursor_position);
+            if !self.gizmo.begin_drag(&self.scene, &self.cursor_ray) {
+                self.box_select_start = Some(self.cursor_position);
+            }
+        } else if let Some(start) = self.box_select_start.take() {
+            self.select_at(start, self.cursor_position);
+        }
+    }
+
+    /// Selects what was clicked at `end`, or boxed in between `start` and `end` if the cursor
+    /// was dragged. With Ctrl held, clicking toggles an object and boxing adds to the selection.
+    fn select_at(&mut self, start: PhysicalPosition<f64>, end: PhysicalPosition<f64>) {
+        let extend = self.modifiers.ctrl();
+        if (end.x - start.x).hypot(end.y - start.y) >= BOX_SELECT_DISTANCE {
+            self.scene
+                .select_in_rect(start, end, self.window_size, extend);
+            return;
+        }
+
+        // Picked from what the GPU traced, so it's exactly what's shown under the cursor
+        let position = (
+            end.x / self.window_size.width as f64,
+            end.y / self.window_size.height as f64,
+        );
+        let picked = match self.renderer.pick(&self.device, &self.queue, position) {
+            Some(Ok(picked)) => picked,
+            Some(Err(e)) => {
+                eprintln!("Failed to read back the object under the cursor: {}", e);
+                None
+            }
+            // Nothing has been rendered to click on yet
+            None => None,
+        };
+        let uuid = picked.and_then(|picked| match picked {
+            PickedObject::Sphere(index) => self.scene.spheres().get(index).map(|s| s.id.0),
+            PickedObject::Instance(index) => self.scene.instances().get(index).map(|i| i.id.0),
+        });
+        match uuid {
+            Some(uuid) => self.scene.select(uuid, extend),
+            None if extend => {}
+            None => self.scene.clear_selection(),
+        }
+    }
+
+    /// Outlines the selection box being dragged out, once it's far enough from a click.
+    fn draw_selection_box(&self, context: &egui::Context) {
+        let Some(start) = self.box_select_start else {
+            return;
+        };
+        let end = self.cursor_position;
+        if (end.x - start.x).hypot(end.y - start.y) < BOX_SELECT_DISTANCE {
+            return;
+        }
+
+        let pixels_per_point = context.pixels_per_point();
+        let to_pos = |position: PhysicalPosition<f64>| {
+            egui::pos2(
+                position.x as f32 / pixels_per_point,
+                position.y as f32 / pixels_per_point,
+            )
+        };
+        let color = color32(self.ui.palette.highlight());
+        context.layer_painter(egui::LayerId::background()).rect(
+            egui::Rect::from_two_pos(to_pos(start), to_pos(end)),
+            0.0,
+            color.gamma_multiply(0.15),
+            egui::Stroke::new(1.0, color),
+        );
+    }
+
+    /// Moves the selection `step` spheres forwards or backwards, so it can be changed without a mouse.
+    fn cycle_selection(&mut self, step: isize) {
+        let selectable = self
+            .scene
+            .spheres()
+            .iter()
+            .map(|s| s.id.0)
+            .collect::<Vec<_>>();
+        if selectable.is_empty() {
+            return;
+        }
+
+        let next = match selectable
+            .iter()
+            .position(|&uuid| self.scene.selection.last() == Some(&uuid))
+        {
+            Some(i) => (i as isize + step).rem_euclid(selectable.len() as isize) as usize,
+            None if step < 0 => selectable.len() - 1,
+            None => 0,
+        };
+        self.scene.select(selectable[next], false);
+    }
+
+    /// Performs the action bound to `key` with the current modifiers, unless the key is meant
+    /// for the UI.
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        let ui_has_focus = self.command_palette.is_open() || self.ui.wants_keyboard_input();
+        if ui_has_focus && !self.hotkeys.is_capturing() {
+            return;
+        }
+
+        if let Some(action) = self.hotkeys.handle_key(KeyChord::new(key, self.modifiers)) {
+            let allowed = !self.input_locked()
+                || matches!(
+                    action,
+                    Action::TogglePresentationMode
+                        | Action::ToggleFullscreen
+                        | Action::SaveImage
+                        | Action::SaveExr
+                        | Action::Quit
+                );
+            if allowed {
+                self.perform(action);
             }
         }
     }
 
     pub fn input(&mut self, event: &Event<'_, ()>) {
+        if let Event::WindowEvent { event, window_id } = event {
+            if *window_id == self.window.id() {
+                match event {
+                    WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => self.handle_key(*key),
+                    _ => {}
+                }
+            }
+        }
+
         if self.ui.contains_mouse() {
             return;
         }
@@ -344,10 +1879,10 @@ impl App {
                     WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                         self.resize(**new_inner_size);
                     }
-                    WindowEvent::CursorMoved { position, .. } => {
+                    WindowEvent::CursorMoved { position, .. } if !self.input_locked() => {
                         self.handle_pointer_move(*position);
                     }
-                    WindowEvent::MouseInput { button, state, .. } => {
+                    WindowEvent::MouseInput { button, state, .. } if !self.input_locked() => {
                         self.handle_pointer_input(*button, *state);
                     }
                     _ => {}
@@ -358,7 +1893,7 @@ impl App {
     }
 
     pub fn run(mut self, event_loop: EventLoop<()>) {
-        event_loop.run(move |event, _, control_flow| {
+        event_loop.run(move |event, target, control_flow| {
             self.ui_input(&event);
 
             self.input(&event);
@@ -384,25 +1919,67 @@ impl App {
                     self.window().request_redraw();
                 }
 
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    window_id,
+                } if window_id == self.window().id() => self.confirm_discarding(Discarding::Quit),
                 Event::WindowEvent {
                     ref event,
                     window_id,
-                } if window_id == self.window().id() => match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    _ => {}
-                },
+                } if Some(window_id) == self.output_window.as_ref().map(|w| w.window().id()) => {
+                    self.handle_output_window_event(event)
+                }
                 _ => {}
             }
+
+            self.sync_output_window(target);
+
+            if self.should_quit {
+                *control_flow = ControlFlow::Exit;
+            }
         });
     }
 }
 
+fn display_mode_label(video_mode: &VideoMode) -> String {
+    let size = video_mode.size();
+    format!(
+        "{}x{} @ {:.2} Hz",
+        size.width,
+        size.height,
+        video_mode.refresh_rate_millihertz() as f32 / 1000.0
+    )
+}
+
+/// Clears the main window's background when the render is shown in the output window instead.
+fn clear(encoder: &mut wgpu::CommandEncoder, output: &wgpu::SurfaceTexture) {
+    let view = output
+        .texture
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Clear Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
+                }),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+}
+
+fn log_repairs(scene: &str, repairs: &[Repair]) {
+    for repair in repairs {
+        log::warn!("Repaired \"{}\": {}", scene, repair);
+    }
+}
@@ -0,0 +1,105 @@
+//! Finds the `shaders` and `assets` directories wherever the app is installed, so it works
+//! without being started from the repository. The directories containing them are searched in
+//! order:
+//!
+//! 1. the one named by the `PATHTRACER_DATA_DIR` environment variable
+//! 2. the executable's, and `../Resources` from it in a macOS app bundle
+//! 3. `$XDG_DATA_HOME/pathtracer` and `pathtracer` in each of `$XDG_DATA_DIRS` on Linux
+//! 4. the working directory
+//!
+//! User settings are kept apart from these, in [`config_dir`], and caches in [`cache_dir`].
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// The environment variable overriding where the data directories are looked for.
+pub const DATA_DIR_VARIABLE: &str = "PATHTRACER_DATA_DIR";
+
+/// The name of the directory under the XDG data and config directories.
+const XDG_APP_NAME: &str = "pathtracer";
+
+/// Returns where `relative`, e.g. `shaders` or `assets/hdri/room.hdr`, is found under the first
+/// directory searched that has it. If none do, it's resolved against the working directory, so
+/// errors about it still name a sensible path.
+pub fn resolve(relative: impl AsRef<Path>) -> PathBuf {
+    let relative = relative.as_ref();
+    search_roots()
+        .iter()
+        .map(|root| root.join(relative))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| relative.to_owned())
+}
+
+/// Where user settings such as quality presets are kept: `$XDG_CONFIG_HOME/pathtracer`, or
+/// `~/.config/pathtracer`, except for `%APPDATA%\pathtracer` on Windows. `None` if there's no
+/// home directory to put it in.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    base.map(|directory| directory.join(XDG_APP_NAME))
+}
+
+/// Where downloads and generated files that can be made again are kept:
+/// `$XDG_CACHE_HOME/pathtracer`, or `~/.cache/pathtracer`, except for
+/// `%LOCALAPPDATA%\pathtracer` on Windows. Falls back to `cache` in the working directory if
+/// there's no home directory to put it in.
+pub fn cache_dir() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+    base.map_or_else(
+        || PathBuf::from("cache"),
+        |directory| directory.join(XDG_APP_NAME),
+    )
+}
+
+/// The directories searched by [`resolve`], in order, found once since they can't change while
+/// running.
+fn search_roots() -> &'static [PathBuf] {
+    static ROOTS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+    ROOTS.get_or_init(|| {
+        let mut roots = Vec::new();
+        if let Some(directory) = env::var_os(DATA_DIR_VARIABLE) {
+            roots.push(PathBuf::from(directory));
+        }
+
+        if let Some(directory) = env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_owned))
+        {
+            if cfg!(target_os = "macos") {
+                roots.push(directory.join("../Resources"));
+            }
+            roots.push(directory);
+        }
+
+        if cfg!(target_os = "linux") {
+            let data_home = env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+            roots.extend(data_home.map(|directory| directory.join(XDG_APP_NAME)));
+
+            let data_dirs = env::var_os("XDG_DATA_DIRS")
+                .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+            roots
+                .extend(env::split_paths(&data_dirs).map(|directory| directory.join(XDG_APP_NAME)));
+        }
+
+        if let Ok(directory) = env::current_dir() {
+            roots.push(directory);
+        }
+        roots
+    })
+}
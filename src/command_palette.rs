@@ -0,0 +1,126 @@
+use crate::{app::Action, hotkeys::Hotkeys};
+
+/// A searchable list of every editor action, opened with Ctrl+P by default.
+#[derive(Default)]
+pub struct CommandPalette {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    /// Shows the palette if it is open and returns the action picked with Enter or a click.
+    pub fn render_ui(
+        &mut self,
+        context: &egui::Context,
+        actions: &[Action],
+        hotkeys: &Hotkeys,
+    ) -> Option<Action> {
+        if !self.open {
+            return None;
+        }
+
+        let mut matches = actions
+            .iter()
+            .filter_map(|&action| Some((fuzzy_score(&self.query, action.name())?, action)))
+            .collect::<Vec<_>>();
+        // Stable, so equally good matches keep the registry's order
+        matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+        let (up, down, enter, escape) = context.input(|i| {
+            (
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+                i.key_pressed(egui::Key::Enter),
+                i.key_pressed(egui::Key::Escape),
+            )
+        });
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(matches.len().saturating_sub(1));
+
+        let mut picked = None;
+        egui::Window::new("Command palette")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 64.0])
+            .show(context, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Type a command")
+                        .desired_width(320.0),
+                );
+                response.request_focus();
+                if response.changed() {
+                    self.selected = 0;
+                }
+
+                ui.separator();
+                if matches.is_empty() {
+                    ui.label("No matching commands");
+                }
+                for (i, (_, action)) in matches.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .selectable_label(i == self.selected, action.name())
+                            .clicked()
+                        {
+                            picked = Some(*action);
+                        }
+                        if let Some(chord) = hotkeys.chord(*action) {
+                            ui.weak(chord.to_string());
+                        }
+                    });
+                }
+            });
+
+        if enter {
+            picked = picked.or(matches.get(self.selected).map(|(_, action)| *action));
+        }
+        if picked.is_some() || escape {
+            self.open = false;
+        }
+
+        picked
+    }
+}
+
+/// Scores how well `query` matches `text` as a case-insensitive subsequence, favoring matches
+/// at the start of words and runs of consecutive characters. `None` if it doesn't match at all.
+fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match = None;
+
+    for query_char in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|&c| c == query_char)?;
+
+        score += 1;
+        if found == 0 || text[found - 1] == ' ' {
+            score += 3;
+        }
+        if previous_match.is_some_and(|previous| previous + 1 == found) {
+            score += 2;
+        }
+
+        previous_match = Some(found);
+        position = found + 1;
+    }
+
+    Some(score)
+}
@@ -0,0 +1,144 @@
+//! Scenes whose renders are known in advance, for checking that changes to how lights and
+//! materials are sampled still converge to the right image.
+
+use crate::{app::Action, renderer::RenderedImage, scene::FURNACE_RADIANCE};
+
+/// How far any pixel of the white furnace may be from [`FURNACE_RADIANCE`], relative to it, for
+/// the check to pass.
+const FURNACE_TOLERANCE: f32 = 0.01;
+
+/// How a white furnace render compared to the radiance it should converge to.
+#[derive(Debug, Clone, Copy)]
+struct FurnaceResult {
+    samples: u32,
+    /// The average of every pixel, relative to [`FURNACE_RADIANCE`].
+    mean: f32,
+    /// How far the pixel furthest from [`FURNACE_RADIANCE`] is from it, relative to it.
+    worst_error: f32,
+}
+
+impl FurnaceResult {
+    /// Compares the channels of every pixel of `image`, averaged over `samples`, to
+    /// [`FURNACE_RADIANCE`].
+    fn new(image: &RenderedImage, samples: u32) -> Self {
+        let values = image
+            .pixels
+            .iter()
+            .flat_map(|pixel| &pixel[..3])
+            .map(|&value| value / FURNACE_RADIANCE);
+        let (sum, count, worst_error) =
+            values.fold((0.0, 0, 0.0f32), |(sum, count, worst), value| {
+                // NaNs are as wrong as a pixel gets, and `max` would skip them
+                let error = match (value - 1.0).abs() {
+                    error if error.is_nan() => f32::INFINITY,
+                    error => error,
+                };
+                (sum + value as f64, count + 1, worst.max(error))
+            });
+        Self {
+            samples,
+            mean: (sum / count.max(1) as f64) as f32,
+            worst_error,
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.worst_error <= FURNACE_TOLERANCE
+    }
+}
+
+/// Loads the diagnostic scenes and checks the white furnace's render once it converges.
+pub struct Diagnostics {
+    /// Whether the white furnace was loaded to be checked once it converges.
+    checking: bool,
+    /// The outcome of the last check, shown until the next one.
+    result: Option<FurnaceResult>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self {
+            checking: false,
+            result: None,
+        }
+    }
+
+    /// Whether the render should be checked by [`Diagnostics::finish_check`] once it converges.
+    pub fn is_checking(&self) -> bool {
+        self.checking
+    }
+
+    /// Waits for the white furnace, which should just have been loaded, to converge.
+    pub fn start_check(&mut self) {
+        self.checking = true;
+        self.result = None;
+    }
+
+    /// Judges the converged render of the white furnace, logging the outcome.
+    pub fn finish_check(&mut self, image: &RenderedImage, samples: u32) {
+        self.checking = false;
+        let result = FurnaceResult::new(image, samples);
+        if result.passed() {
+            log::info!("White furnace check passed: {:?}", result);
+        } else {
+            log::warn!("White furnace check failed: {:?}", result);
+        }
+        self.result = Some(result);
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) -> Option<Action> {
+        let mut action = None;
+        ui.collapsing("Diagnostics", |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Veach MIS")
+                    .on_hover_text(
+                        "Replace the scene with plates reflecting lights from tiny and bright to \
+                        large and dim, which light sampling and BSDF sampling each only handle \
+                        some of",
+                    )
+                    .clicked()
+                {
+                    action = Some(Action::LoadVeachMisScene);
+                }
+                if ui
+                    .button("White furnace")
+                    .on_hover_text(
+                        "Replace the scene with spheres that absorb no light in an evenly lit \
+                        environment, which they should vanish into",
+                    )
+                    .clicked()
+                {
+                    action = Some(Action::LoadWhiteFurnaceScene);
+                }
+            });
+
+            let check = ui
+                .add_enabled(!self.checking, egui::Button::new("Run furnace check"))
+                .on_hover_text(
+                    "Load the white furnace and check that every pixel converges to the \
+                    environment's radiance",
+                );
+            if check.clicked() {
+                action = Some(Action::RunFurnaceCheck);
+            }
+
+            if self.checking {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Waiting for the render to converge");
+                });
+            } else if let Some(result) = self.result {
+                let verdict = if result.passed() { "Passed" } else { "Failed" };
+                ui.label(format!(
+                    "{} after {} samples: averaging {:.4}, worst pixel {:.2}% off",
+                    verdict,
+                    result.samples,
+                    result.mean,
+                    result.worst_error * 100.0
+                ));
+            }
+        });
+        action
+    }
+}
@@ -0,0 +1,360 @@
+use std::{fs, io, path::Path};
+
+use wgpu::{util::DeviceExt, BindGroupLayout, Device, Queue};
+
+/// The longest shaper the copy shader can sample, the 1D texture size every adapter supports.
+pub const MAX_SHAPER_SIZE: usize = 8192;
+/// The most entries along each side of a 3D table.
+pub const MAX_TABLE_SIZE: usize = 256;
+
+/// A display transform baked to a `.cube` LUT, such as an OpenColorIO display and view baked
+/// with `ociobakelut --format resolve_cube`, mapping scene-linear radiance to display-encoded
+/// sRGB. Read in both the Adobe layout, with DOMAIN_MIN and DOMAIN_MAX, and Resolve's, with a
+/// 1D shaper ahead of the 3D table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayLut {
+    /// From the file's TITLE, or empty if it has none.
+    pub title: String,
+    /// Applied to each channel first, to spread scene-linear values over the table.
+    shaper: Option<Shaper>,
+    table: Table,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Shaper {
+    /// The input mapped to the first and last entries, for each channel.
+    domain: [[f32; 3]; 2],
+    entries: Vec<[f32; 3]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Table {
+    /// The input mapped to the first and last entries along each axis.
+    domain: [[f32; 3]; 2],
+    /// The entries along each side.
+    size: usize,
+    /// Red changing fastest, then green, then blue.
+    entries: Vec<[f32; 3]>,
+}
+
+impl DisplayLut {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Reads the source of a `.cube` file. A 1D LUT on its own is applied as a shaper, with a
+    /// table that leaves what it gives as it is.
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let mut title = String::new();
+        let (mut shaper_size, mut table_size) = (None, None);
+        let mut domain = [[0.0; 3], [1.0; 3]];
+        let (mut shaper_range, mut table_range) = (None, None);
+        let mut rows = Vec::new();
+        for (number, line) in source.lines().enumerate() {
+            let invalid = |message: &str| invalid_data(format!("line {}: {}", number + 1, message));
+            let line = line.trim();
+            let mut words = line.split_whitespace();
+            let Some(keyword) = words.next().filter(|keyword| !keyword.starts_with('#')) else {
+                continue;
+            };
+            let numbers = words
+                .clone()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>();
+            let numbers = |count: usize| match numbers.as_deref() {
+                Ok(numbers) if numbers.len() == count && numbers.iter().all(|n| n.is_finite()) => {
+                    Ok(numbers.to_vec())
+                }
+                _ => Err(invalid(&format!("expected {} numbers", count))),
+            };
+            let size = |max: usize| match words.clone().next().map(str::parse::<usize>) {
+                Some(Ok(size)) if (2..=max).contains(&size) => Ok(size),
+                _ => Err(invalid(&format!("expected a size from 2 to {}", max))),
+            };
+            let range = || numbers(2).map(|range| [[range[0]; 3], [range[1]; 3]]);
+
+            match keyword {
+                "TITLE" => title = line["TITLE".len()..].trim().trim_matches('"').to_string(),
+                "LUT_1D_SIZE" => shaper_size = Some(size(MAX_SHAPER_SIZE)?),
+                "LUT_3D_SIZE" => table_size = Some(size(MAX_TABLE_SIZE)?),
+                "DOMAIN_MIN" => domain[0] = triple(&numbers(3)?),
+                "DOMAIN_MAX" => domain[1] = triple(&numbers(3)?),
+                "LUT_1D_INPUT_RANGE" => shaper_range = Some(range()?),
+                "LUT_3D_INPUT_RANGE" => table_range = Some(range()?),
+                _ if keyword.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) => {
+                    let row = line
+                        .split_whitespace()
+                        .map(str::parse::<f32>)
+                        .collect::<Result<Vec<_>, _>>();
+                    match row.as_deref() {
+                        Ok(row) if row.len() == 3 && row.iter().all(|n| n.is_finite()) => {
+                            rows.push(triple(row))
+                        }
+                        _ => return Err(invalid("expected a row of three numbers")),
+                    }
+                }
+                _ => return Err(invalid(&format!("unknown keyword \"{}\"", keyword))),
+            }
+        }
+
+        let expected = shaper_size.unwrap_or(0) + table_size.map_or(0, |size| size.pow(3));
+        if shaper_size.is_none() && table_size.is_none() {
+            return Err(invalid_data("no LUT_1D_SIZE or LUT_3D_SIZE".to_string()));
+        }
+        if rows.len() != expected {
+            return Err(invalid_data(format!(
+                "expected {} rows, found {}",
+                expected,
+                rows.len()
+            )));
+        }
+        for [min, max] in [
+            domain,
+            shaper_range.unwrap_or(domain),
+            table_range.unwrap_or(domain),
+        ] {
+            if (0..3).any(|i| min[i] >= max[i]) {
+                return Err(invalid_data(
+                    "a domain's minimum isn't below its maximum".into(),
+                ));
+            }
+        }
+
+        let table_rows = rows.split_off(shaper_size.unwrap_or(0));
+        let shaper = shaper_size.map(|_| Shaper {
+            domain: shaper_range.unwrap_or(domain),
+            entries: rows,
+        });
+        let table = match table_size {
+            Some(size) => Table {
+                // Past a shaper, the table's input is what the shaper gives
+                domain: table_range.unwrap_or(if shaper.is_some() {
+                    [[0.0; 3], [1.0; 3]]
+                } else {
+                    domain
+                }),
+                size,
+                entries: table_rows,
+            },
+            None => Table::identity(),
+        };
+        Ok(Self {
+            title,
+            shaper,
+            table,
+        })
+    }
+
+    /// The display-encoded color for scene-linear `color`, as the copy shader samples it.
+    pub fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        let shaped = match &self.shaper {
+            Some(shaper) => shaper.apply(color),
+            None => color,
+        };
+        self.table.apply(shaped)
+    }
+}
+
+impl Shaper {
+    fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        std::array::from_fn(|channel| {
+            let (position, fraction) =
+                lookup(color[channel], self.domain, channel, self.entries.len());
+            let [a, b] = [position, position + 1].map(|i| self.entries[i][channel]);
+            a + (b - a) * fraction
+        })
+    }
+}
+
+impl Table {
+    /// Two entries along each side, giving back whatever's between zero and one.
+    fn identity() -> Self {
+        let entries = (0..8)
+            .map(|i| [i & 1, (i >> 1) & 1, i >> 2].map(|bit| bit as f32))
+            .collect();
+        Self {
+            domain: [[0.0; 3], [1.0; 3]],
+            size: 2,
+            entries,
+        }
+    }
+
+    /// Interpolates trilinearly between the eight entries around `color`.
+    fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        let [(r, fr), (g, fg), (b, fb)] =
+            std::array::from_fn(|axis| lookup(color[axis], self.domain, axis, self.size));
+        let entry = |dr: usize, dg: usize, db: usize| {
+            self.entries[(r + dr) + (g + dg) * self.size + (b + db) * self.size * self.size]
+        };
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] {
+            std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+        };
+        let face = |db: usize| {
+            lerp(
+                lerp(entry(0, 0, db), entry(1, 0, db), fr),
+                lerp(entry(0, 1, db), entry(1, 1, db), fr),
+                fg,
+            )
+        };
+        lerp(face(0), face(1), fb)
+    }
+}
+
+/// The entry at or below `value` along `axis` of a LUT with `size` entries over `domain`, and
+/// how far it is towards the next one. Values outside the domain are clamped to it.
+fn lookup(value: f32, domain: [[f32; 3]; 2], axis: usize, size: usize) -> (usize, f32) {
+    let [min, max] = domain.map(|bound| bound[axis]);
+    let position = ((value - min) / (max - min)).clamp(0.0, 1.0) * (size - 1) as f32;
+    // NaNs land on the first entry
+    let position = if position.is_nan() { 0.0 } else { position };
+    let index = (position as usize).min(size - 2);
+    (index, position - index as f32)
+}
+
+fn triple(numbers: &[f32]) -> [f32; 3] {
+    [numbers[0], numbers[1], numbers[2]]
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The domains of the LUT bound to the copy shader. Matches `LutDomains` in the copy shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LutDomains {
+    shaper_min: [f32; 4],
+    shaper_max: [f32; 4],
+    table_min: [f32; 4],
+    table_max: [f32; 4],
+    /// Non-zero when the LUT has a shaper.
+    has_shaper: u32,
+    /// Non-zero when there's a LUT at all, rather than the placeholder bound without one.
+    loaded: u32,
+    _padding: [u32; 2],
+}
+
+/// The layout of the group the copy shader reads the display LUT from: its table, its shaper
+/// and their domains.
+pub fn bind_group_layout(device: &Device) -> BindGroupLayout {
+    let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Display LUT Bind Group Layout"),
+        entries: &[
+            texture(0, wgpu::TextureViewDimension::D3),
+            texture(1, wgpu::TextureViewDimension::D1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// Uploads `lut` for the copy shader, or a placeholder it ignores if there's none.
+pub fn create_bind_group(
+    device: &Device,
+    queue: &Queue,
+    layout: &BindGroupLayout,
+    lut: Option<&DisplayLut>,
+) -> wgpu::BindGroup {
+    let identity = Table::identity();
+    let table = lut.map_or(&identity, |lut| &lut.table);
+    let shaper = lut.and_then(|lut| lut.shaper.as_ref());
+    // Texture rows need no padding when written at creation
+    let texels = |entries: &[[f32; 3]]| -> Vec<[f32; 4]> {
+        entries.iter().map(|&[r, g, b]| [r, g, b, 1.0]).collect()
+    };
+    let texture = |label: &str, size: wgpu::Extent3d, dimension, entries: &[[f32; 3]]| {
+        device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            bytemuck::cast_slice(&texels(entries)),
+        )
+    };
+
+    let side = table.size as u32;
+    let table_texture = texture(
+        "Display LUT Table",
+        wgpu::Extent3d {
+            width: side,
+            height: side,
+            depth_or_array_layers: side,
+        },
+        wgpu::TextureDimension::D3,
+        &table.entries,
+    );
+    let shaper_entries = shaper.map_or(&[[0.0; 3]; 2][..], |shaper| &shaper.entries);
+    let shaper_texture = texture(
+        "Display LUT Shaper",
+        wgpu::Extent3d {
+            width: shaper_entries.len() as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        wgpu::TextureDimension::D1,
+        shaper_entries,
+    );
+
+    let vec4 = |v: [f32; 3]| [v[0], v[1], v[2], 0.0];
+    let shaper_domain = shaper.map_or([[0.0; 3], [1.0; 3]], |shaper| shaper.domain);
+    let domains = LutDomains {
+        shaper_min: vec4(shaper_domain[0]),
+        shaper_max: vec4(shaper_domain[1]),
+        table_min: vec4(table.domain[0]),
+        table_max: vec4(table.domain[1]),
+        has_shaper: shaper.is_some().into(),
+        loaded: lut.is_some().into(),
+        _padding: [0; 2],
+    };
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Display LUT Domains"),
+        contents: bytemuck::bytes_of(&domains),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let view =
+        |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Display LUT Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view(&table_texture)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&view(&shaper_texture)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+
+use crate::thumbnails::{self, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+
+/// The HDRIs available as environments, shown as a gallery of thumbnails.
+pub struct EnvironmentLibrary {
+    environments: Vec<Environment>,
+    selected: Option<usize>,
+}
+
+struct Environment {
+    path: PathBuf,
+    name: String,
+    thumbnail: Option<egui::TextureHandle>,
+    thumbnail_loaded: bool,
+}
+
+impl EnvironmentLibrary {
+    /// Lists the `.hdr` files in `directory`, marking `selected` as the one in use.
+    pub fn new(directory: &Path, selected: &Path) -> Self {
+        let mut paths = std::fs::read_dir(directory)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "hdr"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        let environments = paths
+            .into_iter()
+            .map(|path| Environment {
+                name: path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .replace('_', " "),
+                path,
+                thumbnail: None,
+                thumbnail_loaded: false,
+            })
+            .collect::<Vec<_>>();
+        let selected = environments.iter().position(|e| e.path == selected);
+
+        Self {
+            environments,
+            selected,
+        }
+    }
+
+    /// Marks none as the one in use, for when the environment is replaced by something else.
+    pub fn clear_selection(&mut self) {
+        self.selected = None;
+    }
+
+    /// Returns the path of the newly selected environment, if the selection changed.
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) -> Option<PathBuf> {
+        let mut newly_selected = None;
+
+        if self.environments.is_empty() {
+            ui.label("No HDRIs found");
+        }
+
+        for (i, environment) in self.environments.iter_mut().enumerate() {
+            let thumbnail = environment.thumbnail(ui.ctx()).map(|texture| texture.id());
+            let is_selected = self.selected == Some(i);
+
+            let response = ui
+                .vertical(|ui| {
+                    let size = egui::Vec2::new(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32);
+                    let image = match thumbnail {
+                        Some(texture) => ui.add(
+                            egui::ImageButton::new(egui::load::SizedTexture::new(texture, size))
+                                .selected(is_selected),
+                        ),
+                        None => ui.add_sized(size, egui::SelectableLabel::new(is_selected, "?")),
+                    };
+                    ui.label(&environment.name);
+                    image
+                })
+                .inner;
+
+            if response.clicked() && !is_selected {
+                self.selected = Some(i);
+                newly_selected = Some(environment.path.clone());
+            }
+        }
+
+        newly_selected
+    }
+}
+
+impl Environment {
+    /// Loads the thumbnail the first time it is shown.
+    fn thumbnail(&mut self, context: &egui::Context) -> Option<&egui::TextureHandle> {
+        if !self.thumbnail_loaded {
+            self.thumbnail_loaded = true;
+            match thumbnails::cached(&self.path, thumbnails::hdri) {
+                Ok(image) => {
+                    self.thumbnail = Some(thumbnails::texture(
+                        context,
+                        &self.path.to_string_lossy(),
+                        &image,
+                    ));
+                }
+                Err(e) => log::warn!(
+                    "Failed to load thumbnail for {}: {}",
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+
+        self.thumbnail.as_ref()
+    }
+}
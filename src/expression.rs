@@ -0,0 +1,726 @@
+//! Small expressions for material parameters, such as `0.5 + 0.5 * sin(8 * y + t)`, compiled
+//! to a bytecode the compute shader interprets wherever a ray hits the material.
+//!
+//! An expression is either one value, for a gray, or three separated by commas, for a color.
+//! Each is made of numbers, `+ - * /`, parentheses and:
+//!
+//! ```text
+//! x y z       the point hit, relative to the object's center
+//! nx ny nz    the outward normal there
+//! u v         the texture coordinates there, around and up the sphere from 0 to 1
+//! t           the animation time in seconds
+//! pi
+//! sin cos abs floor fract sqrt min max pow step mix clamp smoothstep noise
+//! canvas_r canvas_g canvas_b
+//! ```
+//!
+//! The functions behave as the WGSL built-ins of the same names, `noise(x, y, z)` is value
+//! noise between 0 and 1 that varies over about a unit and `canvas_r(canvas, u, v)` is the red
+//! painted at `u` and `v` on the canvas of a mesh, counting the meshes with canvases from zero.
+//!
+//! The values can be preceded by locals, such as `a = noise(x, y, z); a, a * a, 1`, which are
+//! computed once however often they're used.
+
+use std::{f32::consts::PI, fmt};
+
+use cgmath::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::scene::Canvas;
+
+/// The most instructions an expression compiles to, not counting the one that ends it.
+pub const MAX_INSTRUCTIONS: usize = 64;
+/// The most values an expression keeps on the stack at once. Matches `EXPRESSION_STACK_SIZE`
+/// in the compute shader.
+pub const MAX_STACK_DEPTH: usize = 8;
+/// The most locals an expression can name. Matches `EXPRESSION_LOCALS` in the compute shader.
+pub const MAX_LOCALS: usize = 8;
+
+/// The operations of the bytecode, grouped by how many values they take off the stack so the
+/// shader can tell them apart with comparisons, but for [`Op::Store`], which pushes nothing.
+/// Matches the `OP_` constants in the compute shader.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    End,
+    Constant,
+    X,
+    Y,
+    Z,
+    NormalX,
+    NormalY,
+    NormalZ,
+    Time,
+    U,
+    V,
+    /// Pushes the local numbered by the instruction's value.
+    Load,
+    Negate,
+    Sin,
+    Cos,
+    Abs,
+    Floor,
+    Fract,
+    Sqrt,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Pow,
+    Step,
+    Mix,
+    Clamp,
+    Smoothstep,
+    Noise,
+    CanvasRed,
+    CanvasGreen,
+    CanvasBlue,
+    /// Pops a value into the local numbered by the instruction's value.
+    Store,
+}
+
+const VARIABLES: [(&str, Op); 9] = [
+    ("x", Op::X),
+    ("y", Op::Y),
+    ("z", Op::Z),
+    ("nx", Op::NormalX),
+    ("ny", Op::NormalY),
+    ("nz", Op::NormalZ),
+    ("u", Op::U),
+    ("v", Op::V),
+    ("t", Op::Time),
+];
+
+const FUNCTIONS: [(&str, Op, usize); 17] = [
+    ("sin", Op::Sin, 1),
+    ("cos", Op::Cos, 1),
+    ("abs", Op::Abs, 1),
+    ("floor", Op::Floor, 1),
+    ("fract", Op::Fract, 1),
+    ("sqrt", Op::Sqrt, 1),
+    ("min", Op::Min, 2),
+    ("max", Op::Max, 2),
+    ("pow", Op::Pow, 2),
+    ("step", Op::Step, 2),
+    ("mix", Op::Mix, 3),
+    ("clamp", Op::Clamp, 3),
+    ("smoothstep", Op::Smoothstep, 3),
+    ("noise", Op::Noise, 3),
+    ("canvas_r", Op::CanvasRed, 3),
+    ("canvas_g", Op::CanvasGreen, 3),
+    ("canvas_b", Op::CanvasBlue, 3),
+];
+
+/// One step of a compiled expression, as the compute shader reads it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Instruction {
+    op: u32,
+    /// The number pushed by a constant, or the local loaded or stored.
+    value: f32,
+}
+
+impl Instruction {
+    const END: Self = Self::new(Op::End);
+
+    const fn new(op: Op) -> Self {
+        Self::with_value(op, 0.0)
+    }
+
+    const fn with_value(op: Op, value: f32) -> Self {
+        Self {
+            op: op as u32,
+            value,
+        }
+    }
+}
+
+/// Why an expression couldn't be compiled. Positions are byte offsets into the source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionError {
+    UnexpectedCharacter {
+        position: usize,
+        character: char,
+    },
+    /// A token where it doesn't fit, or `None` for the end of the source.
+    UnexpectedToken {
+        position: usize,
+        token: Option<String>,
+    },
+    UnknownName {
+        position: usize,
+        name: String,
+    },
+    /// A local named after a variable, a function or another local.
+    NameTaken {
+        position: usize,
+        name: String,
+    },
+    WrongArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Neither one value nor three.
+    WrongValueCount(usize),
+    TooLong,
+    TooDeep,
+    TooManyLocals,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedCharacter {
+                position,
+                character,
+            } => write!(f, "{}: unexpected character '{}'", position, character),
+            Self::UnexpectedToken {
+                position,
+                token: Some(token),
+            } => write!(f, "{}: unexpected \"{}\"", position, token),
+            Self::UnexpectedToken {
+                position,
+                token: None,
+            } => write!(f, "{}: unexpected end of the expression", position),
+            Self::UnknownName { position, name } => {
+                write!(f, "{}: unknown name \"{}\"", position, name)
+            }
+            Self::NameTaken { position, name } => {
+                write!(f, "{}: \"{}\" is already taken", position, name)
+            }
+            Self::WrongArgumentCount {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} arguments, not {}",
+                function, expected, found
+            ),
+            Self::WrongValueCount(count) => write!(
+                f,
+                "expected one value or three separated by commas, not {}",
+                count
+            ),
+            Self::TooLong => write!(
+                f,
+                "longer than {} operations, the most the shader runs",
+                MAX_INSTRUCTIONS
+            ),
+            Self::TooDeep => write!(
+                f,
+                "nested too deeply, needing more than {} values at once",
+                MAX_STACK_DEPTH
+            ),
+            Self::TooManyLocals => write!(f, "more than {} locals", MAX_LOCALS),
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+/// What an expression can refer to where it's evaluated.
+#[derive(Clone, Copy)]
+pub struct Inputs<'a> {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub time: f32,
+    /// The canvases of the meshes that have one, in order. Missing ones read as white.
+    pub canvases: &'a [&'a Canvas],
+}
+
+/// An expression as typed, along with its bytecode or why it has none. Saved as its source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Expression {
+    source: String,
+    code: Result<Vec<Instruction>, ExpressionError>,
+}
+
+impl Expression {
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let code = Compiler::new(&source).and_then(Compiler::compile);
+        Self { source, code }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn error(&self) -> Option<&ExpressionError> {
+        self.code.as_ref().err()
+    }
+
+    /// The bytecode for the shader, ending with the instruction that stops it. `None` if the
+    /// expression didn't compile.
+    pub fn code(&self) -> Option<&[Instruction]> {
+        self.code.as_deref().ok()
+    }
+
+    /// Runs the bytecode the way the shader does, giving a color with no negative channels.
+    pub fn evaluate(&self, inputs: &Inputs) -> Option<Vector3<f32>> {
+        let mut stack = Vec::with_capacity(MAX_STACK_DEPTH);
+        let mut locals = [0.0; MAX_LOCALS];
+        let texture_coordinates = texture_coordinates(inputs.normal);
+        let canvas = |layer: f32, u: f32, v: f32| {
+            let canvas = inputs.canvases.get(layer as usize);
+            canvas.map_or(Vector3::new(1.0, 1.0, 1.0), |canvas| {
+                canvas.albedo(Vector2::new(u, v))
+            })
+        };
+        for instruction in self.code()? {
+            let op = OPS[instruction.op as usize];
+            if op == Op::End {
+                break;
+            }
+            if op == Op::Store {
+                locals[instruction.value as usize] = stack.pop()?;
+                continue;
+            }
+            // Ops are grouped by how many arguments they take, as the shader relies on
+            let arguments = match op as u32 {
+                op if op <= Op::Load as u32 => 0,
+                op if op <= Op::Sqrt as u32 => 1,
+                op if op <= Op::Step as u32 => 2,
+                _ => 3,
+            };
+            let taken = stack.split_off(stack.len() - arguments);
+            let [a, b, c] = std::array::from_fn(|i| taken.get(i).copied().unwrap_or(0.0));
+            stack.push(match op {
+                Op::End | Op::Store => unreachable!(),
+                Op::Constant => instruction.value,
+                Op::X => inputs.position.x,
+                Op::Y => inputs.position.y,
+                Op::Z => inputs.position.z,
+                Op::NormalX => inputs.normal.x,
+                Op::NormalY => inputs.normal.y,
+                Op::NormalZ => inputs.normal.z,
+                Op::Time => inputs.time,
+                Op::U => texture_coordinates.x,
+                Op::V => texture_coordinates.y,
+                Op::Load => locals[instruction.value as usize],
+                Op::Negate => -a,
+                Op::Sin => a.sin(),
+                Op::Cos => a.cos(),
+                Op::Abs => a.abs(),
+                Op::Floor => a.floor(),
+                Op::Fract => a - a.floor(),
+                Op::Sqrt => a.sqrt(),
+                Op::Add => a + b,
+                Op::Subtract => a - b,
+                Op::Multiply => a * b,
+                Op::Divide => a / b,
+                Op::Min => a.min(b),
+                Op::Max => a.max(b),
+                Op::Pow => a.powf(b),
+                Op::Step => {
+                    if a <= b {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                Op::Mix => a + (b - a) * c,
+                Op::Clamp => a.max(b).min(c),
+                Op::Smoothstep => {
+                    let t = ((c - a) / (b - a)).clamp(0.0, 1.0);
+                    t * t * (3.0 - 2.0 * t)
+                }
+                Op::Noise => value_noise(Vector3::new(a, b, c)),
+                Op::CanvasRed => canvas(a, b, c).x,
+                Op::CanvasGreen => canvas(a, b, c).y,
+                Op::CanvasBlue => canvas(a, b, c).z,
+            });
+        }
+
+        let color = match stack[..] {
+            [gray] => Vector3::new(gray, gray, gray),
+            [r, g, b] => Vector3::new(r, g, b),
+            _ => return None,
+        };
+        Some(color.map(|channel| channel.max(0.0)))
+    }
+}
+
+/// The U around the sphere with `normal` from the negative X axis, and the V up it from the
+/// bottom. Matches `sphereTextureCoordinates` in the compute shader.
+fn texture_coordinates(normal: Vector3<f32>) -> Vector2<f32> {
+    Vector2::new(
+        0.5 + normal.z.atan2(normal.x) / (2.0 * PI),
+        0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI,
+    )
+}
+
+/// Trilinearly interpolates a random value between 0 and 1 at each corner of the unit cube
+/// around `point`, eased so its slope is continuous. Matches `valueNoise` in the compute shader.
+fn value_noise(point: Vector3<f32>) -> f32 {
+    let cell = point.map(f32::floor);
+    let eased = (point - cell).map(|f| f * f * (3.0 - 2.0 * f));
+    let corner = |dx: u32, dy: u32, dz: u32| {
+        let [x, y, z]: [u32; 3] = cell.map(|c| c as i32 as u32).into();
+        lattice_hash(x.wrapping_add(dx), y.wrapping_add(dy), z.wrapping_add(dz))
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let face = |dz: u32| {
+        lerp(
+            lerp(corner(0, 0, dz), corner(1, 0, dz), eased.x),
+            lerp(corner(0, 1, dz), corner(1, 1, dz), eased.x),
+            eased.y,
+        )
+    };
+    lerp(face(0), face(1), eased.z)
+}
+
+/// A random value between 0 and 1 for a corner of the noise lattice. Matches `latticeHash` in
+/// the compute shader.
+fn lattice_hash(x: u32, y: u32, z: u32) -> f32 {
+    let mut hash =
+        x.wrapping_mul(0x8da6b343) ^ y.wrapping_mul(0xd8163841) ^ z.wrapping_mul(0xcb1ab31f);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7feb352d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x846ca68b);
+    hash ^= hash >> 16;
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
+impl From<String> for Expression {
+    fn from(source: String) -> Self {
+        Self::new(source)
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> Self {
+        expression.source
+    }
+}
+
+/// Every [`Op`], indexed by its value.
+const OPS: [Op; 35] = [
+    Op::End,
+    Op::Constant,
+    Op::X,
+    Op::Y,
+    Op::Z,
+    Op::NormalX,
+    Op::NormalY,
+    Op::NormalZ,
+    Op::Time,
+    Op::U,
+    Op::V,
+    Op::Load,
+    Op::Negate,
+    Op::Sin,
+    Op::Cos,
+    Op::Abs,
+    Op::Floor,
+    Op::Fract,
+    Op::Sqrt,
+    Op::Add,
+    Op::Subtract,
+    Op::Multiply,
+    Op::Divide,
+    Op::Min,
+    Op::Max,
+    Op::Pow,
+    Op::Step,
+    Op::Mix,
+    Op::Clamp,
+    Op::Smoothstep,
+    Op::Noise,
+    Op::CanvasRed,
+    Op::CanvasGreen,
+    Op::CanvasBlue,
+    Op::Store,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(position, character)) = chars.peek() {
+        if character.is_whitespace() {
+            chars.next();
+        } else if character.is_ascii_digit() || character == '.' {
+            let mut end = position;
+            while let Some(&(i, c)) = chars.peek() {
+                let exponent_sign = (c == '-' || c == '+') && source[..i].ends_with(['e', 'E']);
+                if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number =
+                source[position..end]
+                    .parse()
+                    .map_err(|_| ExpressionError::UnexpectedToken {
+                        position,
+                        token: Some(source[position..end].to_string()),
+                    })?;
+            tokens.push((position, Token::Number(number)));
+        } else if character.is_alphabetic() || character == '_' {
+            let mut end = position;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((position, Token::Name(source[position..end].to_string())));
+        } else if "+-*/(),=;".contains(character) {
+            tokens.push((position, Token::Symbol(character)));
+            chars.next();
+        } else {
+            return Err(ExpressionError::UnexpectedCharacter {
+                position,
+                character,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser emitting the bytecode as it goes, tracking how deep the stack
+/// gets.
+struct Compiler {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Where the source ends, for errors at the end.
+    end: usize,
+    code: Vec<Instruction>,
+    depth: usize,
+    /// The names of the locals, numbered by where they are.
+    locals: Vec<String>,
+}
+
+impl Compiler {
+    fn new(source: &str) -> Result<Self, ExpressionError> {
+        Ok(Self {
+            tokens: tokenize(source)?,
+            next: 0,
+            end: source.len(),
+            code: Vec::new(),
+            depth: 0,
+            locals: Vec::new(),
+        })
+    }
+
+    fn compile(mut self) -> Result<Vec<Instruction>, ExpressionError> {
+        while let (Some((position, Token::Name(name))), Some((_, Token::Symbol('=')))) = (
+            self.tokens.get(self.next).cloned(),
+            self.tokens.get(self.next + 1),
+        ) {
+            self.next += 2;
+            self.local(position, name)?;
+        }
+
+        let mut values = 1;
+        self.expression()?;
+        while self.eat(',') {
+            self.expression()?;
+            values += 1;
+        }
+        if self.next < self.tokens.len() {
+            return Err(self.unexpected());
+        }
+        if values != 1 && values != 3 {
+            return Err(ExpressionError::WrongValueCount(values));
+        }
+
+        self.code.push(Instruction::END);
+        Ok(self.code)
+    }
+
+    /// The value and semicolon of the local `name`, stored for the expression to load.
+    fn local(&mut self, position: usize, name: String) -> Result<(), ExpressionError> {
+        let taken = name == "pi"
+            || VARIABLES.iter().any(|(variable, _)| *variable == name)
+            || FUNCTIONS.iter().any(|(function, _, _)| *function == name)
+            || self.locals.contains(&name);
+        if taken {
+            return Err(ExpressionError::NameTaken { position, name });
+        }
+        if self.locals.len() >= MAX_LOCALS {
+            return Err(ExpressionError::TooManyLocals);
+        }
+
+        self.expression()?;
+        self.expect(';')?;
+        let slot = self.locals.len() as f32;
+        self.locals.push(name);
+        // Takes the value off the stack without pushing one
+        self.emit(Instruction::with_value(Op::Store, slot), 1)?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn expression(&mut self) -> Result<(), ExpressionError> {
+        self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Subtract
+            } else {
+                return Ok(());
+            };
+            self.term()?;
+            self.emit(Instruction::new(op), 2)?;
+        }
+    }
+
+    fn term(&mut self) -> Result<(), ExpressionError> {
+        self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Multiply
+            } else if self.eat('/') {
+                Op::Divide
+            } else {
+                return Ok(());
+            };
+            self.unary()?;
+            self.emit(Instruction::new(op), 2)?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<(), ExpressionError> {
+        if self.eat('-') {
+            self.unary()?;
+            return self.emit(Instruction::new(Op::Negate), 1);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<(), ExpressionError> {
+        let Some((position, token)) = self.tokens.get(self.next).cloned() else {
+            return Err(self.unexpected());
+        };
+        match token {
+            Token::Number(value) => {
+                self.next += 1;
+                self.emit(Instruction::with_value(Op::Constant, value), 0)
+            }
+            Token::Symbol('(') => {
+                self.next += 1;
+                self.expression()?;
+                self.expect(')')
+            }
+            Token::Name(name) => {
+                self.next += 1;
+                if self.eat('(') {
+                    return self.call(position, name);
+                }
+                if name == "pi" {
+                    return self.emit(Instruction::with_value(Op::Constant, PI), 0);
+                }
+                if let Some(slot) = self.locals.iter().position(|local| *local == name) {
+                    return self.emit(Instruction::with_value(Op::Load, slot as f32), 0);
+                }
+                let (_, op) = VARIABLES
+                    .iter()
+                    .find(|(variable, _)| *variable == name)
+                    .ok_or(ExpressionError::UnknownName { position, name })?;
+                self.emit(Instruction::new(*op), 0)
+            }
+            Token::Symbol(_) => Err(self.unexpected()),
+        }
+    }
+
+    /// The arguments and closing parenthesis of a call to `name`.
+    fn call(&mut self, position: usize, name: String) -> Result<(), ExpressionError> {
+        let mut arguments = 0;
+        if !self.eat(')') {
+            loop {
+                self.expression()?;
+                arguments += 1;
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(')')?;
+        }
+
+        let (_, op, expected) = FUNCTIONS
+            .iter()
+            .find(|(function, _, _)| *function == name)
+            .ok_or_else(|| ExpressionError::UnknownName {
+                position,
+                name: name.clone(),
+            })?;
+        if arguments != *expected {
+            return Err(ExpressionError::WrongArgumentCount {
+                function: name,
+                expected: *expected,
+                found: arguments,
+            });
+        }
+        self.emit(Instruction::new(*op), arguments)
+    }
+
+    /// Adds `instruction`, which takes `arguments` values off the stack and pushes one.
+    fn emit(&mut self, instruction: Instruction, arguments: usize) -> Result<(), ExpressionError> {
+        if self.code.len() >= MAX_INSTRUCTIONS {
+            return Err(ExpressionError::TooLong);
+        }
+        self.depth = self.depth + 1 - arguments;
+        if self.depth > MAX_STACK_DEPTH {
+            return Err(ExpressionError::TooDeep);
+        }
+        self.code.push(instruction);
+        Ok(())
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found =
+            matches!(self.tokens.get(self.next), Some((_, Token::Symbol(s))) if *s == symbol);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ExpressionError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn unexpected(&self) -> ExpressionError {
+        match self.tokens.get(self.next) {
+            Some((position, token)) => ExpressionError::UnexpectedToken {
+                position: *position,
+                token: Some(token.to_string()),
+            },
+            None => ExpressionError::UnexpectedToken {
+                position: self.end,
+                token: None,
+            },
+        }
+    }
+}
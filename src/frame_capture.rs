@@ -0,0 +1,97 @@
+//! Captures frames with RenderDoc's in-application API for GPU debugging. Only available when
+//! built with the `renderdoc` feature and started from RenderDoc, or with it injected.
+
+#[cfg(feature = "renderdoc")]
+use std::path::PathBuf;
+
+#[cfg(feature = "renderdoc")]
+use renderdoc::{RenderDoc, V141};
+
+pub struct FrameCapture {
+    /// `None` when RenderDoc isn't loaded into the process.
+    #[cfg(feature = "renderdoc")]
+    renderdoc: Option<RenderDoc<V141>>,
+    /// Whether to capture the next frame.
+    #[cfg(feature = "renderdoc")]
+    requested: bool,
+    #[cfg(feature = "renderdoc")]
+    capturing: bool,
+    #[cfg(feature = "renderdoc")]
+    last_capture: Option<PathBuf>,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "renderdoc")]
+            renderdoc: RenderDoc::new()
+                .map_err(|e| log::info!("RenderDoc isn't available: {}", e))
+                .ok(),
+            #[cfg(feature = "renderdoc")]
+            requested: false,
+            #[cfg(feature = "renderdoc")]
+            capturing: false,
+            #[cfg(feature = "renderdoc")]
+            last_capture: None,
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.renderdoc.is_some();
+        #[cfg(not(feature = "renderdoc"))]
+        false
+    }
+
+    /// Captures every pass of the next frame rendered.
+    pub fn request(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        {
+            self.requested = self.is_available();
+        }
+    }
+
+    /// Starts capturing if a capture was requested, before anything of the frame is recorded.
+    pub fn begin_frame(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        if let (true, Some(renderdoc)) = (std::mem::take(&mut self.requested), &mut self.renderdoc)
+        {
+            // Null handles capture the only device and window
+            renderdoc.start_frame_capture(std::ptr::null(), std::ptr::null());
+            self.capturing = true;
+        }
+    }
+
+    /// Ends the capture started by [`Self::begin_frame`], once the frame has been submitted.
+    pub fn end_frame(&mut self) {
+        #[cfg(feature = "renderdoc")]
+        if let (true, Some(renderdoc)) = (std::mem::take(&mut self.capturing), &mut self.renderdoc)
+        {
+            renderdoc.end_frame_capture(std::ptr::null(), std::ptr::null());
+            self.last_capture = renderdoc
+                .get_num_captures()
+                .checked_sub(1)
+                .and_then(|index| renderdoc.get_capture(index))
+                .map(|(path, _)| path);
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        let button = ui
+            .add_enabled(self.is_available(), egui::Button::new("Capture frame"))
+            .on_hover_text("Capture the next frame's passes with RenderDoc")
+            .on_disabled_hover_text(if cfg!(feature = "renderdoc") {
+                "Start the app from RenderDoc to capture frames"
+            } else {
+                "Build with `--features renderdoc` to capture frames with RenderDoc"
+            });
+        if button.clicked() {
+            self.request();
+        }
+
+        #[cfg(feature = "renderdoc")]
+        if let Some(path) = &self.last_capture {
+            ui.label(format!("Last capture: {}", path.display()));
+        }
+    }
+}
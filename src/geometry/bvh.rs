@@ -0,0 +1,388 @@
+use core::f32;
+use std::ops::Range;
+
+use cgmath::Vector3;
+
+use super::{ray_aabb, ray_triangle, Aabb, Bounded, Primitive, Ray, TriangleHit};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Node {
+    min_corner: [f32; 3],
+    left_child_index: u32,
+    max_corner: [f32; 3],
+    triangle_count: u32,
+}
+
+impl Node {
+    pub fn aabb(&self) -> Aabb {
+        Aabb {
+            min: self.min_corner.into(),
+            max: self.max_corner.into(),
+        }
+    }
+
+    /// The indices of an interior node's two children.
+    pub fn children(&self) -> Option<[usize; 2]> {
+        let left = self.left_child_index as usize;
+        (self.triangle_count == 0).then_some([left, left + 1])
+    }
+
+    /// Where a leaf's items are among its BVH's triangle indices.
+    pub fn items(&self) -> Option<Range<usize>> {
+        let first = self.left_child_index as usize;
+        (self.triangle_count != 0).then_some(first..first + self.triangle_count as usize)
+    }
+
+    /// The node once its BVH is stored after `nodes` other nodes and `indices` other triangle
+    /// indices, e.g. when uploading several BVHs into one buffer.
+    pub fn offset(&self, nodes: u32, indices: u32) -> Self {
+        let offset = if self.triangle_count == 0 {
+            nodes
+        } else {
+            indices
+        };
+        Self {
+            left_child_index: self.left_child_index + offset,
+            ..*self
+        }
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self {
+            min_corner: [f32::MAX; 3],
+            left_child_index: 0,
+            max_corner: [f32::MIN; 3],
+            triangle_count: 0,
+        }
+    }
+}
+
+/// The number of candidate split planes per axis that [`BvhBuilder::BinnedSah`] compares, plus
+/// one.
+const SAH_BINS: usize = 12;
+
+/// Subtrees over fewer items than this are built on one thread, since handing them to another
+/// costs more than it saves.
+const PARALLEL_THRESHOLD: usize = 1 << 14;
+/// Subtrees deeper than this are built on one thread, which is plenty of them to keep every core
+/// busy, and keeps uneven splits from recursing deep enough to overflow the stack.
+const MAX_PARALLEL_DEPTH: u32 = 16;
+
+/// How a [`Bvh`] chooses where to split its nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BvhBuilder {
+    /// Halves each node along its longest axis, which is quick to build but traces slowly
+    /// through uneven meshes.
+    Midpoint,
+    /// Takes the split with the lowest surface area heuristic cost among evenly spaced planes,
+    /// and keeps nodes as leaves where splitting wouldn't pay off.
+    #[default]
+    BinnedSah,
+}
+
+pub struct Bvh {
+    pub nodes: Vec<Node>,
+    pub triangle_indices: Vec<u32>,
+    builder: BvhBuilder,
+}
+
+impl Bvh {
+    pub fn from_triangles<T: Bounded + Sync>(triangles: &[T]) -> Self {
+        Self::build(triangles, BvhBuilder::default())
+    }
+
+    /// Builds the subtrees of large nodes on separate threads.
+    pub fn build<T: Bounded + Sync>(triangles: &[T], builder: BvhBuilder) -> Self {
+        let mut triangle_indices = (0..triangles.len() as u32).collect::<Vec<_>>();
+        let nodes = if triangles.is_empty() {
+            vec![]
+        } else {
+            build_subtree(triangles, &mut triangle_indices, builder, 0)
+        };
+
+        Self {
+            nodes,
+            triangle_indices,
+            builder,
+        }
+    }
+
+    pub fn builder(&self) -> BvhBuilder {
+        self.builder
+    }
+
+    /// The expected cost of tracing a ray through the tree by the surface area heuristic, in
+    /// node visits and triangle tests, for comparing builders.
+    pub fn sah_cost(&self) -> f32 {
+        let Some(root) = self.nodes.first() else {
+            return 0.0;
+        };
+        let root_area = root.aabb().surface_area().max(f32::MIN_POSITIVE);
+        self.nodes
+            .iter()
+            .map(|node| node.aabb().surface_area() / root_area * node.triangle_count.max(1) as f32)
+            .sum()
+    }
+
+    /// Returns the index of the closest triangle `ray` hits within `t_min..t_max` and where it
+    /// hits it. `triangles` must be the ones the BVH was built from.
+    pub fn closest_hit<T: Primitive>(
+        &self,
+        triangles: &[T],
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(usize, TriangleHit)> {
+        self.closest_hit_with(ray, t_min, t_max, |index, t_max| {
+            ray_triangle(ray, triangles[index].vertices(), t_min, t_max).map(|hit| (hit.t, hit))
+        })
+    }
+
+    /// Like [`Bvh::closest_hit`] for any kind of item, which `hit` intersects given its index and
+    /// the distance to the closest hit so far, returning the distance to it and what was hit.
+    pub fn closest_hit_with<H>(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        mut t_max: f32,
+        mut hit: impl FnMut(usize, f32) -> Option<(f32, H)>,
+    ) -> Option<(usize, H)> {
+        let mut closest = None;
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(node_index) = stack.pop() {
+            let node: &Node = &self.nodes[node_index];
+            if ray_aabb(ray, &node.aabb(), t_min, t_max).is_none() {
+                continue;
+            }
+
+            let Some(leaf_items) = node.items() else {
+                stack.extend(node.children().into_iter().flatten());
+                continue;
+            };
+            for &index in &self.triangle_indices[leaf_items] {
+                let index = index as usize;
+                if let Some((t, item_hit)) = hit(index, t_max) {
+                    t_max = t;
+                    closest = Some((index, item_hit));
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+/// Builds the nodes over `indices`, with the root first and leaves indexing into `indices`,
+/// splitting the children of large nodes between threads. `depth` is the root's depth in the
+/// whole tree.
+fn build_subtree<T: Bounded + Sync>(
+    triangles: &[T],
+    indices: &mut [u32],
+    builder: BvhBuilder,
+    depth: u32,
+) -> Vec<Node> {
+    if indices.len() < PARALLEL_THRESHOLD || depth >= MAX_PARALLEL_DEPTH {
+        return SubtreeBuilder::new(triangles, indices, builder).build();
+    }
+
+    let root = leaf(triangles, indices, 0);
+    let Some(left_count) = split(&root, triangles, indices, builder) else {
+        return vec![root];
+    };
+    let (left, right) = indices.split_at_mut(left_count as usize);
+    let (left_nodes, right_nodes) = rayon::join(
+        || build_subtree(triangles, left, builder, depth + 1),
+        || build_subtree(triangles, right, builder, depth + 1),
+    );
+
+    // The children go right after the root, followed by the rest of the left subtree and then
+    // the rest of the right one
+    let left_offset = 2;
+    let right_offset = left_nodes.len() as u32 + 1;
+    let mut nodes = Vec::with_capacity(1 + left_nodes.len() + right_nodes.len());
+    nodes.push(Node {
+        left_child_index: 1,
+        triangle_count: 0,
+        ..root
+    });
+    nodes.push(left_nodes[0].offset(left_offset, 0));
+    nodes.push(right_nodes[0].offset(right_offset, left_count));
+    nodes.extend(
+        left_nodes[1..]
+            .iter()
+            .map(|node| node.offset(left_offset, 0)),
+    );
+    nodes.extend(
+        right_nodes[1..]
+            .iter()
+            .map(|node| node.offset(right_offset, left_count)),
+    );
+    nodes
+}
+
+/// Builds a subtree on one thread, appending the children of each node as it's split.
+struct SubtreeBuilder<'a, T> {
+    triangles: &'a [T],
+    indices: &'a mut [u32],
+    nodes: Vec<Node>,
+    builder: BvhBuilder,
+}
+
+impl<'a, T: Bounded> SubtreeBuilder<'a, T> {
+    fn new(triangles: &'a [T], indices: &'a mut [u32], builder: BvhBuilder) -> Self {
+        Self {
+            triangles,
+            nodes: Vec::with_capacity(indices.len() * 2 - 1),
+            indices,
+            builder,
+        }
+    }
+
+    fn build(mut self) -> Vec<Node> {
+        self.nodes.push(leaf(self.triangles, self.indices, 0));
+        // A stack rather than recursion, since uneven splits can make the tree very deep
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            if let Some(left_child_index) = self.subdivide(node_index) {
+                stack.extend([left_child_index, left_child_index + 1]);
+            }
+        }
+        self.nodes
+    }
+
+    /// Splits the leaf in two if it's worth it, returning the index of its left child.
+    fn subdivide(&mut self, node_index: usize) -> Option<usize> {
+        let node = self.nodes[node_index];
+        if node.triangle_count <= 2 {
+            return None;
+        }
+
+        let first = node.left_child_index as usize;
+        let node_indices = &mut self.indices[first..first + node.triangle_count as usize];
+        let left_count = split(&node, self.triangles, node_indices, self.builder)?;
+        let (left, right) = node_indices.split_at(left_count as usize);
+        let left = leaf(self.triangles, left, node.left_child_index);
+        let right = leaf(self.triangles, right, node.left_child_index + left_count);
+
+        let left_child_index = self.nodes.len();
+        self.nodes.extend([left, right]);
+        self.nodes[node_index].left_child_index = left_child_index as u32;
+        self.nodes[node_index].triangle_count = 0;
+        Some(left_child_index)
+    }
+}
+
+/// A leaf over `indices`, which start at `first` among its BVH's triangle indices.
+fn leaf<T: Bounded>(triangles: &[T], indices: &[u32], first: u32) -> Node {
+    let bounds = indices
+        .iter()
+        .fold(Aabb::from_points(std::iter::empty()), |bounds, &index| {
+            bounds.union(&triangles[index as usize].bounds())
+        });
+    Node {
+        min_corner: bounds.min.into(),
+        left_child_index: first,
+        max_corner: bounds.max.into(),
+        triangle_count: indices.len() as u32,
+    }
+}
+
+/// Partitions `indices`, the items of `node`, around where `builder` splits it, returning how
+/// many went left. `None` if it's better off as a leaf or everything went to one side.
+fn split<T: Bounded>(
+    node: &Node,
+    triangles: &[T],
+    indices: &mut [u32],
+    builder: BvhBuilder,
+) -> Option<u32> {
+    let (axis, split_position) = match builder {
+        BvhBuilder::Midpoint => midpoint_split(node),
+        BvhBuilder::BinnedSah => sah_split(node, triangles, indices)?,
+    };
+
+    let mut i = 0;
+    let mut j = indices.len() - 1;
+    while i < j {
+        if triangles[indices[i] as usize].centroid()[axis] < split_position {
+            i += 1;
+        } else {
+            indices.swap(i, j);
+            j -= 1;
+        }
+    }
+
+    let left_count = i as u32;
+    (left_count != 0 && left_count != node.triangle_count).then_some(left_count)
+}
+
+/// The axis and position of the split of `node`, whose items are `indices`, with the lowest
+/// surface area heuristic cost, or `None` if it costs less as a leaf.
+fn sah_split<T: Bounded>(node: &Node, triangles: &[T], indices: &[u32]) -> Option<(usize, f32)> {
+    let centroids = Aabb::from_points(
+        indices
+            .iter()
+            .map(|&index| triangles[index as usize].centroid()),
+    );
+    let empty = Aabb::from_points(std::iter::empty());
+
+    let mut best = None;
+    let mut best_cost = node.triangle_count as f32 * node.aabb().surface_area();
+    for axis in 0..3 {
+        let (min, max) = (centroids.min[axis], centroids.max[axis]);
+        if min == max {
+            continue;
+        }
+
+        let scale = SAH_BINS as f32 / (max - min);
+        let mut bins = [(empty, 0u32); SAH_BINS];
+        for &index in indices {
+            let triangle = &triangles[index as usize];
+            let bin = (((triangle.centroid()[axis] - min) * scale) as usize).min(SAH_BINS - 1);
+            bins[bin].0 = bins[bin].0.union(&triangle.bounds());
+            bins[bin].1 += 1;
+        }
+
+        // Sweeps from the left for what's left of each plane, then from the right for the
+        // rest
+        let mut left_costs = [0.0; SAH_BINS - 1];
+        let (mut bounds, mut count) = (empty, 0);
+        for (plane, left_cost) in left_costs.iter_mut().enumerate() {
+            bounds = bounds.union(&bins[plane].0);
+            count += bins[plane].1;
+            *left_cost = count as f32 * bounds.surface_area();
+        }
+        let (mut bounds, mut count) = (empty, 0);
+        for plane in (0..SAH_BINS - 1).rev() {
+            bounds = bounds.union(&bins[plane + 1].0);
+            count += bins[plane + 1].1;
+            let cost = left_costs[plane] + count as f32 * bounds.surface_area();
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, min + (plane + 1) as f32 / scale));
+            }
+        }
+    }
+
+    best
+}
+
+/// The longest axis of `node` and its middle.
+fn midpoint_split(node: &Node) -> (usize, f32) {
+    let extent = Vector3::from(node.max_corner) - Vector3::from(node.min_corner);
+    let mut axis = 0;
+    if extent[1] > extent[axis] {
+        axis = 1;
+    }
+    if extent[2] > extent[axis] {
+        axis = 2;
+    }
+
+    (axis, node.min_corner[axis] + extent[axis] / 2.0)
+}
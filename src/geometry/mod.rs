@@ -0,0 +1,376 @@
+//! CPU-side ray intersection, BVH traversal and frustum culling, independent of the GPU renderer.
+//!
+//! These mirror the intersection code in the compute shader, so they can be used for picking
+//! and editor tools, and tested without a device.
+
+use cgmath::{Deg, InnerSpace, Matrix3, Vector2, Vector3, VectorSpace};
+
+mod bvh;
+
+pub use bvh::{Bvh, BvhBuilder, Node};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    /// Doesn't need to be normalized, distances along the ray are in multiples of its length.
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+/// Returns the distance along `ray` to the nearest intersection with the sphere within
+/// `t_min..t_max`, exclusive.
+pub fn ray_sphere(
+    ray: &Ray,
+    center: Vector3<f32>,
+    radius: f32,
+    t_min: f32,
+    t_max: f32,
+) -> Option<f32> {
+    let oc = ray.origin - center;
+    let a = ray.direction.magnitude2();
+    let half_b = oc.dot(ray.direction);
+    let c = oc.magnitude2() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    [(-half_b - root) / a, (-half_b + root) / a]
+        .into_iter()
+        .find(|&t| t > t_min && t < t_max)
+}
+
+/// An intersection with a triangle `[a, b, c]`, at the point `a * w + b * u + c * v` where
+/// `w = 1 - u - v`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleHit {
+    pub t: f32,
+    pub u: f32,
+    pub v: f32,
+}
+
+impl TriangleHit {
+    /// The barycentric weights of the triangle's vertices `[a, b, c]`.
+    pub fn barycentrics(&self) -> [f32; 3] {
+        [1.0 - self.u - self.v, self.u, self.v]
+    }
+
+    /// Interpolates per-vertex `values` at the hit point.
+    pub fn interpolate<T: VectorSpace<Scalar = f32>>(&self, [a, b, c]: [T; 3]) -> T {
+        let [w, u, v] = self.barycentrics();
+        a * w + b * u + c * v
+    }
+}
+
+/// A triangle hit with the triangle's vertex attributes interpolated at the hit point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceHit {
+    pub t: f32,
+    pub point: Vector3<f32>,
+    pub barycentrics: [f32; 3],
+    /// The interpolated shading normal, normalized.
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+/// Intersects `ray` with both sides of a triangle using the Möller-Trumbore algorithm.
+pub fn ray_triangle(
+    ray: &Ray,
+    [a, b, c]: [Vector3<f32>; 3],
+    t_min: f32,
+    t_max: f32,
+) -> Option<TriangleHit> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    // Parallel to the triangle
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inverse_determinant;
+    (t > t_min && t < t_max).then_some(TriangleHit { t, u, v })
+}
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box containing all of `points`, inverted (min > max) if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        points.into_iter().fold(
+            Self {
+                min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+                max: Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+            },
+            |aabb, point| Self {
+                min: Vector3::new(
+                    aabb.min.x.min(point.x),
+                    aabb.min.y.min(point.y),
+                    aabb.min.z.min(point.z),
+                ),
+                max: Vector3::new(
+                    aabb.max.x.max(point.x),
+                    aabb.max.y.max(point.y),
+                    aabb.max.z.max(point.z),
+                ),
+            },
+        )
+    }
+
+    /// Whether the box is inverted, containing nothing.
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    /// The smallest box containing both, where an inverted box counts as empty.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// Zero for an inverted box.
+    pub fn surface_area(&self) -> f32 {
+        let extent = self.max - self.min;
+        if extent.x < 0.0 || extent.y < 0.0 || extent.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+}
+
+/// Returns the distance along `ray` to where it enters the box within `t_min..t_max`, or
+/// `t_min` if it starts inside, using the slab method.
+pub fn ray_aabb(ray: &Ray, aabb: &Aabb, t_min: f32, t_max: f32) -> Option<f32> {
+    let mut t_enter = t_min;
+    let mut t_exit = t_max;
+
+    for axis in 0..3 {
+        let inverse_direction = 1.0 / ray.direction[axis];
+        let t0 = (aabb.min[axis] - ray.origin[axis]) * inverse_direction;
+        let t1 = (aabb.max[axis] - ray.origin[axis]) * inverse_direction;
+        let (near, far) = if inverse_direction < 0.0 {
+            (t1, t0)
+        } else {
+            (t0, t1)
+        };
+
+        // Written so NaNs from a zero direction on an axis of the boundary are ignored
+        if near > t_enter {
+            t_enter = near;
+        }
+        if far < t_exit {
+            t_exit = far;
+        }
+        if t_exit < t_enter {
+            return None;
+        }
+    }
+
+    Some(t_enter)
+}
+
+/// The rotation by Euler angles in degrees, applied around X, then Y, then Z.
+pub fn euler_to_matrix(angles: Vector3<f32>) -> Matrix3<f32> {
+    Matrix3::from_angle_z(Deg(angles.z))
+        * Matrix3::from_angle_y(Deg(angles.y))
+        * Matrix3::from_angle_x(Deg(angles.x))
+}
+
+/// The inverse of [`euler_to_matrix`], with the Y angle within -90..=90 degrees. When it's at
+/// either end, X and Z turn about the same axis and Z is left at zero.
+pub fn matrix_to_euler(rotation: &Matrix3<f32>) -> Vector3<f32> {
+    // Columns are the rotated axes, so `rotation.x.z` is the third row of the first column
+    let sin_y = -rotation.x.z;
+    let cos_y = rotation.x.x.hypot(rotation.x.y);
+    let y = sin_y.atan2(cos_y);
+    let (x, z) = if cos_y > 1e-6 {
+        (
+            rotation.y.z.atan2(rotation.z.z),
+            rotation.x.y.atan2(rotation.x.x),
+        )
+    } else {
+        ((sin_y.signum() * rotation.y.x).atan2(rotation.y.y), 0.0)
+    };
+    Vector3::new(x, y, z).map(f32::to_degrees)
+}
+
+/// The part of space a perspective camera sees, as planes whose normals point inwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    /// The normal and offset of each plane, with `normal.dot(point) + offset >= 0` inside.
+    planes: [(Vector3<f32>, f32); 5],
+}
+
+impl Frustum {
+    /// The frustum looking along `forward` from `origin`, whose sides are `half_width` and
+    /// `half_height` away from the center at a distance of one, cut off at `near`. The axes
+    /// should be orthonormal.
+    pub fn new(
+        origin: Vector3<f32>,
+        [forward, right, up]: [Vector3<f32>; 3],
+        half_width: f32,
+        half_height: f32,
+        near: f32,
+    ) -> Self {
+        let plane = |normal: Vector3<f32>, point: Vector3<f32>| {
+            let normal = normal.normalize();
+            (normal, -normal.dot(point))
+        };
+        Self {
+            planes: [
+                plane(forward, origin + forward * near),
+                plane(forward * half_width - right, origin),
+                plane(forward * half_width + right, origin),
+                plane(forward * half_height - up, origin),
+                plane(forward * half_height + up, origin),
+            ],
+        }
+    }
+
+    /// Whether any of the sphere might be visible. Spheres just outside a corner count as inside.
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|(normal, offset)| normal.dot(center) + offset >= -radius)
+    }
+
+    /// Whether any of the box might be visible. Boxes just outside a corner count as inside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|(normal, offset)| {
+            // The corner furthest along the normal
+            let corner = Vector3::new(
+                if normal.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if normal.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if normal.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            normal.dot(corner) + offset >= 0.0
+        })
+    }
+}
+
+/// A triangle the [`Bvh`] can be traversed against.
+pub trait Primitive {
+    fn vertices(&self) -> [Vector3<f32>; 3];
+
+    /// The shading normals at the vertices, the face normal by default.
+    fn normals(&self) -> [Vector3<f32>; 3] {
+        let [a, b, c] = self.vertices();
+        let normal = (b - a).cross(c - a).normalize();
+        [normal; 3]
+    }
+
+    /// Whether the triangle has no area for a ray to hit or a vertex that isn't finite.
+    fn is_degenerate(&self) -> bool {
+        let [a, b, c] = self.vertices();
+        let area = (b - a).cross(c - a).magnitude2();
+        !area.is_finite() || area == 0.0
+    }
+
+    /// The texture coordinates at the vertices.
+    fn uvs(&self) -> [Vector2<f32>; 3] {
+        [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(1.0, 0.0),
+            Vector2::new(0.0, 1.0),
+        ]
+    }
+
+    /// Interpolates the triangle's attributes at `hit`, which should be a hit on this triangle.
+    fn surface_hit(&self, ray: &Ray, hit: TriangleHit) -> SurfaceHit {
+        SurfaceHit {
+            t: hit.t,
+            point: ray.at(hit.t),
+            barycentrics: hit.barycentrics(),
+            normal: hit.interpolate(self.normals()).normalize(),
+            uv: hit.interpolate(self.uvs()),
+        }
+    }
+}
+
+impl Primitive for [Vector3<f32>; 3] {
+    fn vertices(&self) -> [Vector3<f32>; 3] {
+        *self
+    }
+}
+
+/// Something a [`Bvh`] can be built over, such as a triangle or the bounds of a mesh instance.
+pub trait Bounded {
+    fn bounds(&self) -> Aabb;
+
+    /// The point the builder sorts it by.
+    fn centroid(&self) -> Vector3<f32> {
+        self.bounds().center()
+    }
+}
+
+impl<T: Primitive> Bounded for T {
+    fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices())
+    }
+
+    fn centroid(&self) -> Vector3<f32> {
+        let [a, b, c] = self.vertices();
+        (a + b + c) / 3.0
+    }
+}
+
+impl Bounded for Aabb {
+    fn bounds(&self) -> Aabb {
+        *self
+    }
+}
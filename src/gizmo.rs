@@ -0,0 +1,383 @@
+use cgmath::{ElementWise, InnerSpace, Matrix3, Rad, Vector2, Vector3};
+use uuid::Uuid;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::ModifiersState,
+};
+
+use crate::{
+    geometry::{self, Ray},
+    overlays::color32,
+    scene::{Camera, Placement, Scene},
+    ui::Palette,
+};
+
+/// How long the handles are as a fraction of their distance from the camera, so they stay the
+/// same size on screen.
+const HANDLE_SCALE: f32 = 0.15;
+/// How close the cursor has to be to a handle to grab it, in physical pixels.
+const GRAB_DISTANCE: f32 = 10.0;
+/// The size of the arrow heads and scale boxes in points.
+const HEAD_SIZE: f32 = 8.0;
+/// The points each rotation ring is drawn and hit tested with.
+const RING_SEGMENTS: usize = 48;
+/// What rotations snap to while Ctrl is held.
+const ROTATION_SNAP: f32 = 15.0;
+/// The smallest scale dragging will shrink an object to.
+const MIN_SCALE: f32 = 0.01;
+
+const AXES: [Vector3<f32>; 3] = [
+    Vector3::new(1.0, 0.0, 0.0),
+    Vector3::new(0.0, 1.0, 0.0),
+    Vector3::new(0.0, 0.0, 1.0),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    /// Arrows that move the object along an axis, or across it while Shift is held.
+    Translate,
+    /// Rings that turn the object around an axis, in steps while Ctrl is held.
+    Rotate,
+    /// Handles that scale the object along an axis, or uniformly while Shift is held.
+    Scale,
+}
+
+/// A handle being dragged, and what the selection and cursor were like when it was grabbed.
+struct Drag {
+    axis: usize,
+    /// Each selected object, the handles at the last.
+    start: Vec<(Uuid, Placement)>,
+    /// How far along the axis the cursor was, or its angle around it for rings.
+    grabbed_at: f32,
+    /// Where the cursor was on the plane across the axis, for moving along it.
+    grabbed_on_plane: Option<Vector3<f32>>,
+}
+
+/// Handles along X, Y and Z at the most recently selected object, which move, turn or scale
+/// the whole selection around it when dragged.
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    /// The handle under the cursor, which a click grabs.
+    hovered: Option<usize>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Gizmo");
+            ui.radio_value(&mut self.mode, GizmoMode::Translate, "Move")
+                .on_hover_text("Drag an arrow to move, with Shift to move across it instead");
+            ui.radio_value(&mut self.mode, GizmoMode::Rotate, "Rotate")
+                .on_hover_text("Drag a ring to turn, with Ctrl to snap to 15°");
+            ui.radio_value(&mut self.mode, GizmoMode::Scale, "Scale")
+                .on_hover_text("Drag a handle to scale, with Shift to scale uniformly");
+        });
+    }
+
+    /// Switches to `mode`, dropping whatever is being dragged.
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        self.mode = mode;
+        self.hovered = None;
+        self.drag = None;
+    }
+
+    /// Finds the handle under `cursor`, unless one is already being dragged.
+    pub fn hover(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        cursor: PhysicalPosition<f64>,
+        screen_size: PhysicalSize<u32>,
+    ) {
+        if self.drag.is_some() {
+            return;
+        }
+        self.hovered = None;
+        let placements = scene.selected_placements();
+
+        let cursor = Vector2::new(cursor.x as f32, cursor.y as f32);
+        let mut closest = GRAB_DISTANCE;
+        for (axis, points) in self.handles(camera, &placements, screen_size) {
+            let distance = points
+                .windows(2)
+                .map(|segment| distance_to_segment(cursor, segment[0], segment[1]))
+                .fold(f32::INFINITY, f32::min);
+            if distance < closest {
+                closest = distance;
+                self.hovered = Some(axis);
+            }
+        }
+    }
+
+    /// Grabs the hovered handle with the cursor at `ray`, returning whether one was grabbed.
+    pub fn begin_drag(&mut self, scene: &Scene, ray: &Ray) -> bool {
+        let start = scene.selected_placements();
+        let (Some(axis), Some(&(_, pivot))) = (self.hovered, start.last()) else {
+            return false;
+        };
+        let grabbed_at = match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                closest_on_axis(pivot.position, AXES[axis], ray)
+            }
+            GizmoMode::Rotate => angle_around(pivot.position, axis, ray),
+        };
+        let Some(grabbed_at) = grabbed_at else {
+            return false;
+        };
+        // Scaling divides by how far from the center the handle was grabbed
+        if self.mode == GizmoMode::Scale && grabbed_at.abs() < 1e-4 {
+            return false;
+        }
+
+        self.drag = Some(Drag {
+            axis,
+            start,
+            grabbed_at,
+            grabbed_on_plane: on_plane(pivot.position, AXES[axis], ray),
+        });
+        true
+    }
+
+    /// Moves, turns or scales the selection with the dragged handle to follow the cursor at
+    /// `ray`.
+    pub fn drag(&mut self, scene: &mut Scene, ray: &Ray, modifiers: ModifiersState) {
+        let Some(drag) = &self.drag else {
+            return;
+        };
+        let Some(&(_, pivot)) = drag.start.last() else {
+            return;
+        };
+        let axis = AXES[drag.axis];
+        let mut placements = drag.start.clone();
+
+        // Where the cursor gives no sense of how far to go, e.g. looking straight down the axis,
+        // the selection stays where it is
+        match self.mode {
+            GizmoMode::Translate => {
+                let offset = if modifiers.shift() {
+                    let (Some(grabbed), Some(point)) =
+                        (drag.grabbed_on_plane, on_plane(pivot.position, axis, ray))
+                    else {
+                        return;
+                    };
+                    point - grabbed
+                } else {
+                    let Some(along) = closest_on_axis(pivot.position, axis, ray) else {
+                        return;
+                    };
+                    axis * (along - drag.grabbed_at)
+                };
+                for (_, placement) in &mut placements {
+                    placement.position += offset;
+                }
+            }
+            GizmoMode::Rotate => {
+                let Some(angle) = angle_around(pivot.position, drag.axis, ray) else {
+                    return;
+                };
+                let mut turned = (angle - drag.grabbed_at).to_degrees();
+                if modifiers.ctrl() {
+                    turned = (turned / ROTATION_SNAP).round() * ROTATION_SNAP;
+                }
+                let turn = Matrix3::from_axis_angle(axis, Rad(turned.to_radians()));
+                for (_, placement) in &mut placements {
+                    placement.position =
+                        pivot.position + turn * (placement.position - pivot.position);
+                    placement.rotation = placement.rotation.map(|rotation| {
+                        geometry::matrix_to_euler(&(turn * geometry::euler_to_matrix(rotation)))
+                    });
+                }
+            }
+            GizmoMode::Scale => {
+                let Some(along) = closest_on_axis(pivot.position, axis, ray) else {
+                    return;
+                };
+                let factor = along / drag.grabbed_at;
+                let uniform = modifiers.shift() || pivot.uniform_scale;
+                let mut factors = Vector3::new(1.0, 1.0, 1.0);
+                if uniform {
+                    factors *= factor;
+                } else {
+                    factors[drag.axis] = factor;
+                }
+                for (_, placement) in &mut placements {
+                    placement.position = pivot.position
+                        + (placement.position - pivot.position).mul_element_wise(factors);
+                    // Spheres keep their size when the rest are stretched along one axis
+                    if uniform || !placement.uniform_scale {
+                        placement.scale = placement
+                            .scale
+                            .mul_element_wise(factors)
+                            .map(|scale| scale.max(MIN_SCALE));
+                    }
+                }
+            }
+        }
+
+        scene.place(&placements);
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// Draws the handles at the selection over the viewport, the hovered or dragged one in the
+    /// highlight color.
+    pub fn draw(
+        &self,
+        context: &egui::Context,
+        scene: &Scene,
+        camera: &Camera,
+        screen_size: PhysicalSize<u32>,
+        palette: Palette,
+    ) {
+        let placements = scene.selected_placements();
+        let painter = context.layer_painter(egui::LayerId::background());
+        let pixels_per_point = context.pixels_per_point();
+        let to_pos = |point: Vector2<f32>| {
+            egui::pos2(point.x / pixels_per_point, point.y / pixels_per_point)
+        };
+        let active = self.drag.as_ref().map(|drag| drag.axis).or(self.hovered);
+
+        for (axis, points) in self.handles(camera, &placements, screen_size) {
+            let color = color32(if active == Some(axis) {
+                palette.highlight()
+            } else {
+                palette.axes()[axis]
+            });
+            let points: Vec<_> = points.into_iter().map(to_pos).collect();
+            let stroke = egui::Stroke::new(2.5, color);
+
+            match self.mode {
+                GizmoMode::Translate => {
+                    let (base, tip) = (points[0], points[1]);
+                    painter.line_segment([base, tip], stroke);
+                    let direction = (tip - base).normalized();
+                    let side = direction.rot90() * HEAD_SIZE * 0.5;
+                    let back = tip - direction * HEAD_SIZE;
+                    painter.add(egui::Shape::convex_polygon(
+                        vec![tip + direction * HEAD_SIZE * 0.5, back + side, back - side],
+                        color,
+                        egui::Stroke::NONE,
+                    ));
+                }
+                GizmoMode::Rotate => {
+                    painter.add(egui::Shape::line(points, stroke));
+                }
+                GizmoMode::Scale => {
+                    let (base, tip) = (points[0], points[1]);
+                    painter.line_segment([base, tip], stroke);
+                    painter.rect_filled(
+                        egui::Rect::from_center_size(tip, egui::Vec2::splat(HEAD_SIZE)),
+                        0.0,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+
+    /// The handle for each axis in the current mode at the last of `placements`, as a line
+    /// through points in physical pixels. Rings are left out for a single object that can't
+    /// turn, and lines for axes pointing so
+    /// steeply away from the camera they'd be too short to grab. Parts behind the camera are
+    /// left out too, which can leave a ring open.
+    fn handles(
+        &self,
+        camera: &Camera,
+        placements: &[(Uuid, Placement)],
+        screen_size: PhysicalSize<u32>,
+    ) -> Vec<(usize, Vec<Vector2<f32>>)> {
+        let Some(&(_, pivot)) = placements.last() else {
+            return Vec::new();
+        };
+        let center = pivot.position;
+        let length = (center - camera.origin).magnitude() * HANDLE_SCALE;
+        let project = |point: Vector3<f32>| camera.world_to_screen(point, screen_size);
+
+        if self.mode == GizmoMode::Rotate {
+            if placements.len() == 1 && pivot.rotation.is_none() {
+                return Vec::new();
+            }
+            return (0..3)
+                .map(|axis| {
+                    let (u, v) = (AXES[(axis + 1) % 3], AXES[(axis + 2) % 3]);
+                    let points = (0..=RING_SEGMENTS)
+                        .filter_map(|i| {
+                            let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                            project(center + (u * angle.cos() + v * angle.sin()) * length)
+                        })
+                        .collect();
+                    (axis, points)
+                })
+                .collect();
+        }
+
+        let Some(base) = project(center) else {
+            return Vec::new();
+        };
+        (0..3)
+            .filter_map(|axis| {
+                let tip = project(center + AXES[axis] * length)?;
+                ((tip - base).magnitude() > GRAB_DISTANCE).then_some((axis, vec![base, tip]))
+            })
+            .collect()
+    }
+}
+
+fn distance_to_segment(point: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    let ab = b - a;
+    if ab.magnitude2() == 0.0 {
+        return (point - a).magnitude();
+    }
+    let t = ((point - a).dot(ab) / ab.magnitude2()).clamp(0.0, 1.0);
+    (point - (a + ab * t)).magnitude()
+}
+
+/// How far along the line through `origin` in the unit `direction` the closest point to `ray`
+/// is, `None` if they're nearly parallel.
+fn closest_on_axis(origin: Vector3<f32>, direction: Vector3<f32>, ray: &Ray) -> Option<f32> {
+    let ray_direction = ray.direction.normalize();
+    let cos = direction.dot(ray_direction);
+    let denominator = 1.0 - cos * cos;
+    if denominator < 1e-4 {
+        return None;
+    }
+
+    let offset = ray.origin - origin;
+    Some((direction.dot(offset) - cos * ray_direction.dot(offset)) / denominator)
+}
+
+/// Where `ray` crosses the plane through `origin` facing `normal`, `None` if it's nearly
+/// edge-on or behind the ray.
+fn on_plane(origin: Vector3<f32>, normal: Vector3<f32>, ray: &Ray) -> Option<Vector3<f32>> {
+    let ray_direction = ray.direction.normalize();
+    let cos = normal.dot(ray_direction);
+    if cos.abs() < 1e-2 {
+        return None;
+    }
+
+    let t = normal.dot(origin - ray.origin) / cos;
+    (t > 0.0).then(|| ray.origin + ray_direction * t)
+}
+
+/// The angle around the axis `axis` through `center` of where `ray` crosses the plane across
+/// it, in radians.
+fn angle_around(center: Vector3<f32>, axis: usize, ray: &Ray) -> Option<f32> {
+    let offset = on_plane(center, AXES[axis], ray)? - center;
+    let (u, v) = (AXES[(axis + 1) % 3], AXES[(axis + 2) % 3]);
+    Some(offset.dot(v).atan2(offset.dot(u)))
+}
@@ -0,0 +1,293 @@
+use std::{fmt, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+use crate::{app::Action, assets};
+
+/// The file the bindings are kept in, under [`assets::config_dir`].
+const HOTKEYS_FILE_NAME: &str = "hotkeys.json";
+
+/// A key pressed while holding a set of modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChord {
+    pub key: VirtualKeyCode,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyChord {
+    pub fn new(key: VirtualKeyCode, modifiers: ModifiersState) -> Self {
+        Self { key, modifiers }
+    }
+
+    fn is_modifier(&self) -> bool {
+        matches!(
+            self.key,
+            VirtualKeyCode::LControl
+                | VirtualKeyCode::RControl
+                | VirtualKeyCode::LShift
+                | VirtualKeyCode::RShift
+                | VirtualKeyCode::LAlt
+                | VirtualKeyCode::RAlt
+                | VirtualKeyCode::LWin
+                | VirtualKeyCode::RWin
+        )
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl() {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.shift() {
+            write!(f, "Shift+")?;
+        }
+        if self.modifiers.alt() {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.logo() {
+            write!(f, "Super+")?;
+        }
+
+        match self.key {
+            VirtualKeyCode::LBracket => write!(f, "["),
+            VirtualKeyCode::RBracket => write!(f, "]"),
+            key => {
+                let name = format!("{:?}", key);
+                write!(f, "{}", name.strip_prefix("Key").unwrap_or(&name))
+            }
+        }
+    }
+}
+
+/// Maps editor actions to rebindable key chords, which are saved whenever they're changed so
+/// they're kept across sessions.
+pub struct Hotkeys {
+    bindings: Vec<(Action, Option<KeyChord>)>,
+    /// `None` if there's nowhere to save them.
+    path: Option<PathBuf>,
+    /// The action waiting for a new chord to be pressed in the settings UI.
+    capturing: Option<Action>,
+    /// A captured chord that is already bound to another action, waiting for confirmation.
+    conflict: Option<Conflict>,
+}
+
+struct Conflict {
+    action: Action,
+    chord: KeyChord,
+    bound_to: Action,
+}
+
+impl Hotkeys {
+    /// Reads the saved bindings over the defaults, so actions added since they were saved get
+    /// their default chords.
+    pub fn load() -> Self {
+        let path = assets::config_dir().map(|directory| directory.join(HOTKEYS_FILE_NAME));
+        let saved = path.as_ref().and_then(|path| match fs::read(path) {
+            Ok(contents) => serde_json::from_slice::<Vec<(Action, Option<KeyChord>)>>(&contents)
+                .map_err(|e| log::warn!("Ignoring invalid {}: {}", path.display(), e))
+                .ok(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                None
+            }
+        });
+
+        let mut hotkeys = Self {
+            bindings: Self::defaults(),
+            path,
+            capturing: None,
+            conflict: None,
+        };
+        for (action, chord) in saved.into_iter().flatten() {
+            hotkeys.bind(action, chord);
+        }
+        hotkeys
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                let json = serde_json::to_vec_pretty(&self.bindings)?;
+                fs::write(path, json)
+            });
+        if let Err(e) = result {
+            log::warn!("Failed to save shortcuts to {}: {}", path.display(), e);
+        }
+    }
+
+    fn defaults() -> Vec<(Action, Option<KeyChord>)> {
+        let none = ModifiersState::empty();
+        Action::ALL
+            .into_iter()
+            .map(|action| {
+                let chord = match action {
+                    Action::OpenCommandPalette => {
+                        Some(KeyChord::new(VirtualKeyCode::P, ModifiersState::CTRL))
+                    }
+                    Action::RemoveSelection => Some(KeyChord::new(VirtualKeyCode::Delete, none)),
+                    Action::DuplicateSelection => {
+                        Some(KeyChord::new(VirtualKeyCode::D, ModifiersState::CTRL))
+                    }
+                    Action::CopySelection => {
+                        Some(KeyChord::new(VirtualKeyCode::C, ModifiersState::CTRL))
+                    }
+                    Action::Paste => Some(KeyChord::new(VirtualKeyCode::V, ModifiersState::CTRL)),
+                    Action::TogglePanels => Some(KeyChord::new(VirtualKeyCode::Tab, none)),
+                    Action::TogglePresentationMode => {
+                        Some(KeyChord::new(VirtualKeyCode::F10, none))
+                    }
+                    Action::ToggleFullscreen => Some(KeyChord::new(VirtualKeyCode::F11, none)),
+                    Action::SaveImage => Some(KeyChord::new(VirtualKeyCode::F12, none)),
+                    Action::SaveScene => {
+                        Some(KeyChord::new(VirtualKeyCode::S, ModifiersState::CTRL))
+                    }
+                    Action::SelectPreviousSphere => {
+                        Some(KeyChord::new(VirtualKeyCode::LBracket, none))
+                    }
+                    Action::SelectNextSphere => Some(KeyChord::new(VirtualKeyCode::RBracket, none)),
+                    Action::TranslateMode => Some(KeyChord::new(VirtualKeyCode::G, none)),
+                    Action::RotateMode => Some(KeyChord::new(VirtualKeyCode::R, none)),
+                    Action::ScaleMode => Some(KeyChord::new(VirtualKeyCode::T, none)),
+                    Action::FrameSelection => Some(KeyChord::new(VirtualKeyCode::F, none)),
+                    Action::ApplyQualityPreset(0) => {
+                        Some(KeyChord::new(VirtualKeyCode::Key1, ModifiersState::CTRL))
+                    }
+                    Action::ApplyQualityPreset(1) => {
+                        Some(KeyChord::new(VirtualKeyCode::Key2, ModifiersState::CTRL))
+                    }
+                    Action::ApplyQualityPreset(2) => {
+                        Some(KeyChord::new(VirtualKeyCode::Key3, ModifiersState::CTRL))
+                    }
+                    Action::Quit => Some(KeyChord::new(VirtualKeyCode::Escape, none)),
+                    _ => None,
+                };
+                (action, chord)
+            })
+            .collect()
+    }
+
+    /// Whether the settings UI is waiting for a chord, so key presses shouldn't trigger actions.
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    pub fn chord(&self, action: Action) -> Option<KeyChord> {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .and_then(|(_, chord)| *chord)
+    }
+
+    /// Returns the action bound to `chord`, or binds it if a new chord is being captured.
+    pub fn handle_key(&mut self, chord: KeyChord) -> Option<Action> {
+        if chord.is_modifier() {
+            return None;
+        }
+
+        let bound_to = self
+            .bindings
+            .iter()
+            .find(|(_, c)| *c == Some(chord))
+            .map(|(a, _)| *a);
+
+        let Some(action) = self.capturing.take() else {
+            return bound_to;
+        };
+
+        match bound_to {
+            _ if chord.key == VirtualKeyCode::Escape && chord.modifiers.is_empty() => {}
+            Some(bound_to) if bound_to != action => {
+                self.conflict = Some(Conflict {
+                    action,
+                    chord,
+                    bound_to,
+                })
+            }
+            _ => {
+                self.bind(action, Some(chord));
+                self.save();
+            }
+        }
+
+        None
+    }
+
+    fn bind(&mut self, action: Action, chord: Option<KeyChord>) {
+        if let Some((_, c)) = self.bindings.iter_mut().find(|(a, _)| *a == action) {
+            *c = chord;
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Shortcuts", |ui| {
+            if let Some(conflict) = &self.conflict {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "{} is already bound to \"{}\"",
+                        conflict.chord,
+                        conflict.bound_to.name()
+                    ),
+                );
+                let (action, chord, bound_to) =
+                    (conflict.action, conflict.chord, conflict.bound_to);
+                ui.horizontal(|ui| {
+                    if ui.button("Replace").clicked() {
+                        self.bind(bound_to, None);
+                        self.bind(action, Some(chord));
+                        self.conflict = None;
+                        self.save();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.conflict = None;
+                    }
+                });
+                ui.separator();
+            }
+
+            egui::Grid::new("shortcuts").show(ui, |ui| {
+                for i in 0..self.bindings.len() {
+                    let (action, chord) = self.bindings[i];
+                    ui.label(action.name());
+
+                    let label = if self.capturing == Some(action) {
+                        "Press a key...".to_string()
+                    } else {
+                        chord.map_or("Unbound".to_string(), |chord| chord.to_string())
+                    };
+                    if ui
+                        .button(label)
+                        .on_hover_text("Click, then press the new shortcut, or Escape to cancel")
+                        .clicked()
+                    {
+                        self.capturing = Some(action);
+                        self.conflict = None;
+                    }
+                    if ui
+                        .add_enabled(chord.is_some(), egui::Button::new("Clear"))
+                        .clicked()
+                    {
+                        self.bind(action, None);
+                        self.save();
+                    }
+                    ui.end_row();
+                }
+            });
+
+            if ui.button("Reset to defaults").clicked() {
+                self.bindings = Self::defaults();
+                self.capturing = None;
+                self.conflict = None;
+                self.save();
+            }
+        });
+    }
+}
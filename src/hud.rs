@@ -0,0 +1,119 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How far back the sample rate the ETA is estimated from goes, long enough to smooth over
+/// frame time spikes but short enough to follow changes to the render settings.
+const RATE_WINDOW: Duration = Duration::from_secs(3);
+
+/// How far the HUD is from the corner of the viewport, in points.
+const MARGIN: f32 = 8.0;
+
+/// Render progress drawn in a corner of the viewport, shown even with the panels hidden.
+pub struct Hud {
+    samples: u32,
+    target: u32,
+    /// When the image being accumulated was last reset.
+    started: Instant,
+    /// How long the image took to reach its target, once it has.
+    converged_after: Option<Duration>,
+    /// The sample count at recent frames, oldest first.
+    history: VecDeque<(Instant, u32)>,
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self {
+            samples: 0,
+            target: 0,
+            started: Instant::now(),
+            converged_after: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Records the samples accumulated so far this frame, restarting the clock if the image
+    /// was reset since the last one.
+    pub fn update(&mut self, samples: u32, target: u32) {
+        let now = Instant::now();
+        if samples < self.samples {
+            self.started = now;
+            self.converged_after = None;
+            self.history.clear();
+        }
+        self.samples = samples;
+        self.target = target;
+
+        if samples < target {
+            self.converged_after = None;
+        } else if self.converged_after.is_none() {
+            self.converged_after = Some(now - self.started);
+        }
+
+        self.history.push_back((now, samples));
+        while self
+            .history
+            .front()
+            .is_some_and(|&(time, _)| now - time > RATE_WINDOW)
+        {
+            self.history.pop_front();
+        }
+    }
+
+    /// How long until the target is reached at the rate samples were accumulated recently.
+    fn eta(&self) -> Option<Duration> {
+        let &(first_time, first) = self.history.front()?;
+        let &(last_time, last) = self.history.back()?;
+        let seconds = (last_time - first_time).as_secs_f64();
+        if last <= first || seconds == 0.0 {
+            return None;
+        }
+
+        let rate = (last - first) as f64 / seconds;
+        let remaining = self.target.saturating_sub(self.samples) as f64;
+        Some(Duration::from_secs_f64(remaining / rate))
+    }
+
+    pub fn draw(&self, context: &egui::Context) {
+        let progress = if self.target == 0 {
+            1.0
+        } else {
+            self.samples as f32 / self.target as f32
+        };
+        let timing = match self.converged_after {
+            Some(duration) => format!("Converged in {}", format_duration(duration)),
+            None => format!(
+                "{} elapsed, {} left",
+                format_duration(self.started.elapsed()),
+                self.eta().map_or("?".to_string(), format_duration)
+            ),
+        };
+
+        // Not interactable, so clicks and drags go through to the viewport
+        egui::Area::new("hud")
+            .anchor(egui::Align2::RIGHT_TOP, [-MARGIN, MARGIN])
+            .interactable(false)
+            .show(context, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.add(
+                        egui::ProgressBar::new(progress.min(1.0))
+                            .desired_width(200.0)
+                            .text(format!("{}/{} samples", self.samples, self.target)),
+                    );
+                    ui.label(timing);
+                });
+            });
+    }
+}
+
+/// `duration` as minutes and seconds, with hours in front once there are any.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
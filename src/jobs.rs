@@ -0,0 +1,166 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// A pool of worker threads for work that would otherwise freeze the UI, like decoding files.
+pub struct Jobs {
+    sender: mpsc::Sender<Task>,
+    running: Vec<Arc<Job>>,
+}
+
+/// The state of a job shared between the work and its [`JobHandle`].
+pub struct Job {
+    name: String,
+    /// The progress from zero to one, as the bits of an `f32`.
+    progress: AtomicU32,
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl Job {
+    pub fn set_progress(&self, progress: f32) {
+        self.progress
+            .store(progress.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    /// Whether the work should stop early, because nothing is waiting for its result anymore.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn is_finished(&self) -> bool {
+        // Pairs with the store after sending the result, so it can be taken once this is true
+        self.finished.load(Ordering::Acquire)
+    }
+}
+
+/// Receives the result of a job. Dropping the handle cancels the job.
+pub struct JobHandle<T> {
+    job: Arc<Job>,
+    result: mpsc::Receiver<T>,
+}
+
+impl<T> JobHandle<T> {
+    /// Returns the result if the job has finished, without waiting for it.
+    pub fn try_take(&self) -> Option<T> {
+        self.result.try_recv().ok()
+    }
+
+    /// Whether the job is done, with or without a result.
+    pub fn is_finished(&self) -> bool {
+        self.job.is_finished()
+    }
+}
+
+impl<T> Drop for JobHandle<T> {
+    fn drop(&mut self) {
+        self.job.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Jobs {
+    pub fn new() -> Self {
+        let workers =
+            thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).max(1));
+        let (sender, receiver) = mpsc::channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("Job worker {}", i))
+                .spawn(move || loop {
+                    let task = receiver.lock().unwrap().recv();
+                    match task {
+                        Ok(task) => task(),
+                        // The pool was dropped
+                        Err(_) => break,
+                    }
+                })
+                .unwrap();
+        }
+
+        Self {
+            sender,
+            running: Vec::new(),
+        }
+    }
+
+    /// Runs `work` on a worker thread. It gets the [`Job`] to report progress to and should
+    /// return `None` if it stops early because the job was cancelled.
+    pub fn spawn<T: Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        work: impl FnOnce(&Job) -> Option<T> + Send + 'static,
+    ) -> JobHandle<T> {
+        let job = Arc::new(Job {
+            name: name.into(),
+            progress: AtomicU32::new(0.0f32.to_bits()),
+            cancelled: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        });
+        let (sender, result) = mpsc::sync_channel(1);
+
+        let task_job = job.clone();
+        let task = Box::new(move || {
+            if !task_job.is_cancelled() {
+                // A panicking job shouldn't take a worker down with it
+                match panic::catch_unwind(AssertUnwindSafe(|| work(&task_job))) {
+                    Ok(Some(value)) => {
+                        let _ = sender.send(value);
+                    }
+                    Ok(None) => {}
+                    Err(_) => log::warn!("Job \"{}\" panicked", task_job.name),
+                }
+            }
+            task_job.finished.store(true, Ordering::Release);
+        });
+        self.sender.send(task).unwrap();
+
+        self.running.push(job.clone());
+        JobHandle { job, result }
+    }
+
+    /// Lists the running jobs with their progress and a button to cancel each.
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        self.running.retain(|job| !job.is_finished());
+
+        let title = format!("Jobs ({})", self.running.len());
+        egui::CollapsingHeader::new(title)
+            .id_source("Jobs")
+            .show(ui, |ui| {
+                if self.running.is_empty() {
+                    ui.label("Nothing running");
+                }
+
+                for job in &self.running {
+                    ui.horizontal(|ui| {
+                        ui.label(&job.name);
+                        ui.add(
+                            egui::ProgressBar::new(job.progress())
+                                .desired_width(120.0)
+                                .show_percentage(),
+                        );
+                        let cancelled = job.is_cancelled();
+                        if ui
+                            .add_enabled(!cancelled, egui::Button::new("Cancel"))
+                            .clicked()
+                        {
+                            job.cancelled.store(true, Ordering::Relaxed);
+                        }
+                    });
+                }
+            });
+    }
+}
@@ -1,29 +1,75 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+use std::path::PathBuf;
+
 use crate::app::App;
-use winit::{dpi::LogicalSize, event_loop::EventLoopBuilder, window::WindowBuilder};
+use winit::{
+    dpi::LogicalSize,
+    event_loop::EventLoopBuilder,
+    window::{Icon, WindowBuilder},
+};
 
 mod app;
+mod assets;
+mod command_palette;
+mod diagnostics;
+pub mod display_lut;
+mod environment_library;
+pub mod expression;
+mod frame_capture;
+pub mod geometry;
+mod gizmo;
+mod hotkeys;
+mod hud;
+mod jobs;
 mod model;
+pub mod naming;
+mod outlines;
+mod output_window;
+mod overlays;
+mod poly_haven;
 mod renderer;
-mod scene;
+pub mod scene;
+mod scene_library;
+pub mod shader_preprocessor;
 mod texture;
+mod thumbnails;
 mod ui;
 mod utils;
 
+const WINDOW_TITLE: &str = "Raytracer";
 const WINDOW_WIDTH: u32 = 1920;
 const WINDOW_HEIGHT: u32 = 1080;
 const MAX_NUMBER_OF_SPHERES: u32 = 256;
+const MAX_NUMBER_OF_LIGHTS: u32 = 64;
+/// Bounded by the instances and the BVH over them fitting in a uniform buffer.
+const MAX_NUMBER_OF_INSTANCES: u32 = 256;
+/// Bounded by the materials fitting in a uniform buffer. Objects with materials past it are drawn
+/// with the default one.
+const MAX_NUMBER_OF_MATERIALS: u32 = 256;
+/// Each is a layer of one texture, so the meshes past it are traced without theirs.
+const MAX_NUMBER_OF_CANVASES: u32 = 16;
 
-pub async fn run() {
+/// Opens the editor, on the scene file at `scene_path` if given.
+pub async fn run(scene_path: Option<PathBuf>) {
     env_logger::init();
 
     let event_loop = EventLoopBuilder::new().build();
     let window = WindowBuilder::new()
         .with_inner_size(LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
-        .with_title("Raytracer")
+        .with_title(WINDOW_TITLE)
+        .with_window_icon(window_icon())
         .build(&event_loop)
         .unwrap();
 
-    App::new(window).await.run(event_loop);
+    App::new(window, scene_path).await.run(event_loop);
+}
+
+fn window_icon() -> Option<Icon> {
+    let image = image::load_from_memory(include_bytes!("../assets/icon.png"))
+        .ok()?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+
+    Icon::from_rgba(image.into_raw(), width, height).ok()
 }
@@ -1,5 +1,9 @@
+use std::{env, path::PathBuf};
+
 use pathtracer::run;
 
 fn main() {
-    pollster::block_on(run());
+    // The scene file to open, such as a `.pbrt` scene generated by a script
+    let scene_path = env::args_os().nth(1).map(PathBuf::from);
+    pollster::block_on(run(scene_path));
 }
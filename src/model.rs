@@ -3,15 +3,13 @@ use std::{
     io::{BufReader, Cursor},
 };
 
-use cgmath::Vector3;
-use wgpu::Texture;
-
-use crate::{scene::Material, texture::Texture2D};
+use crate::{geometry::Primitive, scene::MaterialId};
+use cgmath::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct Model {
     pub meshes: Vec<Mesh>,
-    pub materials: Vec<DiffuseTexture>,
 }
 
 #[repr(C)]
@@ -22,7 +20,7 @@ struct Vertex {
     normal: [f32; 3],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Triangle {
     pub a: Vector3<f32>,
     pub b: Vector3<f32>,
@@ -30,21 +28,25 @@ pub struct Triangle {
     pub na: Vector3<f32>,
     pub nb: Vector3<f32>,
     pub nc: Vector3<f32>,
-    pub albedo: Vector3<f32>,
-    pub material: Material,
+    /// Texture coordinates at `a`, `b` and `c`.
+    pub ta: Vector2<f32>,
+    pub tb: Vector2<f32>,
+    pub tc: Vector2<f32>,
+    /// Which of the scene's materials the triangle is made of.
+    pub material: MaterialId,
 }
 
-impl Triangle {
-    pub fn vertices(&self) -> [Vector3<f32>; 3] {
+impl Primitive for Triangle {
+    fn vertices(&self) -> [Vector3<f32>; 3] {
         [self.a, self.b, self.c]
     }
 
-    pub fn centroid(&self) -> [f32; 3] {
-        [
-            (self.a[0] + self.b[0] + self.c[0]) / 3.0,
-            (self.a[1] + self.b[1] + self.c[1]) / 3.0,
-            (self.a[2] + self.b[2] + self.c[2]) / 3.0,
-        ]
+    fn normals(&self) -> [Vector3<f32>; 3] {
+        [self.na, self.nb, self.nc]
+    }
+
+    fn uvs(&self) -> [Vector2<f32>; 3] {
+        [self.ta, self.tb, self.tc]
     }
 }
 
@@ -52,7 +54,7 @@ impl Triangle {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct TriangleBuffer {
     a: [f32; 3],
-    _pad0: f32,
+    material: u32,
     b: [f32; 3],
     _pad1: f32,
     c: [f32; 3],
@@ -63,8 +65,10 @@ pub struct TriangleBuffer {
     _pad4: f32,
     nc: [f32; 3],
     _pad5: f32,
-    albedo: [f32; 3],
-    material: u32,
+    ta: [f32; 2],
+    tb: [f32; 2],
+    tc: [f32; 2],
+    _pad6: [f32; 2],
 }
 
 impl From<&Triangle> for TriangleBuffer {
@@ -76,88 +80,44 @@ impl From<&Triangle> for TriangleBuffer {
             na: triangle.na.into(),
             nb: triangle.nb.into(),
             nc: triangle.nc.into(),
-            albedo: triangle.albedo.into(),
-            material: match triangle.material {
-                Material::Diffuse => 0,
-                Material::Metal => 1,
-                Material::Dielectric => 2,
-                Material::Gizmo => 3,
-            },
-            _pad0: 0.0,
+            ta: triangle.ta.into(),
+            tb: triangle.tb.into(),
+            tc: triangle.tc.into(),
+            material: triangle.material.shader_index(),
             _pad1: 0.0,
             _pad2: 0.0,
             _pad3: 0.0,
             _pad4: 0.0,
             _pad5: 0.0,
+            _pad6: [0.0; 2],
         }
     }
 }
 
-#[derive(Debug)]
-pub struct DiffuseTexture {
-    pub name: String,
-    pub diffuse_texture: Texture,
-    // pub bind_group: wgpu::BindGroup,
-}
-
 #[derive(Debug)]
 pub struct Mesh {
-    pub name: String,
     pub triangles: Vec<Triangle>,
-    pub material: usize,
 }
 
 impl Model {
-    pub fn from_obj(
-        file_path: &str,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        // layout: &wgpu::BindGroupLayout,
-    ) -> Result<Self, std::io::Error> {
+    /// Every triangle is made of `material`.
+    pub fn from_obj(file_path: &str, material: MaterialId) -> Result<Self, std::io::Error> {
         let obj_text = fs::read_to_string(file_path)?;
         let obj_cursor = Cursor::new(obj_text);
         let mut obj_reader = BufReader::new(obj_cursor);
 
-        let (models, obj_materials) = tobj::load_obj_buf(
+        // The OBJ's own materials are ignored, so its MTL file isn't read
+        let (models, _) = tobj::load_obj_buf(
             &mut obj_reader,
             &tobj::LoadOptions {
                 triangulate: true,
                 single_index: true,
                 ..Default::default()
             },
-            |_| {
-                let mat_text = fs::read_to_string(file_path).unwrap();
-                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
-            },
+            |_| Err(tobj::LoadError::OpenFileFailed),
         )
         .unwrap();
 
-        let mut materials = Vec::new();
-        for material in obj_materials.unwrap() {
-            let diffuse_texture =
-                Texture2D::from_file(&material.diffuse_texture.unwrap(), device, queue).unwrap();
-            // let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            //     layout,
-            //     entries: &[
-            //         wgpu::BindGroupEntry {
-            //             binding: 0,
-            //             resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-            //         },
-            //         wgpu::BindGroupEntry {
-            //             binding: 1,
-            //             resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-            //         },
-            //     ],
-            //     label: None,
-            // });
-
-            materials.push(DiffuseTexture {
-                name: material.name,
-                diffuse_texture: diffuse_texture.texture,
-                // bind_group,
-            })
-        }
-
         let meshes = models
             .into_iter()
             .map(|model| {
@@ -196,20 +156,26 @@ impl Model {
                             model.mesh.normals[chunk[2] as usize * 3 + 1],
                             model.mesh.normals[chunk[2] as usize * 3 + 2],
                         ),
-                        albedo: Vector3::new(1.0, 1.0, 1.0),
-                        material: Material::Diffuse,
+                        ta: tex_coords(&model.mesh, chunk[0]),
+                        tb: tex_coords(&model.mesh, chunk[1]),
+                        tc: tex_coords(&model.mesh, chunk[2]),
+                        material,
                     })
                     .collect::<Vec<_>>();
 
-                Mesh {
-                    name: file_path.to_string(),
-                    triangles,
-                    material: model.mesh.material_id.unwrap_or(0),
-                }
+                Mesh { triangles }
             })
             .collect::<Vec<_>>();
 
-        Ok(Model { meshes, materials })
+        Ok(Model { meshes })
     }
 }
 
+/// The texture coordinates of a vertex, or zero if the mesh has none.
+fn tex_coords(mesh: &tobj::Mesh, index: u32) -> Vector2<f32> {
+    let index = index as usize * 2;
+    match mesh.texcoords.get(index..index + 2) {
+        Some(&[u, v]) => Vector2::new(u, v),
+        _ => Vector2::new(0.0, 0.0),
+    }
+}
@@ -0,0 +1,56 @@
+//! Unique, readable names for the objects in a scene, which stay the same as long as the object
+//! is kept, so anything referring to objects can do so by name.
+//!
+//! A name that's already taken gets the lowest free number added to it, so adding spheres
+//! gives `Sphere`, `Sphere.001`, `Sphere.002` and so on, and copying `Sphere.001` gives the
+//! next free `Sphere` number rather than `Sphere.001.001`.
+
+use std::collections::HashSet;
+
+/// The set of names taken so far, handing out new ones that don't collide with them.
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    taken: HashSet<String>,
+}
+
+impl Names {
+    pub fn new<S: Into<String>>(taken: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            taken: taken.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.taken.contains(name)
+    }
+
+    /// Takes `base` if it's free, or else its stem numbered with the lowest free number.
+    pub fn unique(&mut self, base: &str) -> String {
+        if !self.taken.contains(base) {
+            self.taken.insert(base.to_string());
+            return base.to_string();
+        }
+
+        let stem = stem(base);
+        let name = (1..)
+            .map(|number| format!("{}.{:03}", stem, number))
+            .find(|name| !self.taken.contains(name))
+            .expect("there is always a free number");
+        self.taken.insert(name.clone());
+        name
+    }
+}
+
+/// `name` without the number [`Names::unique`] adds to it, if it has one.
+pub fn stem(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((stem, number))
+            if !stem.is_empty()
+                && number.len() >= 3
+                && number.bytes().all(|byte| byte.is_ascii_digit()) =>
+        {
+            stem
+        }
+        _ => name,
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+use uuid::Uuid;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    overlays::color32,
+    scene::{Camera, Mesh, Scene},
+};
+
+/// How many segments a sphere's outline is drawn with.
+const SPHERE_SEGMENTS: usize = 64;
+/// The width of the selection's outline in points.
+const SELECTED_WIDTH: f32 = 2.0;
+/// The width of the hovered object's outline in points, drawn fainter than the selection's.
+const HOVERED_WIDTH: f32 = 1.0;
+
+/// Outlines of the selected and hovered objects, drawn over the render rather than traced into
+/// it so they don't show up in reflections, shadows or saved images.
+pub struct Outlines {
+    /// The edges of each mesh by UUID, along with how many triangles they were found for so an
+    /// edited mesh's are found again.
+    edges: HashMap<Uuid, (usize, Vec<Edge>)>,
+}
+
+/// An edge shared by two of a mesh's triangles, or on its border if only the first has it.
+struct Edge {
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+    triangles: (usize, Option<usize>),
+}
+
+impl Outlines {
+    pub fn new() -> Self {
+        Self {
+            edges: HashMap::new(),
+        }
+    }
+
+    /// Outlines the objects selected in `scene` in `highlight`, and the `hovered` one more
+    /// faintly, as seen through `camera` on a viewport of `screen_size` physical pixels.
+    pub fn draw(
+        &mut self,
+        context: &egui::Context,
+        scene: &Scene,
+        camera: &Camera,
+        screen_size: PhysicalSize<u32>,
+        hovered: Option<Uuid>,
+        highlight: Vector3<f32>,
+    ) {
+        self.edges
+            .retain(|uuid, _| scene.meshes().iter().any(|mesh| mesh.uuid == *uuid));
+        if screen_size.width == 0 || screen_size.height == 0 {
+            return;
+        }
+
+        let painter = context.layer_painter(egui::LayerId::background());
+        let pixels_per_point = context.pixels_per_point();
+        let project = |point: Vector3<f32>| {
+            camera.world_to_screen(point, screen_size).map(|position| {
+                egui::pos2(position.x / pixels_per_point, position.y / pixels_per_point)
+            })
+        };
+        let stroke = |uuid: Uuid| {
+            if scene.is_selected(uuid) {
+                Some(egui::Stroke::new(SELECTED_WIDTH, color32(highlight)))
+            } else if hovered == Some(uuid) {
+                let color = color32(highlight).gamma_multiply(0.6);
+                Some(egui::Stroke::new(HOVERED_WIDTH, color))
+            } else {
+                None
+            }
+        };
+
+        for sphere in scene.spheres() {
+            let Some(stroke) = stroke(sphere.id.0) else {
+                continue;
+            };
+            let (center, radius) = (sphere.transform.position, sphere.shape.radius);
+            let Some(outline) = sphere_outline(center, radius, camera.origin) else {
+                continue;
+            };
+            // Outlines crossing behind the camera are left out rather than clipped
+            let points: Option<Vec<_>> = outline.map(project).collect();
+            if let Some(points) = points {
+                painter.add(egui::Shape::closed_line(points, stroke));
+            }
+        }
+
+        for instance in scene.instances() {
+            let Some(stroke) = stroke(instance.id.0) else {
+                continue;
+            };
+            let mesh = &scene.meshes()[instance.mesh.mesh];
+            let object_to_world = instance.transform.object_to_world();
+            let world_to_object = instance.transform.world_to_object();
+            let eye = (world_to_object * camera.origin.extend(1.0)).truncate();
+            // Only compared with each other, so mirroring the mesh doesn't matter
+            let facing = mesh
+                .triangles
+                .iter()
+                .map(|t| (t.b - t.a).cross(t.c - t.a).dot(t.a - eye) < 0.0)
+                .collect::<Vec<_>>();
+            for edge in self.edges(mesh) {
+                let silhouette = match edge.triangles {
+                    (first, Some(second)) => facing[first] != facing[second],
+                    (_, None) => true,
+                };
+                if !silhouette {
+                    continue;
+                }
+                let [a, b] =
+                    [edge.a, edge.b].map(|point| (object_to_world * point.extend(1.0)).truncate());
+                if let (Some(a), Some(b)) = (project(a), project(b)) {
+                    painter.line_segment([a, b], stroke);
+                }
+            }
+        }
+    }
+
+    /// The edges of `mesh`, found again if its triangles have changed since they last were.
+    fn edges(&mut self, mesh: &Mesh) -> &[Edge] {
+        let (count, edges) = self.edges.entry(mesh.uuid).or_default();
+        if *count != mesh.triangles.len() {
+            *count = mesh.triangles.len();
+            *edges = mesh_edges(mesh);
+        }
+        edges
+    }
+}
+
+/// Pairs up the triangles of `mesh` sharing each edge by where its ends are, since meshes don't
+/// index their vertices. Edges of more than two triangles keep the first two.
+fn mesh_edges(mesh: &Mesh) -> Vec<Edge> {
+    let key = |point: Vector3<f32>| [point.x, point.y, point.z].map(f32::to_bits);
+    let mut edges = HashMap::new();
+    for (index, triangle) in mesh.triangles.iter().enumerate() {
+        let sides = [
+            (triangle.a, triangle.b),
+            (triangle.b, triangle.c),
+            (triangle.c, triangle.a),
+        ];
+        for (a, b) in sides {
+            let ends = if key(a) < key(b) {
+                (key(a), key(b))
+            } else {
+                (key(b), key(a))
+            };
+            let edge = edges.entry(ends).or_insert(Edge {
+                a,
+                b,
+                triangles: (index, None),
+            });
+            if edge.triangles.0 != index && edge.triangles.1.is_none() {
+                edge.triangles.1 = Some(index);
+            }
+        }
+    }
+    edges.into_values().collect()
+}
+
+/// Points around the circle where rays from `eye` graze the sphere at `center`, or `None` if
+/// `eye` is inside it.
+fn sphere_outline(
+    center: Vector3<f32>,
+    radius: f32,
+    eye: Vector3<f32>,
+) -> Option<impl Iterator<Item = Vector3<f32>>> {
+    let offset = center - eye;
+    let distance_squared = offset.magnitude2();
+    let radius_squared = radius * radius;
+    if distance_squared <= radius_squared {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    let axis = offset / distance;
+    // The grazing rays touch the sphere short of its center, on a circle smaller than it
+    let circle_center = center - axis * (radius_squared / distance);
+    let circle_radius = radius * (distance_squared - radius_squared).sqrt() / distance;
+    let helper = if axis.y.abs() < 0.9 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_x()
+    };
+    let u = axis.cross(helper).normalize();
+    let v = axis.cross(u);
+    Some((0..SPHERE_SEGMENTS).map(move |i| {
+        let angle = i as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+        circle_center + (u * angle.cos() + v * angle.sin()) * circle_radius
+    }))
+}
@@ -0,0 +1,90 @@
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder},
+};
+
+/// A borderless window the render output can be detached into, e.g. to show
+/// it full size on a second monitor while the controls stay in the main window.
+pub struct OutputWindow {
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    window: Window,
+}
+
+impl OutputWindow {
+    /// Opens the window on a monitor other than the main window's if there is one.
+    pub fn new(
+        target: &EventLoopWindowTarget<()>,
+        main_window: &Window,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Option<Self> {
+        let other_monitor = target
+            .available_monitors()
+            .find(|monitor| Some(monitor) != main_window.current_monitor().as_ref());
+
+        let builder = WindowBuilder::new()
+            .with_title("Render output")
+            .with_decorations(false)
+            .with_window_icon(crate::window_icon());
+        let builder = match other_monitor {
+            Some(monitor) => builder
+                .with_position(monitor.position())
+                .with_inner_size(monitor.size()),
+            None => builder.with_inner_size(main_window.inner_size()),
+        };
+        let window = builder.build(target).ok()?;
+
+        let surface = unsafe { instance.create_surface(&window) }.ok()?;
+        if !surface.get_capabilities(adapter).formats.contains(&format) {
+            log::warn!("The output window's surface doesn't support {:?}", format);
+            return None;
+        }
+
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+        surface.configure(device, &config);
+
+        Some(Self {
+            surface,
+            config,
+            window,
+        })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(device, &self.config);
+        }
+    }
+
+    pub fn get_current_texture(
+        &mut self,
+        device: &wgpu::Device,
+    ) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.resize(device, self.window.inner_size());
+                self.surface.get_current_texture()
+            }
+            result => result,
+        }
+    }
+}
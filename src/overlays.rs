@@ -0,0 +1,175 @@
+use cgmath::Vector3;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    geometry::Aabb,
+    scene::{Camera, LightKind, Scene},
+};
+
+/// The radius of a point light's icon in points.
+const LIGHT_ICON_RADIUS: f32 = 6.0;
+
+/// Editor drawings over the viewport, culled against the camera's frustum on the CPU so drawing
+/// them costs as much as what's in view rather than the whole scene.
+pub struct Overlays {
+    pub show_lights: bool,
+    pub show_bounds: bool,
+    /// How many overlays were drawn and culled in the last frame.
+    drawn: usize,
+    culled: usize,
+}
+
+impl Overlays {
+    pub fn new() -> Self {
+        Self {
+            show_lights: true,
+            show_bounds: false,
+            drawn: 0,
+            culled: 0,
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Overlays", |ui| {
+            ui.checkbox(&mut self.show_lights, "light icons")
+                .on_hover_text("Mark where the point and quad lights are");
+            ui.checkbox(&mut self.show_bounds, "bounding boxes")
+                .on_hover_text("Outline the bounds of every sphere and the mesh");
+            ui.label(format!(
+                "{} drawn, {} outside the view",
+                self.drawn, self.culled
+            ));
+        });
+    }
+
+    /// Draws the overlays for `scene` seen through `camera` behind the rest of the UI, on a
+    /// viewport of `screen_size` physical pixels.
+    pub fn draw(
+        &mut self,
+        context: &egui::Context,
+        scene: &Scene,
+        camera: &Camera,
+        screen_size: PhysicalSize<u32>,
+        highlight: Vector3<f32>,
+    ) {
+        self.drawn = 0;
+        self.culled = 0;
+        if screen_size.width == 0 || screen_size.height == 0 {
+            return;
+        }
+
+        let painter = context.layer_painter(egui::LayerId::background());
+        let pixels_per_point = context.pixels_per_point();
+        let frustum = camera.frustum(screen_size);
+        let project = |point: Vector3<f32>| {
+            camera.world_to_screen(point, screen_size).map(|position| {
+                egui::pos2(position.x / pixels_per_point, position.y / pixels_per_point)
+            })
+        };
+
+        if self.show_bounds {
+            let stroke = egui::Stroke::new(1.0, color32(highlight));
+            let spheres = scene
+                .spheres()
+                .into_iter()
+                .map(|sphere| sphere.shape.bounds(sphere.transform.position));
+            let instances = scene
+                .instances()
+                .into_iter()
+                .map(|instance| {
+                    let mesh = &scene.meshes()[instance.mesh.mesh];
+                    instance.transform.world_bounds(mesh)
+                })
+                .filter(|aabb| !aabb.is_empty());
+            for aabb in spheres.chain(instances) {
+                if !self.count(frustum.intersects_aabb(&aabb)) {
+                    continue;
+                }
+                for [a, b] in box_edges(&aabb) {
+                    // Edges crossing behind the camera are left out rather than clipped
+                    if let (Some(a), Some(b)) = (project(a), project(b)) {
+                        painter.line_segment([a, b], stroke);
+                    }
+                }
+            }
+        }
+
+        if self.show_lights {
+            let outline = egui::Stroke::new(1.5, color32(highlight));
+            for light in scene.lights().into_iter().map(|light| light.light) {
+                match light.kind {
+                    LightKind::Point => {
+                        // Tested with a radius, so icons half past the edges are still drawn
+                        if !self.count(frustum.intersects_sphere(light.position, 0.1)) {
+                            continue;
+                        }
+                        if let Some(center) = project(light.position) {
+                            painter.circle(
+                                center,
+                                LIGHT_ICON_RADIUS,
+                                color32(light.color),
+                                outline,
+                            );
+                        }
+                    }
+                    LightKind::Quad => {
+                        let corners = light.quad().corners();
+                        if !self.count(frustum.intersects_aabb(&Aabb::from_points(corners))) {
+                            continue;
+                        }
+                        let points: Option<Vec<_>> = corners.into_iter().map(project).collect();
+                        if let Some(points) = points {
+                            painter.add(egui::Shape::closed_line(points, outline));
+                        }
+                    }
+                    LightKind::Directional => {}
+                }
+            }
+        }
+    }
+
+    /// Counts an overlay as drawn or culled, returning whether it's `visible`.
+    fn count(&mut self, visible: bool) -> bool {
+        if visible {
+            self.drawn += 1;
+        } else {
+            self.culled += 1;
+        }
+        visible
+    }
+}
+
+/// The twelve edges of `aabb`.
+fn box_edges(aabb: &Aabb) -> impl Iterator<Item = [Vector3<f32>; 2]> + '_ {
+    let corner = |index: usize| {
+        Vector3::new(
+            if index & 1 == 0 {
+                aabb.min.x
+            } else {
+                aabb.max.x
+            },
+            if index & 2 == 0 {
+                aabb.min.y
+            } else {
+                aabb.max.y
+            },
+            if index & 4 == 0 {
+                aabb.min.z
+            } else {
+                aabb.max.z
+            },
+        )
+    };
+    // Each corner connects to the ones that differ from it along one axis
+    (0..8).flat_map(move |index| {
+        [1, 2, 4]
+            .into_iter()
+            .filter(move |axis| index & axis == 0)
+            .map(move |axis| [corner(index), corner(index | axis)])
+    })
+}
+
+pub fn color32(color: Vector3<f32>) -> egui::Color32 {
+    let [r, g, b] = [color.x, color.y, color.z].map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+    egui::Color32::from_rgb(r, g, b)
+}
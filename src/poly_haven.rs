@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    assets,
+    jobs::{JobHandle, Jobs},
+};
+
+const API_URL: &str = "https://api.polyhaven.com";
+/// Poly Haven asks API users to identify themselves.
+const USER_AGENT: &str = concat!("pathtracer/", env!("CARGO_PKG_VERSION"));
+const RESOLUTIONS: [&str; 4] = ["1k", "2k", "4k", "8k"];
+
+/// An HDRI listed by the Poly Haven API.
+#[derive(Debug, Clone)]
+struct RemoteHdri {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct AssetInfo {
+    name: String,
+}
+
+/// The files of an HDRI by resolution and then format.
+#[derive(Deserialize)]
+struct Files {
+    hdri: HashMap<String, HashMap<String, FileInfo>>,
+}
+
+/// One format of an asset's files, e.g. the `hdr` of the `2k` resolution.
+#[derive(Deserialize)]
+struct FileInfo {
+    url: String,
+    size: u64,
+}
+
+/// Browses the HDRIs on [Poly Haven](https://polyhaven.com) and downloads them to a local cache,
+/// from which they're loaded like any other environment.
+pub struct PolyHaven {
+    hdris: Vec<RemoteHdri>,
+    listing: Option<JobHandle<io::Result<Vec<RemoteHdri>>>>,
+    /// Downloading an HDRI, along with its id.
+    download: Option<(String, JobHandle<io::Result<PathBuf>>)>,
+    filter: String,
+    resolution: usize,
+    error: Option<String>,
+}
+
+impl PolyHaven {
+    pub fn new() -> Self {
+        Self {
+            hdris: Vec::new(),
+            listing: None,
+            download: None,
+            filter: String::new(),
+            resolution: 0,
+            error: None,
+        }
+    }
+
+    /// Returns the path of an HDRI that has finished downloading, once.
+    pub fn update(&mut self) -> Option<PathBuf> {
+        if let Some(job) = &self.listing {
+            if let Some(result) = job.try_take() {
+                self.listing = None;
+                match result {
+                    Ok(hdris) => self.hdris = hdris,
+                    Err(e) => self.fail("list the HDRIs", e),
+                }
+            } else if job.is_finished() {
+                self.listing = None;
+            }
+        }
+
+        let (_, job) = self.download.as_ref()?;
+        let Some(result) = job.try_take() else {
+            if job.is_finished() {
+                self.download = None;
+            }
+            return None;
+        };
+        self.download = None;
+        match result {
+            Ok(path) => Some(path),
+            Err(e) => {
+                self.fail("download the HDRI", e);
+                None
+            }
+        }
+    }
+
+    /// Returns the path of a cached HDRI to switch to, if one was picked.
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, jobs: &mut Jobs) -> Option<PathBuf> {
+        let mut picked = None;
+
+        ui.horizontal(|ui| {
+            let fetching = self.listing.is_some();
+            if ui
+                .add_enabled(!fetching, egui::Button::new("Fetch HDRIs"))
+                .on_hover_text("List the HDRIs available on polyhaven.com")
+                .clicked()
+            {
+                self.error = None;
+                self.listing = Some(jobs.spawn("Listing Poly Haven HDRIs", |_| Some(list())));
+            }
+            if fetching {
+                ui.spinner();
+            }
+
+            egui::ComboBox::from_id_source("Poly Haven resolution")
+                .selected_text(RESOLUTIONS[self.resolution])
+                .show_ui(ui, |ui| {
+                    for (i, resolution) in RESOLUTIONS.iter().enumerate() {
+                        ui.selectable_value(&mut self.resolution, i, *resolution);
+                    }
+                });
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        if self.hdris.is_empty() {
+            return None;
+        }
+
+        ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Search"));
+        let filter = self.filter.to_lowercase();
+        let resolution = RESOLUTIONS[self.resolution];
+        let downloading = self.download.as_ref().map(|(id, _)| id.clone());
+
+        egui::ScrollArea::vertical()
+            .id_source("Poly Haven HDRIs")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for hdri in &self.hdris {
+                    if !hdri.name.to_lowercase().contains(&filter) {
+                        continue;
+                    }
+
+                    ui.horizontal(|ui| {
+                        let path = cache_path(&hdri.id, resolution);
+                        if downloading.as_ref() == Some(&hdri.id) {
+                            ui.spinner();
+                        } else if path.exists() {
+                            if ui.button("Use").clicked() {
+                                picked = Some(path);
+                            }
+                        } else if ui
+                            .add_enabled(downloading.is_none(), egui::Button::new("Download"))
+                            .clicked()
+                        {
+                            self.error = None;
+                            let id = hdri.id.clone();
+                            let name = format!("Downloading {} ({})", hdri.name, resolution);
+                            let job = jobs.spawn(name, move |job| {
+                                download(&id, resolution, path, |progress| {
+                                    job.set_progress(progress);
+                                    !job.is_cancelled()
+                                })
+                                .transpose()
+                            });
+                            self.download = Some((hdri.id.clone(), job));
+                        }
+                        ui.label(&hdri.name);
+                    });
+                }
+            });
+
+        picked
+    }
+
+    fn fail(&mut self, what: &str, error: io::Error) {
+        log::warn!("Failed to {} from Poly Haven: {}", what, error);
+        self.error = Some(format!("Failed to {}: {}", what, error));
+    }
+}
+
+/// Where the HDRI `id` is cached at `resolution`.
+fn cache_path(id: &str, resolution: &str) -> PathBuf {
+    cache_directory().join(format!("{}_{}.hdr", id, resolution))
+}
+
+fn cache_directory() -> PathBuf {
+    assets::cache_dir().join("polyhaven")
+}
+
+fn get(url: &str) -> io::Result<ureq::Response> {
+    ureq::get(url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .map_err(io::Error::other)
+}
+
+/// The HDRIs on Poly Haven, sorted by name.
+fn list() -> io::Result<Vec<RemoteHdri>> {
+    let assets: HashMap<String, AssetInfo> =
+        get(&format!("{}/assets?t=hdris", API_URL))?.into_json()?;
+    let mut hdris = assets
+        .into_iter()
+        .map(|(id, info)| RemoteHdri {
+            id,
+            name: info.name,
+        })
+        .collect::<Vec<_>>();
+    hdris.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(hdris)
+}
+
+/// Downloads the Radiance HDR file of `id` at `resolution` to `path`, reporting the progress to
+/// `progress` until it returns `false`, in which case the download stops with `Ok(None)`.
+fn download(
+    id: &str,
+    resolution: &str,
+    path: PathBuf,
+    mut progress: impl FnMut(f32) -> bool,
+) -> io::Result<Option<PathBuf>> {
+    let mut files: Files = get(&format!("{}/files/{}", API_URL, id))?.into_json()?;
+    let file = files
+        .hdri
+        .remove(resolution)
+        .and_then(|mut formats| formats.remove("hdr"))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no {} HDR file", id, resolution),
+            )
+        })?;
+
+    fs::create_dir_all(cache_directory())?;
+    // Written under another name first, so a cancelled download isn't mistaken for a cached one
+    let partial = path.with_extension("part");
+    let mut reader = get(&file.url)?.into_reader();
+    let mut writer = io::BufWriter::new(fs::File::create(&partial)?);
+    let mut buffer = vec![0; 64 * 1024];
+    let mut downloaded = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+        downloaded += read as u64;
+        if !progress(downloaded as f32 / file.size.max(1) as f32) {
+            drop(writer);
+            let _ = fs::remove_file(&partial);
+            return Ok(None);
+        }
+    }
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&partial, &path)?;
+    Ok(Some(path))
+}
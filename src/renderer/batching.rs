@@ -0,0 +1,264 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use wgpu::{Buffer, BufferUsages, CommandEncoder, Device, QuerySet, Queue};
+
+/// The most samples traced into the main viewport in one frame.
+pub const MAX_SAMPLES_PER_FRAME: u32 = 64;
+/// The most frames a sample is spread over when it takes longer than the budget.
+pub const MAX_BANDS: u32 = 32;
+/// How long tracing may take per frame by default, leaving the rest of a 60 frames per second
+/// frame for denoising, the UI and presenting.
+const DEFAULT_BUDGET: Duration = Duration::from_millis(12);
+
+/// Picks how many progressive samples to trace per frame, timing the batches on the GPU with
+/// timestamp queries so fast GPUs trace more of them within the frame-time budget, and slow ones
+/// split each sample into bands traced over several frames so the UI keeps up.
+pub struct SampleBatching {
+    /// Sizes the batches to the budget, otherwise always traces [`Self::fixed`].
+    pub adaptive: bool,
+    pub budget: Duration,
+    /// Also used where timestamps aren't supported.
+    pub fixed: Batch,
+    /// The batch picked from the last timing.
+    adaptive_batch: Batch,
+    /// `None` when timestamp queries aren't supported.
+    timer: Option<Timer>,
+    /// The samples traced between the timestamps of the frame being recorded.
+    batch: f32,
+    /// The samples of a frame being read back, and whether its timestamps are mapped yet.
+    in_flight: Option<(f32, Arc<AtomicBool>)>,
+    /// How long a sample took to trace in the last batch read back.
+    sample_time: Option<Duration>,
+}
+
+/// How much of the image to trace in a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batch {
+    /// Whole samples, or a single band of one if there's more than one.
+    pub samples: u32,
+    /// How many bands of rows each sample is split into.
+    pub bands: u32,
+}
+
+impl Batch {
+    pub const ONE_SAMPLE: Self = Self {
+        samples: 1,
+        bands: 1,
+    };
+
+    /// How many times to trace, each adding a sample or a band of one.
+    pub fn dispatches(&self) -> u32 {
+        if self.bands > 1 {
+            1
+        } else {
+            self.samples
+        }
+    }
+}
+
+struct Timer {
+    set: QuerySet,
+    resolve_buffer: Buffer,
+    read_back_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl SampleBatching {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let supported = device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        let buffer = |label, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: 2 * std::mem::size_of::<u64>() as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let timer = supported.then(|| Timer {
+            set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Sample Batch Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve_buffer: buffer(
+                "Sample Batch Timestamps Resolve Buffer",
+                BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            ),
+            read_back_buffer: buffer(
+                "Sample Batch Timestamps Read Back Buffer",
+                BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            ),
+            period: queue.get_timestamp_period(),
+        });
+
+        Self {
+            adaptive: supported,
+            budget: DEFAULT_BUDGET,
+            fixed: Batch::ONE_SAMPLE,
+            adaptive_batch: Batch::ONE_SAMPLE,
+            timer,
+            batch: 0.0,
+            in_flight: None,
+            sample_time: None,
+        }
+    }
+
+    /// What to trace this frame, after collecting the timing of an earlier one if it has arrived.
+    pub fn begin_frame(&mut self) -> Batch {
+        self.batch = 0.0;
+        self.collect();
+        if self.adaptive && self.timer.is_some() {
+            self.adaptive_batch
+        } else {
+            self.fixed
+        }
+    }
+
+    fn collect(&mut self) {
+        let (Some(timer), Some((samples, mapped))) = (&self.timer, &self.in_flight) else {
+            return;
+        };
+        if !mapped.load(Ordering::Acquire) {
+            return;
+        }
+
+        let slice = timer.read_back_buffer.slice(..);
+        let ticks: [u64; 2] = bytemuck::cast_slice(&slice.get_mapped_range())
+            .try_into()
+            .expect("Two timestamps");
+        timer.read_back_buffer.unmap();
+
+        let nanoseconds = ticks[1].saturating_sub(ticks[0]) as f64 * timer.period as f64;
+        let sample_time = Duration::from_nanos((nanoseconds / *samples as f64) as u64);
+        self.adaptive_batch = if sample_time > self.budget {
+            let bands = sample_time
+                .as_nanos()
+                .div_ceil(self.budget.as_nanos().max(1));
+            Batch {
+                samples: 1,
+                bands: (bands as u32).clamp(1, MAX_BANDS),
+            }
+        } else {
+            // At most doubles each time, so a misreading can't stall the next frames for long
+            let fitting = self.budget.as_nanos() / sample_time.as_nanos().max(1);
+            let most = MAX_SAMPLES_PER_FRAME.min(2 * samples.ceil() as u32);
+            Batch {
+                samples: (fitting as u32).clamp(1, most),
+                bands: 1,
+            }
+        };
+        self.sample_time = Some(sample_time);
+        self.in_flight = None;
+    }
+
+    /// Marks the start of the batch, before its first sample is traced.
+    pub fn begin(&self, encoder: &mut CommandEncoder) {
+        if let Some(timer) = self.timer() {
+            encoder.write_timestamp(&timer.set, 0);
+        }
+    }
+
+    /// Marks the end of a batch worth `samples`, which is only timed if there were any.
+    pub fn end(&mut self, encoder: &mut CommandEncoder, samples: f32) {
+        let Some(timer) = self.timer() else {
+            return;
+        };
+        if samples == 0.0 {
+            return;
+        }
+
+        encoder.write_timestamp(&timer.set, 1);
+        encoder.resolve_query_set(&timer.set, 0..2, &timer.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &timer.resolve_buffer,
+            0,
+            &timer.read_back_buffer,
+            0,
+            timer.resolve_buffer.size(),
+        );
+        self.batch = samples;
+    }
+
+    /// The timer while the read back buffer is free to time another batch.
+    fn timer(&self) -> Option<&Timer> {
+        self.timer.as_ref().filter(|_| self.in_flight.is_none())
+    }
+
+    /// Starts reading back the timestamps, once the frame that resolved them has been submitted.
+    pub fn read_back(&mut self) {
+        let Some(timer) = &self.timer else {
+            return;
+        };
+        if self.batch == 0.0 || self.in_flight.is_some() {
+            return;
+        }
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let on_mapped = mapped.clone();
+        timer
+            .read_back_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if let Err(e) = result {
+                    log::warn!("Failed to read back timestamps: {}", e);
+                    return;
+                }
+                on_mapped.store(true, Ordering::Release);
+            });
+        self.in_flight = Some((self.batch, mapped));
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled(
+            self.timer.is_some(),
+            egui::Checkbox::new(&mut self.adaptive, "adaptive samples per frame"),
+        )
+        .on_hover_text(
+            "Trace as many samples each frame as fit in the frame budget, so fast GPUs converge \
+            sooner, or spread samples that don't fit over several frames",
+        )
+        .on_disabled_hover_text("The GPU doesn't support timestamp queries");
+
+        if self.adaptive && self.timer.is_some() {
+            let mut budget = self.budget.as_secs_f32() * 1000.0;
+            if ui
+                .add(egui::Slider::new(&mut budget, 1.0..=100.0).text("frame budget (ms)"))
+                .changed()
+            {
+                self.budget = Duration::from_secs_f32(budget / 1000.0);
+            }
+            let batch = match self.adaptive_batch {
+                Batch { samples, bands: 1 } => format!("{} samples per frame", samples),
+                Batch { bands, .. } => format!("1 sample over {} frames", bands),
+            };
+            match self.sample_time {
+                Some(sample_time) => ui.label(format!(
+                    "{} at {:.2} ms per sample",
+                    batch,
+                    sample_time.as_secs_f64() * 1000.0
+                )),
+                None => ui.label(batch),
+            };
+        } else {
+            ui.add(
+                egui::Slider::new(&mut self.fixed.samples, 1..=MAX_SAMPLES_PER_FRAME)
+                    .logarithmic(true)
+                    .text("samples per frame"),
+            );
+            ui.add(
+                egui::Slider::new(&mut self.fixed.bands, 1..=MAX_BANDS).text("bands per sample"),
+            )
+            .on_hover_text(
+                "Split each sample into this many bands of rows, traced one per frame, so \
+                    the UI stays responsive while a sample takes longer than a frame",
+            );
+        }
+    }
+}
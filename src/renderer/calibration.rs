@@ -0,0 +1,112 @@
+use std::{fs, io, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::assets;
+
+use super::{permutations::WorkgroupSize, upscaler::MIN_RENDER_SCALE};
+
+/// The file the calibration is kept in, under [`assets::config_dir`].
+const CALIBRATION_FILE_NAME: &str = "calibration.json";
+/// The longest a frame can take to trace and still feel interactive, at 60 frames per second.
+pub const FRAME_BUDGET: Duration = Duration::from_micros(16_667);
+/// The candidates timed, besides every [`WorkgroupSize`].
+pub const RENDER_SCALES: [f32; 3] = [1.0, 0.75, MIN_RENDER_SCALE];
+pub const SAMPLES_PER_PIXEL: [u32; 3] = [1, 2, 4];
+/// The frames timed for each candidate, after one more to warm up.
+pub const TIMED_FRAMES: u32 = 4;
+
+/// The interactive settings picked for a GPU by timing a few candidates on it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// The name of the GPU it was measured on, so another one is calibrated afresh.
+    pub adapter: String,
+    pub workgroup_size: WorkgroupSize,
+    pub render_scale: f32,
+    pub samples_per_pixel: u32,
+    /// How long a frame took to trace with these settings.
+    pub frame_time: Duration,
+}
+
+/// How long a frame took to trace at a render scale and number of samples per pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    pub render_scale: f32,
+    pub samples_per_pixel: u32,
+    pub frame_time: Duration,
+}
+
+impl Timing {
+    /// The samples per pixel of the output, which is what the settings are ranked by.
+    fn quality(&self) -> f32 {
+        self.render_scale * self.render_scale * self.samples_per_pixel as f32
+    }
+}
+
+impl Calibration {
+    /// Takes the timing with the most samples per output pixel within [`FRAME_BUDGET`],
+    /// preferring the sharper of two equal ones, or the quickest if none keep up.
+    pub fn pick(
+        adapter: String,
+        workgroup_size: WorkgroupSize,
+        timings: &[Timing],
+    ) -> Option<Self> {
+        let within_budget = timings
+            .iter()
+            .filter(|timing| timing.frame_time <= FRAME_BUDGET)
+            .max_by(|a, b| {
+                a.quality()
+                    .total_cmp(&b.quality())
+                    .then(a.render_scale.total_cmp(&b.render_scale))
+            });
+        let timing = within_budget.or_else(|| timings.iter().min_by_key(|t| t.frame_time))?;
+
+        Some(Self {
+            adapter,
+            workgroup_size,
+            render_scale: timing.render_scale,
+            samples_per_pixel: timing.samples_per_pixel,
+            frame_time: timing.frame_time,
+        })
+    }
+
+    /// Reads the saved calibration if it was made on `adapter`.
+    pub fn load(adapter: &str) -> Option<Self> {
+        let path = path()?;
+        let calibration: Self = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| log::warn!("Ignoring invalid {}: {}", path.display(), e))
+                .ok()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        (calibration.adapter == adapter).then_some(calibration)
+    }
+
+    pub fn save(&self) {
+        let Some(path) = path() else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                let json = serde_json::to_vec_pretty(self)?;
+                fs::write(&path, json)
+            });
+        if let Err(e) = result {
+            log::warn!(
+                "Failed to save the calibration to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    assets::config_dir().map(|directory| directory.join(CALIBRATION_FILE_NAME))
+}
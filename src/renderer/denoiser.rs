@@ -0,0 +1,473 @@
+use wgpu::{util::DeviceExt, BindGroupLayout, Buffer, BufferUsages, CommandEncoder, Device};
+
+use crate::{
+    scene::{Camera, CameraBuffer},
+    utils::{ShaderCache, ShaderError},
+};
+
+use super::{
+    permutations::PayloadFormat,
+    profiler::Profiler,
+    tracked_buffer::TrackedBuffer,
+    uploader::Uploader,
+    viewport::{ResolveBuffer, Viewport},
+};
+
+const MAX_ITERATIONS: u32 = 5;
+
+/// A spatiotemporal variance-guided filter (SVGF) for the main viewport's image, which turns the
+/// few samples traced while moving into a usable preview.
+///
+/// Each frame is blended with the previous one where the same surfaces are visible, then
+/// filtered a few times with an edge-aware blur that reaches twice as far each time. The image
+/// after the first blur becomes the history for the next frame.
+pub struct Denoiser {
+    pub enabled: bool,
+    /// How many times the image is blurred.
+    pub iterations: u32,
+    /// The format the pipelines read the G-buffer and pass colors in.
+    payload: PayloadFormat,
+    pipeline_layout: wgpu::PipelineLayout,
+    temporal_pipeline: wgpu::ComputePipeline,
+    variance_pipeline: wgpu::ComputePipeline,
+    atrous_pipeline: wgpu::ComputePipeline,
+    frame_bind_group_layout: BindGroupLayout,
+    pass_bind_group_layout: BindGroupLayout,
+    params_buffer: TrackedBuffer<DenoiseParams>,
+    resolve_buffer: TrackedBuffer<ResolveBuffer>,
+    targets: Targets,
+    /// The camera and render size of the last frame that was denoised, which the history is for.
+    previous_frame: Option<(CameraBuffer, (u32, u32))>,
+}
+
+/// The buffers the size of the viewport that the passes read and write.
+struct Targets {
+    previous_gbuffer: Buffer,
+    history_color: Buffer,
+    history_moments: Buffer,
+    moments: Buffer,
+    temporal_bind_group: wgpu::BindGroup,
+    variance_bind_group: wgpu::BindGroup,
+    /// One for each iteration of the blur.
+    atrous_bind_groups: Vec<wgpu::BindGroup>,
+    /// Reading the output of an odd and an even number of blurs.
+    copy_bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl Denoiser {
+    /// `width` and `height` are the size of the viewport being denoised.
+    pub fn new(
+        device: &Device,
+        shaders: &mut ShaderCache,
+        copy_bind_group_layout: &BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Denoiser Frame Bind Group Layout"),
+                entries: &[
+                    // Cameras and size
+                    uniform(0),
+                    // Accumulated samples
+                    storage(1, true),
+                    // G-buffer
+                    storage(2, true),
+                    // Previous G-buffer
+                    storage(3, true),
+                    // History color, written by the first blur
+                    storage(4, false),
+                    // History moments
+                    storage(5, true),
+                    // Moments
+                    storage(6, false),
+                ],
+            });
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Denoiser Pass Bind Group Layout"),
+                entries: &[
+                    // Step width and whether it's the last pass
+                    uniform(0),
+                    // Source
+                    storage(1, true),
+                    // Destination
+                    storage(2, false),
+                ],
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Denoiser Pipeline Layout"),
+            bind_group_layouts: &[&frame_bind_group_layout, &pass_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let [temporal_pipeline, variance_pipeline, atrous_pipeline] =
+            create_pipelines(device, shaders, &pipeline_layout, PayloadFormat::Full)
+                .unwrap_or_else(|error| panic!("{}", error));
+
+        let resolve_buffer =
+            TrackedBuffer::new(device, "Denoiser Resolve Buffer", BufferUsages::UNIFORM);
+        let targets = Targets::new(
+            device,
+            &pass_bind_group_layout,
+            copy_bind_group_layout,
+            &resolve_buffer.buffer,
+            width,
+            height,
+        );
+
+        Self {
+            enabled: false,
+            iterations: 4,
+            payload: PayloadFormat::Full,
+            pipeline_layout,
+            temporal_pipeline,
+            variance_pipeline,
+            atrous_pipeline,
+            frame_bind_group_layout,
+            pass_bind_group_layout,
+            params_buffer: TrackedBuffer::new(
+                device,
+                "Denoiser Params Buffer",
+                BufferUsages::UNIFORM,
+            ),
+            resolve_buffer,
+            targets,
+            previous_frame: None,
+        }
+    }
+
+    /// Recreates the buffers for a viewport of `width` x `height`, dropping the history.
+    pub fn resize(
+        &mut self,
+        device: &Device,
+        copy_bind_group_layout: &BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) {
+        self.targets = Targets::new(
+            device,
+            &self.pass_bind_group_layout,
+            copy_bind_group_layout,
+            &self.resolve_buffer.buffer,
+            width,
+            height,
+        );
+        self.previous_frame = None;
+    }
+
+    /// The format the G-buffer must be written in for the denoiser to read it.
+    pub fn payload(&self) -> PayloadFormat {
+        self.payload
+    }
+
+    /// Switches the passes to `payload`, dropping the history that was stored in the old format.
+    /// The old format is kept if the shaders for the new one don't compile.
+    pub fn set_payload(
+        &mut self,
+        device: &Device,
+        shaders: &mut ShaderCache,
+        payload: PayloadFormat,
+    ) {
+        if payload == self.payload {
+            return;
+        }
+        match create_pipelines(device, shaders, &self.pipeline_layout, payload) {
+            Ok([temporal, variance, atrous]) => {
+                self.temporal_pipeline = temporal;
+                self.variance_pipeline = variance;
+                self.atrous_pipeline = atrous;
+                self.payload = payload;
+                self.previous_frame = None;
+            }
+            Err(error) => log::warn!("{}", error),
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Denoising", |ui| {
+            ui.checkbox(&mut self.enabled, "enabled").on_hover_text(
+                "Blend in previous frames and blur away noise while keeping edges (SVGF)",
+            );
+            ui.add_enabled(
+                self.enabled,
+                egui::Slider::new(&mut self.iterations, 1..=MAX_ITERATIONS).text("iterations"),
+            );
+        });
+
+        if !self.enabled {
+            self.reset_history();
+        }
+    }
+
+    /// Forgets the previous frame, so the next one denoised isn't blended with it.
+    pub fn reset_history(&mut self) {
+        self.previous_frame = None;
+    }
+
+    /// Filters the image `viewport` shows at `render_size`, as seen from `camera`. The result is
+    /// resolved with [`Self::copy_bind_group`] instead of the viewport's.
+    #[allow(clippy::too_many_arguments)]
+    pub fn denoise(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        viewport: &Viewport,
+        camera: &Camera,
+        render_size: (u32, u32),
+        profiler: &mut Profiler,
+    ) {
+        let camera = CameraBuffer::from(camera);
+        let previous = self
+            .previous_frame
+            .filter(|&(_, previous_size)| previous_size == render_size);
+        self.params_buffer.write(
+            uploader,
+            device,
+            encoder,
+            &DenoiseParams {
+                camera,
+                previous_camera: previous.map_or(camera, |(previous_camera, _)| previous_camera),
+                size: [render_size.0, render_size.1],
+                history_valid: previous.is_some().into(),
+                iterations: self.iterations,
+            },
+        );
+        self.resolve_buffer
+            .write(uploader, device, encoder, &viewport.averaged_resolve());
+
+        let targets = &self.targets;
+        let frame_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Denoiser Frame Bind Group"),
+            layout: &self.frame_bind_group_layout,
+            entries: &[
+                self.params_buffer.buffer.as_entire_binding(),
+                viewport.shown_buffer().as_entire_binding(),
+                viewport.gbuffer().as_entire_binding(),
+                targets.previous_gbuffer.as_entire_binding(),
+                targets.history_color.as_entire_binding(),
+                targets.history_moments.as_entire_binding(),
+                targets.moments.as_entire_binding(),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, resource)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource,
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let bytes = (render_size.0 * render_size.1) as u64 * std::mem::size_of::<[f32; 4]>() as u64;
+        let workgroups = (render_size.0.div_ceil(16), render_size.1.div_ceil(16));
+        let dispatch = |encoder: &mut CommandEncoder,
+                        profiler: &mut Profiler,
+                        name: &'static str,
+                        pipeline: &wgpu::ComputePipeline,
+                        pass_bind_group: &wgpu::BindGroup| {
+            let query = profiler.add_pass(
+                name,
+                workgroups,
+                (16, 16),
+                render_size.0 as u64 * render_size.1 as u64,
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            profiler.begin_query(&mut compute_pass, query);
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &frame_bind_group, &[]);
+            compute_pass.set_bind_group(1, pass_bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+            profiler.end_query(&mut compute_pass, query);
+        };
+
+        dispatch(
+            encoder,
+            profiler,
+            "Denoiser (temporal)",
+            &self.temporal_pipeline,
+            &targets.temporal_bind_group,
+        );
+        dispatch(
+            encoder,
+            profiler,
+            "Denoiser (variance)",
+            &self.variance_pipeline,
+            &targets.variance_bind_group,
+        );
+        for bind_group in &targets.atrous_bind_groups[..self.iterations as usize] {
+            dispatch(
+                encoder,
+                profiler,
+                "Denoiser (à-trous)",
+                &self.atrous_pipeline,
+                bind_group,
+            );
+        }
+
+        encoder.copy_buffer_to_buffer(&targets.moments, 0, &targets.history_moments, 0, bytes);
+        let gbuffer_bytes = (render_size.0 * render_size.1) as u64 * self.payload.surface_size();
+        encoder.copy_buffer_to_buffer(
+            viewport.gbuffer(),
+            0,
+            &targets.previous_gbuffer,
+            0,
+            gbuffer_bytes,
+        );
+        self.previous_frame = Some((camera, render_size));
+    }
+
+    /// Resolves the denoised image, in place of the viewport's [`Viewport::copy_bind_group`].
+    pub fn copy_bind_group(&self) -> &wgpu::BindGroup {
+        // An odd number of blurs ends in `ping`
+        &self.targets.copy_bind_groups[(self.iterations as usize + 1) % 2]
+    }
+}
+
+impl Targets {
+    fn new(
+        device: &Device,
+        pass_bind_group_layout: &BindGroupLayout,
+        copy_bind_group_layout: &BindGroupLayout,
+        resolve_buffer: &Buffer,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let [previous_gbuffer, history_color, history_moments, moments, ping, pong] = [
+            "Denoiser Previous G-Buffer",
+            "Denoiser History Color Buffer",
+            "Denoiser History Moments Buffer",
+            "Denoiser Moments Buffer",
+            "Denoiser Ping Buffer",
+            "Denoiser Pong Buffer",
+        ]
+        .map(|label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                mapped_at_creation: false,
+                size: (width * height) as u64 * std::mem::size_of::<[f32; 4]>() as u64,
+                label: Some(label),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            })
+        });
+
+        let pass_bind_group = |iteration: u32, source: &Buffer, destination: &Buffer| {
+            let pass_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Denoiser Pass Buffer"),
+                contents: bytemuck::cast_slice(&[1u32 << iteration, iteration]),
+                usage: BufferUsages::UNIFORM,
+            });
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Denoiser Pass Bind Group"),
+                layout: pass_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: pass_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: source.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: destination.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+
+        let temporal_bind_group = pass_bind_group(0, &pong, &ping);
+        let variance_bind_group = pass_bind_group(0, &ping, &pong);
+        // Each blur alternates between the two, starting from the variance pass' output
+        let atrous_bind_groups = (0..MAX_ITERATIONS)
+            .map(|i| {
+                if i % 2 == 0 {
+                    pass_bind_group(i, &pong, &ping)
+                } else {
+                    pass_bind_group(i, &ping, &pong)
+                }
+            })
+            .collect();
+
+        let copy_bind_groups = [&ping, &pong].map(|output| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Denoiser Copy Bind Group"),
+                layout: copy_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: output.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: resolve_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        Self {
+            previous_gbuffer,
+            history_color,
+            history_moments,
+            moments,
+            temporal_bind_group,
+            variance_bind_group,
+            atrous_bind_groups,
+            copy_bind_groups,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DenoiseParams {
+    camera: CameraBuffer,
+    previous_camera: CameraBuffer,
+    size: [u32; 2],
+    /// Zero when there's no previous frame to reproject.
+    history_valid: u32,
+    iterations: u32,
+}
+
+fn create_pipelines(
+    device: &Device,
+    shaders: &mut ShaderCache,
+    layout: &wgpu::PipelineLayout,
+    payload: PayloadFormat,
+) -> Result<[wgpu::ComputePipeline; 3], ShaderError> {
+    let shader = shaders.get(device, "denoise.wgsl", payload.defines())?;
+    Ok(["temporal", "variance", "atrous"].map(|entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(&format!(
+                "Denoiser {} Pipeline ({:?})",
+                entry_point, payload
+            )),
+            layout: Some(layout),
+            module: shader,
+            entry_point,
+        })
+    }))
+}
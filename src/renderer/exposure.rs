@@ -0,0 +1,320 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroupLayout, Buffer, BufferUsages, CommandEncoder, Device};
+
+use crate::utils::ShaderCache;
+
+use super::{
+    profiler::Profiler, tracked_buffer::TrackedBuffer, uploader::Uploader, viewport::Viewport,
+};
+
+/// The workgroups the luminance is summed over, each writing one partial sum. Matches
+/// `WORKGROUPS` in the shader.
+const EXPOSURE_WORKGROUPS: u32 = 64;
+const WORKGROUP_SIZE: u32 = 256;
+/// The luminance the average of the image is exposed to.
+const MIDDLE_GRAY: f32 = 0.18;
+
+/// The auto-exposure settings saved along with a scene.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AutoExposureSettings {
+    pub enabled: bool,
+    /// The limits of the exposure picked, in EV stops.
+    pub min_ev: f32,
+    pub max_ev: f32,
+    /// How quickly the exposure follows the image, closing about two thirds of the gap every
+    /// `1 / speed` seconds.
+    pub speed: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_ev: -8.0,
+            max_ev: 8.0,
+            speed: 2.0,
+        }
+    }
+}
+
+/// Exposes the main viewport's image so its average luminance comes out as middle gray, by
+/// reducing the log luminance of the accumulated samples on the GPU and reading the sums back a
+/// few frames later. The exposure eases towards the measured one rather than jumping to it.
+pub struct AutoExposure {
+    pub settings: AutoExposureSettings,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    params_buffer: TrackedBuffer<[u32; 2]>,
+    partial_sums: Buffer,
+    read_back_buffer: Buffer,
+    /// Whether the sums were copied to be read back in the frame being recorded.
+    measured: bool,
+    /// Whether the sums being read back are mapped yet. Nothing is measured meanwhile.
+    in_flight: Option<Arc<AtomicBool>>,
+    /// The exposure the last measurement called for, in EV stops.
+    target: Option<f32>,
+    /// The exposure applied, in EV stops.
+    current: f32,
+    last_update: Option<Instant>,
+}
+
+impl AutoExposure {
+    pub fn new(device: &Device, shaders: &mut ShaderCache) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Auto Exposure Bind Group Layout"),
+            entries: &[
+                // Samples and pixels
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Accumulated samples
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Partial sums
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Auto Exposure Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = shaders
+            .get(device, "luminance.wgsl", &[])
+            .unwrap_or_else(|error| panic!("{}", error));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Auto Exposure Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: shader,
+            entry_point: "main",
+        });
+
+        let buffer = |label, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: EXPOSURE_WORKGROUPS as u64 * std::mem::size_of::<[f32; 2]>() as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        Self {
+            settings: AutoExposureSettings::default(),
+            pipeline,
+            bind_group_layout,
+            params_buffer: TrackedBuffer::new(
+                device,
+                "Auto Exposure Params Buffer",
+                BufferUsages::UNIFORM,
+            ),
+            partial_sums: buffer(
+                "Auto Exposure Partial Sums Buffer",
+                BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            ),
+            read_back_buffer: buffer(
+                "Auto Exposure Read Back Buffer",
+                BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            ),
+            measured: false,
+            in_flight: None,
+            target: None,
+            current: 0.0,
+            last_update: None,
+        }
+    }
+
+    /// The exposure to apply on top of the manual one, zero when turned off.
+    pub fn ev(&self) -> f32 {
+        if self.settings.enabled {
+            self.current
+        } else {
+            0.0
+        }
+    }
+
+    /// Collects a measurement if one has arrived and eases the exposure towards it, once a frame
+    /// before resolving.
+    pub fn update(&mut self) {
+        self.measured = false;
+        self.collect();
+
+        let now = Instant::now();
+        let elapsed = self
+            .last_update
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last_update = Some(now);
+
+        let Some(target) = self.target else {
+            return;
+        };
+        let target = target.clamp(
+            self.settings.min_ev,
+            self.settings.max_ev.max(self.settings.min_ev),
+        );
+        let blend = 1.0 - (-elapsed * self.settings.speed).exp();
+        self.current += (target - self.current) * blend;
+    }
+
+    fn collect(&mut self) {
+        let Some(mapped) = &self.in_flight else {
+            return;
+        };
+        if !mapped.load(Ordering::Acquire) {
+            return;
+        }
+
+        let (log_luminance, pixels) = bytemuck::cast_slice::<u8, [f32; 2]>(
+            &self.read_back_buffer.slice(..).get_mapped_range(),
+        )
+        .iter()
+        .fold((0.0, 0.0), |(sum, count), partial| {
+            (sum + partial[0], count + partial[1])
+        });
+        self.read_back_buffer.unmap();
+        self.in_flight = None;
+
+        if pixels > 0.0 {
+            let target = MIDDLE_GRAY.log2() - log_luminance / pixels;
+            // Jumps straight to the first measurement rather than fading in from nothing
+            if self.target.is_none() {
+                self.current = target;
+            }
+            self.target = Some(target);
+        }
+    }
+
+    /// Sums the log luminance of `viewport`'s image at `render_size`, unless turned off or an
+    /// earlier measurement is still being read back.
+    pub fn measure(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        viewport: &Viewport,
+        render_size: (u32, u32),
+        profiler: &mut Profiler,
+    ) {
+        if !self.settings.enabled || self.in_flight.is_some() {
+            return;
+        }
+
+        let pixels = render_size.0 * render_size.1;
+        self.params_buffer
+            .write(uploader, device, encoder, &[viewport.samples(), pixels]);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Auto Exposure Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: viewport.shown_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.partial_sums.as_entire_binding(),
+                },
+            ],
+        });
+
+        let query = profiler.add_pass(
+            "Auto exposure",
+            (EXPOSURE_WORKGROUPS, 1),
+            (WORKGROUP_SIZE, 1),
+            pixels as u64,
+        );
+        {
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            profiler.begin_query(&mut compute_pass, query);
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(EXPOSURE_WORKGROUPS, 1, 1);
+            profiler.end_query(&mut compute_pass, query);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.partial_sums,
+            0,
+            &self.read_back_buffer,
+            0,
+            self.partial_sums.size(),
+        );
+        self.measured = true;
+    }
+
+    /// Starts reading back the sums, once the frame that measured them has been submitted.
+    pub fn read_back(&mut self) {
+        if !self.measured || self.in_flight.is_some() {
+            return;
+        }
+        self.measured = false;
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let on_mapped = mapped.clone();
+        self.read_back_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if let Err(e) = result {
+                    log::warn!("Failed to read back the image's luminance: {}", e);
+                    return;
+                }
+                on_mapped.store(true, Ordering::Release);
+            });
+        self.in_flight = Some(mapped);
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.settings.enabled, "auto exposure")
+            .on_hover_text(
+                "Expose the image so its average luminance comes out as middle gray. The \
+                exposure slider is added on top",
+            );
+        if !self.settings.enabled {
+            return;
+        }
+
+        ui.add(egui::Slider::new(&mut self.settings.min_ev, -16.0..=16.0).text("min EV"));
+        ui.add(egui::Slider::new(&mut self.settings.max_ev, -16.0..=16.0).text("max EV"));
+        ui.add(
+            egui::Slider::new(&mut self.settings.speed, 0.1..=20.0)
+                .logarithmic(true)
+                .text("adaptation speed"),
+        );
+        ui.label(format!("Automatic exposure: {:+.2} EV", self.current));
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use cgmath::Vector3;
+use uuid::Uuid;
+use wgpu::{BindGroupLayout, CommandEncoder, Device, Queue, TextureView};
+
+use crate::{
+    scene::{
+        Camera, LibraryMaterial, MaterialDataBuffer, MaterialId, MaterialLibrary, Mesh,
+        MeshInstance, Plane, Scene, Sphere, SphereDataBuffer, SphereDescriptor, Surface,
+    },
+    texture::{CubeTexture, HdrLoader, Texture2D},
+};
+
+use super::{
+    permutations::TracePipeline,
+    profiler::Profiler,
+    resources::SceneResources,
+    uploader::Uploader,
+    upscaler::UPSCALER_INPUT_FORMAT,
+    viewport::{PhotonPass, Viewport},
+    EnvironmentBuffer, EnvironmentSettings, ProgressiveRendering, Settings,
+};
+
+pub const MATERIAL_PREVIEW_SIZE: u32 = 128;
+const STUDIO_HDRI_SIZE: u32 = 512;
+
+/// A small offscreen render of a ball with the selected sphere's material on a floor, lit by a
+/// studio HDRI, so material changes can be judged without waiting for the whole scene.
+pub struct MaterialPreview {
+    viewport: Viewport,
+    resources: SceneResources,
+    /// The ball on the floor, seen by the scene's camera.
+    scene: Scene,
+    ball: Uuid,
+    output: Texture2D,
+}
+
+impl MaterialPreview {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        hdr_loader: &HdrLoader,
+        compute_bind_group_layout: &BindGroupLayout,
+        copy_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let data = include_bytes!("../../assets/hdri/room.hdr");
+        let studio_texture = CubeTexture::from_equirectangular_hdri(
+            hdr_loader,
+            device,
+            queue,
+            data,
+            STUDIO_HDRI_SIZE,
+        )
+        .unwrap();
+
+        let floor = Plane {
+            q: Vector3::new(-4.0, -1.0, -4.0),
+            u: Vector3::new(0.0, 0.0, 8.0),
+            v: Vector3::new(8.0, 0.0, 0.0),
+        }
+        // The grey diffuse every material library starts with
+        .triangles(MaterialId::default());
+        let floor = vec![Mesh::new("Floor".to_string(), floor)];
+        let ball = Sphere::new(SphereDescriptor {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: MaterialId::default(),
+        });
+
+        let mut camera = Camera::new();
+        camera.origin = Vector3::new(0.0, 0.6, 3.5);
+        camera.vfov = 40.0;
+        camera.look_in(-camera.origin);
+
+        let ball_uuid = ball.uuid;
+        let scene = Scene::with_meshes(vec![ball], floor, vec![MeshInstance::new(0)], camera);
+
+        let resources =
+            SceneResources::new(device, "Material Preview", scene.meshes(), studio_texture);
+
+        let viewport = Viewport::new(
+            device,
+            "Material preview",
+            MATERIAL_PREVIEW_SIZE,
+            MATERIAL_PREVIEW_SIZE,
+            compute_bind_group_layout,
+            copy_bind_group_layout,
+            &resources,
+        );
+
+        let output = Texture2D::new(
+            device,
+            MATERIAL_PREVIEW_SIZE,
+            MATERIAL_PREVIEW_SIZE,
+            UPSCALER_INPUT_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+
+        Self {
+            viewport,
+            resources,
+            scene,
+            ball: ball_uuid,
+            output,
+        }
+    }
+
+    /// The rendered preview, in linear color.
+    pub fn view(&self) -> &TextureView {
+        &self.output.view
+    }
+
+    /// Traces another sample of a ball of `material` and resolves the preview.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        compute_pipeline: TracePipeline,
+        copy_pipeline: &wgpu::RenderPipeline,
+        display_lut: &wgpu::BindGroup,
+        progressive_rendering: &ProgressiveRendering,
+        settings: &Settings,
+        material: &LibraryMaterial,
+        profiler: &mut Profiler,
+    ) {
+        let scene = &mut self.scene;
+        scene.materials = MaterialLibrary::new();
+        let id = scene.materials.add(material.clone());
+        if let Some(surface) = scene.get_mut::<Surface>(self.ball) {
+            surface.material = id;
+        }
+        let material_data = MaterialDataBuffer::new(&scene.materials);
+        let sphere_data = SphereDataBuffer::new(scene);
+        self.resources
+            .write_instances(uploader, device, encoder, scene);
+        self.resources
+            .write_materials(uploader, device, encoder, &material_data);
+        self.resources
+            .write_spheres(uploader, device, encoder, &sphere_data);
+        self.resources
+            .write_settings(uploader, device, encoder, settings);
+        // The studio lighting stays fixed regardless of the scene's environment settings
+        self.resources.write_environment(
+            uploader,
+            device,
+            encoder,
+            &EnvironmentBuffer::new(&EnvironmentSettings::default(), None),
+        );
+
+        let mut hasher = DefaultHasher::new();
+        bytemuck::bytes_of(&material_data).hash(&mut hasher);
+        bytemuck::bytes_of(&sphere_data).hash(&mut hasher);
+        bytemuck::bytes_of(settings).hash(&mut hasher);
+        let size = (MATERIAL_PREVIEW_SIZE, MATERIAL_PREVIEW_SIZE);
+        self.viewport
+            .update_state(hasher.finish(), &self.scene.camera, size);
+
+        self.viewport.trace(
+            device,
+            encoder,
+            uploader,
+            compute_pipeline,
+            Some(PhotonPass {
+                grid: self.resources.caustic_grid(),
+                photons: settings.photons,
+            }),
+            &self.scene.camera,
+            progressive_rendering,
+            size,
+            0,
+            1,
+            (0, 0),
+            1.0,
+            None,
+            &settings.tone_mapping(),
+            profiler,
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Material Preview Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.output.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_bind_group(0, self.viewport.copy_bind_group(), &[]);
+        render_pass.set_bind_group(1, display_lut, &[]);
+        render_pass.set_pipeline(copy_pipeline);
+        render_pass.draw(0..3, 0..2);
+    }
+}
@@ -0,0 +1,2235 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
+    f32::consts::PI,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    assets,
+    display_lut::{self, DisplayLut},
+    scene::{LightDataBuffer, MaterialDataBuffer, SphereDataBuffer},
+    texture::{CubeTexture, ImageMetadata},
+    utils::{ShaderCache, ShaderError},
+};
+use cgmath::Vector3;
+use wgpu::{
+    CommandEncoder, Device, Queue, SamplerBindingType, SurfaceConfiguration, SurfaceTexture,
+    TextureFormat,
+};
+
+use crate::{
+    scene::{Camera, CanvasRows, Material, MaterialId, Mesh, Scene, SceneEvent},
+    texture,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use self::{
+    batching::{Batch, SampleBatching},
+    calibration::Timing,
+    denoiser::Denoiser,
+    exposure::{AutoExposure, AutoExposureSettings},
+    material_preview::MaterialPreview,
+    permutations::{ComputePipelines, PayloadFormat, ShaderFeatures, WorkgroupSize},
+    presets::{PresetRequest, QualityPreset, QualityPresets},
+    profiler::Profiler,
+    resources::SceneResources,
+    uploader::Uploader,
+    upscaler::{Upscaler, MIN_RENDER_SCALE, UPSCALER_INPUT_FORMAT},
+    viewport::{PhotonPass, Viewport},
+};
+
+pub use calibration::Calibration;
+pub use material_preview::MATERIAL_PREVIEW_SIZE;
+pub use permutations::DebugView;
+pub use viewport::PickedObject;
+
+mod batching;
+mod calibration;
+mod denoiser;
+mod exposure;
+mod material_preview;
+mod permutations;
+mod presets;
+mod profiler;
+mod resources;
+mod tracked_buffer;
+mod uploader;
+mod upscaler;
+mod viewport;
+
+const MAX_NUMBER_OF_SAMPLES: u32 = 4096;
+const PICTURE_IN_PICTURE_WIDTH: u32 = 480;
+const PICTURE_IN_PICTURE_HEIGHT: u32 = 270;
+const PICTURE_IN_PICTURE_MARGIN: u32 = 16;
+
+/// Extended linear sRGB (scRGB), where 1.0 is 80 nits and values above it are brighter.
+pub const HDR_OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// The tone mapping operators of the copy shader, by the index it's given.
+const TONE_MAPPING_OPERATORS: [&str; 5] =
+    ["Linear", "Reinhard", "ACES", "Uncharted 2", "Display LUT"];
+/// The index of the operator applying [`Renderer::display_lut`].
+const DISPLAY_LUT_OPERATOR: u32 = 4;
+
+/// The sample brightness limit when clamping is turned on.
+const DEFAULT_MAX_RADIANCE: f32 = 10.0;
+/// How deep paths go in reference mode, as the compute shader fixes it.
+const REFERENCE_DEPTH: u32 = 256;
+/// The equirectangular size of the sky [`Renderer::set_uniform_environment`] makes, which is
+/// the same everywhere so it can be tiny.
+const UNIFORM_ENVIRONMENT_SIZE: (u32, u32) = (8, 4);
+
+pub struct Renderer {
+    settings: Settings,
+    environment: EnvironmentSettings,
+    compute_pipelines: ComputePipelines,
+    /// The variant of the compute shader used this frame.
+    features: ShaderFeatures,
+    debug_view: DebugView,
+    /// How the denoiser's inputs are stored, traded for bandwidth in the performance preset.
+    payload: PayloadFormat,
+    workgroup_size: WorkgroupSize,
+    /// The settings last picked by [`Self::calibrate`] or loaded, if any.
+    calibration: Option<Calibration>,
+    /// Set from the UI, for [`Self::take_calibration_request`].
+    calibration_requested: bool,
+    /// The `.cube` file the display LUT tone mapping operator applies, and where it's from.
+    display_lut: Option<(PathBuf, Arc<DisplayLut>)>,
+    /// Bound at group 1 of the copy pipelines, replaced when the display LUT is.
+    display_lut_bind_group: wgpu::BindGroup,
+    display_lut_bind_group_layout: wgpu::BindGroupLayout,
+    /// Set when the display LUT changes, to upload it with the next frame.
+    display_lut_changed: bool,
+    /// Set from the UI, for [`Self::take_display_lut_request`].
+    display_lut_requested: bool,
+    /// The materials the meshes' triangles are made of, to tell whether any is a shadow catcher.
+    triangle_materials: BTreeSet<MaterialId>,
+    /// The last shader that failed to compile, until the error is dismissed.
+    shader_error: Option<ShaderError>,
+
+    shaders: ShaderCache,
+    copy_pipeline_layout: wgpu::PipelineLayout,
+    copy_pipeline: wgpu::RenderPipeline,
+    picture_in_picture_pipeline: wgpu::RenderPipeline,
+    output_format: TextureFormat,
+    /// The brightness of diffuse white in nits when rendering to an HDR output.
+    pub paper_white: f32,
+
+    resources: SceneResources,
+    uploader: Uploader,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    copy_bind_group_layout: wgpu::BindGroupLayout,
+
+    hdr_loader: texture::HdrLoader,
+    /// The sun found in the sky texture, resized and boosted by the environment settings.
+    sun: Option<texture::Sun>,
+    /// The directions most of the sky texture's light comes from, brightest first.
+    key_lights: Vec<texture::KeyLight>,
+    /// Incremented whenever the sky texture's contents are replaced.
+    environment_version: u32,
+    /// The rows of each mesh's canvas painted since they were last uploaded.
+    painted_canvases: HashMap<Uuid, CanvasRows>,
+    /// Incremented whenever a canvas is painted.
+    canvas_version: u32,
+
+    upscaler: Upscaler,
+    denoiser: Denoiser,
+    /// The render size of the image currently held in the upscaler's input.
+    displayed_render_size: (u32, u32),
+    main_viewport: Viewport,
+    picture_in_picture_viewport: Viewport,
+    pub picture_in_picture: bool,
+    false_color: FalseColor,
+    material_preview: MaterialPreview,
+    progressive_rendering: ProgressiveRendering,
+    quality_presets: QualityPresets,
+    profiler: Profiler,
+    batching: SampleBatching,
+    auto_exposure: AutoExposure,
+    /// Set once the GPU has finished the last frame's tracing, which has a submission of its own.
+    trace_done: Arc<AtomicBool>,
+    /// The hash [`Self::update_buffers`] returned when the scene was last traced.
+    scene_state: u64,
+}
+
+impl Renderer {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        surface_config: &SurfaceConfiguration,
+        scene: &Scene,
+    ) -> Self {
+        let mut shaders = ShaderCache::new(assets::resolve("shaders"));
+
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    // Accumulated samples
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Camera
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Spheres
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Triangles
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Triangle indices
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // BVH nodes
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Seed
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Sky texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                        },
+                        count: None,
+                    },
+                    // Sky Texture Sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(SamplerBindingType::NonFiltering),
+                        count: None,
+                    },
+                    // Settings
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Render size
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Environment
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 11,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // How much of the accumulated samples to keep
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 12,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // G-buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 13,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Lights
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 14,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Point and directional lights
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 15,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Mesh instances and the BVH over them
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 16,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Object IDs
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 17,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Materials
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 18,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Photons gathered for the caustic preview
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 19,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // What's painted over the meshes' albedo
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 20,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        // TODO: maybe load on separate thread
+        let hdr_loader = texture::HdrLoader::new(device);
+        let data = include_bytes!("../../assets/hdri/partly_cloudy_sky.hdr");
+        let (sky_width, sky_height, sky_pixels) = texture::read_hdr_pixels(data).unwrap();
+        let sun = texture::find_sun(sky_width, sky_height, &sky_pixels);
+        let key_lights = texture::find_key_lights(sky_width, sky_height, &sky_pixels);
+        let sky_texture = CubeTexture::from_equirectangular_pixels(
+            &hdr_loader,
+            device,
+            queue,
+            sky_width,
+            sky_height,
+            &sky_pixels,
+            4096,
+        );
+
+        let resources = SceneResources::new(device, "Scene", scene.meshes(), sky_texture);
+
+        let mut compute_pipelines = ComputePipelines::new(device, &compute_bind_group_layout);
+        // There's nothing to fall back to if the default variant doesn't compile
+        if let Some(error) =
+            compute_pipelines.prepare(device, &mut shaders, ShaderFeatures::default())
+        {
+            panic!("{}", error);
+        }
+
+        let copy_bind_group_layout = Viewport::copy_bind_group_layout(device);
+
+        let (width, height) = fit_render_size(device, surface_config.width, surface_config.height);
+        let main_viewport = Viewport::new(
+            device,
+            "Trace",
+            width,
+            height,
+            &compute_bind_group_layout,
+            &copy_bind_group_layout,
+            &resources,
+        );
+        let picture_in_picture_viewport = Viewport::new(
+            device,
+            "Trace (picture-in-picture)",
+            PICTURE_IN_PICTURE_WIDTH,
+            PICTURE_IN_PICTURE_HEIGHT,
+            &compute_bind_group_layout,
+            &copy_bind_group_layout,
+            &resources,
+        );
+        let material_preview = MaterialPreview::new(
+            device,
+            queue,
+            &hdr_loader,
+            &compute_bind_group_layout,
+            &copy_bind_group_layout,
+        );
+
+        let display_lut_bind_group_layout = display_lut::bind_group_layout(device);
+        let display_lut_bind_group =
+            display_lut::create_bind_group(device, queue, &display_lut_bind_group_layout, None);
+        let copy_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Copy Pipeline Layout"),
+            bind_group_layouts: &[&copy_bind_group_layout, &display_lut_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let copy_shader = shaders
+            .get(device, "copy.wgsl", &[])
+            .unwrap_or_else(|error| panic!("{}", error));
+        let copy_pipeline = create_copy_pipeline(
+            device,
+            &copy_pipeline_layout,
+            copy_shader,
+            "Copy Pipeline",
+            UPSCALER_INPUT_FORMAT,
+        );
+        let picture_in_picture_pipeline = create_copy_pipeline(
+            device,
+            &copy_pipeline_layout,
+            copy_shader,
+            "Picture-in-picture Pipeline",
+            surface_config.format,
+        );
+        let denoiser = Denoiser::new(device, &mut shaders, &copy_bind_group_layout, width, height);
+        let auto_exposure = AutoExposure::new(device, &mut shaders);
+
+        Renderer {
+            settings: Settings {
+                samples_per_pixel: 1,
+                depth: 32,
+                t_min: 0.0001,
+                t_max: 1000.0,
+                reference: 0,
+                tone_mapping: 0,
+                exposure: 0.0,
+                max_radiance: 0.0,
+                caustic_preview: 0,
+                caustic_radius: default_caustic_radius(),
+                photons: default_photons(),
+            },
+            environment: EnvironmentSettings::default(),
+            progressive_rendering: ProgressiveRendering {
+                enabled: true,
+                sample_size: 128,
+                sample_size_while_moving: 1,
+                samples_before_display: 1,
+            },
+            compute_pipelines,
+            features: ShaderFeatures::default(),
+            debug_view: DebugView::None,
+            payload: PayloadFormat::Full,
+            workgroup_size: WorkgroupSize::default(),
+            calibration: None,
+            calibration_requested: false,
+            display_lut: None,
+            display_lut_bind_group,
+            display_lut_bind_group_layout,
+            display_lut_changed: false,
+            display_lut_requested: false,
+            shader_error: None,
+            triangle_materials: triangle_materials(scene.meshes()),
+            shaders,
+            copy_pipeline_layout,
+            copy_pipeline,
+            picture_in_picture_pipeline,
+            output_format: surface_config.format,
+            paper_white: 203.0,
+            resources,
+            uploader: Uploader::new(),
+            compute_bind_group_layout,
+            copy_bind_group_layout,
+            upscaler: Upscaler::new(device, surface_config.format, width, height),
+            denoiser,
+            displayed_render_size: (0, 0),
+            main_viewport,
+            picture_in_picture_viewport,
+            picture_in_picture: false,
+            false_color: FalseColor {
+                enabled: false,
+                quantity: FalseColorQuantity::Luminance,
+            },
+            material_preview,
+            quality_presets: QualityPresets::load(),
+            profiler: Profiler::new(device),
+            batching: SampleBatching::new(device, queue),
+            auto_exposure,
+            trace_done: Arc::new(AtomicBool::new(true)),
+            scene_state: 0,
+            hdr_loader,
+            sun,
+            key_lights,
+            environment_version: 0,
+            painted_canvases: HashMap::new(),
+            canvas_version: 0,
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, is_moving: bool) {
+        ui.collapsing("Rendering", |ui| {
+            ui.collapsing("Quality presets", |ui| {
+                let current = self.current_quality(String::new());
+                let active = self.quality_presets.presets.iter().position(|preset| {
+                    *preset
+                        == QualityPreset {
+                            name: preset.name.clone(),
+                            ..current.clone()
+                        }
+                });
+                match self.quality_presets.render_ui(ui, active) {
+                    Some(PresetRequest::Apply(index)) => {
+                        self.apply_quality_preset(index);
+                    }
+                    Some(PresetRequest::Update(index)) => {
+                        let name = self.quality_presets.presets[index].name.clone();
+                        self.quality_presets.presets[index] = self.current_quality(name);
+                        self.quality_presets.save();
+                    }
+                    Some(PresetRequest::Add(name)) => {
+                        let preset = self.current_quality(name);
+                        self.quality_presets.presets.push(preset);
+                        self.quality_presets.save();
+                    }
+                    None => {}
+                }
+            });
+
+            ui.collapsing("General", |ui| {
+                ui.add(
+                    egui::Slider::new(&mut self.settings.samples_per_pixel, 1..=256)
+                        .text("samples per pixel"),
+                );
+                let mut reference = self.settings.reference != 0;
+                ui.checkbox(&mut reference, "reference mode").on_hover_text(
+                    "Slower, unbiased integration for comparisons: fresh random numbers for every \
+                    bounce, paths up to 256 bounces long, and no clamping, caustic preview, \
+                    denoising or upscaling",
+                );
+                self.settings.reference = reference.into();
+
+                ui.add_enabled(
+                    !reference,
+                    egui::Slider::new(&mut self.settings.depth, 1..=256).text("depth"),
+                );
+                let mut clamp = self.settings.max_radiance > 0.0;
+                ui.add_enabled_ui(!reference, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .checkbox(&mut clamp, "clamp")
+                            .on_hover_text(
+                                "Limit how bright a sample can be, which removes fireflies at the \
+                                cost of some energy. Not applied in reference mode",
+                            )
+                            .changed()
+                        {
+                            self.settings.max_radiance =
+                                if clamp { DEFAULT_MAX_RADIANCE } else { 0.0 };
+                        }
+                        ui.add_enabled(
+                            clamp,
+                            egui::Slider::new(&mut self.settings.max_radiance, 0.1..=100.0)
+                                .logarithmic(true)
+                                .text("max radiance"),
+                        );
+                    });
+                });
+                let mut caustics = self.settings.caustic_preview != 0;
+                ui.add_enabled_ui(!reference, |ui| {
+                    ui.checkbox(&mut caustics, "caustic preview (biased)")
+                        .on_hover_text(
+                            "Light diffuse surfaces seen through or reflected by metals and glass \
+                            with photons traced from the lights, which shows caustics within a \
+                            few samples but blurs them and never converges to the exact image. \
+                            Not applied in reference mode",
+                        );
+                    ui.add_enabled(
+                        caustics,
+                        egui::Slider::new(&mut self.settings.photons, 1024..=1048576)
+                            .logarithmic(true)
+                            .text("photons per sample"),
+                    );
+                    ui.add_enabled(
+                        caustics,
+                        egui::Slider::new(&mut self.settings.caustic_radius, 0.001..=1.0)
+                            .logarithmic(true)
+                            .text("photon radius"),
+                    )
+                    .on_hover_text("Smaller is sharper but noisier");
+                });
+                self.settings.caustic_preview = caustics.into();
+                ui.add(egui::Slider::new(&mut self.settings.t_min, 0.0..=1.0).text("t_min"));
+                ui.add(egui::Slider::new(&mut self.settings.t_max, 1.0..=9000.0).text("t_max"));
+
+                ui.add(
+                    egui::Slider::new(&mut self.settings.exposure, -10.0..=10.0)
+                        .text("exposure (EV)"),
+                )
+                .on_hover_text("Brightens or darkens the image by powers of two");
+                self.auto_exposure.render_ui(ui);
+
+                let operators = TONE_MAPPING_OPERATORS;
+                let selected = operators.get(self.settings.tone_mapping as usize);
+                egui::ComboBox::from_label("tone mapping")
+                    .selected_text(selected.copied().unwrap_or(operators[0]))
+                    .show_ui(ui, |ui| {
+                        for (index, operator) in operators.into_iter().enumerate() {
+                            ui.selectable_value(
+                                &mut self.settings.tone_mapping,
+                                index as u32,
+                                operator,
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("How radiance above 1.0 is compressed into the displayed range");
+                ui.horizontal(|ui| {
+                    let name = self.display_lut.as_ref().map(|(path, lut)| {
+                        let file = path.file_name().unwrap_or_default().to_string_lossy();
+                        if lut.title.is_empty() {
+                            file.into_owned()
+                        } else {
+                            format!("{} ({})", lut.title, file)
+                        }
+                    });
+                    ui.label(name.as_deref().unwrap_or("No display LUT"));
+                    if ui
+                        .button("Load")
+                        .on_hover_text(
+                            "Load a display transform baked to a .cube LUT, such as an \
+                            OpenColorIO display and view baked with ociobakelut, for the display \
+                            LUT tone mapping operator",
+                        )
+                        .clicked()
+                    {
+                        self.display_lut_requested = true;
+                    }
+                    if self.display_lut.is_some() && ui.button("Clear").clicked() {
+                        self.display_lut = None;
+                        self.display_lut_changed = true;
+                    }
+                });
+
+                egui::ComboBox::from_label("debug view")
+                    .selected_text(format!("{:?}", self.debug_view))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.debug_view, DebugView::None, "None");
+                        ui.selectable_value(&mut self.debug_view, DebugView::Normals, "Normals");
+                    });
+            });
+
+            ui.collapsing("Progressive rendering", |ui| {
+                ui.add(egui::Checkbox::new(
+                    &mut self.progressive_rendering.enabled,
+                    "enabled",
+                ));
+
+                ui.add_enabled(
+                    self.progressive_rendering.enabled,
+                    egui::Slider::new(
+                        &mut self.progressive_rendering.sample_size,
+                        1..=MAX_NUMBER_OF_SAMPLES,
+                    )
+                    .logarithmic(true)
+                    .text("samples"),
+                );
+
+                let (samples, target) = self.sample_progress(is_moving);
+                ui.add(egui::Label::new(format!(
+                    "Samples used: {}/{}",
+                    samples, target
+                )));
+
+                ui.add_enabled(
+                    self.progressive_rendering.enabled,
+                    egui::Slider::new(
+                        &mut self.progressive_rendering.sample_size_while_moving,
+                        1..=MAX_NUMBER_OF_SAMPLES,
+                    )
+                    .logarithmic(true)
+                    .text("samples while moving"),
+                );
+
+                ui.add_enabled(
+                    self.progressive_rendering.enabled,
+                    egui::Slider::new(
+                        &mut self.progressive_rendering.samples_before_display,
+                        1..=32,
+                    )
+                    .text("samples before display"),
+                )
+                .on_hover_text(
+                    "Keep showing the previous image after a reset until this many samples \
+                    are ready, instead of a single noisy sample",
+                );
+
+                ui.add_enabled_ui(self.progressive_rendering.enabled, |ui| {
+                    self.batching.render_ui(ui);
+                });
+            });
+
+            ui.collapsing("Performance", |ui| {
+                egui::ComboBox::from_label("payload")
+                    .selected_text(match self.payload {
+                        PayloadFormat::Full => "Full precision",
+                        PayloadFormat::Packed => "Packed",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(
+                            &mut self.payload,
+                            PayloadFormat::Full,
+                            "Full precision",
+                        );
+                        ui.selectable_value(&mut self.payload, PayloadFormat::Packed, "Packed");
+                    })
+                    .response
+                    .on_hover_text(
+                        "Store the denoiser's normals and colors in half the space (octahedral \
+                        normals, RGBM colors) for GPUs limited by memory bandwidth. Colors \
+                        brighter than 16 are clipped while denoising",
+                    );
+
+                egui::ComboBox::from_label("workgroup size")
+                    .selected_text(self.workgroup_size.name())
+                    .show_ui(ui, |ui| {
+                        for size in WorkgroupSize::ALL {
+                            ui.selectable_value(&mut self.workgroup_size, size, size.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("The invocations per workgroup the scene is traced with");
+
+                ui.separator();
+                match &self.calibration {
+                    Some(calibration) => ui.label(format!(
+                        "Calibrated: {}, {:.0}% scale, {} spp in {:.1} ms",
+                        calibration.workgroup_size.name(),
+                        calibration.render_scale * 100.0,
+                        calibration.samples_per_pixel,
+                        calibration.frame_time.as_secs_f64() * 1000.0
+                    )),
+                    None => ui.label("Not calibrated"),
+                };
+                if ui
+                    .button("Calibrate")
+                    .on_hover_text(
+                        "Time a few workgroup sizes, render scales and samples per pixel on this \
+                        GPU and switch to the best that keep up with 60 frames per second. The \
+                        window stops responding for a few seconds meanwhile",
+                    )
+                    .clicked()
+                {
+                    self.calibration_requested = true;
+                }
+            });
+
+            // Reference mode neither denoises nor upscales, whatever these are set to
+            ui.add_enabled_ui(self.settings.reference == 0, |ui| {
+                self.denoiser.render_ui(ui);
+                self.upscaler.render_ui(ui);
+            });
+            self.false_color.render_ui(ui);
+            self.profiler.render_ui(ui);
+        });
+    }
+
+    /// Whether calibration was asked for from the UI since the last call.
+    pub fn take_calibration_request(&mut self) -> bool {
+        std::mem::take(&mut self.calibration_requested)
+    }
+
+    /// Whether loading a display LUT was asked for from the UI since the last call.
+    pub fn take_display_lut_request(&mut self) -> bool {
+        std::mem::take(&mut self.display_lut_requested)
+    }
+
+    /// Applies `lut`, loaded from `path`, with the display LUT tone mapping operator, switching
+    /// to it.
+    pub fn set_display_lut(&mut self, path: PathBuf, lut: DisplayLut) {
+        self.display_lut = Some((path, Arc::new(lut)));
+        self.display_lut_changed = true;
+        self.settings.tone_mapping = DISPLAY_LUT_OPERATOR;
+    }
+
+    /// Switches to the settings of a calibration made earlier.
+    pub fn apply_calibration(&mut self, calibration: Calibration) {
+        self.workgroup_size = calibration.workgroup_size;
+        self.settings.samples_per_pixel = calibration.samples_per_pixel;
+        self.upscaler.enabled = calibration.render_scale < 1.0;
+        self.upscaler.render_scale = calibration.render_scale.clamp(MIN_RENDER_SCALE, 1.0);
+        self.calibration = Some(calibration);
+    }
+
+    /// Times tracing `scene` with every workgroup size, then with the fastest at each of a few
+    /// render scales and samples per pixel, and switches to the best settings that fit the frame
+    /// budget, saving them for the next launch on `adapter`. Blocks until the GPU is done.
+    pub fn calibrate(&mut self, device: &Device, queue: &Queue, scene: &Scene, adapter: &str) {
+        let start = Instant::now();
+        let samples_per_pixel = self.settings.samples_per_pixel;
+        let full_size = self.main_viewport.size();
+
+        let mut workgroup_size = None;
+        for size in WorkgroupSize::ALL {
+            match self.time_frame(device, queue, scene, size, full_size, 1) {
+                Ok(time) => {
+                    if workgroup_size.is_none_or(|(_, fastest)| time < fastest) {
+                        workgroup_size = Some((size, time));
+                    }
+                }
+                Err(error) => log::warn!("Skipping {} workgroups: {}", size.name(), error),
+            }
+        }
+        let Some((workgroup_size, _)) = workgroup_size else {
+            self.settings.samples_per_pixel = samples_per_pixel;
+            return;
+        };
+
+        let mut timings = Vec::new();
+        for render_scale in calibration::RENDER_SCALES {
+            let render_size = (
+                ((full_size.0 as f32 * render_scale).ceil() as u32).max(1),
+                ((full_size.1 as f32 * render_scale).ceil() as u32).max(1),
+            );
+            for samples_per_pixel in calibration::SAMPLES_PER_PIXEL {
+                if let Ok(frame_time) = self.time_frame(
+                    device,
+                    queue,
+                    scene,
+                    workgroup_size,
+                    render_size,
+                    samples_per_pixel,
+                ) {
+                    timings.push(Timing {
+                        render_scale,
+                        samples_per_pixel,
+                        frame_time,
+                    });
+                }
+            }
+        }
+        self.settings.samples_per_pixel = samples_per_pixel;
+
+        let Some(calibration) = Calibration::pick(adapter.to_string(), workgroup_size, &timings)
+        else {
+            return;
+        };
+        log::info!(
+            "Calibrated in {:?}: {} workgroups, {} render scale, {} samples per pixel",
+            start.elapsed(),
+            calibration.workgroup_size.name(),
+            calibration.render_scale,
+            calibration.samples_per_pixel
+        );
+        calibration.save();
+        self.apply_calibration(calibration);
+    }
+
+    /// The average time the GPU takes to trace a frame of `render_size` into the main viewport,
+    /// over [`calibration::TIMED_FRAMES`] after one to warm up.
+    fn time_frame(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        workgroup_size: WorkgroupSize,
+        render_size: (u32, u32),
+        samples_per_pixel: u32,
+    ) -> Result<Duration, ShaderError> {
+        let features = ShaderFeatures {
+            workgroup_size,
+            ..self.features
+        };
+        if let Some(error) = self
+            .compute_pipelines
+            .prepare(device, &mut self.shaders, features)
+        {
+            return Err(error);
+        }
+        self.settings.samples_per_pixel = samples_per_pixel;
+
+        let mut elapsed = Duration::ZERO;
+        for frame in 0..=calibration::TIMED_FRAMES {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Calibration Encoder"),
+            });
+            let scene_state = self.update_buffers(device, &mut encoder, scene);
+            self.main_viewport
+                .bind(device, &self.compute_bind_group_layout, &self.resources);
+            // A different state every frame, so each traces a sample from scratch
+            self.main_viewport.update_state(
+                scene_state.wrapping_add(frame as u64),
+                &scene.camera,
+                render_size,
+            );
+            self.main_viewport.trace(
+                device,
+                &mut encoder,
+                &mut self.uploader,
+                self.compute_pipelines.get(features),
+                Some(PhotonPass {
+                    grid: self.resources.caustic_grid(),
+                    photons: self.settings.photons,
+                }),
+                &scene.camera,
+                &self.progressive_rendering,
+                render_size,
+                scene.frame,
+                1,
+                (0, 0),
+                1.0,
+                None,
+                &self.settings.tone_mapping(),
+                &mut self.profiler,
+            );
+            self.uploader.finish();
+
+            let start = Instant::now();
+            queue.submit([encoder.finish()]);
+            device.poll(wgpu::Maintain::Wait);
+            if frame > 0 {
+                elapsed += start.elapsed();
+            }
+            self.uploader.recall();
+        }
+
+        Ok(elapsed / calibration::TIMED_FRAMES)
+    }
+
+    pub fn quality_preset_count(&self) -> usize {
+        self.quality_presets.presets.len()
+    }
+
+    /// Switches to the settings of the quality preset at `index`, returning whether there is one.
+    pub fn apply_quality_preset(&mut self, index: usize) -> bool {
+        let Some(preset) = self.quality_presets.presets.get(index) else {
+            return false;
+        };
+        self.settings.samples_per_pixel = preset.samples_per_pixel;
+        self.settings.depth = preset.depth;
+        self.settings.max_radiance = preset.max_radiance;
+        self.progressive_rendering.sample_size = preset.samples;
+        self.denoiser.enabled = preset.denoise;
+        self.upscaler.enabled = preset.render_scale < 1.0;
+        self.upscaler.render_scale = preset.render_scale.clamp(MIN_RENDER_SCALE, 1.0);
+        log::info!("Applied the {} quality preset", preset.name);
+        true
+    }
+
+    /// The current settings as a quality preset called `name`.
+    fn current_quality(&self, name: String) -> QualityPreset {
+        QualityPreset {
+            name,
+            samples_per_pixel: self.settings.samples_per_pixel,
+            depth: self.settings.depth,
+            max_radiance: self.settings.max_radiance,
+            samples: self.progressive_rendering.sample_size,
+            denoise: self.denoiser.enabled,
+            render_scale: if self.upscaler.enabled {
+                self.upscaler.render_scale
+            } else {
+                1.0
+            },
+        }
+    }
+
+    pub fn toggle_false_color(&mut self) {
+        self.false_color.enabled = !self.false_color.enabled;
+    }
+
+    /// Whether the main view is denoised, which reference mode never is.
+    fn denoising(&self) -> bool {
+        self.denoiser.enabled && self.settings.reference == 0
+    }
+
+    /// Shows `view` in place of the traced image, or the traced image again if it's shown.
+    pub fn toggle_debug_view(&mut self, view: DebugView) {
+        self.debug_view = if self.debug_view == view {
+            DebugView::None
+        } else {
+            view
+        };
+    }
+
+    pub fn toggle_reference_mode(&mut self) {
+        self.settings.reference = u32::from(self.settings.reference == 0);
+    }
+
+    pub fn render_environment_ui(&mut self, ui: &mut egui::Ui, camera: &Camera) {
+        let mut yaw = self.environment.yaw.to_degrees();
+        ui.add(
+            egui::Slider::new(&mut yaw, -180.0..=180.0)
+                .text("rotation")
+                .suffix("°"),
+        );
+        self.environment.yaw = yaw.to_radians();
+
+        ui.add(
+            egui::Slider::new(&mut self.environment.intensity, 0.0..=16.0)
+                .logarithmic(true)
+                .text("intensity"),
+        );
+
+        let mut show_background = self.environment.show_background != 0;
+        ui.checkbox(&mut show_background, "show background")
+            .on_hover_text(
+                "When off, the HDRI still lights the scene but the camera sees a solid color",
+            );
+        self.environment.show_background = show_background.into();
+
+        ui.add_enabled_ui(!show_background, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("background color");
+                ui.color_edit_button_rgb(&mut self.environment.background_color);
+            });
+        });
+
+        ui.add_enabled_ui(self.sun.is_some(), |ui| {
+            ui.add(
+                egui::Slider::new(&mut self.environment.sun_size, 0.25..=8.0)
+                    .logarithmic(true)
+                    .text("sun size"),
+            )
+            .on_hover_text(
+                "Shrink or grow the HDRI's sun for sharper or softer shadows, keeping its power",
+            )
+            .on_disabled_hover_text("No sun was found in the HDRI");
+            ui.add(
+                egui::Slider::new(&mut self.environment.sun_intensity, 0.0..=16.0)
+                    .logarithmic(true)
+                    .text("sun intensity"),
+            )
+            .on_hover_text("Multiplies the HDRI's sun on top of the environment's intensity")
+            .on_disabled_hover_text("No sun was found in the HDRI");
+        });
+
+        self.render_key_lights_ui(ui, camera);
+    }
+
+    /// Lists the HDRI's brightest light directions, with buttons rotating the environment so
+    /// one of them lights the scene from a side of the view.
+    fn render_key_lights_ui(&mut self, ui: &mut egui::Ui, camera: &Camera) {
+        // Only the horizontal part of a direction can be changed by rotating the environment
+        let azimuth = |direction: Vector3<f32>| direction.z.atan2(direction.x);
+        let sides = [
+            ("Left", -camera.right),
+            ("Front", -camera.forward),
+            ("Right", camera.right),
+            ("Back", camera.forward),
+        ];
+
+        ui.collapsing("Key lights", |ui| {
+            if self.key_lights.is_empty() {
+                ui.label("The HDRI is black");
+            }
+            for key_light in &self.key_lights {
+                let direction = Vector3::from(key_light.direction);
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{:.0}% from {:.0}° up",
+                        key_light.share * 100.0,
+                        direction.y.clamp(-1.0, 1.0).asin().to_degrees(),
+                    ))
+                    .on_hover_text("The share of the HDRI's light coming from around the direction");
+                    for (side, towards) in sides {
+                        let clicked = ui
+                            .small_button(side)
+                            .on_hover_text(format!(
+                                "Rotate the environment so this light comes from the {} of the view",
+                                side.to_lowercase()
+                            ))
+                            .clicked();
+                        if clicked {
+                            let yaw = azimuth(towards) - azimuth(direction);
+                            // Wrapped into the rotation slider's range
+                            self.environment.yaw = (yaw + PI).rem_euclid(2.0 * PI) - PI;
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Replaces the HDRI the scene is lit by with an equirectangular image decoded by
+    /// [`texture::read_hdr_pixels`]. The samples lit by the old one are kept until
+    /// [`SceneEvent::EnvironmentChanged`] is handled.
+    pub fn set_environment(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+    ) {
+        self.sun = texture::find_sun(width, height, pixels);
+        self.key_lights = texture::find_key_lights(width, height, pixels);
+        self.resources.sky_texture().load_equirectangular_pixels(
+            &self.hdr_loader,
+            device,
+            queue,
+            width,
+            height,
+            pixels,
+        );
+    }
+
+    /// Replaces the HDRI with the same `radiance` from every direction and puts the
+    /// environment's settings back to their defaults, as the diagnostic scenes expect.
+    pub fn set_uniform_environment(&mut self, device: &Device, queue: &Queue, radiance: f32) {
+        let (width, height) = UNIFORM_ENVIRONMENT_SIZE;
+        let pixels = vec![[radiance, radiance, radiance, 1.0]; (width * height) as usize];
+        self.set_environment(device, queue, width, height, &pixels);
+        self.environment = EnvironmentSettings::default();
+    }
+
+    /// Rebuilds what depends on the parts of the scene that aren't uploaded every frame.
+    pub fn handle_event(&mut self, device: &Device, scene: &Scene, event: &SceneEvent) {
+        match event {
+            SceneEvent::SceneReplaced | SceneEvent::BvhRebuilt | SceneEvent::MeshAdded(_) => {
+                self.set_meshes(device, scene)
+            }
+            SceneEvent::EnvironmentChanged => self.environment_version += 1,
+            &SceneEvent::CanvasChanged { mesh, rows } => {
+                self.painted_canvases
+                    .entry(mesh)
+                    .and_modify(|painted| *painted = painted.union(rows))
+                    .or_insert(rows);
+                self.canvas_version += 1;
+            }
+            // Spheres and cameras are uploaded and compared every frame
+            _ => {}
+        }
+    }
+
+    /// Reads back the main view as last displayed. `None` if nothing has been displayed since the
+    /// view was last resized.
+    pub fn read_image(
+        &self,
+        device: &Device,
+        queue: &Queue,
+    ) -> Option<Result<RenderedImage, wgpu::BufferAsyncError>> {
+        let (width, height) = self.displayed_render_size;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let pixels = self
+            .main_viewport
+            .read_image(device, queue, (width, height));
+        Some(pixels.map(|pixels| RenderedImage {
+            width,
+            height,
+            pixels,
+        }))
+    }
+
+    /// What an image exported from the main view was rendered from, see [`ImageMetadata`].
+    pub fn image_metadata(&self, scene: &Scene) -> ImageMetadata {
+        let reference = self.settings.reference != 0;
+        let depth = if reference {
+            REFERENCE_DEPTH
+        } else {
+            self.settings.depth
+        };
+        // Reference mode ignores clamping and the caustic preview
+        let max_radiance = match self.settings.max_radiance {
+            max_radiance if max_radiance > 0.0 && !reference => max_radiance.to_string(),
+            _ => "off".to_string(),
+        };
+        let caustic_preview = self.settings.caustic_preview != 0 && !reference;
+        let render_scale = if self.upscaler.is_active() {
+            self.upscaler.render_scale
+        } else {
+            1.0
+        };
+        let display_lut = match &self.display_lut {
+            Some((path, _)) if self.settings.tone_mapping == DISPLAY_LUT_OPERATOR => {
+                path.display().to_string()
+            }
+            _ => "none".to_string(),
+        };
+        let camera = &scene.camera;
+        let vector = |v: Vector3<f32>| format!("{} {} {}", v.x, v.y, v.z);
+        [
+            ("Title", scene.name.clone()),
+            (
+                "Software",
+                format!("pathtracer {}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("Seed", scene.frame.to_string()),
+            ("Samples", self.main_viewport.samples().to_string()),
+            ("Depth", depth.to_string()),
+            ("Reference", reference.to_string()),
+            ("Max radiance", max_radiance),
+            ("Caustic preview", caustic_preview.to_string()),
+            ("Denoised", self.denoising().to_string()),
+            ("Render scale", render_scale.to_string()),
+            ("Exposure", format!("{} EV", self.tone_mapping().exposure)),
+            ("Display LUT", display_lut),
+            ("Camera origin", vector(camera.origin)),
+            ("Camera forward", vector(camera.forward)),
+            ("Camera up", vector(camera.up)),
+            ("Camera vfov", camera.vfov.to_string()),
+            ("Camera projection", format!("{:?}", camera.projection)),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+    }
+
+    /// Reads back what the main view shows at `position`, from (0, 0) at its top left to (1, 1)
+    /// at its bottom right, as the compute shader traced it. `None` if nothing has been displayed
+    /// since the view was last resized.
+    pub fn pick(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        position: (f64, f64),
+    ) -> Option<Result<Option<PickedObject>, wgpu::BufferAsyncError>> {
+        let (width, height) = self.displayed_render_size;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        // Casting saturates, so positions just outside the view pick from its edge
+        let pixel = (
+            ((position.0 * width as f64) as u32).min(width - 1),
+            ((position.1 * height as f64) as u32).min(height - 1),
+        );
+        Some(
+            self.main_viewport
+                .read_object_id(device, queue, (width, height), pixel),
+        )
+    }
+
+    /// The preview of the selected sphere's material, see [`MATERIAL_PREVIEW_SIZE`].
+    pub fn material_preview_view(&self) -> &wgpu::TextureView {
+        self.material_preview.view()
+    }
+
+    /// How many samples the main view is averaging, out of how many it is aiming for.
+    pub fn sample_progress(&self, is_moving: bool) -> (u32, u32) {
+        let samples = self.main_viewport.samples();
+        let target = match (self.progressive_rendering.enabled, is_moving) {
+            (false, _) => samples,
+            (true, true) => self.progressive_rendering.sample_size_while_moving,
+            (true, false) => self.progressive_rendering.sample_size,
+        };
+
+        (samples, target)
+    }
+
+    /// Shows the last shader compile error in a window until it is dismissed.
+    pub fn render_shader_error_ui(&mut self, context: &egui::Context) {
+        let Some(error) = &self.shader_error else {
+            return;
+        };
+
+        let mut dismissed = false;
+        egui::Window::new("Shader error")
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(context, |ui| {
+                ui.label(format!("{} failed to compile:", error.shader));
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        ui.monospace(&error.message);
+                    });
+                ui.label("Rendering continues with the last shader that compiled.");
+                dismissed = ui.button("Dismiss").clicked();
+            });
+        if dismissed {
+            self.shader_error = None;
+        }
+    }
+
+    pub fn render_mode(&self) -> String {
+        let mut mode = if self.progressive_rendering.enabled {
+            "progressive".to_string()
+        } else {
+            "real-time".to_string()
+        };
+        if self.settings.reference != 0 {
+            mode.push_str(", reference");
+        } else if self.settings.caustic_preview != 0 {
+            mode.push_str(", biased caustic preview");
+        }
+        if self.debug_view != DebugView::None {
+            mode.push_str(&format!(", {:?} view", self.debug_view).to_lowercase());
+        }
+        if self.denoising() {
+            mode.push_str(", denoised");
+            if self.denoiser.payload() == PayloadFormat::Packed {
+                mode.push_str(" (packed)");
+            }
+        }
+        if self.upscaler.is_active() {
+            mode.push_str(", upscaled");
+        }
+        if self.output_format == HDR_OUTPUT_FORMAT {
+            mode.push_str(", HDR");
+        }
+
+        mode
+    }
+
+    /// Recreates the main view's accumulation, the denoiser's buffers and the upscaler's input for
+    /// rendering to an output of `width` x `height`, if its size changed.
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        let (width, height) = fit_render_size(device, width, height);
+        if (width, height) == self.main_viewport.size() {
+            return;
+        }
+
+        self.main_viewport.resize(
+            device,
+            width,
+            height,
+            &self.compute_bind_group_layout,
+            &self.copy_bind_group_layout,
+            &self.resources,
+        );
+        self.upscaler.resize(device, width, height);
+        self.denoiser
+            .resize(device, &self.copy_bind_group_layout, width, height);
+        // The upscaler's input no longer holds an image to keep showing
+        self.displayed_render_size = (0, 0);
+    }
+
+    /// Uploads the scene's meshes and their BVHs in place of the ones the renderer was created
+    /// with, e.g. after loading another scene or rebuilding the BVHs.
+    fn set_meshes(&mut self, device: &Device, scene: &Scene) {
+        self.resources.set_meshes(device, scene.meshes());
+        self.triangle_materials = triangle_materials(scene.meshes());
+    }
+
+    pub fn render_settings(&self) -> RenderSettings {
+        RenderSettings {
+            settings: self.settings,
+            environment: self.environment,
+            progressive_rendering: self.progressive_rendering,
+            auto_exposure: self.auto_exposure.settings,
+            display_lut: self.display_lut.as_ref().map(|(path, _)| path.clone()),
+        }
+    }
+
+    /// Switches to `render_settings`, reloading their display LUT unless it's the one applied.
+    pub fn set_render_settings(&mut self, render_settings: RenderSettings) {
+        self.settings = render_settings.settings;
+        self.environment = render_settings.environment;
+        self.progressive_rendering = render_settings.progressive_rendering;
+        self.auto_exposure.settings = render_settings.auto_exposure;
+
+        let applied = self.display_lut.as_ref().map(|(path, _)| path);
+        if render_settings.display_lut.as_ref() != applied {
+            self.display_lut = render_settings.display_lut.and_then(|path| {
+                DisplayLut::load(&path)
+                    .map_err(|e| log::warn!("Failed to load {}: {}", path.display(), e))
+                    .ok()
+                    .map(|lut| (path, Arc::new(lut)))
+            });
+            self.display_lut_changed = true;
+        }
+    }
+
+    /// Recreates the passes that draw to the surface, e.g. when switching between SDR and HDR.
+    pub fn set_output_format(&mut self, device: &Device, format: TextureFormat) {
+        self.output_format = format;
+        self.upscaler.set_output_format(device, format);
+        self.picture_in_picture_pipeline = create_copy_pipeline(
+            device,
+            &self.copy_pipeline_layout,
+            self.shaders
+                .get(device, "copy.wgsl", &[])
+                .unwrap_or_else(|error| panic!("{}", error)),
+            "Picture-in-picture Pipeline",
+            format,
+        );
+    }
+
+    /// The factor the linear radiance is multiplied by before it is written to the output.
+    fn output_scale(&self) -> f32 {
+        if self.output_format == HDR_OUTPUT_FORMAT {
+            self.paper_white / SCRGB_WHITE_NITS
+        } else {
+            1.0
+        }
+    }
+
+    /// Uploads what changed in the buffers shared by every viewport and returns a hash of their
+    /// contents, which changes whenever the accumulated samples are no longer valid.
+    fn update_buffers(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        scene: &Scene,
+    ) -> u64 {
+        let material_data = MaterialDataBuffer::new(&scene.materials);
+        let sphere_data = SphereDataBuffer::new(scene);
+        let light_data = LightDataBuffer::new(scene);
+
+        // Exposure and tone mapping are applied when resolving, so the samples stay valid when
+        // they change
+        let traced_settings = Settings {
+            tone_mapping: 0,
+            exposure: 0.0,
+            ..self.settings
+        };
+        let mut hasher = DefaultHasher::new();
+        bytemuck::bytes_of(&material_data).hash(&mut hasher);
+        bytemuck::bytes_of(&sphere_data).hash(&mut hasher);
+        bytemuck::bytes_of(&light_data).hash(&mut hasher);
+        bytemuck::bytes_of(&traced_settings).hash(&mut hasher);
+        self.progressive_rendering.enabled.hash(&mut hasher);
+        self.environment_version.hash(&mut hasher);
+        self.canvas_version.hash(&mut hasher);
+        self.features.hash(&mut hasher);
+        let environment = EnvironmentBuffer::new(&self.environment, self.sun);
+        bytemuck::bytes_of(&environment).hash(&mut hasher);
+        scene.frame.hash(&mut hasher);
+
+        self.resources
+            .write_canvases(device, encoder, scene.meshes(), &self.painted_canvases);
+        self.painted_canvases.clear();
+        self.resources.generation().hash(&mut hasher);
+        let uploader = &mut self.uploader;
+        self.resources
+            .write_instances(uploader, device, encoder, scene);
+        if let Some(instances) = self.resources.instances() {
+            bytemuck::bytes_of(instances).hash(&mut hasher);
+        }
+        self.resources
+            .write_materials(uploader, device, encoder, &material_data);
+        self.resources
+            .write_spheres(uploader, device, encoder, &sphere_data);
+        self.resources
+            .write_settings(uploader, device, encoder, &self.settings);
+        self.resources
+            .write_environment(uploader, device, encoder, &environment);
+        self.resources
+            .write_analytic_lights(uploader, device, encoder, &light_data);
+
+        hasher.finish()
+    }
+
+    pub fn render(
+        &mut self,
+        output: &mut SurfaceTexture,
+        encoder: &mut CommandEncoder,
+        scene: &Scene,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let output_size = (output.texture.width(), output.texture.height());
+        // Reference mode leaves out every approximation, including the denoiser and upscaler
+        self.upscaler.bypassed = self.settings.reference != 0;
+        if !self.denoising() {
+            self.denoiser.reset_history();
+        }
+        // Stereo images keep their own shape, stretched to the output
+        let render_size = scene
+            .camera
+            .projection
+            .fit(self.upscaler.render_size(output_size.0, output_size.1));
+        self.profiler.begin_frame();
+        if std::mem::take(&mut self.display_lut_changed) {
+            self.display_lut_bind_group = display_lut::create_bind_group(
+                device,
+                queue,
+                &self.display_lut_bind_group_layout,
+                self.display_lut.as_ref().map(|(_, lut)| lut.as_ref()),
+            );
+        }
+
+        self.denoiser
+            .set_payload(device, &mut self.shaders, self.payload);
+        let is_shadow_catcher = |id: &MaterialId| {
+            scene
+                .materials
+                .get(*id)
+                .is_some_and(|material| material.material == Material::ShadowCatcher)
+        };
+        let features = ShaderFeatures {
+            reference: self.settings.reference != 0,
+            caustics: self.settings.caustic_preview != 0 && self.settings.reference == 0,
+            shadow_catcher: self.triangle_materials.iter().any(is_shadow_catcher)
+                || scene
+                    .spheres()
+                    .iter()
+                    .any(|sphere| is_shadow_catcher(&sphere.surface.material)),
+            debug_view: self.debug_view,
+            illuminance: self.false_color.quantity() == Some(FalseColorQuantity::Illuminance),
+            denoise: self.denoising(),
+            // Left alone while not denoising so toggling it doesn't compile another variant
+            payload: if self.denoising() {
+                self.denoiser.payload()
+            } else {
+                PayloadFormat::Full
+            },
+            workgroup_size: self.workgroup_size,
+        };
+        if let Some(error) = self
+            .compute_pipelines
+            .prepare(device, &mut self.shaders, features)
+        {
+            log::warn!("{}", error);
+            self.shader_error = Some(error);
+        }
+        // Keep tracing with the last variant that compiled rather than stopping altogether
+        if self.compute_pipelines.is_compiled(features) {
+            self.features = features;
+        }
+
+        // Picks up the fence and read backs of earlier frames
+        device.poll(wgpu::Maintain::Poll);
+        if self.trace_done.load(Ordering::Acquire) {
+            let mut trace_encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Trace Encoder"),
+                });
+            self.scene_state = self.update_buffers(device, &mut trace_encoder, scene);
+            self.trace_main_viewport(device, &mut trace_encoder, scene, render_size);
+            self.uploader.finish();
+
+            // Submitted on its own so the next frames can tell when the GPU is done with it, and
+            // only draw the UI until then rather than queueing up more tracing behind it
+            self.trace_done.store(false, Ordering::Release);
+            queue.submit([trace_encoder.finish()]);
+            let trace_done = self.trace_done.clone();
+            queue.on_submitted_work_done(move || trace_done.store(true, Ordering::Release));
+        }
+        let scene_state = self.scene_state;
+
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.upscaler.upscale(
+            device,
+            encoder,
+            &mut self.uploader,
+            &view,
+            render_size,
+            output_size,
+        );
+
+        let active_material = scene
+            .active_sphere()
+            .and_then(|sphere| scene.materials.get(sphere.surface.material));
+        if let Some(material) = active_material {
+            self.material_preview.render(
+                device,
+                encoder,
+                &mut self.uploader,
+                self.compute_pipelines.get(self.features),
+                &self.copy_pipeline,
+                &self.display_lut_bind_group,
+                &self.progressive_rendering,
+                &self.settings,
+                material,
+                &mut self.profiler,
+            );
+        }
+
+        if self.picture_in_picture {
+            self.picture_in_picture_viewport.bind(
+                device,
+                &self.compute_bind_group_layout,
+                &self.resources,
+            );
+            self.render_picture_in_picture(device, encoder, scene, scene_state, &view, output_size);
+        }
+        self.profiler.resolve(encoder);
+        // Nothing else is written this frame
+        self.uploader.finish();
+
+        Ok(())
+    }
+
+    /// Traces this frame's batch of samples into the main viewport, then denoises and resolves
+    /// them into the upscaler's input unless a sample is only partly traced.
+    fn trace_main_viewport(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        scene: &Scene,
+        render_size: (u32, u32),
+    ) {
+        self.main_viewport
+            .bind(device, &self.compute_bind_group_layout, &self.resources);
+        self.main_viewport
+            .update_state(self.scene_state, &scene.camera, render_size);
+
+        let output_scale = self.output_scale();
+        self.auto_exposure.update();
+        let tone_mapping = self.tone_mapping();
+        // Several samples a frame only add up while still, the moving average would forget them
+        let batch = self.batching.begin_frame();
+        let batch = if self.progressive_rendering.enabled && !scene.camera.moved_recently() {
+            batch
+        } else {
+            Batch {
+                samples: 1,
+                ..batch
+            }
+        };
+        self.batching.begin(encoder);
+        let mut traced = 0.0;
+        for _ in 0..batch.dispatches() {
+            let share = self.main_viewport.trace(
+                device,
+                encoder,
+                &mut self.uploader,
+                self.compute_pipelines.get(self.features),
+                Some(PhotonPass {
+                    grid: self.resources.caustic_grid(),
+                    photons: self.settings.photons,
+                }),
+                &scene.camera,
+                &self.progressive_rendering,
+                render_size,
+                scene.frame,
+                batch.bands,
+                (0, 0),
+                output_scale,
+                self.false_color.scale(scene.nits_per_unit),
+                &tone_mapping,
+                &mut self.profiler,
+            );
+            if share == 0.0 {
+                break;
+            }
+            traced += share;
+        }
+        self.batching.end(encoder, traced);
+
+        let warming_up = self
+            .progressive_rendering
+            .is_warming_up(self.main_viewport.samples(), scene.camera.moved_recently())
+            && render_size == self.displayed_render_size;
+        if !warming_up && !self.main_viewport.is_mid_sample() {
+            if self.denoising() {
+                self.denoiser.denoise(
+                    device,
+                    encoder,
+                    &mut self.uploader,
+                    &self.main_viewport,
+                    &scene.camera,
+                    render_size,
+                    &mut self.profiler,
+                );
+            }
+            self.resolve(encoder, render_size);
+            self.auto_exposure.measure(
+                device,
+                encoder,
+                &mut self.uploader,
+                &self.main_viewport,
+                render_size,
+                &mut self.profiler,
+            );
+        }
+    }
+
+    /// The settings' tone mapping, with the automatic exposure added to theirs.
+    pub fn tone_mapping(&self) -> ToneMapping {
+        let tone_mapping = self.settings.tone_mapping();
+        ToneMapping {
+            exposure: tone_mapping.exposure + self.auto_exposure.ev(),
+            display_lut: self.display_lut.as_ref().map(|(_, lut)| lut.clone()),
+            ..tone_mapping
+        }
+    }
+
+    /// Lets the uploads of the frames submitted so far be reused once the GPU is done with them,
+    /// and starts reading back the frame's statistics and timings. Called after submitting the
+    /// encoder given to [`Self::render`].
+    pub fn after_submit(&mut self) {
+        self.uploader.recall();
+        self.profiler.read_back();
+        self.batching.read_back();
+        self.auto_exposure.read_back();
+    }
+
+    /// Averages the main viewport's samples into the upscaler's input.
+    fn resolve(&mut self, encoder: &mut CommandEncoder, render_size: (u32, u32)) {
+        self.displayed_render_size = render_size;
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: self.upscaler.input_view(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.1,
+                        g: 0.2,
+                        b: 0.3,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_viewport(
+            0.0,
+            0.0,
+            render_size.0 as f32,
+            render_size.1 as f32,
+            0.0,
+            1.0,
+        );
+        let copy_bind_group = if self.denoising() {
+            self.denoiser.copy_bind_group()
+        } else {
+            self.main_viewport.copy_bind_group()
+        };
+        render_pass.set_bind_group(0, copy_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.display_lut_bind_group, &[]);
+        render_pass.set_pipeline(&self.copy_pipeline);
+        render_pass.draw(0..3, 0..2);
+    }
+
+    /// Renders the scene's final camera into an inset in the bottom right corner of `output`.
+    fn render_picture_in_picture(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        scene: &Scene,
+        scene_state: u64,
+        output: &wgpu::TextureView,
+        output_size: (u32, u32),
+    ) {
+        let (max_width, max_height) = self.picture_in_picture_viewport.size();
+        let size = (
+            max_width.min(output_size.0.saturating_sub(2 * PICTURE_IN_PICTURE_MARGIN)),
+            max_height.min(output_size.1.saturating_sub(2 * PICTURE_IN_PICTURE_MARGIN)),
+        );
+        if size.0 == 0 || size.1 == 0 {
+            return;
+        }
+        let offset = (
+            output_size.0 - size.0 - PICTURE_IN_PICTURE_MARGIN,
+            output_size.1 - size.1 - PICTURE_IN_PICTURE_MARGIN,
+        );
+
+        self.picture_in_picture_viewport
+            .update_state(scene_state, &scene.final_camera, size);
+
+        let output_scale = self.output_scale();
+        let tone_mapping = self.tone_mapping();
+        self.picture_in_picture_viewport.trace(
+            device,
+            encoder,
+            &mut self.uploader,
+            self.compute_pipelines.get(self.features),
+            None,
+            &scene.final_camera,
+            &self.progressive_rendering,
+            size,
+            scene.frame,
+            1,
+            offset,
+            output_scale,
+            self.false_color.scale(scene.nits_per_unit),
+            &tone_mapping,
+            &mut self.profiler,
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Picture-in-picture Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_viewport(
+            offset.0 as f32,
+            offset.1 as f32,
+            size.0 as f32,
+            size.1 as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_bind_group(0, self.picture_in_picture_viewport.copy_bind_group(), &[]);
+        render_pass.set_bind_group(1, &self.display_lut_bind_group, &[]);
+        render_pass.set_pipeline(&self.picture_in_picture_pipeline);
+        render_pass.draw(0..3, 0..2);
+    }
+}
+
+fn create_copy_pipeline(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    label: &str,
+    format: TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+struct Settings {
+    samples_per_pixel: u32,
+    depth: u32,
+    t_min: f32,
+    t_max: f32,
+    /// Non-zero for reference mode: a fresh random number per bounce and a fixed, high depth.
+    reference: u32,
+    /// An index into [`TONE_MAPPING_OPERATORS`], only used when resolving.
+    #[serde(default)]
+    tone_mapping: u32,
+    /// In EV stops, applied before tone mapping.
+    #[serde(default)]
+    exposure: f32,
+    /// The brightest a sample can be, zero for no clamping. Ignored in reference mode.
+    #[serde(default)]
+    max_radiance: f32,
+    /// Non-zero to light diffuse surfaces through metals and glass with photons instead of the
+    /// paths that find the lights by chance, a biased preview. Ignored in reference mode.
+    #[serde(default)]
+    caustic_preview: u32,
+    /// The size of the cells photons are gathered over.
+    #[serde(default = "default_caustic_radius")]
+    caustic_radius: f32,
+    /// How many photons are emitted for each sample.
+    #[serde(default = "default_photons")]
+    photons: u32,
+}
+
+fn default_caustic_radius() -> f32 {
+    0.05
+}
+
+fn default_photons() -> u32 {
+    65536
+}
+
+impl Settings {
+    /// Without a display LUT, which the renderer adds.
+    fn tone_mapping(&self) -> ToneMapping {
+        ToneMapping {
+            exposure: self.exposure,
+            operator: self.tone_mapping,
+            display_lut: None,
+        }
+    }
+}
+
+/// How radiance is turned into displayed colors when resolving.
+#[derive(Clone, Debug)]
+pub struct ToneMapping {
+    /// In EV stops.
+    exposure: f32,
+    /// An index into [`TONE_MAPPING_OPERATORS`].
+    operator: u32,
+    /// Applied by [`DISPLAY_LUT_OPERATOR`], which leaves radiance as the linear operator does
+    /// without one.
+    display_lut: Option<Arc<DisplayLut>>,
+}
+
+impl ToneMapping {
+    /// Exposes and maps linear radiance to what the copy shader displays for it, keeping the
+    /// alpha, for images exported to formats that can't hold radiance.
+    pub fn apply(&self, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        let exposure = 2f32.powf(self.exposure);
+        if let (DISPLAY_LUT_OPERATOR, Some(lut)) = (self.operator, &self.display_lut) {
+            // The LUT gives sRGB encoded values, decoded to be encoded again when written
+            let [r, g, b] = lut.apply([r * exposure, g * exposure, b * exposure]);
+            return [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a];
+        }
+        let map = |x: f32| match self.operator {
+            1 => x / (1.0 + x),
+            2 => aces(x),
+            3 => {
+                // Hable's filmic curve, with white at 11.2
+                let exposure_bias = 2.0;
+                uncharted2(x * exposure_bias) / uncharted2(11.2)
+            }
+            _ => x,
+        };
+        [map(r * exposure), map(g * exposure), map(b * exposure), a]
+    }
+}
+
+/// Narkowicz's fit of the ACES filmic curve, as in the copy shader.
+fn aces(x: f32) -> f32 {
+    let x = x * 0.6;
+    ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+}
+
+/// Matches `srgbToLinear` in the copy shader.
+fn srgb_to_linear(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn uncharted2(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+/// How the HDRI lights the scene and whether camera rays see it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+struct EnvironmentSettings {
+    /// Rotation around the vertical axis, in radians.
+    yaw: f32,
+    intensity: f32,
+    show_background: u32,
+    /// Scales the angular size of the HDRI's sun, keeping its power.
+    #[serde(default = "default_sun_scale")]
+    sun_size: f32,
+    /// Seen by camera rays that miss the scene when `show_background` is off.
+    background_color: [f32; 3],
+    /// Multiplies the HDRI's sun on top of `intensity`.
+    #[serde(default = "default_sun_scale")]
+    sun_intensity: f32,
+}
+
+fn default_sun_scale() -> f32 {
+    1.0
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            intensity: 1.0,
+            show_background: 1,
+            sun_size: 1.0,
+            background_color: [0.05, 0.05, 0.05],
+            sun_intensity: 1.0,
+        }
+    }
+}
+
+/// The environment as the shaders see it, with the sun its settings resize and boost.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentBuffer {
+    settings: EnvironmentSettings,
+    sun_direction: [f32; 3],
+    /// In radians, zero when the HDRI has no sun.
+    sun_radius: f32,
+}
+
+impl EnvironmentBuffer {
+    fn new(settings: &EnvironmentSettings, sun: Option<texture::Sun>) -> Self {
+        Self {
+            settings: *settings,
+            sun_direction: sun.map_or([0.0, 1.0, 0.0], |sun| sun.direction),
+            sun_radius: sun.map_or(0.0, |sun| sun.angular_radius),
+        }
+    }
+}
+
+/// What the false color view shows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FalseColorQuantity {
+    /// The luminance of what's seen, in nits (cd/m²).
+    Luminance,
+    /// The illuminance reaching the surfaces seen, in lux, measured by tracing them as if they
+    /// were white and diffuse.
+    Illuminance,
+}
+
+impl FalseColorQuantity {
+    fn unit(&self) -> &'static str {
+        match self {
+            Self::Luminance => "nits",
+            Self::Illuminance => "lux",
+        }
+    }
+}
+
+/// Replaces the image with bands of approximate luminance or illuminance, one per decade, for
+/// judging lighting levels in physical units. Exposure and tone mapping are left out.
+struct FalseColor {
+    enabled: bool,
+    quantity: FalseColorQuantity,
+}
+
+impl FalseColor {
+    /// The colors of the bands drawn by the copy shader, from below 1 to above 100000.
+    const BANDS: [[u8; 3]; 7] = [
+        [20, 0, 60],
+        [0, 60, 255],
+        [0, 200, 200],
+        [0, 200, 0],
+        [255, 230, 0],
+        [255, 120, 0],
+        [255, 0, 0],
+    ];
+
+    fn quantity(&self) -> Option<FalseColorQuantity> {
+        self.enabled.then_some(self.quantity)
+    }
+
+    /// What the copy shader multiplies the rendered luminance by for the bands, given the
+    /// scene's `nits_per_unit`, or `None` while false color is off. A white diffuse surface
+    /// reflects 1 / π of the illuminance reaching it.
+    fn scale(&self, nits_per_unit: f32) -> Option<f32> {
+        self.quantity().map(|quantity| match quantity {
+            FalseColorQuantity::Luminance => nits_per_unit,
+            FalseColorQuantity::Illuminance => nits_per_unit * std::f32::consts::PI,
+        })
+    }
+
+    fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("False color", |ui| {
+            ui.checkbox(&mut self.enabled, "enabled").on_hover_text(
+                "Converted by the scene's nits per unit radiance, which is set with the lights",
+            );
+            ui.add_enabled_ui(self.enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(
+                        &mut self.quantity,
+                        FalseColorQuantity::Luminance,
+                        "luminance",
+                    );
+                    ui.radio_value(
+                        &mut self.quantity,
+                        FalseColorQuantity::Illuminance,
+                        "illuminance",
+                    )
+                    .on_hover_text("What lights the surfaces, whatever they're made of");
+                });
+            });
+
+            let unit = self.quantity.unit();
+            for (i, [r, g, b]) in Self::BANDS.into_iter().enumerate() {
+                let label = match i {
+                    0 => format!("< 1 {}", unit),
+                    6 => format!("> 100000 {}", unit),
+                    _ => format!(
+                        "{} - {} {}",
+                        10u32.pow(i as u32 - 1),
+                        10u32.pow(i as u32),
+                        unit
+                    ),
+                };
+                ui.horizontal(|ui| {
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::Vec2::splat(12.0), egui::Sense::hover());
+                    ui.painter()
+                        .rect_filled(rect, 0.0, egui::Color32::from_rgb(r, g, b));
+                    ui.label(label);
+                });
+            }
+        });
+    }
+}
+
+/// A render read back from the GPU, in linear radiance.
+pub struct RenderedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+/// The renderer's settings that are saved along with a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderSettings {
+    settings: Settings,
+    environment: EnvironmentSettings,
+    progressive_rendering: ProgressiveRendering,
+    #[serde(default)]
+    auto_exposure: AutoExposureSettings,
+    /// The `.cube` file of the display LUT operator, if one is loaded.
+    #[serde(default)]
+    display_lut: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ProgressiveRendering {
+    enabled: bool,
+    sample_size: u32,
+    sample_size_while_moving: u32,
+    samples_before_display: u32,
+}
+
+impl ProgressiveRendering {
+    /// Returns how many samples a viewport's still accumulation will be worth after adding
+    /// another one to the `samples` it has, and how much of the accumulated sum to keep. `None`
+    /// when it already has enough.
+    fn accumulate_still(&self, samples: u32, restart: bool) -> Option<(u32, f32)> {
+        if !self.enabled || restart {
+            return Some((1, 0.0));
+        }
+
+        (samples < self.sample_size).then_some((samples + 1, 1.0))
+    }
+
+    /// Like [`Self::accumulate_still`] for the moving accumulation, which is a moving average over
+    /// roughly the last few samples so the view stays responsive.
+    fn accumulate_moving(&self, samples: u32, restart: bool) -> (u32, f32) {
+        if restart || samples == 0 {
+            return (1, 0.0);
+        }
+
+        let next = u32::min(samples + 1, self.sample_size_while_moving);
+        (next, (next - 1) as f32 / samples as f32)
+    }
+
+    /// Whether the previous image should stay on screen because too few samples have been
+    /// accumulated since the last reset. Never the case while moving, so the view stays responsive.
+    fn is_warming_up(&self, samples: u32, is_moving: bool) -> bool {
+        self.enabled && !is_moving && samples < self.samples_before_display
+    }
+}
+
+fn triangle_materials(meshes: &[Mesh]) -> BTreeSet<MaterialId> {
+    meshes
+        .iter()
+        .flat_map(|mesh| &mesh.triangles)
+        .map(|triangle| triangle.material)
+        .collect()
+}
+
+/// The largest size up to `width` x `height`, keeping the aspect ratio, whose accumulation
+/// buffer and textures fit in the device's limits.
+fn fit_render_size(device: &Device, width: u32, height: u32) -> (u32, u32) {
+    let limits = device.limits();
+    let max_bytes = u64::min(
+        limits.max_storage_buffer_binding_size as u64,
+        limits.max_buffer_size,
+    );
+    let max_pixels = max_bytes / std::mem::size_of::<[f32; 4]>() as u64;
+    let max_dimension = limits.max_texture_dimension_2d;
+
+    let pixels = width as u64 * height as u64;
+    let scale = if pixels > max_pixels {
+        (max_pixels as f64 / pixels as f64).sqrt()
+    } else {
+        1.0
+    };
+
+    (
+        ((width as f64 * scale) as u32).clamp(1, max_dimension),
+        ((height as f64 * scale) as u32).clamp(1, max_dimension),
+    )
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroupLayout, Device};
+
+use crate::utils::{ShaderCache, ShaderError};
+
+/// A view of the scene for debugging instead of the traced image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DebugView {
+    #[default]
+    None,
+    /// The shading normal of the first surface hit, mapped to colors.
+    Normals,
+}
+
+/// How the G-buffer and the denoiser's intermediate colors are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PayloadFormat {
+    /// 32-bit floats throughout.
+    #[default]
+    Full,
+    /// Octahedral normals and RGBM colors in half the space, for GPUs held back by memory
+    /// bandwidth. Colors brighter than the RGBM range are clipped before the image is shown.
+    Packed,
+}
+
+impl PayloadFormat {
+    pub fn defines(&self) -> &'static [&'static str] {
+        match self {
+            Self::Full => &[],
+            Self::Packed => &["PACKED_PAYLOAD"],
+        }
+    }
+
+    /// The size of an entry of the G-buffer.
+    pub fn surface_size(&self) -> u64 {
+        match self {
+            Self::Full => std::mem::size_of::<[f32; 4]>() as u64,
+            Self::Packed => std::mem::size_of::<[u32; 2]>() as u64,
+        }
+    }
+}
+
+/// The size of the workgroups the compute shader is dispatched in, which suits some GPUs better
+/// than others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum WorkgroupSize {
+    Square8,
+    #[default]
+    Square16,
+    Wide32,
+}
+
+impl WorkgroupSize {
+    pub const ALL: [Self; 3] = [Self::Square8, Self::Square16, Self::Wide32];
+
+    /// The invocations along x and y.
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            Self::Square8 => (8, 8),
+            Self::Square16 => (16, 16),
+            Self::Wide32 => (32, 8),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let (x, y) = self.size();
+        format!("{}×{}", x, y)
+    }
+
+    fn defines(&self) -> &'static [&'static str] {
+        match self {
+            Self::Square8 => &["WORKGROUP_8X8"],
+            Self::Square16 => &[],
+            Self::Wide32 => &["WORKGROUP_32X8"],
+        }
+    }
+}
+
+/// Optional parts of the compute shader, only compiled in when enabled so they cost nothing
+/// otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ShaderFeatures {
+    /// Fresh random numbers for every bounce and long paths, see reference mode.
+    pub reference: bool,
+    /// Caustics gathered from photons emitted before each sample, see the caustic preview.
+    pub caustics: bool,
+    /// Whether anything in the scene is a shadow catcher.
+    pub shadow_catcher: bool,
+    pub debug_view: DebugView,
+    /// The first surface each camera ray hits shaded as white and diffuse, for the illuminance
+    /// false color view.
+    pub illuminance: bool,
+    /// Whether the denoiser needs the G-buffer written.
+    pub denoise: bool,
+    /// The format of the G-buffer, only relevant while denoising.
+    pub payload: PayloadFormat,
+    pub workgroup_size: WorkgroupSize,
+}
+
+impl ShaderFeatures {
+    fn defines(&self) -> Vec<&'static str> {
+        let mut defines = Vec::new();
+        if self.reference {
+            defines.push("REFERENCE");
+        }
+        if self.caustics {
+            defines.push("CAUSTICS");
+        }
+        if self.shadow_catcher {
+            defines.push("SHADOW_CATCHER");
+        }
+        if self.illuminance {
+            defines.push("ILLUMINANCE");
+        }
+        if self.denoise {
+            defines.push("DENOISE");
+        }
+        defines.extend(self.payload.defines());
+        defines.extend(self.workgroup_size.defines());
+        match self.debug_view {
+            DebugView::None => {}
+            DebugView::Normals => defines.push("DEBUG_NORMALS"),
+        }
+        defines
+    }
+}
+
+/// A compute pipeline for each combination of [`ShaderFeatures`], compiled the first time it's
+/// needed. Variants that fail to compile are remembered so they aren't retried every frame.
+pub struct ComputePipelines {
+    layout: wgpu::PipelineLayout,
+    pipelines: HashMap<ShaderFeatures, Result<Variant, ShaderError>>,
+}
+
+/// The entry points compiled from one variant of the compute shader.
+struct Variant {
+    trace: wgpu::ComputePipeline,
+    /// Only compiled with caustics.
+    emit_photons: Option<wgpu::ComputePipeline>,
+}
+
+impl ComputePipelines {
+    pub fn new(device: &Device, bind_group_layout: &BindGroupLayout) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compute Pipeline Layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            layout,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Compiles the pipeline for `features` unless it already has been, returning the error if
+    /// it has just failed to.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        shaders: &mut ShaderCache,
+        features: ShaderFeatures,
+    ) -> Option<ShaderError> {
+        if self.pipelines.contains_key(&features) {
+            return None;
+        }
+
+        let pipeline = shaders
+            .get(device, "compute.wgsl", &features.defines())
+            .map(|module| {
+                let create = |label: &str, entry_point| {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: Some(&format!("{} {:?}", label, features)),
+                        layout: Some(&self.layout),
+                        module,
+                        entry_point,
+                    })
+                };
+                Variant {
+                    trace: create("Compute Pipeline", "main"),
+                    emit_photons: features
+                        .caustics
+                        .then(|| create("Photon Pipeline", "emitPhotons")),
+                }
+            });
+        let error = pipeline.as_ref().err().cloned();
+        self.pipelines.insert(features, pipeline);
+        error
+    }
+
+    /// Whether the pipeline for `features` has been compiled successfully.
+    pub fn is_compiled(&self, features: ShaderFeatures) -> bool {
+        matches!(self.pipelines.get(&features), Some(Ok(_)))
+    }
+
+    /// The pipeline for `features`, which must have been compiled by [`Self::prepare`].
+    pub fn get(&self, features: ShaderFeatures) -> TracePipeline<'_> {
+        match &self.pipelines[&features] {
+            Ok(variant) => TracePipeline {
+                pipeline: &variant.trace,
+                emit_photons: variant.emit_photons.as_ref(),
+                workgroup_size: features.workgroup_size.size(),
+            },
+            Err(error) => panic!("{}", error),
+        }
+    }
+}
+
+/// A variant of the compute shader, along with the workgroup size it's dispatched in.
+#[derive(Clone, Copy)]
+pub struct TracePipeline<'a> {
+    pub pipeline: &'a wgpu::ComputePipeline,
+    /// Fills the caustic grid before each sample, when the variant gathers caustics from it.
+    pub emit_photons: Option<&'a wgpu::ComputePipeline>,
+    pub workgroup_size: (u32, u32),
+}
@@ -0,0 +1,195 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::assets;
+
+/// The file the presets are kept in, under [`assets::config_dir`].
+const PRESETS_FILE_NAME: &str = "quality_presets.json";
+
+/// Render settings that are switched between together, from a single click or shortcut.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityPreset {
+    pub name: String,
+    pub samples_per_pixel: u32,
+    pub depth: u32,
+    /// The brightest a sample can be, zero for no clamping.
+    pub max_radiance: f32,
+    /// How many samples progressive rendering accumulates while still.
+    pub samples: u32,
+    pub denoise: bool,
+    /// The fraction of the output resolution traced, upscaled when below one.
+    pub render_scale: f32,
+}
+
+impl QualityPreset {
+    fn defaults() -> Vec<Self> {
+        vec![
+            Self {
+                name: "Draft".to_string(),
+                samples_per_pixel: 1,
+                depth: 4,
+                max_radiance: 4.0,
+                samples: 64,
+                denoise: true,
+                render_scale: 0.5,
+            },
+            Self {
+                name: "Preview".to_string(),
+                samples_per_pixel: 1,
+                depth: 8,
+                max_radiance: 16.0,
+                samples: 512,
+                denoise: true,
+                render_scale: 0.75,
+            },
+            Self {
+                name: "Final".to_string(),
+                samples_per_pixel: 4,
+                depth: 32,
+                max_radiance: 0.0,
+                samples: 4096,
+                denoise: false,
+                render_scale: 1.0,
+            },
+        ]
+    }
+}
+
+/// The user's quality presets, saved whenever they're changed so they carry over between
+/// sessions and scenes.
+pub struct QualityPresets {
+    pub presets: Vec<QualityPreset>,
+    /// `None` if there's nowhere to save them.
+    path: Option<PathBuf>,
+    /// The name typed in for saving the current settings as a new preset.
+    new_name: String,
+    /// Why the presets couldn't be saved, until they next are.
+    error: Option<String>,
+}
+
+/// What was clicked in [`QualityPresets::render_ui`].
+pub enum PresetRequest {
+    Apply(usize),
+    /// Overwrite the preset with the current settings.
+    Update(usize),
+    /// Add the current settings as a new preset with the name.
+    Add(String),
+}
+
+impl QualityPresets {
+    /// Reads the saved presets, or starts from Draft, Preview and Final if there aren't any.
+    pub fn load() -> Self {
+        let path = assets::config_dir().map(|directory| directory.join(PRESETS_FILE_NAME));
+        let presets = path
+            .as_ref()
+            .and_then(|path| match fs::read(path) {
+                Ok(contents) => serde_json::from_slice(&contents)
+                    .map_err(|e| log::warn!("Ignoring invalid {}: {}", path.display(), e))
+                    .ok(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    log::warn!("Failed to read {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_else(QualityPreset::defaults);
+
+        Self {
+            presets,
+            path,
+            new_name: String::new(),
+            error: None,
+        }
+    }
+
+    pub fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                let json = serde_json::to_vec_pretty(&self.presets)?;
+                fs::write(path, json)
+            });
+        self.error = result
+            .map_err(|e| {
+                log::warn!(
+                    "Failed to save quality presets to {}: {}",
+                    path.display(),
+                    e
+                );
+                e.to_string()
+            })
+            .err();
+    }
+
+    /// `active` is the index of the preset matching the current settings, if any.
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, active: Option<usize>) -> Option<PresetRequest> {
+        let mut request = None;
+        let mut removed = None;
+        let mut changed = false;
+
+        egui::Grid::new("quality presets").show(ui, |ui| {
+            for (i, preset) in self.presets.iter_mut().enumerate() {
+                if ui
+                    .selectable_label(active == Some(i), format!("{}", i + 1))
+                    .on_hover_text("Apply, also bound to the \"Apply quality preset\" shortcuts")
+                    .clicked()
+                {
+                    request = Some(PresetRequest::Apply(i));
+                }
+                changed |= ui
+                    .add(egui::TextEdit::singleline(&mut preset.name).desired_width(80.0))
+                    .lost_focus();
+                if ui
+                    .button("Update")
+                    .on_hover_text("Replace the preset with the current settings")
+                    .clicked()
+                {
+                    request = Some(PresetRequest::Update(i));
+                }
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.new_name)
+                    .hint_text("name")
+                    .desired_width(80.0),
+            );
+            if ui
+                .add_enabled(
+                    !self.new_name.trim().is_empty(),
+                    egui::Button::new("Save current as preset"),
+                )
+                .clicked()
+            {
+                request = Some(PresetRequest::Add(self.new_name.trim().to_string()));
+                self.new_name.clear();
+            }
+        });
+        if ui.button("Reset to defaults").clicked() {
+            self.presets = QualityPreset::defaults();
+            changed = true;
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        if let Some(i) = removed {
+            self.presets.remove(i);
+        }
+        if removed.is_some() || changed {
+            self.save();
+        }
+        request
+    }
+}
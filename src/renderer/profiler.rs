@@ -0,0 +1,217 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use wgpu::{Buffer, BufferUsages, CommandEncoder, ComputePass, Device, QuerySet};
+
+/// The most compute passes counted in one frame.
+const MAX_PASSES: u32 = 32;
+
+/// Counts the invocations of each compute pass with pipeline statistics queries, where the
+/// adapter supports them, to guide workgroup size tuning.
+pub struct Profiler {
+    pub enabled: bool,
+    /// `None` when pipeline statistics aren't supported.
+    queries: Option<Queries>,
+    /// The passes counted in the frame being recorded, by query index.
+    passes: Vec<Pass>,
+    /// The passes of a frame being read back, and whether its results are mapped yet. Nothing is
+    /// counted while the read back buffer is in use.
+    in_flight: Option<(Vec<Pass>, Arc<AtomicBool>)>,
+    /// The passes of the last frame read back, with their invocations.
+    results: Vec<(Pass, u64)>,
+}
+
+struct Queries {
+    set: QuerySet,
+    resolve_buffer: Buffer,
+    read_back_buffer: Buffer,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pass {
+    name: &'static str,
+    workgroups: u64,
+    /// The invocations per workgroup.
+    workgroup_size: u64,
+    /// The invocations that had something to do, e.g. one per pixel.
+    work_items: u64,
+}
+
+impl Profiler {
+    pub fn new(device: &Device) -> Self {
+        let supported = device
+            .features()
+            .contains(wgpu::Features::PIPELINE_STATISTICS_QUERY);
+        let buffer = |label, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: MAX_PASSES as u64 * std::mem::size_of::<u64>() as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let queries = supported.then(|| Queries {
+            set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pipeline Statistics"),
+                ty: wgpu::QueryType::PipelineStatistics(
+                    wgpu::PipelineStatisticsTypes::COMPUTE_SHADER_INVOCATIONS,
+                ),
+                count: MAX_PASSES,
+            }),
+            resolve_buffer: buffer(
+                "Pipeline Statistics Resolve Buffer",
+                BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            ),
+            read_back_buffer: buffer(
+                "Pipeline Statistics Read Back Buffer",
+                BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            ),
+        });
+
+        Self {
+            enabled: false,
+            queries,
+            passes: Vec::new(),
+            in_flight: None,
+            results: Vec::new(),
+        }
+    }
+
+    /// Collects the results of an earlier frame if they've arrived, before recording a new one.
+    pub fn begin_frame(&mut self) {
+        self.passes.clear();
+        let (Some(queries), Some((passes, mapped))) = (&self.queries, &self.in_flight) else {
+            return;
+        };
+        if !mapped.load(Ordering::Acquire) {
+            return;
+        }
+
+        let slice = queries
+            .read_back_buffer
+            .slice(..passes.len() as u64 * std::mem::size_of::<u64>() as u64);
+        let invocations: Vec<u64> = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        queries.read_back_buffer.unmap();
+        self.results = passes.iter().copied().zip(invocations).collect();
+        self.in_flight = None;
+    }
+
+    /// Allocates a query for a compute pass dispatching `dispatch` workgroups of
+    /// `workgroup_size` to process `work_items`, if this frame is being counted.
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        dispatch: (u32, u32),
+        workgroup_size: (u32, u32),
+        work_items: u64,
+    ) -> Option<u32> {
+        let counting = self.enabled && self.queries.is_some() && self.in_flight.is_none();
+        if !counting || self.passes.len() as u32 >= MAX_PASSES {
+            return None;
+        }
+        self.passes.push(Pass {
+            name,
+            workgroups: dispatch.0 as u64 * dispatch.1 as u64,
+            workgroup_size: workgroup_size.0 as u64 * workgroup_size.1 as u64,
+            work_items,
+        });
+        Some(self.passes.len() as u32 - 1)
+    }
+
+    /// Starts counting for the `query` from [`Self::add_pass`], before the pass dispatches.
+    pub fn begin_query<'a>(&'a self, pass: &mut ComputePass<'a>, query: Option<u32>) {
+        if let (Some(queries), Some(query)) = (&self.queries, query) {
+            pass.begin_pipeline_statistics_query(&queries.set, query);
+        }
+    }
+
+    pub fn end_query(&self, pass: &mut ComputePass, query: Option<u32>) {
+        if query.is_some() {
+            pass.end_pipeline_statistics_query();
+        }
+    }
+
+    /// Copies the frame's counts to be read back, after its last pass.
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        let Some(queries) = &self.queries else {
+            return;
+        };
+        if self.passes.is_empty() {
+            return;
+        }
+
+        let count = self.passes.len() as u32;
+        encoder.resolve_query_set(&queries.set, 0..count, &queries.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &queries.read_back_buffer,
+            0,
+            count as u64 * std::mem::size_of::<u64>() as u64,
+        );
+    }
+
+    /// Starts reading back the counts, once the frame that resolved them has been submitted.
+    pub fn read_back(&mut self) {
+        let Some(queries) = &self.queries else {
+            return;
+        };
+        if self.passes.is_empty() || self.in_flight.is_some() {
+            return;
+        }
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let on_mapped = mapped.clone();
+        queries
+            .read_back_buffer
+            .slice(..self.passes.len() as u64 * std::mem::size_of::<u64>() as u64)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if let Err(e) = result {
+                    log::warn!("Failed to read back pipeline statistics: {}", e);
+                    return;
+                }
+                on_mapped.store(true, Ordering::Release);
+            });
+        self.in_flight = Some((std::mem::take(&mut self.passes), mapped));
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Profiler", |ui| {
+            if self.queries.is_none() {
+                ui.label("The GPU doesn't support pipeline statistics queries");
+                return;
+            }
+            ui.checkbox(&mut self.enabled, "count shader invocations");
+            if !self.enabled {
+                return;
+            }
+
+            egui::Grid::new("pipeline statistics")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("pass");
+                    ui.label("invocations");
+                    ui.label("workgroups");
+                    ui.label("lanes used").on_hover_text(
+                        "The share of the invocations that had work to do, an estimate of \
+                            the occupancy lost to workgroups hanging over the edges of the image",
+                    );
+                    ui.end_row();
+
+                    for (pass, invocations) in &self.results {
+                        let dispatched = pass.workgroups * pass.workgroup_size;
+                        ui.label(pass.name);
+                        ui.label(invocations.to_string());
+                        ui.label(pass.workgroups.to_string());
+                        ui.label(format!(
+                            "{:.1}%",
+                            100.0 * pass.work_items as f64 / dispatched.max(1) as f64
+                        ));
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
@@ -0,0 +1,568 @@
+use std::collections::{BTreeMap, HashMap};
+
+use uuid::Uuid;
+use wgpu::{util::DeviceExt, Buffer, BufferUsages, CommandEncoder, Device, Texture, TextureView};
+
+use crate::{
+    geometry::Node,
+    model::TriangleBuffer,
+    scene::{
+        Canvas, CanvasRows, InstanceDataBuffer, LightDataBuffer, MaterialDataBuffer, Mesh, Scene,
+        SphereDataBuffer, CANVAS_SIZE, NO_CANVAS,
+    },
+    texture::CubeTexture,
+    MAX_NUMBER_OF_CANVASES, MAX_NUMBER_OF_INSTANCES,
+};
+
+/// The kinds of lights in the light list, as the compute shader numbers them.
+const LIGHT_SPHERE: u32 = 0;
+const LIGHT_TRIANGLE: u32 = 1;
+
+/// The cells of the hashed grid photons are gathered in for the caustic preview, which matches
+/// CAUSTIC_GRID_CELLS in the compute shader.
+const CAUSTIC_GRID_CELLS: u64 = 65536;
+/// A cell's key and the irradiance of each channel.
+const CAUSTIC_CELL_SIZE: u64 = std::mem::size_of::<[u32; 4]>() as u64;
+
+use super::{tracked_buffer::TrackedBuffer, uploader::Uploader, EnvironmentBuffer, Settings};
+
+/// The buffers and textures a scene is traced from, shared by every viewport of it.
+///
+/// Replacing one of them bumps the generation, so viewports know to rebuild the bind groups that
+/// reference the old one before tracing again.
+pub struct SceneResources {
+    label: String,
+    sphere_data_buffer: TrackedBuffer<SphereDataBuffer>,
+    material_buffer: TrackedBuffer<MaterialDataBuffer>,
+    triangle_buffer: Buffer,
+    triangle_indices_buffer: Buffer,
+    bvh_nodes_buffer: Buffer,
+    /// Where each mesh's BVH starts among the nodes of all of them.
+    mesh_roots: Vec<u32>,
+    /// The indices of each mesh's triangles among the triangles of all of them, by the material
+    /// they're made of as the shader knows it, to find the ones that emit light.
+    triangles_by_material: Vec<TrianglesByMaterial>,
+    instance_buffer: TrackedBuffer<InstanceDataBuffer>,
+    /// The mesh of each instance in the order they were last uploaded.
+    instance_meshes: Vec<usize>,
+    /// The number of lights followed by the kind, index and instance of each, as last uploaded.
+    lights: Vec<u32>,
+    light_buffer: Buffer,
+    sky_texture: CubeTexture,
+    settings_buffer: TrackedBuffer<Settings>,
+    environment_buffer: TrackedBuffer<EnvironmentBuffer>,
+    /// The point and directional lights.
+    analytic_light_buffer: TrackedBuffer<LightDataBuffer>,
+    /// The photons of the caustic preview, cleared and emitted into before each sample.
+    caustic_grid: Buffer,
+    /// What's painted over the meshes with canvases, a layer for each.
+    canvas_texture: Texture,
+    canvas_view: TextureView,
+    /// The meshes whose canvases are the layers of `canvas_texture`, in order.
+    canvas_meshes: Vec<Uuid>,
+    /// The layer of each mesh's canvas, or [`NO_CANVAS`].
+    mesh_canvases: Vec<u32>,
+    generation: u64,
+}
+
+impl SceneResources {
+    pub fn new(device: &Device, label: &str, meshes: &[Mesh], sky_texture: CubeTexture) -> Self {
+        let sphere_data_buffer = TrackedBuffer::new(
+            device,
+            &format!("{} Sphere Buffer", label),
+            BufferUsages::STORAGE,
+        );
+        let material_buffer = TrackedBuffer::new(
+            device,
+            &format!("{} Material Buffer", label),
+            BufferUsages::UNIFORM,
+        );
+        let settings_buffer = TrackedBuffer::new(
+            device,
+            &format!("{} Settings Buffer", label),
+            BufferUsages::UNIFORM,
+        );
+        let environment_buffer = TrackedBuffer::new(
+            device,
+            &format!("{} Environment Buffer", label),
+            BufferUsages::UNIFORM,
+        );
+        let analytic_light_buffer = TrackedBuffer::new(
+            device,
+            &format!("{} Analytic Light Buffer", label),
+            BufferUsages::STORAGE,
+        );
+        let instance_buffer = TrackedBuffer::new(
+            device,
+            &format!("{} Instance Buffer", label),
+            BufferUsages::UNIFORM,
+        );
+
+        let MeshBuffers {
+            triangle_buffer,
+            triangle_indices_buffer,
+            bvh_nodes_buffer,
+            mesh_roots,
+            triangles_by_material,
+        } = create_mesh_buffers(device, label, meshes);
+        let lights = light_list([].into_iter(), None, &triangles_by_material, &[]);
+        let light_buffer = create_light_buffer(device, label, &lights);
+        let caustic_grid = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Caustic Grid", label)),
+            size: CAUSTIC_GRID_CELLS * CAUSTIC_CELL_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (canvas_texture, canvas_view) = create_canvas_texture(device, label, 0);
+
+        Self {
+            label: label.to_string(),
+            sphere_data_buffer,
+            material_buffer,
+            triangle_buffer,
+            triangle_indices_buffer,
+            bvh_nodes_buffer,
+            mesh_roots,
+            triangles_by_material,
+            instance_buffer,
+            instance_meshes: Vec::new(),
+            lights,
+            light_buffer,
+            sky_texture,
+            settings_buffer,
+            environment_buffer,
+            analytic_light_buffer,
+            caustic_grid,
+            canvas_texture,
+            canvas_view,
+            canvas_meshes: Vec::new(),
+            mesh_canvases: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Changes whenever a buffer or texture is replaced, but not when one's contents are written.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Uploads new meshes and the BVHs built over them.
+    pub fn set_meshes(&mut self, device: &Device, meshes: &[Mesh]) {
+        MeshBuffers {
+            triangle_buffer: self.triangle_buffer,
+            triangle_indices_buffer: self.triangle_indices_buffer,
+            bvh_nodes_buffer: self.bvh_nodes_buffer,
+            mesh_roots: self.mesh_roots,
+            triangles_by_material: self.triangles_by_material,
+        } = create_mesh_buffers(device, &self.label, meshes);
+        // The instances are written again before the light list, which indexes all three, is
+        // rebuilt along with the spheres
+        self.instance_meshes.clear();
+        // A replaced scene can have meshes of the same UUIDs painted differently
+        self.canvas_meshes.clear();
+        self.generation += 1;
+    }
+
+    /// Uploads the rows of each mesh's canvas in `painted`, or every canvas if meshes were given
+    /// or lost one since the last write, before the instances are written.
+    pub fn write_canvases(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        meshes: &[Mesh],
+        painted: &HashMap<Uuid, CanvasRows>,
+    ) {
+        let canvas_meshes = meshes
+            .iter()
+            .filter(|mesh| mesh.canvas.is_some())
+            .take(MAX_NUMBER_OF_CANVASES as usize)
+            .map(|mesh| mesh.uuid)
+            .collect::<Vec<_>>();
+        let reallocated = canvas_meshes != self.canvas_meshes;
+        if reallocated {
+            (self.canvas_texture, self.canvas_view) =
+                create_canvas_texture(device, &self.label, canvas_meshes.len() as u32);
+            self.canvas_meshes = canvas_meshes;
+            self.generation += 1;
+        }
+        self.mesh_canvases = meshes
+            .iter()
+            .map(|mesh| {
+                self.canvas_meshes
+                    .iter()
+                    .position(|&uuid| uuid == mesh.uuid)
+                    .map_or(NO_CANVAS, |layer| layer as u32)
+            })
+            .collect();
+
+        for (mesh, &layer) in meshes.iter().zip(&self.mesh_canvases) {
+            let Some(canvas) = mesh.canvas.as_ref().filter(|_| layer != NO_CANVAS) else {
+                continue;
+            };
+            let rows = match painted.get(&mesh.uuid) {
+                _ if reallocated => CanvasRows::ALL,
+                Some(&rows) if !rows.is_empty() => rows,
+                _ => continue,
+            };
+            self.upload_canvas_rows(device, encoder, canvas, layer, rows);
+        }
+    }
+
+    /// Copies `rows` of `canvas` to `layer` of the canvases' texture. Whole rows are copied so
+    /// they're always as aligned as copies to textures need them to be.
+    fn upload_canvas_rows(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        canvas: &Canvas,
+        layer: u32,
+        rows: CanvasRows,
+    ) {
+        let staging = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Canvas Staging Buffer", self.label)),
+            contents: canvas.rows(rows),
+            usage: BufferUsages::COPY_SRC,
+        });
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(CANVAS_SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::ImageCopyTexture {
+                texture: &self.canvas_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: rows.start,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: CANVAS_SIZE,
+                height: rows.end - rows.start,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Uploads the instances of `scene`, whose meshes were last set, and the top-level BVH
+    /// built over them, after the canvases and before the spheres are written.
+    pub fn write_instances(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        scene: &Scene,
+    ) {
+        let instance_data = InstanceDataBuffer::new(scene, &self.mesh_roots, &self.mesh_canvases);
+        self.instance_buffer
+            .write(uploader, device, encoder, &instance_data);
+
+        self.instance_meshes.clear();
+        let instances = scene.instances();
+        if instances.len() <= MAX_NUMBER_OF_INSTANCES as usize {
+            self.instance_meshes.extend(
+                scene
+                    .tlas()
+                    .triangle_indices
+                    .iter()
+                    .map(|&index| instances[index as usize].mesh.mesh),
+            );
+        }
+    }
+
+    /// What [`SceneResources::write_instances`] last wrote.
+    pub fn instances(&self) -> Option<&InstanceDataBuffer> {
+        self.instance_buffer.contents()
+    }
+
+    pub fn sky_texture(&self) -> &CubeTexture {
+        &self.sky_texture
+    }
+
+    pub fn caustic_grid(&self) -> &Buffer {
+        &self.caustic_grid
+    }
+
+    /// Uploads the scene's materials, before the spheres are written.
+    pub fn write_materials(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        materials: &MaterialDataBuffer,
+    ) {
+        self.material_buffer
+            .write(uploader, device, encoder, materials);
+    }
+
+    /// Uploads only the spheres that changed since the last write, and the light list if the
+    /// emissive spheres or triangles changed.
+    pub fn write_spheres(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        sphere_data: &SphereDataBuffer,
+    ) {
+        self.sphere_data_buffer
+            .write(uploader, device, encoder, sphere_data);
+
+        let materials = self.material_buffer.contents();
+        let lights = light_list(
+            materials
+                .into_iter()
+                .flat_map(|materials| sphere_data.emissive_spheres(materials)),
+            materials,
+            &self.triangles_by_material,
+            &self.instance_meshes,
+        );
+        if lights != self.lights {
+            self.light_buffer = create_light_buffer(device, &self.label, &lights);
+            self.lights = lights;
+            self.generation += 1;
+        }
+    }
+
+    pub fn write_settings(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        settings: &Settings,
+    ) {
+        self.settings_buffer
+            .write(uploader, device, encoder, settings);
+    }
+
+    pub fn write_environment(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        environment: &EnvironmentBuffer,
+    ) {
+        self.environment_buffer
+            .write(uploader, device, encoder, environment);
+    }
+
+    pub fn write_analytic_lights(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        lights: &LightDataBuffer,
+    ) {
+        self.analytic_light_buffer
+            .write(uploader, device, encoder, lights);
+    }
+
+    /// The compute bindings shared by every viewport of the scene, i.e. everything except the
+    /// accumulation, the camera, the seed, the render size and the history.
+    pub fn entries(&self) -> [wgpu::BindGroupEntry<'_>; 14] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.sphere_data_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: self.triangle_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: self.triangle_indices_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: self.bvh_nodes_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(&self.sky_texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::Sampler(&self.sky_texture.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: self.settings_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: self.environment_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: self.light_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: self.analytic_light_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 16,
+                resource: self.instance_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 18,
+                resource: self.material_buffer.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 19,
+                resource: self.caustic_grid.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 20,
+                resource: wgpu::BindingResource::TextureView(&self.canvas_view),
+            },
+        ]
+    }
+}
+
+/// A mesh's triangles by the shader index of the material they're made of.
+type TrianglesByMaterial = BTreeMap<u32, Vec<u32>>;
+
+/// Every mesh's triangles, BVH and triangle indices in BVH order, each concatenated into one
+/// buffer.
+struct MeshBuffers {
+    triangle_buffer: Buffer,
+    triangle_indices_buffer: Buffer,
+    bvh_nodes_buffer: Buffer,
+    mesh_roots: Vec<u32>,
+    triangles_by_material: Vec<TrianglesByMaterial>,
+}
+
+fn create_mesh_buffers(device: &Device, label: &str, meshes: &[Mesh]) -> MeshBuffers {
+    let mut triangles = Vec::new();
+    let mut triangle_indices = Vec::new();
+    let mut nodes = Vec::new();
+    let mut mesh_roots = Vec::new();
+    let mut triangles_by_material = Vec::new();
+    for mesh in meshes {
+        let triangle_offset = triangles.len() as u32;
+        mesh_roots.push(nodes.len() as u32);
+        let mut by_material = TrianglesByMaterial::new();
+        for (i, triangle) in mesh.triangles.iter().enumerate() {
+            by_material
+                .entry(triangle.material.shader_index())
+                .or_default()
+                .push(triangle_offset + i as u32);
+        }
+        triangles_by_material.push(by_material);
+
+        let (node_offset, index_offset) = (nodes.len() as u32, triangle_indices.len() as u32);
+        nodes.extend(
+            mesh.bvh
+                .nodes
+                .iter()
+                .map(|node| node.offset(node_offset, index_offset)),
+        );
+        triangle_indices.extend(
+            mesh.bvh
+                .triangle_indices
+                .iter()
+                .map(|index| index + triangle_offset),
+        );
+        triangles.extend(mesh.triangles.iter().map(TriangleBuffer::from));
+    }
+
+    // A binding can't be empty, and without instances nothing is traced against these anyway
+    if nodes.is_empty() {
+        nodes.push(Node::default());
+        triangle_indices.push(0);
+        triangles.push(bytemuck::Zeroable::zeroed());
+    }
+
+    let triangle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Triangle Buffer", label)),
+        contents: bytemuck::cast_slice(&triangles),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let triangle_indices_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Triangle Indices Buffer", label)),
+        contents: bytemuck::cast_slice(&triangle_indices),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let bvh_nodes_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} BVH Nodes Buffer", label)),
+        contents: bytemuck::cast_slice(&nodes),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+
+    MeshBuffers {
+        triangle_buffer,
+        triangle_indices_buffer,
+        bvh_nodes_buffer,
+        mesh_roots,
+        triangles_by_material,
+    }
+}
+
+/// The number of lights followed by the kind, index and instance of each, with the triangles
+/// of each mesh made of an emissive one of `materials` repeated for every instance of it in
+/// `instance_meshes`. Without materials, only `spheres` are lights.
+fn light_list(
+    spheres: impl Iterator<Item = u32>,
+    materials: Option<&MaterialDataBuffer>,
+    triangles_by_material: &[TrianglesByMaterial],
+    instance_meshes: &[usize],
+) -> Vec<u32> {
+    let mut lights = vec![0];
+    for sphere in spheres {
+        lights.extend([LIGHT_SPHERE, sphere, 0]);
+    }
+    for (instance, &mesh) in instance_meshes.iter().enumerate() {
+        let emissive = triangles_by_material[mesh]
+            .iter()
+            .filter(|(&material, _)| materials.is_some_and(|m| m.is_emissive(material)))
+            .flat_map(|(_, triangles)| triangles);
+        for &triangle in emissive {
+            lights.extend([LIGHT_TRIANGLE, triangle, instance as u32]);
+        }
+    }
+    lights[0] = (lights.len() as u32 - 1) / 3;
+    lights
+}
+
+/// A layer for each of `layers` canvases, or a single texel to bind when there are none.
+fn create_canvas_texture(device: &Device, label: &str, layers: u32) -> (Texture, TextureView) {
+    let size = if layers == 0 { 1 } else { CANVAS_SIZE };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(&format!("{} Canvas Texture", label)),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers.max(1),
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    // A single layer would otherwise be viewed as a plain 2D texture
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+fn create_light_buffer(device: &Device, label: &str, lights: &[u32]) -> Buffer {
+    // A binding can't be empty, so there's always room for one light
+    let mut contents = lights.to_vec();
+    contents.resize(contents.len().max(4), 0);
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Light Buffer", label)),
+        contents: bytemuck::cast_slice(&contents),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
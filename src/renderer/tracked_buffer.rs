@@ -0,0 +1,82 @@
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device};
+
+use super::uploader::Uploader;
+
+/// The granularity in bytes at which [`TrackedBuffer`] finds what changed, a multiple of
+/// [`wgpu::COPY_BUFFER_ALIGNMENT`].
+const BLOCK_SIZE: usize = 16;
+
+/// A buffer holding one `T`, along with a copy of what was last written to it, so only the parts
+/// that changed are uploaded again.
+pub struct TrackedBuffer<T> {
+    pub buffer: Buffer,
+    /// Boxed, since some of these are the size of every sphere in the scene.
+    contents: Option<Box<T>>,
+}
+
+impl<T: bytemuck::Pod> TrackedBuffer<T> {
+    pub fn new(device: &Device, label: &str, usage: BufferUsages) -> Self {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            mapped_at_creation: false,
+            size: std::mem::size_of::<T>() as u64,
+            label: Some(label),
+            usage: usage | BufferUsages::COPY_DST,
+        });
+
+        Self {
+            buffer,
+            contents: None,
+        }
+    }
+
+    /// What was last written, if anything.
+    pub fn contents(&self) -> Option<&T> {
+        self.contents.as_deref()
+    }
+
+    /// Uploads the runs of blocks of `value` that differ from the last write, or all of it the
+    /// first time.
+    pub fn write(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        value: &T,
+    ) {
+        let new = bytemuck::bytes_of(value);
+        let Some(contents) = &mut self.contents else {
+            uploader.write(device, encoder, &self.buffer, 0, new);
+            self.contents = Some(Box::new(*value));
+            return;
+        };
+
+        let old = bytemuck::bytes_of(contents.as_ref());
+        let changed = |block: usize| {
+            let range = block * BLOCK_SIZE..((block + 1) * BLOCK_SIZE).min(new.len());
+            old[range.clone()] != new[range]
+        };
+
+        let blocks = new.len().div_ceil(BLOCK_SIZE);
+        let mut block = 0;
+        while block < blocks {
+            if !changed(block) {
+                block += 1;
+                continue;
+            }
+            let start = block;
+            while block < blocks && changed(block) {
+                block += 1;
+            }
+            let range = start * BLOCK_SIZE..(block * BLOCK_SIZE).min(new.len());
+            uploader.write(
+                device,
+                encoder,
+                &self.buffer,
+                range.start as u64,
+                &new[range],
+            );
+        }
+
+        **contents = *value;
+    }
+}
@@ -0,0 +1,50 @@
+use std::num::NonZeroU64;
+
+use wgpu::{util::StagingBelt, Buffer, CommandEncoder, Device};
+
+/// The size of the staging buffers, enough for every sphere in the scene along with the rest of
+/// a frame's uniforms.
+const CHUNK_SIZE: u64 = 1 << 20;
+
+/// Uploads buffer contents through a ring of staging buffers that are reused from frame to
+/// frame, rather than the queue allocating a new one for every write.
+pub struct Uploader {
+    belt: StagingBelt,
+}
+
+impl Uploader {
+    pub fn new() -> Self {
+        Self {
+            belt: StagingBelt::new(CHUNK_SIZE),
+        }
+    }
+
+    /// Copies `data` to `offset` in `buffer`, ahead of whatever is recorded into `encoder` next.
+    /// Both must be multiples of [`wgpu::COPY_BUFFER_ALIGNMENT`].
+    pub fn write(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        buffer: &Buffer,
+        offset: u64,
+        data: &[u8],
+    ) {
+        let Some(size) = NonZeroU64::new(data.len() as u64) else {
+            return;
+        };
+        self.belt
+            .write_buffer(encoder, buffer, offset, size, device)
+            .copy_from_slice(data);
+    }
+
+    /// Closes the staging buffers written so far. Called before submitting the encoders they were
+    /// written with.
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    /// Reuses the staging buffers once the GPU has copied out of them. Called after submitting.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
@@ -0,0 +1,238 @@
+use wgpu::{Buffer, BufferDescriptor, CommandEncoder, Device, TextureFormat, TextureView};
+
+use crate::{texture::Texture2D, utils};
+
+use super::uploader::Uploader;
+
+pub const UPSCALER_INPUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// The smallest fraction of the output resolution that's rendered.
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+
+pub struct Upscaler {
+    pub enabled: bool,
+    pub render_scale: f32,
+    /// Renders at the output's resolution whether or not the upscaler is enabled, for reference
+    /// mode.
+    pub bypassed: bool,
+    input: Texture2D,
+    output_format: TextureFormat,
+    buffer: Buffer,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Upscaler {
+    /// `width` and `height` are the largest render size the upscaler accepts.
+    pub fn new(device: &Device, output_format: TextureFormat, width: u32, height: u32) -> Self {
+        let input = Texture2D::new(
+            device,
+            width,
+            height,
+            UPSCALER_INPUT_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            mapped_at_creation: false,
+            size: std::mem::size_of::<UpscalerBuffer>() as u64,
+            label: Some("Upscaler Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let src = utils::load_shader_source("upscale.wgsl").unwrap();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("upscale"),
+            source: wgpu::ShaderSource::Wgsl(src.into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Upscaler Bind Group Layout"),
+            entries: &[
+                // Input texture
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                // Input and output sizes
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Upscaler Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscaler Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Upscaler Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(output_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            enabled: false,
+            render_scale: 0.67,
+            bypassed: false,
+            input,
+            output_format,
+            buffer,
+            pipeline,
+            bind_group,
+        }
+    }
+
+    pub fn set_output_format(&mut self, device: &Device, output_format: TextureFormat) {
+        let (width, height) = self.max_render_size();
+        *self = Self {
+            enabled: self.enabled,
+            render_scale: self.render_scale,
+            bypassed: self.bypassed,
+            ..Self::new(device, output_format, width, height)
+        };
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        *self = Self {
+            enabled: self.enabled,
+            render_scale: self.render_scale,
+            bypassed: self.bypassed,
+            ..Self::new(device, self.output_format, width, height)
+        };
+    }
+
+    fn max_render_size(&self) -> (u32, u32) {
+        (self.input.texture.width(), self.input.texture.height())
+    }
+
+    /// The view the accumulated image should be resolved into before upscaling.
+    pub fn input_view(&self) -> &TextureView {
+        &self.input.view
+    }
+
+    /// Whether the render is upscaled, i.e. enabled and not bypassed.
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.bypassed
+    }
+
+    /// The resolution the path tracer should render at for the given output size.
+    pub fn render_size(&self, output_width: u32, output_height: u32) -> (u32, u32) {
+        let scale = if self.is_active() {
+            self.render_scale
+        } else {
+            1.0
+        };
+        let (max_width, max_height) = self.max_render_size();
+
+        (
+            ((output_width as f32 * scale).ceil() as u32).clamp(1, max_width),
+            ((output_height as f32 * scale).ceil() as u32).clamp(1, max_height),
+        )
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Upscaling", |ui| {
+            ui.add(egui::Checkbox::new(&mut self.enabled, "enabled"))
+                .on_hover_text("Render at a lower resolution and upscale the result (FSR 1 EASU)");
+
+            ui.add_enabled(
+                self.enabled,
+                egui::Slider::new(&mut self.render_scale, MIN_RENDER_SCALE..=1.0)
+                    .text("render scale"),
+            );
+        });
+    }
+
+    pub fn upscale(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        output: &TextureView,
+        input_size: (u32, u32),
+        output_size: (u32, u32),
+    ) {
+        uploader.write(
+            device,
+            encoder,
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(&[UpscalerBuffer {
+                input_size: [input_size.0 as f32, input_size.1 as f32],
+                output_size: [output_size.0 as f32, output_size.1 as f32],
+                enabled: self.is_active() as u32,
+                _padding: [0; 3],
+            }]),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(0..3, 0..2);
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscalerBuffer {
+    input_size: [f32; 2],
+    output_size: [f32; 2],
+    enabled: u32,
+    _padding: [u32; 3],
+}
@@ -0,0 +1,737 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::mpsc,
+};
+
+use wgpu::{
+    BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Queue,
+};
+
+use crate::scene::{Camera, CameraBuffer};
+
+use super::{
+    permutations::TracePipeline, profiler::Profiler, resources::SceneResources,
+    tracked_buffer::TrackedBuffer, uploader::Uploader, ProgressiveRendering, ToneMapping,
+};
+
+/// The object ID of pixels showing only the background. Matches the compute shader's.
+const NO_OBJECT: u32 = u32::MAX;
+/// Set in the object IDs of mesh instances, whose index is in the rest of the bits.
+const OBJECT_INSTANCE: u32 = 1 << 31;
+/// The invocations of a workgroup of the compute shader's photon entry point.
+const PHOTON_WORKGROUP_SIZE: u32 = 64;
+
+/// The photons a viewport emits into the scene's caustic grid before each of its samples, when
+/// the variant it traces with gathers caustics.
+#[derive(Clone, Copy)]
+pub struct PhotonPass<'a> {
+    pub grid: &'a Buffer,
+    pub photons: u32,
+}
+
+/// A camera's view of the scene with its own accumulation state.
+///
+/// Samples traced while the camera is moving go into a separate, short-lived accumulation, so
+/// the still one keeps its samples and picks up where it left off if the camera comes back to
+/// exactly where it was.
+pub struct Viewport {
+    /// What the profiler calls the viewport's compute pass.
+    label: &'static str,
+    width: u32,
+    height: u32,
+    camera_buffer: TrackedBuffer<CameraBuffer>,
+    render_size_buffer: TrackedBuffer<[u32; 2]>,
+    seed_buffer: Buffer,
+    resolve_buffer: TrackedBuffer<ResolveBuffer>,
+    /// How much of the accumulated sum the next sample keeps, zero to start over.
+    history_buffer: TrackedBuffer<f32>,
+    /// The normal and distance of the surface seen through each pixel, written by the denoising
+    /// variant of the compute shader.
+    gbuffer: Buffer,
+    /// What each pixel's latest sample hit first, see [`PickedObject`].
+    object_ids: Buffer,
+    still: Accumulation,
+    moving: Accumulation,
+    /// Whether the last sample went into the moving accumulation, which is then the one shown.
+    was_moving: bool,
+    /// The sample whose bands are being traced, if it's been split and isn't finished.
+    partial: Option<PartialSample>,
+    /// Hash of everything the samples traced this frame depend on.
+    state: u64,
+    /// The [`SceneResources::generation`] the compute bind groups were built for.
+    resources_generation: u64,
+}
+
+/// What a pixel shows, as the compute shader writes it to the object IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickedObject {
+    /// The index of a sphere in the scene.
+    Sphere(usize),
+    /// The index of a mesh instance in the scene.
+    Instance(usize),
+}
+
+impl PickedObject {
+    fn from_id(id: u32) -> Option<Self> {
+        match id {
+            NO_OBJECT => None,
+            id if id & OBJECT_INSTANCE != 0 => {
+                Some(Self::Instance((id & !OBJECT_INSTANCE) as usize))
+            }
+            id => Some(Self::Sphere(id as usize)),
+        }
+    }
+}
+
+/// A sample split into bands of rows, traced over several calls to [`Viewport::trace`].
+#[derive(Debug, Clone, Copy)]
+struct PartialSample {
+    moving: bool,
+    /// The viewport state it was started for.
+    state: u64,
+    render_size: (u32, u32),
+    /// What [`ProgressiveRendering`] said to accumulate it with.
+    samples: u32,
+    history: f32,
+    /// The first row of the next band.
+    next_row: u32,
+}
+
+impl PartialSample {
+    fn new(moving: bool, state: u64, render_size: (u32, u32), samples: u32, history: f32) -> Self {
+        Self {
+            moving,
+            state,
+            render_size,
+            samples,
+            history,
+            next_row: 0,
+        }
+    }
+}
+
+/// A running sum of samples traced into a buffer, one `vec4<f32>` per pixel.
+struct Accumulation {
+    buffer: Buffer,
+    compute_bind_group: wgpu::BindGroup,
+    copy_bind_group: wgpu::BindGroup,
+    /// How many samples the sum is worth.
+    samples: u32,
+    /// The number of samples traced since the sum last started over, which seeds the next.
+    sample_index: u32,
+    /// The viewport state the latest sample was traced for.
+    state: Option<u64>,
+}
+
+impl Accumulation {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        device: &Device,
+        width: u32,
+        height: u32,
+        compute_bind_group_layout: &BindGroupLayout,
+        copy_bind_group_layout: &BindGroupLayout,
+        viewport_entries: &[wgpu::BindGroupEntry],
+        resolve_buffer: &Buffer,
+        resources: &SceneResources,
+    ) -> Self {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            mapped_at_creation: false,
+            size: (width * height) as u64 * std::mem::size_of::<[f32; 4]>() as u64,
+            label: Some("Accumulation Buffer"),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let compute_bind_group = create_compute_bind_group(
+            device,
+            compute_bind_group_layout,
+            &buffer,
+            viewport_entries,
+            resources,
+        );
+
+        let copy_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: copy_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: resolve_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            buffer,
+            compute_bind_group,
+            copy_bind_group,
+            samples: 0,
+            sample_index: 0,
+            state: None,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn new(
+        device: &Device,
+        label: &'static str,
+        width: u32,
+        height: u32,
+        compute_bind_group_layout: &BindGroupLayout,
+        copy_bind_group_layout: &BindGroupLayout,
+        resources: &SceneResources,
+    ) -> Self {
+        let camera_buffer = TrackedBuffer::new(device, "Camera Buffer", BufferUsages::UNIFORM);
+        let render_size_buffer =
+            TrackedBuffer::new(device, "Render Size Buffer", BufferUsages::UNIFORM);
+
+        let seed_buffer = device.create_buffer(&BufferDescriptor {
+            mapped_at_creation: false,
+            // The frame, the sample index, and the first row of the band being traced
+            size: std::mem::size_of::<[u32; 4]>() as u64,
+            label: Some("Seed Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let resolve_buffer = TrackedBuffer::new(device, "Resolve Buffer", BufferUsages::UNIFORM);
+        let history_buffer = TrackedBuffer::new(device, "History Buffer", BufferUsages::UNIFORM);
+
+        let gbuffer = create_gbuffer(device, width, height);
+        let object_ids = create_object_ids(device, width, height);
+
+        let viewport_entries = viewport_entries(
+            &camera_buffer.buffer,
+            &seed_buffer,
+            &render_size_buffer.buffer,
+            &history_buffer.buffer,
+            &gbuffer,
+            &object_ids,
+        );
+        let [still, moving] = [(); 2].map(|_| {
+            Accumulation::new(
+                device,
+                width,
+                height,
+                compute_bind_group_layout,
+                copy_bind_group_layout,
+                &viewport_entries,
+                &resolve_buffer.buffer,
+                resources,
+            )
+        });
+
+        Self {
+            label,
+            width,
+            height,
+            camera_buffer,
+            render_size_buffer,
+            seed_buffer,
+            resolve_buffer,
+            history_buffer,
+            gbuffer,
+            object_ids,
+            still,
+            moving,
+            was_moving: false,
+            partial: None,
+            state: 0,
+            resources_generation: resources.generation(),
+        }
+    }
+
+    /// Recreates the accumulation buffers at a new size, starting both over.
+    pub fn resize(
+        &mut self,
+        device: &Device,
+        width: u32,
+        height: u32,
+        compute_bind_group_layout: &BindGroupLayout,
+        copy_bind_group_layout: &BindGroupLayout,
+        resources: &SceneResources,
+    ) {
+        self.gbuffer = create_gbuffer(device, width, height);
+        self.object_ids = create_object_ids(device, width, height);
+
+        let viewport_entries = viewport_entries(
+            &self.camera_buffer.buffer,
+            &self.seed_buffer,
+            &self.render_size_buffer.buffer,
+            &self.history_buffer.buffer,
+            &self.gbuffer,
+            &self.object_ids,
+        );
+        let [still, moving] = [(); 2].map(|_| {
+            Accumulation::new(
+                device,
+                width,
+                height,
+                compute_bind_group_layout,
+                copy_bind_group_layout,
+                &viewport_entries,
+                &self.resolve_buffer.buffer,
+                resources,
+            )
+        });
+
+        self.still = still;
+        self.moving = moving;
+        self.partial = None;
+        self.width = width;
+        self.height = height;
+        self.resources_generation = resources.generation();
+    }
+
+    /// Rebuilds the compute bind groups if any of `resources`' buffers or textures were replaced
+    /// since they were built.
+    pub fn bind(
+        &mut self,
+        device: &Device,
+        compute_bind_group_layout: &BindGroupLayout,
+        resources: &SceneResources,
+    ) {
+        if self.resources_generation == resources.generation() {
+            return;
+        }
+
+        let viewport_entries = viewport_entries(
+            &self.camera_buffer.buffer,
+            &self.seed_buffer,
+            &self.render_size_buffer.buffer,
+            &self.history_buffer.buffer,
+            &self.gbuffer,
+            &self.object_ids,
+        );
+        for accumulation in [&mut self.still, &mut self.moving] {
+            accumulation.compute_bind_group = create_compute_bind_group(
+                device,
+                compute_bind_group_layout,
+                &accumulation.buffer,
+                &viewport_entries,
+                resources,
+            );
+        }
+        self.resources_generation = resources.generation();
+    }
+
+    /// The size of the accumulation buffers, which is the largest size this viewport can render
+    /// at.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// How many samples the image shown is worth.
+    pub fn samples(&self) -> u32 {
+        self.shown().samples
+    }
+
+    fn shown(&self) -> &Accumulation {
+        if self.was_moving {
+            &self.moving
+        } else {
+            &self.still
+        }
+    }
+
+    /// Records what the next samples depend on. The still accumulation starts over with its next
+    /// sample if it was for something else. `scene_state` is a hash of the buffers shared by
+    /// every viewport.
+    pub fn update_state(&mut self, scene_state: u64, camera: &Camera, render_size: (u32, u32)) {
+        let mut hasher = DefaultHasher::new();
+        scene_state.hash(&mut hasher);
+        bytemuck::bytes_of(&CameraBuffer::from(camera)).hash(&mut hasher);
+        render_size.hash(&mut hasher);
+        self.state = hasher.finish();
+    }
+
+    /// Adds a band of rows of a sample of `frame` at `render_size` to the moving or still
+    /// accumulation, unless the still one already has enough, and prepares the viewport for
+    /// resolving it at `offset` in the render target, multiplied by `output_scale`. Each sample is
+    /// split into `bands` to spread it over several calls, one for the whole sample. With
+    /// `false_color_nits`, the image is resolved as a false color view, treating a luminance of
+    /// 1.0 as that many nits or lux, otherwise it's exposed and tone mapped as
+    /// `tone_mapping` says. Viewports without a `photon_pass` gather whatever photons another one
+    /// last emitted. Returns the share of a sample traced, zero if nothing was.
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        pipeline: TracePipeline,
+        photon_pass: Option<PhotonPass>,
+        camera: &Camera,
+        progressive_rendering: &ProgressiveRendering,
+        render_size: (u32, u32),
+        frame: u32,
+        bands: u32,
+        offset: (u32, u32),
+        output_scale: f32,
+        false_color_nits: Option<f32>,
+        tone_mapping: &ToneMapping,
+        profiler: &mut Profiler,
+    ) -> f32 {
+        let is_moving = progressive_rendering.enabled && camera.moved_recently();
+        // A moving sample is finished with the camera it started with rather than never finishing
+        let partial = self.partial.take();
+        let resumed = partial.filter(|partial| {
+            partial.moving == is_moving
+                && partial.render_size == render_size
+                && (is_moving || partial.state == self.state)
+        });
+        // Some rows of an abandoned sample were added, so the sum has to start over
+        let abandoned = partial.is_some() && resumed.is_none();
+
+        let (accumulation, next) = if let Some(partial) = resumed {
+            let accumulation = if is_moving {
+                &mut self.moving
+            } else {
+                &mut self.still
+            };
+            (accumulation, Some(partial))
+        } else if is_moving {
+            // Blending in the end of a previous movement would smear the view
+            let restart = !self.was_moving || abandoned;
+            let (samples, history) =
+                progressive_rendering.accumulate_moving(self.moving.samples, restart);
+            let next = PartialSample::new(true, self.state, render_size, samples, history);
+            (&mut self.moving, Some(next))
+        } else {
+            let restart = self.still.state != Some(self.state) || abandoned;
+            let next = progressive_rendering
+                .accumulate_still(self.still.samples, restart)
+                .map(|(samples, history)| {
+                    PartialSample::new(false, self.state, render_size, samples, history)
+                });
+            (&mut self.still, next)
+        };
+        self.was_moving = is_moving;
+
+        let mut traced = 0.0;
+        if let Some(mut sample) = next {
+            if sample.next_row == 0 {
+                self.camera_buffer
+                    .write(uploader, device, encoder, &CameraBuffer::from(camera));
+                self.render_size_buffer.write(
+                    uploader,
+                    device,
+                    encoder,
+                    &[render_size.0, render_size.1],
+                );
+                self.history_buffer
+                    .write(uploader, device, encoder, &sample.history);
+
+                // Seeded by the frame and sample rather than the clock, so the same frame renders
+                // the same way every time
+                accumulation.sample_index = if sample.history == 0.0 {
+                    0
+                } else {
+                    accumulation.sample_index + 1
+                };
+                uploader.write(
+                    device,
+                    encoder,
+                    &self.seed_buffer,
+                    0,
+                    bytemuck::cast_slice(&[frame, accumulation.sample_index]),
+                );
+
+                // Seeded like the sample, so each one gathers a fresh set of photons
+                if let (Some(emit_photons), Some(photon_pass)) =
+                    (pipeline.emit_photons, photon_pass)
+                {
+                    encoder.clear_buffer(photon_pass.grid, 0, None);
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                    compute_pass.set_pipeline(emit_photons);
+                    compute_pass.set_bind_group(0, &accumulation.compute_bind_group, &[]);
+                    compute_pass.dispatch_workgroups(
+                        photon_pass.photons.div_ceil(PHOTON_WORKGROUP_SIZE),
+                        1,
+                        1,
+                    );
+                }
+            }
+            uploader.write(
+                device,
+                encoder,
+                &self.seed_buffer,
+                std::mem::size_of::<[u32; 2]>() as u64,
+                bytemuck::cast_slice(&[sample.next_row, 0]),
+            );
+
+            // Whole rows of workgroups, so the bands line up
+            let (x, y) = pipeline.workgroup_size;
+            let band_rows = render_size.1.div_ceil(bands.max(1)).next_multiple_of(y);
+            let rows = band_rows.min(render_size.1 - sample.next_row);
+            let workgroups = (render_size.0.div_ceil(x), rows.div_ceil(y));
+            let query = profiler.add_pass(
+                self.label,
+                workgroups,
+                pipeline.workgroup_size,
+                render_size.0 as u64 * rows as u64,
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            profiler.begin_query(&mut compute_pass, query);
+            compute_pass.set_pipeline(pipeline.pipeline);
+            compute_pass.set_bind_group(0, &accumulation.compute_bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+            profiler.end_query(&mut compute_pass, query);
+            drop(compute_pass);
+
+            traced = rows as f32 / render_size.1 as f32;
+            sample.next_row += rows;
+            if sample.next_row < render_size.1 {
+                self.partial = Some(sample);
+            } else {
+                accumulation.samples = sample.samples;
+                accumulation.state = Some(self.state);
+            }
+        }
+
+        let resolve = ResolveBuffer {
+            samples: self.shown().samples,
+            scale: output_scale,
+            offset: [offset.0, offset.1],
+            false_color_nits: false_color_nits.unwrap_or(0.0),
+            width: render_size.0,
+            tone_mapping: tone_mapping.operator,
+            exposure: 2f32.powf(tone_mapping.exposure),
+        };
+        self.resolve_buffer
+            .write(uploader, device, encoder, &resolve);
+        traced
+    }
+
+    /// Whether only some bands of the latest sample have been traced, so the accumulation isn't
+    /// ready to be resolved.
+    pub fn is_mid_sample(&self) -> bool {
+        self.partial.is_some()
+    }
+
+    /// Reads back the image shown at `size`, averaged over its samples. Blocks until the GPU has
+    /// finished everything submitted so far.
+    pub fn read_image(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        size: (u32, u32),
+    ) -> Result<Vec<[f32; 4]>, wgpu::BufferAsyncError> {
+        let shown = self.shown();
+        let bytes = (size.0 * size.1) as u64 * std::mem::size_of::<[f32; 4]>() as u64;
+        let staging_buffer = device.create_buffer(&BufferDescriptor {
+            mapped_at_creation: false,
+            size: bytes,
+            label: Some("Readback Buffer"),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        // Rows are `size.0` pixels wide, so the image is the start of the buffer
+        encoder.copy_buffer_to_buffer(&shown.buffer, 0, &staging_buffer, 0, bytes);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap()?;
+
+        let samples = shown.samples.max(1) as f32;
+        let pixels = bytemuck::cast_slice::<u8, [f32; 4]>(&slice.get_mapped_range())
+            .iter()
+            .map(|pixel| pixel.map(|channel| channel / samples))
+            .collect();
+        staging_buffer.unmap();
+
+        Ok(pixels)
+    }
+
+    /// Reads back what `pixel` of the image at `size` showed when it was last traced. Blocks
+    /// until the GPU has finished everything submitted so far.
+    pub fn read_object_id(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        size: (u32, u32),
+        pixel: (u32, u32),
+    ) -> Result<Option<PickedObject>, wgpu::BufferAsyncError> {
+        let bytes = std::mem::size_of::<u32>() as u64;
+        let staging_buffer = device.create_buffer(&BufferDescriptor {
+            mapped_at_creation: false,
+            size: bytes,
+            label: Some("Object ID Readback Buffer"),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let offset = (pixel.1 * size.0 + pixel.0) as u64 * bytes;
+        encoder.copy_buffer_to_buffer(&self.object_ids, offset, &staging_buffer, 0, bytes);
+        queue.submit([encoder.finish()]);
+
+        let slice = staging_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap()?;
+
+        let id = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range())[0];
+        staging_buffer.unmap();
+
+        Ok(PickedObject::from_id(id))
+    }
+
+    pub fn copy_bind_group(&self) -> &wgpu::BindGroup {
+        &self.shown().copy_bind_group
+    }
+
+    /// The sum of the samples shown, with the number of them in alpha.
+    pub fn shown_buffer(&self) -> &Buffer {
+        &self.shown().buffer
+    }
+
+    pub fn gbuffer(&self) -> &Buffer {
+        &self.gbuffer
+    }
+
+    /// How the image was last resolved, for an image that's already averaged over its samples.
+    pub fn averaged_resolve(&self) -> ResolveBuffer {
+        let resolve = self.resolve_buffer.contents().copied();
+        ResolveBuffer {
+            samples: 1,
+            ..resolve.unwrap_or(bytemuck::Zeroable::zeroed())
+        }
+    }
+
+    pub fn copy_bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                // Accumulated samples
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Sample count, output scale, offset, false color and row width
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+}
+
+/// The compute bindings of a viewport's own buffers.
+fn viewport_entries<'a>(
+    camera_buffer: &'a Buffer,
+    seed_buffer: &'a Buffer,
+    render_size_buffer: &'a Buffer,
+    history_buffer: &'a Buffer,
+    gbuffer: &'a Buffer,
+    object_ids: &'a Buffer,
+) -> [wgpu::BindGroupEntry<'a>; 6] {
+    [
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: camera_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 6,
+            resource: seed_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 10,
+            resource: render_size_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 12,
+            resource: history_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 13,
+            resource: gbuffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 17,
+            resource: object_ids.as_entire_binding(),
+        },
+    ]
+}
+
+fn create_gbuffer(device: &Device, width: u32, height: u32) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        mapped_at_creation: false,
+        size: (width * height) as u64 * std::mem::size_of::<[f32; 4]>() as u64,
+        label: Some("G-Buffer"),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    })
+}
+
+fn create_object_ids(device: &Device, width: u32, height: u32) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        mapped_at_creation: false,
+        size: (width * height) as u64 * std::mem::size_of::<u32>() as u64,
+        label: Some("Object ID Buffer"),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    })
+}
+
+fn create_compute_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    accumulation_buffer: &Buffer,
+    viewport_entries: &[wgpu::BindGroupEntry],
+    resources: &SceneResources,
+) -> wgpu::BindGroup {
+    let mut entries = vec![wgpu::BindGroupEntry {
+        binding: 0,
+        resource: accumulation_buffer.as_entire_binding(),
+    }];
+    entries.extend_from_slice(viewport_entries);
+    entries.extend(resources.entries());
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &entries,
+    })
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ResolveBuffer {
+    samples: u32,
+    scale: f32,
+    offset: [u32; 2],
+    /// Zero when false color is off.
+    false_color_nits: f32,
+    /// The number of pixels per row in the accumulation buffer.
+    width: u32,
+    tone_mapping: u32,
+    /// The factor the radiance is multiplied by before tone mapping.
+    exposure: f32,
+}
@@ -1,13 +1,76 @@
-use std::time::Instant;
+use std::{f32::consts::TAU, time::Instant};
 
 use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+use serde::{Deserialize, Serialize};
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize},
-    event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event::{ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent},
     window::{CursorGrabMode, Window},
 };
 
-#[derive(Debug)]
+use crate::geometry::{Aabb, Frustum, Ray};
+
+/// The closest distance to the camera that is projected or drawn over.
+const NEAR_PLANE: f32 = 0.01;
+/// A typical distance between a person's eyes, in meters.
+const DEFAULT_IPD: f32 = 0.064;
+/// How much each line scrolled narrows or widens the field of view.
+const FOV_ZOOM_STEP: f32 = 1.1;
+/// How far each line scrolled moves the camera, in seconds of flying at its speed.
+const DOLLY_STEP: f32 = 0.1;
+/// How many pixels a touchpad scrolls per line of a mouse wheel.
+const PIXELS_PER_LINE: f32 = 50.0;
+/// The smallest radius [`Camera::frame`] fits in view, so a point isn't framed from on top of it.
+const MIN_FRAMED_RADIUS: f32 = 0.1;
+
+/// How the renderer's camera rays leave the camera.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Omni-directional stereo over the whole sphere, with the left eye's equirectangular
+    /// panorama above the right's, for VR headsets.
+    Stereo360,
+    /// Omni-directional stereo over the half sphere in front, with the left eye's panorama
+    /// beside the right's (VR180).
+    Stereo180,
+}
+
+impl Projection {
+    /// How the compute shader identifies the projection.
+    pub fn shader_index(&self) -> u32 {
+        match self {
+            Projection::Perspective => 0,
+            Projection::Stereo360 => 1,
+            Projection::Stereo180 => 2,
+        }
+    }
+
+    /// The width over the height the whole image needs, or `None` if any will do.
+    pub fn aspect_ratio(&self) -> Option<u32> {
+        match self {
+            Projection::Perspective => None,
+            Projection::Stereo360 => Some(1),
+            Projection::Stereo180 => Some(2),
+        }
+    }
+
+    /// The largest image within `size` with the projection's aspect ratio, with even sides so
+    /// each eye gets whole pixels.
+    pub fn fit(&self, size: (u32, u32)) -> (u32, u32) {
+        let Some(aspect_ratio) = self.aspect_ratio() else {
+            return size;
+        };
+        let height = (size.1.min(size.0 / aspect_ratio) & !1).max(2);
+        (height * aspect_ratio, height)
+    }
+}
+
+fn default_ipd() -> f32 {
+    DEFAULT_IPD
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub origin: Vector3<f32>,
     pub forward: Vector3<f32>,
@@ -15,21 +78,15 @@ pub struct Camera {
     pub up: Vector3<f32>,
     pub focal_length: f32,
     pub vfov: f32,
+    #[serde(default)]
+    pub projection: Projection,
+    /// The distance between the eyes of the stereo projections, in scene units.
+    #[serde(default = "default_ipd")]
+    pub ipd: f32,
+    #[serde(skip, default = "Instant::now")]
     last_move_time: Instant,
 }
 
-#[derive(Debug)]
-pub struct Ray {
-    pub origin: Vector3<f32>,
-    pub direction: Vector3<f32>,
-}
-
-impl Ray {
-    pub fn at(&self, t: f32) -> Vector3<f32> {
-        self.origin + self.direction * t
-    }
-}
-
 impl Camera {
     pub fn new() -> Self {
         Self {
@@ -39,10 +96,33 @@ impl Camera {
             up: Vector3::new(0.0, 1.0, 0.0),
             focal_length: 1.0,
             vfov: 75.0,
+            projection: Projection::Perspective,
+            ipd: DEFAULT_IPD,
             last_move_time: Instant::now(),
         }
     }
 
+    /// Points the camera in `forward`, keeping it level with the horizon.
+    pub fn look_in(&mut self, forward: Vector3<f32>) {
+        self.forward = forward.normalize();
+        self.right = self.forward.cross(Vector3::unit_y()).normalize();
+        self.up = self.right.cross(self.forward).normalize();
+        self.mark_moved();
+    }
+
+    /// Moves the camera along its view direction until the sphere around `bounds` fits in its
+    /// vertical field of view.
+    pub fn frame(&mut self, bounds: &Aabb) {
+        let radius = ((bounds.max - bounds.min).magnitude() / 2.0).max(MIN_FRAMED_RADIUS);
+        let distance = radius / (self.vfov.to_radians() / 2.0).sin();
+        self.origin = bounds.center() - self.forward * distance;
+        self.mark_moved();
+    }
+
+    pub fn mark_moved(&mut self) {
+        self.last_move_time = Instant::now();
+    }
+
     pub fn moved_recently(&self) -> bool {
         self.last_move_time.elapsed().as_secs_f32() < 0.2
     }
@@ -68,6 +148,52 @@ impl Camera {
             direction: direction.normalize(),
         }
     }
+
+    /// How far the edges of the view are from its center, one unit in front of the camera.
+    fn half_extent(&self, screen_size: PhysicalSize<u32>) -> Vector2<f32> {
+        let aspect_ratio = screen_size.width as f32 / screen_size.height as f32;
+        let half_height = (self.vfov.to_radians() / 2.0).tan() * self.focal_length;
+        Vector2::new(half_height * aspect_ratio, half_height)
+    }
+
+    /// What the camera sees on a screen of `screen_size`, for culling.
+    pub fn frustum(&self, screen_size: PhysicalSize<u32>) -> Frustum {
+        let half_extent = self.half_extent(screen_size);
+        Frustum::new(
+            self.origin,
+            [self.forward, self.right, self.up],
+            half_extent.x,
+            half_extent.y,
+            NEAR_PLANE,
+        )
+    }
+
+    /// The inverse of [`Self::screen_pos_to_ray`], `None` for points behind the camera.
+    pub fn world_to_screen(
+        &self,
+        point: Vector3<f32>,
+        screen_size: PhysicalSize<u32>,
+    ) -> Option<Vector2<f32>> {
+        let offset = point - self.origin;
+        let depth = offset.dot(self.forward);
+        if depth < NEAR_PLANE {
+            return None;
+        }
+
+        let half_extent = self.half_extent(screen_size);
+        let x = offset.dot(self.right) / depth / half_extent.x;
+        let y = offset.dot(self.up) / depth / half_extent.y;
+        Some(Vector2::new(
+            (x + 1.0) / 2.0 * screen_size.width as f32,
+            (1.0 - y) / 2.0 * screen_size.height as f32,
+        ))
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[repr(C)]
@@ -78,9 +204,9 @@ pub struct CameraBuffer {
     forward: [f32; 3],
     vfov: f32,
     right: [f32; 3],
-    _padding1: u32,
+    projection: u32,
     up: [f32; 3],
-    _padding2: u32,
+    ipd: f32,
 }
 
 impl From<&Camera> for CameraBuffer {
@@ -91,13 +217,65 @@ impl From<&Camera> for CameraBuffer {
             forward: camera.forward.into(),
             vfov: camera.vfov,
             right: camera.right.into(),
-            _padding1: 0,
+            projection: camera.projection.shader_index(),
             up: camera.up.into(),
-            _padding2: 0,
+            ipd: camera.ipd,
+        }
+    }
+}
+
+/// What scrolling the mouse wheel over the viewport does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScrollZoom {
+    /// Narrows or widens the field of view, leaving the camera where it is.
+    #[default]
+    Fov,
+    /// Moves the camera forwards or backwards.
+    Dolly,
+}
+
+/// Procedural, hand-held looking shake, turning the camera away from where it's pointed.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraShake {
+    pub enabled: bool,
+    /// The furthest the camera is turned, in degrees.
+    pub amplitude: f32,
+    /// Roughly how many times a second the camera sways, in Hz.
+    pub frequency: f32,
+}
+
+impl CameraShake {
+    /// How far the camera is turned at `time` seconds into the animation, as yaw and pitch in
+    /// degrees. Following the animation's time rather than the clock's, each frame is shaken
+    /// the same way every time it's rendered, and a still frame accumulates samples as usual.
+    pub fn offset(&self, time: f32) -> Vector2<f32> {
+        if !self.enabled {
+            return Vector2::zero();
+        }
+        let phase = time * self.frequency * TAU;
+        Vector2::new(noise(phase, 0.0), noise(phase, 10.0)) * self.amplitude
+    }
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            amplitude: 0.5,
+            frequency: 1.0,
         }
     }
 }
 
+/// Smooth, irregular motion within [-1, 1] from sines of unrelated frequencies, with `seed`
+/// shifting their phases so differently seeded motion doesn't move together.
+fn noise(phase: f32, seed: f32) -> f32 {
+    let sum = (phase + seed).sin()
+        + 0.5 * (2.13 * phase + 1.7 * seed).sin()
+        + 0.25 * (4.37 * phase + 3.1 * seed).sin();
+    sum / 1.75
+}
+
 #[derive(Debug)]
 pub struct CameraController {
     is_right_mouse_button_pressed: bool,
@@ -110,7 +288,11 @@ pub struct CameraController {
     yaw: f32,
     pitch: f32,
     prev_cursor_pos: Option<Vector2<f32>>,
+    /// Lines scrolled since the camera was last updated, positive away from the user.
+    scrolled: f32,
     pub speed: f32,
+    pub scroll_zoom: ScrollZoom,
+    pub shake: CameraShake,
 }
 
 impl CameraController {
@@ -126,10 +308,20 @@ impl CameraController {
             prev_cursor_pos: None,
             yaw: -90.0,
             pitch: 0.0,
+            scrolled: 0.0,
             speed: 3.0,
+            scroll_zoom: ScrollZoom::default(),
+            shake: CameraShake::default(),
         }
     }
 
+    /// Turns the camera it flies to look in `forward`.
+    pub fn look_in(&mut self, forward: Vector3<f32>) {
+        let forward = forward.normalize();
+        self.yaw = forward.z.atan2(forward.x).to_degrees();
+        self.pitch = forward.y.asin().to_degrees().clamp(-89.0, 89.0);
+    }
+
     pub fn input(&mut self, event: &WindowEvent, window: &mut Window) {
         match event {
             WindowEvent::KeyboardInput {
@@ -201,21 +393,28 @@ impl CameraController {
                 self.yaw += x_offset * 0.1;
                 self.pitch += y_offset * 0.1;
 
-                if self.pitch > 89.0 {
-                    self.pitch = 89.0;
-                } else if self.pitch < -89.0 {
-                    self.pitch = -89.0;
-                }
+                self.pitch = self.pitch.clamp(-89.0, 89.0);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scrolled += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
             }
             _ => {}
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera, delta_time: f32) {
+    /// Moves and turns `camera` as it's being flown, scrolled and shaken, `time` seconds into
+    /// the animation.
+    pub fn update_camera(&mut self, camera: &mut Camera, delta_time: f32, time: f32) {
+        let shake = self.shake.offset(time);
+        let yaw = self.yaw + shake.x;
+        let pitch = (self.pitch + shake.y).clamp(-89.0, 89.0);
         let new_forward = Vector3::new(
-            self.yaw.to_radians().cos() * self.pitch.to_radians().cos(),
-            self.pitch.to_radians().sin(),
-            self.yaw.to_radians().sin() * self.pitch.to_radians().cos(),
+            yaw.to_radians().cos() * pitch.to_radians().cos(),
+            pitch.to_radians().sin(),
+            yaw.to_radians().sin() * pitch.to_radians().cos(),
         );
 
         if camera.forward.dot(new_forward) < 0.999999 {
@@ -254,5 +453,24 @@ impl CameraController {
             camera.last_move_time = Instant::now();
         }
         camera.origin += (forward + right + up) * self.speed * delta_time;
+
+        let scrolled = std::mem::take(&mut self.scrolled);
+        if scrolled != 0.0 {
+            match self.scroll_zoom {
+                ScrollZoom::Fov => {
+                    camera.vfov = (camera.vfov / FOV_ZOOM_STEP.powf(scrolled)).clamp(1.0, 170.0);
+                }
+                ScrollZoom::Dolly => {
+                    camera.origin += camera.forward * scrolled * self.speed * DOLLY_STEP;
+                }
+            }
+            camera.mark_moved();
+        }
+    }
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
     }
 }
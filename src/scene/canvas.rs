@@ -0,0 +1,212 @@
+use std::{io::Cursor, path::Path};
+
+use cgmath::{Vector2, Vector3};
+use egui::ecolor::{gamma_u8_from_linear_f32, linear_f32_from_gamma_u8};
+use image::{imageops::FilterType, ImageFormat, ImageOutputFormat, ImageResult, RgbaImage};
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::MAX_NUMBER_OF_CANVASES;
+
+use super::{MeshRef, Scene, SceneEvent};
+
+/// The width and height in texels of every canvas, which matches the layers of the texture the
+/// compute shader reads them from.
+pub const CANVAS_SIZE: u32 = 512;
+
+/// An albedo painted over a mesh by its texture coordinates, multiplying the albedo of whatever
+/// it's made of. Kept on the CPU and uploaded a few rows at a time as it's painted.
+///
+/// Saved as a PNG, which a mask or ID map compresses well in.
+#[derive(Clone)]
+pub struct Canvas {
+    /// sRGB with an opaque alpha, row by row from the top, i.e. from a V of 1.
+    texels: Vec<[u8; 4]>,
+}
+
+/// The rows of a canvas that changed, `start..end` from the top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanvasRows {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl CanvasRows {
+    pub const ALL: Self = Self {
+        start: 0,
+        end: CANVAS_SIZE,
+    };
+
+    /// The rows in either `self` or `other`, and any between them.
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+impl Canvas {
+    /// A white canvas, which leaves the albedo it's painted over as it is.
+    pub fn new() -> Self {
+        Self {
+            texels: vec![[255; 4]; (CANVAS_SIZE * CANVAS_SIZE) as usize],
+        }
+    }
+
+    /// The texels of `rows`, as the texture they're uploaded to is laid out.
+    pub fn rows(&self, rows: CanvasRows) -> &[u8] {
+        let row = |y: u32| (y * CANVAS_SIZE) as usize;
+        bytemuck::cast_slice(&self.texels[row(rows.start)..row(rows.end)])
+    }
+
+    /// The linear albedo painted at `uv`, which wraps around. Matches `canvasAlbedo` in the compute
+    /// shader.
+    pub fn albedo(&self, uv: Vector2<f32>) -> Vector3<f32> {
+        let size = CANVAS_SIZE as f32;
+        let wrapped = Vector2::new(uv.x.rem_euclid(1.0), (1.0 - uv.y).rem_euclid(1.0));
+        let texel = |coordinate: f32| ((coordinate * size) as u32).min(CANVAS_SIZE - 1);
+        let [r, g, b, _] =
+            self.texels[(texel(wrapped.y) * CANVAS_SIZE + texel(wrapped.x)) as usize];
+        Vector3::new(r, g, b).map(linear_f32_from_gamma_u8)
+    }
+
+    /// Paints a dab of the linear `color` with an antialiased edge `radius` texels around `uv`,
+    /// which wraps around like the shader samples it, returning the rows it touched.
+    pub fn paint(&mut self, uv: Vector2<f32>, radius: f32, color: Vector3<f32>) -> CanvasRows {
+        let size = CANVAS_SIZE as f32;
+        let center = Vector2::new(uv.x.rem_euclid(1.0), (1.0 - uv.y).rem_euclid(1.0)) * size;
+        if !center.x.is_finite() || !center.y.is_finite() {
+            return CanvasRows { start: 0, end: 0 };
+        }
+
+        let clamp = |value: f32| value.clamp(0.0, size) as u32;
+        let (x_start, x_end) = (clamp(center.x - radius), clamp(center.x + radius + 1.0));
+        let rows = CanvasRows {
+            start: clamp(center.y - radius),
+            end: clamp(center.y + radius + 1.0),
+        };
+        for y in rows.start..rows.end {
+            for x in x_start..x_end {
+                let offset = Vector2::new(x as f32 + 0.5, y as f32 + 0.5) - center;
+                let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+                let coverage = (radius - distance + 0.5).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    let texel = &mut self.texels[(y * CANVAS_SIZE + x) as usize];
+                    blend(texel, color, coverage);
+                }
+            }
+        }
+        rows
+    }
+
+    /// Paints all of the canvas the linear `color`.
+    pub fn fill(&mut self, color: Vector3<f32>) -> CanvasRows {
+        let mut texel = [255; 4];
+        blend(&mut texel, color, 1.0);
+        self.texels.fill(texel);
+        CanvasRows::ALL
+    }
+
+    /// Exports the canvas, to be used as a mask or ID map elsewhere.
+    pub fn save_png(&self, path: &Path) -> ImageResult<()> {
+        self.image().save_with_format(path, ImageFormat::Png)
+    }
+
+    fn image(&self) -> RgbaImage {
+        let bytes = bytemuck::cast_slice(&self.texels).to_vec();
+        RgbaImage::from_raw(CANVAS_SIZE, CANVAS_SIZE, bytes).expect("canvas is the right size")
+    }
+
+    /// Reads a canvas saved as a PNG, resizing it if it's another size.
+    fn from_png(png: &[u8]) -> ImageResult<Self> {
+        let image = image::load_from_memory_with_format(png, ImageFormat::Png)?
+            .resize_exact(CANVAS_SIZE, CANVAS_SIZE, FilterType::Triangle)
+            .into_rgba8();
+        let texels = image
+            .pixels()
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect();
+        Ok(Self { texels })
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mixes `coverage` of the linear `color` into the sRGB `texel`, in linear space.
+fn blend(texel: &mut [u8; 4], color: Vector3<f32>, coverage: f32) {
+    for (channel, color) in texel.iter_mut().zip([color.x, color.y, color.z]) {
+        let old = linear_f32_from_gamma_u8(*channel);
+        *channel = gamma_u8_from_linear_f32(old + (color.clamp(0.0, 1.0) - old) * coverage);
+    }
+}
+
+impl Serialize for Canvas {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut png = Vec::new();
+        self.image()
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .map_err(ser::Error::custom)?;
+        serializer.serialize_bytes(&png)
+    }
+}
+
+impl<'de> Deserialize<'de> for Canvas {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let png = Vec::<u8>::deserialize(deserializer)?;
+        Self::from_png(&png).map_err(de::Error::custom)
+    }
+}
+
+impl Scene {
+    /// The mesh of the instance selected last, if that's what was.
+    pub fn selected_mesh(&self) -> Option<usize> {
+        let &uuid = self.selection.last()?;
+        Some(self.get::<MeshRef>(uuid)?.mesh)
+    }
+
+    /// Gives the mesh at `mesh` a white canvas to paint on, unless it has one or there's no room
+    /// for more.
+    pub fn add_canvas(&mut self, mesh: usize) {
+        let canvases = self.meshes.iter().filter(|m| m.canvas.is_some()).count();
+        if self.meshes[mesh].canvas.is_some() || canvases >= MAX_NUMBER_OF_CANVASES as usize {
+            return;
+        }
+        self.meshes[mesh].canvas = Some(Canvas::new());
+        self.publish(SceneEvent::CanvasChanged {
+            mesh: self.meshes[mesh].uuid,
+            rows: CanvasRows::ALL,
+        });
+    }
+
+    pub fn remove_canvas(&mut self, mesh: usize) {
+        if self.meshes[mesh].canvas.take().is_some() {
+            self.publish(SceneEvent::CanvasChanged {
+                mesh: self.meshes[mesh].uuid,
+                rows: CanvasRows::ALL,
+            });
+        }
+    }
+
+    /// Paints the canvas of the mesh at `mesh` with `paint`, which returns the rows it changed.
+    /// Does nothing if the mesh has no canvas.
+    pub fn paint_canvas(&mut self, mesh: usize, paint: impl FnOnce(&mut Canvas) -> CanvasRows) {
+        let Some(canvas) = &mut self.meshes[mesh].canvas else {
+            return;
+        };
+        let rows = paint(canvas);
+        if !rows.is_empty() {
+            self.publish(SceneEvent::CanvasChanged {
+                mesh: self.meshes[mesh].uuid,
+                rows,
+            });
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Copying objects as text and pasting them back, so they can be moved between scenes or
+//! shared in a message.
+
+use std::{borrow::Cow, collections::HashMap, io};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{MAX_NUMBER_OF_INSTANCES, MAX_NUMBER_OF_SPHERES};
+
+use super::{
+    file::{invalid_data, mesh_indices, InstanceFile, MeshFile, FORMAT_VERSION},
+    Camera, MaterialId, MaterialLibrary, MeshInstance, Repair, Scene, SceneEvent, Sphere,
+};
+
+/// Tells copied objects apart from any other JSON on the clipboard.
+const CLIPBOARD_KIND: &str = "pathtracer-objects";
+
+/// What [`Scene::copy_selected`] writes, with the meshes the instances are of, so they can be
+/// pasted into scenes that don't have them.
+#[derive(Serialize, Deserialize)]
+struct Clipboard<'a> {
+    kind: Cow<'a, str>,
+    /// The [`FORMAT_VERSION`] of scene files from the same build.
+    version: u32,
+    #[serde(default)]
+    spheres: Vec<Sphere>,
+    /// The library of the scene copied from, which the spheres and triangles refer to.
+    #[serde(default)]
+    materials: Cow<'a, MaterialLibrary>,
+    #[serde(default)]
+    meshes: Vec<MeshFile<'a>>,
+    #[serde(default)]
+    instances: Vec<InstanceFile>,
+}
+
+impl Scene {
+    /// The selected spheres and instances as JSON, `None` if nothing is selected.
+    pub fn copy_selected(&self) -> Option<String> {
+        let spheres = self
+            .spheres()
+            .iter()
+            .filter(|s| self.is_selected(s.id.0))
+            .map(Sphere::from_components)
+            .collect::<Vec<_>>();
+        let instances = self
+            .instances()
+            .iter()
+            .filter(|i| self.is_selected(i.id.0))
+            .map(MeshInstance::from_components)
+            .collect::<Vec<_>>();
+        if spheres.is_empty() && instances.is_empty() {
+            return None;
+        }
+
+        let mut meshes = instances.iter().map(|i| i.mesh).collect::<Vec<_>>();
+        meshes.sort_unstable();
+        meshes.dedup();
+        let clipboard = Clipboard {
+            kind: Cow::Borrowed(CLIPBOARD_KIND),
+            version: FORMAT_VERSION,
+            spheres,
+            materials: Cow::Borrowed(&self.materials),
+            meshes: meshes
+                .into_iter()
+                .map(|mesh| MeshFile::from(&self.meshes[mesh]))
+                .collect(),
+            instances: instances
+                .into_iter()
+                .map(|instance| InstanceFile::new(&instance, &self.meshes))
+                .collect(),
+        };
+        serde_json::to_string(&clipboard).ok()
+    }
+
+    /// Adds the objects in `text` from [`Scene::copy_selected`], in this scene or another, under
+    /// new UUIDs and selects them. Meshes the scene doesn't have yet are added with them, and
+    /// materials it has nothing looking like.
+    /// Whatever the renderer can't handle is repaired as it is when loading, returning what.
+    pub fn paste(&mut self, text: &str) -> io::Result<Vec<Repair>> {
+        let clipboard = serde_json::from_str::<Clipboard>(text.trim())
+            .ok()
+            .filter(|clipboard| clipboard.kind == CLIPBOARD_KIND)
+            .ok_or_else(|| invalid_data("the clipboard doesn't hold copied objects".to_string()))?;
+        if clipboard.version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "copied in version {} of the format, but this build reads {}",
+                clipboard.version, FORMAT_VERSION
+            )));
+        }
+        if self.sphere_count() + clipboard.spheres.len() > MAX_NUMBER_OF_SPHERES as usize {
+            return Err(invalid_data(format!(
+                "no room for {} more spheres",
+                clipboard.spheres.len()
+            )));
+        }
+        if self.instance_count() + clipboard.instances.len() > MAX_NUMBER_OF_INSTANCES as usize {
+            return Err(invalid_data(format!(
+                "no room for {} more mesh instances",
+                clipboard.instances.len()
+            )));
+        }
+
+        let meshes = clipboard
+            .meshes
+            .into_iter()
+            .map(MeshFile::into_mesh)
+            .collect::<Vec<_>>();
+        let indices = mesh_indices(&meshes);
+        let instances = clipboard
+            .instances
+            .into_iter()
+            .map(|instance| instance.into_instance(&indices))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Repaired on their own, since the clipboard can hold anything another program put there
+        let mut pasted = Scene::with_meshes(clipboard.spheres, meshes, instances, Camera::new());
+        pasted.materials = clipboard.materials.into_owned();
+        let repairs = pasted.validate();
+        let spheres = pasted
+            .spheres()
+            .iter()
+            .map(Sphere::from_components)
+            .collect::<Vec<_>>();
+        let instances = pasted
+            .instances()
+            .iter()
+            .map(MeshInstance::from_components)
+            .collect::<Vec<_>>();
+
+        // IDs only mean something in the scene copied from, so materials are matched by looks
+        let mut materials = HashMap::new();
+        let mut material = |id: MaterialId, scene: &mut Scene| {
+            *materials.entry(id).or_insert_with(|| {
+                scene
+                    .materials
+                    .find_or_add_like(pasted.materials[id].clone())
+            })
+        };
+
+        let mut meshes = Vec::new();
+        for mut mesh in pasted.meshes {
+            let index = match self.meshes.iter().position(|m| m.uuid == mesh.uuid) {
+                Some(index) => index,
+                None => {
+                    for triangle in &mut mesh.triangles {
+                        triangle.material = material(triangle.material, self);
+                    }
+                    let uuid = mesh.uuid;
+                    self.meshes.push(mesh);
+                    self.publish(SceneEvent::MeshAdded(uuid));
+                    self.meshes.len() - 1
+                }
+            };
+            meshes.push(index);
+        }
+
+        let mut uuids = Vec::new();
+        for mut sphere in spheres {
+            sphere.uuid = Uuid::new_v4();
+            sphere.material = material(sphere.material, self);
+            uuids.push(self.push_sphere(sphere));
+        }
+        let has_instances = !instances.is_empty();
+        for mut instance in instances {
+            let uuid = Uuid::new_v4();
+            instance.uuid = uuid;
+            instance.mesh = meshes[instance.mesh];
+            instance.name = self.unique_name(&instance.name);
+            self.spawn_instance(instance);
+            self.publish(SceneEvent::ObjectAdded(uuid));
+            uuids.push(uuid);
+        }
+        if has_instances {
+            self.update_tlas();
+        }
+
+        self.select_all(uuids, false);
+        Ok(repairs)
+    }
+}
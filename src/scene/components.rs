@@ -0,0 +1,200 @@
+//! What the scene's objects are made of. Each sphere, mesh instance and light is an entity in the
+//! scene's [`World`], with the components for what it is: spheres have a [`SphereShape`] and a
+//! [`Surface`], instances a [`MeshRef`], and both a [`Transform`] and an [`Emitter`].
+
+use bevy_ecs::{
+    prelude::{Component, Entity, World},
+    query::{QueryState, WorldQuery},
+};
+use cgmath::{Matrix4, SquareMatrix, Vector3};
+use uuid::Uuid;
+
+use crate::{
+    expression::Expression,
+    geometry::{self, Aabb},
+};
+
+use super::{Light, LightLinks, MaterialGraph, MaterialId, Mesh};
+
+/// Identifies an object wherever it's referred to from outside the world: the selection, light
+/// links, events and saved files.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub Uuid);
+
+/// Unique among the scene's objects, given when the object is added to it.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct Name(pub String);
+
+/// When an object was added, which objects are listed, uploaded and picked in.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Order(pub(super) u64);
+
+/// Where an object is. Spheres are placed by their position alone, their size being their
+/// [`SphereShape`].
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub position: Vector3<f32>,
+    /// Euler angles in degrees, applied around X, then Y, then Z.
+    pub rotation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    /// Unturned and unscaled at `position`.
+    pub fn at(position: Vector3<f32>) -> Self {
+        Self {
+            position,
+            rotation: Vector3::new(0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// From the object's own space to world space.
+    pub fn object_to_world(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from(geometry::euler_to_matrix(self.rotation))
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// The identity if the object is scaled to nothing on some axis.
+    pub fn world_to_object(&self) -> Matrix4<f32> {
+        self.object_to_world()
+            .invert()
+            .unwrap_or(Matrix4::identity())
+    }
+
+    /// The box around `mesh`'s bounds once placed.
+    pub fn world_bounds(&self, mesh: &Mesh) -> Aabb {
+        let bounds = mesh.bounds();
+        // Transforming the corners of an inverted box could turn it into a huge real one
+        if bounds.is_empty() {
+            return bounds;
+        }
+        let transform = self.object_to_world();
+        Aabb::from_points((0..8).map(|corner| {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    bounds.min[axis]
+                } else {
+                    bounds.max[axis]
+                }
+            };
+            (transform * Vector3::new(pick(0), pick(1), pick(2)).extend(1.0)).truncate()
+        }))
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct SphereShape {
+    pub radius: f32,
+}
+
+impl SphereShape {
+    /// The box around a sphere of the shape at `center`.
+    pub fn bounds(&self, center: Vector3<f32>) -> Aabb {
+        let extent = Vector3::new(1.0, 1.0, 1.0) * self.radius;
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+/// Places one of the scene's meshes.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshRef {
+    /// Into the scene's meshes.
+    pub mesh: usize,
+}
+
+/// What a sphere is made of. The triangles of meshes carry their own materials.
+#[derive(Component, Debug, Clone)]
+pub struct Surface {
+    /// Which of the scene's materials the object is made of.
+    pub material: MaterialId,
+    /// Multiplies the material's albedo wherever the object is hit.
+    pub albedo_expression: Option<Expression>,
+    /// The nodes `albedo_expression` was built from, if it wasn't typed in.
+    pub material_graph: Option<MaterialGraph>,
+}
+
+/// How an object lights the scene while it's emissive.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Emitter {
+    /// Whether camera rays see the object's emission. A hidden emitter still lights the scene
+    /// and shows in reflections, like a studio light out of shot.
+    pub camera_visible: bool,
+    /// Which objects the emission illuminates.
+    pub light_links: LightLinks,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            camera_visible: true,
+            light_links: LightLinks::default(),
+        }
+    }
+}
+
+/// The components of a sphere.
+#[derive(WorldQuery)]
+#[world_query(mutable)]
+pub struct SphereQuery {
+    pub entity: Entity,
+    pub order: &'static Order,
+    pub id: &'static ObjectId,
+    pub name: &'static mut Name,
+    pub transform: &'static mut Transform,
+    pub shape: &'static mut SphereShape,
+    pub surface: &'static mut Surface,
+    pub emitter: &'static mut Emitter,
+}
+
+/// The components of a mesh instance.
+#[derive(WorldQuery)]
+#[world_query(mutable)]
+pub struct InstanceQuery {
+    pub entity: Entity,
+    pub order: &'static Order,
+    pub id: &'static ObjectId,
+    pub name: &'static mut Name,
+    pub transform: &'static mut Transform,
+    pub mesh: &'static MeshRef,
+    pub emitter: &'static mut Emitter,
+}
+
+/// The components of a light.
+#[derive(WorldQuery)]
+#[world_query(mutable)]
+pub struct LightQuery {
+    pub entity: Entity,
+    pub order: &'static Order,
+    pub id: &'static ObjectId,
+    pub name: &'static mut Name,
+    pub light: &'static mut Light,
+}
+
+/// The queries the scene's objects are read through. Their archetypes are brought up to date
+/// whenever an object is spawned, so they can be run from a shared reference to the world.
+pub(super) struct Queries {
+    pub spheres: QueryState<SphereQuery>,
+    pub instances: QueryState<InstanceQuery>,
+    pub lights: QueryState<LightQuery>,
+}
+
+impl Queries {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            spheres: world.query(),
+            instances: world.query(),
+            lights: world.query(),
+        }
+    }
+
+    pub fn update_archetypes(&mut self, world: &World) {
+        self.spheres.update_archetypes(world);
+        self.instances.update_archetypes(world);
+        self.lights.update_archetypes(world);
+    }
+}
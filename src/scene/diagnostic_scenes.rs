@@ -0,0 +1,133 @@
+use cgmath::Vector3;
+
+use super::{
+    Camera, LibraryMaterial, Material, MaterialLibrary, Plane, Scene, Sphere, SphereDescriptor,
+    DEFAULT_IOR,
+};
+
+/// The radiance [`Scene::white_furnace`] is meant to be lit with from every direction, which
+/// every pixel of its render converges to.
+pub const FURNACE_RADIANCE: f32 = 1.0;
+
+impl Scene {
+    /// Veach's multiple importance sampling scene: four plates reflecting four spherical
+    /// lights of the same power, from a tiny bright one to a large dim one. Light sampling
+    /// handles the small lights on the broad reflections and BSDF sampling the large lights on
+    /// the sharp ones, so noise concentrated in either corner points at the strategy that's
+    /// failing. Meant to be lit by a black environment.
+    pub fn veach_mis() -> Self {
+        let mut materials = MaterialLibrary::new();
+        let backdrop = materials.find_or_add(Vector3::new(0.4, 0.4, 0.4), Material::Diffuse);
+
+        // The radii of the lights, each emitting the same power
+        let lights = [(-3.75, 0.0333), (-1.25, 0.1), (1.25, 0.3), (3.75, 0.9)];
+        let mut spheres = lights
+            .into_iter()
+            .map(|(x, radius): (f32, f32)| {
+                let intensity = 1.0 / (radius * radius);
+                let material = materials.find_or_add(
+                    Vector3::new(1.0, 1.0, 1.0),
+                    Material::Emissive { intensity },
+                );
+                Sphere::new(SphereDescriptor {
+                    center: Vector3::new(x, 0.0, 0.0),
+                    radius,
+                    material,
+                })
+            })
+            .collect::<Vec<_>>();
+        // Lights the backdrop from above and behind the camera
+        let fill = materials.find_or_add(
+            Vector3::new(1.0, 1.0, 1.0),
+            Material::Emissive { intensity: 800.0 },
+        );
+        spheres.push(Sphere::new(SphereDescriptor {
+            center: Vector3::new(10.0, 10.0, 4.0),
+            radius: 0.5,
+            material: fill,
+        }));
+
+        // The near and far edges of each plate, which are 8 wide, and how rough it is. Like
+        // Veach's, they get rougher towards the camera.
+        let plates = [
+            ((-2.70651, 0.25609), (-2.08375, -0.526323), 0.01),
+            ((-3.28825, 1.36972), (-2.83856, 0.476536), 0.04),
+            ((-3.73096, 2.70046), (-3.43378, 1.74564), 0.1),
+            ((-3.99615, 4.0667), (-3.82069, 3.08221), 0.2),
+        ];
+        let mut triangles = plates
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, ((near_y, near_z), (far_y, far_z), roughness))| {
+                let plate = materials.add(LibraryMaterial {
+                    name: format!("Plate {}", i + 1),
+                    albedo: Vector3::new(0.8, 0.8, 0.8),
+                    material: Material::Metal,
+                    roughness,
+                    ior: DEFAULT_IOR,
+                });
+                Plane {
+                    q: Vector3::new(-4.0, near_y, near_z),
+                    u: Vector3::new(8.0, 0.0, 0.0),
+                    v: Vector3::new(0.0, far_y - near_y, far_z - near_z),
+                }
+                .triangles(plate)
+            })
+            .collect::<Vec<_>>();
+        triangles.extend(
+            Plane {
+                q: Vector3::new(-10.0, -4.14615, 10.0),
+                u: Vector3::new(20.0, 0.0, 0.0),
+                v: Vector3::new(0.0, 0.0, -20.0),
+            }
+            .triangles(backdrop),
+        );
+        triangles.extend(
+            Plane {
+                q: Vector3::new(-10.0, -4.14615, -2.0),
+                u: Vector3::new(20.0, 0.0, 0.0),
+                v: Vector3::new(0.0, 20.0, 0.0),
+            }
+            .triangles(backdrop),
+        );
+
+        let mut camera = Camera::new();
+        camera.origin = Vector3::new(0.0, 2.0, 15.0);
+        camera.look_in(Vector3::new(0.0, -2.0, 2.5) - camera.origin);
+        camera.vfov = 28.0;
+
+        let mut scene = Scene::new(spheres, triangles, camera);
+        scene.name = "Veach MIS".to_string();
+        scene.materials = materials;
+        scene
+    }
+
+    /// A white diffuse, metal and glass sphere, none of which absorb any light. Lit evenly by
+    /// an environment of [`FURNACE_RADIANCE`] they should vanish into it, so any pixel of the
+    /// render that isn't that radiance is light being lost or made up by their sampling.
+    pub fn white_furnace() -> Self {
+        let mut materials = MaterialLibrary::new();
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        let spheres = [Material::Diffuse, Material::Metal, Material::Dielectric]
+            .into_iter()
+            .zip([-1.1, 0.0, 1.1])
+            .map(|(material, x)| {
+                Sphere::new(SphereDescriptor {
+                    center: Vector3::new(x, 0.0, 0.0),
+                    radius: 0.5,
+                    material: materials.find_or_add(white, material),
+                })
+            })
+            .collect();
+
+        let mut camera = Camera::new();
+        camera.origin = Vector3::new(0.0, 0.0, 3.0);
+        camera.look_in(-camera.origin);
+        camera.vfov = 60.0;
+
+        let mut scene = Scene::new(spheres, Vec::new(), camera);
+        scene.name = "White furnace".to_string();
+        scene.materials = materials;
+        scene
+    }
+}
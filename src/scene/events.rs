@@ -0,0 +1,62 @@
+use uuid::Uuid;
+
+use super::{CanvasRows, MaterialId};
+
+/// A change to the scene, published with [`super::Scene::publish`] by whatever made it, so the
+/// renderer and the editor tools can react without being called from there directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SceneEvent {
+    ObjectAdded(Uuid),
+    ObjectRemoved(Uuid),
+    /// A mesh was added to the scene, to be uploaded along with the rest.
+    MeshAdded(Uuid),
+    /// A sphere or mesh instance was moved or resized.
+    ObjectMoved(Uuid),
+    /// Which material a sphere is made of, or whether an object's emitters are visible to the
+    /// camera, changed.
+    MaterialChanged(Uuid),
+    /// A material in the scene's library was added or edited, changing every object made of it.
+    MaterialEdited(MaterialId),
+    /// A sphere, mesh, mesh instance or light was renamed.
+    ObjectRenamed(Uuid),
+    /// A light was added, removed or edited, or which objects an emissive sphere or mesh
+    /// instance illuminates changed.
+    LightChanged(Uuid),
+    /// The scene's nits per unit radiance changed, which rescales the lights given in physical
+    /// units.
+    UnitsChanged,
+    /// The final camera was moved or reframed. The free camera isn't part of the scene's
+    /// contents, so moving it isn't an event.
+    CameraMoved,
+    /// The HDRI the scene is lit by was replaced.
+    EnvironmentChanged,
+    /// A mesh's canvas was added or removed, which changes all of its `rows`, or painted.
+    CanvasChanged {
+        mesh: Uuid,
+        rows: CanvasRows,
+    },
+    /// The meshes' BVHs were rebuilt over the same triangles.
+    BvhRebuilt,
+    /// The whole scene was replaced, e.g. by loading a file.
+    SceneReplaced,
+}
+
+impl SceneEvent {
+    /// Whether the event is an edit that should be saved.
+    pub fn is_edit(&self) -> bool {
+        match self {
+            Self::ObjectAdded(_)
+            | Self::ObjectRemoved(_)
+            | Self::MeshAdded(_)
+            | Self::ObjectMoved(_)
+            | Self::MaterialChanged(_)
+            | Self::MaterialEdited(_)
+            | Self::ObjectRenamed(_)
+            | Self::LightChanged(_)
+            | Self::CanvasChanged { .. }
+            | Self::UnitsChanged
+            | Self::CameraMoved => true,
+            Self::EnvironmentChanged | Self::BvhRebuilt | Self::SceneReplaced => false,
+        }
+    }
+}
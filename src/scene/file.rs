@@ -0,0 +1,430 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    model::Triangle, renderer::RenderSettings, MAX_NUMBER_OF_INSTANCES, MAX_NUMBER_OF_MATERIALS,
+};
+
+use super::{
+    Camera, Canvas, Light, LightLinks, Material, MaterialLibrary, Mesh, MeshInstance, Scene,
+    Sphere, DEFAULT_NITS_PER_UNIT,
+};
+
+/// The version of the format [`SceneSnapshot::write`] writes. Files without one are version 1.
+pub(super) const FORMAT_VERSION: u32 = 3;
+
+/// Upgrades a file of the version at each index plus one to the next version, in place. Adding
+/// a version means adding a migration here and bumping [`FORMAT_VERSION`].
+const MIGRATIONS: [fn(&mut Value) -> io::Result<()>; FORMAT_VERSION as usize - 1] =
+    [refer_to_meshes_by_uuid, share_materials];
+
+/// What [`SceneSnapshot::write`] writes as JSON.
+#[derive(Serialize, Deserialize)]
+struct SceneFile<'a> {
+    version: u32,
+    camera: Cow<'a, Camera>,
+    final_camera: Cow<'a, Camera>,
+    spheres: Vec<Sphere>,
+    #[serde(default)]
+    lights: Vec<LightFile>,
+    #[serde(default = "default_nits_per_unit")]
+    nits_per_unit: f32,
+    #[serde(default)]
+    materials: Cow<'a, MaterialLibrary>,
+    #[serde(default)]
+    meshes: Vec<MeshFile<'a>>,
+    #[serde(default)]
+    instances: Vec<InstanceFile>,
+    render_settings: Cow<'a, RenderSettings>,
+}
+
+/// A light with the components that identify it, which the other objects' records carry
+/// themselves.
+#[derive(Serialize, Deserialize)]
+struct LightFile {
+    uuid: Uuid,
+    #[serde(default)]
+    name: String,
+    #[serde(flatten)]
+    light: Light,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct MeshFile<'a> {
+    uuid: Uuid,
+    name: Cow<'a, str>,
+    triangles: Cow<'a, [Triangle]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    canvas: Option<Cow<'a, Canvas>>,
+}
+
+impl<'a> From<&'a Mesh> for MeshFile<'a> {
+    fn from(mesh: &'a Mesh) -> Self {
+        Self {
+            uuid: mesh.uuid,
+            name: Cow::Borrowed(&mesh.name),
+            triangles: Cow::Borrowed(&mesh.triangles),
+            canvas: mesh.canvas.as_ref().map(Cow::Borrowed),
+        }
+    }
+}
+
+impl MeshFile<'_> {
+    fn into_owned(self) -> MeshFile<'static> {
+        MeshFile {
+            uuid: self.uuid,
+            name: Cow::Owned(self.name.into_owned()),
+            triangles: Cow::Owned(self.triangles.into_owned()),
+            canvas: self.canvas.map(|canvas| Cow::Owned(canvas.into_owned())),
+        }
+    }
+
+    /// Builds the mesh's BVH, keeping its UUID.
+    pub(super) fn into_mesh(self) -> Mesh {
+        Mesh {
+            uuid: self.uuid,
+            canvas: self.canvas.map(Cow::into_owned),
+            ..Mesh::new(self.name.into_owned(), self.triangles.into_owned())
+        }
+    }
+}
+
+/// A [`MeshInstance`] referring to its mesh by UUID, so it doesn't depend on the order the
+/// meshes are saved in.
+#[derive(Serialize, Deserialize)]
+pub(super) struct InstanceFile {
+    uuid: Uuid,
+    #[serde(default)]
+    name: String,
+    mesh: Uuid,
+    position: Vector3<f32>,
+    rotation: Vector3<f32>,
+    scale: Vector3<f32>,
+    #[serde(default = "super::default_camera_visible")]
+    camera_visible: bool,
+    #[serde(default, skip_serializing_if = "LightLinks::is_empty")]
+    light_links: LightLinks,
+}
+
+impl InstanceFile {
+    /// `instance`, which is of one of `meshes`.
+    pub(super) fn new(instance: &MeshInstance, meshes: &[Mesh]) -> Self {
+        Self {
+            uuid: instance.uuid,
+            name: instance.name.clone(),
+            mesh: meshes[instance.mesh].uuid,
+            position: instance.position,
+            rotation: instance.rotation,
+            scale: instance.scale,
+            camera_visible: instance.camera_visible,
+            light_links: instance.light_links.clone(),
+        }
+    }
+
+    /// The instance, with its mesh looked up in `indices` from UUIDs to indices.
+    pub(super) fn into_instance(self, indices: &HashMap<Uuid, usize>) -> io::Result<MeshInstance> {
+        let mesh = *indices.get(&self.mesh).ok_or_else(|| {
+            invalid_data(format!("instance {} refers to a missing mesh", self.uuid))
+        })?;
+        Ok(MeshInstance {
+            uuid: self.uuid,
+            name: self.name,
+            mesh,
+            position: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+            camera_visible: self.camera_visible,
+            light_links: self.light_links,
+        })
+    }
+}
+
+/// A copy of the scene as it was when saving began, so it can be written in the background
+/// while the scene goes on being edited.
+pub struct SceneSnapshot {
+    file: SceneFile<'static>,
+    /// How many edits the scene had had, see [`Scene::mark_saved`].
+    edits: u64,
+}
+
+/// A [`SceneSnapshot`] that was written, to be recorded with [`Scene::mark_saved`].
+pub struct SavedSnapshot {
+    edits: u64,
+}
+
+impl SceneSnapshot {
+    pub fn write(self, path: &Path) -> io::Result<SavedSnapshot> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &self.file)?;
+        Ok(SavedSnapshot { edits: self.edits })
+    }
+}
+
+impl SceneFile<'_> {
+    fn into_owned(self) -> SceneFile<'static> {
+        SceneFile {
+            version: self.version,
+            camera: Cow::Owned(self.camera.into_owned()),
+            final_camera: Cow::Owned(self.final_camera.into_owned()),
+            spheres: self.spheres,
+            lights: self.lights,
+            nits_per_unit: self.nits_per_unit,
+            materials: Cow::Owned(self.materials.into_owned()),
+            meshes: self.meshes.into_iter().map(MeshFile::into_owned).collect(),
+            instances: self.instances,
+            render_settings: Cow::Owned(self.render_settings.into_owned()),
+        }
+    }
+}
+
+impl Scene {
+    /// Copies the scene and `render_settings` to be written with [`SceneSnapshot::write`] and
+    /// then recorded with [`Scene::mark_saved`].
+    pub fn snapshot(&self, render_settings: &RenderSettings) -> SceneSnapshot {
+        let file = SceneFile {
+            version: FORMAT_VERSION,
+            camera: Cow::Borrowed(&self.camera),
+            final_camera: Cow::Borrowed(&self.final_camera),
+            spheres: self.spheres().iter().map(Sphere::from_components).collect(),
+            lights: self
+                .lights()
+                .into_iter()
+                .map(|light| LightFile {
+                    uuid: light.id.0,
+                    name: light.name.0.clone(),
+                    light: light.light.clone(),
+                })
+                .collect(),
+            nits_per_unit: self.nits_per_unit,
+            materials: Cow::Borrowed(&self.materials),
+            meshes: self.meshes.iter().map(MeshFile::from).collect(),
+            instances: self
+                .instances()
+                .iter()
+                .map(|instance| {
+                    InstanceFile::new(&MeshInstance::from_components(instance), &self.meshes)
+                })
+                .collect(),
+            render_settings: Cow::Borrowed(render_settings),
+        };
+        SceneSnapshot {
+            file: file.into_owned(),
+            edits: self.edits,
+        }
+    }
+
+    /// Names the scene after `path`, where a snapshot of it was written, leaving only the edits
+    /// made since the snapshot unsaved.
+    pub fn mark_saved(&mut self, path: &Path, saved: SavedSnapshot) {
+        self.name = name_from_path(path);
+        self.saved_edits = saved.edits;
+    }
+
+    /// Reads a scene written by [`SceneSnapshot::write`] by this or an earlier version, along
+    /// with the renderer settings saved with it.
+    pub fn load(path: &Path) -> io::Result<(Scene, RenderSettings)> {
+        let mut value: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        migrate(&mut value)?;
+        let file: SceneFile = serde_json::from_value(value)?;
+
+        let meshes = file
+            .meshes
+            .into_iter()
+            .map(MeshFile::into_mesh)
+            .collect::<Vec<_>>();
+        if file.instances.len() > MAX_NUMBER_OF_INSTANCES as usize {
+            return Err(invalid_data(format!(
+                "more than {} mesh instances",
+                MAX_NUMBER_OF_INSTANCES
+            )));
+        }
+        if file.materials.len() > MAX_NUMBER_OF_MATERIALS as usize {
+            return Err(invalid_data(format!(
+                "more than {} materials",
+                MAX_NUMBER_OF_MATERIALS
+            )));
+        }
+        let indices = mesh_indices(&meshes);
+        let instances = file
+            .instances
+            .into_iter()
+            .map(|instance| instance.into_instance(&indices))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut scene =
+            Scene::with_meshes(file.spheres, meshes, instances, file.camera.into_owned());
+        scene.final_camera = file.final_camera.into_owned();
+        for light in file.lights {
+            scene.spawn(light.uuid, light.name, light.light);
+        }
+        scene.nits_per_unit = file.nits_per_unit;
+        scene.materials = file.materials.into_owned();
+        // Files from before objects were named, or edited by hand, may not have unique names
+        scene.name_objects();
+        scene.name = name_from_path(path);
+
+        Ok((scene, file.render_settings.into_owned()))
+    }
+}
+
+/// Upgrades a saved scene from whichever version it was saved as to [`FORMAT_VERSION`].
+fn migrate(file: &mut Value) -> io::Result<()> {
+    let object = file
+        .as_object_mut()
+        .ok_or_else(|| invalid_data("not a scene file".to_string()))?;
+    let version = match object.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|&version| version >= 1)
+            .ok_or_else(|| invalid_data(format!("invalid version {}", version)))?
+            as u32,
+    };
+    if version > FORMAT_VERSION {
+        return Err(invalid_data(format!(
+            "saved in version {} of the format, newer than the {} this build reads",
+            version, FORMAT_VERSION
+        )));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(file)
+            .map_err(|e| invalid_data(format!("upgrading from version {}: {}", from + 1, e)))?;
+        file["version"] = json!(from + 2);
+    }
+    Ok(())
+}
+
+/// Version 1 referred to meshes by their index and, before instancing, kept a single mesh's
+/// `triangles` at the top level. It also saved the selection's gizmo sphere along with the
+/// scene.
+fn refer_to_meshes_by_uuid(file: &mut Value) -> io::Result<()> {
+    let object = file
+        .as_object_mut()
+        .ok_or_else(|| invalid_data("not a scene file".to_string()))?;
+
+    if let Some(triangles) = object.remove("triangles") {
+        let has_meshes = object
+            .get("meshes")
+            .and_then(Value::as_array)
+            .is_some_and(|meshes| !meshes.is_empty());
+        let is_empty = triangles.as_array().is_some_and(Vec::is_empty);
+        if !has_meshes && !is_empty {
+            object.insert(
+                "meshes".to_string(),
+                json!([{ "name": "Mesh", "triangles": triangles }]),
+            );
+            object.insert(
+                "instances".to_string(),
+                json!([{
+                    "uuid": Uuid::new_v4(),
+                    "mesh": 0,
+                    "position": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "rotation": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                    "scale": { "x": 1.0, "y": 1.0, "z": 1.0 },
+                }]),
+            );
+        }
+    }
+
+    let mut uuids = Vec::new();
+    if let Some(meshes) = object.get_mut("meshes").and_then(Value::as_array_mut) {
+        for mesh in meshes {
+            let uuid = Uuid::new_v4();
+            mesh["uuid"] = json!(uuid);
+            uuids.push(uuid);
+        }
+    }
+    if let Some(instances) = object.get_mut("instances").and_then(Value::as_array_mut) {
+        for instance in instances {
+            let uuid = instance["mesh"]
+                .as_u64()
+                .and_then(|index| uuids.get(index as usize))
+                .ok_or_else(|| {
+                    invalid_data(format!(
+                        "instance {} refers to a missing mesh",
+                        instance["uuid"]
+                    ))
+                })?;
+            instance["mesh"] = json!(uuid);
+        }
+    }
+
+    if let Some(spheres) = object.get_mut("spheres").and_then(Value::as_array_mut) {
+        spheres.retain(|sphere| sphere["material"] != "Gizmo");
+    }
+    Ok(())
+}
+
+/// Version 2 kept an albedo and material on every sphere and triangle, which now refer to a
+/// library of materials shared by the objects that look the same.
+fn share_materials(file: &mut Value) -> io::Result<()> {
+    let object = file
+        .as_object_mut()
+        .ok_or_else(|| invalid_data("not a scene file".to_string()))?;
+
+    let mut library = MaterialLibrary::new();
+    let mut share = |object: &mut Value| -> io::Result<()> {
+        let object = object
+            .as_object_mut()
+            .ok_or_else(|| invalid_data("an object isn't a JSON object".to_string()))?;
+        let (Some(albedo), Some(material)) = (object.remove("albedo"), object.remove("material"))
+        else {
+            return Err(invalid_data(
+                "an object has no albedo or material".to_string(),
+            ));
+        };
+        let albedo: Vector3<f32> = serde_json::from_value(albedo)?;
+        let material: Material = serde_json::from_value(material)?;
+        object.insert(
+            "material".to_string(),
+            json!(library.find_or_add(albedo, material)),
+        );
+        Ok(())
+    };
+
+    if let Some(spheres) = object.get_mut("spheres").and_then(Value::as_array_mut) {
+        spheres.iter_mut().try_for_each(&mut share)?;
+    }
+    if let Some(meshes) = object.get_mut("meshes").and_then(Value::as_array_mut) {
+        for mesh in meshes {
+            if let Some(triangles) = mesh.get_mut("triangles").and_then(Value::as_array_mut) {
+                triangles.iter_mut().try_for_each(&mut share)?;
+            }
+        }
+    }
+    object.insert("materials".to_string(), serde_json::to_value(&library)?);
+    Ok(())
+}
+
+fn default_nits_per_unit() -> f32 {
+    DEFAULT_NITS_PER_UNIT
+}
+
+/// Each mesh's index, by its UUID.
+pub(super) fn mesh_indices(meshes: &[Mesh]) -> HashMap<Uuid, usize> {
+    meshes
+        .iter()
+        .enumerate()
+        .map(|(index, mesh)| (mesh.uuid, index))
+        .collect()
+}
+
+pub(super) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(super) fn name_from_path(path: &Path) -> String {
+    path.file_stem().map_or("Untitled".to_string(), |stem| {
+        stem.to_string_lossy().into_owned()
+    })
+}
@@ -0,0 +1,688 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use cgmath::{Deg, InnerSpace, Matrix4, SquareMatrix, Vector2, Vector3, Zero};
+use roxmltree::{Document, Node};
+
+use super::{
+    super::{file::invalid_data, LightKind},
+    camera, conductor_albedo, light, ply, transform_point, transform_vector, vertical_fov,
+    Imported, IndexedMesh, Surface, DEFAULT_ALBEDO, DEFAULT_METAL_ALBEDO,
+};
+use crate::scene::DEFAULT_IOR;
+
+/// The indices of refraction Mitsuba lets dielectrics name rather than give as numbers.
+const NAMED_IORS: [(&str, f32); 9] = [
+    ("vacuum", 1.0),
+    ("air", 1.000277),
+    ("water", 1.333),
+    ("acrylic glass", 1.49),
+    ("polypropylene", 1.49),
+    ("bk7", 1.5046),
+    ("fused quartz", 1.458),
+    ("sodium chloride", 1.544),
+    ("diamond", 2.419),
+];
+
+/// How many segments a disk is made of.
+const DISK_SEGMENTS: u32 = 64;
+
+/// Reads the subset of a Mitsuba 0.6 or 3 XML scene this renderer has counterparts for.
+pub(super) fn import(path: &Path) -> io::Result<Imported> {
+    let source = fs::read_to_string(path)?;
+    let document = Document::parse(&source)
+        .map_err(|error| invalid_data(format!("{}: {}", path.display(), error)))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "scene" {
+        return Err(invalid_data(format!(
+            "{} isn't a Mitsuba scene",
+            path.display()
+        )));
+    }
+
+    let mut importer = Importer {
+        directory: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        defaults: HashMap::new(),
+        bsdfs: HashMap::new(),
+        imported: Imported::default(),
+    };
+    for node in root.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "default" => {
+                if let (Some(name), Some(value)) = (node.attribute("name"), node.attribute("value"))
+                {
+                    importer
+                        .defaults
+                        .insert(name.to_string(), value.to_string());
+                }
+            }
+            "bsdf" => {
+                let surface = importer.bsdf(node);
+                if let Some(id) = node.attribute("id") {
+                    importer.bsdfs.insert(id.to_string(), surface);
+                }
+            }
+            "shape" => importer.shape(node)?,
+            "sensor" | "camera" => importer.sensor(node),
+            "emitter" => importer.emitter(node),
+            "integrator" | "sampler" | "film" | "texture" => {}
+            "medium" => importer.imported.skip("participating media"),
+            other => importer.imported.skip(format!("<{}> elements", other)),
+        }
+    }
+    Ok(importer.imported)
+}
+
+struct Importer {
+    /// What meshes are found relative to.
+    directory: PathBuf,
+    /// The values of `$name` parameters, given by `<default>` elements.
+    defaults: HashMap<String, String>,
+    /// BSDFs defined at the top level, by their IDs.
+    bsdfs: HashMap<String, Surface>,
+    imported: Imported,
+}
+
+impl Importer {
+    /// The attribute `name` of `node`, with a `$parameter` replaced by its default.
+    fn attribute<'a>(&'a self, node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+        let value = node.attribute(name)?;
+        match value.strip_prefix('$') {
+            Some(parameter) => self.defaults.get(parameter).map(String::as_str),
+            None => Some(value),
+        }
+    }
+
+    /// The child of `node` given as the parameter `name`, which Mitsuba 0.6 wrote in camel case
+    /// and 3 in snake case, so both are passed.
+    fn parameter<'a, 'input>(
+        &self,
+        node: Node<'a, 'input>,
+        names: &[&str],
+    ) -> Option<Node<'a, 'input>> {
+        node.children().find(|child| {
+            child
+                .attribute("name")
+                .is_some_and(|name| names.contains(&name))
+        })
+    }
+
+    fn float(&self, node: Node, names: &[&str]) -> Option<f32> {
+        let child = self.parameter(node, names)?;
+        self.attribute(child, "value")?.trim().parse().ok()
+    }
+
+    fn string<'a>(&'a self, node: Node<'a, '_>, names: &[&str]) -> Option<&'a str> {
+        let child = self.parameter(node, names)?;
+        self.attribute(child, "value")
+    }
+
+    /// The index of refraction given as the parameter `names`, as a number or one of
+    /// [`NAMED_IORS`], or `default` if there's none.
+    fn ior(&mut self, node: Node, names: &[&str], default: f32) -> f32 {
+        let Some(value) = self.string(node, names).map(str::to_string) else {
+            return default;
+        };
+        let named = NAMED_IORS
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value.trim()));
+        match (value.trim().parse(), named) {
+            (Ok(ior), _) => ior,
+            (_, Some((_, ior))) => ior,
+            _ => {
+                self.imported.skip(format!(
+                    "the index of refraction \"{}\", imported as {}",
+                    value, default
+                ));
+                default
+            }
+        }
+    }
+
+    /// A point or vector given either as `x`, `y` and `z` attributes or as a list in `value`.
+    fn vector(&self, node: Node, default: f32) -> Option<Vector3<f32>> {
+        if let Some(value) = self.attribute(node, "value") {
+            return match *numbers(value).as_slice() {
+                [x, y, z] => Some(Vector3::new(x, y, z)),
+                [value] => Some(Vector3::new(value, value, value)),
+                _ => None,
+            };
+        }
+        let axis = |name| {
+            self.attribute(node, name)
+                .map_or(Some(default), |value| value.trim().parse().ok())
+        };
+        Some(Vector3::new(axis("x")?, axis("y")?, axis("z")?))
+    }
+
+    /// The color given as the parameter `names`, or `None` if it isn't or can't be read as one,
+    /// in which case it's noted as skipped.
+    fn color(&mut self, node: Node, names: &[&str]) -> Option<Vector3<f32>> {
+        let child = self.parameter(node, names)?;
+        let value = self.attribute(child, "value").map(str::to_string);
+        match (child.tag_name().name(), value) {
+            ("rgb" | "srgb" | "float", Some(value)) => match *numbers(&value).as_slice() {
+                [r, g, b] => Some(Vector3::new(r, g, b)),
+                [value] => Some(Vector3::new(value, value, value)),
+                _ => None,
+            },
+            // Either a single value or pairs of wavelengths and values, which become gray
+            ("spectrum", Some(value)) => {
+                let values = value
+                    .split(',')
+                    .map(|entry| entry.rsplit(':').next().unwrap_or(entry))
+                    .map(|value| value.trim().parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|values| !values.is_empty());
+                match values {
+                    Some(values) => {
+                        let average = values.iter().sum::<f32>() / values.len() as f32;
+                        Some(Vector3::new(average, average, average))
+                    }
+                    None => {
+                        self.imported
+                            .skip("spectra from files, imported as their default");
+                        None
+                    }
+                }
+            }
+            ("blackbody", _) => {
+                self.imported.skip("blackbody colors, imported as white");
+                Some(Vector3::new(1.0, 1.0, 1.0))
+            }
+            ("texture" | "ref", _) => {
+                self.imported
+                    .skip("textures, imported as their default color");
+                None
+            }
+            (tag, _) => {
+                self.imported
+                    .skip(format!("<{}> colors, imported as their default", tag));
+                None
+            }
+        }
+    }
+
+    /// The transform of `node`'s `to_world` parameter, or the identity if it has none.
+    fn to_world(&self, node: Node) -> io::Result<Matrix4<f32>> {
+        let mut transform = Matrix4::identity();
+        let Some(child) = self.parameter(node, &["to_world", "toWorld"]) else {
+            return Ok(transform);
+        };
+        // Each element is applied after the ones before it
+        for element in child.children().filter(Node::is_element) {
+            let step = match element.tag_name().name() {
+                "translate" => Matrix4::from_translation(
+                    self.vector(element, 0.0)
+                        .ok_or_else(|| invalid_data("invalid translation".to_string()))?,
+                ),
+                "scale" => {
+                    let scale = self
+                        .vector(element, 1.0)
+                        .ok_or_else(|| invalid_data("invalid scale".to_string()))?;
+                    Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+                }
+                "rotate" => {
+                    let axis = match self.attribute(element, "axis") {
+                        Some(axis) => vector_of(axis),
+                        None => self.vector(element, 0.0),
+                    };
+                    let angle = self
+                        .attribute(element, "angle")
+                        .and_then(|angle| angle.trim().parse().ok());
+                    match (axis, angle) {
+                        (Some(axis), Some(angle)) if axis.magnitude2() > 0.0 => {
+                            Matrix4::from_axis_angle(axis.normalize(), Deg(angle))
+                        }
+                        _ => return Err(invalid_data("invalid rotation".to_string())),
+                    }
+                }
+                "matrix" => {
+                    let values = self.attribute(element, "value").map(numbers);
+                    match values.as_deref() {
+                        // Written row by row
+                        Some(&[a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p]) => {
+                            Matrix4::new(a, e, i, m, b, f, j, n, c, g, k, o, d, h, l, p)
+                        }
+                        Some(&[a, b, c, d, e, f, g, h, i]) => Matrix4::new(
+                            a, d, g, 0.0, b, e, h, 0.0, c, f, i, 0.0, 0.0, 0.0, 0.0, 1.0,
+                        ),
+                        _ => return Err(invalid_data("invalid matrix".to_string())),
+                    }
+                }
+                "lookat" => {
+                    let point = |name| self.attribute(element, name).and_then(vector_of);
+                    let (Some(origin), Some(target)) = (point("origin"), point("target")) else {
+                        return Err(invalid_data("invalid lookat".to_string()));
+                    };
+                    let up = point("up").unwrap_or(Vector3::unit_y());
+                    look_at(origin, target, up)?
+                }
+                other => return Err(invalid_data(format!("unknown transform <{}>", other))),
+            };
+            transform = step * transform;
+        }
+        Ok(transform)
+    }
+
+    fn sensor(&mut self, node: Node) {
+        let kind = self.attribute(node, "type").unwrap_or_default().to_string();
+        if kind != "perspective" {
+            self.imported
+                .skip(format!("{} sensors, imported as perspective ones", kind));
+        }
+        let world_from_camera = match self.to_world(node) {
+            Ok(transform) => transform,
+            Err(error) => {
+                self.imported
+                    .skip(format!("the sensor's transform: {}", error));
+                return;
+            }
+        };
+
+        let film = node.children().find(|child| child.has_tag_name("film"));
+        let size = |names: &[&str], default| {
+            film.and_then(|film| self.float(film, names))
+                .unwrap_or(default)
+        };
+        let aspect_ratio = size(&["width"], 768.0) / size(&["height"], 576.0);
+        let fov = self.float(node, &["fov"]).unwrap_or(45.0);
+        let vfov = match self.string(node, &["fov_axis", "fovAxis"]).unwrap_or("x") {
+            "x" => vertical_fov(fov, aspect_ratio),
+            "smaller" if aspect_ratio >= 1.0 => fov,
+            "smaller" => vertical_fov(fov, aspect_ratio),
+            "larger" if aspect_ratio < 1.0 => fov,
+            "larger" => vertical_fov(fov, aspect_ratio),
+            _ => fov,
+        };
+        // Mitsuba's cameras have x pointing to the left of the image
+        let camera = camera(&world_from_camera, -Vector3::unit_x(), vfov);
+        self.imported.camera = Some(camera);
+    }
+
+    fn bsdf(&mut self, node: Node) -> Surface {
+        let kind = self.attribute(node, "type").unwrap_or_default().to_string();
+        match kind.as_str() {
+            // Wrappers of another BSDF, which this renderer's materials already are like
+            "twosided" | "mask" | "bumpmap" | "normalmap" => {
+                let inner = node
+                    .children()
+                    .find(|child| child.has_tag_name("bsdf") || child.has_tag_name("ref"));
+                if kind != "twosided" {
+                    self.imported.skip(format!("{} BSDFs", kind));
+                }
+                match inner {
+                    Some(inner) => self.surface(inner),
+                    None => Surface::default(),
+                }
+            }
+            "diffuse" | "roughdiffuse" => {
+                let albedo = self.color(node, &["reflectance"]);
+                Surface::diffuse(albedo.unwrap_or(DEFAULT_ALBEDO))
+            }
+            "conductor" | "roughconductor" => {
+                // Mitsuba's rough conductors default to an alpha of 0.1
+                let roughness = match kind.as_str() {
+                    "roughconductor" => self.float(node, &["alpha"]).unwrap_or(0.1),
+                    _ => 0.0,
+                };
+                let eta = self.color(node, &["eta"]);
+                let k = self.color(node, &["k"]);
+                let albedo = match (eta, k) {
+                    (Some(eta), Some(k)) => conductor_albedo(eta, k),
+                    _ => match self.string(node, &["material"]) {
+                        Some("none") | None => self
+                            .color(node, &["specular_reflectance", "specularReflectance"])
+                            .unwrap_or(Vector3::new(1.0, 1.0, 1.0)),
+                        Some(material) => {
+                            self.imported.skip(format!(
+                                "the conductor \"{}\", imported as copper",
+                                material
+                            ));
+                            DEFAULT_METAL_ALBEDO
+                        }
+                    },
+                };
+                Surface::metal(albedo, roughness)
+            }
+            "dielectric" | "roughdielectric" | "thindielectric" => {
+                let interior = self.ior(node, &["int_ior", "intIOR"], DEFAULT_IOR);
+                let exterior = self.ior(node, &["ext_ior", "extIOR"], 1.0);
+                Surface::dielectric(interior / exterior)
+            }
+            "plastic" | "roughplastic" => {
+                self.imported
+                    .skip(format!("{} BSDFs, imported as diffuse", kind));
+                let albedo = self.color(node, &["diffuse_reflectance", "diffuseReflectance"]);
+                Surface::diffuse(albedo.unwrap_or(DEFAULT_ALBEDO))
+            }
+            "principled" => {
+                let albedo = self.color(node, &["base_color"]).unwrap_or(DEFAULT_ALBEDO);
+                if self.float(node, &["metallic"]).is_some_and(|m| m > 0.5) {
+                    let roughness = self.float(node, &["roughness"]).unwrap_or(0.5);
+                    Surface::metal(albedo, roughness)
+                } else {
+                    Surface::diffuse(albedo)
+                }
+            }
+            other => {
+                self.imported
+                    .skip(format!("{} BSDFs, imported as diffuse", other));
+                Surface::default()
+            }
+        }
+    }
+
+    /// The surface of a `<bsdf>`, or of the one a `<ref>` refers to.
+    fn surface(&mut self, node: Node) -> Surface {
+        if !node.has_tag_name("ref") {
+            return self.bsdf(node);
+        }
+        let id = self.attribute(node, "id").unwrap_or_default();
+        match self.bsdfs.get(id) {
+            Some(&surface) => surface,
+            None => {
+                let id = id.to_string();
+                self.imported
+                    .skip(format!("references to the undefined BSDF \"{}\"", id));
+                Surface::default()
+            }
+        }
+    }
+
+    fn emitter(&mut self, node: Node) {
+        let kind = self.attribute(node, "type").unwrap_or_default().to_string();
+        let transform = match self.to_world(node) {
+            Ok(transform) => transform,
+            Err(error) => {
+                self.imported
+                    .skip(format!("an emitter's transform: {}", error));
+                return;
+            }
+        };
+        let light = match kind.as_str() {
+            "point" => {
+                let intensity = self.color(node, &["intensity"]).unwrap_or(white());
+                let position = self
+                    .parameter(node, &["position"])
+                    .and_then(|position| self.vector(position, 0.0))
+                    .unwrap_or(Vector3::zero());
+                let mut light = light(LightKind::Point, intensity);
+                light.position = transform_point(&transform, position);
+                light
+            }
+            "directional" => {
+                let irradiance = self.color(node, &["irradiance"]).unwrap_or(white());
+                let direction = self
+                    .parameter(node, &["direction"])
+                    .and_then(|direction| self.vector(direction, 0.0))
+                    .unwrap_or(Vector3::unit_z());
+                let mut light = light(LightKind::Directional, irradiance);
+                light.direction = transform_vector(&transform, direction).normalize();
+                light
+            }
+            "constant" | "envmap" | "sky" | "sunsky" => {
+                self.imported
+                    .skip("environment emitters; set the environment in the render settings");
+                return;
+            }
+            other => {
+                self.imported.skip(format!("{} emitters", other));
+                return;
+            }
+        };
+        self.imported.lights.push(light);
+    }
+
+    fn shape(&mut self, node: Node) -> io::Result<()> {
+        let kind = self.attribute(node, "type").unwrap_or_default().to_string();
+        let transform = self.to_world(node)?;
+        let mut surface = Surface::default();
+        for child in node.children().filter(Node::is_element) {
+            match child.tag_name().name() {
+                "bsdf" | "ref" => surface = self.surface(child),
+                "emitter" if self.attribute(child, "type") == Some("area") => {
+                    let radiance = self.color(child, &["radiance"]).unwrap_or(white());
+                    surface = Surface::emissive(radiance);
+                }
+                "emitter" => self
+                    .imported
+                    .skip("emitters on shapes other than area ones"),
+                _ => {}
+            }
+        }
+        let name = self
+            .attribute(node, "id")
+            .map_or_else(|| shape_name(&kind).to_string(), str::to_string);
+
+        let mesh = match kind.as_str() {
+            "obj" | "ply" => {
+                let filename = self
+                    .string(node, &["filename"])
+                    .ok_or_else(|| invalid_data(format!("{} shape has no filename", kind)))?;
+                let path = self.directory.join(filename);
+                if kind == "obj" {
+                    read_obj(&path)?
+                } else {
+                    ply::read(&path)?
+                }
+            }
+            "rectangle" => IndexedMesh {
+                positions: vec![
+                    Vector3::new(-1.0, -1.0, 0.0),
+                    Vector3::new(1.0, -1.0, 0.0),
+                    Vector3::new(1.0, 1.0, 0.0),
+                    Vector3::new(-1.0, 1.0, 0.0),
+                ],
+                normals: Some(vec![Vector3::unit_z(); 4]),
+                uvs: Some(vec![
+                    Vector2::new(0.0, 0.0),
+                    Vector2::new(1.0, 0.0),
+                    Vector2::new(1.0, 1.0),
+                    Vector2::new(0.0, 1.0),
+                ]),
+                triangles: vec![[0, 1, 2], [0, 2, 3]],
+            },
+            "cube" => cube(),
+            "disk" => disk(),
+            "sphere" => {
+                let center = self
+                    .parameter(node, &["center"])
+                    .and_then(|center| self.vector(center, 0.0))
+                    .unwrap_or(Vector3::zero());
+                let radius = self.float(node, &["radius"]).unwrap_or(1.0);
+                // Spheres stay round, so they're scaled by the average of the axes' scales
+                let scale = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()]
+                    .map(|axis| transform_vector(&transform, axis).magnitude())
+                    .iter()
+                    .sum::<f32>()
+                    / 3.0;
+                let center = transform_point(&transform, center);
+                self.imported
+                    .add_sphere(&name, center, radius * scale, surface);
+                return Ok(());
+            }
+            other => {
+                self.imported.skip(format!("{} shapes", other));
+                return Ok(());
+            }
+        };
+        let material = self.imported.material(surface);
+        let triangles = mesh.checked()?.triangles(&transform, material);
+        self.imported.add_mesh(&name, triangles);
+        Ok(())
+    }
+}
+
+/// What a shape of `kind` without an ID is named.
+fn shape_name(kind: &str) -> &'static str {
+    match kind {
+        "rectangle" => "Rectangle",
+        "cube" => "Cube",
+        "disk" => "Disk",
+        "sphere" => "Sphere",
+        _ => "Mesh",
+    }
+}
+
+fn white() -> Vector3<f32> {
+    Vector3::new(1.0, 1.0, 1.0)
+}
+
+/// The numbers in a list separated by commas or whitespace.
+fn numbers(value: &str) -> Vec<f32> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .filter_map(|word| word.parse().ok())
+        .collect()
+}
+
+fn vector_of(value: &str) -> Option<Vector3<f32>> {
+    match numbers(value).as_slice() {
+        &[x, y, z] => Some(Vector3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+/// The transform placing a camera at `origin` looking at `target`, built as Mitsuba does with
+/// the camera's x axis to the left.
+fn look_at(
+    origin: Vector3<f32>,
+    target: Vector3<f32>,
+    up: Vector3<f32>,
+) -> io::Result<Matrix4<f32>> {
+    let direction = (target - origin).normalize();
+    let left = up.cross(direction);
+    if left.magnitude2() == 0.0 {
+        return Err(invalid_data(
+            "the up vector is parallel to the viewing direction".to_string(),
+        ));
+    }
+    let left = left.normalize();
+    let up = direction.cross(left);
+    Ok(Matrix4::from_cols(
+        left.extend(0.0),
+        up.extend(0.0),
+        direction.extend(0.0),
+        origin.extend(1.0),
+    ))
+}
+
+/// All the meshes of an OBJ file as one, ignoring its materials.
+fn read_obj(path: &Path) -> io::Result<IndexedMesh> {
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj(path, &options)
+        .map_err(|error| invalid_data(format!("{}: {}", path.display(), error)))?;
+
+    let mut mesh = IndexedMesh {
+        positions: Vec::new(),
+        normals: Some(Vec::new()),
+        uvs: Some(Vec::new()),
+        triangles: Vec::new(),
+    };
+    for model in models {
+        let model = model.mesh;
+        let offset = mesh.positions.len() as u32;
+        let vertices = model.positions.len() / 3;
+        mesh.positions.extend(
+            model
+                .positions
+                .chunks_exact(3)
+                .map(|p| Vector3::new(p[0], p[1], p[2])),
+        );
+        // Normals and texture coordinates are kept only if every model has them
+        if model.normals.len() == vertices * 3 {
+            if let Some(normals) = &mut mesh.normals {
+                normals.extend(
+                    model
+                        .normals
+                        .chunks_exact(3)
+                        .map(|n| Vector3::new(n[0], n[1], n[2])),
+                );
+            }
+        } else {
+            mesh.normals = None;
+        }
+        if model.texcoords.len() == vertices * 2 {
+            if let Some(uvs) = &mut mesh.uvs {
+                uvs.extend(
+                    model
+                        .texcoords
+                        .chunks_exact(2)
+                        .map(|t| Vector2::new(t[0], t[1])),
+                );
+            }
+        } else {
+            mesh.uvs = None;
+        }
+        mesh.triangles.extend(
+            model
+                .indices
+                .chunks_exact(3)
+                .map(|t| [t[0] + offset, t[1] + offset, t[2] + offset]),
+        );
+    }
+    Ok(mesh)
+}
+
+/// The cube from -1 to 1 along each axis, with a flat normal on each face.
+fn cube() -> IndexedMesh {
+    let mut mesh = IndexedMesh {
+        positions: Vec::new(),
+        normals: Some(Vec::new()),
+        uvs: Some(Vec::new()),
+        triangles: Vec::new(),
+    };
+    for axis in 0..3 {
+        for side in [-1.0, 1.0] {
+            let mut normal = Vector3::zero();
+            normal[axis] = side;
+            let mut u = Vector3::zero();
+            u[(axis + 1) % 3] = 1.0;
+            let v = normal.cross(u);
+            let start = mesh.positions.len() as u32;
+            for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                mesh.positions.push(normal + u * x + v * y);
+                if let Some(normals) = &mut mesh.normals {
+                    normals.push(normal);
+                }
+                if let Some(uvs) = &mut mesh.uvs {
+                    uvs.push(Vector2::new((x + 1.0) / 2.0, (y + 1.0) / 2.0));
+                }
+            }
+            mesh.triangles.push([start, start + 1, start + 2]);
+            mesh.triangles.push([start, start + 2, start + 3]);
+        }
+    }
+    mesh
+}
+
+/// The disk of radius one around the origin in the xy plane, facing along z.
+fn disk() -> IndexedMesh {
+    let mut positions = vec![Vector3::zero()];
+    let mut uvs = vec![Vector2::new(0.5, 0.5)];
+    for i in 0..DISK_SEGMENTS {
+        let angle = i as f32 / DISK_SEGMENTS as f32 * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        positions.push(Vector3::new(cos, sin, 0.0));
+        uvs.push(Vector2::new((cos + 1.0) / 2.0, (sin + 1.0) / 2.0));
+    }
+    let triangles = (0..DISK_SEGMENTS)
+        .map(|i| [0, i + 1, (i + 1) % DISK_SEGMENTS + 1])
+        .collect();
+    IndexedMesh {
+        normals: Some(vec![Vector3::unit_z(); positions.len()]),
+        positions,
+        uvs: Some(uvs),
+        triangles,
+    }
+}
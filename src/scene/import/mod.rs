@@ -0,0 +1,342 @@
+use std::{collections::BTreeSet, io, path::Path};
+
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Vector2, Vector3, Zero};
+use uuid::Uuid;
+
+use crate::{model::Triangle, MAX_NUMBER_OF_INSTANCES};
+
+use super::{
+    file::{invalid_data, name_from_path},
+    Camera, LibraryMaterial, Light, LightKind, Material, MaterialId, MaterialLibrary, Mesh,
+    MeshInstance, Scene, Sphere, SphereDescriptor, DEFAULT_IOR,
+};
+
+mod mitsuba;
+mod pbrt;
+mod ply;
+
+/// The albedo of imported surfaces whose color isn't given or can't be read.
+const DEFAULT_ALBEDO: Vector3<f32> = Vector3::new(0.5, 0.5, 0.5);
+/// The albedo of imported metals whose color isn't given or can't be read, roughly copper's.
+const DEFAULT_METAL_ALBEDO: Vector3<f32> = Vector3::new(0.95, 0.64, 0.54);
+
+impl Scene {
+    /// Whether [`Scene::import`] reads files like `path`, going by its extension.
+    pub fn can_import(path: &Path) -> bool {
+        matches!(extension(path).as_deref(), Some("pbrt" | "xml"))
+    }
+
+    /// Reads a PBRT (v3 or v4) or Mitsuba XML scene, mapping its shapes, materials and lights
+    /// onto the closest this renderer has and logging what it has no counterpart for.
+    pub fn import(path: &Path) -> io::Result<Scene> {
+        let imported = match extension(path).as_deref() {
+            Some("pbrt") => pbrt::import(path)?,
+            Some("xml") => mitsuba::import(path)?,
+            _ => {
+                return Err(invalid_data(format!(
+                    "{} isn't a PBRT or Mitsuba scene",
+                    path.display()
+                )))
+            }
+        };
+        Ok(imported.into_scene(path))
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// How an imported surface looks, as close as this renderer's materials come to it.
+#[derive(Debug, Clone, Copy)]
+struct Surface {
+    albedo: Vector3<f32>,
+    material: Material,
+    roughness: f32,
+    ior: f32,
+}
+
+impl Surface {
+    fn diffuse(albedo: Vector3<f32>) -> Self {
+        Self {
+            albedo,
+            material: Material::Diffuse,
+            roughness: 0.0,
+            ior: DEFAULT_IOR,
+        }
+    }
+
+    /// A metal as rough as `roughness`, clamped to the range metals have.
+    fn metal(albedo: Vector3<f32>, roughness: f32) -> Self {
+        Self {
+            albedo,
+            material: Material::Metal,
+            roughness: roughness.clamp(0.0, 1.0),
+            ior: DEFAULT_IOR,
+        }
+    }
+
+    /// Clear glass, or whatever else has an index of refraction of `ior`, which can't be below
+    /// one.
+    fn dielectric(ior: f32) -> Self {
+        Self {
+            albedo: Vector3::new(1.0, 1.0, 1.0),
+            material: Material::Dielectric,
+            roughness: 0.0,
+            ior: ior.max(1.0),
+        }
+    }
+
+    fn emissive(radiance: Vector3<f32>) -> Self {
+        let (albedo, intensity) = split_radiance(radiance);
+        Self {
+            albedo,
+            material: Material::Emissive { intensity },
+            roughness: 0.0,
+            ior: DEFAULT_IOR,
+        }
+    }
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Self::diffuse(DEFAULT_ALBEDO)
+    }
+}
+
+/// Splits `radiance` into a color no brighter than one and the intensity it's scaled by, as
+/// emissive materials and lights take it.
+fn split_radiance(radiance: Vector3<f32>) -> (Vector3<f32>, f32) {
+    let intensity = radiance.x.max(radiance.y).max(radiance.z);
+    if intensity > 0.0 {
+        (radiance / intensity, intensity)
+    } else {
+        (Vector3::zero(), 0.0)
+    }
+}
+
+/// A light of `kind` shining with `radiance`.
+fn light(kind: LightKind, radiance: Vector3<f32>) -> Light {
+    let (color, intensity) = split_radiance(radiance);
+    Light {
+        color,
+        intensity,
+        ..Light::new(kind)
+    }
+}
+
+/// The reflectance at normal incidence of a conductor whose complex index of refraction is
+/// `eta` + `k`i, per channel.
+fn conductor_albedo(eta: Vector3<f32>, k: Vector3<f32>) -> Vector3<f32> {
+    let reflectance =
+        |eta: f32, k: f32| ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+    Vector3::new(
+        reflectance(eta.x, k.x),
+        reflectance(eta.y, k.y),
+        reflectance(eta.z, k.z),
+    )
+}
+
+/// A camera at `world_from_camera`'s origin looking along its z axis with its y axis up, and
+/// `right` in camera space to the right of the image. `vfov` is in degrees.
+fn camera(world_from_camera: &Matrix4<f32>, right: Vector3<f32>, vfov: f32) -> Camera {
+    let mut camera = Camera::new();
+    camera.origin = transform_point(world_from_camera, Vector3::zero());
+    camera.forward = transform_vector(world_from_camera, Vector3::unit_z()).normalize();
+    camera.up = transform_vector(world_from_camera, Vector3::unit_y()).normalize();
+    camera.right = transform_vector(world_from_camera, right).normalize();
+    camera.vfov = vfov;
+    camera
+}
+
+/// The vertical field of view of an image `aspect_ratio` times as wide as it is tall, whose
+/// horizontal one is `hfov`, both in degrees.
+fn vertical_fov(hfov: f32, aspect_ratio: f32) -> f32 {
+    (2.0 * ((hfov.to_radians() / 2.0).tan() / aspect_ratio).atan()).to_degrees()
+}
+
+fn transform_point(transform: &Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+    (transform * point.extend(1.0)).truncate()
+}
+
+fn transform_vector(transform: &Matrix4<f32>, vector: Vector3<f32>) -> Vector3<f32> {
+    (transform * vector.extend(0.0)).truncate()
+}
+
+/// `triangle` moved by `transform`, whose normals move by `normal_transform`.
+fn transform_triangle(
+    triangle: &Triangle,
+    transform: &Matrix4<f32>,
+    normal_transform: &Matrix4<f32>,
+) -> Triangle {
+    let normal = |normal| transform_vector(normal_transform, normal).normalize();
+    Triangle {
+        a: transform_point(transform, triangle.a),
+        b: transform_point(transform, triangle.b),
+        c: transform_point(transform, triangle.c),
+        na: normal(triangle.na),
+        nb: normal(triangle.nb),
+        nc: normal(triangle.nc),
+        ..triangle.clone()
+    }
+}
+
+/// Normals move by the inverse transpose of what points do, which keeps them perpendicular.
+fn normal_transform(transform: &Matrix4<f32>) -> Matrix4<f32> {
+    transform
+        .invert()
+        .unwrap_or(Matrix4::identity())
+        .transpose()
+}
+
+/// A mesh as most formats store it, with its vertices shared between triangles by index.
+struct IndexedMesh {
+    positions: Vec<Vector3<f32>>,
+    normals: Option<Vec<Vector3<f32>>>,
+    uvs: Option<Vec<Vector2<f32>>>,
+    triangles: Vec<[u32; 3]>,
+}
+
+impl IndexedMesh {
+    /// Fails if a triangle refers to a missing vertex, or some vertices lack a normal or
+    /// texture coordinates the rest have.
+    fn checked(self) -> io::Result<Self> {
+        let vertices = self.positions.len();
+        if self.normals.as_ref().is_some_and(|n| n.len() != vertices) {
+            return Err(invalid_data(
+                "a mesh has a different number of normals and vertices".to_string(),
+            ));
+        }
+        if self.uvs.as_ref().is_some_and(|uvs| uvs.len() != vertices) {
+            return Err(invalid_data(
+                "a mesh has a different number of texture coordinates and vertices".to_string(),
+            ));
+        }
+        if self
+            .triangles
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= vertices)
+        {
+            return Err(invalid_data(
+                "a mesh refers to a missing vertex".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// The triangles placed by `transform` and made of `material`, with flat normals if the
+    /// mesh has none.
+    fn triangles(&self, transform: &Matrix4<f32>, material: MaterialId) -> Vec<Triangle> {
+        let normal_transform = normal_transform(transform);
+        self.triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let [a, b, c] = [a, b, c].map(|i| i as usize);
+                let [pa, pb, pc] = [a, b, c].map(|i| self.positions[i]);
+                let flat = (pb - pa).cross(pc - pa);
+                let [na, nb, nc] = match &self.normals {
+                    Some(normals) => [a, b, c].map(|i| normals[i]),
+                    // Degenerate triangles are removed when the scene is validated
+                    None if flat.magnitude2() > 0.0 => [flat.normalize(); 3],
+                    None => [Vector3::unit_y(); 3],
+                };
+                let [ta, tb, tc] = match &self.uvs {
+                    Some(uvs) => [a, b, c].map(|i| uvs[i]),
+                    None => [Vector2::zero(); 3],
+                };
+                let triangle = Triangle {
+                    a: pa,
+                    b: pb,
+                    c: pc,
+                    na,
+                    nb,
+                    nc,
+                    ta,
+                    tb,
+                    tc,
+                    material,
+                };
+                transform_triangle(&triangle, transform, &normal_transform)
+            })
+            .collect()
+    }
+}
+
+/// What an importer reads from a scene file, in world space.
+#[derive(Default)]
+struct Imported {
+    spheres: Vec<Sphere>,
+    /// The triangles of each shape by name, each becoming a mesh placed once.
+    meshes: Vec<(String, Vec<Triangle>)>,
+    lights: Vec<Light>,
+    /// Shared by the shapes whose surfaces look the same.
+    materials: MaterialLibrary,
+    camera: Option<Camera>,
+    /// What the scene has that this renderer can't show, logged once each.
+    skipped: BTreeSet<String>,
+}
+
+impl Imported {
+    fn skip(&mut self, what: impl Into<String>) {
+        self.skipped.insert(what.into());
+    }
+
+    /// The material shapes shaded as `surface` are made of.
+    fn material(&mut self, surface: Surface) -> MaterialId {
+        self.materials.find_or_add_like(LibraryMaterial {
+            name: String::new(),
+            albedo: surface.albedo,
+            material: surface.material,
+            roughness: surface.roughness,
+            ior: surface.ior,
+        })
+    }
+
+    fn add_sphere(&mut self, name: &str, center: Vector3<f32>, radius: f32, surface: Surface) {
+        let material = self.material(surface);
+        let mut sphere = Sphere::new(SphereDescriptor {
+            center,
+            radius,
+            material,
+        });
+        sphere.name = name.to_string();
+        self.spheres.push(sphere);
+    }
+
+    fn add_mesh(&mut self, name: &str, triangles: Vec<Triangle>) {
+        if !triangles.is_empty() {
+            self.meshes.push((name.to_string(), triangles));
+        }
+    }
+
+    fn into_scene(self, path: &Path) -> Scene {
+        let name = name_from_path(path);
+        for what in &self.skipped {
+            log::warn!("Skipped in \"{}\": {}", name, what);
+        }
+
+        let mut meshes = self.meshes;
+        // Every shape is already where it belongs, so they can be merged without moving any
+        if meshes.len() > MAX_NUMBER_OF_INSTANCES as usize {
+            let triangles = meshes.into_iter().flat_map(|(_, t)| t).collect();
+            meshes = vec![(name.clone(), triangles)];
+        }
+        let instances = (0..meshes.len()).map(MeshInstance::new).collect();
+        let meshes = meshes
+            .into_iter()
+            .map(|(name, triangles)| Mesh::new(name, triangles))
+            .collect();
+
+        let camera = self.camera.unwrap_or_default();
+        let mut scene = Scene::with_meshes(self.spheres, meshes, instances, camera);
+        for light in self.lights {
+            scene.spawn(Uuid::new_v4(), String::new(), light);
+        }
+        scene.materials = self.materials;
+        scene.name_objects();
+        scene.name = name;
+        scene
+    }
+}
@@ -0,0 +1,749 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use cgmath::{Deg, InnerSpace, Matrix4, SquareMatrix, Vector2, Vector3, Zero};
+
+use crate::model::Triangle;
+
+use super::{
+    super::{file::invalid_data, LightKind},
+    camera, conductor_albedo, light, normal_transform, ply, transform_point, transform_triangle,
+    transform_vector, vertical_fov, Imported, IndexedMesh, Surface, DEFAULT_ALBEDO,
+    DEFAULT_METAL_ALBEDO,
+};
+use crate::scene::DEFAULT_IOR;
+
+/// Reads a PBRT v3 or v4 scene. Both versions are read alike, since they mostly differ in the
+/// names of materials and parameters rather than in the syntax.
+pub(super) fn import(path: &Path) -> io::Result<Imported> {
+    let directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut importer = Importer::new(directory);
+    importer.read(path)?;
+    Ok(importer.finish())
+}
+
+#[derive(Debug)]
+enum Token {
+    /// A directive or the value of a boolean, which v4 doesn't quote.
+    Word(String),
+    String(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> io::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((start, char)) = chars.next() {
+        match char {
+            '#' => {
+                chars.find(|&(_, char)| char == '\n');
+            }
+            '[' => tokens.push(Token::Open),
+            ']' => tokens.push(Token::Close),
+            '"' => {
+                let (end, _) = chars
+                    .find(|&(_, char)| char == '"')
+                    .ok_or_else(|| invalid_data("unterminated string".to_string()))?;
+                tokens.push(Token::String(source[start + 1..end].to_string()));
+            }
+            char if char.is_whitespace() => {}
+            _ => {
+                let mut end = source.len();
+                while let Some(&(index, char)) = chars.peek() {
+                    if char.is_whitespace() || matches!(char, '[' | ']' | '"' | '#') {
+                        end = index;
+                        break;
+                    }
+                    chars.next();
+                }
+                let word = &source[start..end];
+                tokens.push(match word.parse() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) => Token::Word(word.to_string()),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    String(String),
+}
+
+impl Value {
+    fn from_token(token: Token) -> io::Result<Self> {
+        Ok(match token {
+            Token::Number(number) => Value::Number(number),
+            Token::String(string) => Value::String(string),
+            // v3 quotes booleans, so v4's are read the same way
+            Token::Word(word) if word == "true" || word == "false" => Value::String(word),
+            token => return Err(invalid_data(format!("unexpected {:?}", token))),
+        })
+    }
+}
+
+/// A parameter such as `"rgb Kd" [0.5 0.5 0.5]`.
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+/// A directive with the arguments it takes in order, such as a shape's kind, and then the
+/// parameters it takes by name.
+struct Directive {
+    name: String,
+    arguments: Vec<Value>,
+    params: Vec<Param>,
+}
+
+impl Directive {
+    fn numbers(&self) -> Vec<f32> {
+        self.arguments
+            .iter()
+            .filter_map(|value| match value {
+                Value::Number(number) => Some(*number as f32),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The first of the arguments that's a string, such as the kind of a shape.
+    fn string(&self) -> io::Result<&str> {
+        self.arguments
+            .iter()
+            .find_map(|value| match value {
+                Value::String(string) => Some(string.as_str()),
+                _ => None,
+            })
+            .ok_or_else(|| invalid_data(format!("{} takes a name", self.name)))
+    }
+
+    fn floats(&self, name: &str) -> Option<Vec<f32>> {
+        let param = self.params.iter().find(|param| param.name == name)?;
+        param
+            .values
+            .iter()
+            .map(|value| match value {
+                Value::Number(number) => Some(*number as f32),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn float(&self, name: &str) -> Option<f32> {
+        self.floats(name)?.first().copied()
+    }
+
+    fn param_string(&self, name: &str) -> Option<&str> {
+        let param = self.params.iter().find(|param| param.name == name)?;
+        match param.values.first()? {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn points(&self, name: &str) -> Option<Vec<Vector3<f32>>> {
+        let floats = self.floats(name)?;
+        Some(
+            floats
+                .chunks_exact(3)
+                .map(|p| Vector3::new(p[0], p[1], p[2]))
+                .collect(),
+        )
+    }
+
+    fn point(&self, name: &str) -> Option<Vector3<f32>> {
+        self.points(name)?.first().copied()
+    }
+}
+
+fn is_directive(token: &Token) -> bool {
+    matches!(token, Token::Word(word) if word.starts_with(|c: char| c.is_ascii_uppercase()))
+}
+
+fn parse(tokens: Vec<Token>) -> io::Result<Vec<Directive>> {
+    let mut directives = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let Token::Word(name) = token else {
+            return Err(invalid_data(format!(
+                "expected a directive, found {:?}",
+                token
+            )));
+        };
+        let mut directive = Directive {
+            name,
+            arguments: Vec::new(),
+            params: Vec::new(),
+        };
+        while let Some(token) = tokens.next_if(|token| !is_directive(token)) {
+            match token {
+                // Parameters are named by their type and name together
+                Token::String(string) if string.split_whitespace().count() == 2 => {
+                    let (ty, name) = string.trim().split_once(char::is_whitespace).unwrap();
+                    let name = name.trim();
+                    let mut values = Vec::new();
+                    match tokens.next() {
+                        Some(Token::Open) => loop {
+                            match tokens.next() {
+                                Some(Token::Close) => break,
+                                Some(token) => values.push(Value::from_token(token)?),
+                                None => return Err(invalid_data("unterminated list".to_string())),
+                            }
+                        },
+                        Some(token) => values.push(Value::from_token(token)?),
+                        None => return Err(invalid_data(format!("{} has no value", name))),
+                    }
+                    directive.params.push(Param {
+                        ty: ty.to_string(),
+                        name: name.to_string(),
+                        values,
+                    });
+                }
+                Token::Open | Token::Close if directive.params.is_empty() => {}
+                token if directive.params.is_empty() => {
+                    directive.arguments.push(Value::from_token(token)?)
+                }
+                token => {
+                    return Err(invalid_data(format!(
+                        "unexpected {:?} in {}",
+                        token, directive.name
+                    )))
+                }
+            }
+        }
+        directives.push(directive);
+    }
+    Ok(directives)
+}
+
+/// What `AttributeBegin` saves and `AttributeEnd` restores.
+#[derive(Clone)]
+struct State {
+    transform: Matrix4<f32>,
+    surface: Surface,
+    /// What shapes emit as area lights, if they do.
+    emission: Option<Vector3<f32>>,
+}
+
+struct Importer {
+    /// What included files and meshes are found relative to, which is always the directory
+    /// of the scene rather than of the file including them.
+    directory: PathBuf,
+    imported: Imported,
+    state: State,
+    stack: Vec<State>,
+    named_materials: HashMap<String, Surface>,
+    coordinate_systems: HashMap<String, Matrix4<f32>>,
+    /// The name of the object being defined, whose shapes are kept for its instances rather
+    /// than added to the scene.
+    object: Option<String>,
+    objects: HashMap<String, Vec<Triangle>>,
+    /// The camera's transform and field of view, placed once the film's shape is known.
+    camera: Option<(Matrix4<f32>, f32)>,
+    aspect_ratio: f32,
+}
+
+impl Importer {
+    fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            imported: Imported::default(),
+            state: State {
+                transform: Matrix4::identity(),
+                surface: Surface::default(),
+                emission: None,
+            },
+            stack: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            object: None,
+            objects: HashMap::new(),
+            camera: None,
+            aspect_ratio: 1280.0 / 720.0,
+        }
+    }
+
+    fn read(&mut self, path: &Path) -> io::Result<()> {
+        let source = fs::read_to_string(path)?;
+        for directive in parse(tokenize(&source)?)? {
+            self.apply(&directive).map_err(|error| {
+                invalid_data(format!(
+                    "{} in {}: {}",
+                    directive.name,
+                    path.display(),
+                    error
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Imported {
+        if let Some((camera_from_world, fov)) = self.camera {
+            // The field of view is of the image's shorter side
+            let vfov = if self.aspect_ratio >= 1.0 {
+                fov
+            } else {
+                vertical_fov(fov, self.aspect_ratio)
+            };
+            let world_from_camera = camera_from_world.invert().unwrap_or(Matrix4::identity());
+            let world_from_camera = mirror() * world_from_camera;
+            self.imported.camera = Some(camera(&world_from_camera, Vector3::unit_x(), vfov));
+        }
+        self.imported
+    }
+
+    fn concat(&mut self, transform: Matrix4<f32>) {
+        self.state.transform = self.state.transform * transform;
+    }
+
+    fn pop(&mut self) -> io::Result<State> {
+        self.stack
+            .pop()
+            .ok_or_else(|| invalid_data("unmatched end of a block".to_string()))
+    }
+
+    fn apply(&mut self, directive: &Directive) -> io::Result<()> {
+        let numbers = directive.numbers();
+        match directive.name.as_str() {
+            "Identity" => self.state.transform = Matrix4::identity(),
+            "Translate" => {
+                let [x, y, z] = arguments(&numbers)?;
+                self.concat(Matrix4::from_translation(Vector3::new(x, y, z)));
+            }
+            "Scale" => {
+                let [x, y, z] = arguments(&numbers)?;
+                self.concat(Matrix4::from_nonuniform_scale(x, y, z));
+            }
+            "Rotate" => {
+                let [angle, x, y, z] = arguments(&numbers)?;
+                let axis = Vector3::new(x, y, z).normalize();
+                self.concat(Matrix4::from_axis_angle(axis, Deg(angle)));
+            }
+            "LookAt" => {
+                let [ex, ey, ez, lx, ly, lz, ux, uy, uz] = arguments(&numbers)?;
+                let eye = Vector3::new(ex, ey, ez);
+                let look = Vector3::new(lx, ly, lz);
+                self.concat(look_at(eye, look, Vector3::new(ux, uy, uz))?);
+            }
+            "Transform" => self.state.transform = matrix(&numbers)?,
+            "ConcatTransform" => self.concat(matrix(&numbers)?),
+            "CoordinateSystem" => {
+                let name = directive.string()?.to_string();
+                self.coordinate_systems.insert(name, self.state.transform);
+            }
+            "CoordSysTransform" => {
+                let name = directive.string()?;
+                match self.coordinate_systems.get(name) {
+                    Some(&transform) => self.state.transform = transform,
+                    None => self
+                        .imported
+                        .skip(format!("the unknown coordinate system \"{}\"", name)),
+                }
+            }
+            "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+            "AttributeEnd" => self.state = self.pop()?,
+            "TransformEnd" => self.state.transform = self.pop()?.transform,
+            "WorldBegin" => {
+                self.state.transform = Matrix4::identity();
+                let world = Matrix4::identity();
+                self.coordinate_systems.insert("world".to_string(), world);
+            }
+            "Camera" => self.camera(directive)?,
+            "Film" => {
+                let width = directive.float("xresolution").unwrap_or(1280.0);
+                let height = directive.float("yresolution").unwrap_or(720.0);
+                if width > 0.0 && height > 0.0 {
+                    self.aspect_ratio = width / height;
+                }
+            }
+            "Material" => self.state.surface = self.material(directive.string()?, directive),
+            "MakeNamedMaterial" => {
+                let name = directive.string()?.to_string();
+                let kind = directive.param_string("type").unwrap_or("diffuse");
+                let surface = self.material(kind, directive);
+                self.named_materials.insert(name, surface);
+            }
+            "NamedMaterial" => {
+                let name = directive.string()?;
+                match self.named_materials.get(name) {
+                    Some(&surface) => self.state.surface = surface,
+                    None => self
+                        .imported
+                        .skip(format!("the undefined material \"{}\"", name)),
+                }
+            }
+            "AreaLightSource" => {
+                let radiance = self.color(directive, &["L"]).unwrap_or(white());
+                let scale = directive.float("scale").unwrap_or(1.0);
+                self.state.emission = Some(radiance * scale);
+            }
+            "LightSource" => self.light(directive.string()?, directive),
+            "Shape" => self.shape(directive.string()?, directive)?,
+            "ObjectBegin" => {
+                let name = directive.string()?.to_string();
+                self.stack.push(self.state.clone());
+                self.objects.insert(name.clone(), Vec::new());
+                self.object = Some(name);
+            }
+            "ObjectEnd" => {
+                self.object = None;
+                self.state = self.pop()?;
+            }
+            "ObjectInstance" => {
+                let name = directive.string()?;
+                let Some(triangles) = self.objects.get(name) else {
+                    self.imported
+                        .skip(format!("instances of the undefined object \"{}\"", name));
+                    return Ok(());
+                };
+                let transform = mirror() * self.state.transform;
+                let normal_transform = normal_transform(&transform);
+                let triangles = triangles
+                    .iter()
+                    .map(|t| transform_triangle(t, &transform, &normal_transform))
+                    .collect();
+                self.imported.add_mesh(name, triangles);
+            }
+            "Include" | "Import" => {
+                let path = self.directory.join(directive.string()?);
+                self.read(&path)?;
+            }
+            "MakeNamedMedium" | "MediumInterface" => self.imported.skip("participating media"),
+            "Texture" | "WorldEnd" | "ReverseOrientation" | "Sampler" | "Integrator"
+            | "PixelFilter" | "Accelerator" | "ColorSpace" | "Option" | "Attribute"
+            | "TransformTimes" | "ActiveTransformAll" | "ActiveTransform" => {}
+            other => self.imported.skip(format!("the {} directive", other)),
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, directive: &Directive) -> io::Result<()> {
+        let kind = directive.string()?;
+        if kind != "perspective" {
+            self.imported
+                .skip(format!("{} cameras, imported as perspective ones", kind));
+        }
+        if directive.float("lensradius").is_some_and(|r| r > 0.0) {
+            self.imported.skip("depth of field");
+        }
+        let fov = directive.float("fov").unwrap_or(90.0);
+        self.camera = Some((self.state.transform, fov));
+        let world_from_camera = self.state.transform.invert().unwrap_or(Matrix4::identity());
+        self.coordinate_systems
+            .insert("camera".to_string(), world_from_camera);
+        Ok(())
+    }
+
+    /// The first of `names` the directive has as a color, or a number for gray. Colors this
+    /// renderer can't show, such as textures, are noted as skipped.
+    fn color(&mut self, directive: &Directive, names: &[&str]) -> Option<Vector3<f32>> {
+        let param = directive
+            .params
+            .iter()
+            .find(|param| names.contains(&param.name.as_str()))?;
+        let floats = directive.floats(&param.name);
+        match (param.ty.as_str(), floats) {
+            ("rgb" | "color", Some(floats)) if floats.len() >= 3 => {
+                Some(Vector3::new(floats[0], floats[1], floats[2]))
+            }
+            ("float", Some(floats)) if !floats.is_empty() => {
+                Some(Vector3::new(1.0, 1.0, 1.0) * floats[0])
+            }
+            // Pairs of wavelengths and values, which become gray
+            ("spectrum", Some(floats)) if floats.len() >= 2 => {
+                let values = floats
+                    .chunks_exact(2)
+                    .map(|pair| pair[1])
+                    .collect::<Vec<_>>();
+                let average = values.iter().sum::<f32>() / values.len() as f32;
+                Some(white() * average)
+            }
+            ("blackbody", floats) => {
+                self.imported.skip("blackbody colors, imported as white");
+                // v3 gives a scale after the temperature, v4 normalizes them
+                let scale = floats.and_then(|f| f.get(1).copied()).unwrap_or(1.0);
+                Some(white() * scale)
+            }
+            ("texture", _) => {
+                self.imported
+                    .skip("textures, imported as their default color");
+                None
+            }
+            (ty, _) => {
+                self.imported
+                    .skip(format!("{} colors, imported as their default", ty));
+                None
+            }
+        }
+    }
+
+    fn material(&mut self, kind: &str, directive: &Directive) -> Surface {
+        match kind {
+            "matte"
+            | "diffuse"
+            | "plastic"
+            | "coateddiffuse"
+            | "substrate"
+            | "uber"
+            | "translucent"
+            | "diffusetransmission" => {
+                if kind != "matte" && kind != "diffuse" {
+                    self.imported
+                        .skip(format!("{} materials, imported as diffuse", kind));
+                }
+                let albedo = self.color(directive, &["Kd", "reflectance"]);
+                Surface::diffuse(albedo.unwrap_or(DEFAULT_ALBEDO))
+            }
+            "mirror" => {
+                let albedo = self.color(directive, &["Kr"]);
+                Surface::metal(albedo.unwrap_or(white() * 0.9), 0.0)
+            }
+            "metal" | "conductor" => {
+                let roughness = directive
+                    .float("roughness")
+                    .or(directive.float("uroughness"))
+                    .unwrap_or(0.0);
+                if let Some(albedo) = self.color(directive, &["reflectance"]) {
+                    return Surface::metal(albedo, roughness);
+                }
+                let eta = self.color(directive, &["eta"]);
+                let k = self.color(directive, &["k"]);
+                let albedo = match (eta, k) {
+                    (Some(eta), Some(k)) => conductor_albedo(eta, k),
+                    _ => DEFAULT_METAL_ALBEDO,
+                };
+                Surface::metal(albedo, roughness)
+            }
+            "glass" | "dielectric" | "thindielectric" => {
+                let eta = directive.float("eta").or(directive.float("index"));
+                Surface::dielectric(eta.unwrap_or(DEFAULT_IOR))
+            }
+            "disney" => {
+                let albedo = self.color(directive, &["color"]).unwrap_or(DEFAULT_ALBEDO);
+                if directive.float("metallic").is_some_and(|m| m > 0.5) {
+                    let roughness = directive.float("roughness").unwrap_or(0.5);
+                    Surface::metal(albedo, roughness)
+                } else {
+                    Surface::diffuse(albedo)
+                }
+            }
+            "" | "none" | "interface" => {
+                self.imported
+                    .skip("interface materials, imported as diffuse");
+                Surface::default()
+            }
+            other => {
+                self.imported
+                    .skip(format!("{} materials, imported as diffuse", other));
+                Surface::default()
+            }
+        }
+    }
+
+    fn light(&mut self, kind: &str, directive: &Directive) {
+        let scale = directive.float("scale").unwrap_or(1.0);
+        let transform = mirror() * self.state.transform;
+        let light = match kind {
+            "point" | "spot" => {
+                if kind == "spot" {
+                    self.imported.skip("spot lights, imported as point lights");
+                }
+                let intensity = self.color(directive, &["I"]).unwrap_or(white()) * scale;
+                let from = directive.point("from").unwrap_or(Vector3::zero());
+                let mut light = light(LightKind::Point, intensity);
+                light.position = transform_point(&transform, from);
+                light
+            }
+            "distant" => {
+                let radiance = self.color(directive, &["L"]).unwrap_or(white()) * scale;
+                let from = directive.point("from").unwrap_or(Vector3::zero());
+                let to = directive.point("to").unwrap_or(Vector3::unit_z());
+                let mut light = light(LightKind::Directional, radiance);
+                light.direction = transform_vector(&transform, to - from).normalize();
+                light
+            }
+            "infinite" => {
+                self.imported
+                    .skip("environment lights; set the environment in the render settings");
+                return;
+            }
+            other => {
+                self.imported.skip(format!("{} lights", other));
+                return;
+            }
+        };
+        self.imported.lights.push(light);
+    }
+
+    fn shape(&mut self, kind: &str, directive: &Directive) -> io::Result<()> {
+        let surface = match self.state.emission {
+            Some(radiance) => Surface::emissive(radiance),
+            None => self.state.surface,
+        };
+        // Objects are mirrored along with each of their instances
+        let transform = match self.object {
+            Some(_) => self.state.transform,
+            None => mirror() * self.state.transform,
+        };
+        let uvs = || -> Option<Vec<Vector2<f32>>> {
+            let floats = directive.floats("uv").or(directive.floats("st"))?;
+            Some(
+                floats
+                    .chunks_exact(2)
+                    .map(|uv| Vector2::new(uv[0], uv[1]))
+                    .collect(),
+            )
+        };
+        let mesh = match kind {
+            "trianglemesh" => {
+                let positions = directive.points("P").unwrap_or_default();
+                let indices = match directive.floats("indices") {
+                    Some(indices) => indices.iter().map(|&i| i as u32).collect(),
+                    // A single triangle doesn't need them
+                    None if positions.len() == 3 => vec![0, 1, 2],
+                    None => return Err(invalid_data("triangle mesh has no indices".to_string())),
+                };
+                IndexedMesh {
+                    positions,
+                    normals: directive.points("N"),
+                    uvs: uvs(),
+                    triangles: indices
+                        .chunks_exact(3)
+                        .map(|t| [t[0], t[1], t[2]])
+                        .collect(),
+                }
+            }
+            "bilinearmesh" => {
+                let positions = directive.points("P").unwrap_or_default();
+                let indices = match directive.floats("indices") {
+                    Some(indices) => indices.iter().map(|&i| i as u32).collect(),
+                    None if positions.len() == 4 => vec![0, 1, 2, 3],
+                    None => return Err(invalid_data("bilinear mesh has no indices".to_string())),
+                };
+                // Each patch's corners go around it as 0, 1, 3, 2
+                let triangles = indices
+                    .chunks_exact(4)
+                    .flat_map(|q| [[q[0], q[1], q[3]], [q[0], q[3], q[2]]])
+                    .collect();
+                IndexedMesh {
+                    positions,
+                    normals: directive.points("N"),
+                    uvs: uvs(),
+                    triangles,
+                }
+            }
+            "plymesh" => {
+                let filename = directive
+                    .param_string("filename")
+                    .ok_or_else(|| invalid_data("PLY mesh has no filename".to_string()))?;
+                if filename.ends_with(".gz") {
+                    self.imported.skip("compressed PLY meshes");
+                    return Ok(());
+                }
+                ply::read(&self.directory.join(filename))?
+            }
+            "sphere" => {
+                if self.object.is_some() {
+                    self.imported.skip("spheres in object instances");
+                    return Ok(());
+                }
+                let partial = ["zmin", "zmax", "phimax"];
+                if partial.iter().any(|name| directive.float(name).is_some()) {
+                    self.imported.skip("partial spheres, imported whole");
+                }
+                let center = transform_point(&transform, Vector3::zero());
+                // Spheres stay round, so they're scaled by the average of the axes' scales
+                let scale = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()]
+                    .map(|axis| transform_vector(&transform, axis).magnitude())
+                    .iter()
+                    .sum::<f32>()
+                    / 3.0;
+                let radius = directive.float("radius").unwrap_or(1.0) * scale;
+                self.imported.add_sphere("", center, radius, surface);
+                return Ok(());
+            }
+            other => {
+                self.imported.skip(format!("{} shapes", other));
+                return Ok(());
+            }
+        };
+
+        let material = self.imported.material(surface);
+        let triangles = mesh.checked()?.triangles(&transform, material);
+        match &self.object {
+            Some(object) => self
+                .objects
+                .entry(object.clone())
+                .or_default()
+                .extend(triangles),
+            None => self.imported.add_mesh(kind_name(kind), triangles),
+        }
+        Ok(())
+    }
+}
+
+/// What a mesh imported from a shape of `kind` is named.
+fn kind_name(kind: &str) -> &'static str {
+    match kind {
+        "plymesh" => "PLY Mesh",
+        "bilinearmesh" => "Bilinear Mesh",
+        _ => "Triangle Mesh",
+    }
+}
+
+/// Flips the x axis of the scene, since PBRT's cameras are left-handed and this renderer's
+/// aren't, so it's seen as PBRT renders it.
+fn mirror() -> Matrix4<f32> {
+    Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)
+}
+
+fn white() -> Vector3<f32> {
+    Vector3::new(1.0, 1.0, 1.0)
+}
+
+/// The `N` numbers a directive takes.
+fn arguments<const N: usize>(numbers: &[f32]) -> io::Result<[f32; N]> {
+    numbers
+        .try_into()
+        .map_err(|_| invalid_data(format!("expected {} numbers, found {}", N, numbers.len())))
+}
+
+/// A matrix given column by column, as PBRT writes them.
+fn matrix(numbers: &[f32]) -> io::Result<Matrix4<f32>> {
+    let [a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p] = arguments(numbers)?;
+    Ok(Matrix4::new(a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p))
+}
+
+/// The camera space transform of a camera at `eye` looking at `look`, built as PBRT does in
+/// its left-handed coordinates.
+fn look_at(eye: Vector3<f32>, look: Vector3<f32>, up: Vector3<f32>) -> io::Result<Matrix4<f32>> {
+    let direction = (look - eye).normalize();
+    let right = up.normalize().cross(direction);
+    if right.magnitude2() == 0.0 {
+        return Err(invalid_data(
+            "the up vector is parallel to the viewing direction".to_string(),
+        ));
+    }
+    let right = right.normalize();
+    let up = direction.cross(right);
+    let world_from_camera = Matrix4::from_cols(
+        right.extend(0.0),
+        up.extend(0.0),
+        direction.extend(0.0),
+        eye.extend(1.0),
+    );
+    world_from_camera
+        .invert()
+        .ok_or_else(|| invalid_data("the camera can't be inverted".to_string()))
+}
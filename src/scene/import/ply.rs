@@ -0,0 +1,260 @@
+use std::{fs, io, path::Path, str::SplitAsciiWhitespace};
+
+use cgmath::{Vector2, Vector3};
+
+use super::{super::file::invalid_data, IndexedMesh};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid_data(format!("unknown PLY type {}", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    /// A list of `Scalar`s preceded by how many there are.
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Where the values of the elements are read from, after the header.
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        little_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64> {
+        let truncated = || invalid_data("PLY file ends early".to_string());
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or_else(truncated)?;
+                word.parse()
+                    .map_err(|_| invalid_data(format!("invalid PLY value {}", word)))
+            }
+            Body::Binary {
+                bytes,
+                little_endian,
+            } => {
+                let size = scalar.size();
+                if bytes.len() < size {
+                    return Err(truncated());
+                }
+                let mut value = [0; 8];
+                value[..size].copy_from_slice(&bytes[..size]);
+                if !*little_endian {
+                    value[..size].reverse();
+                }
+                *bytes = &bytes[size..];
+
+                let [a, b, c, d, ..] = value;
+                Ok(match scalar {
+                    Scalar::I8 => a as i8 as f64,
+                    Scalar::U8 => a as f64,
+                    Scalar::I16 => i16::from_le_bytes([a, b]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([a, b]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([a, b, c, d]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([a, b, c, d]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([a, b, c, d]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(value),
+                })
+            }
+        }
+    }
+}
+
+/// Reads the vertices and faces of an ASCII or binary PLY file, ignoring any other elements.
+/// Faces of more than three vertices are split into fans of triangles.
+pub(super) fn read(path: &Path) -> io::Result<IndexedMesh> {
+    let bytes = fs::read(path)?;
+    let header_end = bytes
+        .windows(b"end_header".len())
+        .position(|window| window == b"end_header")
+        .ok_or_else(|| invalid_data("not a PLY file".to_string()))?;
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+    // The body starts after the line ending the header
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |end| header_end + end + 1);
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid_data("not a PLY file".to_string()));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words = line.split_ascii_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(invalid_data(format!("unknown PLY format {}", name))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data(format!("invalid PLY element count {}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property outside an element".to_string()))?;
+                element.properties.push(Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                ));
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property outside an element".to_string()))?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), Scalar::parse(ty)?));
+            }
+            _ => {}
+        }
+    }
+
+    let body = &bytes[body_start.min(bytes.len())..];
+    let mut body = match format {
+        Some(Format::Ascii) => Body::Ascii(
+            std::str::from_utf8(body)
+                .map_err(|_| invalid_data("PLY file isn't text".to_string()))?
+                .split_ascii_whitespace(),
+        ),
+        Some(format) => Body::Binary {
+            bytes: body,
+            little_endian: format == Format::LittleEndian,
+        },
+        None => return Err(invalid_data("PLY file has no format".to_string())),
+    };
+
+    let mut mesh = IndexedMesh {
+        positions: Vec::new(),
+        normals: None,
+        uvs: None,
+        triangles: Vec::new(),
+    };
+    for element in &elements {
+        let has = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .any(|p| matches!(p, Property::Scalar(name, _) if names.contains(&name.as_str())))
+        };
+        if element.name == "vertex" {
+            if has(&["nx"]) {
+                mesh.normals = Some(Vec::with_capacity(element.count));
+            }
+            if has(&["u", "s", "texture_u", "texture_s"]) {
+                mesh.uvs = Some(Vec::with_capacity(element.count));
+            }
+        }
+
+        for _ in 0..element.count {
+            let mut position = Vector3::new(0.0, 0.0, 0.0);
+            let mut normal = Vector3::new(0.0, 0.0, 0.0);
+            let mut uv = Vector2::new(0.0, 0.0);
+            for property in &element.properties {
+                match property {
+                    Property::Scalar(name, scalar) => {
+                        let value = body.read(*scalar)? as f32;
+                        match name.as_str() {
+                            "x" => position.x = value,
+                            "y" => position.y = value,
+                            "z" => position.z = value,
+                            "nx" => normal.x = value,
+                            "ny" => normal.y = value,
+                            "nz" => normal.z = value,
+                            "u" | "s" | "texture_u" | "texture_s" => uv.x = value,
+                            "v" | "t" | "texture_v" | "texture_t" => uv.y = value,
+                            _ => {}
+                        }
+                    }
+                    Property::List(name, count, item) => {
+                        let count = body.read(*count)? as usize;
+                        let mut indices = Vec::with_capacity(count);
+                        for _ in 0..count {
+                            indices.push(body.read(*item)? as u32);
+                        }
+                        let is_face = element.name == "face"
+                            && matches!(name.as_str(), "vertex_indices" | "vertex_index");
+                        if is_face {
+                            for i in 1..indices.len().saturating_sub(1) {
+                                mesh.triangles
+                                    .push([indices[0], indices[i], indices[i + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                mesh.positions.push(position);
+                if let Some(normals) = &mut mesh.normals {
+                    normals.push(normal);
+                }
+                if let Some(uvs) = &mut mesh.uvs {
+                    uvs.push(uv);
+                }
+            }
+        }
+    }
+
+    mesh.checked()
+}
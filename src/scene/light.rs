@@ -0,0 +1,622 @@
+use std::{cmp, f32::consts::PI};
+
+use bevy_ecs::prelude::Component;
+use bytemuck::Zeroable;
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{MAX_NUMBER_OF_INSTANCES, MAX_NUMBER_OF_LIGHTS, MAX_NUMBER_OF_SPHERES};
+
+use super::{Emitter, Material, MaterialId, Plane, Scene, SceneEvent};
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Shines in every direction from `position`, falling off with the square of the distance.
+    Point,
+    /// Shines in `direction` from infinitely far away, like the sun.
+    Directional,
+    /// A rectangle of `size` centered on `position` that emits towards `direction`, casting soft
+    /// shadows.
+    Quad,
+}
+
+impl LightKind {
+    /// What a new light of the kind is named.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightKind::Point => "Point Light",
+            LightKind::Directional => "Directional Light",
+            LightKind::Quad => "Quad Light",
+        }
+    }
+
+    /// What the compute shader calls the kind.
+    fn shader_index(&self) -> u32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+            LightKind::Quad => 2,
+        }
+    }
+}
+
+/// What a light's intensity is given in: the renderer's own units or a photometric unit, which is
+/// converted by the scene's [`super::Scene::nits_per_unit`].
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum IntensityUnit {
+    /// Radiance as the shaders add it up, times the color.
+    #[default]
+    Relative,
+    /// Luminous intensity, of point lights.
+    Candela,
+    /// Illuminance, of directional lights.
+    Lux,
+    /// Luminance, of quad lights.
+    Nits,
+    /// Luminous flux, of point and quad lights.
+    Lumens,
+}
+
+impl IntensityUnit {
+    /// The units a light of `kind` can be given in.
+    pub fn all(kind: LightKind) -> &'static [Self] {
+        match kind {
+            LightKind::Point => &[Self::Relative, Self::Candela, Self::Lumens],
+            LightKind::Directional => &[Self::Relative, Self::Lux],
+            LightKind::Quad => &[Self::Relative, Self::Nits, Self::Lumens],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Relative => "Relative",
+            Self::Candela => "Candela",
+            Self::Lux => "Lux",
+            Self::Nits => "Nits",
+            Self::Lumens => "Lumens",
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Self::Relative => "",
+            Self::Candela => " cd",
+            Self::Lux => " lx",
+            Self::Nits => " nt",
+            Self::Lumens => " lm",
+        }
+    }
+}
+
+/// Which spheres and mesh instances a light, or an emissive sphere or mesh instance, illuminates.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LightLinks {
+    /// The objects only illuminated, by UUID, or none for all of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Uuid>,
+    /// The objects never illuminated, by UUID, though they still cast shadows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<Uuid>,
+}
+
+impl LightLinks {
+    /// Whether every object is illuminated.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn illuminates(&self, object: Uuid) -> bool {
+        !self.exclude.contains(&object)
+            && (self.include.is_empty() || self.include.contains(&object))
+    }
+
+    /// Links or unlinks `object`, leaving the rest as they are.
+    pub fn set_illuminates(&mut self, object: Uuid, lit: bool) {
+        if lit {
+            self.exclude.retain(|&excluded| excluded != object);
+            if !self.include.is_empty() && !self.include.contains(&object) {
+                self.include.push(object);
+            }
+        } else if !self.exclude.contains(&object) {
+            // Emptying `include` instead would link everything else
+            self.exclude.push(object);
+        }
+    }
+}
+
+/// A light the path tracer only reaches with shadow rays, so it isn't seen by the camera or in
+/// reflections.
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Vector3<f32>,
+    /// The direction the light travels in.
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    /// The radiant intensity of a point light, the irradiance of a directional light or the
+    /// radiance of a quad light, in `unit`.
+    pub intensity: f32,
+    #[serde(default)]
+    pub unit: IntensityUnit,
+    /// The width and height of a quad light.
+    #[serde(default = "default_size")]
+    pub size: Vector2<f32>,
+    #[serde(flatten)]
+    pub links: LightLinks,
+}
+
+fn default_size() -> Vector2<f32> {
+    Vector2::new(1.0, 1.0)
+}
+
+impl Light {
+    pub fn new(kind: LightKind) -> Self {
+        Self {
+            kind,
+            position: Vector3::new(0.0, 3.0, 0.0),
+            direction: Vector3::new(0.3, -1.0, 0.2),
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: match kind {
+                LightKind::Point => 10.0,
+                LightKind::Directional => 2.0,
+                LightKind::Quad => 5.0,
+            },
+            size: default_size(),
+            unit: IntensityUnit::Relative,
+            links: LightLinks::default(),
+        }
+    }
+
+    /// The intensity in the renderer's own units, which the color is multiplied by, given that
+    /// a radiance of 1.0 is `nits_per_unit` nits. Photometric intensities describe how bright
+    /// the light looks whatever its color, so they're divided by the color's luminance.
+    pub fn relative_intensity(&self, nits_per_unit: f32) -> f32 {
+        if self.unit == IntensityUnit::Relative {
+            return self.intensity;
+        }
+        let luminance = luminance(self.color);
+        if luminance <= 0.0 || nits_per_unit <= 0.0 {
+            return 0.0;
+        }
+        self.intensity / self.photometric_factor() / (luminance * nits_per_unit)
+    }
+
+    /// Switches the light to `unit`, converting its intensity so it stays as bright.
+    pub fn set_unit(&mut self, unit: IntensityUnit, nits_per_unit: f32) {
+        let relative = self.relative_intensity(nits_per_unit);
+        self.unit = unit;
+        self.intensity = match unit {
+            IntensityUnit::Relative => relative,
+            _ => relative * self.photometric_factor() * luminance(self.color) * nits_per_unit,
+        };
+    }
+
+    /// What the luminance, luminous intensity or illuminance the light gives is multiplied by
+    /// to be in its unit.
+    fn photometric_factor(&self) -> f32 {
+        match (self.unit, self.kind) {
+            // A point light shines over the whole sphere of directions
+            (IntensityUnit::Lumens, LightKind::Point) => 4.0 * PI,
+            // A quad light is a Lambertian emitter on one side
+            (IntensityUnit::Lumens, _) => PI * self.size.x * self.size.y,
+            _ => 1.0,
+        }
+    }
+
+    /// The rectangle of a quad light, whose normal is its direction.
+    pub fn quad(&self) -> Plane {
+        let normal = if self.direction.magnitude2() > 0.0 {
+            self.direction.normalize()
+        } else {
+            -Vector3::unit_y()
+        };
+        let helper = if normal.x.abs() < 0.9 {
+            Vector3::unit_x()
+        } else {
+            Vector3::unit_z()
+        };
+        // Chosen so u × v points along the normal
+        let tangent = helper.cross(normal).normalize();
+        let bitangent = normal.cross(tangent);
+        let u = tangent * self.size.x;
+        let v = bitangent * self.size.y;
+
+        Plane {
+            q: self.position - (u + v) / 2.0,
+            u,
+            v,
+        }
+    }
+
+    /// Edits the light `uuid`, whose photometric units are converted with `nits_per_unit`,
+    /// returning whether it changed.
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, uuid: Uuid, nits_per_unit: f32) -> bool {
+        let mut responses = Vec::new();
+        let mut unit_changed = false;
+
+        if self.kind != LightKind::Directional {
+            ui.horizontal(|ui| {
+                ui.label("Position");
+                responses.extend([
+                    ui.add(egui::DragValue::new(&mut self.position.x).speed(0.1)),
+                    ui.add(egui::DragValue::new(&mut self.position.y).speed(0.1)),
+                    ui.add(egui::DragValue::new(&mut self.position.z).speed(0.1)),
+                ]);
+            });
+        }
+        if self.kind != LightKind::Point {
+            ui.horizontal(|ui| {
+                ui.label("Direction");
+                responses.extend([
+                    ui.add(egui::DragValue::new(&mut self.direction.x).speed(0.05)),
+                    ui.add(egui::DragValue::new(&mut self.direction.y).speed(0.05)),
+                    ui.add(egui::DragValue::new(&mut self.direction.z).speed(0.05)),
+                ]);
+            });
+        }
+        if self.kind == LightKind::Quad {
+            ui.horizontal(|ui| {
+                ui.label("Size");
+                responses.extend([
+                    ui.add(
+                        egui::DragValue::new(&mut self.size.x)
+                            .speed(0.05)
+                            .clamp_range(0.01..=f32::MAX),
+                    ),
+                    ui.add(
+                        egui::DragValue::new(&mut self.size.y)
+                            .speed(0.05)
+                            .clamp_range(0.01..=f32::MAX),
+                    ),
+                ]);
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            let mut color: [f32; 3] = self.color.into();
+            responses.push(ui.color_edit_button_rgb(&mut color));
+            self.color = color.into();
+        });
+        ui.horizontal(|ui| {
+            responses.push(
+                ui.add(
+                    egui::DragValue::new(&mut self.intensity)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX)
+                        .prefix("intensity ")
+                        .suffix(self.unit.suffix()),
+                ),
+            );
+            let mut unit = self.unit;
+            egui::ComboBox::from_id_source((uuid, "unit"))
+                .selected_text(unit.name())
+                .show_ui(ui, |ui| {
+                    for &option in IntensityUnit::all(self.kind) {
+                        ui.selectable_value(&mut unit, option, option.name());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Physical units are converted by the nits per unit radiance set above the \
+                    lights",
+                );
+            if unit != self.unit {
+                self.set_unit(unit, nits_per_unit);
+                unit_changed = true;
+            }
+        });
+
+        unit_changed || responses.iter().any(|r| r.changed())
+    }
+
+    /// Edits which of `objects`, the UUID and name of every sphere and mesh instance, the light
+    /// `uuid` illuminates, returning whether the links changed.
+    pub fn linking_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        uuid: Uuid,
+        objects: &[(Uuid, String)],
+    ) -> bool {
+        let mut changed = false;
+        ui.collapsing("Light Linking", |ui| {
+            changed |= link_list_ui(ui, &mut self.links.include, objects, (uuid, "Include"))
+                .on_hover_text("Only these objects are lit by the light, or every one if empty")
+                .changed();
+            changed |= link_list_ui(ui, &mut self.links.exclude, objects, (uuid, "Exclude"))
+                .on_hover_text("These objects aren't lit by the light, but still cast its shadows")
+                .changed();
+        });
+        changed
+    }
+}
+
+/// A light, or an emissive sphere or mesh instance, as the objects it can illuminate list it.
+#[derive(Debug, Clone)]
+pub struct LightSource {
+    pub uuid: Uuid,
+    pub name: String,
+    pub links: LightLinks,
+}
+
+impl Scene {
+    /// The lights, then the emissive spheres and mesh instances, with what they illuminate.
+    pub fn light_sources(&self) -> Vec<LightSource> {
+        let emissive = |material: MaterialId| {
+            self.materials
+                .get(material)
+                .is_some_and(|m| matches!(m.material, Material::Emissive { .. }))
+        };
+        let source = |uuid, name: &str, links: &LightLinks| LightSource {
+            uuid,
+            name: name.to_string(),
+            links: links.clone(),
+        };
+
+        let lights = self
+            .lights()
+            .into_iter()
+            .map(|light| source(light.id.0, &light.name.0, &light.light.links));
+        let spheres = self
+            .spheres()
+            .into_iter()
+            .filter(|sphere| emissive(sphere.surface.material))
+            .map(|sphere| source(sphere.id.0, &sphere.name.0, &sphere.emitter.light_links));
+        let instances = self
+            .instances()
+            .into_iter()
+            .filter(|instance| {
+                let triangles = &self.meshes[instance.mesh.mesh].triangles;
+                triangles.iter().any(|triangle| emissive(triangle.material))
+            })
+            .map(|instance| {
+                source(
+                    instance.id.0,
+                    &instance.name.0,
+                    &instance.emitter.light_links,
+                )
+            });
+        lights.chain(spheres).chain(instances).collect()
+    }
+
+    /// Links `object` to the light, sphere or mesh instance `source`, or unlinks it.
+    pub fn set_illuminates(&mut self, source: Uuid, object: Uuid, lit: bool) {
+        let links = if let Some(light) = self.get_mut::<Light>(source) {
+            &mut light.links
+        } else if let Some(emitter) = self.get_mut::<Emitter>(source) {
+            &mut emitter.light_links
+        } else {
+            return;
+        };
+        links.set_illuminates(object, lit);
+        self.publish(SceneEvent::LightChanged(source));
+    }
+}
+
+/// Checkboxes for which of `sources` illuminate `object`, returning the ones toggled and
+/// whether they now do.
+pub fn object_linking_ui(
+    ui: &mut egui::Ui,
+    object: Uuid,
+    sources: &[LightSource],
+) -> Vec<(Uuid, bool)> {
+    let mut toggled = Vec::new();
+    ui.collapsing("Light Linking", |ui| {
+        let sources = sources.iter().filter(|source| source.uuid != object);
+        let mut any = false;
+        for source in sources {
+            any = true;
+            let mut lit = source.links.illuminates(object);
+            if ui
+                .checkbox(&mut lit, format!("Lit by {}", source.name))
+                .changed()
+            {
+                toggled.push((source.uuid, lit));
+            }
+        }
+        if !any {
+            ui.label("There are no lights or emissive objects");
+        }
+    });
+    toggled
+}
+
+/// Lists the objects in `links` with a button to unlink each, and a combo box that links more of
+/// `objects`. The response is marked changed if `links` was.
+fn link_list_ui(
+    ui: &mut egui::Ui,
+    links: &mut Vec<Uuid>,
+    objects: &[(Uuid, String)],
+    id_source: (Uuid, &str),
+) -> egui::Response {
+    let name = |uuid: Uuid| {
+        objects
+            .iter()
+            .find(|(object, _)| *object == uuid)
+            .map_or("Removed object", |(_, name)| name.as_str())
+    };
+
+    let mut changed = false;
+    let mut response = ui
+        .vertical(|ui| {
+            let mut added = None;
+            egui::ComboBox::from_id_source(id_source)
+                .selected_text(format!("{} ({})", id_source.1, links.len()))
+                .show_ui(ui, |ui| {
+                    for (uuid, name) in objects {
+                        if !links.contains(uuid) && ui.selectable_label(false, name).clicked() {
+                            added = Some(*uuid);
+                        }
+                    }
+                });
+            if let Some(uuid) = added {
+                links.push(uuid);
+                changed = true;
+            }
+
+            links.retain(|&uuid| {
+                let keep = !ui
+                    .horizontal(|ui| {
+                        let remove = ui.small_button("✖").clicked();
+                        ui.label(name(uuid));
+                        remove
+                    })
+                    .inner;
+                changed |= !keep;
+                keep
+            });
+        })
+        .response;
+    if changed {
+        response.mark_changed();
+    }
+    response
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightBuffer {
+    /// The position of a point light, the direction a directional light travels in, or a corner
+    /// of a quad light.
+    vector: [f32; 3],
+    kind: u32,
+    radiance: [f32; 3],
+    _padding: u32,
+    /// The edges of a quad light from `vector`, zero for other lights.
+    u: [f32; 3],
+    _padding2: u32,
+    v: [f32; 3],
+    _padding3: u32,
+}
+
+impl LightBuffer {
+    /// `light`, with a radiance of 1.0 being `nits_per_unit` nits.
+    fn new(light: &Light, nits_per_unit: f32) -> Self {
+        let (vector, u, v) = match light.kind {
+            LightKind::Point => (light.position, Vector3::zero(), Vector3::zero()),
+            // Zero would light everything from nowhere
+            LightKind::Directional if light.direction.magnitude2() > 0.0 => (
+                light.direction.normalize(),
+                Vector3::zero(),
+                Vector3::zero(),
+            ),
+            LightKind::Directional => (-Vector3::unit_y(), Vector3::zero(), Vector3::zero()),
+            LightKind::Quad => {
+                let quad = light.quad();
+                (quad.q, quad.u, quad.v)
+            }
+        };
+
+        Self {
+            vector: vector.into(),
+            kind: light.kind.shader_index(),
+            radiance: (light.color * light.relative_intensity(nits_per_unit)).into(),
+            _padding: 0,
+            u: u.into(),
+            _padding2: 0,
+            v: v.into(),
+            _padding3: 0,
+        }
+    }
+}
+
+/// How many objects the light linking of [`LightDataBuffer`] covers: every sphere, then every
+/// mesh instance.
+const LINKED_OBJECTS: usize = (MAX_NUMBER_OF_SPHERES + MAX_NUMBER_OF_INSTANCES) as usize;
+/// How many emissive spheres and mesh instances can be linked to the objects they illuminate, a
+/// bit for each in [`LightDataBuffer`]. Past it, they illuminate everything.
+const MAX_LINKED_EMITTERS: usize = 32;
+/// The link slot of an object whose emission isn't linked.
+const NO_LINK_SLOT: u32 = u32::MAX;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightDataBuffer {
+    light_count: u32,
+    _padding: u32,
+    /// The lights that don't illuminate each sphere, then each mesh instance, a bit for each
+    /// light, so zero leaves an object lit by all of them.
+    unlit: [[u32; 2]; LINKED_OBJECTS],
+    /// The slot of each sphere, then each mesh instance, whose emission is linked to what it
+    /// illuminates, or [`NO_LINK_SLOT`].
+    emitter_slots: [u32; LINKED_OBJECTS],
+    /// The linked emitters that don't illuminate each sphere, then each mesh instance, a bit for
+    /// each slot.
+    unlit_by_emitters: [u32; LINKED_OBJECTS],
+    _padding2: [u32; 2],
+    lights: [LightBuffer; MAX_NUMBER_OF_LIGHTS as _],
+}
+
+impl LightDataBuffer {
+    /// The lights of `scene`, and the emissive spheres and mesh instances with
+    /// [`LightLinks`] of their own, linked to its spheres and mesh instances.
+    pub fn new(scene: &Scene) -> Self {
+        let lights = scene.lights();
+        let lights = &lights[..cmp::min(lights.len(), MAX_NUMBER_OF_LIGHTS as usize)];
+        let mut light_buffer = [LightBuffer::zeroed(); MAX_NUMBER_OF_LIGHTS as _];
+        for (i, light) in lights.iter().enumerate() {
+            light_buffer[i] = LightBuffer::new(light.light, scene.nits_per_unit);
+        }
+
+        let spheres = scene.spheres();
+        let instances = scene.instances();
+        let spheres = spheres
+            .iter()
+            .map(|sphere| (sphere.id.0, &sphere.emitter.light_links));
+        let instances = instances
+            .iter()
+            .map(|instance| (instance.id.0, &instance.emitter.light_links));
+        let mut objects = spheres
+            .take(MAX_NUMBER_OF_SPHERES as usize)
+            .map(Some)
+            .collect::<Vec<_>>();
+        objects.resize(MAX_NUMBER_OF_SPHERES as usize, None);
+        objects.extend(instances.take(MAX_NUMBER_OF_INSTANCES as usize).map(Some));
+
+        let mut emitter_slots = [NO_LINK_SLOT; LINKED_OBJECTS];
+        let linked_emitters = objects
+            .iter()
+            .enumerate()
+            .filter_map(|(i, object)| Some((i, object.filter(|(_, links)| !links.is_empty())?.1)))
+            .take(MAX_LINKED_EMITTERS)
+            .enumerate()
+            .map(|(slot, (i, links))| {
+                emitter_slots[i] = slot as u32;
+                links
+            })
+            .collect::<Vec<_>>();
+
+        let mut unlit = [[0; 2]; LINKED_OBJECTS];
+        let mut unlit_by_emitters = [0; LINKED_OBJECTS];
+        for (i, object) in objects.iter().enumerate() {
+            let Some((object, _)) = *object else {
+                continue;
+            };
+            for (j, light) in lights.iter().enumerate() {
+                if !light.light.links.illuminates(object) {
+                    unlit[i][j / 32] |= 1 << (j % 32);
+                }
+            }
+            for (slot, links) in linked_emitters.iter().enumerate() {
+                if !links.illuminates(object) {
+                    unlit_by_emitters[i] |= 1 << slot;
+                }
+            }
+        }
+
+        Self {
+            light_count: lights.len() as u32,
+            _padding: 0,
+            unlit,
+            emitter_slots,
+            unlit_by_emitters,
+            _padding2: [0; 2],
+            lights: light_buffer,
+        }
+    }
+}
+
+/// The relative luminance of a linear sRGB color.
+fn luminance(color: Vector3<f32>) -> f32 {
+    color.dot(Vector3::new(0.2126, 0.7152, 0.0722))
+}
@@ -0,0 +1,761 @@
+use cgmath::Vector3;
+use egui::{epaint::CubicBezierShape, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::expression::MAX_LOCALS;
+
+use super::{LibraryMaterial, Material, DEFAULT_IOR};
+
+/// Nodes wired together into a sphere's albedo expression, compiled to the expression's source
+/// so they run on the same bytecode as one typed in. Each node gives one value, but for BSDF
+/// nodes, which are baked into the material library as the sphere's material.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialGraph {
+    /// The first is the output, whose inputs are the red, green and blue the albedo is
+    /// multiplied by and the BSDF node the sphere is made of, if it's linked to one.
+    pub nodes: Vec<GraphNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub kind: NodeKind,
+    /// One for each of [`NodeKind::inputs`].
+    pub inputs: Vec<NodeInput>,
+    /// Where the node's top left corner is in the editor.
+    pub position: [f32; 2],
+}
+
+/// What a node's input is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NodeInput {
+    /// The value of the node at this index.
+    Link(usize),
+    Value(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NodeKind {
+    Output,
+    /// One of the expression's variables, such as `x` or `t`.
+    Variable(Variable),
+    /// [`crate::expression`]'s value noise over the point hit, scaled by the first input and
+    /// moving along Z at the speed of the second.
+    Noise,
+    /// A channel of the canvas numbered by the first input among the meshes with one, at the
+    /// texture coordinates of the other two.
+    Texture(Channel),
+    Math(MathOp),
+    /// How the surface scatters light, with the roughness, index of refraction or intensity
+    /// its kind has as its input.
+    Bsdf(Bsdf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Variable {
+    X,
+    Y,
+    Z,
+    NormalX,
+    NormalY,
+    NormalZ,
+    U,
+    V,
+    Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bsdf {
+    Diffuse,
+    Metal,
+    Dielectric,
+    Emissive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Sin,
+    Cos,
+    Abs,
+    Floor,
+    Fract,
+    Sqrt,
+    Min,
+    Max,
+    Pow,
+    Step,
+    Mix,
+    Clamp,
+    Smoothstep,
+}
+
+/// The width of a node in the editor.
+const NODE_WIDTH: f32 = 170.0;
+/// The height of the editor's canvas.
+const CANVAS_HEIGHT: f32 = 320.0;
+/// Which of the output's inputs is the BSDF.
+const SURFACE_INPUT: usize = 3;
+
+impl Variable {
+    const ALL: [Self; 9] = [
+        Self::X,
+        Self::Y,
+        Self::Z,
+        Self::NormalX,
+        Self::NormalY,
+        Self::NormalZ,
+        Self::U,
+        Self::V,
+        Self::Time,
+    ];
+
+    /// The name the expression refers to it by.
+    fn source(&self) -> &'static str {
+        match self {
+            Self::X => "x",
+            Self::Y => "y",
+            Self::Z => "z",
+            Self::NormalX => "nx",
+            Self::NormalY => "ny",
+            Self::NormalZ => "nz",
+            Self::U => "u",
+            Self::V => "v",
+            Self::Time => "t",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::X => "Position X",
+            Self::Y => "Position Y",
+            Self::Z => "Position Z",
+            Self::NormalX => "Normal X",
+            Self::NormalY => "Normal Y",
+            Self::NormalZ => "Normal Z",
+            Self::U => "Texture U",
+            Self::V => "Texture V",
+            Self::Time => "Time",
+        }
+    }
+}
+
+impl Channel {
+    const ALL: [Self; 3] = [Self::Red, Self::Green, Self::Blue];
+
+    /// The function the expression samples the channel with.
+    fn function(&self) -> &'static str {
+        match self {
+            Self::Red => "canvas_r",
+            Self::Green => "canvas_g",
+            Self::Blue => "canvas_b",
+        }
+    }
+}
+
+impl Bsdf {
+    const ALL: [Self; 4] = [Self::Diffuse, Self::Metal, Self::Dielectric, Self::Emissive];
+
+    /// The white material of the kind, with `parameter` as its roughness, index of refraction
+    /// or intensity, for the albedo expression to color.
+    fn material(&self, parameter: f32) -> LibraryMaterial {
+        let (material, roughness, ior) = match self {
+            Self::Diffuse => (Material::Diffuse, 0.0, DEFAULT_IOR),
+            Self::Metal => (Material::Metal, parameter.clamp(0.0, 1.0), DEFAULT_IOR),
+            Self::Dielectric => (Material::Dielectric, 0.0, parameter.max(1.0)),
+            Self::Emissive => (
+                Material::Emissive {
+                    intensity: parameter.max(0.0),
+                },
+                0.0,
+                DEFAULT_IOR,
+            ),
+        };
+        LibraryMaterial {
+            name: String::new(),
+            albedo: Vector3::new(1.0, 1.0, 1.0),
+            material,
+            roughness,
+            ior,
+        }
+    }
+}
+
+impl MathOp {
+    const ALL: [Self; 17] = [
+        Self::Add,
+        Self::Subtract,
+        Self::Multiply,
+        Self::Divide,
+        Self::Sin,
+        Self::Cos,
+        Self::Abs,
+        Self::Floor,
+        Self::Fract,
+        Self::Sqrt,
+        Self::Min,
+        Self::Max,
+        Self::Pow,
+        Self::Step,
+        Self::Mix,
+        Self::Clamp,
+        Self::Smoothstep,
+    ];
+
+    /// The operator between the two inputs, or the function the inputs are passed to.
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Sin => "sin",
+            Self::Cos => "cos",
+            Self::Abs => "abs",
+            Self::Floor => "floor",
+            Self::Fract => "fract",
+            Self::Sqrt => "sqrt",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Pow => "pow",
+            Self::Step => "step",
+            Self::Mix => "mix",
+            Self::Clamp => "clamp",
+            Self::Smoothstep => "smoothstep",
+        }
+    }
+
+    fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Self::Add | Self::Subtract | Self::Multiply | Self::Divide
+        )
+    }
+}
+
+impl NodeKind {
+    /// Every kind that can be added to a graph, which has one output already.
+    pub fn addable() -> impl Iterator<Item = Self> {
+        Variable::ALL
+            .into_iter()
+            .map(Self::Variable)
+            .chain([Self::Noise])
+            .chain(Channel::ALL.into_iter().map(Self::Texture))
+            .chain(MathOp::ALL.into_iter().map(Self::Math))
+            .chain(Bsdf::ALL.into_iter().map(Self::Bsdf))
+    }
+
+    /// Whether the node gives a value other nodes can read, rather than being the output or a
+    /// BSDF.
+    fn gives_value(&self) -> bool {
+        !matches!(self, Self::Output | Self::Bsdf(_))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Output => "Albedo",
+            Self::Variable(variable) => variable.name(),
+            Self::Noise => "Noise",
+            Self::Texture(channel) => match channel {
+                Channel::Red => "Canvas red",
+                Channel::Green => "Canvas green",
+                Channel::Blue => "Canvas blue",
+            },
+            Self::Bsdf(bsdf) => match bsdf {
+                Bsdf::Diffuse => "Diffuse BSDF",
+                Bsdf::Metal => "Metal BSDF",
+                Bsdf::Dielectric => "Dielectric BSDF",
+                Bsdf::Emissive => "Emissive BSDF",
+            },
+            Self::Math(op) => match op {
+                MathOp::Add => "Add",
+                MathOp::Subtract => "Subtract",
+                MathOp::Multiply => "Multiply",
+                MathOp::Divide => "Divide",
+                MathOp::Sin => "Sine",
+                MathOp::Cos => "Cosine",
+                MathOp::Abs => "Absolute",
+                MathOp::Floor => "Floor",
+                MathOp::Fract => "Fraction",
+                MathOp::Sqrt => "Square root",
+                MathOp::Min => "Minimum",
+                MathOp::Max => "Maximum",
+                MathOp::Pow => "Power",
+                MathOp::Step => "Step",
+                MathOp::Mix => "Mix",
+                MathOp::Clamp => "Clamp",
+                MathOp::Smoothstep => "Smoothstep",
+            },
+        }
+    }
+
+    /// The names of the node's inputs.
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            Self::Output => &["red", "green", "blue", "surface"],
+            Self::Variable(_) => &[],
+            Self::Noise => &["scale", "speed"],
+            Self::Texture(_) => &["canvas", "u", "v"],
+            Self::Bsdf(bsdf) => match bsdf {
+                Bsdf::Diffuse => &[],
+                Bsdf::Metal => &["roughness"],
+                Bsdf::Dielectric => &["ior"],
+                Bsdf::Emissive => &["intensity"],
+            },
+            Self::Math(op) => match op {
+                MathOp::Add | MathOp::Subtract | MathOp::Multiply | MathOp::Divide => &["a", "b"],
+                MathOp::Min | MathOp::Max => &["a", "b"],
+                MathOp::Pow => &["base", "exponent"],
+                MathOp::Step => &["edge", "x"],
+                MathOp::Mix => &["a", "b", "t"],
+                MathOp::Clamp => &["x", "low", "high"],
+                MathOp::Smoothstep => &["low", "high", "x"],
+                _ => &["x"],
+            },
+        }
+    }
+
+    /// What unlinked inputs start at.
+    fn default_input(&self) -> f32 {
+        match self {
+            Self::Output | Self::Noise | Self::Bsdf(Bsdf::Emissive) => 1.0,
+            Self::Bsdf(Bsdf::Dielectric) => DEFAULT_IOR,
+            _ => 0.0,
+        }
+    }
+}
+
+impl GraphNode {
+    pub fn new(kind: NodeKind, position: [f32; 2]) -> Self {
+        Self {
+            kind,
+            inputs: vec![NodeInput::Value(kind.default_input()); kind.inputs().len()],
+            position,
+        }
+    }
+}
+
+impl MaterialGraph {
+    /// An output leaving the albedo as it is.
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![GraphNode::new(NodeKind::Output, [NODE_WIDTH * 2.0, 20.0])],
+        }
+    }
+
+    /// The albedo expression the graph builds, a gray if the red, green and blue are the same.
+    /// Nodes read more than once are computed once, into locals, as many as an expression can
+    /// have. Links to missing nodes, back to a node that depends on them or to a BSDF read as
+    /// zero.
+    pub fn compile(&self) -> String {
+        let Some(output) = self.nodes.first() else {
+            return "1".to_string();
+        };
+        let Some(channels) = output.inputs.get(..SURFACE_INPUT) else {
+            return "1".to_string();
+        };
+        // A gray reads its one value once
+        let channels = if channels.iter().all(|&channel| channel == channels[0]) {
+            &channels[..1]
+        } else {
+            channels
+        };
+        let mut compiler = GraphCompiler {
+            graph: self,
+            reads: self.reads(channels),
+            sources: vec![None; self.nodes.len()],
+            locals: Vec::new(),
+            visiting: vec![0],
+        };
+        let channels: Vec<_> = channels
+            .iter()
+            .map(|&input| compiler.input_source(input))
+            .collect();
+        let values = match &channels[..] {
+            [r, g, b] if r == g && g == b => r.clone(),
+            [r, g, b] => format!("{}, {}, {}", r, g, b),
+            [gray] => gray.clone(),
+            _ => unreachable!("there's one channel or three"),
+        };
+        let locals: String = compiler
+            .locals
+            .iter()
+            .map(|local| format!("{}; ", local))
+            .collect();
+        locals + &values
+    }
+
+    /// The material of the BSDF node linked to the output's surface, to make the sphere of, or
+    /// `None` if it's linked to none. The BSDF's input reads as its default if it's linked.
+    pub fn surface(&self) -> Option<LibraryMaterial> {
+        let NodeInput::Link(node) = *self.nodes.first()?.inputs.get(SURFACE_INPUT)? else {
+            return None;
+        };
+        let node = self.nodes.get(node)?;
+        let NodeKind::Bsdf(bsdf) = node.kind else {
+            return None;
+        };
+        let parameter = match node.inputs.first() {
+            Some(NodeInput::Value(value)) if value.is_finite() => *value,
+            _ => node.kind.default_input(),
+        };
+        Some(bsdf.material(parameter))
+    }
+
+    /// How many times the albedo reads each node when built from `channels`, following links
+    /// as [`GraphCompiler`] does, so links it reads as zero aren't counted.
+    fn reads(&self, channels: &[NodeInput]) -> Vec<usize> {
+        let mut reads = vec![0; self.nodes.len()];
+        let mut reached = vec![false; self.nodes.len()];
+        let mut visiting = vec![0];
+        for &input in channels {
+            self.count_reads(input, &mut reads, &mut reached, &mut visiting);
+        }
+        reads
+    }
+
+    fn count_reads(
+        &self,
+        input: NodeInput,
+        reads: &mut [usize],
+        reached: &mut [bool],
+        visiting: &mut Vec<usize>,
+    ) {
+        let NodeInput::Link(node) = input else {
+            return;
+        };
+        if node >= self.nodes.len()
+            || visiting.contains(&node)
+            || !self.nodes[node].kind.gives_value()
+        {
+            return;
+        }
+        reads[node] += 1;
+        if std::mem::replace(&mut reached[node], true) {
+            return;
+        }
+        visiting.push(node);
+        let inputs = &self.nodes[node].inputs;
+        for i in 0..self.nodes[node].kind.inputs().len() {
+            let input = inputs.get(i).copied().unwrap_or(NodeInput::Value(0.0));
+            self.count_reads(input, reads, reached, visiting);
+        }
+        visiting.pop();
+    }
+
+    /// Whether `node` reads the value of `other`, directly or through other nodes.
+    fn depends_on(&self, node: usize, other: usize) -> bool {
+        let mut stack = vec![node];
+        let mut seen = vec![false; self.nodes.len()];
+        while let Some(node) = stack.pop() {
+            if node == other {
+                return true;
+            }
+            if std::mem::replace(&mut seen[node], true) {
+                continue;
+            }
+            stack.extend(
+                self.nodes[node]
+                    .inputs
+                    .iter()
+                    .filter_map(|input| match input {
+                        NodeInput::Link(linked) if *linked < self.nodes.len() => Some(*linked),
+                        _ => None,
+                    }),
+            );
+        }
+        false
+    }
+
+    /// Removes the node at `index`, setting the inputs linked to it to zero.
+    fn remove(&mut self, index: usize) {
+        self.nodes.remove(index);
+        for node in &mut self.nodes {
+            for input in &mut node.inputs {
+                match input {
+                    NodeInput::Link(linked) if *linked == index => *input = NodeInput::Value(0.0),
+                    NodeInput::Link(linked) if *linked > index => *linked -= 1,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Edits the graph on a canvas of nodes with wires from each node to the inputs it's linked
+    /// to, returning whether what it compiles to or its surface changed and whether a node was
+    /// let go of after being moved.
+    pub fn render_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        id_source: impl std::hash::Hash,
+    ) -> (bool, bool) {
+        let id = ui.make_persistent_id(id_source);
+        let before = (self.compile(), self.surface());
+        let mut moved = false;
+
+        let (canvas, painter) = ui.allocate_painter(
+            Vec2::new(ui.available_width(), CANVAS_HEIGHT),
+            Sense::click(),
+        );
+        painter.rect_filled(canvas.rect, 4.0, ui.visuals().extreme_bg_color);
+        // Filled in once the nodes have been laid out, underneath them
+        let wires = painter.add(Shape::Noop);
+
+        let names: Vec<_> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| format!("{} {}", node.kind.name(), i))
+            .collect();
+        let kinds: Vec<_> = self.nodes.iter().map(|node| node.kind).collect();
+        // Which nodes each node can be linked to without a cycle
+        let linkable: Vec<Vec<bool>> = (0..self.nodes.len())
+            .map(|node| {
+                (0..self.nodes.len())
+                    .map(|other| other != 0 && !self.depends_on(other, node))
+                    .collect()
+            })
+            .collect();
+
+        let mut outputs = vec![Pos2::ZERO; self.nodes.len()];
+        let mut links = Vec::new();
+        let mut removed = None;
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            let min = canvas.rect.min + Vec2::from(node.position);
+            let mut child = ui.child_ui(
+                Rect::from_min_size(min, Vec2::new(NODE_WIDTH, CANVAS_HEIGHT)),
+                egui::Layout::top_down(egui::Align::Min),
+            );
+            child.set_clip_rect(canvas.rect);
+            let frame = egui::Frame::window(ui.style()).show(&mut child, |ui| {
+                ui.set_width(NODE_WIDTH - 16.0);
+                ui.horizontal(|ui| {
+                    let header = ui
+                        .add(
+                            egui::Label::new(egui::RichText::new(&names[i]).strong())
+                                .sense(Sense::drag()),
+                        )
+                        .on_hover_cursor(egui::CursorIcon::Grab);
+                    if header.dragged() {
+                        let size = canvas.rect.size() - Vec2::new(NODE_WIDTH, 40.0);
+                        let position = Vec2::from(node.position) + header.drag_delta();
+                        node.position = [
+                            position.x.clamp(0.0, size.x.max(0.0)),
+                            position.y.clamp(0.0, size.y.max(0.0)),
+                        ];
+                    }
+                    moved |= header.drag_released();
+                    if i != 0 && ui.small_button("✖").clicked() {
+                        removed = Some(i);
+                    }
+                });
+
+                let kind = node.kind;
+                for (j, (input, name)) in node.inputs.iter_mut().zip(kind.inputs()).enumerate() {
+                    // The output's surface is linked to BSDFs, and everything else to values
+                    let surface = i == 0 && j == SURFACE_INPUT;
+                    let accepts = |other: usize| {
+                        linkable[i][other]
+                            && if surface {
+                                matches!(kinds[other], NodeKind::Bsdf(_))
+                            } else {
+                                kinds[other].gives_value() && !matches!(kind, NodeKind::Bsdf(_))
+                            }
+                    };
+                    let unlinked = if surface { "Own material" } else { "Value" };
+                    let row = ui.horizontal(|ui| {
+                        let linked = match *input {
+                            NodeInput::Link(linked) => {
+                                names.get(linked).map_or("Missing", String::as_str)
+                            }
+                            NodeInput::Value(_) => unlinked,
+                        };
+                        egui::ComboBox::from_id_source((id, i, *name))
+                            .selected_text(linked)
+                            .width(70.0)
+                            .show_ui(ui, |ui| {
+                                if ui
+                                    .selectable_label(
+                                        matches!(input, NodeInput::Value(_)),
+                                        unlinked,
+                                    )
+                                    .clicked()
+                                {
+                                    *input = NodeInput::Value(0.0);
+                                }
+                                for (other, name) in names.iter().enumerate() {
+                                    if accepts(other) {
+                                        ui.selectable_value(input, NodeInput::Link(other), name);
+                                    }
+                                }
+                            });
+                        match input {
+                            NodeInput::Value(value) if !surface => {
+                                ui.add(egui::DragValue::new(value).speed(0.01));
+                            }
+                            _ => {}
+                        }
+                        ui.label(*name);
+                    });
+                    if let NodeInput::Link(linked) = *input {
+                        links.push((linked, row.response.rect.left_center()));
+                    }
+                }
+            });
+            outputs[i] = frame.response.rect.right_center();
+        }
+
+        let stroke = Stroke::new(2.0, ui.visuals().widgets.active.bg_fill);
+        let curves = links
+            .into_iter()
+            .filter_map(|(from, to)| {
+                let from = *outputs.get(from)?;
+                let bend = Vec2::new(((to.x - from.x).abs() / 2.0).max(30.0), 0.0);
+                Some(Shape::CubicBezier(CubicBezierShape::from_points_stroke(
+                    [from, from + bend, to - bend, to],
+                    false,
+                    egui::Color32::TRANSPARENT,
+                    stroke,
+                )))
+            })
+            .collect();
+        painter.set(wires, Shape::Vec(curves));
+
+        let mut added = None;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source((id, "add node"))
+                .selected_text("Add node")
+                .show_ui(ui, |ui| {
+                    for kind in NodeKind::addable() {
+                        if ui.selectable_label(false, kind.name()).clicked() {
+                            added = Some(kind);
+                        }
+                    }
+                });
+            ui.label(self.compile())
+                .on_hover_text("The albedo expression the graph builds");
+        });
+
+        if let Some(index) = removed {
+            self.remove(index);
+        }
+        if let Some(kind) = added {
+            // Staggered so new nodes don't hide each other
+            let offset = (self.nodes.len() % 6) as f32 * 24.0;
+            self.nodes
+                .push(GraphNode::new(kind, [10.0 + offset, 10.0 + offset]));
+        }
+        ((self.compile(), self.surface()) != before, moved)
+    }
+}
+
+impl Default for MaterialGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The source of a graph's albedo expression as it's built, with the nodes it's built so far.
+struct GraphCompiler<'a> {
+    graph: &'a MaterialGraph,
+    /// How many times each node is read, from [`MaterialGraph::reads`].
+    reads: Vec<usize>,
+    /// What each node built so far is read as, the name of its local if it has one.
+    sources: Vec<Option<String>>,
+    /// The locals the expression starts with, such as `n2 = sin(x)`.
+    locals: Vec<String>,
+    /// The nodes being built, each reading the next.
+    visiting: Vec<usize>,
+}
+
+impl GraphCompiler<'_> {
+    fn input_source(&mut self, input: NodeInput) -> String {
+        let nodes = &self.graph.nodes;
+        match input {
+            NodeInput::Value(value) if value.is_finite() => format!("{}", value),
+            NodeInput::Value(_) => "0".to_string(),
+            NodeInput::Link(node)
+                if node >= nodes.len()
+                    || self.visiting.contains(&node)
+                    || !nodes[node].kind.gives_value() =>
+            {
+                "0".to_string()
+            }
+            NodeInput::Link(node) => {
+                if let Some(source) = &self.sources[node] {
+                    return source.clone();
+                }
+                self.visiting.push(node);
+                let mut source = self.node_source(node);
+                self.visiting.pop();
+                let shared = self.reads[node] > 1 && !is_name_or_number(&source);
+                if shared && self.locals.len() < MAX_LOCALS {
+                    let name = format!("n{}", node);
+                    self.locals.push(format!("{} = {}", name, source));
+                    source = name;
+                }
+                self.sources[node] = Some(source.clone());
+                source
+            }
+        }
+    }
+
+    fn node_source(&mut self, node: usize) -> String {
+        let node = &self.graph.nodes[node];
+        let inputs: Vec<_> = (0..node.kind.inputs().len())
+            .map(|i| {
+                let input = node.inputs.get(i).copied().unwrap_or(NodeInput::Value(0.0));
+                self.input_source(input)
+            })
+            .collect();
+        let mut inputs = inputs.into_iter();
+        let mut next = || inputs.next().unwrap_or_default();
+        match node.kind {
+            NodeKind::Output | NodeKind::Bsdf(_) => "0".to_string(),
+            NodeKind::Variable(variable) => variable.source().to_string(),
+            NodeKind::Noise => {
+                let (scale, speed) = (next(), next());
+                format!(
+                    "noise(x * {0}, y * {0}, z * {0} + t * {1})",
+                    parenthesized(&scale),
+                    parenthesized(&speed)
+                )
+            }
+            NodeKind::Texture(channel) => {
+                let (canvas, u, v) = (next(), next(), next());
+                format!("{}({}, {}, {})", channel.function(), canvas, u, v)
+            }
+            NodeKind::Math(op) if op.is_operator() => {
+                format!("({} {} {})", next(), op.symbol(), next())
+            }
+            NodeKind::Math(op) => {
+                let arguments: Vec<_> = node.kind.inputs().iter().map(|_| next()).collect();
+                format!("{}({})", op.symbol(), arguments.join(", "))
+            }
+        }
+    }
+}
+
+fn is_name_or_number(source: &str) -> bool {
+    source
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '.' || c == '_')
+}
+
+/// `source` in parentheses unless it's already a single name or number.
+fn parenthesized(source: &str) -> String {
+    if is_name_or_number(source) {
+        source.to_string()
+    } else {
+        format!("({})", source)
+    }
+}
@@ -0,0 +1,226 @@
+use std::ops::{Index, IndexMut};
+
+use bytemuck::Zeroable;
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{naming::Names, MAX_NUMBER_OF_MATERIALS};
+
+use super::{or, Material};
+
+/// The index of refraction of new dielectrics, that of glass.
+pub const DEFAULT_IOR: f32 = 1.5;
+
+/// Common indices of refraction dielectrics can be set to, by what has them.
+pub const IOR_PRESETS: [(&str, f32); 3] = [("Water", 1.333), ("Glass", 1.5), ("Diamond", 2.418)];
+
+/// Where a material is in its scene's [`MaterialLibrary`], which is how spheres and triangles
+/// refer to it. The default, zero, is the library's first material.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct MaterialId(pub u32);
+
+impl MaterialId {
+    /// How the compute shader refers to the material, falling back to the default past the
+    /// materials it has room for.
+    pub fn shader_index(self) -> u32 {
+        if self.0 < MAX_NUMBER_OF_MATERIALS {
+            self.0
+        } else {
+            0
+        }
+    }
+}
+
+/// A material shared by every object referring to it, so editing it changes all of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryMaterial {
+    /// Unique among the library's materials.
+    pub name: String,
+    pub albedo: Vector3<f32>,
+    pub material: Material,
+    /// How far metals scatter reflections from the mirror direction, from zero for a mirror to
+    /// one for a matte finish. Ignored by the other kinds.
+    #[serde(default)]
+    pub roughness: f32,
+    /// The index of refraction of dielectrics, at least one. Ignored by the other kinds.
+    #[serde(default = "default_ior")]
+    pub ior: f32,
+}
+
+fn default_ior() -> f32 {
+    DEFAULT_IOR
+}
+
+/// The materials of a scene, in the order their IDs number them. Never empty once validated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MaterialLibrary {
+    materials: Vec<LibraryMaterial>,
+}
+
+impl MaterialLibrary {
+    /// A library of only the default material, a grey diffuse.
+    pub fn new() -> Self {
+        Self {
+            materials: vec![LibraryMaterial {
+                name: "Default".to_string(),
+                albedo: Vector3::new(0.5, 0.5, 0.5),
+                material: Material::Diffuse,
+                roughness: 0.0,
+                ior: DEFAULT_IOR,
+            }],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// Removes every material after the first `len`, leaving whatever refers to them missing.
+    pub fn truncate(&mut self, len: usize) {
+        self.materials.truncate(len);
+    }
+
+    pub fn get(&self, id: MaterialId) -> Option<&LibraryMaterial> {
+        self.materials.get(id.0 as usize)
+    }
+
+    pub fn get_mut(&mut self, id: MaterialId) -> Option<&mut LibraryMaterial> {
+        self.materials.get_mut(id.0 as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &LibraryMaterial)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .map(|(index, material)| (MaterialId(index as u32), material))
+    }
+
+    /// Adds `material` under a unique name after its own, or its kind's if it has none.
+    pub fn add(&mut self, mut material: LibraryMaterial) -> MaterialId {
+        material.name = self
+            .names()
+            .unique(or(&material.name, material.material.name()));
+        self.materials.push(material);
+        MaterialId(self.materials.len() as u32 - 1)
+    }
+
+    /// The first smooth material with `albedo` and `material` and the default index of
+    /// refraction, adding one named after its kind if there's none, so objects that looked the
+    /// same share one.
+    pub fn find_or_add(&mut self, albedo: Vector3<f32>, material: Material) -> MaterialId {
+        self.find_or_add_like(LibraryMaterial {
+            name: String::new(),
+            albedo,
+            material,
+            roughness: 0.0,
+            ior: DEFAULT_IOR,
+        })
+    }
+
+    /// The first material that looks like `material`, whatever it's named, or `material` added
+    /// if there's none.
+    pub fn find_or_add_like(&mut self, material: LibraryMaterial) -> MaterialId {
+        match self.find_like(&material) {
+            Some(id) => id,
+            None => self.add(material),
+        }
+    }
+
+    /// The first material that looks like `material`, whatever it's named.
+    pub fn find_like(&self, material: &LibraryMaterial) -> Option<MaterialId> {
+        let index = self.materials.iter().position(|m| {
+            m.albedo == material.albedo
+                && m.material == material.material
+                && m.roughness == material.roughness
+                && m.ior == material.ior
+        })?;
+        Some(MaterialId(index as u32))
+    }
+
+    /// Numbers the name of the material `id` if another has it too, or names it after its kind
+    /// if it's blank.
+    pub fn make_name_unique(&mut self, id: MaterialId) {
+        let index = id.0 as usize;
+        let Some(material) = self.materials.get_mut(index) else {
+            return;
+        };
+        let base = or(material.name.trim(), material.material.name()).to_string();
+        material.name.clear();
+
+        let unique = self.names().unique(&base);
+        self.materials[index].name = unique;
+    }
+
+    fn names(&self) -> Names {
+        Names::new(self.materials.iter().map(|m| m.name.as_str()))
+    }
+}
+
+impl Default for MaterialLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<MaterialId> for MaterialLibrary {
+    type Output = LibraryMaterial;
+
+    fn index(&self, id: MaterialId) -> &LibraryMaterial {
+        &self.materials[id.0 as usize]
+    }
+}
+
+impl IndexMut<MaterialId> for MaterialLibrary {
+    fn index_mut(&mut self, id: MaterialId) -> &mut LibraryMaterial {
+        &mut self.materials[id.0 as usize]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialBuffer {
+    albedo: [f32; 3],
+    material: u32,
+    roughness: f32,
+    ior: f32,
+    _padding: [f32; 2],
+}
+
+/// The materials the compute shader has room for, indexed by [`MaterialId::shader_index`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialDataBuffer {
+    materials: [MaterialBuffer; MAX_NUMBER_OF_MATERIALS as _],
+}
+
+impl MaterialDataBuffer {
+    pub fn new(library: &MaterialLibrary) -> Self {
+        let mut data = Self::zeroed();
+        for (buffer, material) in data.materials.iter_mut().zip(&library.materials) {
+            *buffer = MaterialBuffer {
+                albedo: material.material.shader_albedo(material.albedo).into(),
+                material: material.material.shader_index(),
+                roughness: material.roughness,
+                ior: material.ior,
+                _padding: [0.0; 2],
+            };
+        }
+        data
+    }
+
+    /// Whether surfaces of the material the shader knows as `index` emit light.
+    pub fn is_emissive(&self, index: u32) -> bool {
+        let emissive = Material::Emissive { intensity: 0.0 }.shader_index();
+        self.materials
+            .get(index as usize)
+            .is_some_and(|material| material.material == emissive)
+    }
+}
@@ -0,0 +1,175 @@
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    geometry::{Aabb, Bvh, BvhBuilder, Node},
+    model::Triangle,
+    MAX_NUMBER_OF_INSTANCES,
+};
+
+use super::{Canvas, Emitter, InstanceQueryReadOnlyItem, LightLinks, MeshRef, Scene, Transform};
+
+/// Triangles in their own object space, with a BVH built over them once, which
+/// [`MeshInstance`]s place in the scene any number of times.
+pub struct Mesh {
+    /// What instances refer to the mesh by in saved scenes.
+    pub uuid: Uuid,
+    pub name: String,
+    pub triangles: Vec<Triangle>,
+    pub bvh: Bvh,
+    /// Painted over the albedo of the triangles by their texture coordinates.
+    pub canvas: Option<Canvas>,
+}
+
+impl Mesh {
+    pub fn new(name: String, triangles: Vec<Triangle>) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            name,
+            bvh: Bvh::from_triangles(&triangles),
+            triangles,
+            canvas: None,
+        }
+    }
+
+    pub fn rebuild_bvh(&mut self, builder: BvhBuilder) {
+        self.bvh = Bvh::build(&self.triangles, builder);
+    }
+
+    /// In object space, inverted if the mesh is empty.
+    pub fn bounds(&self) -> Aabb {
+        self.bvh
+            .nodes
+            .first()
+            .map_or(Aabb::from_points(std::iter::empty()), Node::aabb)
+    }
+}
+
+/// A copy of a [`Mesh`] placed in the scene, which can be moved without rebuilding the mesh's
+/// BVH. Its components gathered together, as instances are copied and added to a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshInstance {
+    pub uuid: Uuid,
+    /// Unique among the scene's objects, given when the instance is added to it.
+    #[serde(default)]
+    pub name: String,
+    /// Into the scene's meshes.
+    pub mesh: usize,
+    pub position: Vector3<f32>,
+    /// Euler angles in degrees, applied around X, then Y, then Z.
+    pub rotation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+    /// Whether camera rays see the mesh's emissive triangles, see [`super::Sphere::camera_visible`].
+    #[serde(default = "super::default_camera_visible")]
+    pub camera_visible: bool,
+    /// Which objects the mesh's emissive triangles illuminate.
+    #[serde(default, skip_serializing_if = "LightLinks::is_empty")]
+    pub light_links: LightLinks,
+}
+
+impl MeshInstance {
+    /// The mesh where it was modelled.
+    pub fn new(mesh: usize) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            name: String::new(),
+            mesh,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Vector3::new(0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            camera_visible: true,
+            light_links: LightLinks::default(),
+        }
+    }
+
+    /// The instance `instance` is in the world.
+    pub fn from_components(instance: &InstanceQueryReadOnlyItem) -> Self {
+        Self {
+            uuid: instance.id.0,
+            name: instance.name.0.clone(),
+            mesh: instance.mesh.mesh,
+            position: instance.transform.position,
+            rotation: instance.transform.rotation,
+            scale: instance.transform.scale,
+            camera_visible: instance.emitter.camera_visible,
+            light_links: instance.emitter.light_links.clone(),
+        }
+    }
+
+    /// What the instance is spawned with besides its UUID and name.
+    pub(super) fn into_components(self) -> (Transform, MeshRef, Emitter) {
+        (
+            Transform {
+                position: self.position,
+                rotation: self.rotation,
+                scale: self.scale,
+            },
+            MeshRef { mesh: self.mesh },
+            Emitter {
+                camera_visible: self.camera_visible,
+                light_links: self.light_links,
+            },
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceBuffer {
+    world_to_object: [[f32; 4]; 4],
+    object_to_world: [[f32; 4]; 4],
+    /// The index of the root of the mesh's BVH among every mesh's nodes.
+    root: u32,
+    /// Non-zero if camera rays pass through the mesh's emissive triangles.
+    hidden_from_camera: u32,
+    /// The instance's index in the scene, for picking.
+    index: u32,
+    /// The layer of the mesh's canvas among the canvases' texture, or [`NO_CANVAS`].
+    canvas: u32,
+}
+
+/// What [`InstanceBuffer::canvas`] is for meshes without a canvas, which matches NO_CANVAS in the
+/// compute shader.
+pub const NO_CANVAS: u32 = u32::MAX;
+
+/// The instances and the top-level BVH over them, as the compute shader reads them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceDataBuffer {
+    instance_count: u32,
+    _padding: [u32; 3],
+    tlas: [Node; 2 * MAX_NUMBER_OF_INSTANCES as usize],
+    /// In the order the leaves of `tlas` index them.
+    instances: [InstanceBuffer; MAX_NUMBER_OF_INSTANCES as usize],
+}
+
+impl InstanceDataBuffer {
+    /// The instances of `scene` under its top-level BVH, where `roots` holds the index of the
+    /// root node of each mesh's BVH once uploaded and `canvases` the layer of each mesh's canvas.
+    /// Empty if there are too many instances.
+    pub fn new(scene: &Scene, roots: &[u32], canvases: &[u32]) -> Self {
+        let mut data: Self = bytemuck::Zeroable::zeroed();
+        let instances = scene.instances();
+        if instances.len() > MAX_NUMBER_OF_INSTANCES as usize {
+            return data;
+        }
+
+        let tlas = scene.tlas();
+        data.instance_count = tlas.triangle_indices.len() as u32;
+        data.tlas[..tlas.nodes.len()].copy_from_slice(&tlas.nodes);
+        for (slot, &index) in tlas.triangle_indices.iter().enumerate() {
+            let instance = &instances[index as usize];
+            let mesh = instance.mesh.mesh;
+            data.instances[slot] = InstanceBuffer {
+                world_to_object: instance.transform.world_to_object().into(),
+                object_to_world: instance.transform.object_to_world().into(),
+                root: roots[mesh],
+                hidden_from_camera: !instance.emitter.camera_visible as u32,
+                index,
+                canvas: canvases[mesh],
+            };
+        }
+        data
+    }
+}
@@ -1,54 +1,589 @@
-use cgmath::Vector3;
+use std::collections::HashMap;
+
+use bevy_ecs::prelude::{Component, Entity, World};
+use cgmath::{InnerSpace, Matrix, Vector3};
 use egui::Response;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-mod bvh;
 mod camera;
+mod canvas;
+mod clipboard;
+mod components;
+mod diagnostic_scenes;
+mod events;
+mod file;
+mod import;
+mod light;
+mod material_graph;
+mod material_library;
+mod mesh;
+mod packing;
 mod plane;
+mod point_cache;
+mod scatter;
+mod selection;
 mod sphere;
+mod texture_paint;
+mod validation;
 
 pub use camera::*;
+pub use canvas::{Canvas, CanvasRows, CANVAS_SIZE};
+pub use components::*;
+pub use diagnostic_scenes::FURNACE_RADIANCE;
+pub use events::SceneEvent;
+pub use file::SavedSnapshot;
+pub use light::*;
+pub use material_graph::*;
+pub use material_library::*;
+pub use mesh::*;
+pub use packing::SpherePacking;
 pub use plane::*;
+pub use point_cache::{PointCache, PointCachePlayer};
+pub use scatter::ScatterBrush;
+pub use selection::Placement;
 pub use sphere::*;
+pub use texture_paint::TexturePainter;
+pub use validation::Repair;
 
-use crate::{model::Triangle, renderer::Renderer};
+use crate::{
+    expression::Expression,
+    geometry::{self, Aabb, BvhBuilder, Primitive, SurfaceHit},
+    naming::Names,
+    MAX_NUMBER_OF_INSTANCES, MAX_NUMBER_OF_LIGHTS, MAX_NUMBER_OF_MATERIALS,
+};
 
-use self::bvh::Bvh;
+pub use crate::{
+    geometry::{Bvh, Ray},
+    model::Triangle,
+};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Material {
     Diffuse,
     Metal,
     Dielectric,
-    Gizmo,
+    /// Invisible except for the shadows and reflections cast onto it, for compositing objects
+    /// over the background.
+    ShadowCatcher,
+    /// A light source emitting its albedo times `intensity`.
+    Emissive {
+        intensity: f32,
+    },
+}
+
+impl Material {
+    /// How the compute shader identifies the material.
+    pub fn shader_index(&self) -> u32 {
+        match self {
+            Material::Diffuse => 0,
+            Material::Metal => 1,
+            Material::Dielectric => 2,
+            Material::ShadowCatcher => 4,
+            Material::Emissive { .. } => 5,
+        }
+    }
+
+    /// What a new material of the kind is named.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Diffuse => "Diffuse",
+            Material::Metal => "Metal",
+            Material::Dielectric => "Dielectric",
+            Material::ShadowCatcher => "Shadow catcher",
+            Material::Emissive { .. } => "Emissive",
+        }
+    }
+
+    /// The color the compute shader gets for a surface with `albedo`, which for emissive
+    /// materials is the radiance they emit.
+    pub fn shader_albedo(&self, albedo: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            Material::Emissive { intensity } => albedo * *intensity,
+            _ => albedo,
+        }
+    }
+
+    /// Radio buttons for the materials a user can pick, and the intensity of emissive ones.
+    fn render_ui(&mut self, ui: &mut egui::Ui) -> Vec<Response> {
+        let mut responses = vec![
+            ui.radio_value(self, Material::Diffuse, "Diffuse"),
+            ui.radio_value(self, Material::Metal, "Metal"),
+            ui.radio_value(self, Material::Dielectric, "Dielectric"),
+            ui.radio_value(self, Material::ShadowCatcher, "Shadow catcher"),
+        ];
+
+        let is_emissive = matches!(self, Material::Emissive { .. });
+        let mut emissive = ui.radio(is_emissive, "Emissive");
+        if emissive.clicked() && !is_emissive {
+            *self = Material::Emissive { intensity: 1.0 };
+            emissive.mark_changed();
+        }
+        responses.push(emissive);
+
+        if let Material::Emissive { intensity } = self {
+            responses.push(
+                ui.add(
+                    egui::DragValue::new(intensity)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX)
+                        .prefix("intensity "),
+                ),
+            );
+        }
+
+        responses
+    }
+}
+
+const RAYCAST_T_MIN: f32 = 0.001;
+/// The rate animation frames play at, for [`Scene::time`].
+const FRAMES_PER_SECOND: f32 = 24.0;
+/// [`Scene::nits_per_unit`] of new scenes and files from before it was saved, about what an
+/// interior lit to a radiance of 1.0 looks like.
+pub const DEFAULT_NITS_PER_UNIT: f32 = 1000.0;
+
+/// What a [`Scene::raycast`] hit.
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    /// The sphere or mesh instance hit, as the selection refers to it.
+    pub object: Uuid,
+    pub point: Vector3<f32>,
+    /// The shading normal at `point`.
+    pub normal: Vector3<f32>,
 }
 
+/// Everything that's rendered. The spheres, mesh instances and lights are entities in a
+/// [`World`] made of [`components`], identified by UUID wherever they're referred to from
+/// outside it, and listed, uploaded and picked in the order they were added. Edits are
+/// published as [`SceneEvent`]s for whatever depends on them.
 pub struct Scene {
+    pub name: String,
     pub camera: Camera,
-    pub spheres: Vec<Sphere>,
-    pub selected_sphere: Option<Uuid>,
-    pub triangles: Vec<Triangle>,
-    pub bvh: Bvh,
+    /// A fixed camera for framing the final image, shown picture-in-picture.
+    pub final_camera: Camera,
+    world: World,
+    queries: Queries,
+    /// The entity of each object, by UUID.
+    entities: HashMap<Uuid, Entity>,
+    /// The [`Order`] of the next object added.
+    next_order: u64,
+    /// The luminance in nits (cd/m²) of a radiance of 1.0, which lights given in physical units
+    /// are converted by and the false color view reads the render by.
+    pub nits_per_unit: f32,
+    /// Shared by the spheres and triangles referring to them.
+    pub materials: MaterialLibrary,
+    /// The selected spheres and instances in the order they were selected, the gizmo at the
+    /// last.
+    pub selection: Vec<Uuid>,
+    meshes: Vec<Mesh>,
+    /// Over the bounds of the mesh instances in world space, rebuilt whenever one is added,
+    /// moved or removed.
+    tlas: Bvh,
+    /// The animation frame being shown, which seeds the renderer's sampling so every frame
+    /// renders the same way each time.
+    pub frame: u32,
+    /// How many edits have been published, and how many of them were in the file last saved.
+    edits: u64,
+    saved_edits: u64,
+    /// Published since the last [`Scene::take_events`].
+    events: Vec<SceneEvent>,
 }
 
 impl Scene {
+    /// Places `triangles` in the scene as a single mesh, as they are.
     pub fn new(spheres: Vec<Sphere>, triangles: Vec<Triangle>, camera: Camera) -> Self {
-        Self {
+        let (meshes, instances) = if triangles.is_empty() {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                vec![Mesh::new("Mesh".to_string(), triangles)],
+                vec![MeshInstance::new(0)],
+            )
+        };
+        Self::with_meshes(spheres, meshes, instances, camera)
+    }
+
+    /// Every instance's mesh should be one of `meshes`.
+    pub fn with_meshes(
+        spheres: Vec<Sphere>,
+        meshes: Vec<Mesh>,
+        instances: Vec<MeshInstance>,
+        camera: Camera,
+    ) -> Self {
+        let mut world = World::new();
+        let queries = Queries::new(&mut world);
+        let mut scene = Self {
+            name: "Untitled".to_string(),
+            final_camera: camera.clone(),
             camera,
-            spheres,
-            selected_sphere: None,
-            bvh: Bvh::from_triangles(&triangles),
-            triangles,
+            world,
+            queries,
+            entities: HashMap::new(),
+            next_order: 0,
+            nits_per_unit: DEFAULT_NITS_PER_UNIT,
+            materials: MaterialLibrary::new(),
+            selection: Vec::new(),
+            meshes,
+            tlas: Bvh::from_triangles::<Aabb>(&[]),
+            frame: 0,
+            edits: 0,
+            saved_edits: 0,
+            events: Vec::new(),
+        };
+        for sphere in spheres {
+            scene.spawn_sphere(sphere);
+        }
+        for instance in instances {
+            scene.spawn_instance(instance);
+        }
+        scene.update_tlas();
+        scene.name_objects();
+        scene
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    /// The spheres in the order they were added, which is how they're uploaded and picked.
+    pub fn spheres(&self) -> Vec<SphereQueryReadOnlyItem<'_>> {
+        let mut spheres = self
+            .queries
+            .spheres
+            .iter_manual(&self.world)
+            .collect::<Vec<_>>();
+        spheres.sort_unstable_by_key(|sphere| *sphere.order);
+        spheres
+    }
+
+    /// The mesh instances in the order they were added, which is how they're uploaded and
+    /// picked.
+    pub fn instances(&self) -> Vec<InstanceQueryReadOnlyItem<'_>> {
+        let mut instances = self
+            .queries
+            .instances
+            .iter_manual(&self.world)
+            .collect::<Vec<_>>();
+        instances.sort_unstable_by_key(|instance| *instance.order);
+        instances
+    }
+
+    /// The lights in the order they were added, which is how they're uploaded.
+    pub fn lights(&self) -> Vec<LightQueryReadOnlyItem<'_>> {
+        let mut lights = self
+            .queries
+            .lights
+            .iter_manual(&self.world)
+            .collect::<Vec<_>>();
+        lights.sort_unstable_by_key(|light| *light.order);
+        lights
+    }
+
+    pub fn sphere_count(&self) -> usize {
+        self.queries.spheres.iter_manual(&self.world).count()
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.queries.instances.iter_manual(&self.world).count()
+    }
+
+    pub fn light_count(&self) -> usize {
+        self.queries.lights.iter_manual(&self.world).count()
+    }
+
+    /// The `T` component of the object `uuid`, if it has one.
+    pub fn get<T: Component>(&self, uuid: Uuid) -> Option<&T> {
+        self.world.get(*self.entities.get(&uuid)?)
+    }
+
+    /// The `T` component of the object `uuid`, if it has one, to edit. Whatever depends on it
+    /// is only told with an event.
+    pub fn get_mut<T: Component>(&mut self, uuid: Uuid) -> Option<&mut T> {
+        let entity = *self.entities.get(&uuid)?;
+        self.world
+            .get_mut::<T>(entity)
+            .map(|component| component.into_inner())
+    }
+
+    /// A copy of the sphere `uuid`.
+    pub fn sphere(&self, uuid: Uuid) -> Option<Sphere> {
+        let entity = *self.entities.get(&uuid)?;
+        let sphere = self.queries.spheres.get_manual(&self.world, entity).ok()?;
+        Some(Sphere::from_components(&sphere))
+    }
+
+    /// A copy of the mesh instance `uuid`.
+    pub fn instance(&self, uuid: Uuid) -> Option<MeshInstance> {
+        let entity = *self.entities.get(&uuid)?;
+        let instance = self
+            .queries
+            .instances
+            .get_manual(&self.world, entity)
+            .ok()?;
+        Some(MeshInstance::from_components(&instance))
+    }
+
+    /// Adds an object made of `components` to the world, after every other.
+    fn spawn(&mut self, uuid: Uuid, name: String, components: impl bevy_ecs::bundle::Bundle) {
+        let order = Order(self.next_order);
+        self.next_order += 1;
+        let entity = self
+            .world
+            .spawn((ObjectId(uuid), Name(name), order, components))
+            .id();
+        self.entities.insert(uuid, entity);
+        self.queries.update_archetypes(&self.world);
+    }
+
+    fn spawn_sphere(&mut self, sphere: Sphere) {
+        let (uuid, name) = (sphere.uuid, sphere.name.clone());
+        self.spawn(uuid, name, sphere.into_components());
+    }
+
+    fn spawn_instance(&mut self, instance: MeshInstance) {
+        let (uuid, name) = (instance.uuid, instance.name.clone());
+        self.spawn(uuid, name, instance.into_components());
+    }
+
+    /// Removes the object `uuid` from the world, returning whether there was one.
+    fn despawn(&mut self, uuid: Uuid) -> bool {
+        self.entities
+            .remove(&uuid)
+            .is_some_and(|entity| self.world.despawn(entity))
+    }
+
+    /// How far into the animation [`Self::frame`] is in seconds, as material expressions see it.
+    pub fn time(&self) -> f32 {
+        self.frame as f32 / FRAMES_PER_SECOND
+    }
+
+    /// The top-level BVH, whose leaves index [`Scene::instances`].
+    pub fn tlas(&self) -> &Bvh {
+        &self.tlas
+    }
+
+    /// Leaves out instances of empty meshes, which have no BVH root of their own to trace.
+    fn update_tlas(&mut self) {
+        let (placed, bounds): (Vec<u32>, Vec<Aabb>) = self
+            .instances()
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| !self.meshes[instance.mesh.mesh].triangles.is_empty())
+            .map(|(index, instance)| {
+                (
+                    index as u32,
+                    instance
+                        .transform
+                        .world_bounds(&self.meshes[instance.mesh.mesh]),
+                )
+            })
+            .unzip();
+        let mut tlas = Bvh::from_triangles(&bounds);
+        for index in &mut tlas.triangle_indices {
+            *index = placed[*index as usize];
+        }
+        self.tlas = tlas;
+    }
+
+    /// Whether the scene has been edited since it was created or last saved.
+    pub fn is_dirty(&self) -> bool {
+        self.edits != self.saved_edits
+    }
+
+    /// Records `event` for whatever reacts to changes in the scene, and marks the scene as
+    /// edited if it is one.
+    pub fn publish(&mut self, event: SceneEvent) {
+        if event.is_edit() {
+            self.edits += 1;
+        }
+        self.events.push(event);
+    }
+
+    /// The events published since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<SceneEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// The names of the scene's objects, for naming new ones so they don't collide.
+    pub fn names(&self) -> Names {
+        Names::new(
+            self.entities
+                .values()
+                .filter_map(|&entity| self.world.get::<Name>(entity).map(|name| name.0.as_str())),
+        )
+    }
+
+    /// The name of the sphere, instance or light `uuid`.
+    pub fn name_of(&self, uuid: Uuid) -> Option<&str> {
+        self.get::<Name>(uuid).map(|name| name.0.as_str())
+    }
+
+    /// `base`, numbered if an object already has that name.
+    pub fn unique_name(&self, base: &str) -> String {
+        self.names().unique(base)
+    }
+
+    /// The name of the sphere, instance or light `uuid`, and what it's named when left blank.
+    fn name_mut(&mut self, uuid: Uuid) -> Option<(&mut String, String)> {
+        let default = if self.get::<SphereShape>(uuid).is_some() {
+            "Sphere".to_string()
+        } else if let Some(mesh) = self.get::<MeshRef>(uuid) {
+            self.meshes[mesh.mesh].name.clone()
+        } else {
+            self.get::<Light>(uuid)?.kind.name().to_string()
+        };
+        Some((&mut self.get_mut::<Name>(uuid)?.0, default))
+    }
+
+    /// Numbers the name of the sphere, instance or light `uuid` if another object has it too,
+    /// or names it after what it is if it's blank.
+    fn make_name_unique(&mut self, uuid: Uuid) {
+        let Some((name, default)) = self.name_mut(uuid) else {
+            return;
+        };
+        let base = or(name.trim(), &default).to_string();
+        name.clear();
+
+        let unique = self.unique_name(&base);
+        if let Some((name, _)) = self.name_mut(uuid) {
+            *name = unique;
+        }
+    }
+
+    /// Names objects without a name, or with one an earlier object has, after what they are.
+    fn name_objects(&mut self) {
+        let mut names = Names::default();
+        for mut sphere in in_order(self.queries.spheres.iter_mut(&mut self.world), |s| *s.order) {
+            sphere.name.0 = names.unique(or(&sphere.name.0, "Sphere"));
+        }
+        let instances = self.queries.instances.iter_mut(&mut self.world);
+        for mut instance in in_order(instances, |i| *i.order) {
+            let mesh = &self.meshes[instance.mesh.mesh].name;
+            instance.name.0 = names.unique(or(&instance.name.0, mesh));
+        }
+        for mut light in in_order(self.queries.lights.iter_mut(&mut self.world), |l| *l.order) {
+            let kind = light.light.kind.name();
+            light.name.0 = names.unique(or(&light.name.0, kind));
+        }
+    }
+
+    /// Adds a unit sphere of the default material at the origin.
+    pub fn add_sphere(&mut self) {
+        self.push_sphere(Sphere::new(SphereDescriptor {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: MaterialId::default(),
+        }));
+    }
+
+    /// Adds `sphere` under a unique name after its own, or "Sphere" if it has none.
+    pub fn push_sphere(&mut self, mut sphere: Sphere) -> Uuid {
+        sphere.name = self.unique_name(or(&sphere.name, "Sphere"));
+        let uuid = sphere.uuid;
+        self.spawn_sphere(sphere);
+        self.publish(SceneEvent::ObjectAdded(uuid));
+        uuid
+    }
+
+    /// Adds a white light of `kind` above the origin.
+    pub fn add_light(&mut self, kind: LightKind) {
+        self.push_light(Light::new(kind), "");
+    }
+
+    /// Adds `light` under a unique name after `name`, or its kind if that's blank.
+    pub fn push_light(&mut self, light: Light, name: &str) -> Uuid {
+        let uuid = Uuid::new_v4();
+        let name = self.unique_name(or(name, light.kind.name()));
+        self.spawn(uuid, name, light);
+        self.publish(SceneEvent::LightChanged(uuid));
+        uuid
+    }
+
+    /// Removes the light `uuid`.
+    pub fn remove_light(&mut self, uuid: Uuid) {
+        if self.get::<Light>(uuid).is_some() && self.despawn(uuid) {
+            self.deselect(uuid);
+            self.publish(SceneEvent::LightChanged(uuid));
+        }
+    }
+
+    /// Places another copy of the mesh at `mesh` where it was modelled, unless there's no room
+    /// for more instances.
+    pub fn add_instance(&mut self, mesh: usize) {
+        self.push_instance(MeshInstance::new(mesh));
+    }
+
+    /// Adds `instance`, named after its mesh if it has no name, unless there's no room for more
+    /// instances.
+    pub fn push_instance(&mut self, mut instance: MeshInstance) -> Option<Uuid> {
+        if self.instance_count() >= MAX_NUMBER_OF_INSTANCES as usize {
+            return None;
+        }
+        instance.name = self.unique_name(or(&instance.name, &self.meshes[instance.mesh].name));
+        let uuid = instance.uuid;
+        self.spawn_instance(instance);
+        self.update_tlas();
+        self.publish(SceneEvent::ObjectAdded(uuid));
+        Some(uuid)
+    }
+
+    pub fn remove_instance(&mut self, uuid: Uuid) {
+        if self.get::<MeshRef>(uuid).is_none() || !self.despawn(uuid) {
+            return;
+        }
+        self.deselect(uuid);
+        self.update_tlas();
+        self.publish(SceneEvent::ObjectRemoved(uuid));
+    }
+
+    /// The builder the meshes' BVHs were last built with.
+    pub fn bvh_builder(&self) -> BvhBuilder {
+        self.meshes
+            .first()
+            .map_or(BvhBuilder::default(), |mesh| mesh.bvh.builder())
+    }
+
+    /// Rebuilds every mesh's BVH with `builder`.
+    pub fn rebuild_bvh(&mut self, builder: BvhBuilder) {
+        let start = std::time::Instant::now();
+        for mesh in &mut self.meshes {
+            mesh.rebuild_bvh(builder);
+        }
+        log::info!(
+            "Built {:?} BVHs over {} meshes in {:?}",
+            builder,
+            self.meshes.len(),
+            start.elapsed()
+        );
+        self.publish(SceneEvent::BvhRebuilt);
+    }
+
+    /// Removes the last sphere.
+    pub fn remove_last_sphere(&mut self) {
+        if let Some(uuid) = self.spheres().last().map(|s| s.id.0) {
+            self.remove_sphere(uuid);
         }
     }
 
+    pub fn remove_sphere(&mut self, uuid: Uuid) {
+        if self.get::<SphereShape>(uuid).is_none() || !self.despawn(uuid) {
+            return;
+        }
+        self.deselect(uuid);
+        self.publish(SceneEvent::ObjectRemoved(uuid));
+    }
+
     pub fn render_ui(
         &mut self,
         ui: &mut egui::Ui,
         context: &egui::Context,
-        renderer: &mut Renderer,
+        material_preview: egui::Image,
     ) {
-        let mut responses: Vec<Response> = Vec::new();
+        let mut events = Vec::new();
+        let sources = self.light_sources();
+        // The lights and emitters toggled on objects, the objects and whether they're now lit
+        let mut links = Vec::new();
 
         ui.collapsing("Scene", |ui| {
             ui.horizontal(|ui| {
@@ -57,13 +592,7 @@ impl Scene {
                     .on_hover_text("Add a sphere to the scene")
                     .clicked()
                 {
-                    self.spheres.push(Sphere::new(SphereDescriptor {
-                        center: Vector3::new(0.0, 0.0, 0.0),
-                        radius: 1.0,
-                        albedo: Vector3::new(0.5, 0.5, 0.5),
-                        material: Material::Diffuse,
-                    }));
-                    renderer.progressive_rendering.reset_ready_samples();
+                    self.add_sphere();
                 }
 
                 if ui
@@ -71,130 +600,617 @@ impl Scene {
                     .on_hover_text("Remove the last sphere from the scene")
                     .clicked()
                 {
-                    self.spheres.pop();
-                    renderer.progressive_rendering.reset_ready_samples();
+                    self.remove_last_sphere();
                 }
             });
             ui.separator();
 
-            for (i, sphere) in self.spheres.iter_mut().enumerate() {
-                ui.collapsing(format!("Sphere {}", i), |ui| {
-                    ui.horizontal(|ui| {
-                        ui.label("Center");
-                        responses.extend([
-                            ui.add(egui::DragValue::new(&mut sphere.center.x).speed(0.1)),
-                            ui.add(egui::DragValue::new(&mut sphere.center.y).speed(0.1)),
-                            ui.add(egui::DragValue::new(&mut sphere.center.z).speed(0.1)),
-                        ]);
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Radius");
-                        responses.push(ui.add(egui::DragValue::new(&mut sphere.radius).speed(0.1)));
+            let mut removed = None;
+            let spheres = self.queries.spheres.iter_mut(&mut self.world);
+            for mut sphere in in_order(spheres, |s| *s.order) {
+                let uuid = sphere.id.0;
+                egui::CollapsingHeader::new(sphere.name.0.clone())
+                    .id_source(uuid)
+                    .show(ui, |ui| {
+                        events.extend(sphere_ui(ui, &mut sphere, &mut self.materials));
+                        let toggled = object_linking_ui(ui, uuid, &sources);
+                        links.extend(toggled.into_iter().map(|(s, lit)| (s, uuid, lit)));
+                        if ui.button("Remove").clicked() {
+                            removed = Some(uuid);
+                        }
                     });
-                    ui.horizontal(|ui| {
-                        ui.label("Albedo");
-                        responses.extend([
-                            ui.add(egui::DragValue::new(&mut sphere.albedo.x)),
-                            ui.add(egui::DragValue::new(&mut sphere.albedo.y)),
-                            ui.add(egui::DragValue::new(&mut sphere.albedo.z)),
-                        ]);
-
-                        let mut color: [f32; 3] = sphere.albedo.into();
-                        responses.push(ui.color_edit_button_rgb(&mut color));
-                        sphere.albedo = color.into();
+            }
+            if let Some(uuid) = removed {
+                self.remove_sphere(uuid);
+            }
+        });
+
+        ui.collapsing("Materials", |ui| {
+            let has_room = self.materials.len() < MAX_NUMBER_OF_MATERIALS as usize;
+            if ui
+                .add_enabled(has_room, egui::Button::new("Add Material"))
+                .on_hover_text("Add a grey diffuse material for objects to share")
+                .clicked()
+            {
+                let id = self.materials.add(LibraryMaterial {
+                    name: String::new(),
+                    albedo: Vector3::new(0.5, 0.5, 0.5),
+                    material: Material::Diffuse,
+                    roughness: 0.0,
+                    ior: DEFAULT_IOR,
+                });
+                events.push(SceneEvent::MaterialEdited(id));
+            }
+            ui.separator();
+
+            let ids = self.materials.iter().map(|(id, _)| id).collect::<Vec<_>>();
+            for id in ids {
+                let material = &mut self.materials[id];
+                egui::CollapsingHeader::new(material.name.clone())
+                    .id_source(("material", id))
+                    .show(ui, |ui| {
+                        events.extend(library_material_ui(ui, material, id));
                     });
-                    ui.horizontal(|ui| {
-                        ui.label("Material");
-                        responses.extend([
-                            ui.radio_value(&mut sphere.material, Material::Diffuse, "Diffuse"),
-                            ui.radio_value(&mut sphere.material, Material::Metal, "Metal"),
-                            ui.radio_value(
-                                &mut sphere.material,
-                                Material::Dielectric,
-                                "Dielectric",
-                            ),
-                        ]);
+            }
+        });
+
+        ui.collapsing("Lights", |ui| {
+            let response = ui
+                .add(
+                    egui::Slider::new(&mut self.nits_per_unit, 1.0..=100000.0)
+                        .logarithmic(true)
+                        .text("nits per unit radiance"),
+                )
+                .on_hover_text(
+                    "The luminance in cd/m² that a radiance of 1.0 in the render represents, \
+                    which lights in physical units and the false color view are converted by",
+                );
+            if response.changed() {
+                events.push(SceneEvent::UnitsChanged);
+            }
+            let has_room = self.light_count() < MAX_NUMBER_OF_LIGHTS as usize;
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(has_room, egui::Button::new("Add Point Light"))
+                    .clicked()
+                {
+                    self.add_light(LightKind::Point);
+                }
+                if ui
+                    .add_enabled(has_room, egui::Button::new("Add Directional Light"))
+                    .clicked()
+                {
+                    self.add_light(LightKind::Directional);
+                }
+                if ui
+                    .add_enabled(has_room, egui::Button::new("Add Quad Light"))
+                    .clicked()
+                {
+                    self.add_light(LightKind::Quad);
+                }
+            });
+            ui.separator();
+
+            let objects: Vec<_> = self
+                .spheres()
+                .iter()
+                .map(|sphere| (sphere.id.0, sphere.name.0.clone()))
+                .chain(
+                    self.instances()
+                        .iter()
+                        .map(|instance| (instance.id.0, instance.name.0.clone())),
+                )
+                .collect();
+            let mut removed = None;
+            let lights = self.queries.lights.iter_mut(&mut self.world);
+            for mut light in in_order(lights, |l| *l.order) {
+                let uuid = light.id.0;
+                egui::CollapsingHeader::new(light.name.0.clone())
+                    .id_source(uuid)
+                    .show(ui, |ui| {
+                        events.extend(name_ui(ui, &mut light.name.0, uuid));
+                        if light.light.render_ui(ui, uuid, self.nits_per_unit) {
+                            events.push(SceneEvent::LightChanged(uuid));
+                        }
+                        if light.light.linking_ui(ui, uuid, &objects) {
+                            events.push(SceneEvent::LightChanged(uuid));
+                        }
+                        if ui.button("Remove").clicked() {
+                            removed = Some(uuid);
+                        }
                     });
+            }
+            if let Some(uuid) = removed {
+                self.remove_light(uuid);
+            }
+        });
+
+        ui.collapsing("Meshes", |ui| {
+            let mut builder = self.bvh_builder();
+            egui::ComboBox::from_label("BVH builder")
+                .selected_text(match builder {
+                    BvhBuilder::Midpoint => "Midpoint",
+                    BvhBuilder::BinnedSah => "Binned SAH",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut builder, BvhBuilder::Midpoint, "Midpoint");
+                    ui.selectable_value(&mut builder, BvhBuilder::BinnedSah, "Binned SAH");
+                })
+                .response
+                .on_hover_text(
+                    "How the meshes' acceleration structures are split. The surface area \
+                    heuristic builds more slowly but traces faster",
+                );
+            if builder != self.bvh_builder() {
+                self.rebuild_bvh(builder);
+            }
+            ui.separator();
+
+            let has_room = self.instance_count() < MAX_NUMBER_OF_INSTANCES as usize;
+            let mut added = None;
+            for (i, mesh) in self.meshes.iter_mut().enumerate() {
+                events.extend(name_ui(ui, &mut mesh.name, mesh.uuid));
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} triangles", mesh.triangles.len()));
+                    if ui
+                        .add_enabled(has_room, egui::Button::new("Add Instance"))
+                        .clicked()
+                    {
+                        added = Some(i);
+                    }
                 });
+                ui.label(format!(
+                    "{} nodes, expected cost {:.1}",
+                    mesh.bvh.nodes.len(),
+                    mesh.bvh.sah_cost()
+                ))
+                .on_hover_text(
+                    "Node visits and triangle tests per ray by the surface area heuristic",
+                );
+            }
+            if let Some(mesh) = added {
+                self.add_instance(mesh);
             }
         });
 
-        if let Some(selected_sphere) = self.selected_sphere {
-            if let Some(sphere) = self.spheres.iter_mut().find(|s| s.uuid == selected_sphere) {
-                egui::Window::new("Selected Sphere")
-                    .default_pos(egui::Pos2::new(400.0, 400.0))
-                    .resizable(true)
-                    .show(context, |ui| {
+        ui.collapsing("Instances", |ui| {
+            let mut removed = None;
+            let mut selected = None;
+            let mut moved = false;
+            let instances = self.queries.instances.iter_mut(&mut self.world);
+            for mut instance in in_order(instances, |i| *i.order) {
+                let uuid = instance.id.0;
+                egui::CollapsingHeader::new(instance.name.0.clone())
+                    .id_source(uuid)
+                    .show(ui, |ui| {
+                        events.extend(name_ui(ui, &mut instance.name.0, uuid));
+                        if instance_ui(ui, &mut instance.transform) {
+                            moved = true;
+                            events.push(SceneEvent::ObjectMoved(uuid));
+                        }
+                        let camera_visible = &mut instance.emitter.camera_visible;
+                        if camera_visible_ui(ui, camera_visible).changed() {
+                            events.push(SceneEvent::MaterialChanged(uuid));
+                        }
+                        let toggled = object_linking_ui(ui, uuid, &sources);
+                        links.extend(toggled.into_iter().map(|(s, lit)| (s, uuid, lit)));
                         ui.horizontal(|ui| {
-                            ui.label("Center");
-                            responses.extend([
-                                ui.add(egui::DragValue::new(&mut sphere.center.x).speed(0.1)),
-                                ui.add(egui::DragValue::new(&mut sphere.center.y).speed(0.1)),
-                                ui.add(egui::DragValue::new(&mut sphere.center.z).speed(0.1)),
-                            ]);
-                        });
-                        ui.horizontal(|ui| {
-                            ui.label("Radius");
-                            responses
-                                .push(ui.add(egui::DragValue::new(&mut sphere.radius).speed(0.1)));
-                        });
-                        ui.horizontal(|ui| {
-                            ui.label("Albedo");
-                            responses.extend([
-                                ui.add(egui::DragValue::new(&mut sphere.albedo.x)),
-                                ui.add(egui::DragValue::new(&mut sphere.albedo.y)),
-                                ui.add(egui::DragValue::new(&mut sphere.albedo.z)),
-                            ]);
-
-                            let mut color: [f32; 3] = sphere.albedo.into();
-                            responses.push(ui.color_edit_button_rgb(&mut color));
-                            sphere.albedo = color.into();
-                        });
-                        ui.horizontal(|ui| {
-                            ui.label("Material");
-                            responses.extend([
-                                ui.radio_value(&mut sphere.material, Material::Diffuse, "Diffuse"),
-                                ui.radio_value(&mut sphere.material, Material::Metal, "Metal"),
-                                ui.radio_value(
-                                    &mut sphere.material,
-                                    Material::Dielectric,
-                                    "Dielectric",
-                                ),
-                            ]);
+                            let is_selected = self.selection.contains(&uuid);
+                            if ui
+                                .selectable_label(is_selected, "Select")
+                                .on_hover_text(
+                                    "Move, turn and scale the instance with the gizmo, along with \
+                                the rest of the selection while Ctrl is held",
+                                )
+                                .clicked()
+                            {
+                                selected = Some(uuid);
+                            }
+                            if ui.button("Remove").clicked() {
+                                removed = Some(uuid);
+                            }
                         });
                     });
             }
+            if moved {
+                self.update_tlas();
+            }
+            if let Some(uuid) = selected {
+                let extend = ui.input(|input| input.modifiers.ctrl);
+                self.select(uuid, extend);
+            }
+            if let Some(uuid) = removed {
+                self.remove_instance(uuid);
+            }
+        });
+
+        for (source, object, lit) in links {
+            self.set_illuminates(source, object, lit);
         }
+        events.extend(self.render_selection_ui(context, material_preview));
 
-        if responses.iter().any(|r| r.changed()) {
-            renderer.progressive_rendering.reset_ready_samples();
+        for event in events {
+            match event {
+                SceneEvent::ObjectRenamed(uuid) => self.make_name_unique(uuid),
+                SceneEvent::MaterialEdited(id) => self.materials.make_name_unique(id),
+                _ => {}
+            }
+            self.publish(event);
         }
     }
 
-    pub fn hit_closest_sphere(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut closest_so_far = t_max;
-        let mut closest_hit: Option<HitRecord> = None;
+    /// Returns the closest sphere or triangle `ray` hits.
+    pub fn raycast(&self, ray: &Ray) -> Option<Hit> {
+        self.raycast_where(ray, |_| true)
+    }
+
+    /// Returns where `point` would land if dropped straight down onto the scene.
+    pub fn drop_to_ground(&self, point: Vector3<f32>) -> Option<Vector3<f32>> {
+        let ray = Ray {
+            origin: point,
+            direction: -Vector3::unit_y(),
+        };
+
+        self.raycast(&ray).map(|hit| hit.point)
+    }
+
+    /// Returns the closest triangle, or sphere `include` accepts the UUID of, that `ray` hits.
+    fn raycast_where(&self, ray: &Ray, include: impl Fn(Uuid) -> bool) -> Option<Hit> {
+        let sphere_hit = self
+            .spheres()
+            .into_iter()
+            .filter(|s| include(s.id.0))
+            .filter_map(|s| {
+                let center = s.transform.position;
+                let t = geometry::ray_sphere(ray, center, s.shape.radius, RAYCAST_T_MIN, f32::MAX)?;
+                Some((t, s.id.0, center))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        let t_max = sphere_hit.map_or(f32::MAX, |(t, ..)| t);
+        if let Some((instance, _, hit)) = self.hit_closest_triangle(ray, RAYCAST_T_MIN, t_max) {
+            return Some(Hit {
+                object: instance.id.0,
+                point: hit.point,
+                normal: hit.normal,
+            });
+        }
 
-        for sphere in self.spheres.iter() {
-            if let Some(hit) = sphere.hit(ray, t_min, closest_so_far) {
-                closest_so_far = hit.t;
-                closest_hit = Some(hit);
+        sphere_hit.map(|(t, object, center)| {
+            let point = ray.at(t);
+            Hit {
+                object,
+                point,
+                normal: (point - center).normalize(),
             }
+        })
+    }
+
+    /// Returns the closest triangle `ray` hits within `t_min..t_max`, the instance it's placed
+    /// by, and where it hits it.
+    pub fn hit_closest_triangle(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(InstanceQueryReadOnlyItem<'_>, &Triangle, SurfaceHit)> {
+        let instances = self.instances();
+        let (_, (index, triangle, hit)) =
+            self.tlas
+                .closest_hit_with(ray, t_min, t_max, |index, t_max| {
+                    let instance = &instances[index];
+                    let mesh = &self.meshes[instance.mesh.mesh];
+                    let world_to_object = instance.transform.world_to_object();
+                    // Left unnormalized, so distances along it are the same as along `ray`
+                    let local_ray = Ray {
+                        origin: (world_to_object * ray.origin.extend(1.0)).truncate(),
+                        direction: (world_to_object * ray.direction.extend(0.0)).truncate(),
+                    };
+                    let (triangle, hit) =
+                        mesh.bvh
+                            .closest_hit(&mesh.triangles, &local_ray, t_min, t_max)?;
+                    let triangle = &mesh.triangles[triangle];
+
+                    let mut surface_hit = triangle.surface_hit(&local_ray, hit);
+                    surface_hit.point = ray.at(hit.t);
+                    // Normals transform by the inverse transpose
+                    surface_hit.normal = (world_to_object.transpose()
+                        * surface_hit.normal.extend(0.0))
+                    .truncate()
+                    .normalize();
+                    Some((hit.t, (index, triangle, surface_hit)))
+                })?;
+
+        let instance = instances.into_iter().nth(index)?;
+        Some((instance, triangle, hit))
+    }
+}
+
+/// Edits where an instance is placed, returning whether it moved.
+fn instance_ui(ui: &mut egui::Ui, instance: &mut Transform) -> bool {
+    let mut moved: Vec<Response> = Vec::new();
+
+    ui.horizontal(|ui| {
+        ui.label("Position");
+        moved.extend([
+            ui.add(egui::DragValue::new(&mut instance.position.x).speed(0.1)),
+            ui.add(egui::DragValue::new(&mut instance.position.y).speed(0.1)),
+            ui.add(egui::DragValue::new(&mut instance.position.z).speed(0.1)),
+        ]);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Rotation");
+        moved.extend([
+            ui.add(egui::DragValue::new(&mut instance.rotation.x).suffix("°")),
+            ui.add(egui::DragValue::new(&mut instance.rotation.y).suffix("°")),
+            ui.add(egui::DragValue::new(&mut instance.rotation.z).suffix("°")),
+        ]);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Scale");
+        moved.extend([
+            ui.add(egui::DragValue::new(&mut instance.scale.x).speed(0.01)),
+            ui.add(egui::DragValue::new(&mut instance.scale.y).speed(0.01)),
+            ui.add(egui::DragValue::new(&mut instance.scale.z).speed(0.01)),
+        ]);
+    });
+
+    moved.iter().any(|r| r.changed())
+}
+
+/// Edits the name of `uuid`, returning a rename once it's been changed and the field is left,
+/// so it's only made unique then rather than while it's typed.
+fn name_ui(ui: &mut egui::Ui, name: &mut String, uuid: Uuid) -> Option<SceneEvent> {
+    name_edited(ui, name).then_some(SceneEvent::ObjectRenamed(uuid))
+}
+
+/// Edits `name`, returning whether it changed once the field is left.
+fn name_edited(ui: &mut egui::Ui, name: &mut String) -> bool {
+    ui.horizontal(|ui| {
+        ui.label("Name");
+        let response = ui.text_edit_singleline(name);
+        // What the name was when the field was focused
+        if response.gained_focus() {
+            ui.data_mut(|data| data.insert_temp(response.id, name.clone()));
+        }
+        if !response.lost_focus() {
+            return false;
         }
+        let before = ui.data_mut(|data| data.get_temp::<String>(response.id));
+        before.as_ref() != Some(name)
+    })
+    .inner
+}
+
+/// Edits `sphere` and the material of `materials` it's made of, returning what changed.
+fn sphere_ui(
+    ui: &mut egui::Ui,
+    sphere: &mut SphereQueryItem,
+    materials: &mut MaterialLibrary,
+) -> Vec<SceneEvent> {
+    let mut events = Vec::new();
+    let mut moved: Vec<Response> = Vec::new();
+    let mut material: Vec<Response> = Vec::new();
+    let uuid = sphere.id.0;
+    let surface = &mut *sphere.surface;
 
-        closest_hit
+    events.extend(name_ui(ui, &mut sphere.name.0, uuid));
+
+    ui.horizontal(|ui| {
+        ui.label("Center");
+        moved.extend([
+            ui.add(egui::DragValue::new(&mut sphere.transform.position.x).speed(0.1)),
+            ui.add(egui::DragValue::new(&mut sphere.transform.position.y).speed(0.1)),
+            ui.add(egui::DragValue::new(&mut sphere.transform.position.z).speed(0.1)),
+        ]);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Radius");
+        moved.push(ui.add(egui::DragValue::new(&mut sphere.shape.radius).speed(0.1)));
+    });
+    let before = surface.material;
+    ui.horizontal(|ui| {
+        ui.label("Material");
+        let selected = materials
+            .get(surface.material)
+            .map_or("Missing", |material| material.name.as_str())
+            .to_string();
+        egui::ComboBox::from_id_source((uuid, "material"))
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for (id, material) in materials.iter() {
+                    ui.selectable_value(&mut surface.material, id, &material.name);
+                }
+            });
+
+        let has_room = materials.len() < MAX_NUMBER_OF_MATERIALS as usize;
+        if ui
+            .add_enabled(has_room, egui::Button::new("Copy"))
+            .on_hover_text("Make the sphere of a copy of the material, to edit on its own")
+            .clicked()
+        {
+            if let Some(copy) = materials.get(surface.material).cloned() {
+                surface.material = materials.add(copy);
+                events.push(SceneEvent::MaterialEdited(surface.material));
+            }
+        }
+    });
+    if surface.material != before {
+        events.push(SceneEvent::MaterialChanged(uuid));
+    }
+    if let Some(shared) = materials.get_mut(surface.material) {
+        ui.indent((uuid, "shared material"), |ui| {
+            events.extend(library_material_ui(ui, shared, surface.material));
+        });
+        if matches!(shared.material, Material::Emissive { .. }) {
+            material.push(camera_visible_ui(ui, &mut sphere.emitter.camera_visible));
+        }
+    }
+    ui.horizontal(|ui| {
+        ui.label("Albedo expression");
+        let mut source = surface
+            .albedo_expression
+            .as_ref()
+            .map_or(String::new(), |expression| expression.source().to_string());
+        let response = ui
+            .add_enabled(
+                surface.material_graph.is_none(),
+                egui::TextEdit::singleline(&mut source).hint_text("0.5 + 0.5 * sin(8 * y + t)"),
+            )
+            .on_hover_text(
+                "Multiplies the albedo by one value, or three separated by commas for a color, \
+                of x, y and z relative to the center, the normal's nx, ny and nz, the texture \
+                coordinates u and v, and the time t. Values used more than once can be named \
+                first, as in \"a = noise(x, y, z); a, a * a, 1\". Functions: sin cos abs floor \
+                fract sqrt min max pow step mix clamp smoothstep noise canvas_r canvas_g \
+                canvas_b",
+            );
+        if response.changed() {
+            surface.albedo_expression =
+                (!source.trim().is_empty()).then(|| Expression::new(source));
+        }
+        material.push(response);
+    });
+    if let Some(error) = surface
+        .albedo_expression
+        .as_ref()
+        .and_then(Expression::error)
+    {
+        ui.colored_label(ui.visuals().error_fg_color, error.to_string());
     }
 
-    pub fn update(&mut self) -> Option<()> {
-        let selected_sphere = self.selected_sphere?;
-        let mut spheres_iter = self.spheres.iter_mut();
-        let sphere = spheres_iter.find(|s| s.uuid == selected_sphere)?;
-        let gizmo = spheres_iter.find(|s| s.label == Some("selected_sphere_gizmo".to_string()))?;
+    let mut graph_changed = false;
+    ui.collapsing("Material graph", |ui| {
+        let Some(graph) = &mut surface.material_graph else {
+            if ui
+                .button("Build with nodes")
+                .on_hover_text("Replace the albedo expression with one built from a node graph")
+                .clicked()
+            {
+                let graph = MaterialGraph::new();
+                surface.albedo_expression = Some(Expression::new(graph.compile()));
+                surface.material_graph = Some(graph);
+                graph_changed = true;
+            }
+            return;
+        };
+        let (compiled, released) = graph.render_ui(ui, (uuid, "material graph"));
+        if compiled {
+            surface.albedo_expression = Some(Expression::new(graph.compile()));
+            // The BSDF is baked into the library, shared with anything that looks the same
+            if let Some(bsdf) = graph.surface() {
+                let has_room = materials.len() < MAX_NUMBER_OF_MATERIALS as usize;
+                match materials.find_like(&bsdf) {
+                    Some(id) => surface.material = id,
+                    None if has_room => {
+                        surface.material = materials.add(bsdf);
+                        events.push(SceneEvent::MaterialEdited(surface.material));
+                    }
+                    None => {}
+                }
+            }
+        }
+        graph_changed = compiled || released;
+        if ui
+            .button("Remove graph")
+            .on_hover_text("Keep the expression the graph built, to edit as text")
+            .clicked()
+        {
+            surface.material_graph = None;
+            graph_changed = true;
+        }
+    });
 
-        gizmo.center = sphere.center;
-        gizmo.radius = sphere.radius + 0.01;
+    if moved.iter().any(|r| r.changed()) {
+        events.push(SceneEvent::ObjectMoved(uuid));
+    }
+    if graph_changed || material.iter().any(|r| r.changed()) {
+        events.push(SceneEvent::MaterialChanged(uuid));
+    }
+    events
+}
 
-        Some(())
+/// Edits the material `id`, returning an edit once it changed. Every object made of it changes
+/// with it.
+fn library_material_ui(
+    ui: &mut egui::Ui,
+    material: &mut LibraryMaterial,
+    id: MaterialId,
+) -> Option<SceneEvent> {
+    let mut changed = name_edited(ui, &mut material.name);
+    let mut responses: Vec<Response> = Vec::new();
+    ui.horizontal(|ui| {
+        ui.label("Albedo");
+        responses.extend([
+            ui.add(egui::DragValue::new(&mut material.albedo.x)),
+            ui.add(egui::DragValue::new(&mut material.albedo.y)),
+            ui.add(egui::DragValue::new(&mut material.albedo.z)),
+        ]);
+
+        let mut color: [f32; 3] = material.albedo.into();
+        responses.push(ui.color_edit_button_rgb(&mut color));
+        material.albedo = color.into();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Kind");
+        responses.extend(material.material.render_ui(ui));
+    });
+    if material.material == Material::Metal {
+        responses.push(
+            ui.add(egui::Slider::new(&mut material.roughness, 0.0..=1.0).text("roughness"))
+                .on_hover_text("Blurs reflections, from a mirror at zero to nearly matte at one"),
+        );
     }
+    if material.material == Material::Dielectric {
+        ui.horizontal(|ui| {
+            responses.push(
+                ui.add(
+                    egui::DragValue::new(&mut material.ior)
+                        .speed(0.01)
+                        .clamp_range(1.0..=4.0)
+                        .prefix("IOR "),
+                )
+                .on_hover_text("How strongly the material bends light passing into it"),
+            );
+            for (name, ior) in IOR_PRESETS {
+                let mut preset = ui.selectable_label(material.ior == ior, name);
+                if preset.clicked() && material.ior != ior {
+                    material.ior = ior;
+                    preset.mark_changed();
+                }
+                responses.push(preset);
+            }
+        });
+    }
+    changed |= responses.iter().any(|r| r.changed());
+    changed.then_some(SceneEvent::MaterialEdited(id))
+}
+
+/// Toggles whether camera rays see an emitter, leaving the light it casts.
+fn camera_visible_ui(ui: &mut egui::Ui, camera_visible: &mut bool) -> Response {
+    ui.checkbox(camera_visible, "Visible to camera")
+        .on_hover_text(
+            "Hide the emitter from the camera while it still lights the scene and shows in \
+        reflections, like a studio light out of shot",
+        )
 }
 
+fn default_camera_visible() -> bool {
+    true
+}
+
+/// `items` sorted by the [`Order`] `order` reads from each.
+fn in_order<T>(items: impl Iterator<Item = T>, order: impl Fn(&T) -> Order) -> Vec<T> {
+    let mut items = items.collect::<Vec<_>>();
+    items.sort_unstable_by_key(order);
+    items
+}
+
+/// `name`, or `default` if it's empty.
+fn or<'a>(name: &'a str, default: &'a str) -> &'a str {
+    if name.is_empty() {
+        default
+    } else {
+        name
+    }
+}
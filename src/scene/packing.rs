@@ -0,0 +1,153 @@
+use cgmath::{InnerSpace, Vector3};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::MAX_NUMBER_OF_SPHERES;
+
+use super::{Material, Scene, Sphere, SphereDescriptor};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PackingMode {
+    /// Anywhere inside the bounds.
+    Volume,
+    /// Resting on whatever is below the bounds.
+    Surface,
+}
+
+/// Fills a box with non-overlapping spheres of random radius by Poisson-disc dart throwing,
+/// either floating in the box or dropped onto the surface below it.
+pub struct SpherePacking {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    min_radius: f32,
+    max_radius: f32,
+    count: u32,
+    mode: PackingMode,
+}
+
+impl SpherePacking {
+    /// Rejected candidates per requested sphere before giving up on a crowded box.
+    const ATTEMPTS_PER_SPHERE: u32 = 30;
+    /// Random materials added per generation for the spheres to share, which keeps packings
+    /// from filling the scene's material library.
+    const PALETTE_SIZE: usize = 8;
+
+    pub fn new() -> Self {
+        Self {
+            min: Vector3::new(-3.0, -0.5, -4.0),
+            max: Vector3::new(3.0, 2.0, 2.0),
+            min_radius: 0.05,
+            max_radius: 0.25,
+            count: 64,
+            mode: PackingMode::Surface,
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, scene: &mut Scene) {
+        ui.collapsing("Sphere packing", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Min");
+                ui.add(egui::DragValue::new(&mut self.min.x).speed(0.1));
+                ui.add(egui::DragValue::new(&mut self.min.y).speed(0.1));
+                ui.add(egui::DragValue::new(&mut self.min.z).speed(0.1));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max");
+                ui.add(egui::DragValue::new(&mut self.max.x).speed(0.1));
+                ui.add(egui::DragValue::new(&mut self.max.y).speed(0.1));
+                ui.add(egui::DragValue::new(&mut self.max.z).speed(0.1));
+            });
+            ui.add(egui::Slider::new(&mut self.min_radius, 0.01..=2.0).text("min radius"));
+            ui.add(egui::Slider::new(&mut self.max_radius, 0.01..=2.0).text("max radius"));
+            ui.add(egui::Slider::new(&mut self.count, 1..=MAX_NUMBER_OF_SPHERES).text("spheres"));
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.mode, PackingMode::Volume, "Volume");
+                ui.radio_value(&mut self.mode, PackingMode::Surface, "Surface");
+            });
+
+            let capacity = (MAX_NUMBER_OF_SPHERES as usize).saturating_sub(scene.sphere_count());
+            ui.label(format!("Room for {} more spheres", capacity));
+            if ui.button("Generate").clicked() {
+                self.generate(scene);
+            }
+        });
+    }
+
+    /// Adds up to `count` spheres that don't overlap each other or the scene's spheres.
+    fn generate(&self, scene: &mut Scene) {
+        let mut rng = rand::thread_rng();
+        let capacity = (MAX_NUMBER_OF_SPHERES as usize).saturating_sub(scene.sphere_count());
+        let count = (self.count as usize).min(capacity);
+        let (min_radius, max_radius) = if self.min_radius < self.max_radius {
+            (self.min_radius, self.max_radius)
+        } else {
+            (self.max_radius, self.min_radius)
+        };
+
+        let mut placed = Vec::new();
+        for _ in 0..count as u32 * Self::ATTEMPTS_PER_SPHERE {
+            if placed.len() == count {
+                break;
+            }
+
+            let radius = rng.gen_range(min_radius..=max_radius);
+            let Some(center) = self.candidate(scene, radius, &mut rng) else {
+                continue;
+            };
+
+            let overlaps = scene
+                .spheres()
+                .iter()
+                .map(|s| (s.transform.position, s.shape.radius))
+                .chain(placed.iter().copied())
+                .any(|(other, other_radius)| (center - other).magnitude() < radius + other_radius);
+            if !overlaps {
+                placed.push((center, radius));
+            }
+        }
+
+        if placed.is_empty() {
+            return;
+        }
+        let kinds = [Material::Diffuse, Material::Metal, Material::Dielectric];
+        let palette = (0..Self::PALETTE_SIZE)
+            .map(|_| {
+                let albedo = Vector3::new(rng.gen(), rng.gen(), rng.gen());
+                let kind = *kinds.choose(&mut rng).unwrap();
+                scene.materials.find_or_add(albedo, kind)
+            })
+            .collect::<Vec<_>>();
+        for (center, radius) in placed {
+            let sphere = Sphere::new(SphereDescriptor {
+                center,
+                radius,
+                material: *palette.choose(&mut rng).unwrap(),
+            });
+            scene.push_sphere(sphere);
+        }
+    }
+
+    /// A random center for a sphere of `radius` that fits in the bounds, or on the surface
+    /// below them.
+    fn candidate(&self, scene: &Scene, radius: f32, rng: &mut impl Rng) -> Option<Vector3<f32>> {
+        let mut random_axis = |axis: usize| {
+            let (min, max) = (self.min[axis] + radius, self.max[axis] - radius);
+            (min < max).then(|| rng.gen_range(min..max))
+        };
+        let x = random_axis(0)?;
+        let z = random_axis(2)?;
+
+        match self.mode {
+            PackingMode::Volume => Some(Vector3::new(x, random_axis(1)?, z)),
+            PackingMode::Surface => {
+                let ground = scene.drop_to_ground(Vector3::new(x, self.max.y, z))?;
+                Some(ground + Vector3::unit_y() * radius)
+            }
+        }
+    }
+}
+
+impl Default for SpherePacking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
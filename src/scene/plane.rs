@@ -1,19 +1,17 @@
-use cgmath::{InnerSpace, Vector3};
+use cgmath::{InnerSpace, Vector2, Vector3};
 
 use crate::model::Triangle;
 
-use super::Material;
+use super::MaterialId;
 
 pub struct Plane {
     pub q: Vector3<f32>,
     pub u: Vector3<f32>,
     pub v: Vector3<f32>,
-    pub albedo: Vector3<f32>,
-    pub material: Material,
 }
 
 impl Plane {
-    pub fn triangles(self) -> Vec<Triangle> {
+    pub fn triangles(self, material: MaterialId) -> Vec<Triangle> {
         let normal = self.normal();
         let triangle1 = Triangle {
             a: self.q,
@@ -22,8 +20,10 @@ impl Plane {
             na: normal,
             nb: normal,
             nc: normal,
-            albedo: self.albedo,
-            material: self.material,
+            ta: Vector2::new(0.0, 0.0),
+            tb: Vector2::new(1.0, 0.0),
+            tc: Vector2::new(0.0, 1.0),
+            material,
         };
 
         let triangle2 = Triangle {
@@ -33,8 +33,10 @@ impl Plane {
             na: normal,
             nb: normal,
             nc: normal,
-            albedo: self.albedo,
-            material: self.material,
+            ta: Vector2::new(1.0, 1.0),
+            tb: Vector2::new(1.0, 0.0),
+            tc: Vector2::new(0.0, 1.0),
+            material,
         };
 
         vec![triangle1, triangle2]
@@ -43,5 +45,13 @@ impl Plane {
     pub fn normal(&self) -> Vector3<f32> {
         self.u.cross(self.v).normalize()
     }
-}
 
+    pub fn corners(&self) -> [Vector3<f32>; 4] {
+        [
+            self.q,
+            self.q + self.u,
+            self.q + self.u + self.v,
+            self.q + self.v,
+        ]
+    }
+}
@@ -0,0 +1,381 @@
+use std::path::Path;
+
+use cgmath::Vector3;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    jobs::{JobHandle, Jobs},
+    MAX_NUMBER_OF_SPHERES,
+};
+
+use super::{
+    Material, MeshRef, Scene, SceneEvent, Sphere, SphereDescriptor, SphereShape, Transform,
+};
+
+/// Per-frame positions of a set of points exported from a simulation, e.g.
+/// `{ "fps": 24, "radius": 0.1, "frames": [[[0, 1, 0], ...], ...] }`, optionally with
+/// `"rotations"` and `"scales"` laid out like `frames`.
+#[derive(Debug, Deserialize)]
+pub struct PointCache {
+    pub fps: f32,
+    /// The radius of the spheres created for the points.
+    #[serde(default = "PointCache::default_radius")]
+    pub radius: f32,
+    pub frames: Vec<Vec<[f32; 3]>>,
+    /// Euler angles in degrees for each point of each frame, applied around X, then Y, then Z,
+    /// or none to leave the objects turned as they are.
+    #[serde(default)]
+    pub rotations: Vec<Vec<[f32; 3]>>,
+    /// Factors for each point of each frame that the size of its object when bound is scaled by,
+    /// or none to leave it.
+    #[serde(default)]
+    pub scales: Vec<Vec<f32>>,
+}
+
+impl PointCache {
+    fn default_radius() -> f32 {
+        0.1
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let cache: Self = serde_json::from_slice(&std::fs::read(path)?)?;
+
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        if !(cache.fps.is_finite() && cache.fps > 0.0) {
+            return Err(invalid("fps must be positive"));
+        }
+        if !(cache.radius.is_finite() && cache.radius > 0.0) {
+            return Err(invalid("radius must be positive"));
+        }
+        let points = cache
+            .frames
+            .first()
+            .ok_or_else(|| invalid("no frames"))?
+            .len();
+        if cache.frames.iter().any(|frame| frame.len() != points) {
+            return Err(invalid("every frame must have the same number of points"));
+        }
+        fn matches_frames<T>(track: &[Vec<T>], frames: usize, points: usize) -> bool {
+            track.is_empty()
+                || track.len() == frames && track.iter().all(|frame| frame.len() == points)
+        }
+        if !matches_frames(&cache.rotations, cache.frames.len(), points) {
+            return Err(invalid(
+                "rotations must have a frame and point for each position",
+            ));
+        }
+        if !matches_frames(&cache.scales, cache.frames.len(), points) {
+            return Err(invalid(
+                "scales must have a frame and point for each position",
+            ));
+        }
+
+        // Spheres and instances are placed from these as they are, without Scene::validate
+        let finite =
+            |track: &[Vec<[f32; 3]>]| track.iter().flatten().flatten().all(|v| v.is_finite());
+        if !finite(&cache.frames) {
+            return Err(invalid("positions must be finite"));
+        }
+        if !finite(&cache.rotations) {
+            return Err(invalid("rotations must be finite"));
+        }
+        if !cache
+            .scales
+            .iter()
+            .flatten()
+            .all(|scale| scale.is_finite() && *scale > 0.0)
+        {
+            return Err(invalid("scales must be positive"));
+        }
+
+        Ok(cache)
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.frames[0].len()
+    }
+}
+
+/// What a [`PointCachePlayer`] binds the points of a cache to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BindTarget {
+    /// A sphere created for each point.
+    NewSpheres,
+    /// The selected spheres and mesh instances, in the order they were selected.
+    Selection,
+}
+
+/// An object driven by a point of the cache.
+struct Binding {
+    uuid: Uuid,
+    /// The sphere's radius or the instance's scale when bound, which the cache's scales multiply.
+    scale: Vector3<f32>,
+}
+
+/// Plays a loaded [`PointCache`] back on spheres created for its points, or on existing spheres
+/// and mesh instances.
+pub struct PointCachePlayer {
+    path: String,
+    error: Option<String>,
+    /// Parsing the cache in the background, along with its path.
+    loading: Option<JobHandle<(String, std::io::Result<PointCache>)>>,
+    cache: Option<PointCache>,
+    target: BindTarget,
+    /// The objects driven by the cache, one per point.
+    bindings: Vec<Binding>,
+    /// Whether the bound spheres were created for the cache, and are removed when it's bound
+    /// again.
+    created: bool,
+    frame: usize,
+    playing: bool,
+    elapsed: f32,
+}
+
+impl PointCachePlayer {
+    pub fn new() -> Self {
+        Self {
+            path: String::new(),
+            error: None,
+            loading: None,
+            cache: None,
+            target: BindTarget::NewSpheres,
+            bindings: Vec::new(),
+            created: false,
+            frame: 0,
+            playing: false,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, scene: &mut Scene, jobs: &mut Jobs) {
+        ui.collapsing("Point cache", |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.path).hint_text("cache.json"));
+                if ui
+                    .add_enabled(self.loading.is_none(), egui::Button::new("Load"))
+                    .clicked()
+                {
+                    let path = self.path.clone();
+                    self.loading = Some(jobs.spawn(format!("Loading {}", path), move |_| {
+                        let cache = PointCache::load(Path::new(&path));
+                        Some((path, cache))
+                    }));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.target, BindTarget::NewSpheres, "New spheres");
+                ui.radio_value(&mut self.target, BindTarget::Selection, "Selection")
+                    .on_hover_text(
+                        "Drive the selected spheres and mesh instances, in the order they were \
+                        selected, turning and scaling instances as well",
+                    );
+                if ui
+                    .add_enabled(self.cache.is_some(), egui::Button::new("Bind"))
+                    .clicked()
+                {
+                    self.bind(scene);
+                }
+            });
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            let Some(cache) = &self.cache else {
+                return;
+            };
+            ui.label(format!(
+                "{} points bound, {} frames at {} fps",
+                self.bindings.len(),
+                cache.frames.len(),
+                cache.fps
+            ));
+
+            let last_frame = cache.frames.len() - 1;
+            let fps = cache.fps;
+            ui.horizontal(|ui| {
+                let label = if self.playing { "Pause" } else { "Play" };
+                if ui.button(label).clicked() {
+                    self.playing = !self.playing;
+                }
+                let mut frame = self.frame;
+                if ui
+                    .add(egui::Slider::new(&mut frame, 0..=last_frame).text("frame"))
+                    .changed()
+                {
+                    self.elapsed = frame as f32 / fps;
+                    self.seek(scene, frame);
+                }
+            });
+        });
+    }
+
+    /// Forgets the cache when the scene its objects were in is replaced.
+    pub fn handle_event(&mut self, event: &SceneEvent) {
+        if *event == SceneEvent::SceneReplaced {
+            self.cache = None;
+            self.bindings.clear();
+            self.created = false;
+            self.playing = false;
+        }
+    }
+
+    /// Binds a cache that finished loading and advances playback by `delta` seconds, looping at
+    /// the end of the cache.
+    pub fn update(&mut self, scene: &mut Scene, delta: f32) {
+        if let Some(loading) = &self.loading {
+            if let Some((path, result)) = loading.try_take() {
+                self.loading = None;
+                match result {
+                    Ok(cache) => {
+                        self.cache = Some(cache);
+                        self.bind(scene);
+                    }
+                    Err(error) => {
+                        log::warn!("Failed to load point cache {}: {}", path, error);
+                        self.error = Some(error.to_string());
+                    }
+                }
+            } else if loading.is_finished() {
+                self.loading = None;
+            }
+        }
+
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        if !self.playing {
+            return;
+        }
+
+        self.elapsed += delta;
+        let frame = (self.elapsed * cache.fps) as usize % cache.frames.len();
+        if frame != self.frame {
+            self.seek(scene, frame);
+        }
+    }
+
+    /// Binds the cache's points to the objects [`Self::target`] picks, and shows its first
+    /// frame.
+    fn bind(&mut self, scene: &mut Scene) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        self.error = None;
+
+        // Replace the spheres created for the cache when it was last bound
+        let previous = std::mem::take(&mut self.bindings);
+        if std::mem::take(&mut self.created) {
+            for binding in previous {
+                scene.despawn(binding.uuid);
+                scene.publish(SceneEvent::ObjectRemoved(binding.uuid));
+            }
+        }
+
+        match self.target {
+            BindTarget::NewSpheres => {
+                let capacity =
+                    (MAX_NUMBER_OF_SPHERES as usize).saturating_sub(scene.sphere_count());
+                if cache.point_count() > capacity {
+                    log::warn!(
+                        "Point cache has {} points, only binding the first {}",
+                        cache.point_count(),
+                        capacity
+                    );
+                }
+
+                let material = scene
+                    .materials
+                    .find_or_add(Vector3::new(0.8, 0.8, 0.8), Material::Diffuse);
+                self.bindings = cache.frames[0]
+                    .iter()
+                    .take(capacity)
+                    .map(|&center| {
+                        let mut sphere = Sphere::new(SphereDescriptor {
+                            center: center.into(),
+                            radius: cache.radius,
+                            material,
+                        });
+                        sphere.name = "Point".to_string();
+                        Binding {
+                            uuid: scene.push_sphere(sphere),
+                            scale: Vector3::new(cache.radius, cache.radius, cache.radius),
+                        }
+                    })
+                    .collect();
+                self.created = true;
+            }
+            BindTarget::Selection => {
+                self.bindings = scene
+                    .selection
+                    .iter()
+                    .filter_map(|&uuid| {
+                        let scale = if let Some(shape) = scene.get::<SphereShape>(uuid) {
+                            Vector3::new(shape.radius, shape.radius, shape.radius)
+                        } else {
+                            scene.get::<MeshRef>(uuid)?;
+                            scene.get::<Transform>(uuid)?.scale
+                        };
+                        Some(Binding { uuid, scale })
+                    })
+                    .take(cache.point_count())
+                    .collect();
+                if self.bindings.is_empty() {
+                    self.error = Some("Select spheres or mesh instances to bind".to_string());
+                } else if self.bindings.len() < cache.point_count() {
+                    log::warn!(
+                        "Point cache has {} points, only binding the first {} to the selection",
+                        cache.point_count(),
+                        self.bindings.len()
+                    );
+                }
+            }
+        }
+
+        self.elapsed = 0.0;
+        self.seek(scene, 0);
+    }
+
+    fn seek(&mut self, scene: &mut Scene, frame: usize) {
+        let Some(cache) = &self.cache else {
+            return;
+        };
+        self.frame = frame;
+        scene.frame = frame as u32;
+
+        let mut instances_moved = false;
+        for (point, binding) in self.bindings.iter().enumerate() {
+            let position = cache.frames[frame][point].into();
+            let rotation = cache.rotations.get(frame).map(|r| Vector3::from(r[point]));
+            let scale = cache
+                .scales
+                .get(frame)
+                .map_or(binding.scale, |s| binding.scale * s[point]);
+            let is_sphere = if let Some(shape) = scene.get_mut::<SphereShape>(binding.uuid) {
+                shape.radius = scale.x;
+                true
+            } else {
+                false
+            };
+            let Some(transform) = scene.get_mut::<Transform>(binding.uuid) else {
+                continue;
+            };
+            transform.position = position;
+            if !is_sphere {
+                transform.rotation = rotation.unwrap_or(transform.rotation);
+                transform.scale = scale;
+                instances_moved = true;
+            }
+            scene.publish(SceneEvent::ObjectMoved(binding.uuid));
+        }
+        if instances_moved {
+            scene.update_tlas();
+        }
+    }
+}
+
+impl Default for PointCachePlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
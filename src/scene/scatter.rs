@@ -0,0 +1,158 @@
+use std::f32::consts::TAU;
+
+use cgmath::{Deg, InnerSpace, Matrix3, Quaternion, Rad, Vector3};
+use rand::Rng;
+use uuid::Uuid;
+
+use crate::geometry;
+
+use super::{MeshInstance, Ray, Scene};
+
+/// Paints instances of a source mesh onto the surface under the cursor while the left mouse
+/// button is held, each turned by a random angle about the surface and scaled by a random
+/// factor.
+pub struct ScatterBrush {
+    pub enabled: bool,
+    /// The UUID of the mesh that's instanced.
+    source: Option<Uuid>,
+    /// The minimum distance between instances placed in one stroke.
+    spacing: f32,
+    /// How much each instance's scale may differ from one, as a fraction of it.
+    scale_jitter: f32,
+    /// How far in degrees each instance may lean away from its up axis, on top of the random
+    /// turn about it.
+    tilt_jitter: f32,
+    /// Whether instances stand on the surface along its normal rather than upright.
+    align_to_normal: bool,
+    painting: bool,
+    last_placed: Option<Vector3<f32>>,
+    /// The instances placed in the current stroke, which aren't painted onto in turn.
+    placed: Vec<Uuid>,
+}
+
+impl ScatterBrush {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            source: None,
+            spacing: 0.5,
+            scale_jitter: 0.3,
+            tilt_jitter: 0.0,
+            align_to_normal: true,
+            painting: false,
+            last_placed: None,
+            placed: Vec::new(),
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, scene: &Scene) {
+        ui.collapsing("Scatter brush", |ui| {
+            ui.checkbox(&mut self.enabled, "enabled").on_hover_text(
+                "Hold the left mouse button over the scene to paint instances of the source mesh",
+            );
+
+            let selected_text = scene
+                .meshes
+                .iter()
+                .find(|mesh| Some(mesh.uuid) == self.source)
+                .map_or("None", |mesh| mesh.name.as_str());
+            egui::ComboBox::from_label("source")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    for mesh in &scene.meshes {
+                        ui.selectable_value(&mut self.source, Some(mesh.uuid), &mesh.name);
+                    }
+                });
+
+            ui.add(egui::Slider::new(&mut self.spacing, 0.05..=5.0).text("spacing"));
+            ui.add(egui::Slider::new(&mut self.scale_jitter, 0.0..=0.9).text("scale jitter"));
+            ui.add(
+                egui::Slider::new(&mut self.tilt_jitter, 0.0..=90.0)
+                    .text("tilt jitter")
+                    .suffix("°"),
+            );
+            ui.checkbox(&mut self.align_to_normal, "align to surface")
+                .on_hover_text("Stand instances along the surface's normal instead of upright");
+        });
+    }
+
+    pub fn begin_stroke(&mut self, scene: &mut Scene, ray: &Ray) {
+        self.painting = true;
+        self.last_placed = None;
+        self.placed.clear();
+        self.paint(scene, ray);
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.painting = false;
+    }
+
+    /// Places an instance where `ray` hits the scene, if a stroke is in progress and the hit is
+    /// far enough from the previous instance.
+    pub fn paint(&mut self, scene: &mut Scene, ray: &Ray) {
+        if !self.painting {
+            return;
+        }
+        let Some(mesh) = scene
+            .meshes
+            .iter()
+            .position(|mesh| Some(mesh.uuid) == self.source)
+        else {
+            return;
+        };
+        let Some(hit) = scene.raycast(ray) else {
+            return;
+        };
+        if self.placed.contains(&hit.object) {
+            return;
+        }
+        let (point, normal) = (hit.point, hit.normal);
+
+        if self
+            .last_placed
+            .is_some_and(|last| (point - last).magnitude() < self.spacing)
+        {
+            return;
+        }
+
+        // Triangles can be hit from either side
+        let normal = if normal.dot(ray.direction) > 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        let up = if self.align_to_normal {
+            normal
+        } else {
+            Vector3::unit_y()
+        };
+
+        let mut rng = rand::thread_rng();
+        let turn = Matrix3::from_angle_y(Rad(rng.gen_range(0.0..TAU)));
+        let lean_axis = Matrix3::from_angle_y(Rad(rng.gen_range(0.0..TAU))) * Vector3::unit_x();
+        let lean =
+            Matrix3::from_axis_angle(lean_axis, Deg(rng.gen_range(-1.0..=1.0) * self.tilt_jitter));
+        let stand = Matrix3::from(Quaternion::from_arc(Vector3::unit_y(), up, None));
+        let rotation = stand * lean * turn;
+        let scale = 1.0 + rng.gen_range(-1.0..=1.0) * self.scale_jitter;
+
+        // Rest the bottom of the mesh on the surface
+        let bounds = scene.meshes[mesh].bounds();
+        let bottom = if bounds.is_empty() { 0.0 } else { bounds.min.y };
+
+        let mut instance = MeshInstance::new(mesh);
+        instance.position = point - rotation * Vector3::new(0.0, bottom * scale, 0.0);
+        instance.rotation = geometry::matrix_to_euler(&rotation);
+        instance.scale = Vector3::new(scale, scale, scale);
+        if let Some(uuid) = scene.push_instance(instance) {
+            self.placed.push(uuid);
+            self.last_placed = Some(point);
+        }
+    }
+}
+
+impl Default for ScatterBrush {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,467 @@
+use cgmath::Vector3;
+use uuid::Uuid;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    expression::Expression, geometry::Aabb, MAX_NUMBER_OF_INSTANCES, MAX_NUMBER_OF_SPHERES,
+};
+
+use super::{
+    camera_visible_ui, instance_ui, name_ui, sphere_ui, InstanceQueryItem, MeshInstance, MeshRef,
+    Ray, Scene, SceneEvent, Sphere, SphereQueryItem, SphereQueryReadOnlyItem, SphereShape,
+    Transform,
+};
+
+/// How far duplicates are moved from what they're copies of, so both can be seen.
+const DUPLICATE_OFFSET: Vector3<f32> = Vector3::new(0.5, 0.0, 0.5);
+
+/// Where an object is, as the gizmo edits it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub position: Vector3<f32>,
+    /// Euler angles in degrees like [`Transform::rotation`], `None` for objects that look
+    /// the same however they're turned.
+    pub rotation: Option<Vector3<f32>>,
+    pub scale: Vector3<f32>,
+    /// Whether the object can only be scaled the same on every axis, like a sphere.
+    pub uniform_scale: bool,
+}
+
+impl Scene {
+    pub fn is_selected(&self, uuid: Uuid) -> bool {
+        self.selection.contains(&uuid)
+    }
+
+    /// Selects `uuid` in place of everything else, or toggles whether it's selected if `extend`.
+    pub fn select(&mut self, uuid: Uuid, extend: bool) {
+        if !extend {
+            self.selection.clear();
+        } else if self.is_selected(uuid) {
+            self.deselect(uuid);
+            return;
+        }
+        self.selection.push(uuid);
+    }
+
+    /// Selects `uuids` in place of everything else, or along with it if `extend`.
+    pub fn select_all(&mut self, uuids: impl IntoIterator<Item = Uuid>, extend: bool) {
+        if !extend {
+            self.selection.clear();
+        }
+        for uuid in uuids {
+            if !self.is_selected(uuid) {
+                self.selection.push(uuid);
+            }
+        }
+    }
+
+    pub fn deselect(&mut self, uuid: Uuid) {
+        self.selection.retain(|&selected| selected != uuid);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    /// Removes every selected sphere and instance.
+    pub fn remove_selected(&mut self) {
+        for uuid in self.selection.clone() {
+            self.remove_sphere(uuid);
+            self.remove_instance(uuid);
+        }
+    }
+
+    /// Copies every selected sphere and instance, materials and all, a little way off from
+    /// the originals, and selects the copies in their place. Spheres and instances are only
+    /// copied while there's room for more, and the ones skipped are logged.
+    pub fn duplicate_selected(&mut self) {
+        let mut duplicates = Vec::new();
+        let mut instances_added = false;
+        let mut skipped = 0;
+        for uuid in self.selection.clone() {
+            if let Some(mut sphere) = self.sphere(uuid) {
+                if self.sphere_count() >= MAX_NUMBER_OF_SPHERES as usize {
+                    skipped += 1;
+                    continue;
+                }
+                sphere.uuid = Uuid::new_v4();
+                sphere.center += DUPLICATE_OFFSET;
+                duplicates.push(self.push_sphere(sphere));
+            } else if let Some(mut instance) = self.instance(uuid) {
+                if self.instance_count() >= MAX_NUMBER_OF_INSTANCES as usize {
+                    skipped += 1;
+                    continue;
+                }
+                let duplicate = Uuid::new_v4();
+                instance.uuid = duplicate;
+                instance.name = self.unique_name(&instance.name);
+                instance.position += DUPLICATE_OFFSET;
+                self.spawn_instance(instance);
+                self.publish(SceneEvent::ObjectAdded(duplicate));
+                duplicates.push(duplicate);
+                instances_added = true;
+            }
+        }
+
+        if skipped > 0 {
+            log::warn!(
+                "Skipped duplicating {} objects, the scene has no room for more",
+                skipped
+            );
+        }
+        if instances_added {
+            self.update_tlas();
+        }
+        if !duplicates.is_empty() {
+            self.select_all(duplicates, false);
+        }
+    }
+
+    /// The most recently selected sphere, whose material is previewed.
+    pub fn active_sphere(&self) -> Option<SphereQueryReadOnlyItem<'_>> {
+        let &uuid = self
+            .selection
+            .iter()
+            .rev()
+            .find(|&&uuid| self.get::<SphereShape>(uuid).is_some())?;
+        let entity = self.entities[&uuid];
+        self.queries.spheres.get_manual(&self.world, entity).ok()
+    }
+
+    pub fn has_selected_spheres(&self) -> bool {
+        self.selection
+            .iter()
+            .any(|&uuid| self.get::<SphereShape>(uuid).is_some())
+    }
+
+    /// Where each selected object is, in the order they were selected. The gizmo sits at the
+    /// last one.
+    pub fn selected_placements(&self) -> Vec<(Uuid, Placement)> {
+        self.selection
+            .iter()
+            .filter_map(|&uuid| Some((uuid, self.placement(uuid)?)))
+            .collect()
+    }
+
+    pub fn placement(&self, uuid: Uuid) -> Option<Placement> {
+        let transform = self.get::<Transform>(uuid)?;
+        if let Some(shape) = self.get::<SphereShape>(uuid) {
+            return Some(Placement {
+                position: transform.position,
+                rotation: None,
+                scale: Vector3::new(1.0, 1.0, 1.0) * shape.radius,
+                uniform_scale: true,
+            });
+        }
+
+        Some(Placement {
+            position: transform.position,
+            rotation: Some(transform.rotation),
+            scale: transform.scale,
+            uniform_scale: false,
+        })
+    }
+
+    /// Moves, turns and scales each object as its placement says.
+    pub fn place(&mut self, placements: &[(Uuid, Placement)]) {
+        let mut instances_moved = false;
+        for &(uuid, placement) in placements {
+            if let Some(shape) = self.get_mut::<SphereShape>(uuid) {
+                shape.radius = placement.scale.x;
+            } else if self.get::<MeshRef>(uuid).is_some() {
+                instances_moved = true;
+            } else {
+                continue;
+            }
+            let Some(transform) = self.get_mut::<Transform>(uuid) else {
+                continue;
+            };
+            transform.position = placement.position;
+            if !placement.uniform_scale {
+                if let Some(rotation) = placement.rotation {
+                    transform.rotation = rotation;
+                }
+                transform.scale = placement.scale;
+            }
+            self.publish(SceneEvent::ObjectMoved(uuid));
+        }
+        if instances_moved {
+            self.update_tlas();
+        }
+    }
+
+    /// Moves [`Scene::camera`] back or forward until the selection fits in view, without turning
+    /// it.
+    pub fn frame_selection(&mut self) {
+        let spheres = self.spheres();
+        let spheres = spheres
+            .iter()
+            .filter(|s| self.is_selected(s.id.0))
+            .map(|s| s.shape.bounds(s.transform.position));
+        let instances = self.instances();
+        let instances = instances
+            .iter()
+            .filter(|i| self.is_selected(i.id.0))
+            .map(|i| i.transform.world_bounds(&self.meshes[i.mesh.mesh]));
+        let bounds = spheres
+            .chain(instances)
+            .fold(Aabb::from_points(std::iter::empty()), |a, b| a.union(&b));
+        if !bounds.is_empty() {
+            self.camera.frame(&bounds);
+        }
+    }
+
+    /// Moves each selected sphere down until it rests on whatever is below its center, other
+    /// than the rest of the selection.
+    pub fn drop_selected_to_ground(&mut self) {
+        let selection = self.selection.clone();
+        let landings = selection
+            .iter()
+            .filter_map(|&uuid| {
+                let radius = self.get::<SphereShape>(uuid)?.radius;
+                let ray = Ray {
+                    origin: self.get::<Transform>(uuid)?.position,
+                    direction: -Vector3::unit_y(),
+                };
+                let hit = self.raycast_where(&ray, |s| !self.is_selected(s))?;
+                Some((uuid, hit.point + Vector3::unit_y() * radius))
+            })
+            .collect::<Vec<_>>();
+
+        for (uuid, center) in landings {
+            if let Some(transform) = self.get_mut::<Transform>(uuid) {
+                transform.position = center;
+                self.publish(SceneEvent::ObjectMoved(uuid));
+            }
+        }
+    }
+
+    /// Selects the spheres and instances whose centers [`Scene::camera`] sees between the
+    /// corners `a` and `b` of a rectangle on screen, in place of everything else or along with
+    /// it if `extend`.
+    pub fn select_in_rect(
+        &mut self,
+        a: PhysicalPosition<f64>,
+        b: PhysicalPosition<f64>,
+        screen_size: PhysicalSize<u32>,
+        extend: bool,
+    ) {
+        let (min_x, max_x) = (a.x.min(b.x) as f32, a.x.max(b.x) as f32);
+        let (min_y, max_y) = (a.y.min(b.y) as f32, a.y.max(b.y) as f32);
+        let inside = |point: Vector3<f32>| {
+            self.camera
+                .world_to_screen(point, screen_size)
+                .is_some_and(|p| (min_x..=max_x).contains(&p.x) && (min_y..=max_y).contains(&p.y))
+        };
+
+        let spheres = self
+            .spheres()
+            .into_iter()
+            .filter(|s| inside(s.transform.position))
+            .map(|s| s.id.0);
+        let instances = self
+            .instances()
+            .into_iter()
+            .filter(|i| {
+                let bounds = i.transform.world_bounds(&self.meshes[i.mesh.mesh]);
+                inside(bounds.center())
+            })
+            .map(|i| i.id.0);
+        let uuids = spheres.chain(instances).collect::<Vec<_>>();
+        self.select_all(uuids, extend);
+    }
+
+    /// A window editing the selected objects. With several selected, it shows the most
+    /// recently selected sphere and instance, and copies whatever is changed on them to the
+    /// rest, moving them all by the same amount.
+    pub fn render_selection_ui(
+        &mut self,
+        context: &egui::Context,
+        material_preview: egui::Image,
+    ) -> Vec<SceneEvent> {
+        let mut events = Vec::new();
+        let spheres = self
+            .selection
+            .iter()
+            .copied()
+            .filter(|&uuid| self.get::<SphereShape>(uuid).is_some())
+            .collect::<Vec<_>>();
+        let instances = self
+            .selection
+            .iter()
+            .copied()
+            .filter(|&uuid| self.get::<MeshRef>(uuid).is_some())
+            .collect::<Vec<_>>();
+        if spheres.is_empty() && instances.is_empty() {
+            return events;
+        }
+
+        let mut removed = false;
+        let mut duplicated = false;
+        egui::Window::new("Selection")
+            .default_pos(egui::Pos2::new(400.0, 400.0))
+            .resizable(true)
+            .show(context, |ui| {
+                ui.horizontal(|ui| {
+                    if self.selection.len() > 1 {
+                        ui.label(format!(
+                            "{} objects selected, edited together",
+                            self.selection.len()
+                        ));
+                    }
+                    duplicated = ui
+                        .button("Duplicate")
+                        .on_hover_text(
+                            "Add copies of the selected objects and select them (Ctrl+D)",
+                        )
+                        .clicked();
+                    removed = ui
+                        .button("Remove")
+                        .on_hover_text("Remove the selected objects from the scene (Delete)")
+                        .clicked();
+                });
+
+                if let Some(&active) = spheres.last() {
+                    ui.add(material_preview);
+                    // The active sphere is edited in place, and the edit copied to the others
+                    let (Some(original), Ok(mut sphere)) = (
+                        self.sphere(active),
+                        self.queries
+                            .spheres
+                            .get_mut(&mut self.world, self.entities[&active]),
+                    ) else {
+                        return;
+                    };
+                    let (library_edits, changes): (Vec<_>, Vec<_>) =
+                        sphere_ui(ui, &mut sphere, &mut self.materials)
+                            .into_iter()
+                            .partition(|change| matches!(change, SceneEvent::MaterialEdited(_)));
+                    let Some(edited) = self.sphere(active) else {
+                        return;
+                    };
+                    events.extend(library_edits);
+                    for &uuid in &spheres {
+                        let entity = self.entities[&uuid];
+                        let Ok(mut sphere) = self.queries.spheres.get_mut(&mut self.world, entity)
+                        else {
+                            continue;
+                        };
+                        if uuid != active {
+                            edit_sphere(&mut sphere, &original, &edited);
+                        }
+                        events.extend(changes.iter().map(|change| match change {
+                            SceneEvent::MaterialChanged(_) => SceneEvent::MaterialChanged(uuid),
+                            SceneEvent::ObjectRenamed(_) => SceneEvent::ObjectRenamed(uuid),
+                            _ => SceneEvent::ObjectMoved(uuid),
+                        }));
+                    }
+                }
+
+                if let Some(&active) = instances.last() {
+                    if !spheres.is_empty() {
+                        ui.separator();
+                    }
+                    let (Some(original), Ok(mut instance)) = (
+                        self.instance(active),
+                        self.queries
+                            .instances
+                            .get_mut(&mut self.world, self.entities[&active]),
+                    ) else {
+                        return;
+                    };
+                    let renamed = name_ui(ui, &mut instance.name.0, active).is_some();
+                    let moved = instance_ui(ui, &mut instance.transform);
+                    let camera_visible = &mut instance.emitter.camera_visible;
+                    let visibility_changed = camera_visible_ui(ui, camera_visible).changed();
+                    let Some(edited) = self.instance(active) else {
+                        return;
+                    };
+                    for &uuid in &instances {
+                        let entity = self.entities[&uuid];
+                        let Ok(mut instance) =
+                            self.queries.instances.get_mut(&mut self.world, entity)
+                        else {
+                            continue;
+                        };
+                        if uuid != active {
+                            edit_instance(&mut instance, &original, &edited);
+                        }
+                        if renamed {
+                            events.push(SceneEvent::ObjectRenamed(uuid));
+                        }
+                        if visibility_changed {
+                            events.push(SceneEvent::MaterialChanged(uuid));
+                        }
+                        if moved {
+                            events.push(SceneEvent::ObjectMoved(uuid));
+                        }
+                    }
+                    if moved {
+                        self.update_tlas();
+                    }
+                }
+            });
+
+        if duplicated {
+            self.duplicate_selected();
+        }
+        if removed {
+            self.remove_selected();
+        }
+        events
+    }
+}
+
+/// Moves `sphere` as far as `original` was moved to `edited`, and copies the rest of what
+/// changed.
+fn edit_sphere(sphere: &mut SphereQueryItem, original: &Sphere, edited: &Sphere) {
+    if edited.name != original.name {
+        sphere.name.0 = edited.name.clone();
+    }
+    sphere.transform.position += edited.center - original.center;
+    if edited.radius != original.radius {
+        sphere.shape.radius = edited.radius;
+    }
+    if edited.material != original.material {
+        sphere.surface.material = edited.material;
+    }
+    let source = |sphere: &Sphere| {
+        sphere
+            .albedo_expression
+            .as_ref()
+            .map(|expression| expression.source().to_string())
+    };
+    if source(edited) != source(original) {
+        sphere.surface.albedo_expression = source(edited).map(Expression::new);
+    }
+    if edited.material_graph != original.material_graph {
+        sphere.surface.material_graph = edited.material_graph.clone();
+    }
+    if edited.camera_visible != original.camera_visible {
+        sphere.emitter.camera_visible = edited.camera_visible;
+    }
+}
+
+/// Moves `instance` as far as `original` was moved to `edited`, and copies the rest of what
+/// changed.
+fn edit_instance(instance: &mut InstanceQueryItem, original: &MeshInstance, edited: &MeshInstance) {
+    if edited.name != original.name {
+        instance.name.0 = edited.name.clone();
+    }
+    let transform = &mut *instance.transform;
+    transform.position += edited.position - original.position;
+    copy_changed(&mut transform.rotation, original.rotation, edited.rotation);
+    copy_changed(&mut transform.scale, original.scale, edited.scale);
+    if edited.camera_visible != original.camera_visible {
+        instance.emitter.camera_visible = edited.camera_visible;
+    }
+}
+
+/// Copies the components of `edited` that differ from `original` into `target`.
+fn copy_changed(target: &mut Vector3<f32>, original: Vector3<f32>, edited: Vector3<f32>) {
+    for axis in 0..3 {
+        if edited[axis] != original[axis] {
+            target[axis] = edited[axis];
+        }
+    }
+}
@@ -1,81 +1,100 @@
-use std::{cmp, usize};
+use std::cmp;
 
-use crate::MAX_NUMBER_OF_SPHERES;
+use crate::{
+    expression::{Expression, Instruction},
+    MAX_NUMBER_OF_SPHERES,
+};
 use bytemuck::Zeroable;
-use cgmath::{InnerSpace, Vector3};
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::{Material, Ray};
+use super::{
+    Emitter, LightLinks, MaterialDataBuffer, MaterialGraph, MaterialId, Scene,
+    SphereQueryReadOnlyItem, SphereShape, Surface, Transform,
+};
 
 pub struct SphereDescriptor {
     pub center: Vector3<f32>,
     pub radius: f32,
-    pub albedo: Vector3<f32>,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
-#[derive(Debug)]
+/// A sphere's components gathered together, as spheres are saved, copied and added to a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sphere {
     pub uuid: uuid::Uuid,
-    pub label: Option<String>,
+    /// Unique among the scene's objects, given when the sphere is added to it. Older files
+    /// saved an unused `label` in its place.
+    #[serde(default)]
+    pub name: String,
     pub center: Vector3<f32>,
     pub radius: f32,
-    pub albedo: Vector3<f32>,
-    pub material: Material,
+    /// Which of the scene's materials the sphere is made of.
+    pub material: MaterialId,
+    /// Multiplies the material's albedo wherever the sphere is hit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub albedo_expression: Option<Expression>,
+    /// The nodes `albedo_expression` was built from, if it wasn't typed in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material_graph: Option<MaterialGraph>,
+    /// Whether camera rays see the sphere while it's emissive. A hidden emitter still lights
+    /// the scene and shows in reflections, like a studio light out of shot.
+    #[serde(default = "super::default_camera_visible")]
+    pub camera_visible: bool,
+    /// Which objects the sphere illuminates while it's emissive.
+    #[serde(default, skip_serializing_if = "LightLinks::is_empty")]
+    pub light_links: LightLinks,
 }
 
 impl Sphere {
     pub fn new(sphere_descriptor: SphereDescriptor) -> Self {
         Self {
             uuid: Uuid::new_v4(),
-            label: None,
+            name: String::new(),
             center: sphere_descriptor.center,
             radius: sphere_descriptor.radius,
-            albedo: sphere_descriptor.albedo,
             material: sphere_descriptor.material,
+            albedo_expression: None,
+            material_graph: None,
+            camera_visible: true,
+            light_links: LightLinks::default(),
         }
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.magnitude2();
-        let half_b = oc.dot(ray.direction);
-        let c = oc.magnitude2() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-
-        if discriminant > 0.0 {
-            let root = discriminant.sqrt();
-
-            let mut t = (-half_b - root) / a;
-            if t < t_max && t > t_min {
-                let point = ray.at(t);
-                return Some(HitRecord {
-                    point,
-                    t,
-                    sphere: self,
-                });
-            }
-
-            t = (-half_b + root) / a;
-            if t < t_max && t > t_min {
-                let point = ray.at(t);
-                return Some(HitRecord {
-                    point,
-                    t,
-                    sphere: self,
-                });
-            }
+    /// The sphere `sphere` is in the world.
+    pub fn from_components(sphere: &SphereQueryReadOnlyItem) -> Self {
+        Self {
+            uuid: sphere.id.0,
+            name: sphere.name.0.clone(),
+            center: sphere.transform.position,
+            radius: sphere.shape.radius,
+            material: sphere.surface.material,
+            albedo_expression: sphere.surface.albedo_expression.clone(),
+            material_graph: sphere.surface.material_graph.clone(),
+            camera_visible: sphere.emitter.camera_visible,
+            light_links: sphere.emitter.light_links.clone(),
         }
-
-        None
     }
-}
 
-#[derive(Debug)]
-pub struct HitRecord<'a> {
-    pub point: Vector3<f32>,
-    pub t: f32,
-    pub sphere: &'a Sphere,
+    /// What the sphere is spawned with besides its UUID and name.
+    pub(super) fn into_components(self) -> (Transform, SphereShape, Surface, Emitter) {
+        (
+            Transform::at(self.center),
+            SphereShape {
+                radius: self.radius,
+            },
+            Surface {
+                material: self.material,
+                albedo_expression: self.albedo_expression,
+                material_graph: self.material_graph,
+            },
+            Emitter {
+                camera_visible: self.camera_visible,
+                light_links: self.light_links,
+            },
+        )
+    }
 }
 
 #[repr(C)]
@@ -83,21 +102,16 @@ pub struct HitRecord<'a> {
 pub struct SphereBuffer {
     center: [f32; 3],
     radius: f32,
-    albedo: [f32; 3],
-    material: f32,
+    material: u32,
+    _padding: [u32; 3],
 }
-impl From<&Sphere> for SphereBuffer {
-    fn from(sphere: &Sphere) -> Self {
+impl From<&SphereQueryReadOnlyItem<'_>> for SphereBuffer {
+    fn from(sphere: &SphereQueryReadOnlyItem) -> Self {
         Self {
-            center: sphere.center.into(),
-            radius: sphere.radius,
-            albedo: sphere.albedo.into(),
-            material: match sphere.material {
-                Material::Diffuse => 0.0,
-                Material::Metal => 1.0,
-                Material::Dielectric => 2.0,
-                Material::Gizmo => 3.0,
-            },
+            center: sphere.transform.position.into(),
+            radius: sphere.shape.radius,
+            material: sphere.surface.material.shader_index(),
+            _padding: [0; 3],
         }
     }
 }
@@ -106,26 +120,72 @@ impl From<&Sphere> for SphereBuffer {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct SphereDataBuffer {
     sphere_count: u32,
-    _padding: [u32; 3],
+    /// The animation time in seconds, for the albedo expressions.
+    time: f32,
+    _padding: [u32; 2],
     spheres: [SphereBuffer; MAX_NUMBER_OF_SPHERES as _],
+    /// Where each sphere's albedo expression starts in `expression_code`, or [`NO_EXPRESSION`].
+    albedo_expressions: [u32; MAX_NUMBER_OF_SPHERES as _],
+    /// Non-zero for spheres camera rays pass through if they're emissive.
+    hidden_from_camera: [u32; MAX_NUMBER_OF_SPHERES as _],
+    expression_code: [Instruction; MAX_EXPRESSION_CODE],
 }
 
-impl From<&Vec<Sphere>> for SphereDataBuffer {
-    fn from(spheres: &Vec<Sphere>) -> Self {
-        let mut sphere_buffer = [SphereBuffer::zeroed(); MAX_NUMBER_OF_SPHERES as _];
+/// The instructions shared by every sphere's albedo expression. Spheres past it are drawn with
+/// their plain albedo. Matches `EXPRESSION_CODE_SIZE` in the compute shader.
+const MAX_EXPRESSION_CODE: usize = 1024;
+const NO_EXPRESSION: u32 = u32::MAX;
+
+impl SphereDataBuffer {
+    /// The spheres of `scene`, whose albedo expressions see its [`Scene::time`] as `t`.
+    pub fn new(scene: &Scene) -> Self {
+        let spheres = scene.spheres();
+        let mut data = Self {
+            sphere_count: cmp::min(spheres.len(), MAX_NUMBER_OF_SPHERES as usize) as u32,
+            time: scene.time(),
+            _padding: [0; 2],
+            spheres: [SphereBuffer::zeroed(); MAX_NUMBER_OF_SPHERES as _],
+            albedo_expressions: [NO_EXPRESSION; MAX_NUMBER_OF_SPHERES as _],
+            hidden_from_camera: [0; MAX_NUMBER_OF_SPHERES as _],
+            expression_code: [Instruction::zeroed(); MAX_EXPRESSION_CODE],
+        };
+
+        let mut code_used = 0;
         for (i, sphere) in spheres
             .iter()
             .take(MAX_NUMBER_OF_SPHERES as usize)
             .enumerate()
         {
-            sphere_buffer[i] = SphereBuffer::from(sphere);
+            data.spheres[i] = SphereBuffer::from(sphere);
+            data.hidden_from_camera[i] = !sphere.emitter.camera_visible as u32;
+
+            let Some(code) = sphere
+                .surface
+                .albedo_expression
+                .as_ref()
+                .and_then(Expression::code)
+            else {
+                continue;
+            };
+            if code_used + code.len() > MAX_EXPRESSION_CODE {
+                continue;
+            }
+            data.expression_code[code_used..code_used + code.len()].copy_from_slice(code);
+            data.albedo_expressions[i] = code_used as u32;
+            code_used += code.len();
         }
+        data
+    }
 
-        Self {
-            sphere_count: cmp::min(spheres.len(), MAX_NUMBER_OF_SPHERES as usize) as u32,
-            _padding: [0; 3],
-            spheres: sphere_buffer,
-        }
+    /// The indices of the spheres whose material in `materials` emits light.
+    pub fn emissive_spheres<'a>(
+        &'a self,
+        materials: &'a MaterialDataBuffer,
+    ) -> impl Iterator<Item = u32> + 'a {
+        self.spheres[..self.sphere_count as usize]
+            .iter()
+            .enumerate()
+            .filter(|(_, sphere)| materials.is_emissive(sphere.material))
+            .map(|(i, _)| i as u32)
     }
 }
-
@@ -0,0 +1,163 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use crate::{geometry::Ray, MAX_NUMBER_OF_CANVASES};
+
+use super::{CanvasRows, Scene, CANVAS_SIZE, RAYCAST_T_MIN};
+
+/// How far apart dabs are along a stroke, as a fraction of the brush radius.
+const DAB_SPACING: f32 = 0.25;
+/// Strokes jumping further than this across the canvas between two cursor moves crossed a seam
+/// in the mesh's texture coordinates, so nothing is painted in between.
+const MAX_STROKE_JUMP: f32 = 0.1;
+
+/// Paints onto the canvas of the selected mesh where the cursor is over it, while the left mouse
+/// button is held, for quick masks and ID maps.
+pub struct TexturePainter {
+    pub enabled: bool,
+    /// Linear.
+    color: [f32; 3],
+    /// In texels of the canvas.
+    radius: f32,
+    painting: bool,
+    /// The texture coordinates of the last dab of the stroke in progress.
+    last_uv: Option<Vector2<f32>>,
+}
+
+impl TexturePainter {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            color: [0.0, 0.0, 0.0],
+            radius: 8.0,
+            painting: false,
+            last_uv: None,
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui, scene: &mut Scene) {
+        ui.collapsing("Texture painting", |ui| {
+            ui.checkbox(&mut self.enabled, "enabled").on_hover_text(
+                "Hold the left mouse button over the selected mesh to paint on its canvas",
+            );
+
+            let Some(mesh) = scene.selected_mesh() else {
+                ui.label("Select a mesh instance to paint on");
+                return;
+            };
+            if scene.meshes()[mesh].canvas.is_none() {
+                let canvases = scene.meshes().iter().filter(|m| m.canvas.is_some());
+                let room = canvases.count() < MAX_NUMBER_OF_CANVASES as usize;
+                if ui
+                    .add_enabled(room, egui::Button::new("Add canvas"))
+                    .on_hover_text(
+                        "Paint over the albedo of every instance of the mesh by its texture \
+                        coordinates",
+                    )
+                    .clicked()
+                {
+                    scene.add_canvas(mesh);
+                }
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgb(&mut self.color);
+                ui.label("color");
+            });
+            let max_radius = CANVAS_SIZE as f32 / 4.0;
+            ui.add(egui::Slider::new(&mut self.radius, 1.0..=max_radius).text("radius"));
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Fill")
+                    .on_hover_text("Paint the whole canvas the color")
+                    .clicked()
+                {
+                    let color = Vector3::from(self.color);
+                    scene.paint_canvas(mesh, |canvas| canvas.fill(color));
+                }
+                if ui.button("Export PNG").clicked() {
+                    export_canvas(scene, mesh);
+                }
+                if ui.button("Remove canvas").clicked() {
+                    scene.remove_canvas(mesh);
+                }
+            });
+        });
+    }
+
+    pub fn begin_stroke(&mut self, scene: &mut Scene, ray: &Ray) {
+        self.painting = true;
+        self.last_uv = None;
+        self.paint(scene, ray);
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.painting = false;
+    }
+
+    /// Paints where `ray` hits the selected mesh instance, if a stroke is in progress, filling
+    /// in dabs back to where the stroke last painted.
+    pub fn paint(&mut self, scene: &mut Scene, ray: &Ray) {
+        if !self.painting {
+            return;
+        }
+        let Some(&selected) = scene.selection.last() else {
+            return;
+        };
+        let Some((mesh, uv)) = scene
+            .hit_closest_triangle(ray, RAYCAST_T_MIN, f32::INFINITY)
+            .filter(|(instance, _, _)| instance.id.0 == selected)
+            .map(|(instance, _, hit)| (instance.mesh.mesh, hit.uv))
+        else {
+            return;
+        };
+
+        let spacing = self.radius * DAB_SPACING / CANVAS_SIZE as f32;
+        let dabs = match self.last_uv {
+            Some(last) if (uv - last).magnitude() <= MAX_STROKE_JUMP => {
+                let dabs = ((uv - last).magnitude() / spacing) as u32;
+                if dabs == 0 {
+                    return;
+                }
+                (1..=dabs)
+                    .map(|dab| last + (uv - last) * (dab as f32 / dabs as f32))
+                    .collect()
+            }
+            _ => vec![uv],
+        };
+
+        let (radius, color) = (self.radius, Vector3::from(self.color));
+        scene.paint_canvas(mesh, |canvas| {
+            dabs.into_iter()
+                .map(|uv| canvas.paint(uv, radius, color))
+                .reduce(CanvasRows::union)
+                .expect("a stroke paints at least one dab")
+        });
+        self.last_uv = Some(uv);
+    }
+}
+
+impl Default for TexturePainter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Asks where to save the canvas of the mesh at `mesh` with a file dialog.
+fn export_canvas(scene: &Scene, mesh: usize) {
+    let mesh = &scene.meshes()[mesh];
+    let Some(canvas) = &mesh.canvas else {
+        return;
+    };
+    let path = rfd::FileDialog::new()
+        .set_title("Export canvas")
+        .set_file_name(format!("{}.png", mesh.name))
+        .add_filter("PNG", &["png"])
+        .save_file();
+    if let Some(path) = path {
+        if let Err(e) = canvas.save_png(&path) {
+            eprintln!("Failed to export {}: {}", path.display(), e);
+        }
+    }
+}
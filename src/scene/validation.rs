@@ -0,0 +1,290 @@
+use std::fmt;
+
+use cgmath::{Array, InnerSpace, Vector3};
+
+use crate::{geometry::Primitive, MAX_NUMBER_OF_MATERIALS};
+
+use super::{
+    in_order, Light, Material, MaterialId, MaterialLibrary, Scene, DEFAULT_IOR,
+    DEFAULT_NITS_PER_UNIT,
+};
+
+/// Smaller spheres are treated as having no radius at all.
+const MIN_RADIUS: f32 = 1e-6;
+
+/// Something in a scene the shaders can't render sensibly, and what was done about it.
+#[derive(Debug, Clone)]
+pub struct Repair {
+    pub problem: String,
+    pub fix: String,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.problem, self.fix)
+    }
+}
+
+impl Scene {
+    /// Fixes or removes whatever in a freshly loaded or imported scene would make the shaders
+    /// misbehave, like NaN transforms, zero-radius spheres, degenerate triangles, material
+    /// values out of range, a unit conversion that isn't positive and more materials than they
+    /// have room for, returning what it repaired. Objects made of a removed material are made of
+    /// the default instead.
+    pub fn validate(&mut self) -> Vec<Repair> {
+        let mut repairs = Vec::new();
+        let mut repair = |problem: String, fix: &str| {
+            repairs.push(Repair {
+                problem,
+                fix: fix.to_string(),
+            })
+        };
+
+        if self.materials.is_empty() {
+            self.materials = MaterialLibrary::new();
+            repair("The scene has no materials".to_string(), "added a default");
+        }
+        if self.materials.len() > MAX_NUMBER_OF_MATERIALS as usize {
+            repair(
+                format!(
+                    "The scene has {} materials, more than the {} the renderer has room for",
+                    self.materials.len(),
+                    MAX_NUMBER_OF_MATERIALS
+                ),
+                "removed the rest",
+            );
+            self.materials.truncate(MAX_NUMBER_OF_MATERIALS as usize);
+        }
+        let ids = self.materials.iter().map(|(id, _)| id).collect::<Vec<_>>();
+        for id in ids {
+            let material = &mut self.materials[id];
+            if clamp_albedo(&mut material.albedo) {
+                repair(
+                    format!("{} has an albedo outside 0 to 1", material.name),
+                    "clamped",
+                );
+            }
+            if clamp_roughness(&mut material.roughness) {
+                repair(
+                    format!("{} has a roughness outside 0 to 1", material.name),
+                    "clamped",
+                );
+            }
+            if !(material.ior >= 1.0 && material.ior.is_finite()) {
+                material.ior = DEFAULT_IOR;
+                repair(
+                    format!("{} has an invalid index of refraction", material.name),
+                    "replaced",
+                );
+            }
+            if repair_material(&mut material.material) {
+                repair(
+                    format!("{} has an invalid intensity", material.name),
+                    "replaced",
+                );
+            }
+        }
+
+        let mut removed = Vec::new();
+        let spheres = self.queries.spheres.iter_mut(&mut self.world);
+        for mut sphere in in_order(spheres, |s| *s.order) {
+            let name = &sphere.name.0;
+            if !sphere.transform.position.is_finite() || !sphere.shape.radius.is_finite() {
+                repair(
+                    format!("{} has a NaN or infinite transform", name),
+                    "removed",
+                );
+                removed.push(sphere.id.0);
+                continue;
+            }
+            if sphere.shape.radius.abs() < MIN_RADIUS {
+                repair(format!("{} has no radius", name), "removed");
+                removed.push(sphere.id.0);
+                continue;
+            }
+            if self.materials.get(sphere.surface.material).is_none() {
+                repair(
+                    format!("{} is made of a missing material", name),
+                    "replaced with the default",
+                );
+                sphere.surface.material = MaterialId::default();
+            }
+        }
+        for uuid in removed {
+            self.despawn(uuid);
+        }
+
+        let mut instances_moved = false;
+        let instances = self.queries.instances.iter_mut(&mut self.world);
+        for mut instance in in_order(instances, |i| *i.order) {
+            let name = instance.name.0.clone();
+            let transform = &mut *instance.transform;
+            if !transform.position.is_finite() {
+                transform.position = Vector3::new(0.0, 0.0, 0.0);
+                repair(format!("{} has a NaN or infinite position", name), "reset");
+                instances_moved = true;
+            }
+            if !transform.rotation.is_finite() {
+                transform.rotation = Vector3::new(0.0, 0.0, 0.0);
+                repair(format!("{} has a NaN or infinite rotation", name), "reset");
+                instances_moved = true;
+            }
+            // A zero scale leaves no way back from world space into the mesh's
+            let scale = transform
+                .scale
+                .map(|s| if s.is_finite() && s != 0.0 { s } else { 1.0 });
+            if scale != transform.scale {
+                transform.scale = scale;
+                repair(
+                    format!("{} has a zero, NaN or infinite scale", name),
+                    "reset to 1 on those axes",
+                );
+                instances_moved = true;
+            }
+        }
+        if instances_moved {
+            self.update_tlas();
+        }
+
+        let mut meshes_emptied = false;
+        for mesh in &mut self.meshes {
+            let count = mesh.triangles.len();
+            mesh.triangles.retain(|triangle| !triangle.is_degenerate());
+            let degenerate = count - mesh.triangles.len();
+
+            let (mut normals, mut missing) = (0, 0);
+            for triangle in &mut mesh.triangles {
+                let [a, b, c] = triangle.vertices();
+                let face_normal = (b - a).cross(c - a).normalize();
+                for normal in [&mut triangle.na, &mut triangle.nb, &mut triangle.nc] {
+                    if !normal.is_finite() || normal.magnitude2() == 0.0 {
+                        *normal = face_normal;
+                        normals += 1;
+                    }
+                }
+                if self.materials.get(triangle.material).is_none() {
+                    triangle.material = MaterialId::default();
+                    missing += 1;
+                }
+            }
+
+            if degenerate > 0 {
+                repair(
+                    format!(
+                        "{} has {} triangles with no area or NaN vertices",
+                        mesh.name, degenerate
+                    ),
+                    "removed",
+                );
+                mesh.rebuild_bvh(mesh.bvh.builder());
+                meshes_emptied |= mesh.triangles.is_empty();
+            }
+            if normals > 0 {
+                repair(
+                    format!("{} has {} zero or NaN normals", mesh.name, normals),
+                    "replaced with face normals",
+                );
+            }
+            if missing > 0 {
+                repair(
+                    format!(
+                        "{} has {} triangles made of a missing material",
+                        mesh.name, missing
+                    ),
+                    "replaced with the default",
+                );
+            }
+        }
+        // Instances of empty meshes are left out of the TLAS
+        if meshes_emptied {
+            self.update_tlas();
+        }
+
+        if !(self.nits_per_unit > 0.0 && self.nits_per_unit.is_finite()) {
+            self.nits_per_unit = DEFAULT_NITS_PER_UNIT;
+            repair(
+                "The scene's nits per unit radiance isn't positive".to_string(),
+                "reset to the default",
+            );
+        }
+        let lights = self.queries.lights.iter_mut(&mut self.world);
+        for mut light in in_order(lights, |l| *l.order) {
+            if repair_light(&mut light.light) {
+                repair(
+                    format!("{} has NaN, infinite or negative values", light.name.0),
+                    "reset to defaults",
+                );
+            }
+        }
+
+        repairs
+    }
+}
+
+/// Clamps `albedo` to what a surface can reflect, returning whether it was out of range.
+fn clamp_albedo(albedo: &mut Vector3<f32>) -> bool {
+    let clamped = albedo.map(|c| {
+        if c.is_finite() {
+            c.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    });
+    // Also true for NaNs, which never equal themselves
+    let changed = clamped != *albedo;
+    *albedo = clamped;
+    changed
+}
+
+/// Clamps `roughness` to the range metals have, returning whether it was out of it.
+fn clamp_roughness(roughness: &mut f32) -> bool {
+    if (0.0..=1.0).contains(roughness) {
+        return false;
+    }
+    *roughness = if roughness.is_finite() {
+        roughness.clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    true
+}
+
+/// Replaces emissive intensities that aren't finite and positive, returning whether it did.
+fn repair_material(material: &mut Material) -> bool {
+    match *material {
+        Material::Emissive { intensity } if !intensity.is_finite() || intensity < 0.0 => {
+            *material = Material::Emissive { intensity: 1.0 };
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Resets whatever about `light` is NaN, infinite or negative to a new light's, returning
+/// whether anything was.
+fn repair_light(light: &mut Light) -> bool {
+    let default = Light::new(light.kind);
+    let mut repaired = false;
+    let mut check = |valid: bool| {
+        repaired |= !valid;
+        valid
+    };
+
+    if !check(light.position.is_finite()) {
+        light.position = default.position;
+    }
+    if !check(light.direction.is_finite() && light.direction.magnitude2() > 0.0) {
+        light.direction = default.direction;
+    }
+    if !check(light.color.is_finite() && light.color.x.min(light.color.y).min(light.color.z) >= 0.0)
+    {
+        light.color = default.color;
+    }
+    if !check(light.intensity.is_finite() && light.intensity >= 0.0) {
+        light.intensity = default.intensity;
+    }
+    if !check(light.size.is_finite() && light.size.x > 0.0 && light.size.y > 0.0) {
+        light.size = default.size;
+    }
+    repaired
+}
@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+
+use crate::thumbnails::{self, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+
+/// The scene files in a directory, shown as a gallery of thumbnails of how they looked when
+/// they were last saved.
+pub struct SceneLibrary {
+    directory: PathBuf,
+    scenes: Vec<SceneFile>,
+}
+
+struct SceneFile {
+    path: PathBuf,
+    name: String,
+    thumbnail: Option<egui::TextureHandle>,
+    thumbnail_loaded: bool,
+}
+
+impl SceneLibrary {
+    /// Lists the `.json` files in `directory`.
+    pub fn new(directory: &Path) -> Self {
+        let mut library = Self {
+            directory: directory.to_path_buf(),
+            scenes: Vec::new(),
+        };
+        library.refresh();
+        library
+    }
+
+    /// Lists the files again, for when one was saved or the directory changed outside the app.
+    pub fn refresh(&mut self) {
+        let mut paths = std::fs::read_dir(&self.directory)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        self.scenes = paths
+            .into_iter()
+            .map(|path| SceneFile {
+                name: path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                path,
+                thumbnail: None,
+                thumbnail_loaded: false,
+            })
+            .collect();
+    }
+
+    /// Returns the path of the scene that was clicked, to be loaded.
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) -> Option<PathBuf> {
+        let mut clicked = None;
+
+        ui.horizontal(|ui| {
+            ui.label(self.directory.display().to_string());
+            if ui.small_button("Refresh").clicked() {
+                self.refresh();
+            }
+        });
+        if self.scenes.is_empty() {
+            ui.label("No scene files found");
+        }
+
+        for scene in &mut self.scenes {
+            let thumbnail = scene.thumbnail(ui.ctx()).map(|texture| texture.id());
+
+            let response = ui
+                .vertical(|ui| {
+                    let size = egui::Vec2::new(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32);
+                    let image = match thumbnail {
+                        Some(texture) => ui.add(egui::ImageButton::new(
+                            egui::load::SizedTexture::new(texture, size),
+                        )),
+                        None => ui
+                            .add_sized(size, egui::Button::new("?"))
+                            .on_hover_text("Saved without a render, or by another program"),
+                    };
+                    ui.label(&scene.name);
+                    image
+                })
+                .inner;
+
+            if response.clicked() {
+                clicked = Some(scene.path.clone());
+            }
+        }
+
+        clicked
+    }
+}
+
+impl SceneFile {
+    /// Loads the thumbnail the first time it is shown, if the scene was saved with one.
+    fn thumbnail(&mut self, context: &egui::Context) -> Option<&egui::TextureHandle> {
+        if !self.thumbnail_loaded {
+            self.thumbnail_loaded = true;
+            self.thumbnail = thumbnails::cached_only(&self.path)
+                .map(|image| thumbnails::texture(context, &self.path.to_string_lossy(), &image));
+        }
+
+        self.thumbnail.as_ref()
+    }
+}
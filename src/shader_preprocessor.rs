@@ -0,0 +1,375 @@
+//! Expands the comment-style directives in the WGSL shaders, so the files stay valid WGSL for
+//! editors while sharing code and compiling in optional features:
+//!
+//! ```text
+//! //!include "file.wgsl"
+//! //!define NAME
+//! //!ifdef NAME
+//! //!ifndef NAME
+//! //!else
+//! //!endif
+//! ```
+//!
+//! Include paths starting with `./` or `../` are relative to the including file, any other path
+//! is relative to `shaders/include`. Each file is included at most once.
+
+use std::{
+    collections::HashSet,
+    fmt, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Why a shader couldn't be preprocessed.
+#[derive(Debug)]
+pub enum PreprocessError {
+    /// A shader or an included file couldn't be read.
+    Read { path: PathBuf, error: io::Error },
+    /// A directive that isn't one of the supported ones, or has the wrong arguments.
+    InvalidDirective {
+        path: PathBuf,
+        line: usize,
+        directive: String,
+    },
+    /// An `//!include` whose path isn't in double quotes.
+    MalformedInclude {
+        path: PathBuf,
+        line: usize,
+        directive: String,
+    },
+    /// An `//!else` or `//!endif` outside of a conditional, or a second `//!else` in one.
+    UnmatchedDirective {
+        path: PathBuf,
+        line: usize,
+        directive: String,
+    },
+    /// An `//!ifdef` or `//!ifndef` at `line` that is never closed.
+    MissingEndif { path: PathBuf, line: usize },
+    /// Files that include each other, from the first one back to itself.
+    IncludeCycle { chain: Vec<PathBuf> },
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { path, error } => write!(f, "{}: {}", path.display(), error),
+            Self::InvalidDirective {
+                path,
+                line,
+                directive,
+            } => write!(
+                f,
+                "{}:{}: invalid directive \"{}\"",
+                path.display(),
+                line,
+                directive
+            ),
+            Self::MalformedInclude {
+                path,
+                line,
+                directive,
+            } => write!(
+                f,
+                "{}:{}: expected a path in double quotes in \"{}\"",
+                path.display(),
+                line,
+                directive
+            ),
+            Self::UnmatchedDirective {
+                path,
+                line,
+                directive,
+            } => write!(
+                f,
+                "{}:{}: \"{}\" doesn't match an //!ifdef or //!ifndef",
+                path.display(),
+                line,
+                directive
+            ),
+            Self::MissingEndif { path, line } => write!(
+                f,
+                "{}:{}: conditional is missing its //!endif",
+                path.display(),
+                line
+            ),
+            Self::IncludeCycle { chain } => {
+                write!(f, "include cycle: ")?;
+                for (i, path) in chain.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// A shader's source after [`preprocess_shader`], with where each of its lines came from.
+pub struct PreprocessedShader {
+    pub source: String,
+    /// The file and line number of each line in `source`.
+    origins: Vec<(PathBuf, usize)>,
+}
+
+impl PreprocessedShader {
+    /// The file and line number that line `line_number` of the source came from, counting from
+    /// one.
+    pub fn origin(&self, line_number: usize) -> Option<(&Path, usize)> {
+        let (path, line) = self.origins.get(line_number.checked_sub(1)?)?;
+        Some((path, *line))
+    }
+
+    /// Parses and validates the source with naga, describing what's wrong in terms of the files
+    /// the lines came from rather than the expanded source.
+    pub fn validate(&self) -> Result<(), String> {
+        let module = naga::front::wgsl::parse_str(&self.source).map_err(|error| {
+            let location = error.location(&self.source);
+            format!("{}: {}", self.describe_location(location), error.message())
+        })?;
+
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|error| {
+            let location = error.location(&self.source);
+            let mut message = format!("{}: {}", self.describe_location(location), error);
+            let mut source = std::error::Error::source(error.as_inner());
+            while let Some(cause) = source {
+                message.push_str(&format!("\n  {}", cause));
+                source = cause.source();
+            }
+            for (span, label) in error.spans() {
+                let location = Some(span.location(&self.source));
+                message.push_str(&format!(
+                    "\n  {}: {}",
+                    self.describe_location(location),
+                    label
+                ));
+            }
+            message
+        })?;
+
+        Ok(())
+    }
+
+    fn describe_location(&self, location: Option<naga::SourceLocation>) -> String {
+        let origin = location.and_then(|location| {
+            let (path, line) = self.origin(location.line_number as usize)?;
+            Some(format!(
+                "{}:{}:{}",
+                path.display(),
+                line,
+                location.line_position
+            ))
+        });
+        origin.unwrap_or_else(|| "unknown location".to_string())
+    }
+}
+
+/// Reads `shaders_root/name` and expands its directives for `defines`.
+pub fn preprocess_shader(
+    shaders_root: &Path,
+    name: &str,
+    defines: &[&str],
+) -> Result<PreprocessedShader, PreprocessError> {
+    preprocess_shader_with(shaders_root, name, defines, |path| {
+        std::fs::read_to_string(path)
+    })
+}
+
+/// Like [`preprocess_shader`], but reads files with `read` instead of from the file system.
+pub fn preprocess_shader_with(
+    shaders_root: &Path,
+    name: &str,
+    defines: &[&str],
+    read: impl FnMut(&Path) -> io::Result<String>,
+) -> Result<PreprocessedShader, PreprocessError> {
+    let mut preprocessor = Preprocessor {
+        include_root: normalize(&shaders_root.join("include")),
+        defines: defines.iter().map(|define| define.to_string()).collect(),
+        included: HashSet::new(),
+        stack: Vec::new(),
+        read,
+    };
+    let mut output = Vec::new();
+    preprocessor.expand(&normalize(&shaders_root.join(name)), &mut output)?;
+
+    let (lines, origins): (Vec<_>, Vec<_>) = output.into_iter().unzip();
+    Ok(PreprocessedShader {
+        source: lines.join("\n"),
+        origins,
+    })
+}
+
+struct Preprocessor<R> {
+    include_root: PathBuf,
+    defines: HashSet<String>,
+    included: HashSet<PathBuf>,
+    /// The files being expanded, each included by the one before it.
+    stack: Vec<PathBuf>,
+    read: R,
+}
+
+/// An `//!ifdef` or `//!ifndef` block being expanded.
+struct Conditional {
+    line: usize,
+    kept: bool,
+    has_else: bool,
+}
+
+impl<R: FnMut(&Path) -> io::Result<String>> Preprocessor<R> {
+    /// Appends the lines kept from `path` to `output`, along with their origins.
+    fn expand(
+        &mut self,
+        path: &Path,
+        output: &mut Vec<(String, (PathBuf, usize))>,
+    ) -> Result<(), PreprocessError> {
+        let source = (self.read)(path).map_err(|error| PreprocessError::Read {
+            path: path.to_owned(),
+            error,
+        })?;
+        self.included.insert(path.to_owned());
+        self.stack.push(path.to_owned());
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let active = conditionals.iter().all(|conditional| conditional.kept);
+            let Some(directive) = line.trim_start().strip_prefix("//!") else {
+                if active {
+                    output.push((line.to_owned(), (path.to_owned(), number)));
+                }
+                continue;
+            };
+
+            let (keyword, argument) = directive
+                .trim()
+                .split_once(char::is_whitespace)
+                .map_or((directive.trim(), ""), |(keyword, argument)| {
+                    (keyword, argument.trim())
+                });
+            let invalid = || PreprocessError::InvalidDirective {
+                path: path.to_owned(),
+                line: number,
+                directive: line.trim().to_owned(),
+            };
+            let unmatched = || PreprocessError::UnmatchedDirective {
+                path: path.to_owned(),
+                line: number,
+                directive: line.trim().to_owned(),
+            };
+            match (keyword, argument) {
+                ("ifdef" | "ifndef", name) if is_identifier(name) => {
+                    conditionals.push(Conditional {
+                        line: number,
+                        kept: self.defines.contains(name) == (keyword == "ifdef"),
+                        has_else: false,
+                    })
+                }
+                ("else", "") => {
+                    let conditional = conditionals.last_mut().ok_or_else(unmatched)?;
+                    if conditional.has_else {
+                        return Err(unmatched());
+                    }
+                    conditional.kept = !conditional.kept;
+                    conditional.has_else = true;
+                }
+                ("endif", "") => {
+                    conditionals.pop().ok_or_else(unmatched)?;
+                }
+                // Checked even when skipped, or they would throw off the nesting
+                ("ifdef" | "ifndef" | "else" | "endif", _) => return Err(invalid()),
+                _ if !active => {}
+                ("define", name) if is_identifier(name) => {
+                    self.defines.insert(name.to_owned());
+                }
+                ("include", argument) => {
+                    let file = argument
+                        .strip_prefix('"')
+                        .and_then(|file| file.strip_suffix('"'))
+                        .filter(|file| !file.is_empty() && !file.contains('"'))
+                        .ok_or_else(|| PreprocessError::MalformedInclude {
+                            path: path.to_owned(),
+                            line: number,
+                            directive: line.trim().to_owned(),
+                        })?;
+                    self.include(path, file, output)?;
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        if let Some(conditional) = conditionals.first() {
+            return Err(PreprocessError::MissingEndif {
+                path: path.to_owned(),
+                line: conditional.line,
+            });
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        from: &Path,
+        file: &str,
+        output: &mut Vec<(String, (PathBuf, usize))>,
+    ) -> Result<(), PreprocessError> {
+        let include = if file.starts_with("./") || file.starts_with("../") {
+            normalize(&from.parent().unwrap_or(Path::new("")).join(file))
+        } else {
+            normalize(&self.include_root.join(file))
+        };
+
+        if let Some(start) = self.stack.iter().position(|path| *path == include) {
+            let mut chain = self.stack[start..].to_vec();
+            chain.push(include);
+            return Err(PreprocessError::IncludeCycle { chain });
+        }
+        if self.included.contains(&include) {
+            return Ok(());
+        }
+        self.expand(&include, output)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Removes `.` and resolves `..` without touching the file system, so the same file is always
+/// the same path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
@@ -1,9 +1,16 @@
-use std::{io::Cursor, path::Path};
+use std::{
+    collections::HashMap,
+    f32::consts::PI,
+    fs::File,
+    io::{BufWriter, Cursor},
+    path::Path,
+};
 
 use crate::utils;
+use cgmath::{InnerSpace, Vector3};
 use image::{
     codecs::hdr::{HdrDecoder, HdrMetadata},
-    GenericImageView, ImageResult,
+    ImageResult,
 };
 use wgpu::{
     Device, Sampler, SamplerDescriptor, Texture, TextureFormat, TextureUsages, TextureView,
@@ -13,7 +20,6 @@ use wgpu::{
 pub struct Texture2D {
     pub texture: Texture,
     pub view: TextureView,
-    pub sampler: Sampler,
 }
 
 impl Texture2D {
@@ -39,80 +45,9 @@ impl Texture2D {
             view_formats: &[],
         });
 
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("Texture Sampler"),
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
         let view = texture.create_view(&TextureViewDescriptor::default());
 
-        Self {
-            texture,
-            view,
-            sampler,
-        }
-    }
-    pub fn from_file(path: &str, device: &wgpu::Device, queue: &wgpu::Queue) -> ImageResult<Self> {
-        let data = std::fs::read(path).unwrap();
-        Texture2D::from_bytes(device, queue, &data, false)
-    }
-
-    pub fn from_bytes(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        bytes: &[u8],
-        is_normal_map: bool,
-    ) -> ImageResult<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Texture2D::from_image(device, queue, &img, is_normal_map)
-    }
-
-    pub fn from_image(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        img: &image::DynamicImage,
-        is_normal_map: bool,
-    ) -> ImageResult<Self> {
-        let (width, height) = img.dimensions();
-        let rgba = img.to_rgba8();
-
-        let format = if is_normal_map {
-            wgpu::TextureFormat::Rgba8Unorm
-        } else {
-            wgpu::TextureFormat::Rgba8UnormSrgb
-        };
-
-        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
-        let size = wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-        let texture = Texture2D::new(device, width, height, format, usage);
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
-            },
-            size,
-        );
-
-        Ok(texture)
+        Self { texture, view }
     }
 }
 
@@ -176,17 +111,48 @@ impl CubeTexture {
         data: &[u8],
         dst_size: u32,
     ) -> ImageResult<Self> {
-        let hdr_decoder = HdrDecoder::new(Cursor::new(data))?;
-        let HdrMetadata { width, height, .. } = hdr_decoder.metadata();
-        let mut pixels = vec![[0.0, 0.0, 0.0, 0.0]; width as usize * height as usize];
-        hdr_decoder.read_image_transform(
-            |pix| {
-                let rgb = pix.to_hdr();
-                [rgb.0[0], rgb.0[1], rgb.0[2], 1.0f32]
-            },
-            &mut pixels[..],
-        )?;
+        let (width, height, pixels) = read_hdr_pixels(data)?;
+        Ok(Self::from_equirectangular_pixels(
+            hdr_loader, device, queue, width, height, &pixels, dst_size,
+        ))
+    }
 
+    /// Like [`Self::from_equirectangular_hdri`], with the image already decoded by
+    /// [`read_hdr_pixels`].
+    pub fn from_equirectangular_pixels(
+        hdr_loader: &HdrLoader,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+        dst_size: u32,
+    ) -> Self {
+        let dst = CubeTexture::create_2d(
+            device,
+            dst_size,
+            dst_size,
+            hdr_loader.texture_format,
+            1,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        dst.load_equirectangular_pixels(hdr_loader, device, queue, width, height, pixels);
+
+        dst
+    }
+
+    /// Replaces the contents of the cube map with an equirectangular image decoded by
+    /// [`read_hdr_pixels`], e.g. to switch environments without having to recreate the bind
+    /// groups that use it.
+    pub fn load_equirectangular_pixels(
+        &self,
+        hdr_loader: &HdrLoader,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[[f32; 4]],
+    ) {
         let size = wgpu::Extent3d {
             width,
             height,
@@ -210,7 +176,7 @@ impl CubeTexture {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            bytemuck::cast_slice(&pixels),
+            bytemuck::cast_slice(pixels),
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: Some(width * std::mem::size_of::<[f32; 4]>() as u32),
@@ -225,16 +191,7 @@ impl CubeTexture {
             ..Default::default()
         });
 
-        let dst = CubeTexture::create_2d(
-            device,
-            dst_size,
-            dst_size,
-            hdr_loader.texture_format,
-            1,
-            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
-        );
-
-        let dst_view = dst.texture.create_view(&wgpu::TextureViewDescriptor {
+        let dst_view = self.texture.create_view(&wgpu::TextureViewDescriptor {
             dimension: Some(wgpu::TextureViewDimension::D2Array),
             ..Default::default()
         });
@@ -257,7 +214,7 @@ impl CubeTexture {
         let mut encoder = device.create_command_encoder(&Default::default());
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
 
-        let num_workgroups = (dst_size + 15) / 16;
+        let num_workgroups = self.texture.width().div_ceil(16);
         pass.set_pipeline(&hdr_loader.equirect_to_cubemap);
         pass.set_bind_group(0, &bind_group, &[]);
         pass.dispatch_workgroups(num_workgroups, num_workgroups, 6);
@@ -265,9 +222,481 @@ impl CubeTexture {
         drop(pass);
 
         queue.submit([encoder.finish()]);
+    }
+}
+
+/// Decodes a Radiance HDR image into linear RGBA pixels, returning its width and height too.
+pub fn read_hdr_pixels(data: &[u8]) -> ImageResult<(u32, u32, Vec<[f32; 4]>)> {
+    let hdr_decoder = HdrDecoder::new(Cursor::new(data))?;
+    let HdrMetadata { width, height, .. } = hdr_decoder.metadata();
+    let mut pixels = vec![[0.0, 0.0, 0.0, 0.0]; width as usize * height as usize];
+    hdr_decoder.read_image_transform(
+        |pix| {
+            let rgb = pix.to_hdr();
+            [rgb.0[0], rgb.0[1], rgb.0[2], 1.0f32]
+        },
+        &mut pixels[..],
+    )?;
+
+    Ok((width, height, pixels))
+}
+
+/// How much brighter than the average of the sky its brightest spot has to be to count as a sun.
+const SUN_CONTRAST: f32 = 100.0;
+/// The fraction of the brightest pixel's luminance the rest of the sun's disk has to reach.
+const SUN_THRESHOLD: f32 = 0.1;
+/// How far from the brightest pixel the sun's disk is looked for, in radians.
+const MAX_SUN_RADIUS: f32 = 0.1;
+
+/// The sun of an HDRI: a small spot far brighter than the rest of the sky.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sun {
+    /// Towards the center of the disk, as the sky's cube map is sampled.
+    pub direction: [f32; 3],
+    /// In radians.
+    pub angular_radius: f32,
+}
+
+fn luminance(pixel: &[f32; 4]) -> f32 {
+    0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2]
+}
+
+fn latitude(height: u32, y: u32) -> f32 {
+    ((y as f32 + 0.5) / height as f32 - 0.5) * PI
+}
+
+/// How much of the sphere a pixel of a `width` x `height` equirectangular image covers. Rows
+/// near the poles cover less.
+fn pixel_solid_angle(width: u32, height: u32, y: u32) -> f32 {
+    (2.0 * PI / width as f32) * (PI / height as f32) * latitude(height, y).cos()
+}
+
+/// Towards the pixel at `x`, `y`, as the sky's cube map is sampled. The inverse of the mapping
+/// the cube map is made with, which flips it vertically.
+fn pixel_direction(width: u32, height: u32, x: u32, y: u32) -> Vector3<f32> {
+    let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+    let latitude = latitude(height, y);
+    Vector3::new(
+        latitude.cos() * longitude.cos(),
+        -latitude.sin(),
+        latitude.cos() * longitude.sin(),
+    )
+}
+
+/// Finds the sun in an equirectangular image decoded by [`read_hdr_pixels`], if it has one.
+pub fn find_sun(width: u32, height: u32, pixels: &[[f32; 4]]) -> Option<Sun> {
+    let solid_angle = |y: u32| pixel_solid_angle(width, height, y);
+    let direction = |x: u32, y: u32| pixel_direction(width, height, x, y);
+    let pixel = |index: usize| (index as u32 % width, index as u32 / width);
+
+    let (brightest, peak) = pixels
+        .iter()
+        .map(luminance)
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let total: f32 = pixels
+        .iter()
+        .enumerate()
+        .map(|(index, p)| luminance(p) * solid_angle(pixel(index).1))
+        .sum();
+    let average = total / (4.0 * PI);
+    if peak <= SUN_CONTRAST * average {
+        return None;
+    }
+
+    let (x, y) = pixel(brightest);
+    let center = direction(x, y);
+    let mut weighted_direction = Vector3::new(0.0, 0.0, 0.0);
+    let mut disk = 0.0;
+    for (index, p) in pixels.iter().enumerate() {
+        let luminance = luminance(p);
+        if luminance < SUN_THRESHOLD * peak {
+            continue;
+        }
+        let (x, y) = pixel(index);
+        let direction = direction(x, y);
+        if direction.dot(center) < MAX_SUN_RADIUS.cos() {
+            continue;
+        }
+        weighted_direction += direction * luminance * solid_angle(y);
+        disk += solid_angle(y);
+    }
+
+    // Never smaller than the brightest pixel
+    let pixel_radius = PI / height as f32 / 2.0;
+    Some(Sun {
+        direction: weighted_direction.normalize().into(),
+        angular_radius: (disk / PI).sqrt().max(pixel_radius),
+    })
+}
+
+/// The cells across and down an equirectangular image that [`find_key_lights`] adds its light
+/// up over. Coarse enough that a sun or a window falls into one or two.
+const KEY_LIGHT_CELLS: (u32, u32) = (32, 16);
+/// How many of the brightest cells [`find_key_lights`] lists.
+const MAX_KEY_LIGHTS: usize = 4;
+/// How far apart the cells [`find_key_lights`] lists have to be, in radians, so a bright area
+/// spanning neighbouring cells is listed once.
+const MIN_KEY_LIGHT_SEPARATION: f32 = 0.5;
+
+/// A direction much of an HDRI's light comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyLight {
+    /// Towards the light, as the sky's cube map is sampled.
+    pub direction: [f32; 3],
+    /// The fraction of the HDRI's light coming from around the direction.
+    pub share: f32,
+}
+
+/// The directions most of the light of an equirectangular image decoded by
+/// [`read_hdr_pixels`] comes from, brightest first. Its light is added up in a histogram over
+/// a coarse grid of cells, and the brightest cells apart from brighter ones are picked.
+pub fn find_key_lights(width: u32, height: u32, pixels: &[[f32; 4]]) -> Vec<KeyLight> {
+    let (columns, rows) = KEY_LIGHT_CELLS;
+    let mut power = vec![0.0; (columns * rows) as usize];
+    let mut weighted_directions = vec![Vector3::new(0.0, 0.0, 0.0); power.len()];
+    for (index, p) in pixels.iter().enumerate() {
+        let (x, y) = (index as u32 % width, index as u32 / width);
+        let cell = ((y * rows / height) * columns + x * columns / width) as usize;
+        let light = luminance(p).max(0.0) * pixel_solid_angle(width, height, y);
+        power[cell] += light;
+        weighted_directions[cell] += pixel_direction(width, height, x, y) * light;
+    }
+
+    let total: f32 = power.iter().sum();
+    if total <= 0.0 {
+        return Vec::new();
+    }
+    let mut cells = (0..power.len()).collect::<Vec<_>>();
+    cells.sort_by(|&a, &b| power[b].total_cmp(&power[a]));
+
+    let mut key_lights: Vec<KeyLight> = Vec::new();
+    for cell in cells {
+        if key_lights.len() == MAX_KEY_LIGHTS || power[cell] <= 0.0 {
+            break;
+        }
+        let direction = weighted_directions[cell].normalize();
+        let is_apart = key_lights.iter().all(|key_light| {
+            direction.dot(key_light.direction.into()) < MIN_KEY_LIGHT_SEPARATION.cos()
+        });
+        if is_apart {
+            key_lights.push(KeyLight {
+                direction: direction.into(),
+                share: power[cell] / total,
+            });
+        }
+    }
+    key_lights
+}
+
+/// Text written into exported images as keywords and values, so a render can be traced back to
+/// what it was rendered from.
+pub type ImageMetadata = Vec<(String, String)>;
+
+/// Writes linear RGBA pixels as an 8-bit sRGB PNG, clipping anything brighter than 1.0, with
+/// `metadata` in text chunks.
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+    metadata: &ImageMetadata,
+) -> ImageResult<()> {
+    let encode = |linear: f32| {
+        let linear = linear.clamp(0.0, 1.0);
+        let srgb = if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    };
+    let bytes = pixels
+        .iter()
+        .flat_map(|&[r, g, b, a]| {
+            [
+                encode(r),
+                encode(g),
+                encode(b),
+                (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let to_image_error =
+        |e: png::EncodingError| encoding_error(image::ImageFormat::Png, Box::new(e));
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in metadata {
+        // tEXt chunks are Latin-1, so anything else goes in a UTF-8 iTXt chunk
+        let added = if text.is_ascii() {
+            encoder.add_text_chunk(keyword.clone(), text.clone())
+        } else {
+            encoder.add_itxt_chunk(keyword.clone(), text.clone())
+        };
+        added.map_err(to_image_error)?;
+    }
+    let mut writer = encoder.write_header().map_err(to_image_error)?;
+    writer.write_image_data(&bytes).map_err(to_image_error)?;
+    writer.finish().map_err(to_image_error)
+}
+
+/// The primaries OpenEXRs are exported in. Renders are linear Rec. 709, converted on export for
+/// pipelines that work in ACES.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrColorSpace {
+    #[default]
+    LinearRec709,
+    /// ACEScg, on the AP1 primaries, which compositing and grading work in.
+    AcesCg,
+    /// ACES2065-1, on the AP0 primaries, for interchange and archiving.
+    Aces2065_1,
+}
 
-        Ok(dst)
+impl ExrColorSpace {
+    pub const ALL: [Self; 3] = [Self::LinearRec709, Self::AcesCg, Self::Aces2065_1];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LinearRec709 => "Linear Rec. 709",
+            Self::AcesCg => "ACEScg",
+            Self::Aces2065_1 => "ACES2065-1",
+        }
+    }
+
+    /// Converts linear Rec. 709 to this color space, with the Bradford adaptation from D65 to
+    /// the ACES white point.
+    pub fn convert(self, rgb: [f32; 3]) -> [f32; 3] {
+        let matrix = match self {
+            Self::LinearRec709 => return rgb,
+            Self::AcesCg => [
+                [0.613_132_4, 0.339_538_03, 0.047_416_7],
+                [0.070_124_38, 0.916_394, 0.013_451_524],
+                [0.020_587_658, 0.109_574_57, 0.869_785_4],
+            ],
+            Self::Aces2065_1 => [
+                [0.439_701, 0.382_978, 0.177_335],
+                [0.089_792_3, 0.813_423, 0.096_761_6],
+                [0.017_544, 0.111_544, 0.870_704],
+            ],
+        };
+        matrix.map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+    }
+
+    /// The red, green, blue and white points, written to the header so readers know the
+    /// primaries.
+    fn chromaticities(self) -> exr::meta::attribute::Chromaticities {
+        use exr::prelude::Vec2;
+
+        let ([red, green, blue], white) = match self {
+            Self::LinearRec709 => ([(0.64, 0.33), (0.30, 0.60), (0.15, 0.06)], (0.3127, 0.3290)),
+            Self::AcesCg => (
+                [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044)],
+                (0.32168, 0.33767),
+            ),
+            Self::Aces2065_1 => (
+                [(0.7347, 0.2653), (0.0, 1.0), (0.0001, -0.077)],
+                (0.32168, 0.33767),
+            ),
+        };
+        let point = |(x, y)| Vec2(x, y);
+        exr::meta::attribute::Chromaticities {
+            red: point(red),
+            green: point(green),
+            blue: point(blue),
+            white: point(white),
+        }
+    }
+}
+
+/// Writes linear RGBA pixels as a 32-bit float OpenEXR in `color_space`, keeping everything
+/// brighter than 1.0, with `metadata` in text attributes of the header.
+pub fn write_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+    color_space: ExrColorSpace,
+    metadata: &ImageMetadata,
+) -> ImageResult<()> {
+    use exr::prelude::{AttributeValue, Image, SpecificChannels, Text, Vec2, WritableImage};
+
+    let channels = SpecificChannels::rgba(|Vec2(x, y): Vec2<usize>| {
+        let [r, g, b, a] = pixels[y * width as usize + x];
+        let [r, g, b] = color_space.convert([r, g, b]);
+        (r, g, b, a)
+    });
+    let mut image = Image::from_channels((width as usize, height as usize), channels);
+    image.attributes.chromaticities = Some(color_space.chromaticities());
+    for (name, text) in metadata {
+        // EXR text is Latin-1
+        let latin1 = |text: &str| {
+            let text = text
+                .chars()
+                .map(|c| if (c as u32) < 256 { c } else { '?' })
+                .collect::<String>();
+            Text::new_or_panic(text)
+        };
+        image
+            .layer_data
+            .attributes
+            .other
+            .insert(latin1(name), AttributeValue::Text(latin1(text)));
+    }
+    image
+        .write()
+        .to_file(path)
+        .map_err(|e| encoding_error(image::ImageFormat::OpenExr, Box::new(e)))
+}
+
+fn encoding_error(
+    format: image::ImageFormat,
+    error: Box<dyn std::error::Error + Send + Sync>,
+) -> image::ImageError {
+    image::ImageError::Encoding(image::error::EncodingError::new(format.into(), error))
+}
+
+/// The default [`TextureBudget`] in mebibytes, leaving room for the renderer's own buffers on a
+/// 4 GB GPU.
+pub const DEFAULT_TEXTURE_BUDGET: u32 = 2048;
+
+/// A limit on the GPU memory used by imported textures and HDRIs, which are shrunk on load to
+/// fit rather than failing to allocate.
+pub struct TextureBudget {
+    /// In mebibytes.
+    pub limit: u32,
+    /// The bytes used by each imported image, by name.
+    used: HashMap<String, u64>,
+    /// The images that were shrunk to fit, oldest first.
+    reductions: Vec<Reduction>,
+}
+
+/// An image loaded at a lower resolution than the file's, to stay within a [`TextureBudget`].
+pub struct Reduction {
+    pub name: String,
+    pub original: (u32, u32),
+    pub reduced: (u32, u32),
+}
+
+impl TextureBudget {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            used: HashMap::new(),
+            reductions: Vec::new(),
+        }
+    }
+
+    /// The bytes `name` may use alongside everything else imported, not counting what it replaces.
+    pub fn available(&self, name: &str) -> u64 {
+        let others: u64 = self
+            .used
+            .iter()
+            .filter(|(used_by, _)| *used_by != name)
+            .map(|(_, bytes)| bytes)
+            .sum();
+        (self.limit as u64 * 1024 * 1024).saturating_sub(others)
+    }
+
+    /// Records that `name` was loaded at `reduced` pixels from an image of `original`, reporting
+    /// it if it had to be shrunk.
+    pub fn record(
+        &mut self,
+        name: &str,
+        original: (u32, u32),
+        reduced: (u32, u32),
+        bytes_per_pixel: u64,
+    ) {
+        self.used.insert(
+            name.to_string(),
+            reduced.0 as u64 * reduced.1 as u64 * bytes_per_pixel,
+        );
+        self.reductions.retain(|reduction| reduction.name != name);
+        if reduced != original {
+            eprintln!(
+                "Loaded {} at {}x{} instead of {}x{} to fit the texture budget",
+                name, reduced.0, reduced.1, original.0, original.1
+            );
+            self.reductions.push(Reduction {
+                name: name.to_string(),
+                original,
+                reduced,
+            });
+        }
+    }
+
+    pub fn render_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Texture memory", |ui| {
+            ui.add(
+                egui::Slider::new(&mut self.limit, 256..=16384)
+                    .logarithmic(true)
+                    .text("budget (MiB)"),
+            )
+            .on_hover_text(
+                "Imported textures and HDRIs are loaded at a lower resolution when they would \
+                exceed this. Applies to the next images loaded.",
+            );
+            let used: u64 = self.used.values().sum();
+            ui.label(format!("{:.1} MiB in use", used as f64 / (1024.0 * 1024.0)));
+            for reduction in &self.reductions {
+                ui.label(format!(
+                    "{}: {}x{} → {}x{}",
+                    reduction.name,
+                    reduction.original.0,
+                    reduction.original.1,
+                    reduction.reduced.0,
+                    reduction.reduced.1
+                ));
+            }
+        });
+    }
+}
+
+/// How many times an image of `width` by `height` has to be halved to fit in `available` bytes,
+/// stopping at a single pixel.
+pub fn halvings_to_fit(width: u32, height: u32, bytes_per_pixel: u64, available: u64) -> u32 {
+    let (mut width, mut height) = (width, height);
+    let mut halvings = 0;
+    while width as u64 * height as u64 * bytes_per_pixel > available && (width > 1 || height > 1) {
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+        halvings += 1;
+    }
+    halvings
+}
+
+/// Halves the resolution of linear pixels `halvings` times by averaging blocks of 2x2 pixels,
+/// returning the new width and height too.
+pub fn downscale_pixels(
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+    halvings: u32,
+) -> (u32, u32, Vec<[f32; 4]>) {
+    let (mut width, mut height, mut pixels) = (width, height, pixels);
+    for _ in 0..halvings {
+        let (new_width, new_height) = ((width / 2).max(1), (height / 2).max(1));
+        let pixel =
+            |x: u32, y: u32| pixels[(y.min(height - 1) * width + x.min(width - 1)) as usize];
+        let mut downscaled = Vec::with_capacity(new_width as usize * new_height as usize);
+        for y in 0..new_height {
+            for x in 0..new_width {
+                let block = [
+                    pixel(2 * x, 2 * y),
+                    pixel(2 * x + 1, 2 * y),
+                    pixel(2 * x, 2 * y + 1),
+                    pixel(2 * x + 1, 2 * y + 1),
+                ];
+                downscaled.push(std::array::from_fn(|channel| {
+                    block.iter().map(|p| p[channel]).sum::<f32>() / 4.0
+                }));
+            }
+        }
+        (width, height, pixels) = (new_width, new_height, downscaled);
     }
+    (width, height, pixels)
 }
 
 pub struct HdrLoader {
@@ -278,7 +707,7 @@ pub struct HdrLoader {
 
 impl HdrLoader {
     pub fn new(device: &wgpu::Device) -> Self {
-        let src = utils::load_shader_source(Path::new("shaders"), "equirectangular.wgsl").unwrap();
+        let src = utils::load_shader_source("equirectangular.wgsl").unwrap();
 
         let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("compute"),
@@ -0,0 +1,138 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use image::{ImageResult, Rgb, RgbImage};
+
+use crate::{assets, texture};
+
+pub const THUMBNAIL_WIDTH: u32 = 128;
+pub const THUMBNAIL_HEIGHT: u32 = 64;
+
+/// Returns the thumbnail for the file at `path` from the disk cache, or generates and caches it
+/// if the file is new or changed since the thumbnail was made.
+pub fn cached(
+    path: &Path,
+    generate: impl FnOnce(&[u8]) -> ImageResult<RgbImage>,
+) -> ImageResult<RgbImage> {
+    if let Some(thumbnail) = cached_only(path) {
+        return Ok(thumbnail);
+    }
+
+    let thumbnail = generate(&fs::read(path)?)?;
+    if let Err(e) = store(path, &thumbnail) {
+        log::warn!("Failed to cache thumbnail for {}: {}", path.display(), e);
+    }
+
+    Ok(thumbnail)
+}
+
+/// Returns the thumbnail for the file at `path` from the disk cache, if one was made since the
+/// file last changed. For files a thumbnail can't be generated from, such as scenes, which
+/// are only stored when they're rendered.
+pub fn cached_only(path: &Path) -> Option<RgbImage> {
+    let thumbnail = image::open(cache_path(path).ok()?).ok()?;
+    Some(thumbnail.into_rgb8())
+}
+
+/// Caches `thumbnail` for the file at `path` as it is now.
+pub fn store(path: &Path, thumbnail: &RgbImage) -> ImageResult<()> {
+    fs::create_dir_all(cache_directory())?;
+    thumbnail.save(cache_path(path)?)
+}
+
+/// Uploads `thumbnail` for egui to show.
+pub fn texture(context: &egui::Context, name: &str, thumbnail: &RgbImage) -> egui::TextureHandle {
+    let image = egui::ColorImage::from_rgb(
+        [thumbnail.width() as usize, thumbnail.height() as usize],
+        thumbnail.as_raw(),
+    );
+    context.load_texture(name, image, egui::TextureOptions::LINEAR)
+}
+
+/// The cached thumbnail's name includes a hash of the file's path, size and modification time,
+/// so edited files get a new thumbnail.
+fn cache_path(path: &Path) -> std::io::Result<PathBuf> {
+    let metadata = fs::metadata(path)?;
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified()?.hash(&mut hasher);
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    Ok(cache_directory().join(format!("{}-{:016x}.png", stem, hasher.finish())))
+}
+
+fn cache_directory() -> PathBuf {
+    assets::cache_dir().join("thumbnails")
+}
+
+/// Downsamples an equirectangular HDRI and tone maps it for display.
+pub fn hdri(data: &[u8]) -> ImageResult<RgbImage> {
+    let (width, height, pixels) = texture::read_hdr_pixels(data)?;
+
+    Ok(downsample(
+        (0, 0, width, height),
+        |x, y| pixels[(y * width + x) as usize],
+        // Reinhard, then gamma
+        |c| ((c / (1.0 + c)).powf(1.0 / 2.2) * 255.0).round() as u8,
+    ))
+}
+
+/// Downsamples a render of `width` × `height` `pixels`, already tone mapped for display, cropped
+/// to the thumbnail's aspect ratio around its center.
+pub fn render(width: u32, height: u32, pixels: &[[f32; 4]]) -> RgbImage {
+    let aspect = THUMBNAIL_WIDTH as f32 / THUMBNAIL_HEIGHT as f32;
+    let crop_width = width.min((height as f32 * aspect).round() as u32).max(1);
+    let crop_height = height.min((width as f32 / aspect).round() as u32).max(1);
+    let crop = (
+        (width - crop_width.min(width)) / 2,
+        (height - crop_height.min(height)) / 2,
+        crop_width,
+        crop_height,
+    );
+
+    downsample(
+        crop,
+        |x, y| pixels[(y * width + x) as usize],
+        |linear| {
+            let linear = linear.clamp(0.0, 1.0);
+            let srgb = if linear <= 0.0031308 {
+                linear * 12.92
+            } else {
+                1.055 * linear.powf(1.0 / 2.4) - 0.055
+            };
+            (srgb * 255.0).round() as u8
+        },
+    )
+}
+
+/// Averages the block of the `(x, y, width, height)` rectangle of `pixel`s covered by each
+/// thumbnail pixel, then encodes its color channels with `encode`.
+fn downsample(
+    (left, top, width, height): (u32, u32, u32, u32),
+    pixel: impl Fn(u32, u32) -> [f32; 4],
+    encode: impl Fn(f32) -> u8,
+) -> RgbImage {
+    RgbImage::from_fn(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, |x, y| {
+        // Average the block of source pixels covered by this thumbnail pixel
+        let x_start = x * width / THUMBNAIL_WIDTH;
+        let y_start = y * height / THUMBNAIL_HEIGHT;
+        let x_range = x_start..((x + 1) * width / THUMBNAIL_WIDTH).max(x_start + 1);
+        let y_range = y_start..((y + 1) * height / THUMBNAIL_HEIGHT).max(y_start + 1);
+        let count = (x_range.len() * y_range.len()) as f32;
+
+        let mut sum = [0.0; 3];
+        for sy in y_range {
+            for sx in x_range.clone() {
+                let pixel = pixel(left + sx.min(width - 1), top + sy.min(height - 1));
+                sum.iter_mut().zip(pixel).for_each(|(s, p)| *s += p);
+            }
+        }
+
+        Rgb(sum.map(|c| encode(c / count)))
+    })
+}
@@ -1,5 +1,11 @@
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+};
 
+use accesskit_winit::Adapter;
+use cgmath::Vector3;
+use egui::accesskit::{ActionHandler, ActionRequest};
 use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
 use egui_winit_platform::{Platform, PlatformDescriptor};
 use wgpu::{CommandEncoder, Device, Queue, SurfaceTexture, TextureFormat};
@@ -7,11 +13,18 @@ use winit::{event::Event, window::Window};
 
 pub struct Ui {
     pub platform: RefCell<Platform>,
-    render_pass: RenderPass,
+    /// One render pass per output format the UI may be drawn to, all sharing the same textures.
+    render_passes: Vec<(TextureFormat, RenderPass)>,
+    /// Exposes the UI to screen readers and other assistive technologies.
+    accesskit: Adapter,
+    action_requests: Arc<Mutex<Vec<ActionRequest>>>,
+    pub palette: Palette,
+    ui_scale: f32,
+    large_hit_targets: bool,
 }
 
 impl Ui {
-    pub fn new(window: &Window, device: &Device, surface_format: TextureFormat) -> Self {
+    pub fn new(window: &Window, device: &Device, surface_formats: &[TextureFormat]) -> Self {
         let window_size = window.inner_size();
         let platform = Platform::new(PlatformDescriptor {
             physical_width: window_size.width,
@@ -19,11 +32,30 @@ impl Ui {
             scale_factor: window.scale_factor(),
             ..Default::default()
         });
-        let render_pass = RenderPass::new(device, surface_format, 1);
+        let render_passes = surface_formats
+            .iter()
+            .map(|&format| (format, RenderPass::new(device, format, 1)))
+            .collect();
+
+        let context = platform.context();
+        let action_requests = Arc::new(Mutex::new(Vec::new()));
+        let accesskit = Adapter::with_action_handler(
+            window,
+            move || {
+                context.enable_accesskit();
+                context.accesskit_placeholder_tree_update()
+            },
+            Box::new(QueuedActionHandler(action_requests.clone())),
+        );
 
         Self {
             platform: RefCell::new(platform),
-            render_pass,
+            render_passes,
+            accesskit,
+            action_requests,
+            palette: Palette::default(),
+            ui_scale: 1.0,
+            large_hit_targets: false,
         }
     }
 
@@ -40,39 +72,216 @@ impl Ui {
             .create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut platform = self.platform.borrow_mut();
-        let full_output = platform.end_frame(Some(window));
+        let mut full_output = platform.end_frame(Some(window));
         let paint_jobs = platform.context().tessellate(full_output.shapes);
 
+        if let Some(update) = full_output.platform_output.accesskit_update.take() {
+            self.accesskit.update(update);
+        }
+
         let screen_descriptor = ScreenDescriptor {
             physical_width: output.texture.width(),
             physical_height: output.texture.height(),
             scale_factor: window.scale_factor() as f32,
         };
 
-        self.render_pass
-            .add_textures(device, queue, &full_output.textures_delta)
-            .expect("error adding textures");
-        self.render_pass
-            .update_buffers(device, queue, &paint_jobs, &screen_descriptor);
-        self.render_pass
+        for (_, render_pass) in &mut self.render_passes {
+            render_pass
+                .add_textures(device, queue, &full_output.textures_delta)
+                .expect("error adding textures");
+        }
+
+        let format = output.texture.format();
+        let (_, render_pass) = self
+            .render_passes
+            .iter_mut()
+            .find(|(f, _)| *f == format)
+            .expect("no UI render pass for the output format");
+        render_pass.update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+        render_pass
             .execute(encoder, &view, &paint_jobs, &screen_descriptor, None)
             .unwrap();
-        self.render_pass
-            .remove_textures(full_output.textures_delta)
-            .expect("error removing textures");
+
+        for (_, render_pass) in &mut self.render_passes {
+            render_pass
+                .remove_textures(full_output.textures_delta.clone())
+                .expect("error removing textures");
+        }
+    }
+
+    /// Makes a wgpu texture available for drawing in the UI, e.g. with [`egui::Ui::image`].
+    pub fn register_native_texture(
+        &mut self,
+        device: &Device,
+        texture: &wgpu::TextureView,
+    ) -> egui::TextureId {
+        let ids = self
+            .render_passes
+            .iter_mut()
+            .map(|(_, render_pass)| {
+                render_pass.egui_texture_from_wgpu_texture(
+                    device,
+                    texture,
+                    wgpu::FilterMode::Linear,
+                )
+            })
+            .collect::<Vec<_>>();
+        // Every render pass hands out ids in the same order
+        assert!(ids.windows(2).all(|ids| ids[0] == ids[1]));
+
+        ids[0]
+    }
+
+    pub fn render_accessibility_ui(&mut self, ui: &mut egui::Ui) {
+        let mut style_changed = false;
+
+        ui.collapsing("Accessibility", |ui| {
+            egui::ComboBox::from_label("palette")
+                .selected_text(self.palette.name())
+                .show_ui(ui, |ui| {
+                    for palette in Palette::ALL {
+                        ui.selectable_value(&mut self.palette, palette, palette.name());
+                    }
+                })
+                .response
+                .on_hover_text("Colors used for the selection outline, gizmo and debug overlays");
+
+            style_changed |= ui
+                .add(egui::Slider::new(&mut self.ui_scale, 0.75..=2.0).text("UI scale"))
+                .changed();
+            style_changed |= ui
+                .checkbox(&mut self.large_hit_targets, "large hit targets")
+                .changed();
+
+            ui.label("Tab moves between controls, or hides the panels when none is focused. [ and ] cycle \
+                the selected object.");
+        });
+
+        if style_changed {
+            ui.ctx().set_style(self.style());
+        }
+    }
+
+    fn style(&self) -> egui::Style {
+        let mut style = egui::Style::default();
+
+        for font_id in style.text_styles.values_mut() {
+            font_id.size *= self.ui_scale;
+        }
+
+        let spacing = &mut style.spacing;
+        if self.large_hit_targets {
+            spacing.interact_size.y = spacing.interact_size.y.max(32.0);
+            spacing.button_padding *= 2.0;
+            spacing.icon_width *= 1.5;
+            spacing.icon_width_inner *= 1.5;
+        }
+        spacing.item_spacing *= self.ui_scale;
+        spacing.button_padding *= self.ui_scale;
+        spacing.interact_size *= self.ui_scale;
+        spacing.icon_width *= self.ui_scale;
+        spacing.icon_width_inner *= self.ui_scale;
+        spacing.slider_width *= self.ui_scale;
+
+        style
     }
 
     pub fn begin_new_frame(&mut self, time: f64) {
         let mut platform = self.platform.borrow_mut();
         platform.update_time(time);
+
+        let action_requests = std::mem::take(&mut *self.action_requests.lock().unwrap());
+        platform.raw_input_mut().events.extend(
+            action_requests
+                .into_iter()
+                .map(egui::Event::AccessKitActionRequest),
+        );
+
         platform.begin_frame();
     }
 
-    pub fn handle_event(&mut self, event: &Event<()>) {
+    pub fn handle_event(&mut self, event: &Event<()>, window: &Window) {
+        if let Event::WindowEvent {
+            event: window_event,
+            window_id,
+        } = event
+        {
+            if *window_id == window.id() && !self.accesskit.on_event(window, window_event) {
+                return;
+            }
+        }
+
         self.platform.borrow_mut().handle_event(event);
     }
 
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.platform.borrow().context().wants_keyboard_input()
+    }
+
     pub fn contains_mouse(&self) -> bool {
         self.platform.borrow().context().is_pointer_over_area()
     }
 }
+
+/// Collects the actions requested by assistive technologies, which may arrive on any thread,
+/// until they are passed to egui at the start of the next frame.
+struct QueuedActionHandler(Arc<Mutex<Vec<ActionRequest>>>);
+
+impl ActionHandler for QueuedActionHandler {
+    fn do_action(&self, request: ActionRequest) {
+        self.0.lock().unwrap().push(request);
+    }
+}
+
+/// Color schemes for overlays, including ones that stay distinguishable with color vision
+/// deficiencies.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Palette {
+    #[default]
+    Default,
+    /// Okabe & Ito's palette, safe for protanopia, deuteranopia and tritanopia.
+    OkabeIto,
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::Default, Palette::OkabeIto, Palette::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Default => "Default",
+            Palette::OkabeIto => "Okabe-Ito (color-blind safe)",
+            Palette::HighContrast => "High contrast",
+        }
+    }
+
+    /// The color the selection is outlined in.
+    pub fn highlight(&self) -> Vector3<f32> {
+        match self {
+            Palette::Default => Vector3::new(1.0, 0.6, 0.0),
+            Palette::OkabeIto => Vector3::new(0.34, 0.71, 0.91),
+            Palette::HighContrast => Vector3::new(1.0, 0.0, 1.0),
+        }
+    }
+
+    /// The colors of the gizmo's X, Y and Z handles.
+    pub fn axes(&self) -> [Vector3<f32>; 3] {
+        match self {
+            Palette::Default => [
+                Vector3::new(0.9, 0.2, 0.2),
+                Vector3::new(0.3, 0.8, 0.2),
+                Vector3::new(0.2, 0.4, 0.95),
+            ],
+            Palette::OkabeIto => [
+                Vector3::new(0.84, 0.37, 0.0),
+                Vector3::new(0.0, 0.62, 0.45),
+                Vector3::new(0.0, 0.45, 0.7),
+            ],
+            Palette::HighContrast => [
+                Vector3::new(1.0, 0.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                Vector3::new(0.0, 0.6, 1.0),
+            ],
+        }
+    }
+}
@@ -1,24 +1,77 @@
-use std::path::Path;
-
-pub fn load_shader_source(shaders_root: &Path, name: &str) -> Result<String, std::io::Error> {
-    let path = std::path::Path::new(shaders_root).join(name);
-    let src = std::fs::read_to_string(path)?
-        .lines()
-        .map(|line| {
-            if line.starts_with("//!include") {
-                let path = line
-                    .split_whitespace()
-                    .nth(1)
-                    .expect("invalid include statement")
-                    .replace('"', "");
-                load_shader_source(&Path::new(shaders_root).join("include"), &path)
-            } else {
-                Ok(line.to_owned())
-            }
-        })
-        .collect::<Result<Vec<_>, _>>()?
-        .join("\n");
-
-    Ok(src)
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    assets,
+    shader_preprocessor::{preprocess_shader, PreprocessError},
+};
+
+/// Preprocesses the shader `name` from the installed `shaders` directory.
+pub fn load_shader_source(name: &str) -> Result<String, PreprocessError> {
+    preprocess_shader(&assets::resolve("shaders"), name, &[]).map(|shader| shader.source)
+}
+
+/// Why a shader couldn't be loaded or compiled.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub shader: String,
+    pub message: String,
 }
 
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.shader, self.message)
+    }
+}
+
+/// Shader modules preprocessed by [`preprocess_shader`], by file and defines, so each variant is
+/// only compiled once.
+pub struct ShaderCache {
+    shaders_root: PathBuf,
+    modules: HashMap<(String, Vec<String>), wgpu::ShaderModule>,
+}
+
+impl ShaderCache {
+    pub fn new(shaders_root: impl Into<PathBuf>) -> Self {
+        Self {
+            shaders_root: shaders_root.into(),
+            modules: HashMap::new(),
+        }
+    }
+
+    /// Compiles the variant of the shader `name` with `defines` the first time it's asked for,
+    /// after validating it so errors can be shown rather than panicking inside wgpu.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        defines: &[&str],
+    ) -> Result<&wgpu::ShaderModule, ShaderError> {
+        let mut sorted_defines: Vec<String> =
+            defines.iter().map(|define| define.to_string()).collect();
+        sorted_defines.sort();
+        sorted_defines.dedup();
+        let key = (name.to_owned(), sorted_defines);
+
+        if !self.modules.contains_key(&key) {
+            let error = |message: String| ShaderError {
+                shader: if defines.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{} ({})", name, defines.join(", "))
+                },
+                message,
+            };
+            let shader = preprocess_shader(&self.shaders_root, name, defines)
+                .map_err(|e| error(e.to_string()))?;
+            shader.validate().map_err(error)?;
+
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(shader.source.into()),
+            });
+            self.modules.insert(key.clone(), module);
+        }
+
+        Ok(&self.modules[&key])
+    }
+}
@@ -0,0 +1,240 @@
+use cgmath::Vector3;
+use pathtracer::geometry::{self, Bounded, Bvh, BvhBuilder, Primitive, Ray};
+use proptest::{prelude::*, test_runner::TestCaseError};
+
+mod common;
+
+use common::{direction, vector};
+
+const T_MAX: f32 = 1.0e6;
+
+/// One of the six directions along the axes, which have zeros the slab test has to cope with.
+fn axis_direction() -> impl Strategy<Value = Vector3<f32>> {
+    (0..3usize, prop::bool::ANY).prop_map(|(axis, negative)| {
+        let mut direction = Vector3::new(0.0, 0.0, 0.0);
+        direction[axis] = if negative { -1.0 } else { 1.0 };
+        direction
+    })
+}
+
+fn builder() -> impl Strategy<Value = BvhBuilder> {
+    prop_oneof![Just(BvhBuilder::Midpoint), Just(BvhBuilder::BinnedSah)]
+}
+
+/// Any three points, including ones in a line or on top of each other.
+fn soup(size: std::ops::Range<usize>) -> impl Strategy<Value = Vec<[Vector3<f32>; 3]>> {
+    prop::collection::vec(
+        [
+            vector(-10.0..10.0),
+            vector(-10.0..10.0),
+            vector(-10.0..10.0),
+        ],
+        size,
+    )
+}
+
+/// Triangles all in the plane `z = 0`, so every node is flat.
+fn coplanar_soup() -> impl Strategy<Value = Vec<[Vector3<f32>; 3]>> {
+    soup(1..64).prop_map(|triangles| {
+        triangles
+            .into_iter()
+            .map(|vertices| vertices.map(|v| Vector3::new(v.x, v.y, 0.0)))
+            .collect()
+    })
+}
+
+/// Triangles sharing vertices with themselves and with copies of each other.
+fn duplicated_soup() -> impl Strategy<Value = Vec<[Vector3<f32>; 3]>> {
+    (soup(1..32), 0..3usize).prop_map(|(triangles, repeated)| {
+        triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let collapsed = match repeated {
+                    0 => [a, a, b],
+                    1 => [a, b, b],
+                    _ => [a, a, a],
+                };
+                [[a, b, c], [a, b, c], collapsed]
+            })
+            .collect()
+    })
+}
+
+/// Checks the BVH's nodes hold every triangle once, within bounds that shrink towards the
+/// leaves, and that there are no more nodes than a binary tree over them can have.
+fn check_structure(bvh: &Bvh, triangles: &[[Vector3<f32>; 3]]) -> Result<(), TestCaseError> {
+    if triangles.is_empty() {
+        prop_assert!(bvh.nodes.is_empty());
+        prop_assert!(bvh.triangle_indices.is_empty());
+        return Ok(());
+    }
+    prop_assert!(bvh.nodes.len() < 2 * triangles.len());
+
+    let mut indices = bvh.triangle_indices.clone();
+    indices.sort_unstable();
+    prop_assert!(indices.iter().copied().eq(0..triangles.len() as u32));
+
+    let mut visited = vec![false; bvh.nodes.len()];
+    let mut leaf_items = Vec::new();
+    let mut stack = vec![0];
+    while let Some(index) = stack.pop() {
+        prop_assert!(!visited[index], "node {} is reached twice", index);
+        visited[index] = true;
+        let node = &bvh.nodes[index];
+        let bounds = node.aabb();
+
+        match (node.children(), node.items()) {
+            (Some(children), None) => {
+                for child in children {
+                    let child_bounds = bvh.nodes[child].aabb();
+                    prop_assert!(bounds.contains(child_bounds.min));
+                    prop_assert!(bounds.contains(child_bounds.max));
+                    stack.push(child);
+                }
+            }
+            (None, Some(items)) => {
+                prop_assert!(!items.is_empty());
+                for &triangle in &bvh.triangle_indices[items.clone()] {
+                    for vertex in triangles[triangle as usize] {
+                        prop_assert!(bounds.contains(vertex));
+                    }
+                }
+                leaf_items.push(items);
+            }
+            _ => prop_assert!(false, "node {} is neither a leaf nor interior", index),
+        }
+    }
+    prop_assert!(visited.iter().all(|&visited| visited), "unreachable nodes");
+
+    // The leaves split the triangle indices between them without gaps or overlaps
+    leaf_items.sort_by_key(|items| items.start);
+    let mut next = 0;
+    for items in leaf_items {
+        prop_assert_eq!(items.start, next);
+        next = items.end;
+    }
+    prop_assert_eq!(next, triangles.len());
+    Ok(())
+}
+
+fn check_closest_hit(
+    bvh: &Bvh,
+    triangles: &[[Vector3<f32>; 3]],
+    ray: &Ray,
+) -> Result<(), TestCaseError> {
+    let expected = triangles
+        .iter()
+        .filter_map(|triangle| geometry::ray_triangle(ray, triangle.vertices(), 0.0, T_MAX))
+        .map(|hit| hit.t)
+        .min_by(f32::total_cmp);
+    let actual = bvh
+        .closest_hit(triangles, ray, 0.0, T_MAX)
+        .map(|(_, hit)| hit.t);
+
+    // Overlapping triangles hit at the same point can round to distances an ulp apart, and the
+    // box around the closer one can round to just past the farther one
+    match (actual, expected) {
+        (Some(actual), Some(expected)) => prop_assert!(
+            (actual - expected).abs() <= expected * 1e-5,
+            "{} != {}",
+            actual,
+            expected
+        ),
+        _ => prop_assert_eq!(actual, expected),
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn every_triangle_is_in_one_leaf_within_its_bounds(
+        triangles in soup(0..256),
+        builder in builder(),
+    ) {
+        check_structure(&Bvh::build(&triangles, builder), &triangles)?;
+    }
+
+    #[test]
+    fn bvh_over_coplanar_triangles_finds_the_same_hits_as_brute_force(
+        triangles in coplanar_soup(),
+        rays in prop::collection::vec((vector(-20.0..20.0), direction()), 8),
+        builder in builder(),
+    ) {
+        let bvh = Bvh::build(&triangles, builder);
+        check_structure(&bvh, &triangles)?;
+
+        for (origin, direction) in rays {
+            check_closest_hit(&bvh, &triangles, &Ray { origin, direction })?;
+            // Straight through the plane, and along it where nothing should be hit
+            let down = Ray { origin, direction: -Vector3::unit_z() };
+            check_closest_hit(&bvh, &triangles, &down)?;
+            let along = Ray { origin: Vector3::new(origin.x, origin.y, 0.0), direction };
+            check_closest_hit(&bvh, &triangles, &along)?;
+        }
+    }
+
+    #[test]
+    fn bvh_over_duplicated_vertices_and_triangles_finds_the_same_hits_as_brute_force(
+        triangles in duplicated_soup(),
+        rays in prop::collection::vec((vector(-20.0..20.0), direction()), 8),
+        builder in builder(),
+    ) {
+        let bvh = Bvh::build(&triangles, builder);
+        check_structure(&bvh, &triangles)?;
+
+        for (origin, direction) in rays {
+            check_closest_hit(&bvh, &triangles, &Ray { origin, direction })?;
+        }
+    }
+
+    #[test]
+    fn rays_along_the_axes_find_the_same_hits_as_brute_force(
+        triangles in soup(1..64),
+        rays in prop::collection::vec((vector(-20.0..20.0), axis_direction()), 8),
+        builder in builder(),
+    ) {
+        let bvh = Bvh::build(&triangles, builder);
+
+        for (origin, direction) in rays {
+            check_closest_hit(&bvh, &triangles, &Ray { origin, direction })?;
+        }
+    }
+}
+
+#[test]
+fn identical_triangles_stay_in_one_leaf() {
+    let triangle = [
+        Vector3::new(-1.0, -1.0, 0.0),
+        Vector3::new(1.0, -1.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+    ];
+    let triangles = vec![triangle; 16];
+
+    for builder in [BvhBuilder::Midpoint, BvhBuilder::BinnedSah] {
+        let bvh = Bvh::build(&triangles, builder);
+        check_structure(&bvh, &triangles).unwrap();
+        assert_eq!(bvh.nodes.len(), 1);
+        assert_eq!(bvh.nodes[0].aabb(), triangle.bounds());
+
+        let ray = Ray {
+            origin: Vector3::new(0.0, 0.0, 5.0),
+            direction: -Vector3::unit_z(),
+        };
+        let (_, hit) = bvh.closest_hit(&triangles, &ray, 0.0, T_MAX).unwrap();
+        assert_eq!(hit.t, 5.0);
+    }
+}
+
+#[test]
+fn empty_bvh_hits_nothing() {
+    let triangles: Vec<[Vector3<f32>; 3]> = Vec::new();
+    let bvh = Bvh::from_triangles(&triangles);
+    check_structure(&bvh, &triangles).unwrap();
+    assert_eq!(bvh.sah_cost(), 0.0);
+
+    let ray = Ray {
+        origin: Vector3::new(0.0, 0.0, 0.0),
+        direction: Vector3::unit_x(),
+    };
+    assert!(bvh.closest_hit(&triangles, &ray, 0.0, T_MAX).is_none());
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by the integration tests, each of which includes this module with `mod common`.
+
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::{fs, path::PathBuf};
+
+use cgmath::{InnerSpace, Vector3};
+use proptest::prelude::*;
+
+pub fn vector(range: std::ops::Range<f32>) -> impl Strategy<Value = Vector3<f32>> {
+    (range.clone(), range.clone(), range).prop_map(|(x, y, z)| Vector3::new(x, y, z))
+}
+
+pub fn direction() -> impl Strategy<Value = Vector3<f32>> {
+    vector(-1.0..1.0)
+        .prop_filter("non-zero", |v| v.magnitude() > 0.1)
+        .prop_map(|v| v.normalize())
+}
+
+/// An empty directory for the files of the test named `test`, which has to be unique among all
+/// of the tests.
+pub fn temp_dir(test: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join("pathtracer-tests").join(test);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
@@ -0,0 +1,90 @@
+use std::io;
+
+use pathtracer::display_lut::DisplayLut;
+
+/// A 3D table with `size` entries along each side, each made by `entry` from its position in
+/// the domain.
+fn table(size: usize, entry: impl Fn([f32; 3]) -> [f32; 3]) -> String {
+    let mut source = format!("TITLE \"Test\"\nLUT_3D_SIZE {}\n", size);
+    for i in 0..size.pow(3) {
+        let position = [i % size, i / size % size, i / (size * size)]
+            .map(|index| index as f32 / (size - 1) as f32);
+        let [r, g, b] = entry(position);
+        source += &format!("{} {} {}\n", r, g, b);
+    }
+    source
+}
+
+fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+    for (actual, expected) in actual.into_iter().zip(expected) {
+        assert!((actual - expected).abs() < 1e-5, "{:?}", actual);
+    }
+}
+
+fn assert_invalid(source: &str) {
+    let error = DisplayLut::parse(source).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+}
+
+#[test]
+fn an_identity_table_leaves_colors_in_its_domain_alone() {
+    let lut = DisplayLut::parse(&table(17, |position| position)).unwrap();
+    assert_eq!(lut.title, "Test");
+    assert_close(lut.apply([0.25, 0.5, 0.9]), [0.25, 0.5, 0.9]);
+    // Past the domain, colors are clamped to it
+    assert_close(lut.apply([2.0, -1.0, 1.0]), [1.0, 0.0, 1.0]);
+}
+
+#[test]
+fn tables_are_interpolated_between_their_entries() {
+    let lut = DisplayLut::parse(&table(2, |[r, g, b]| [g, b, r * 0.5])).unwrap();
+    assert_close(lut.apply([0.5, 0.25, 1.0]), [0.25, 1.0, 0.25]);
+}
+
+#[test]
+fn a_domain_spreads_the_table_over_radiance_above_one() {
+    let source = table(2, |position| position).replacen(
+        "LUT_3D_SIZE 2\n",
+        "LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 4 4 4\n",
+        1,
+    );
+    let lut = DisplayLut::parse(&source).unwrap();
+    assert_close(lut.apply([2.0, 1.0, 4.0]), [0.5, 0.25, 1.0]);
+}
+
+#[test]
+fn a_shaper_is_applied_before_the_table() {
+    // Resolve's layout: a shaper from 0..16 onto 0..1, then a table doubling it
+    let source = "# Baked\nLUT_1D_SIZE 2\nLUT_1D_INPUT_RANGE 0 16\nLUT_3D_SIZE 2\n\
+        0 0 0\n1 1 1\n"
+        .to_string()
+        + &table(2, |position| position.map(|x| x * 2.0))
+            .replace("TITLE \"Test\"\nLUT_3D_SIZE 2\n", "");
+    let lut = DisplayLut::parse(&source).unwrap();
+    assert_close(lut.apply([4.0, 8.0, 16.0]), [0.5, 1.0, 2.0]);
+}
+
+#[test]
+fn a_1d_lut_on_its_own_is_applied_per_channel() {
+    let lut = DisplayLut::parse("LUT_1D_SIZE 3\n0 0 0\n0.8 0.5 0.2\n1 1 1\n").unwrap();
+    assert_close(lut.apply([0.5, 0.25, 0.75]), [0.8, 0.25, 0.6]);
+}
+
+#[test]
+fn malformed_luts_are_rejected() {
+    // No size
+    assert_invalid("TITLE \"Empty\"\n");
+    // Too few rows
+    assert_invalid("LUT_3D_SIZE 2\n0 0 0\n1 1 1\n");
+    // Rows of two numbers
+    assert_invalid(&table(2, |position| position).replace("1 1 1\n", "1 1\n"));
+    // A size past what the shader can sample
+    assert_invalid("LUT_3D_SIZE 1000\n");
+    // An empty domain
+    assert_invalid(&table(2, |position| position).replacen(
+        "LUT_3D_SIZE 2\n",
+        "LUT_3D_SIZE 2\nDOMAIN_MIN 1 0 0\nDOMAIN_MAX 1 1 1\n",
+        1,
+    ));
+    assert_invalid("LUT_1D_SIZE 2\nUNKNOWN 1\n0 0 0\n1 1 1\n");
+}
@@ -0,0 +1,220 @@
+use cgmath::{Vector2, Vector3};
+use pathtracer::{
+    expression::{Expression, ExpressionError, Inputs, MAX_LOCALS, MAX_STACK_DEPTH},
+    scene::Canvas,
+};
+use proptest::prelude::*;
+
+fn evaluate(source: &str, inputs: Inputs) -> Vector3<f32> {
+    Expression::new(source)
+        .evaluate(&inputs)
+        .unwrap_or_else(|| panic!("\"{}\" doesn't compile", source))
+}
+
+fn gray(source: &str) -> f32 {
+    let color = evaluate(
+        source,
+        Inputs {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+            canvases: &[],
+        },
+    );
+    assert_eq!(color.x, color.y);
+    assert_eq!(color.y, color.z);
+    color.x
+}
+
+#[test]
+fn operators_follow_the_usual_precedence() {
+    assert_eq!(gray("1 + 2 * 3"), 7.0);
+    assert_eq!(gray("(1 + 2) * 3"), 9.0);
+    assert_eq!(gray("8 / 2 / 2"), 2.0);
+    assert_eq!(gray("5 - 2 - 1"), 2.0);
+    assert_eq!(gray("-2 * -3"), 6.0);
+    assert_eq!(gray("1.5e1 - .5"), 14.5);
+}
+
+#[test]
+fn functions_match_their_wgsl_built_ins() {
+    assert_eq!(gray("fract(2.25)"), 0.25);
+    assert_eq!(gray("fract(-0.25)"), 0.75);
+    assert_eq!(gray("step(0.5, 0.5)"), 1.0);
+    assert_eq!(gray("step(0.5, 0.4)"), 0.0);
+    assert_eq!(gray("mix(1, 3, 0.25)"), 1.5);
+    assert_eq!(gray("clamp(5, 0, 2)"), 2.0);
+    assert_eq!(gray("smoothstep(0, 2, 1)"), 0.5);
+    assert_eq!(gray("pow(2, 3) + sqrt(4) + abs(-1) + floor(1.5)"), 12.0);
+}
+
+#[test]
+fn noise_is_smooth_between_lattice_corners() {
+    // The corners of the lattice are hashed, so the origin's is the hash of zero
+    assert_eq!(gray("noise(0, 0, 0)"), 0.0);
+    let corner = gray("noise(1, 2, 3)");
+    assert!((0.0..1.0).contains(&corner));
+    assert!((gray("noise(0.9999, 2, 3)") - corner).abs() < 1e-3);
+    assert!((gray("noise(1.0001, 2, 3)") - corner).abs() < 1e-3);
+    assert_ne!(gray("noise(1, 2, 3)"), gray("noise(2, 2, 3)"));
+    assert!((0.0..1.0).contains(&gray("noise(-3.3, 7.1, 0.5)")));
+}
+
+#[test]
+fn invalid_expressions_report_why() {
+    let error = |source: &str| Expression::new(source).error().cloned();
+
+    assert_eq!(
+        error("2 * w"),
+        Some(ExpressionError::UnknownName {
+            position: 4,
+            name: "w".to_string()
+        })
+    );
+    assert_eq!(
+        error("mix(1, 2)"),
+        Some(ExpressionError::WrongArgumentCount {
+            function: "mix".to_string(),
+            expected: 3,
+            found: 2
+        })
+    );
+    assert_eq!(error("x, y"), Some(ExpressionError::WrongValueCount(2)));
+    assert_eq!(
+        error("(1 + 2"),
+        Some(ExpressionError::UnexpectedToken {
+            position: 6,
+            token: None
+        })
+    );
+    assert_eq!(
+        error("1 # 2"),
+        Some(ExpressionError::UnexpectedCharacter {
+            position: 2,
+            character: '#'
+        })
+    );
+    assert!(Expression::new("1 +")
+        .evaluate(&Inputs {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+            canvases: &[],
+        })
+        .is_none());
+}
+
+#[test]
+fn expressions_needing_too_deep_a_stack_are_rejected() {
+    let nested = |depth: usize| "1 + (".repeat(depth - 1) + "1" + &")".repeat(depth - 1);
+
+    assert_eq!(Expression::new(nested(MAX_STACK_DEPTH)).error(), None);
+    assert_eq!(
+        Expression::new(nested(MAX_STACK_DEPTH + 1)).error(),
+        Some(&ExpressionError::TooDeep)
+    );
+}
+
+#[test]
+fn locals_are_computed_once_and_read_by_name() {
+    assert_eq!(gray("a = 2 + 1; b = a * a; b - a"), 6.0);
+
+    let single = Expression::new("sin(x + 1) * sin(x + 1) * sin(x + 1)");
+    let shared = Expression::new("s = sin(x + 1); s * s * s");
+    let length = |expression: &Expression| expression.code().unwrap().len();
+    assert!(length(&shared) < length(&single));
+
+    let error = |source: &str| Expression::new(source).error().cloned();
+    let taken = |position, name: &str| {
+        Some(ExpressionError::NameTaken {
+            position,
+            name: name.to_string(),
+        })
+    };
+    assert_eq!(error("x = 1; x"), taken(0, "x"));
+    assert_eq!(error("noise = 1; noise"), taken(0, "noise"));
+    assert_eq!(error("a = 1; a = 2; a"), taken(7, "a"));
+    assert_eq!(
+        error("a = 1 a"),
+        Some(ExpressionError::UnexpectedToken {
+            position: 6,
+            token: Some("a".to_string())
+        })
+    );
+
+    let locals = |count: usize| {
+        let names: String = (0..count).map(|i| format!("l{} = {}; ", i, i)).collect();
+        names + "1"
+    };
+    assert_eq!(Expression::new(locals(MAX_LOCALS)).error(), None);
+    assert_eq!(
+        error(&locals(MAX_LOCALS + 1)),
+        Some(ExpressionError::TooManyLocals)
+    );
+}
+
+#[test]
+fn canvases_are_sampled_at_the_texture_coordinates() {
+    let mut canvas = Canvas::new();
+    canvas.fill(Vector3::new(0.5, 0.25, 1.0));
+    let painted = canvas.albedo(Vector2::new(0.5, 0.5));
+    let inputs = Inputs {
+        position: Vector3::new(0.0, 0.0, 0.0),
+        // Halfway around and up the sphere
+        normal: Vector3::new(1.0, 0.0, 0.0),
+        time: 0.0,
+        canvases: &[&canvas],
+    };
+
+    assert_eq!(evaluate("u, v, 0", inputs), Vector3::new(0.5, 0.5, 0.0));
+    assert_eq!(
+        evaluate(
+            "canvas_r(0, u, v), canvas_g(0, u, v), canvas_b(0, u, v)",
+            inputs
+        ),
+        painted
+    );
+    assert!((painted.x - 0.5).abs() < 0.01 && (painted.y - 0.25).abs() < 0.01);
+    // Meshes without a canvas leave the albedo as it is
+    assert_eq!(
+        evaluate("canvas_r(1, u, v)", inputs),
+        Vector3::new(1.0, 1.0, 1.0)
+    );
+}
+
+#[test]
+fn expressions_are_saved_as_their_source() {
+    let expression = Expression::new("0.5 + 0.5 * sin(8 * y + t)");
+    let json = serde_json::to_string(&expression).unwrap();
+    assert_eq!(json, "\"0.5 + 0.5 * sin(8 * y + t)\"");
+
+    let loaded: Expression = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.source(), expression.source());
+    assert_eq!(loaded.code(), expression.code());
+}
+
+proptest! {
+    #[test]
+    fn three_values_make_a_color_without_negative_channels(
+        x in -10.0f32..10.0,
+        y in -10.0f32..10.0,
+        z in -10.0f32..10.0,
+        time in 0.0f32..100.0,
+    ) {
+        let inputs = Inputs {
+            position: Vector3::new(x, y, z),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            time,
+            canvases: &[],
+        };
+
+        let color = evaluate("x, y + ny, z * t", inputs);
+
+        prop_assert_eq!(color, Vector3::new(x.max(0.0), (y + 1.0).max(0.0), (z * time).max(0.0)));
+    }
+
+    #[test]
+    fn constants_round_trip_through_the_source(value in 0.0f32..1e6) {
+        prop_assert_eq!(gray(&value.to_string()), value);
+    }
+}
@@ -0,0 +1,364 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+use pathtracer::geometry::{self, Aabb, Bvh, BvhBuilder, Frustum, Primitive, Ray};
+use proptest::prelude::*;
+
+mod common;
+
+use common::{direction, vector};
+
+const T_MAX: f32 = 1.0e6;
+
+/// A triangle that isn't too close to degenerate.
+fn triangle() -> impl Strategy<Value = [Vector3<f32>; 3]> {
+    [
+        vector(-10.0..10.0),
+        vector(-10.0..10.0),
+        vector(-10.0..10.0),
+    ]
+    .prop_filter("not degenerate", |[a, b, c]| {
+        (b - a).cross(c - a).magnitude() > 1.0
+    })
+}
+
+/// Weights that add up to one and place the point strictly inside the triangle.
+fn barycentrics() -> impl Strategy<Value = [f32; 3]> {
+    (0.05f32..0.9, 0.05f32..0.9)
+        .prop_filter("inside", |(u, v)| u + v < 0.95)
+        .prop_map(|(u, v)| [1.0 - u - v, u, v])
+}
+
+fn point_on([a, b, c]: [Vector3<f32>; 3], [w, u, v]: [f32; 3]) -> Vector3<f32> {
+    a * w + b * u + c * v
+}
+
+/// A frustum looking along `forward` from `origin` with a 90° vertical field of view.
+fn frustum(origin: Vector3<f32>, forward: Vector3<f32>, aspect_ratio: f32) -> Frustum {
+    let right = forward.cross(Vector3::unit_y()).normalize();
+    let up = right.cross(forward);
+    Frustum::new(origin, [forward, right, up], aspect_ratio, 1.0, 0.01)
+}
+
+fn cube(center: Vector3<f32>, half_size: f32) -> Aabb {
+    Aabb {
+        min: center - Vector3::new(half_size, half_size, half_size),
+        max: center + Vector3::new(half_size, half_size, half_size),
+    }
+}
+
+proptest! {
+    #[test]
+    fn ray_towards_sphere_hits_its_near_side(
+        center in vector(-10.0..10.0),
+        radius in 0.1f32..5.0,
+        direction in direction(),
+        distance in 0.5f32..50.0,
+    ) {
+        let origin = center - direction * (radius + distance);
+        let ray = Ray { origin, direction };
+
+        let t = geometry::ray_sphere(&ray, center, radius, 0.0, T_MAX);
+
+        prop_assert!(t.is_some());
+        prop_assert!((t.unwrap() - distance).abs() < 1.0e-3 * (1.0 + distance));
+    }
+
+    #[test]
+    fn ray_away_from_sphere_misses(
+        center in vector(-10.0..10.0),
+        radius in 0.1f32..5.0,
+        direction in direction(),
+        distance in 0.5f32..50.0,
+    ) {
+        let origin = center - direction * (radius + distance);
+        let ray = Ray { origin, direction: -direction };
+
+        prop_assert_eq!(geometry::ray_sphere(&ray, center, radius, 0.0, T_MAX), None);
+    }
+
+    #[test]
+    fn ray_from_inside_sphere_hits_the_far_side(
+        center in vector(-10.0..10.0),
+        radius in 0.5f32..5.0,
+        direction in direction(),
+    ) {
+        let ray = Ray { origin: center, direction };
+
+        let t = geometry::ray_sphere(&ray, center, radius, 0.001, T_MAX).unwrap();
+
+        prop_assert!((t - radius).abs() < 1.0e-3 * radius);
+    }
+
+    #[test]
+    fn ray_through_triangle_recovers_the_point(
+        triangle in triangle(),
+        weights in barycentrics(),
+        direction in direction(),
+        distance in 0.5f32..50.0,
+    ) {
+        let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).normalize();
+        prop_assume!(direction.dot(normal).abs() > 0.1);
+        let target = point_on(triangle, weights);
+        let ray = Ray { origin: target - direction * distance, direction };
+
+        let hit = geometry::ray_triangle(&ray, triangle, 0.0, T_MAX);
+
+        prop_assert!(hit.is_some());
+        let hit = hit.unwrap();
+        prop_assert!((hit.t - distance).abs() < 1.0e-2 * (1.0 + distance));
+        for (actual, expected) in hit.barycentrics().into_iter().zip(weights) {
+            prop_assert!((actual - expected).abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn ray_beside_triangle_misses(
+        triangle in triangle(),
+        weights in barycentrics(),
+        direction in direction(),
+    ) {
+        let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).normalize();
+        prop_assume!(direction.dot(normal).abs() > 0.1);
+        // Reflect the point across the edge `bc`, putting it outside the triangle
+        let [w, u, v] = weights;
+        let outside = point_on(triangle, [-w, u + w, v + w]);
+        let ray = Ray { origin: outside - direction * 10.0, direction };
+
+        prop_assert_eq!(geometry::ray_triangle(&ray, triangle, 0.0, T_MAX), None);
+    }
+
+    #[test]
+    fn aabb_contains_the_points_it_was_built_from(
+        points in prop::collection::vec(vector(-100.0..100.0), 1..32),
+    ) {
+        let aabb = Aabb::from_points(points.iter().copied());
+
+        for point in points {
+            prop_assert!(aabb.contains(point));
+        }
+    }
+
+    #[test]
+    fn ray_towards_aabb_center_hits_it(
+        points in prop::collection::vec(vector(-10.0..10.0), 2..16),
+        direction in direction(),
+        distance in 30.0f32..100.0,
+    ) {
+        let aabb = Aabb::from_points(points);
+        let ray = Ray { origin: aabb.center() - direction * distance, direction };
+
+        let t = geometry::ray_aabb(&ray, &aabb, 0.0, T_MAX);
+
+        prop_assert!(t.is_some());
+        prop_assert!(t.unwrap() <= distance);
+    }
+
+    #[test]
+    fn ray_from_inside_aabb_hits_it_at_t_min(
+        points in prop::collection::vec(vector(-10.0..10.0), 2..16),
+        direction in direction(),
+    ) {
+        let aabb = Aabb::from_points(points);
+
+        let ray = Ray { origin: aabb.center(), direction };
+
+        prop_assert_eq!(geometry::ray_aabb(&ray, &aabb, 0.0, T_MAX), Some(0.0));
+    }
+
+    #[test]
+    fn bvh_finds_the_same_closest_hit_as_brute_force(
+        triangles in prop::collection::vec(triangle(), 0..64),
+        origin in vector(-20.0..20.0),
+        direction in direction(),
+        builder in prop_oneof![Just(BvhBuilder::Midpoint), Just(BvhBuilder::BinnedSah)],
+    ) {
+        let bvh = Bvh::build(&triangles, builder);
+        let ray = Ray { origin, direction };
+
+        let expected = triangles
+            .iter()
+            .filter_map(|triangle| geometry::ray_triangle(&ray, triangle.vertices(), 0.0, T_MAX))
+            .map(|hit| hit.t)
+            .min_by(f32::total_cmp);
+        let actual = bvh
+            .closest_hit(&triangles, &ray, 0.0, T_MAX)
+            .map(|(_, hit)| hit.t);
+
+        prop_assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn bvh_over_boxes_finds_the_same_closest_box_as_brute_force(
+        boxes in prop::collection::vec((vector(-20.0..20.0), 0.1f32..5.0), 1..64),
+        origin in vector(-30.0..30.0),
+        direction in direction(),
+    ) {
+        let boxes = boxes
+            .into_iter()
+            .map(|(center, half_size)| cube(center, half_size))
+            .collect::<Vec<_>>();
+        let bvh = Bvh::from_triangles(&boxes);
+        let ray = Ray { origin, direction };
+
+        let expected = boxes
+            .iter()
+            .filter_map(|aabb| geometry::ray_aabb(&ray, aabb, 0.0, T_MAX))
+            .min_by(f32::total_cmp);
+        let actual = bvh
+            .closest_hit_with(&ray, 0.0, T_MAX, |index, t_max| {
+                geometry::ray_aabb(&ray, &boxes[index], 0.0, t_max).map(|t| (t, ()))
+            })
+            .map(|(index, _)| geometry::ray_aabb(&ray, &boxes[index], 0.0, T_MAX).unwrap());
+
+        prop_assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn surface_hit_interpolates_vertex_attributes(
+        triangle in triangle(),
+        weights in barycentrics(),
+        direction in direction(),
+    ) {
+        struct Textured([Vector3<f32>; 3]);
+        impl Primitive for Textured {
+            fn vertices(&self) -> [Vector3<f32>; 3] {
+                self.0
+            }
+
+            fn uvs(&self) -> [Vector2<f32>; 3] {
+                [Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0), Vector2::new(0.0, 1.0)]
+            }
+        }
+
+        let face_normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).normalize();
+        prop_assume!(direction.dot(face_normal).abs() > 0.1);
+        let target = point_on(triangle, weights);
+        let ray = Ray { origin: target - direction * 10.0, direction };
+        let textured = Textured(triangle);
+
+        let hit = geometry::ray_triangle(&ray, triangle, 0.0, T_MAX).unwrap();
+        let surface = textured.surface_hit(&ray, hit);
+
+        prop_assert!((surface.point - target).magnitude() < 1.0e-2);
+        prop_assert!((surface.normal - face_normal).magnitude() < 1.0e-4);
+        // With these texture coordinates, the UV is the weights of `b` and `c`
+        prop_assert!((surface.uv - Vector2::new(weights[1], weights[2])).magnitude() < 1.0e-2);
+    }
+
+    #[test]
+    fn points_in_view_are_inside_the_frustum(
+        origin in vector(-10.0..10.0),
+        forward in direction(),
+        aspect_ratio in 0.5f32..2.0,
+        depth in 0.1f32..50.0,
+        (x, y) in (-0.99f32..0.99, -0.99f32..0.99),
+    ) {
+        prop_assume!(forward.y.abs() < 0.99);
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let up = right.cross(forward);
+        let point = origin + (forward + right * x * aspect_ratio + up * y) * depth;
+        let frustum = frustum(origin, forward, aspect_ratio);
+
+        prop_assert!(frustum.intersects_sphere(point, 0.0));
+        prop_assert!(frustum.intersects_aabb(&cube(point, 0.001)));
+    }
+
+    #[test]
+    fn objects_behind_the_camera_are_culled(
+        origin in vector(-10.0..10.0),
+        forward in direction(),
+        aspect_ratio in 0.5f32..2.0,
+        radius in 0.1f32..5.0,
+        distance in 0.1f32..50.0,
+    ) {
+        prop_assume!(forward.y.abs() < 0.99);
+        // Boxes reach further than the spheres inside them, up to sqrt(3) times the radius
+        let center = origin - forward * (radius * 1.8 + distance);
+        let frustum = frustum(origin, forward, aspect_ratio);
+
+        prop_assert!(!frustum.intersects_sphere(center, radius));
+        prop_assert!(!frustum.intersects_aabb(&cube(center, radius)));
+    }
+
+    #[test]
+    fn euler_angles_round_trip_through_a_matrix(
+        angles in vector(-180.0..180.0),
+        // Gimbal lock, where X and Z turn about the same axis
+        locked in prop::bool::weighted(0.2),
+    ) {
+        let angles = if locked { Vector3::new(angles.x, 90.0, angles.z) } else { angles };
+        let rotation = geometry::euler_to_matrix(angles);
+        let round_trip = geometry::euler_to_matrix(geometry::matrix_to_euler(&rotation));
+
+        for (a, b) in [rotation.x, rotation.y, rotation.z]
+            .iter()
+            .zip([round_trip.x, round_trip.y, round_trip.z])
+        {
+            prop_assert!((a - b).magnitude() < 1e-3, "{:?} != {:?}", rotation, round_trip);
+        }
+    }
+
+    #[test]
+    fn triangles_with_area_are_not_degenerate(triangle in triangle()) {
+        prop_assert!(!triangle.is_degenerate());
+    }
+
+    #[test]
+    fn triangles_with_a_repeated_vertex_are_degenerate(
+        a in vector(-10.0..10.0),
+        b in vector(-10.0..10.0),
+    ) {
+        prop_assert!([a, a, b].is_degenerate());
+        prop_assert!([a, b, b].is_degenerate());
+        prop_assert!([a, b, a].is_degenerate());
+    }
+}
+
+#[test]
+fn triangles_with_non_finite_vertices_are_degenerate() {
+    let a = Vector3::new(0.0, 0.0, 0.0);
+    let b = Vector3::new(1.0, 0.0, 0.0);
+    for c in [
+        Vector3::new(f32::NAN, 1.0, 0.0),
+        Vector3::new(0.0, f32::INFINITY, 0.0),
+    ] {
+        assert!([a, b, c].is_degenerate());
+    }
+}
+
+proptest! {
+    // Large enough for the top of the tree to be built on several threads, so only a few cases
+    #![proptest_config(ProptestConfig::with_cases(4))]
+
+    #[test]
+    fn bvh_built_in_parallel_finds_the_same_closest_hits_as_brute_force(
+        triangles in prop::collection::vec(
+            (vector(-20.0..20.0), triangle())
+                .prop_map(|(center, vertices)| vertices.map(|vertex| center + vertex * 0.05)),
+            20_000..24_000,
+        ),
+        rays in prop::collection::vec((vector(-20.0..20.0), direction()), 16),
+        builder in prop_oneof![Just(BvhBuilder::Midpoint), Just(BvhBuilder::BinnedSah)],
+    ) {
+        let bvh = Bvh::build(&triangles, builder);
+
+        let mut indices = bvh.triangle_indices.clone();
+        indices.sort_unstable();
+        prop_assert!(indices.iter().copied().eq(0..triangles.len() as u32));
+
+        for (origin, direction) in rays {
+            let ray = Ray { origin, direction };
+            let expected = triangles
+                .iter()
+                .filter_map(|triangle| {
+                    geometry::ray_triangle(&ray, triangle.vertices(), 0.0, T_MAX)
+                })
+                .map(|hit| hit.t)
+                .min_by(f32::total_cmp);
+            let actual = bvh
+                .closest_hit(&triangles, &ray, 0.0, T_MAX)
+                .map(|(_, hit)| hit.t);
+
+            prop_assert_eq!(actual, expected);
+        }
+    }
+}
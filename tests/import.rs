@@ -0,0 +1,602 @@
+use std::{fs, io, path::PathBuf};
+
+use cgmath::{InnerSpace, Vector3};
+use pathtracer::scene::{Camera, LightKind, Material, Scene};
+
+mod common;
+
+/// Writes `files` by name into a directory of their own and imports the first.
+fn import(test: &str, files: &[(&str, &[u8])]) -> io::Result<Scene> {
+    let directory = common::temp_dir(test);
+    for (name, contents) in files {
+        fs::write(directory.join(name), contents).unwrap();
+    }
+    Scene::import(&directory.join(files[0].0))
+}
+
+fn import_pbrt(test: &str, source: &str) -> io::Result<Scene> {
+    import(test, &[("scene.pbrt", source.as_bytes())])
+}
+
+fn assert_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+    assert!(
+        (actual - expected).magnitude() < 1e-4,
+        "{:?} isn't {:?}",
+        actual,
+        expected
+    );
+}
+
+/// The vertices of every triangle of every mesh, PBRT's x axis flipped as they're imported.
+fn vertices(scene: &Scene) -> Vec<[Vector3<f32>; 3]> {
+    scene
+        .meshes()
+        .iter()
+        .flat_map(|mesh| &mesh.triangles)
+        .map(|t| [t.a, t.b, t.c])
+        .collect()
+}
+
+const CORNELL_BOX: &str = r#"
+LookAt 0 1 3.5  0 1 0  0 1 0
+Camera "perspective" "float fov" [40]
+Film "rgb" "integer xresolution" [512] "integer yresolution" [512]
+WorldBegin
+
+MakeNamedMaterial "white" "string type" "diffuse" "rgb reflectance" [0.7 0.7 0.7]
+MakeNamedMaterial "red" "string type" "diffuse" "rgb reflectance" [0.6 0.05 0.05]
+MakeNamedMaterial "green" "string type" "diffuse" "rgb reflectance" [0.1 0.5 0.1]
+
+NamedMaterial "white"
+# Floor
+Shape "bilinearmesh" "point3 P" [-1 0 -1  1 0 -1  -1 0 1  1 0 1]
+NamedMaterial "red"
+# Left wall
+Shape "trianglemesh" "point3 P" [-1 0 -1  -1 0 1  -1 2 1  -1 2 -1]
+    "integer indices" [0 1 2  0 2 3]
+NamedMaterial "green"
+# Right wall
+Shape "trianglemesh" "point3 P" [1 0 -1  1 2 -1  1 2 1  1 0 1]
+    "integer indices" [0 1 2  0 2 3]
+
+AttributeBegin
+    AreaLightSource "diffuse" "rgb L" [17 12 4]
+    Shape "bilinearmesh" "point3 P" [-0.25 1.99 -0.25  0.25 1.99 -0.25  -0.25 1.99 0.25  0.25 1.99 0.25]
+AttributeEnd
+
+AttributeBegin
+    Material "dielectric" "float eta" 1.5
+    Translate 0.4 0.3 0.2
+    Shape "sphere" "float radius" 0.3
+AttributeEnd
+"#;
+
+#[test]
+fn cornell_box_imports_its_camera_walls_light_and_sphere() {
+    let scene = import_pbrt("import-cornell-box", CORNELL_BOX).unwrap();
+
+    assert_eq!(scene.name, "scene");
+    // PBRT's cameras are left-handed, so the scene is mirrored along x
+    assert_near(scene.camera.origin, Vector3::new(0.0, 1.0, 3.5));
+    assert_near(scene.camera.forward, Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(scene.camera.vfov, 40.0);
+
+    assert_eq!(scene.meshes().len(), 4);
+    assert_eq!(scene.instances().len(), 4);
+    let albedos = scene
+        .meshes()
+        .iter()
+        .map(|mesh| scene.materials[mesh.triangles[0].material].albedo)
+        .collect::<Vec<_>>();
+    assert_near(albedos[0], Vector3::new(0.7, 0.7, 0.7));
+    assert_near(albedos[1], Vector3::new(0.6, 0.05, 0.05));
+    assert_near(albedos[2], Vector3::new(0.1, 0.5, 0.1));
+
+    let light = &scene.materials[scene.meshes()[3].triangles[0].material];
+    assert_eq!(light.material, Material::Emissive { intensity: 17.0 });
+    assert_near(light.albedo, Vector3::new(1.0, 12.0 / 17.0, 4.0 / 17.0));
+
+    let spheres = scene.spheres();
+    assert_eq!(spheres.len(), 1);
+    let sphere = &spheres[0];
+    assert_near(sphere.transform.position, Vector3::new(-0.4, 0.3, 0.2));
+    assert!((sphere.shape.radius - 0.3).abs() < 1e-6);
+    let glass = &scene.materials[sphere.surface.material];
+    assert_eq!(glass.material, Material::Dielectric);
+    assert_eq!(glass.ior, 1.5);
+}
+
+#[test]
+fn trianglemesh_keeps_its_indices_normals_and_texture_coordinates() {
+    let scene = import_pbrt(
+        "import-trianglemesh",
+        r#"
+        Shape "trianglemesh"
+            "point3 P" [0 0 0  1 0 0  1 1 0  0 1 0]
+            "integer indices" [0 1 2  2 3 0]
+            "normal N" [0 0 1  0 0 1  0 0 1  0 0 1]
+            "point2 uv" [0 0  1 0  1 1  0 1]
+        "#,
+    )
+    .unwrap();
+
+    let triangles = &scene.meshes()[0].triangles;
+    assert_eq!(triangles.len(), 2);
+    assert_eq!(
+        vertices(&scene),
+        vec![
+            [
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(-1.0, 0.0, 0.0),
+                Vector3::new(-1.0, 1.0, 0.0),
+            ],
+            [
+                Vector3::new(-1.0, 1.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                Vector3::new(0.0, 0.0, 0.0),
+            ],
+        ]
+    );
+    assert_near(triangles[0].na, Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(triangles[1].ta, cgmath::Vector2::new(1.0, 1.0));
+    assert_eq!(triangles[1].tb, cgmath::Vector2::new(0.0, 1.0));
+}
+
+#[test]
+fn bilinearmesh_splits_each_patch_into_two_triangles() {
+    let scene = import_pbrt(
+        "import-bilinearmesh",
+        r#"
+        Shape "bilinearmesh"
+            "point3 P" [0 0 0  1 0 0  0 0 1  1 0 1  2 0 0  2 0 1]
+            "integer indices" [0 1 2 3  1 4 3 5]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(scene.meshes()[0].name, "Bilinear Mesh");
+    let vertices = vertices(&scene);
+    assert_eq!(vertices.len(), 4);
+    // Corners 0, 1, 3 and 0, 3, 2 of the first patch
+    assert_eq!(
+        vertices[0],
+        [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 1.0),
+        ]
+    );
+    assert_eq!(
+        vertices[1],
+        [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(-1.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ]
+    );
+}
+
+const PLY_SCENE: &str = r#"Shape "plymesh" "string filename" "quad.ply""#;
+
+/// A unit quad in the xy plane, as a single face the importer splits into a fan.
+const QUAD: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 0.0],
+];
+
+fn assert_quad(scene: &Scene) {
+    let flipped = QUAD.map(|[x, y, z]| Vector3::new(-x, y, z));
+    assert_eq!(scene.meshes()[0].name, "PLY Mesh");
+    assert_eq!(
+        vertices(scene),
+        vec![
+            [flipped[0], flipped[1], flipped[2]],
+            [flipped[0], flipped[2], flipped[3]],
+        ]
+    );
+}
+
+#[test]
+fn ascii_ply_meshes_are_read() {
+    let mut ply = String::from(
+        "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+        property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n",
+    );
+    for [x, y, z] in QUAD {
+        ply += &format!("{} {} {}\n", x, y, z);
+    }
+    ply += "4 0 1 2 3\n";
+
+    let scene = import(
+        "import-ply-ascii",
+        &[
+            ("scene.pbrt", PLY_SCENE.as_bytes()),
+            ("quad.ply", ply.as_bytes()),
+        ],
+    )
+    .unwrap();
+    assert_quad(&scene);
+}
+
+#[test]
+fn binary_ply_meshes_are_read_in_either_byte_order() {
+    for (format, little_endian) in [("binary_little_endian", true), ("binary_big_endian", false)] {
+        let mut ply = format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+            end_header\n",
+            format
+        )
+        .into_bytes();
+        for value in QUAD.iter().flatten() {
+            ply.extend(if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            });
+        }
+        ply.push(4);
+        for index in 0i32..4 {
+            ply.extend(if little_endian {
+                index.to_le_bytes()
+            } else {
+                index.to_be_bytes()
+            });
+        }
+
+        let test = format!("import-ply-{}", format);
+        let files: [(&str, &[u8]); 2] = [("scene.pbrt", PLY_SCENE.as_bytes()), ("quad.ply", &ply)];
+        let scene = import(&test, &files).unwrap();
+        assert_quad(&scene);
+    }
+}
+
+fn import_error(test: &str, files: &[(&str, &[u8])]) -> io::Error {
+    match import(test, files) {
+        Ok(_) => panic!("{} imported", files[0].0),
+        Err(e) => e,
+    }
+}
+
+fn pbrt_error(test: &str, source: &str) -> String {
+    let error = import_error(test, &[("scene.pbrt", source.as_bytes())]);
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+    error.to_string()
+}
+
+#[test]
+fn malformed_pbrt_files_are_rejected() {
+    let unterminated = pbrt_error("import-unterminated", r#"Shape "sphere"#);
+    assert!(
+        unterminated.contains("unterminated string"),
+        "{}",
+        unterminated
+    );
+
+    let unmatched = pbrt_error("import-unmatched", "AttributeEnd");
+    assert!(unmatched.contains("unmatched"), "{}", unmatched);
+
+    let arguments = pbrt_error("import-arguments", "Translate 1 2");
+    assert!(arguments.contains("expected 3 numbers"), "{}", arguments);
+
+    let missing_vertex = pbrt_error(
+        "import-missing-vertex",
+        r#"Shape "trianglemesh" "point3 P" [0 0 0  1 0 0  0 1 0] "integer indices" [0 1 3]"#,
+    );
+    assert!(
+        missing_vertex.contains("missing vertex"),
+        "{}",
+        missing_vertex
+    );
+
+    let normals = pbrt_error(
+        "import-normals",
+        r#"Shape "trianglemesh" "point3 P" [0 0 0  1 0 0  0 1 0] "normal N" [0 0 1]"#,
+    );
+    assert!(normals.contains("normals"), "{}", normals);
+
+    let up = pbrt_error("import-up", "LookAt 0 0 0  0 1 0  0 1 0");
+    assert!(up.contains("parallel"), "{}", up);
+}
+
+#[test]
+fn malformed_ply_files_are_rejected() {
+    let not_ply = import_error(
+        "import-not-ply",
+        &[
+            ("scene.pbrt", PLY_SCENE.as_bytes()),
+            ("quad.ply", b"solid quad\nendsolid\n"),
+        ],
+    );
+    assert!(
+        not_ply.to_string().contains("not a PLY file"),
+        "{}",
+        not_ply
+    );
+
+    let truncated = import_error(
+        "import-truncated-ply",
+        &[
+            ("scene.pbrt", PLY_SCENE.as_bytes()),
+            (
+                "quad.ply",
+                b"ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+                property float z\nend_header\n0 0 0\n1 0 0\n",
+            ),
+        ],
+    );
+    assert!(
+        truncated.to_string().contains("ends early"),
+        "{}",
+        truncated
+    );
+}
+
+#[test]
+fn missing_and_unknown_files_are_errors() {
+    let missing = Scene::import(&PathBuf::from("no/such/scene.pbrt"))
+        .err()
+        .unwrap();
+    assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+    let unknown = Scene::import(&PathBuf::from("scene.obj")).err().unwrap();
+    assert_eq!(unknown.kind(), io::ErrorKind::InvalidData);
+}
+
+fn import_mitsuba(test: &str, source: &str) -> io::Result<Scene> {
+    import(test, &[("scene.xml", source.as_bytes())])
+}
+
+#[test]
+fn mitsuba_sensors_become_the_camera() {
+    let scene = import_mitsuba(
+        "mitsuba-sensor",
+        r#"<scene version="3.0.0">
+            <default name="fov" value="30"/>
+            <sensor type="perspective">
+                <float name="fov" value="$fov"/>
+                <string name="fov_axis" value="y"/>
+                <transform name="to_world">
+                    <lookat origin="0, 1, 5" target="0, 1, 0" up="0, 1, 0"/>
+                </transform>
+                <film type="hdrfilm">
+                    <integer name="width" value="640"/>
+                    <integer name="height" value="480"/>
+                </film>
+            </sensor>
+        </scene>"#,
+    )
+    .unwrap();
+
+    assert_near(scene.camera.origin, Vector3::new(0.0, 1.0, 5.0));
+    assert_near(scene.camera.forward, Vector3::new(0.0, 0.0, -1.0));
+    assert_near(scene.camera.up, Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(scene.camera.vfov, 30.0);
+}
+
+#[test]
+fn mitsuba_shapes_are_placed_by_their_transforms() {
+    let scene = import_mitsuba(
+        "mitsuba-shapes",
+        r#"<scene version="0.6.0">
+            <shape type="rectangle" id="floor">
+                <transform name="toWorld">
+                    <scale x="2" y="3"/>
+                    <rotate x="1" angle="-90"/>
+                    <translate y="-1"/>
+                </transform>
+            </shape>
+            <shape type="cube">
+                <transform name="toWorld">
+                    <matrix value="1 0 0 5  0 1 0 0  0 0 1 0  0 0 0 1"/>
+                </transform>
+            </shape>
+            <shape type="disk"/>
+            <shape type="sphere">
+                <point name="center" x="1" y="0" z="0"/>
+                <float name="radius" value="0.5"/>
+                <transform name="toWorld">
+                    <scale value="2"/>
+                    <translate z="3"/>
+                </transform>
+            </shape>
+        </scene>"#,
+    )
+    .unwrap();
+
+    let names = scene
+        .meshes()
+        .iter()
+        .map(|mesh| mesh.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["floor", "Cube", "Disk"]);
+
+    // The rectangle's corners are scaled, turned to face up and lowered
+    let floor = &scene.meshes()[0].triangles[0];
+    assert_near(floor.a, Vector3::new(-2.0, -1.0, 3.0));
+    assert_near(floor.b, Vector3::new(2.0, -1.0, 3.0));
+    assert_near(floor.c, Vector3::new(2.0, -1.0, -3.0));
+    assert_near(floor.na, Vector3::new(0.0, 1.0, 0.0));
+
+    let cube = &scene.meshes()[1].triangles;
+    assert_eq!(cube.len(), 12);
+    for triangle in cube {
+        for vertex in [triangle.a, triangle.b, triangle.c] {
+            assert!((4.0..=6.0).contains(&vertex.x), "{:?}", vertex);
+        }
+    }
+    assert_eq!(scene.meshes()[2].triangles.len(), 64);
+
+    let sphere = &scene.spheres()[0];
+    assert_near(sphere.transform.position, Vector3::new(2.0, 0.0, 3.0));
+    assert!((sphere.shape.radius - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn mitsuba_bsdfs_become_the_closest_materials() {
+    let scene = import_mitsuba(
+        "mitsuba-bsdfs",
+        r#"<scene version="3.0.0">
+            <bsdf type="twosided" id="red">
+                <bsdf type="diffuse">
+                    <rgb name="reflectance" value="0.8, 0.1, 0.1"/>
+                </bsdf>
+            </bsdf>
+            <shape type="sphere"><ref id="red"/></shape>
+            <shape type="sphere">
+                <bsdf type="roughconductor">
+                    <float name="alpha" value="0.3"/>
+                    <rgb name="specular_reflectance" value="0.9"/>
+                </bsdf>
+            </shape>
+            <shape type="sphere">
+                <bsdf type="dielectric">
+                    <string name="int_ior" value="water"/>
+                </bsdf>
+            </shape>
+            <shape type="sphere">
+                <bsdf type="dielectric">
+                    <float name="int_ior" value="1.5"/>
+                    <float name="ext_ior" value="1.25"/>
+                </bsdf>
+            </shape>
+            <shape type="sphere">
+                <emitter type="area">
+                    <rgb name="radiance" value="10, 5, 0"/>
+                </emitter>
+            </shape>
+            <shape type="sphere"><ref id="red"/></shape>
+        </scene>"#,
+    )
+    .unwrap();
+
+    let spheres = scene.spheres();
+    let materials = spheres
+        .iter()
+        .map(|sphere| &scene.materials[sphere.surface.material])
+        .collect::<Vec<_>>();
+    assert_eq!(materials[0].material, Material::Diffuse);
+    assert_near(materials[0].albedo, Vector3::new(0.8, 0.1, 0.1));
+
+    assert_eq!(materials[1].material, Material::Metal);
+    assert_eq!(materials[1].roughness, 0.3);
+    assert_near(materials[1].albedo, Vector3::new(0.9, 0.9, 0.9));
+
+    assert_eq!(materials[2].material, Material::Dielectric);
+    assert_eq!(materials[2].ior, 1.333);
+    assert!((materials[3].ior - 1.2).abs() < 1e-6);
+
+    assert_eq!(
+        materials[4].material,
+        Material::Emissive { intensity: 10.0 }
+    );
+    assert_near(materials[4].albedo, Vector3::new(1.0, 0.5, 0.0));
+
+    // Shapes referring to the same BSDF share its material
+    assert_eq!(spheres[0].surface.material, spheres[5].surface.material);
+}
+
+#[test]
+fn mitsuba_emitters_become_lights() {
+    let scene = import_mitsuba(
+        "mitsuba-emitters",
+        r#"<scene version="3.0.0">
+            <emitter type="point">
+                <point name="position" x="1" y="2" z="3"/>
+                <rgb name="intensity" value="4, 2, 2"/>
+            </emitter>
+            <emitter type="directional">
+                <vector name="direction" x="0" y="-1" z="0"/>
+                <rgb name="irradiance" value="3"/>
+            </emitter>
+            <emitter type="constant"/>
+        </scene>"#,
+    )
+    .unwrap();
+
+    let lights = scene.lights();
+    assert_eq!(lights.len(), 2);
+    let point = lights[0].light;
+    assert_eq!(point.kind, LightKind::Point);
+    assert_near(point.position, Vector3::new(1.0, 2.0, 3.0));
+    assert_eq!(point.intensity, 4.0);
+    assert_near(point.color, Vector3::new(1.0, 0.5, 0.5));
+
+    let directional = lights[1].light;
+    assert_eq!(directional.kind, LightKind::Directional);
+    assert_near(directional.direction, Vector3::new(0.0, -1.0, 0.0));
+    assert_eq!(directional.intensity, 3.0);
+}
+
+fn mitsuba_error(test: &str, source: &str) -> String {
+    let error = match import_mitsuba(test, source) {
+        Ok(_) => panic!("{} imported", source),
+        Err(e) => e,
+    };
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+    error.to_string()
+}
+
+#[test]
+fn malformed_mitsuba_files_are_rejected() {
+    let xml = mitsuba_error("mitsuba-xml", "<scene><shape></scene>");
+    assert!(xml.contains("scene.xml"), "{}", xml);
+
+    let root = mitsuba_error("mitsuba-root", "<shape/>");
+    assert!(root.contains("isn't a Mitsuba scene"), "{}", root);
+
+    let transform = mitsuba_error(
+        "mitsuba-transform",
+        r#"<scene><shape type="cube">
+            <transform name="to_world"><shear value="1"/></transform>
+        </shape></scene>"#,
+    );
+    assert!(
+        transform.contains("unknown transform <shear>"),
+        "{}",
+        transform
+    );
+
+    let rotation = mitsuba_error(
+        "mitsuba-rotation",
+        r#"<scene><shape type="cube">
+            <transform name="to_world"><rotate angle="90"/></transform>
+        </shape></scene>"#,
+    );
+    assert!(rotation.contains("invalid rotation"), "{}", rotation);
+
+    let matrix = mitsuba_error(
+        "mitsuba-matrix",
+        r#"<scene><shape type="cube">
+            <transform name="to_world"><matrix value="1 0 0 1"/></transform>
+        </shape></scene>"#,
+    );
+    assert!(matrix.contains("invalid matrix"), "{}", matrix);
+
+    let lookat = mitsuba_error(
+        "mitsuba-lookat",
+        r#"<scene><shape type="cube"><transform name="to_world">
+            <lookat origin="0 0 0" target="0 1 0" up="0 1 0"/>
+        </transform></shape></scene>"#,
+    );
+    assert!(lookat.contains("parallel"), "{}", lookat);
+
+    let filename = mitsuba_error("mitsuba-filename", r#"<scene><shape type="ply"/></scene>"#);
+    assert!(filename.contains("has no filename"), "{}", filename);
+}
+
+#[test]
+fn mitsuba_sensors_with_broken_transforms_leave_the_default_camera() {
+    let scene = import_mitsuba(
+        "mitsuba-broken-sensor",
+        r#"<scene>
+            <sensor type="perspective">
+                <transform name="to_world"><translate value="1 2"/></transform>
+            </sensor>
+        </scene>"#,
+    )
+    .unwrap();
+    assert_near(scene.camera.origin, Camera::default().origin);
+}
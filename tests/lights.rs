@@ -0,0 +1,136 @@
+use std::f32::consts::PI;
+
+use cgmath::{Vector2, Vector3};
+use pathtracer::scene::{
+    Camera, Emitter, IntensityUnit, Light, LightKind, Material, MaterialId, Scene, Sphere,
+    SphereDescriptor,
+};
+use uuid::Uuid;
+
+const NITS_PER_UNIT: f32 = 100.0;
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() <= expected.abs() * 1e-5,
+        "{} isn't {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn relative_intensities_are_used_as_they_are() {
+    let mut light = Light::new(LightKind::Point);
+    light.intensity = 3.0;
+    light.color = Vector3::new(0.5, 0.0, 0.0);
+    assert_eq!(light.relative_intensity(NITS_PER_UNIT), 3.0);
+}
+
+#[test]
+fn photometric_intensities_are_divided_by_the_luminance_of_a_unit() {
+    let mut light = Light::new(LightKind::Directional);
+    light.unit = IntensityUnit::Lux;
+    light.intensity = 1000.0;
+    assert_close(light.relative_intensity(NITS_PER_UNIT), 10.0);
+
+    // The same illuminance from a green light takes less radiance, since green looks brighter
+    light.color = Vector3::new(0.0, 1.0, 0.0);
+    assert_close(light.relative_intensity(NITS_PER_UNIT), 10.0 / 0.7152);
+
+    light.color = Vector3::new(0.0, 0.0, 0.0);
+    assert_eq!(light.relative_intensity(NITS_PER_UNIT), 0.0);
+}
+
+#[test]
+fn lumens_are_spread_over_the_light() {
+    let mut point = Light::new(LightKind::Point);
+    point.unit = IntensityUnit::Lumens;
+    point.intensity = 4.0 * PI * 100.0;
+    assert_close(point.relative_intensity(NITS_PER_UNIT), 1.0);
+
+    let mut quad = Light::new(LightKind::Quad);
+    quad.size = Vector2::new(2.0, 0.5);
+    quad.unit = IntensityUnit::Lumens;
+    quad.intensity = PI * 100.0;
+    assert_close(quad.relative_intensity(NITS_PER_UNIT), 1.0);
+}
+
+#[test]
+fn switching_units_keeps_the_light_as_bright() {
+    for kind in [LightKind::Point, LightKind::Directional, LightKind::Quad] {
+        let mut light = Light::new(kind);
+        light.color = Vector3::new(1.0, 0.8, 0.6);
+        light.size = Vector2::new(0.5, 3.0);
+        let relative = light.relative_intensity(NITS_PER_UNIT);
+        for &unit in IntensityUnit::all(kind)
+            .iter()
+            .chain([&IntensityUnit::Relative])
+        {
+            light.set_unit(unit, NITS_PER_UNIT);
+            assert_eq!(light.unit, unit);
+            assert_close(light.relative_intensity(NITS_PER_UNIT), relative);
+        }
+    }
+
+    let mut point = Light::new(LightKind::Point);
+    point.set_unit(IntensityUnit::Candela, NITS_PER_UNIT);
+    let candela = point.intensity;
+    point.set_unit(IntensityUnit::Lumens, NITS_PER_UNIT);
+    assert_close(point.intensity, candela * 4.0 * PI);
+}
+
+#[test]
+fn light_linking_includes_then_excludes() {
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let mut light = Light::new(LightKind::Point);
+    assert!(light.links.illuminates(a) && light.links.illuminates(b));
+
+    light.links.include.push(a);
+    assert!(light.links.illuminates(a));
+    assert!(!light.links.illuminates(b));
+
+    // Excluding wins over including
+    light.links.exclude.push(a);
+    assert!(!light.links.illuminates(a));
+
+    light.links.include.clear();
+    assert!(!light.links.illuminates(a));
+    assert!(light.links.illuminates(b));
+}
+
+#[test]
+fn emissive_spheres_are_light_sources_objects_can_be_unlinked_from() {
+    let mut scene = Scene::new(Vec::new(), Vec::new(), Camera::default());
+    let glowing = scene.materials.find_or_add(
+        Vector3::new(1.0, 1.0, 1.0),
+        Material::Emissive { intensity: 4.0 },
+    );
+    let sphere = |material| {
+        Sphere::new(SphereDescriptor {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material,
+        })
+    };
+    let (emitter, lit) = (sphere(glowing), sphere(MaterialId::default()));
+    let (emitter_id, lit_id) = (emitter.uuid, lit.uuid);
+    scene.push_sphere(emitter);
+    scene.push_sphere(lit);
+    let light_id = scene.push_light(Light::new(LightKind::Point), "");
+
+    let sources: Vec<_> = scene.light_sources().iter().map(|s| s.uuid).collect();
+    assert_eq!(sources, [light_id, emitter_id]);
+
+    let links = |scene: &Scene| {
+        scene
+            .get::<Emitter>(emitter_id)
+            .unwrap()
+            .light_links
+            .clone()
+    };
+    scene.set_illuminates(emitter_id, lit_id, false);
+    assert!(!links(&scene).illuminates(lit_id));
+    scene.set_illuminates(emitter_id, lit_id, true);
+    assert!(links(&scene).illuminates(lit_id));
+    assert!(links(&scene).is_empty());
+}
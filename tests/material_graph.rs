@@ -0,0 +1,192 @@
+use cgmath::Vector3;
+use pathtracer::{
+    expression::{Expression, Inputs, MAX_INSTRUCTIONS},
+    scene::{
+        Bsdf, Channel, GraphNode, Material, MaterialGraph, MathOp, NodeInput, NodeKind, Variable,
+    },
+};
+
+fn graph(nodes: Vec<GraphNode>) -> MaterialGraph {
+    let mut graph = MaterialGraph::new();
+    graph.nodes.extend(nodes);
+    graph
+}
+
+fn node(kind: NodeKind, inputs: Vec<NodeInput>) -> GraphNode {
+    GraphNode {
+        kind,
+        inputs,
+        position: [0.0, 0.0],
+    }
+}
+
+fn evaluate(graph: &MaterialGraph, position: Vector3<f32>) -> Vector3<f32> {
+    let source = graph.compile();
+    Expression::new(source.clone())
+        .evaluate(&Inputs {
+            position,
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+            canvases: &[],
+        })
+        .unwrap_or_else(|| panic!("\"{}\" doesn't compile", source))
+}
+
+#[test]
+fn a_new_graph_leaves_the_albedo_as_it_is() {
+    assert_eq!(MaterialGraph::new().compile(), "1");
+}
+
+#[test]
+fn linked_nodes_compile_to_the_expression_they_stand_for() {
+    use NodeInput::{Link, Value};
+
+    let mut graph = graph(vec![
+        node(NodeKind::Variable(Variable::Y), vec![]),
+        node(NodeKind::Math(MathOp::Multiply), vec![Link(1), Value(-2.0)]),
+        node(
+            NodeKind::Math(MathOp::Clamp),
+            vec![Link(2), Value(0.0), Value(1.0)],
+        ),
+    ]);
+    graph.nodes[0].inputs = vec![Link(3), Value(0.5), Link(1)];
+
+    assert_eq!(graph.compile(), "clamp((y * -2), 0, 1), 0.5, y");
+    assert_eq!(
+        evaluate(&graph, Vector3::new(0.0, -0.25, 0.0)),
+        Vector3::new(0.5, 0.5, 0.0)
+    );
+
+    // The same value in every channel is a gray
+    graph.nodes[0].inputs = vec![Link(3); 3];
+    assert_eq!(graph.compile(), "clamp((y * -2), 0, 1)");
+}
+
+#[test]
+fn noise_nodes_sample_the_point_hit() {
+    let mut graph = graph(vec![node(
+        NodeKind::Noise,
+        vec![NodeInput::Value(4.0), NodeInput::Value(0.0)],
+    )]);
+    graph.nodes[0].inputs = vec![NodeInput::Link(1); 3];
+
+    assert_eq!(graph.compile(), "noise(x * 4, y * 4, z * 4 + t * 0)");
+    let noise = Expression::new("noise(1, 2, 3)")
+        .evaluate(&Inputs {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            time: 0.0,
+            canvases: &[],
+        })
+        .unwrap();
+    assert_eq!(evaluate(&graph, Vector3::new(0.25, 0.5, 0.75)), noise);
+}
+
+#[test]
+fn nodes_read_more_than_once_are_computed_once() {
+    use NodeInput::{Link, Value};
+
+    let mut graph = graph(vec![
+        node(NodeKind::Noise, vec![Value(4.0), Value(0.0)]),
+        node(NodeKind::Math(MathOp::Multiply), vec![Link(1), Value(2.0)]),
+    ]);
+    graph.nodes[0].inputs = vec![Link(1), Link(2), Link(1)];
+
+    assert_eq!(
+        graph.compile(),
+        "n1 = noise(x * 4, y * 4, z * 4 + t * 0); n1, (n1 * 2), n1"
+    );
+    let position = Vector3::new(0.25, 0.5, 0.75);
+    let noise = evaluate(&graph, position).x;
+    assert_eq!(
+        evaluate(&graph, position),
+        Vector3::new(noise, noise * 2.0, noise)
+    );
+}
+
+#[test]
+fn shared_nodes_keep_deep_graphs_within_the_instruction_limit() {
+    use NodeInput::Link;
+
+    // Each node squares the one before, which written out in full would read the sine 64 times
+    let mut nodes = vec![
+        node(NodeKind::Math(MathOp::Sin), vec![Link(2)]),
+        node(NodeKind::Variable(Variable::X), vec![]),
+    ];
+    // The sine, then each product before the next
+    for previous in [1, 3, 4, 5, 6, 7] {
+        nodes.push(node(
+            NodeKind::Math(MathOp::Multiply),
+            vec![Link(previous), Link(previous)],
+        ));
+    }
+    let mut graph = graph(nodes);
+    graph.nodes[0].inputs = vec![Link(8); 3];
+
+    let expression = Expression::new(graph.compile());
+    assert_eq!(expression.error(), None);
+    assert!(expression.code().unwrap().len() <= MAX_INSTRUCTIONS);
+    let squared = evaluate(&graph, Vector3::new(1.4, 0.0, 0.0)).x;
+    assert!((squared - 1.4f32.sin().powi(64)).abs() < 1e-4);
+}
+
+#[test]
+fn texture_nodes_sample_a_canvas() {
+    use NodeInput::{Link, Value};
+
+    let mut graph = graph(vec![
+        node(NodeKind::Variable(Variable::U), vec![]),
+        node(NodeKind::Variable(Variable::V), vec![]),
+        node(
+            NodeKind::Texture(Channel::Green),
+            vec![Value(2.0), Link(1), Link(2)],
+        ),
+    ]);
+    graph.nodes[0].inputs = vec![Link(3); 3];
+
+    assert_eq!(graph.compile(), "canvas_g(2, u, v)");
+}
+
+#[test]
+fn bsdf_nodes_linked_to_the_surface_give_the_material() {
+    use NodeInput::{Link, Value};
+
+    let mut graph = graph(vec![
+        node(NodeKind::Bsdf(Bsdf::Metal), vec![Value(0.3)]),
+        node(NodeKind::Math(MathOp::Add), vec![Link(1), Value(0.5)]),
+    ]);
+    assert_eq!(graph.surface(), None);
+
+    graph.nodes[0].inputs = vec![Value(1.0), Value(1.0), Link(2), Link(1)];
+    let surface = graph.surface().unwrap();
+    assert_eq!(surface.material, Material::Metal);
+    assert_eq!(surface.roughness, 0.3);
+    assert_eq!(surface.albedo, Vector3::new(1.0, 1.0, 1.0));
+    // A BSDF has no value to add to
+    assert_eq!(graph.compile(), "1, 1, (0 + 0.5)");
+
+    graph.nodes[1] = node(NodeKind::Bsdf(Bsdf::Dielectric), vec![Value(0.5)]);
+    assert_eq!(graph.surface().unwrap().ior, 1.0);
+}
+
+#[test]
+fn broken_links_read_as_zero() {
+    use NodeInput::{Link, Value};
+
+    // Saved by hand or an older editor, rather than made in this one
+    let mut graph = graph(vec![
+        node(NodeKind::Math(MathOp::Add), vec![Link(2), Value(1.0)]),
+        node(NodeKind::Math(MathOp::Sin), vec![Link(1)]),
+    ]);
+    graph.nodes[0].inputs = vec![Link(1), Link(7), Value(f32::NAN)];
+
+    assert_eq!(graph.compile(), "(sin(0) + 1), 0, 0");
+}
+
+#[test]
+fn graphs_are_saved_with_their_nodes() {
+    let graph = graph(vec![node(NodeKind::Variable(Variable::Time), vec![])]);
+    let json = serde_json::to_string(&graph).unwrap();
+    let loaded: MaterialGraph = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, graph);
+}
@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+
+use pathtracer::naming::{self, Names};
+use proptest::prelude::*;
+
+#[test]
+fn repeated_names_are_numbered_from_one() {
+    let mut names = Names::default();
+
+    assert_eq!(names.unique("Sphere"), "Sphere");
+    assert_eq!(names.unique("Sphere"), "Sphere.001");
+    assert_eq!(names.unique("Sphere"), "Sphere.002");
+    assert_eq!(names.unique("Point Light"), "Point Light");
+}
+
+#[test]
+fn copies_of_numbered_names_number_on_from_their_stem() {
+    let mut names = Names::new(["Sphere", "Sphere.001", "Sphere.003"]);
+
+    assert_eq!(names.unique("Sphere.001"), "Sphere.002");
+    assert_eq!(names.unique("Sphere.003"), "Sphere.004");
+    // A free numbered name is kept as it is
+    assert_eq!(names.unique("Sphere.007"), "Sphere.007");
+}
+
+#[test]
+fn only_numbers_added_by_numbering_are_stripped() {
+    assert_eq!(naming::stem("Sphere.001"), "Sphere");
+    assert_eq!(naming::stem("Sphere.1234"), "Sphere");
+    assert_eq!(naming::stem("bunny.v2"), "bunny.v2");
+    assert_eq!(naming::stem("version 1.10"), "version 1.10");
+    assert_eq!(naming::stem(".001"), ".001");
+}
+
+proptest! {
+    #[test]
+    fn unique_names_never_collide(
+        taken in prop::collection::vec("[ab]{1,2}(\\.00[0-9])?", 0..8),
+        bases in prop::collection::vec("[ab]{1,2}(\\.00[0-9])?", 1..32),
+    ) {
+        let mut names = Names::new(taken.iter().cloned());
+        let mut seen = taken.into_iter().collect::<HashSet<_>>();
+
+        for base in bases {
+            let name = names.unique(&base);
+            prop_assert!(seen.insert(name.clone()), "{} was handed out twice", name);
+            prop_assert!(names.contains(&name));
+            prop_assert_eq!(naming::stem(&name), naming::stem(&base));
+        }
+    }
+}
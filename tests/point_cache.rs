@@ -0,0 +1,65 @@
+mod common;
+
+use std::{fs, io};
+
+use pathtracer::scene::PointCache;
+
+fn load(test: &str, json: &str) -> io::Result<PointCache> {
+    let path = common::temp_dir(test).join("cache.json");
+    fs::write(&path, json).unwrap();
+    PointCache::load(&path)
+}
+
+fn assert_invalid(test: &str, json: &str) {
+    let error = load(test, json).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+}
+
+#[test]
+fn a_cache_with_rotations_and_scales_loads() {
+    let cache = load(
+        "point-cache-valid",
+        r#"{ "fps": 24, "frames": [[[0, 1, 0], [1, 1, 0]], [[0, 2, 0], [1, 2, 0]]],
+            "rotations": [[[0, 0, 0], [0, 90, 0]], [[0, 0, 0], [0, 180, 0]]],
+            "scales": [[1, 2], [0.5, 1]] }"#,
+    )
+    .unwrap();
+    assert_eq!(cache.point_count(), 2);
+    assert_eq!(cache.radius, 0.1);
+}
+
+#[test]
+fn frames_with_different_numbers_of_points_are_rejected() {
+    assert_invalid(
+        "point-cache-ragged",
+        r#"{ "fps": 24, "frames": [[[0, 1, 0]], [[0, 1, 0], [1, 1, 0]]] }"#,
+    );
+}
+
+#[test]
+fn positions_past_the_range_of_f32_are_rejected() {
+    assert_invalid(
+        "point-cache-infinite",
+        r#"{ "fps": 24, "frames": [[[0, 1e300, 0]]] }"#,
+    );
+}
+
+#[test]
+fn zero_and_negative_scales_are_rejected() {
+    assert_invalid(
+        "point-cache-zero-scale",
+        r#"{ "fps": 24, "frames": [[[0, 1, 0]]], "scales": [[0]] }"#,
+    );
+    assert_invalid(
+        "point-cache-negative-scale",
+        r#"{ "fps": 24, "frames": [[[0, 1, 0]]], "scales": [[-1]] }"#,
+    );
+}
+
+#[test]
+fn a_radius_of_zero_is_rejected() {
+    assert_invalid(
+        "point-cache-zero-radius",
+        r#"{ "fps": 24, "radius": 0, "frames": [[[0, 1, 0]]] }"#,
+    );
+}
@@ -0,0 +1,313 @@
+use std::{fs, io, path::PathBuf};
+
+use cgmath::Vector3;
+use pathtracer::scene::{LightKind, Material, Scene, DEFAULT_NITS_PER_UNIT};
+use serde_json::{json, Value};
+
+mod common;
+
+fn vector(x: f32, y: f32, z: f32) -> Value {
+    json!({ "x": x, "y": y, "z": z })
+}
+
+fn camera() -> Value {
+    json!({
+        "origin": vector(0.0, 1.0, 4.0),
+        "forward": vector(0.0, 0.0, -1.0),
+        "right": vector(1.0, 0.0, 0.0),
+        "up": vector(0.0, 1.0, 0.0),
+        "focal_length": 1.0,
+        "vfov": 60.0,
+    })
+}
+
+fn render_settings() -> Value {
+    json!({
+        "settings": {
+            "samples_per_pixel": 1,
+            "depth": 8,
+            "t_min": 0.001,
+            "t_max": 1000.0,
+            "reference": 0,
+        },
+        "environment": {
+            "yaw": 0.0,
+            "intensity": 1.0,
+            "show_background": 1,
+            "background_color": [0.0, 0.0, 0.0],
+        },
+        "progressive_rendering": {
+            "enabled": true,
+            "sample_size": 128,
+            "sample_size_while_moving": 1,
+            "samples_before_display": 1,
+        },
+    })
+}
+
+/// A triangle as version 1 and 2 saved them, with its own albedo and material.
+fn triangle(x: f32, albedo: Value, material: Value) -> Value {
+    let normal = vector(0.0, 0.0, 1.0);
+    let uv = json!({ "x": 0.0, "y": 0.0 });
+    json!({
+        "a": vector(x, 0.0, 0.0),
+        "b": vector(x + 1.0, 0.0, 0.0),
+        "c": vector(x, 1.0, 0.0),
+        "na": normal, "nb": normal, "nc": normal,
+        "ta": uv, "tb": uv, "tc": uv,
+        "albedo": albedo,
+        "material": material,
+    })
+}
+
+fn sphere(x: f32, albedo: Value, material: Value) -> Value {
+    json!({
+        "uuid": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+        "label": "Ball",
+        "center": vector(x, 0.0, 0.0),
+        "radius": 0.5,
+        "albedo": albedo,
+        "material": material,
+    })
+}
+
+fn write(test: &str, file: &Value) -> PathBuf {
+    let path = common::temp_dir(test).join("scene.json");
+    fs::write(&path, serde_json::to_vec(file).unwrap()).unwrap();
+    path
+}
+
+fn load(test: &str, file: &Value) -> io::Result<Scene> {
+    Scene::load(&write(test, file)).map(|(scene, _)| scene)
+}
+
+fn load_error(test: &str, file: &Value) -> String {
+    match load(test, file) {
+        Ok(_) => panic!("{} loaded", file),
+        Err(e) => {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", e);
+            e.to_string()
+        }
+    }
+}
+
+#[test]
+fn version_1_single_meshes_are_upgraded() {
+    let red = vector(0.8, 0.1, 0.1);
+    let file = json!({
+        "camera": camera(),
+        "final_camera": camera(),
+        "spheres": [
+            sphere(0.0, red.clone(), json!("Diffuse")),
+            // The selection's gizmo, saved by mistake
+            sphere(2.0, vector(1.0, 1.0, 1.0), json!("Gizmo")),
+        ],
+        "triangles": [
+            triangle(0.0, red.clone(), json!("Diffuse")),
+            triangle(1.0, vector(1.0, 1.0, 1.0), json!({ "Emissive": { "intensity": 5.0 } })),
+        ],
+        "render_settings": render_settings(),
+    });
+    let scene = load("file-version-1-single-mesh", &file).unwrap();
+
+    assert_eq!(scene.name, "scene");
+    assert_eq!(scene.spheres().len(), 1);
+    assert_eq!(scene.meshes().len(), 1);
+    assert_eq!(scene.meshes()[0].name, "Mesh");
+    assert_eq!(scene.meshes()[0].triangles.len(), 2);
+    assert_eq!(scene.instances().len(), 1);
+    assert_eq!(scene.instances()[0].mesh.mesh, 0);
+    assert_eq!(
+        scene.instances()[0].transform.scale,
+        Vector3::new(1.0, 1.0, 1.0)
+    );
+
+    // The sphere and the triangle that looked the same now share a material
+    let triangles = &scene.meshes()[0].triangles;
+    assert_eq!(scene.spheres()[0].surface.material, triangles[0].material);
+    let red = &scene.materials[triangles[0].material];
+    assert_eq!(red.material, Material::Diffuse);
+    assert_eq!(red.albedo, Vector3::new(0.8, 0.1, 0.1));
+    let light = &scene.materials[triangles[1].material];
+    assert_eq!(light.material, Material::Emissive { intensity: 5.0 });
+    assert_eq!(scene.materials.len(), 3);
+}
+
+const MESH_UUIDS: [&str; 2] = [
+    "0e8d6c42-5a38-4a8e-9a47-6a2f1b0c7d11",
+    "9b1f4c3e-2d7a-4f6b-8c5e-3a1d2e4f5a62",
+];
+
+/// A file with two meshes, instanced once and twice, of `version` 1, which referred to the meshes
+/// by their indices, or 2, which referred to them by UUID.
+fn instanced_file(version: u32) -> Value {
+    let white = vector(0.9, 0.9, 0.9);
+    let instance = |uuid: &str, mesh: usize, x: f32| {
+        let mesh = match version {
+            1 => json!(mesh),
+            _ => json!(MESH_UUIDS[mesh]),
+        };
+        json!({
+            "uuid": uuid,
+            "name": "",
+            "mesh": mesh,
+            "position": vector(x, 0.0, 0.0),
+            "rotation": vector(0.0, 90.0, 0.0),
+            "scale": vector(2.0, 2.0, 2.0),
+        })
+    };
+    let mut file = json!({
+        "camera": camera(),
+        "final_camera": camera(),
+        "spheres": [sphere(0.0, white.clone(), json!("Metal"))],
+        "meshes": [
+            { "name": "Floor", "triangles": [triangle(0.0, white.clone(), json!("Diffuse"))] },
+            { "name": "Glass", "triangles": [triangle(0.0, white.clone(), json!("Dielectric"))] },
+        ],
+        "instances": [
+            instance("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8", 0, 0.0),
+            instance("b1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8", 1, 1.0),
+            instance("c1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8", 1, 2.0),
+        ],
+        "render_settings": render_settings(),
+    });
+    if version > 1 {
+        file["version"] = json!(version);
+        for (mesh, uuid) in MESH_UUIDS.into_iter().enumerate() {
+            file["meshes"][mesh]["uuid"] = json!(uuid);
+        }
+    }
+    file
+}
+
+fn assert_instanced(scene: &Scene) {
+    let names = scene
+        .meshes()
+        .iter()
+        .map(|mesh| mesh.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Floor", "Glass"]);
+    let meshes = scene
+        .instances()
+        .iter()
+        .map(|instance| instance.mesh.mesh)
+        .collect::<Vec<_>>();
+    assert_eq!(meshes, [0, 1, 1]);
+    let transform = scene.instances()[2].transform;
+    assert_eq!(transform.position, Vector3::new(2.0, 0.0, 0.0));
+    assert_eq!(transform.rotation, Vector3::new(0.0, 90.0, 0.0));
+
+    let kinds = [
+        scene.spheres()[0].surface.material,
+        scene.meshes()[0].triangles[0].material,
+        scene.meshes()[1].triangles[0].material,
+    ]
+    .map(|id| scene.materials[id].material);
+    assert_eq!(
+        kinds,
+        [Material::Metal, Material::Diffuse, Material::Dielectric]
+    );
+}
+
+#[test]
+fn version_1_instances_are_upgraded() {
+    let scene = load("file-version-1-instances", &instanced_file(1)).unwrap();
+    assert_instanced(&scene);
+}
+
+#[test]
+fn version_2_files_are_upgraded() {
+    let scene = load("file-version-2", &instanced_file(2)).unwrap();
+    assert_instanced(&scene);
+    let uuids = scene.meshes().iter().map(|mesh| mesh.uuid.to_string());
+    assert!(uuids.eq(MESH_UUIDS));
+}
+
+#[test]
+fn version_1_instances_of_missing_meshes_are_rejected() {
+    let mut file = instanced_file(1);
+    file["instances"][1]["mesh"] = json!(2);
+    let error = load_error("file-missing-mesh", &file);
+    assert!(error.contains("upgrading from version 1"), "{}", error);
+    assert!(error.contains("refers to a missing mesh"), "{}", error);
+}
+
+#[test]
+fn saved_scenes_load_as_they_were() {
+    let (mut scene, render_settings) =
+        Scene::load(&write("file-round-trip-original", &instanced_file(2))).unwrap();
+    assert_eq!(scene.nits_per_unit, DEFAULT_NITS_PER_UNIT);
+    scene.add_sphere();
+    scene.add_light(LightKind::Quad);
+    scene.nits_per_unit = 250.0;
+    assert!(scene.is_dirty());
+
+    let path = common::temp_dir("file-round-trip").join("saved.json");
+    let saved = scene.snapshot(&render_settings).write(&path).unwrap();
+    scene.mark_saved(&path, saved);
+    assert!(!scene.is_dirty());
+    assert_eq!(scene.name, "saved");
+
+    let file: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(file["version"], 3);
+    let (loaded, loaded_settings) = Scene::load(&path).unwrap();
+    assert!(!loaded.is_dirty());
+    assert_eq!(
+        format!("{:?}", loaded_settings),
+        format!("{:?}", render_settings)
+    );
+
+    let spheres = |scene: &Scene| {
+        let spheres = scene.spheres().into_iter();
+        spheres
+            .map(|s| {
+                (
+                    s.id.0,
+                    s.name.0.clone(),
+                    *s.transform,
+                    *s.shape,
+                    s.surface.material,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(spheres(&loaded), spheres(&scene));
+    let instances = |scene: &Scene| {
+        let instances = scene.instances().into_iter();
+        instances
+            .map(|i| (i.id.0, i.name.0.clone(), *i.mesh, *i.transform))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(instances(&loaded), instances(&scene));
+    let lights = |scene: &Scene| {
+        let lights = scene.lights().into_iter();
+        lights
+            .map(|l| (l.id.0, l.name.0.clone(), format!("{:?}", l.light)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(lights(&loaded), lights(&scene));
+    let meshes = |scene: &Scene| {
+        let meshes = scene.meshes().iter();
+        meshes
+            .map(|m| (m.uuid, m.name.clone(), format!("{:?}", m.triangles)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(meshes(&loaded), meshes(&scene));
+    let materials = |scene: &Scene| format!("{:?}", scene.materials);
+    assert_eq!(materials(&loaded), materials(&scene));
+    assert_eq!(loaded.camera.origin, scene.camera.origin);
+    assert_eq!(loaded.nits_per_unit, 250.0);
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let mut file = instanced_file(2);
+    file["version"] = json!(4);
+    let error = load_error("file-newer-version", &file);
+    assert!(error.contains("version 4"), "{}", error);
+    assert!(error.contains("newer than the 3"), "{}", error);
+
+    file["version"] = json!(0);
+    let error = load_error("file-version-0", &file);
+    assert!(error.contains("invalid version 0"), "{}", error);
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use pathtracer::shader_preprocessor::{
+    preprocess_shader_with, PreprocessError, PreprocessedShader,
+};
+use proptest::prelude::*;
+
+/// Preprocesses `main.wgsl` from in-memory `files`, given relative to the shaders root.
+fn preprocess(
+    files: &[(&str, &str)],
+    defines: &[&str],
+) -> Result<PreprocessedShader, PreprocessError> {
+    let files: HashMap<PathBuf, String> = files
+        .iter()
+        .map(|(path, source)| (Path::new("shaders").join(path), source.to_string()))
+        .collect();
+    preprocess_shader_with(Path::new("shaders"), "main.wgsl", defines, |path| {
+        files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+    })
+}
+
+#[test]
+fn conditionals_keep_the_lines_for_the_defines() {
+    let main = "//!ifdef A\na\n//!else\nnot a\n//!endif\n//!ifndef B\nnot b\n//!endif";
+
+    assert_eq!(
+        preprocess(&[("main.wgsl", main)], &["A"]).unwrap().source,
+        "a\nnot b"
+    );
+    assert_eq!(
+        preprocess(&[("main.wgsl", main)], &["B"]).unwrap().source,
+        "not a"
+    );
+}
+
+#[test]
+fn defines_apply_to_the_rest_of_the_shader() {
+    let main = "//!include \"defines.wgsl\"\n//!ifdef A\na\n//!endif";
+    let files = [("main.wgsl", main), ("include/defines.wgsl", "//!define A")];
+
+    assert_eq!(preprocess(&files, &[]).unwrap().source, "a");
+}
+
+#[test]
+fn includes_are_expanded_once_and_mapped_back_to_their_files() {
+    let main = "//!include \"a.wgsl\"\n//!include \"a.wgsl\"\nmain";
+    let shader = preprocess(&[("main.wgsl", main), ("include/a.wgsl", "a1\na2")], &[]).unwrap();
+
+    assert_eq!(shader.source, "a1\na2\nmain");
+    assert_eq!(
+        shader.origin(2),
+        Some((Path::new("shaders/include/a.wgsl"), 2))
+    );
+    assert_eq!(shader.origin(3), Some((Path::new("shaders/main.wgsl"), 3)));
+    assert_eq!(shader.origin(4), None);
+}
+
+#[test]
+fn relative_includes_resolve_from_the_including_file() {
+    let files = [
+        ("main.wgsl", "//!include \"lighting/sky.wgsl\""),
+        (
+            "include/lighting/sky.wgsl",
+            "//!include \"./sun.wgsl\"\n//!include \"../common.wgsl\"",
+        ),
+        ("include/lighting/sun.wgsl", "sun"),
+        ("include/common.wgsl", "common"),
+    ];
+
+    assert_eq!(preprocess(&files, &[]).unwrap().source, "sun\ncommon");
+}
+
+#[test]
+fn include_cycles_are_reported_with_the_chain() {
+    let files = [
+        ("main.wgsl", "//!include \"a.wgsl\""),
+        ("include/a.wgsl", "//!include \"b.wgsl\""),
+        ("include/b.wgsl", "//!include \"./a.wgsl\""),
+    ];
+
+    match preprocess(&files, &[]) {
+        Err(PreprocessError::IncludeCycle { chain }) => assert_eq!(
+            chain,
+            [
+                "shaders/include/a.wgsl",
+                "shaders/include/b.wgsl",
+                "shaders/include/a.wgsl"
+            ]
+            .map(PathBuf::from)
+        ),
+        other => panic!("expected an include cycle, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn a_shader_including_itself_is_a_cycle() {
+    let files = [("main.wgsl", "//!include \"./main.wgsl\"")];
+
+    assert!(matches!(
+        preprocess(&files, &[]),
+        Err(PreprocessError::IncludeCycle { .. })
+    ));
+}
+
+#[test]
+fn malformed_directives_are_reported_with_their_line() {
+    let cases = [
+        ("//!include a.wgsl", "malformed include"),
+        ("//!include \"a.wgsl", "malformed include"),
+        ("//!include \"\"", "malformed include"),
+        ("//!include", "malformed include"),
+        ("//!ifdef", "invalid"),
+        ("//!ifdef A B", "invalid"),
+        ("//!define", "invalid"),
+        ("//!endif extra", "invalid"),
+        ("//!unknown", "invalid"),
+        ("//!else", "unmatched"),
+        ("//!endif", "unmatched"),
+        ("//!ifdef A\n//!else\n//!else\n//!endif", "unmatched"),
+    ];
+
+    for (directive, expected) in cases {
+        let main = format!("first line\n{}", directive);
+        let error = preprocess(&[("main.wgsl", &main)], &[]).err();
+        let line = match (&error, expected) {
+            (Some(PreprocessError::MalformedInclude { line, .. }), "malformed include")
+            | (Some(PreprocessError::InvalidDirective { line, .. }), "invalid")
+            | (Some(PreprocessError::UnmatchedDirective { line, .. }), "unmatched") => *line,
+            _ => panic!("expected {} for {:?}, got {:?}", expected, directive, error),
+        };
+        assert!(line >= 2, "wrong line {} for {:?}", line, directive);
+    }
+}
+
+#[test]
+fn unclosed_conditionals_report_where_they_start() {
+    let main = "a\n//!ifdef A\n//!ifndef B\n//!endif";
+
+    assert!(matches!(
+        preprocess(&[("main.wgsl", main)], &[]),
+        Err(PreprocessError::MissingEndif { line: 2, .. })
+    ));
+}
+
+#[test]
+fn missing_includes_report_the_file() {
+    let main = "//!include \"missing.wgsl\"";
+
+    match preprocess(&[("main.wgsl", main)], &[]) {
+        Err(PreprocessError::Read { path, .. }) => {
+            assert_eq!(path, Path::new("shaders/include/missing.wgsl"))
+        }
+        other => panic!("expected a read error, got {:?}", other.err()),
+    }
+}
+
+/// Lines that are mostly directives, with some broken on purpose.
+fn line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[a-z ;{}]{0,8}",
+        "(A|B|C)".prop_map(|name| format!("//!ifdef {}", name)),
+        "(A|B|C)".prop_map(|name| format!("//!ifndef {}", name)),
+        "(A|B|C)".prop_map(|name| format!("//!define {}", name)),
+        Just("//!else".to_string()),
+        Just("//!endif".to_string()),
+        "(a|b|c|main|\\./a|\\.\\./b|x/\\.\\./c)"
+            .prop_map(|file| format!("//!include \"{}.wgsl\"", file)),
+        "//!.{0,12}",
+    ]
+}
+
+fn file() -> impl Strategy<Value = String> {
+    prop::collection::vec(line(), 0..12).prop_map(|lines| lines.join("\n"))
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_files_preprocess_without_panicking(
+        main in file(),
+        a in file(),
+        b in file(),
+        c in file(),
+        defines in prop::collection::vec("(A|B|C)", 0..3),
+    ) {
+        let files = [
+            ("main.wgsl", main.as_str()),
+            ("include/a.wgsl", &a),
+            ("include/b.wgsl", &b),
+            ("include/c.wgsl", &c),
+        ];
+        let defines: Vec<&str> = defines.iter().map(String::as_str).collect();
+
+        if let Ok(shader) = preprocess(&files, &defines) {
+            let lines = shader.source.lines().count();
+            for line in 1..=lines {
+                prop_assert!(shader.origin(line).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn files_without_directives_are_unchanged(lines in prop::collection::vec("[^/\n\r]{1,16}", 1..16)) {
+        let main = lines.join("\n");
+
+        let shader = preprocess(&[("main.wgsl", &main)], &[]).unwrap();
+
+        prop_assert!(shader.source.lines().eq(main.lines()));
+    }
+}
@@ -0,0 +1,265 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+use pathtracer::scene::{
+    Camera, LibraryMaterial, Light, LightKind, Material, MaterialId, Mesh, MeshInstance, Repair,
+    Scene, Sphere, SphereDescriptor, Surface, Triangle, DEFAULT_NITS_PER_UNIT,
+};
+
+/// As in lib.rs, where the shaders' buffers are sized.
+const MAX_NUMBER_OF_MATERIALS: usize = 256;
+
+fn triangle(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Triangle {
+    let normal = Vector3::new(0.0, 0.0, 1.0);
+    Triangle {
+        a,
+        b,
+        c,
+        na: normal,
+        nb: normal,
+        nc: normal,
+        ta: Vector2::new(0.0, 0.0),
+        tb: Vector2::new(1.0, 0.0),
+        tc: Vector2::new(0.0, 1.0),
+        material: MaterialId::default(),
+    }
+}
+
+fn unit_triangle() -> Triangle {
+    triangle(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+    )
+}
+
+fn sphere(radius: f32) -> Sphere {
+    Sphere::new(SphereDescriptor {
+        center: Vector3::new(0.0, 0.0, 0.0),
+        radius,
+        material: MaterialId::default(),
+    })
+}
+
+fn scene_of(spheres: Vec<Sphere>, meshes: Vec<Mesh>) -> Scene {
+    let instances = (0..meshes.len()).map(MeshInstance::new).collect();
+    Scene::with_meshes(spheres, meshes, instances, Camera::default())
+}
+
+/// The repairs as they're shown, for checking against what's expected.
+fn messages(repairs: &[Repair]) -> Vec<String> {
+    repairs.iter().map(Repair::to_string).collect()
+}
+
+#[test]
+fn valid_scenes_need_no_repairs() {
+    let mut scene = scene_of(
+        vec![sphere(1.0)],
+        vec![Mesh::new("Mesh".to_string(), vec![unit_triangle()])],
+    );
+    scene.push_light(Light::new(LightKind::Quad), "");
+    assert!(scene.validate().is_empty());
+}
+
+#[test]
+fn material_values_out_of_range_are_clamped_or_replaced() {
+    let mut scene = scene_of(Vec::new(), Vec::new());
+    let default = MaterialId::default();
+    scene.materials[default].albedo = Vector3::new(2.0, f32::NAN, -1.0);
+    scene.materials[default].roughness = 3.0;
+    scene.materials[default].ior = 0.5;
+    let light = scene.materials.add(LibraryMaterial {
+        name: "Light".to_string(),
+        albedo: Vector3::new(1.0, 1.0, 1.0),
+        material: Material::Emissive {
+            intensity: f32::INFINITY,
+        },
+        roughness: 0.0,
+        ior: 1.5,
+    });
+
+    assert_eq!(
+        messages(&scene.validate()),
+        [
+            "Default has an albedo outside 0 to 1: clamped",
+            "Default has a roughness outside 0 to 1: clamped",
+            "Default has an invalid index of refraction: replaced",
+            "Light has an invalid intensity: replaced",
+        ]
+    );
+    let material = &scene.materials[default];
+    assert_eq!(material.albedo, Vector3::new(1.0, 0.0, 0.0));
+    assert_eq!(material.roughness, 1.0);
+    assert!(material.ior >= 1.0);
+    assert_eq!(
+        scene.materials[light].material,
+        Material::Emissive { intensity: 1.0 }
+    );
+}
+
+#[test]
+fn empty_material_libraries_get_a_default() {
+    let mut scene = scene_of(vec![sphere(1.0)], Vec::new());
+    scene.materials.truncate(0);
+
+    assert_eq!(
+        messages(&scene.validate()),
+        ["The scene has no materials: added a default"]
+    );
+    assert_eq!(scene.materials.len(), 1);
+}
+
+#[test]
+fn materials_past_the_limit_are_removed() {
+    let mut scene = scene_of(vec![sphere(1.0)], Vec::new());
+    let mut last = MaterialId::default();
+    while scene.materials.len() < MAX_NUMBER_OF_MATERIALS + 10 {
+        last = scene
+            .materials
+            .find_or_add(Vector3::new(0.5, 0.5, 0.5), Material::Metal);
+        scene.materials[last].roughness = scene.materials.len() as f32 / 1000.0;
+    }
+    let uuid = scene.spheres()[0].id.0;
+    scene.get_mut::<Surface>(uuid).unwrap().material = last;
+
+    assert_eq!(
+        messages(&scene.validate()),
+        [
+            format!(
+                "The scene has {} materials, more than the {} the renderer has room for: \
+                removed the rest",
+                MAX_NUMBER_OF_MATERIALS + 10,
+                MAX_NUMBER_OF_MATERIALS
+            ),
+            format!(
+                "{} is made of a missing material: replaced with the default",
+                scene.name_of(uuid).unwrap()
+            ),
+        ]
+    );
+    assert_eq!(scene.materials.len(), MAX_NUMBER_OF_MATERIALS);
+    assert_eq!(scene.spheres()[0].surface.material, MaterialId::default());
+}
+
+#[test]
+fn broken_spheres_are_removed() {
+    let mut nan = sphere(1.0);
+    nan.center.y = f32::NAN;
+    let mut missing = sphere(1.0);
+    missing.material = MaterialId(7);
+    let mut scene = scene_of(vec![nan, sphere(0.0), missing, sphere(1.0)], Vec::new());
+    let names = scene
+        .spheres()
+        .iter()
+        .map(|sphere| sphere.name.0.clone())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        messages(&scene.validate()),
+        [
+            format!("{} has a NaN or infinite transform: removed", names[0]),
+            format!("{} has no radius: removed", names[1]),
+            format!(
+                "{} is made of a missing material: replaced with the default",
+                names[2]
+            ),
+        ]
+    );
+    let spheres = scene.spheres();
+    let kept = spheres.iter().map(|sphere| &sphere.name.0);
+    assert!(kept.eq(&names[2..]));
+    assert_eq!(spheres[0].surface.material, MaterialId::default());
+}
+
+#[test]
+fn broken_instance_transforms_are_reset() {
+    let mesh = Mesh::new("Mesh".to_string(), vec![unit_triangle()]);
+    let mut instance = MeshInstance::new(0);
+    instance.position.x = f32::INFINITY;
+    instance.rotation.z = f32::NAN;
+    instance.scale = Vector3::new(2.0, 0.0, f32::NAN);
+    let mut scene = Scene::with_meshes(Vec::new(), vec![mesh], vec![instance], Camera::default());
+    let name = scene.instances()[0].name.0.clone();
+
+    assert_eq!(
+        messages(&scene.validate()),
+        [
+            format!("{} has a NaN or infinite position: reset", name),
+            format!("{} has a NaN or infinite rotation: reset", name),
+            format!(
+                "{} has a zero, NaN or infinite scale: reset to 1 on those axes",
+                name
+            ),
+        ]
+    );
+    let transform = scene.instances()[0].transform;
+    assert_eq!(transform.position, Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(transform.rotation, Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(transform.scale, Vector3::new(2.0, 1.0, 1.0));
+}
+
+#[test]
+fn broken_triangles_are_removed_or_repaired() {
+    let origin = Vector3::new(0.0, 0.0, 0.0);
+    let flat = triangle(
+        origin,
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(2.0, 0.0, 0.0),
+    );
+    let nan = triangle(
+        origin,
+        Vector3::new(f32::NAN, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+    );
+    let mut normals = unit_triangle();
+    normals.na = Vector3::new(0.0, 0.0, 0.0);
+    normals.nc = Vector3::new(f32::NAN, 0.0, 0.0);
+    let mut missing = unit_triangle();
+    missing.material = MaterialId(3);
+    let mesh = Mesh::new("Mesh".to_string(), vec![flat, nan, normals, missing]);
+    let mut scene = scene_of(Vec::new(), vec![mesh]);
+
+    assert_eq!(
+        messages(&scene.validate()),
+        [
+            "Mesh has 2 triangles with no area or NaN vertices: removed",
+            "Mesh has 2 zero or NaN normals: replaced with face normals",
+            "Mesh has 1 triangles made of a missing material: replaced with the default",
+        ]
+    );
+    let triangles = &scene.meshes()[0].triangles;
+    assert_eq!(triangles.len(), 2);
+    assert_eq!(triangles[0].na, Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(triangles[0].nc, Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(triangles[1].material, MaterialId::default());
+}
+
+#[test]
+fn broken_lights_are_reset() {
+    let mut scene = scene_of(Vec::new(), Vec::new());
+    let mut light = Light::new(LightKind::Quad);
+    light.direction = Vector3::new(0.0, 0.0, 0.0);
+    light.intensity = -1.0;
+    light.size.x = f32::NAN;
+    scene.push_light(light, "Lamp");
+
+    assert_eq!(
+        messages(&scene.validate()),
+        ["Lamp has NaN, infinite or negative values: reset to defaults"]
+    );
+    let default = Light::new(LightKind::Quad);
+    let light = scene.lights()[0].light;
+    assert!(light.direction.magnitude2() > 0.0);
+    assert_eq!(light.intensity, default.intensity);
+    assert_eq!(light.size, default.size);
+}
+
+#[test]
+fn unit_conversions_that_arent_positive_are_reset() {
+    let mut scene = scene_of(Vec::new(), Vec::new());
+    scene.nits_per_unit = -5.0;
+
+    assert_eq!(
+        messages(&scene.validate()),
+        ["The scene's nits per unit radiance isn't positive: reset to the default"]
+    );
+    assert_eq!(scene.nits_per_unit, DEFAULT_NITS_PER_UNIT);
+}